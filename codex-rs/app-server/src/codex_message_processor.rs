@@ -993,11 +993,7 @@ impl CodexMessageProcessor {
             .collect();
 
         // Submit user input to the conversation.
-        let _ = conversation
-            .submit(Op::UserInput {
-                items: mapped_items,
-            })
-            .await;
+        let _ = conversation.submit(Op::user_input(mapped_items)).await;
 
         // Acknowledge with an empty result.
         self.outgoing
@@ -1041,16 +1037,15 @@ impl CodexMessageProcessor {
             .collect();
 
         let _ = conversation
-            .submit(Op::UserTurn {
-                items: mapped_items,
+            .submit(Op::user_turn(
+                mapped_items,
                 cwd,
                 approval_policy,
                 sandbox_policy,
                 model,
                 effort,
                 summary,
-                final_output_json_schema: None,
-            })
+            ))
             .await;
 
         self.outgoing
@@ -21,6 +21,7 @@ use codex_tui::AppExitInfo;
 use codex_tui::Cli as TuiCli;
 use owo_colors::OwoColorize;
 use std::path::PathBuf;
+use std::time::Duration;
 use supports_color::Stream;
 
 mod mcp_cmd;
@@ -97,6 +98,9 @@ enum Subcommand {
     /// Internal: run the responses API proxy.
     #[clap(hide = true)]
     ResponsesApiProxy(ResponsesApiProxyArgs),
+
+    /// [experimental] Send a test event to a visualizer endpoint and report the result as JSON.
+    VisualizerSelfTest(VisualizerSelfTestCommand),
 }
 
 #[derive(Debug, Parser)]
@@ -185,6 +189,17 @@ struct LogoutCommand {
     config_overrides: CliConfigOverrides,
 }
 
+#[derive(Debug, Parser)]
+struct VisualizerSelfTestCommand {
+    /// Visualizer websocket URL. Defaults to `CODEX_VISUALIZER_WS`.
+    #[arg(value_name = "URL")]
+    url: Option<String>,
+
+    /// Give up after this many seconds.
+    #[arg(long = "timeout", value_name = "SECONDS", default_value_t = 5)]
+    timeout_secs: u64,
+}
+
 #[derive(Debug, Parser)]
 struct GenerateTsCommand {
     /// Output directory where .ts files will be written
@@ -381,6 +396,9 @@ async fn cli_main(codex_linux_sandbox_exe: Option<PathBuf>) -> anyhow::Result<()
         Some(Subcommand::GenerateTs(gen_cli)) => {
             codex_protocol_ts::generate_ts(&gen_cli.out_dir, gen_cli.prettier.as_deref())?;
         }
+        Some(Subcommand::VisualizerSelfTest(self_test_cli)) => {
+            run_visualizer_self_test(self_test_cli).await?;
+        }
     }
 
     Ok(())
@@ -465,6 +483,22 @@ fn merge_resume_cli_flags(interactive: &mut TuiCli, resume_cli: TuiCli) {
         .extend(resume_cli.config_overrides.raw_overrides);
 }
 
+async fn run_visualizer_self_test(cmd: VisualizerSelfTestCommand) -> anyhow::Result<()> {
+    let Some(url) = cmd
+        .url
+        .or_else(|| std::env::var("CODEX_VISUALIZER_WS").ok())
+    else {
+        anyhow::bail!("no visualizer URL given and CODEX_VISUALIZER_WS is not set");
+    };
+    let report =
+        codex_core::visualizer::self_test(&url, Duration::from_secs(cmd.timeout_secs)).await;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 fn print_completion(cmd: CompletionCommand) {
     let mut app = MultitoolCli::command();
     let name = "codex";
@@ -0,0 +1,117 @@
+//! Notes that human reviewers pin to the timeline with `Op::Annotate`.
+//!
+//! An annotation is stored in the rollout, kept in the activity log for
+//! digests, and emitted as an `annotation` visualizer event, which lands in
+//! the lane of its `sub_id` when it has one and in timeline reports.
+
+use serde_json::json;
+
+use crate::codex::Session;
+use crate::protocol::Annotation;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::RolloutItem;
+use crate::visualizer::now_ms;
+
+const MAX_TEXT_CHARS: usize = 2_000;
+const MAX_TAGS: usize = 16;
+const MAX_TAG_CHARS: usize = 64;
+
+fn validate(text: &str, tags: &[String]) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("annotation text is empty".to_string());
+    }
+    let chars = text.chars().count();
+    if chars > MAX_TEXT_CHARS {
+        return Err(format!(
+            "annotation text is {chars} characters; the limit is {MAX_TEXT_CHARS}"
+        ));
+    }
+    if tags.len() > MAX_TAGS {
+        return Err(format!(
+            "annotation has {} tags; the limit is {MAX_TAGS}",
+            tags.len()
+        ));
+    }
+    for tag in tags {
+        if tag.trim().is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!(
+                "annotation tag `{tag}` must be 1 to {MAX_TAG_CHARS} characters"
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    /// Handle `Op::Annotate` submitted as `op_id`.
+    pub(crate) async fn annotate(
+        &self,
+        op_id: String,
+        text: String,
+        sub_id: Option<String>,
+        tags: Vec<String>,
+    ) {
+        if let Err(message) = validate(&text, &tags) {
+            self.send_event(Event {
+                id: op_id,
+                msg: EventMsg::Error(ErrorEvent {
+                    message: format!("invalid annotation: {message}"),
+                }),
+            })
+            .await;
+            return;
+        }
+        let annotation = Annotation {
+            text,
+            sub_id,
+            tags,
+            at_ms: u64::try_from(now_ms()).unwrap_or(u64::MAX),
+        };
+        self.record_annotation(annotation.clone()).await;
+        self.persist_rollout_items(&[RolloutItem::Annotation(annotation.clone())])
+            .await;
+        self.emit_with_state(
+            "annotation",
+            json!({
+                "subId": annotation.sub_id,
+                "text": annotation.text,
+                "tags": annotation.tags,
+                "atMs": annotation.at_ms,
+            }),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn validation_bounds_text_and_tags() {
+        let long_tag = "t".repeat(MAX_TAG_CHARS + 1);
+        let results = [
+            validate("went wrong here", &["regression".to_string()]),
+            validate("  \n", &[]),
+            validate(&"x".repeat(MAX_TEXT_CHARS + 1), &[]),
+            validate("ok", &vec!["tag".to_string(); MAX_TAGS + 1]),
+            validate("ok", &[long_tag.clone()]),
+        ];
+        assert_eq!(
+            results,
+            [
+                Ok(()),
+                Err("annotation text is empty".to_string()),
+                Err("annotation text is 2001 characters; the limit is 2000".to_string()),
+                Err("annotation has 17 tags; the limit is 16".to_string()),
+                Err(format!(
+                    "annotation tag `{long_tag}` must be 1 to 64 characters"
+                )),
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::approvals::APPROVAL_TIMED_OUT_MESSAGE;
+use crate::approvals::ApprovalResolution;
 use crate::codex::Session;
 use crate::codex::TurnContext;
 use crate::function_tool::FunctionCallError;
@@ -61,21 +63,24 @@ pub(crate) async fn apply_patch(
             // give the user the option to expand the set of writable roots so
             // that similar patches can be auto-approved in the future during
             // this session.
-            let rx_approve = sess
+            let resolution = sess
                 .request_patch_approval(sub_id.to_owned(), call_id.to_owned(), &action, None, None)
                 .await;
-            match rx_approve.await.unwrap_or_default() {
-                ReviewDecision::Approved | ReviewDecision::ApprovedForSession => {
-                    InternalApplyPatchInvocation::DelegateToExec(ApplyPatchExec {
-                        action,
-                        user_explicitly_approved_this_action: true,
-                    })
-                }
-                ReviewDecision::Denied | ReviewDecision::Abort => {
+            match resolution {
+                ApprovalResolution::Decided(
+                    ReviewDecision::Approved | ReviewDecision::ApprovedForSession,
+                ) => InternalApplyPatchInvocation::DelegateToExec(ApplyPatchExec {
+                    action,
+                    user_explicitly_approved_this_action: true,
+                }),
+                ApprovalResolution::Decided(ReviewDecision::Denied | ReviewDecision::Abort) => {
                     InternalApplyPatchInvocation::Output(Err(FunctionCallError::RespondToModel(
                         "patch rejected by user".to_string(),
                     )))
                 }
+                ApprovalResolution::ApprovalTimedOut => InternalApplyPatchInvocation::Output(Err(
+                    FunctionCallError::RespondToModel(APPROVAL_TIMED_OUT_MESSAGE.to_string()),
+                )),
             }
         }
         SafetyCheck::Reject { reason } => InternalApplyPatchInvocation::Output(Err(
@@ -0,0 +1,99 @@
+//! Resolution of approval requests under the configured
+//! [`ApprovalLimits`](crate::config_types::ApprovalLimits).
+
+use std::time::Duration;
+
+use codex_otel::otel_event_manager::ToolDecisionSource;
+use codex_protocol::protocol::ReviewDecision;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Returned to the model in place of the tool output when an approval
+/// request expires.
+pub(crate) const APPROVAL_TIMED_OUT_MESSAGE: &str =
+    "approval request timed out with no response from the user; the action was not performed";
+
+/// How a pending approval request was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ApprovalResolution {
+    Decided(ReviewDecision),
+    /// Nobody answered within the configured timeout. Treated as a denial,
+    /// but reported to the model distinctly so it does not read it as the
+    /// user's choice.
+    ApprovalTimedOut,
+}
+
+impl ApprovalResolution {
+    /// The decision to act on; a timeout counts as [`ReviewDecision::Denied`].
+    pub(crate) fn decision(self) -> ReviewDecision {
+        match self {
+            Self::Decided(decision) => decision,
+            Self::ApprovalTimedOut => ReviewDecision::Denied,
+        }
+    }
+
+    pub(crate) fn decision_source(self) -> ToolDecisionSource {
+        match self {
+            Self::Decided(_) => ToolDecisionSource::User,
+            Self::ApprovalTimedOut => ToolDecisionSource::Config,
+        }
+    }
+}
+
+/// Sleep until `timeout` of unpaused time has passed. `paused` is the
+/// session's pause flag; the countdown stops while it is `true` and resumes
+/// with the remaining time when it clears. If the session drops the flag the
+/// countdown simply runs out.
+pub(crate) async fn sleep_unpaused(timeout: Duration, mut paused: watch::Receiver<bool>) {
+    let mut remaining = timeout;
+    loop {
+        while *paused.borrow_and_update() {
+            if paused.changed().await.is_err() {
+                break;
+            }
+        }
+        let started = Instant::now();
+        tokio::select! {
+            () = tokio::time::sleep(remaining) => return,
+            changed = paused.changed() => {
+                remaining = remaining.saturating_sub(started.elapsed());
+                if changed.is_err() {
+                    tokio::time::sleep(remaining).await;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test(start_paused = true)]
+    async fn countdown_pauses_and_resumes() {
+        let (pause, paused) = watch::channel(false);
+        let started = Instant::now();
+        let sleeper = tokio::spawn(sleep_unpaused(Duration::from_secs(10), paused));
+
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        pause.send_replace(true);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(!sleeper.is_finished());
+
+        pause.send_replace(false);
+        sleeper.await.expect("sleeper");
+        assert_eq!(started.elapsed(), Duration::from_secs(70));
+    }
+
+    #[test]
+    fn timeout_is_a_config_denial() {
+        let timed_out = ApprovalResolution::ApprovalTimedOut;
+        assert_eq!(timed_out.decision(), ReviewDecision::Denied);
+        assert!(matches!(
+            timed_out.decision_source(),
+            ToolDecisionSource::Config
+        ));
+    }
+}
@@ -277,12 +277,15 @@ pub(crate) async fn stream_chat_completions(
     }
 
     let tools_json = create_tools_json_for_chat_completions_api(&prompt.tools)?;
-    let payload = json!({
+    let mut payload = json!({
         "model": model_family.slug,
         "messages": messages,
         "stream": true,
         "tools": tools_json,
     });
+    if let Some(seed) = prompt.seed {
+        payload["seed"] = json!(seed);
+    }
 
     debug!(
         "POST to {}: {}",
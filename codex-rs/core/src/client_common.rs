@@ -41,6 +41,12 @@ pub struct Prompt {
 
     /// Optional the output schema for the model's response.
     pub output_schema: Option<Value>,
+
+    /// Sampling seed for providers that accept one; ignored by the rest.
+    pub seed: Option<u64>,
+
+    /// The task kind's workspace template, appended to the instructions.
+    pub(crate) task_instructions: Option<String>,
 }
 
 impl Prompt {
@@ -58,13 +64,17 @@ impl Prompt {
             ToolSpec::Freeform(f) => f.name == "apply_patch",
             _ => false,
         });
-        if self.base_instructions_override.is_none()
+        let instructions = if self.base_instructions_override.is_none()
             && model.needs_special_apply_patch_instructions
             && !is_apply_patch_tool_present
         {
             Cow::Owned(format!("{base}\n{APPLY_PATCH_TOOL_INSTRUCTIONS}"))
         } else {
             Cow::Borrowed(base)
+        };
+        match &self.task_instructions {
+            Some(task_instructions) => Cow::Owned(format!("{instructions}\n\n{task_instructions}")),
+            None => instructions,
         }
     }
 
@@ -1,8 +1,13 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Weak;
 use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::time::Duration;
+use std::time::Instant;
 
 use crate::AuthManager;
 use crate::client_common::REVIEW_PROMPT;
@@ -14,10 +19,13 @@ use crate::user_notification::UserNotifier;
 use async_channel::Receiver;
 use async_channel::Sender;
 use codex_apply_patch::ApplyPatchAction;
+use codex_mcp_client::ProgressSender;
 use codex_protocol::ConversationId;
 use codex_protocol::protocol::ConversationPathResponseEvent;
 use codex_protocol::protocol::ExitedReviewModeEvent;
+use codex_protocol::protocol::ReviewFindingsItem;
 use codex_protocol::protocol::ReviewRequest;
+use codex_protocol::protocol::ReviewTarget;
 use codex_protocol::protocol::RolloutItem;
 use codex_protocol::protocol::SessionSource;
 use codex_protocol::protocol::TaskStartedEvent;
@@ -31,7 +39,11 @@ use serde_json;
 use serde_json::Value;
 use serde_json::json;
 use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::sync::broadcast;
 use tokio::sync::oneshot;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
@@ -40,15 +52,26 @@ use tracing::warn;
 
 use crate::ModelProviderInfo;
 use crate::apply_patch::convert_apply_patch_to_protocol;
+use crate::approvals::ApprovalResolution;
+use crate::approvals::sleep_unpaused;
 use crate::client::ModelClient;
 use crate::client_common::Prompt;
 use crate::client_common::ResponseEvent;
 use crate::config::Config;
 use crate::config_types::ShellEnvironmentPolicy;
+use crate::config_types::VisualizerTls;
+use crate::content_scope::prompt_content;
+use crate::conversation_end::ConversationEndGuard;
 use crate::conversation_history::ConversationHistory;
+use crate::conversation_lease;
+use crate::conversation_lease::LeaseHeld;
+use crate::digest::ActivityLog;
+use crate::digest::DigestSince;
 use crate::environment_context::EnvironmentContext;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
+use crate::event_stream::EventStream;
+use crate::event_stream::event_broadcast;
 use crate::exec::ExecToolCallOutput;
 #[cfg(test)]
 use crate::exec::StreamOutput;
@@ -58,6 +81,13 @@ use crate::exec_command::WriteStdinParams;
 use crate::executor::Executor;
 use crate::executor::ExecutorConfig;
 use crate::executor::normalize_exec_result;
+use crate::git_info::attached_head;
+use crate::git_info::changed_files;
+use crate::git_info::commit_created_since;
+use crate::git_info::may_create_commit;
+use crate::image_degradation;
+use crate::instrumentation::InstrumentationCoverage;
+use crate::instrumentation::InstrumentationGap;
 use crate::mcp::auth::compute_auth_statuses;
 use crate::mcp_connection_manager::McpConnectionManager;
 use crate::model_family::find_family_for_model;
@@ -74,6 +104,8 @@ use crate::protocol::AgentReasoningSectionBreakEvent;
 use crate::protocol::ApplyPatchApprovalRequestEvent;
 use crate::protocol::AskForApproval;
 use crate::protocol::BackgroundEventEvent;
+use crate::protocol::ClientContext;
+use crate::protocol::ConversationEndReason;
 use crate::protocol::ErrorEvent;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
@@ -86,24 +118,45 @@ use crate::protocol::Op;
 use crate::protocol::PatchApplyBeginEvent;
 use crate::protocol::PatchApplyEndEvent;
 use crate::protocol::RateLimitSnapshot;
+use crate::protocol::RecentEvent;
+use crate::protocol::RecentEventsEvent;
 use crate::protocol::ReviewDecision;
+use crate::protocol::ReviewFinding;
 use crate::protocol::ReviewOutputEvent;
 use crate::protocol::SandboxPolicy;
 use crate::protocol::SessionConfiguredEvent;
 use crate::protocol::StreamErrorEvent;
 use crate::protocol::Submission;
+use crate::protocol::TaskError;
+use crate::protocol::TaskErrorKind;
 use crate::protocol::TokenCountEvent;
 use crate::protocol::TokenUsage;
 use crate::protocol::TurnDiffEvent;
 use crate::protocol::WebSearchBeginEvent;
+use crate::review_slices;
+use crate::review_slices::ReviewSlice;
+use crate::review_slices::SlicePlan;
 use crate::rollout::RolloutRecorder;
 use crate::rollout::RolloutRecorderParams;
+use crate::session_features::FinishedTask;
+use crate::session_features::SessionFeatures;
+use crate::session_features::resolve_features;
 use crate::shell;
+use crate::shutdown::ShutdownStage;
+use crate::shutdown::ShutdownTimeouts;
+use crate::shutdown::StageOutcome;
+use crate::snapshot_consistency::SnapshotConsistency;
 use crate::state::ActiveTurn;
 use crate::state::SessionServices;
+use crate::strict_telemetry::STRICT_TELEMETRY_TIMEOUT;
+use crate::tasks::BackgroundTasks;
 use crate::tasks::CompactTask;
-use crate::tasks::RegularTask;
+use crate::tasks::ReasoningPhaseTracker;
 use crate::tasks::ReviewTask;
+use crate::tasks::TaskCancellation;
+use crate::tasks::TaskResult;
+use crate::tasks::TaskTemplates;
+use crate::tasks::TimelineReportTask;
 use crate::tools::ToolRouter;
 use crate::tools::context::SharedTurnDiffTracker;
 use crate::tools::format_exec_output_str;
@@ -114,7 +167,20 @@ use crate::user_instructions::UserInstructions;
 use crate::user_notification::UserNotification;
 use crate::util::backoff;
 use crate::visualizer::AgentVisualizer;
+use crate::visualizer::ContentField;
+use crate::visualizer::CwdSnapshot;
+use crate::visualizer::EventBatching;
+use crate::visualizer::EventChunking;
+use crate::visualizer::EventReplay;
+use crate::visualizer::PinStore;
+use crate::visualizer::RetentionRules;
 use crate::visualizer::SessionVisualizer;
+use crate::visualizer::TelemetryFidelity;
+use crate::visualizer::TimestampEncoding;
+use crate::visualizer::TlsFiles;
+use crate::visualizer::TrustedRoots;
+use crate::visualizer::VisualizerStatus;
+use crate::visualizer::render_html;
 use codex_otel::otel_event_manager::OtelEventManager;
 use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
 use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
@@ -135,6 +201,10 @@ pub struct Codex {
     next_id: AtomicU64,
     tx_sub: Sender<Submission>,
     rx_event: Receiver<Event>,
+    event_broadcast: broadcast::Sender<Arc<Event>>,
+    /// For the task queries; weak, so the session still drops once the
+    /// submission loop is done with it.
+    session: Weak<Session>,
 }
 
 /// Wrapper returned by [`Codex::spawn`] containing the spawned [`Codex`],
@@ -148,6 +218,9 @@ pub struct CodexSpawnOk {
 pub(crate) const INITIAL_SUBMIT_ID: &str = "";
 pub(crate) const SUBMISSION_CHANNEL_CAPACITY: usize = 64;
 
+/// How often dropped visualizer events are summarized.
+const DROP_SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
 impl Codex {
     /// Spawn a new [`Codex`] and initialize the session.
     pub async fn spawn(
@@ -158,7 +231,98 @@ impl Codex {
     ) -> CodexResult<CodexSpawnOk> {
         let (tx_sub, rx_sub) = async_channel::bounded(SUBMISSION_CHANNEL_CAPACITY);
         let (tx_event, rx_event) = async_channel::unbounded();
-        let visualizer = AgentVisualizer::from_env();
+        let fidelity = if config.coarse_visualizer_telemetry {
+            TelemetryFidelity::Coarse
+        } else {
+            TelemetryFidelity::Full
+        };
+        let visualizer = AgentVisualizer::from_env(
+            fidelity,
+            config.visualizer_idle_shutdown,
+            RetentionRules::new(config.visualizer_retention.clone()),
+            if config.visualizer_string_timestamps {
+                TimestampEncoding::String
+            } else {
+                TimestampEncoding::Number
+            },
+            TrustedRoots::new(config.visualizer_trusted_roots.clone()),
+            config.visualizer_token_placement,
+            session_source == SessionSource::Cli,
+        )
+        .with_reconnect_policy(config.visualizer_reconnect)
+        .with_max_connect_failures(config.visualizer_max_connect_failures)
+        .with_drop_policy(config.visualizer_drop_policy)
+        .with_queue_capacity(config.visualizer_queue_capacity)
+        .with_max_event_bytes(config.visualizer_max_event_bytes);
+        let visualizer = match config.visualizer_keepalive {
+            Some(interval) => {
+                visualizer.with_heartbeat(interval, config.visualizer_keepalive_timeout)
+            }
+            None => visualizer,
+        };
+        let visualizer = match config.visualizer_tls {
+            VisualizerTls::Verify => visualizer,
+            VisualizerTls::TrustOnFirstUse => {
+                visualizer.with_pinned_tls(PinStore::new(&config.codex_home))
+            }
+        };
+        let visualizer = visualizer
+            .with_tls(&TlsFiles {
+                ca_file: config.visualizer_tls_ca_file.clone(),
+                client_cert: config.visualizer_tls_client_cert.clone(),
+                client_key: config.visualizer_tls_client_key.clone(),
+            })
+            .map_err(|err| {
+                CodexErr::Fatal(format!("visualizer TLS configuration is unusable: {err}"))
+            })?;
+        let visualizer = if config.visualizer_compression {
+            visualizer.with_compression()
+        } else {
+            visualizer
+        };
+        let visualizer = if config.visualizer_chunks.enabled {
+            visualizer.with_event_chunking(EventChunking {
+                frame_bytes: config.visualizer_chunks.frame_bytes,
+                max_event_bytes: config.visualizer_chunks.max_event_bytes,
+            })
+        } else {
+            visualizer
+        };
+        let visualizer = if config.visualizer_batch.enabled {
+            visualizer.with_event_batching(EventBatching {
+                interval: Duration::from_millis(config.visualizer_batch.interval_ms),
+                max_events: config.visualizer_batch.max_events.max(1),
+                max_bytes: config.visualizer_batch.max_bytes,
+            })
+        } else {
+            visualizer
+        };
+        let visualizer =
+            visualizer.with_replay(config.visualizer_replay.enabled.then(|| EventReplay {
+                max_events: config.visualizer_replay.max_events,
+                max_age: Duration::from_secs(config.visualizer_replay.max_age_secs),
+            }));
+        let visualizer = if config.visualizer_strict_telemetry {
+            visualizer.with_strict_delivery(STRICT_TELEMETRY_TIMEOUT)
+        } else {
+            visualizer
+        };
+        let visualizer = match &config.visualizer_durable_queue_dir {
+            Some(dir) => visualizer.with_durable_queue(dir).map_err(|err| {
+                CodexErr::Fatal(format!(
+                    "visualizer durable queue {} is unusable: {err}",
+                    dir.display()
+                ))
+            })?,
+            None => visualizer,
+        };
+        // What was lost is reported as `visualizer_gap`; this only notes
+        // where the relay will see the hole.
+        let visualizer = visualizer.with_gap_detector(Arc::new(|expected, received| {
+            debug!("visualizer relay sent sequence {received} where {expected} was expected");
+        }));
+        // Last, so an eager connection already uses the pins and policy.
+        let visualizer = visualizer.with_connect_mode(config.visualizer_connect);
 
         // Visualization hook: this is where AGENTS.md guidance (plus any
         // configured overrides) is loaded into memory before the session
@@ -190,18 +354,21 @@ impl Codex {
             (false, false) => "none",
         };
 
+        let mut bootstrap = json!({
+            "instructionsSource": instructions_source,
+            "userInstructions": user_instructions,
+            "projectDocPaths": project_doc_path_strings,
+            "projectDocMaxBytes": config.project_doc_max_bytes,
+            "baseInstructions": config.base_instructions,
+        });
+        CwdSnapshot::capture(&config.cwd)
+            .await
+            .add_to(&mut bootstrap);
         visualizer
             .emit(
                 None,
                 "memory_bootstrap",
-                json!({
-                    "instructionsSource": instructions_source,
-                    "userInstructions": user_instructions,
-                    "projectDocPaths": project_doc_path_strings,
-                    "projectDocMaxBytes": config.project_doc_max_bytes,
-                    "baseInstructions": config.base_instructions,
-                    "cwd": config.cwd.display().to_string(),
-                }),
+                bootstrap,
                 Some(json!({
                     "model": config.model,
                     "provider": config.model_provider,
@@ -239,7 +406,10 @@ impl Codex {
         .await
         .map_err(|e| {
             error!("Failed to create session: {e:#}");
-            CodexErr::InternalAgentDied
+            match e.downcast_ref::<LeaseHeld>() {
+                Some(held) => CodexErr::Fatal(held.to_string()),
+                None => CodexErr::InternalAgentDied,
+            }
         })?;
         let conversation_id = session.conversation_id;
 
@@ -267,6 +437,7 @@ impl Codex {
                     "sandboxPolicy": config.sandbox_policy.clone(),
                     "approvalPolicy": config.approval_policy,
                     "sessionSource": session_source,
+                    "features": session.features(),
                 }),
                 Some(session_loop_state),
             )
@@ -275,6 +446,8 @@ impl Codex {
             next_id: AtomicU64::new(0),
             tx_sub,
             rx_event,
+            event_broadcast: session.event_broadcast.clone(),
+            session: Arc::downgrade(&session),
         };
 
         Ok(CodexSpawnOk {
@@ -312,9 +485,59 @@ impl Codex {
             .map_err(|_| CodexErr::InternalAgentDied)?;
         Ok(event)
     }
+
+    /// An additional, independent stream of the events sent from now on.
+    /// See [`EventStream`] for its lag semantics. [`Self::next_event`] is not
+    /// affected and still receives every event.
+    pub fn event_stream(&self) -> EventStream {
+        EventStream::new(&self.event_broadcast)
+    }
+
+    /// Resolves once no task is running and what ended the last one has
+    /// been sent as an event. Returns at once if the session is idle or
+    /// has shut down.
+    pub async fn wait_for_idle(&self) {
+        if let Some(session) = self.session.upgrade() {
+            session.wait_for_idle().await;
+        }
+    }
+
+    /// Abort the running task, if any, on the user's behalf, e.g. when an
+    /// embedding REPL or CLI sees Ctrl-C. Its `TurnAborted` and
+    /// `task_aborted` carry [`TurnAbortReason::UserRequested`], which tells
+    /// the stop apart from a task being replaced. Does nothing once the
+    /// session has shut down.
+    pub async fn request_abort(&self) {
+        if let Some(session) = self.session.upgrade() {
+            session.request_abort().await;
+        }
+    }
+
+    /// How many tasks are running; `0` once the session has shut down.
+    pub async fn active_task_count(&self) -> usize {
+        match self.session.upgrade() {
+            Some(session) => session.active_task_count().await,
+            None => 0,
+        }
+    }
+
+    /// The kinds of the running tasks, in the order they were spawned, as
+    /// telemetry reports them: `Regular`, `Review` or `Compact`.
+    pub async fn task_kinds(&self) -> Vec<String> {
+        match self.session.upgrade() {
+            Some(session) => session
+                .task_kinds()
+                .await
+                .iter()
+                .map(|kind| format!("{kind:?}"))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
 use crate::state::SessionState;
+use crate::task_revert::TaskWrites;
 
 /// Context for an initialized model agent
 ///
@@ -322,15 +545,27 @@ use crate::state::SessionState;
 pub(crate) struct Session {
     conversation_id: ConversationId,
     tx_event: Sender<Event>,
-    state: Mutex<SessionState>,
+    pub(crate) state: Mutex<SessionState>,
     pub(crate) active_turn: Mutex<Option<ActiveTurn>>,
+    /// Held while the active turn's tasks are aborted or replaced and the
+    /// transition is reported; see `report_transition` in `tasks`.
+    pub(crate) task_transitions: Mutex<()>,
+    /// Aborts whose task was halted but that are not reported yet; see
+    /// [`Session::wait_for_idle`].
+    pub(crate) aborts_in_flight: AtomicUsize,
+    /// Notified whenever the active turn is cleared; see
+    /// [`Session::wait_for_idle`].
+    pub(crate) idle: Notify,
+    /// System tasks outside the active turn; see `tasks::BackgroundTasks`.
+    pub(crate) background_tasks: BackgroundTasks,
     pub(crate) services: SessionServices,
     next_internal_sub_id: AtomicU64,
-    visualizer: SessionVisualizer,
+    pub(crate) visualizer: SessionVisualizer,
+    event_broadcast: broadcast::Sender<Arc<Event>>,
 }
 
 /// The context needed for a single turn of the conversation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct TurnContext {
     pub(crate) client: ModelClient,
     /// The session's current working directory. All relative paths provided by
@@ -462,8 +697,16 @@ impl Session {
             anyhow::anyhow!("failed to initialize rollout recorder: {e:#}")
         })?;
         let rollout_path = rollout_recorder.rollout_path.clone();
+        let (conversation_lease, visualizer) = conversation_lease::claim(
+            &rollout_path,
+            conversation_id,
+            config.conversation_lease_conflict,
+            visualizer,
+        )
+        .await?;
         // Create the mutable state for the Session.
-        let state = SessionState::new();
+        let mut state = SessionState::new();
+        state.activity = ActivityLog::new(config.digest_retained_turns);
 
         // Handle MCP manager result and record any startup failures.
         let (mcp_connection_manager, failed_clients) = match mcp_res {
@@ -553,11 +796,25 @@ impl Session {
             rollout: Mutex::new(Some(rollout_recorder)),
             user_shell: default_shell,
             show_raw_agent_reasoning: config.show_raw_agent_reasoning,
+            warn_on_invalid_cwd: config.warn_on_invalid_cwd,
+            task_timeout: config.task_timeout,
+            task_retry: config.task_retry,
+            task_progress_interval: config.task_progress_interval,
+            task_input_chunk_size: config.task_input_chunk_size,
+            features: resolve_features(&config),
+            approval_limits: config.approval_limits,
+            paused: watch::channel(false).0,
             executor: Executor::new(ExecutorConfig::new(
                 turn_context.sandbox_policy.clone(),
                 turn_context.cwd.clone(),
                 config.codex_linux_sandbox_exe.clone(),
             )),
+            _conversation_lease: conversation_lease,
+            task_templates: TaskTemplates::new(config.task_templates.clone()),
+            turn_templates_dir: config.turn_templates_dir.clone(),
+            image_degradation: config.image_degradation,
+            instrumentation: InstrumentationCoverage::default(),
+            snapshot_consistency: SnapshotConsistency::new(config.visualizer_consistency_checks),
         };
 
         let sess = Arc::new(Session {
@@ -565,11 +822,20 @@ impl Session {
             tx_event: tx_event.clone(),
             state: Mutex::new(state),
             active_turn: Mutex::new(None),
+            task_transitions: Mutex::new(()),
+            aborts_in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            background_tasks: BackgroundTasks::default(),
             services,
             next_internal_sub_id: AtomicU64::new(0),
             visualizer: SessionVisualizer::new(visualizer, conversation_id),
+            event_broadcast: event_broadcast(),
         });
 
+        sess.watch_telemetry_failures();
+        sess.watch_visualizer_abandonment();
+        sess.watch_visualizer_drops();
+
         // Dispatch the SessionConfiguredEvent first and then report any errors.
         // If resuming, include converted initial messages in the payload so UIs can render them immediately.
         let initial_messages = initial_history.get_event_msgs();
@@ -592,6 +858,7 @@ impl Session {
         for event in events {
             sess.send_event(event).await;
         }
+        sess.report_recovered_task(INITIAL_SUBMIT_ID).await;
 
         Ok((sess, turn_context))
     }
@@ -600,7 +867,7 @@ impl Session {
         self.tx_event.clone()
     }
 
-    fn next_internal_sub_id(&self) -> String {
+    pub(crate) fn next_internal_sub_id(&self) -> String {
         let id = self
             .next_internal_sub_id
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
@@ -621,6 +888,9 @@ impl Session {
             InitialHistory::Resumed(_) | InitialHistory::Forked(_) => {
                 let rollout_items = conversation_history.get_rollout_items();
                 let persist = matches!(conversation_history, InitialHistory::Forked(_));
+                if !persist {
+                    self.detect_incomplete_task(&rollout_items).await;
+                }
 
                 // Always add response items to conversation history
                 let reconstructed_history =
@@ -633,12 +903,35 @@ impl Session {
                 if persist && !rollout_items.is_empty() {
                     self.persist_rollout_items(&rollout_items).await;
                 }
+
+                // Files written before review findings were persisted simply
+                // have none to restore.
+                let findings = rollout_items.iter().rev().find_map(|item| match item {
+                    RolloutItem::ReviewFindings(item) => Some(item.findings.clone()),
+                    _ => None,
+                });
+                if let Some(findings) = findings {
+                    let count = findings.len();
+                    self.state.lock().await.review_findings = findings;
+                    self.emit_with_state("findings_restored", json!({ "count": count }))
+                        .await;
+                }
             }
         }
     }
 
+    /// See [`Codex::event_stream`].
+    #[cfg(test)]
+    pub(crate) fn event_stream(&self) -> EventStream {
+        EventStream::new(&self.event_broadcast)
+    }
+
     /// Persist the event to rollout and send it to clients.
     pub(crate) async fn send_event(&self, event: Event) {
+        if let EventMsg::Error(error) = &event.msg {
+            self.record_error(&event.id, &error.message).await;
+        }
+        let content = self.protocol_event_content(&event).await;
         let event_value = match serde_json::to_value(&event) {
             Ok(value) => value,
             Err(err) => json!({
@@ -646,50 +939,69 @@ impl Session {
             }),
         };
         // Persist the event into rollout (recorder filters as needed)
-        let rollout_items = vec![RolloutItem::EventMsg(event.msg.clone())];
+        // The checkpoint goes in the same write, so it is never lost alone.
+        let mut rollout_items = vec![RolloutItem::EventMsg(event.msg.clone())];
+        rollout_items.extend(self.task_checkpoint(&event).await);
         self.persist_rollout_items(&rollout_items).await;
+        if self.event_broadcast.receiver_count() > 0 {
+            // Only fails when every stream has been dropped in the meantime.
+            let _ = self.event_broadcast.send(Arc::new(event.clone()));
+        }
         if let Err(e) = self.tx_event.send(event).await {
             error!("failed to send tool call event: {e}");
         }
         let state = self.visualization_state_snapshot().await;
         self.visualizer
-            .emit(
+            .emit_scoped(
                 "protocol_event",
                 json!({ "event": event_value }),
+                content,
                 Some(state),
             )
             .await;
     }
 
+    /// Remember the latest client context for state snapshots.
+    async fn remember_client_context(&self, client_context: Option<&ClientContext>) {
+        if let Some(client_context) = client_context {
+            self.state.lock().await.client_context = Some(client_context.clone());
+        }
+    }
+
     async fn visualization_state_snapshot(&self) -> Value {
-        let (history_items, token_info, rate_limits) = {
+        let (history_items, token_info, rate_limits, client_context, review_findings) = {
             let state = self.state.lock().await;
             (
                 state.history.len(),
                 state.token_info.clone(),
                 state.latest_rate_limits.clone(),
+                state.client_context.clone(),
+                state.review_findings.len(),
             )
         };
 
-        let (active_tasks, turn_state) = {
+        let tasks = self.running_tasks().await;
+        #[cfg(test)]
+        let tasks = if self.services.snapshot_consistency.hides_tasks() {
+            Vec::new()
+        } else {
+            tasks
+        };
+        let active_tasks = tasks
+            .into_iter()
+            .map(|task| {
+                json!({
+                    "subId": task.sub_id,
+                    "kind": format!("{:?}", task.kind),
+                    "status": format!("{:?}", task.status),
+                })
+            })
+            .collect::<Vec<_>>();
+        let turn_state = {
             let active = self.active_turn.lock().await;
-            if let Some(active_turn) = active.as_ref() {
-                (
-                    active_turn
-                        .tasks
-                        .iter()
-                        .map(|(sub_id, task)| {
-                            json!({
-                                "subId": sub_id,
-                                "kind": format!("{:?}", task.kind),
-                            })
-                        })
-                        .collect::<Vec<_>>(),
-                    Some(Arc::clone(&active_turn.turn_state)),
-                )
-            } else {
-                (Vec::new(), None)
-            }
+            active
+                .as_ref()
+                .map(|active_turn| Arc::clone(&active_turn.turn_state))
         };
 
         let (pending_approvals, pending_inputs) = if let Some(turn_state) = turn_state {
@@ -702,26 +1014,200 @@ impl Session {
             (0, 0)
         };
 
-        json!({
+        let mut snapshot = json!({
             "activeTasks": active_tasks,
             "pendingApprovals": pending_approvals,
             "pendingInputs": pending_inputs,
             "historyItems": history_items,
             "tokenInfo": token_info,
             "rateLimits": rate_limits,
-        })
+            "reviewFindings": review_findings,
+            "features": self.features(),
+        });
+        if let Some(client_context) = client_context {
+            snapshot["clientContext"] = json!(client_context);
+        }
+        snapshot
+    }
+
+    /// At teardown of the task `sub_id`, report any required instrumentation
+    /// it did not emit; see [`Self::report_instrumentation_gap`].
+    pub(crate) async fn check_instrumentation(&self, sub_id: &str, task: FinishedTask) {
+        if let Some(gap) = self.services.instrumentation.torn_down(sub_id, task) {
+            self.report_instrumentation_gap(sub_id, gap).await;
+        }
+    }
+
+    /// Emit `gap` as an `instrumentation_gap` and log a warning naming the
+    /// missing action types.
+    pub(crate) async fn report_instrumentation_gap(&self, sub_id: &str, gap: InstrumentationGap) {
+        warn!(
+            "task {sub_id} was torn down without emitting {}",
+            gap.missing.join(", ")
+        );
+        self.emit_with_state(
+            "instrumentation_gap",
+            json!({
+                "subId": sub_id,
+                "missing": gap.missing,
+                "fired": gap.fired,
+                "aborted": gap.aborted,
+            }),
+        )
+        .await;
+    }
+
+    /// Render this session's recent visualizer events as a standalone HTML
+    /// timeline and write it to `path`.
+    pub(crate) async fn write_timeline_report(&self, path: &Path) -> std::io::Result<()> {
+        let html = render_html(&self.visualizer.recent_events());
+        tokio::fs::write(path, html).await
+    }
+
+    /// Tell the client once the visualizer gives up on its relay. Holds the
+    /// session weakly, so the watcher ends with it.
+    pub(crate) fn watch_visualizer_abandonment(self: &Arc<Self>) {
+        let Some(mut abandonment) = self.visualizer.abandonment() else {
+            return;
+        };
+        let session = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let failures = match abandonment.wait_for(Option::is_some).await {
+                Ok(failures) => (*failures).unwrap_or_default(),
+                Err(_) => return,
+            };
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+            let sub_id = session.state.lock().await.last_task_sub_id.clone();
+            session
+                .send_event(Event {
+                    id: sub_id.unwrap_or_default(),
+                    msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
+                        message: format!(
+                            "Visualizer disconnected permanently: the relay could not be reached {failures} times in a row"
+                        ),
+                    }),
+                })
+                .await;
+        });
+    }
+
+    /// Every [`DROP_SUMMARY_INTERVAL`], emit `visualizer_events_dropped`
+    /// if the visualizer queue dropped events since the last summary,
+    /// `visualizer_events_sampled` if sampling dropped any, and
+    /// `visualizer_gap` with the events lost since the last gap; the gap is
+    /// also reported as soon as the relay is reconnected to. Holds the
+    /// session weakly, so the watcher ends with it.
+    pub(crate) fn watch_visualizer_drops(self: &Arc<Self>) {
+        if self.visualizer.status() == VisualizerStatus::Disabled {
+            return;
+        }
+        let session = Arc::downgrade(self);
+        let mut recoveries = self.visualizer.gap_recoveries();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(DROP_SUMMARY_INTERVAL);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    recovered = recoveries.changed() => {
+                        if recovered.is_err() {
+                            return;
+                        }
+                    }
+                }
+                let Some(session) = session.upgrade() else {
+                    return;
+                };
+                if let Some(summary) = session.visualizer.drop_summary() {
+                    session
+                        .visualizer
+                        .emit("visualizer_events_dropped", summary, None)
+                        .await;
+                }
+                if let Some(summary) = session.visualizer.sampling_summary() {
+                    session
+                        .visualizer
+                        .emit("visualizer_events_sampled", summary, None)
+                        .await;
+                }
+                if let Some(gap) = session.visualizer.take_gap() {
+                    session
+                        .visualizer
+                        .emit("visualizer_gap", gap.to_json(), None)
+                        .await;
+                }
+            }
+        });
     }
 
     pub(crate) async fn emit_with_state(&self, action_type: &str, action: Value) {
+        #[cfg(test)]
+        if self.services.instrumentation.is_skipped(action_type) {
+            return;
+        }
+        self.services.instrumentation.record(action_type, &action);
         let state = self.visualization_state_snapshot().await;
+        self.check_snapshot(action_type, &action, &state).await;
         self.visualizer.emit(action_type, action, Some(state)).await;
     }
 
+    /// Report `state` as a `telemetry_inconsistency` if it disagrees with
+    /// the active turn; see `snapshot_consistency`.
+    async fn check_snapshot(&self, action_type: &str, action: &Value, state: &Value) {
+        let checks = &self.services.snapshot_consistency;
+        if !checks.checks(action_type) {
+            return;
+        }
+        let running = self
+            .running_tasks()
+            .await
+            .into_iter()
+            .map(|task| task.sub_id)
+            .collect();
+        let sub_id = action.get("subId").and_then(Value::as_str);
+        let Some(inconsistency) = checks.check(action_type, sub_id, state, running) else {
+            return;
+        };
+        warn!(
+            "state snapshot of `{action_type}` disagrees with the active turn: {}",
+            inconsistency.problems.join("; ")
+        );
+        self.visualizer
+            .emit(
+                "telemetry_inconsistency",
+                inconsistency.to_json(action_type, sub_id),
+                None,
+            )
+            .await;
+    }
+
+    /// Like [`Session::emit_with_state`] for actions carrying file contents.
+    pub(crate) async fn emit_scoped_with_state(
+        &self,
+        action_type: &str,
+        action: Value,
+        content: Vec<ContentField>,
+    ) {
+        #[cfg(test)]
+        if self.services.instrumentation.is_skipped(action_type) {
+            return;
+        }
+        self.services.instrumentation.record(action_type, &action);
+        let state = self.visualization_state_snapshot().await;
+        self.check_snapshot(action_type, &action, &state).await;
+        self.visualizer
+            .emit_scoped(action_type, action, content, Some(state))
+            .await;
+    }
+
     /// Emit an exec approval request event and await the user's decision.
     ///
-    /// The request is keyed by `sub_id`/`call_id` so matching responses are delivered
-    /// to the correct in-flight turn. If the task is aborted, this returns the
-    /// default `ReviewDecision` (`Denied`).
+    /// The request is keyed by `call_id` so each response is delivered to the
+    /// request it answers, even with several outstanding for one turn. If the task is aborted, this returns the
+    /// default `ReviewDecision` (`Denied`). See [`Self::await_approval`] for
+    /// how approval limits apply.
     pub async fn request_command_approval(
         &self,
         sub_id: String,
@@ -729,35 +1215,14 @@ impl Session {
         command: Vec<String>,
         cwd: PathBuf,
         reason: Option<String>,
-    ) -> ReviewDecision {
-        // Add the tx_approve callback to the map before sending the request.
-        let (tx_approve, rx_approve) = oneshot::channel();
-        let event_id = sub_id.clone();
-        let prev_entry = {
-            let mut active = self.active_turn.lock().await;
-            match active.as_mut() {
-                Some(at) => {
-                    let mut ts = at.turn_state.lock().await;
-                    ts.insert_pending_approval(sub_id, tx_approve)
-                }
-                None => None,
-            }
-        };
-        if prev_entry.is_some() {
-            warn!("Overwriting existing pending approval for sub_id: {event_id}");
-        }
-
-        let event = Event {
-            id: event_id,
-            msg: EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
-                call_id,
-                command,
-                cwd,
-                reason,
-            }),
-        };
-        self.send_event(event).await;
-        rx_approve.await.unwrap_or_default()
+    ) -> ApprovalResolution {
+        let msg = EventMsg::ExecApprovalRequest(ExecApprovalRequestEvent {
+            call_id: call_id.clone(),
+            command,
+            cwd,
+            reason,
+        });
+        self.await_approval(sub_id, &call_id, msg).await
     }
 
     pub async fn request_patch_approval(
@@ -767,44 +1232,118 @@ impl Session {
         action: &ApplyPatchAction,
         reason: Option<String>,
         grant_root: Option<PathBuf>,
-    ) -> oneshot::Receiver<ReviewDecision> {
+    ) -> ApprovalResolution {
+        let msg = EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
+            call_id: call_id.clone(),
+            changes: convert_apply_patch_to_protocol(action),
+            reason,
+            grant_root,
+        });
+        self.await_approval(sub_id, &call_id, msg).await
+    }
+
+    /// Send an approval request and wait for it to resolve.
+    ///
+    /// With a per-task cap configured, the request is not sent until one of
+    /// the task's outstanding requests resolves. With a timeout configured,
+    /// a request left unanswered for that much unpaused time resolves as
+    /// [`ApprovalResolution::ApprovalTimedOut`] and an `approval_expired`
+    /// visualizer event is emitted. A task with a shorter
+    /// [`crate::tasks::SessionTask::approval_timeout`] of its own is held to
+    /// that instead, and told when it runs out.
+    async fn await_approval(
+        &self,
+        sub_id: String,
+        call_id: &str,
+        request: EventMsg,
+    ) -> ApprovalResolution {
+        let limits = self.services.approval_limits;
+        let turn_state = {
+            let active = self.active_turn.lock().await;
+            active.as_ref().map(|at| Arc::clone(&at.turn_state))
+        };
+
+        // Held until this function returns, i.e. while the request is outstanding.
+        let _slot = match (limits.max_outstanding_per_task, &turn_state) {
+            (Some(max), Some(turn_state)) => {
+                let slots = turn_state.lock().await.approval_slots(&sub_id, max);
+                slots.acquire_owned().await.ok()
+            }
+            _ => None,
+        };
+
         // Add the tx_approve callback to the map before sending the request.
         let (tx_approve, rx_approve) = oneshot::channel();
-        let event_id = sub_id.clone();
-        let prev_entry = {
-            let mut active = self.active_turn.lock().await;
-            match active.as_mut() {
-                Some(at) => {
-                    let mut ts = at.turn_state.lock().await;
-                    ts.insert_pending_approval(sub_id, tx_approve)
-                }
-                None => None,
-            }
+        let prev_entry = match &turn_state {
+            Some(turn_state) => turn_state.lock().await.insert_pending_approval(
+                sub_id.clone(),
+                call_id.to_string(),
+                tx_approve,
+            ),
+            None => None,
         };
         if prev_entry.is_some() {
-            warn!("Overwriting existing pending approval for sub_id: {event_id}");
+            warn!("Overwriting existing pending approval for call_id: {call_id}");
         }
 
-        let event = Event {
-            id: event_id,
-            msg: EventMsg::ApplyPatchApprovalRequest(ApplyPatchApprovalRequestEvent {
-                call_id,
-                changes: convert_apply_patch_to_protocol(action),
-                reason,
-                grant_root,
-            }),
+        self.send_event(Event {
+            id: sub_id.clone(),
+            msg: request,
+        })
+        .await;
+
+        let task = self.running_task(&sub_id).await;
+        let task_timeout = task.as_ref().and_then(|task| task.approval_timeout());
+        let timeout = match (limits.timeout, task_timeout) {
+            (Some(limit), Some(task_timeout)) => Some(limit.min(task_timeout)),
+            (limit, task_timeout) => limit.or(task_timeout),
         };
-        self.send_event(event).await;
-        rx_approve
+        let Some(timeout) = timeout else {
+            return ApprovalResolution::Decided(rx_approve.await.unwrap_or_default());
+        };
+        let resolution = tokio::select! {
+            decision = rx_approve => ApprovalResolution::Decided(decision.unwrap_or_default()),
+            () = sleep_unpaused(timeout, self.services.paused.subscribe()) => {
+                if let Some(turn_state) = &turn_state {
+                    turn_state.lock().await.remove_pending_approval(call_id);
+                }
+                self.emit_with_state(
+                    "approval_expired",
+                    json!({
+                        "subId": sub_id,
+                        "callId": call_id,
+                        "timeoutMs": timeout.as_millis() as u64,
+                    }),
+                )
+                .await;
+                self.notify_background_event(&sub_id, "approval request timed out; treating it as denied")
+                    .await;
+                ApprovalResolution::ApprovalTimedOut
+            }
+        };
+        if resolution == ApprovalResolution::ApprovalTimedOut
+            && let Some(task) = task.filter(|_| task_timeout == Some(timeout))
+        {
+            task.approval_timed_out();
+        }
+        resolution
+    }
+
+    /// Pause or resume the session's approval timeouts.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.services.paused.send_replace(paused);
     }
 
-    pub async fn notify_approval(&self, sub_id: &str, decision: ReviewDecision) {
+    /// Deliver `decision` to the approval request `id` names: the request
+    /// with that call id, or else the oldest outstanding request of the task
+    /// with that sub id.
+    pub async fn notify_approval(&self, id: &str, decision: ReviewDecision) {
         let entry = {
             let mut active = self.active_turn.lock().await;
             match active.as_mut() {
                 Some(at) => {
                     let mut ts = at.turn_state.lock().await;
-                    ts.remove_pending_approval(sub_id)
+                    ts.take_pending_approval(id)
                 }
                 None => None,
             }
@@ -814,7 +1353,7 @@ impl Session {
                 tx_approve.send(decision).ok();
             }
             None => {
-                warn!("No pending approval found for sub_id: {sub_id}");
+                warn!("No pending approval found for id: {id}");
             }
         }
     }
@@ -826,6 +1365,53 @@ impl Session {
         self.persist_rollout_response_items(items).await;
     }
 
+    /// Pick the files of `target` that the review `sub_id` covers and move
+    /// the session's review cursor to them, warning when it had to be reset.
+    /// `None` reviews the whole target; reviews without a target, such as
+    /// custom prompts, are never sliced.
+    async fn plan_review_slice(
+        &self,
+        cwd: &Path,
+        sub_id: &str,
+        target: Option<&ReviewTarget>,
+        resume: bool,
+        slice_files: usize,
+    ) -> Option<ReviewSlice> {
+        let files = changed_files(cwd, target?).await.unwrap_or_default();
+        let SlicePlan { slice, warning, .. } = {
+            let mut state = self.state.lock().await;
+            let mut plan =
+                review_slices::plan(state.review_cursor.as_ref(), files, resume, slice_files);
+            state.review_cursor = plan.cursor.take();
+            plan
+        };
+        if let Some(warning) = warning {
+            self.notify_background_event(sub_id, warning).await;
+        }
+        slice
+    }
+
+    /// End the running review slice, advancing the cursor past it if the
+    /// review `completed`.
+    async fn finish_review_slice(&self, completed: bool) -> Option<ReviewSlice> {
+        self.state
+            .lock()
+            .await
+            .review_cursor
+            .as_mut()?
+            .finish(completed)
+    }
+
+    /// Keep the findings of a completed review and write them to the rollout
+    /// so a resumed session still has them.
+    async fn record_review_findings(&self, findings: Vec<ReviewFinding>) {
+        let item = RolloutItem::ReviewFindings(ReviewFindingsItem {
+            findings: findings.clone(),
+        });
+        self.persist_rollout_items(&[item]).await;
+        self.state.lock().await.review_findings = findings;
+    }
+
     fn reconstruct_history_from_rollout(
         &self,
         turn_context: &TurnContext,
@@ -859,11 +1445,6 @@ impl Session {
         state.record_items(items.iter());
     }
 
-    async fn replace_history(&self, items: Vec<ResponseItem>) {
-        let mut state = self.state.lock().await;
-        state.replace_history(items);
-    }
-
     async fn persist_rollout_response_items(&self, items: &[ResponseItem]) {
         let rollout_items: Vec<RolloutItem> = items
             .iter()
@@ -887,7 +1468,7 @@ impl Session {
         items
     }
 
-    async fn persist_rollout_items(&self, items: &[RolloutItem]) {
+    pub(crate) async fn persist_rollout_items(&self, items: &[RolloutItem]) {
         let recorder = {
             let guard = self.services.rollout.lock().await;
             guard.clone()
@@ -960,10 +1541,28 @@ impl Session {
         // Add to conversation history and persist response item to rollout
         self.record_conversation_items(std::slice::from_ref(&response_item))
             .await;
+        self.persist_usermsg(&response_item).await;
+    }
+
+    /// [`Self::record_input_and_rollout_usermsg`] for input whose images were
+    /// reduced: the history the model sees gets `degraded`, while the rollout
+    /// keeps `original` as submitted.
+    async fn record_degraded_input_and_rollout_usermsg(
+        &self,
+        degraded: ResponseInputItem,
+        original: &ResponseInputItem,
+    ) {
+        self.record_into_history(&[degraded.into()]).await;
+        let response_item: ResponseItem = original.clone().into();
+        self.persist_rollout_response_items(std::slice::from_ref(&response_item))
+            .await;
+        self.persist_usermsg(&response_item).await;
+    }
 
-        // Derive user message events and persist only UserMessage to rollout
+    /// Derive user message events and persist only UserMessage to rollout.
+    async fn persist_usermsg(&self, response_item: &ResponseItem) {
         let msgs =
-            map_response_item_to_event_messages(&response_item, self.show_raw_agent_reasoning());
+            map_response_item_to_event_messages(response_item, self.show_raw_agent_reasoning());
         let user_msgs: Vec<RolloutItem> = msgs
             .into_iter()
             .filter_map(|m| match m {
@@ -976,6 +1575,77 @@ impl Session {
         }
     }
 
+    /// Run the image degradation ladder over a user turn's `input` when its
+    /// images would take the conversation over the context budget, and tell
+    /// the user and the visualizer exactly what was reduced.
+    async fn degrade_image_input(
+        &self,
+        turn_context: &TurnContext,
+        sub_id: &str,
+        input: &[InputItem],
+    ) -> Option<Vec<InputItem>> {
+        if input
+            .iter()
+            .all(|item| matches!(item, InputItem::Text { .. }))
+        {
+            return None;
+        }
+        let budget_tokens = turn_context
+            .client
+            .get_auto_compact_token_limit()
+            .and_then(|limit| u64::try_from(limit).ok())
+            .or_else(|| turn_context.client.get_model_context_window())?;
+        let used_tokens = self
+            .state
+            .lock()
+            .await
+            .token_info
+            .as_ref()
+            .map_or(0, |info| info.last_token_usage.tokens_in_context_window());
+        let ladder = self.services.image_degradation;
+        let input = input.to_vec();
+        let (degraded, degradation) = tokio::task::spawn_blocking(move || {
+            image_degradation::degrade(&input, used_tokens, budget_tokens, ladder)
+        })
+        .await
+        .ok()??;
+
+        let mut payload = json!(degradation);
+        payload["subId"] = json!(sub_id);
+        self.emit_with_state("input_degraded", payload).await;
+        self.notify_background_event(sub_id, degradation.describe())
+            .await;
+        Some(degraded)
+    }
+
+    /// Record input the user sent while the task was running, degrading its
+    /// images the same way as a turn's initial input. Returns the item to
+    /// send to the model.
+    async fn record_pending_input(
+        &self,
+        turn_context: &TurnContext,
+        sub_id: &str,
+        input: Vec<InputItem>,
+    ) -> ResponseItem {
+        let degraded = self.degrade_image_input(turn_context, sub_id, &input).await;
+        let original: ResponseItem = ResponseInputItem::from(input).into();
+        match degraded {
+            Some(degraded) => {
+                let degraded: ResponseItem = ResponseInputItem::from(degraded).into();
+                self.record_into_history(std::slice::from_ref(&degraded))
+                    .await;
+                self.persist_rollout_response_items(std::slice::from_ref(&original))
+                    .await;
+                degraded
+            }
+            None => {
+                self.record_conversation_items(std::slice::from_ref(&original))
+                    .await;
+                original
+            }
+        }
+    }
+
     async fn on_exec_command_begin(
         &self,
         turn_diff_tracker: SharedTurnDiffTracker,
@@ -998,6 +1668,7 @@ impl Session {
                     let mut tracker = turn_diff_tracker.lock().await;
                     tracker.on_patch_begin(&changes);
                 }
+                self.check_focus_drift(&sub_id, &call_id, &changes).await;
 
                 EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
                     call_id,
@@ -1100,6 +1771,11 @@ impl Session {
         let is_apply_patch = context.apply_patch.is_some();
         let sub_id = context.sub_id.clone();
         let call_id = context.call_id.clone();
+        let head_before = if !is_apply_patch && may_create_commit(&context.command_for_display) {
+            attached_head(&context.cwd).await
+        } else {
+            None
+        };
 
         self.on_exec_command_begin(turn_diff_tracker.clone(), context.clone())
             .await;
@@ -1110,6 +1786,22 @@ impl Session {
             .run(request, self, approval_policy, &context)
             .await;
 
+        if let Some(before) = head_before
+            && let Some(commit) = commit_created_since(&context.cwd, &before).await
+        {
+            self.emit_with_state(
+                "git_commit_created",
+                json!({
+                    "subId": sub_id,
+                    "callId": call_id,
+                    "sha": commit.sha,
+                    "subject": commit.subject,
+                    "filesChanged": commit.files_changed,
+                }),
+            )
+            .await;
+        }
+
         let normalized = normalize_exec_result(&result);
         let borrowed = normalized.event_output();
 
@@ -1166,14 +1858,14 @@ impl Session {
         match active.as_mut() {
             Some(at) => {
                 let mut ts = at.turn_state.lock().await;
-                ts.push_pending_input(input.into());
+                ts.push_pending_input(input);
                 Ok(())
             }
             None => Err(input),
         }
     }
 
-    pub async fn get_pending_input(&self) -> Vec<ResponseInputItem> {
+    pub async fn get_pending_input(&self) -> Vec<Vec<InputItem>> {
         let mut active = self.active_turn.lock().await;
         match active.as_mut() {
             Some(at) => {
@@ -1189,10 +1881,11 @@ impl Session {
         server: &str,
         tool: &str,
         arguments: Option<serde_json::Value>,
+        progress: Option<ProgressSender>,
     ) -> anyhow::Result<CallToolResult> {
         self.services
             .mcp_connection_manager
-            .call_tool(server, tool, arguments)
+            .call_tool(server, tool, arguments, progress)
             .await
     }
 
@@ -1244,6 +1937,13 @@ impl Session {
         self.abort_all_tasks(TurnAbortReason::Interrupted).await;
     }
 
+    /// Abort the running task, if any, on the user's behalf; see
+    /// [`Codex::request_abort`].
+    pub(crate) async fn request_abort(self: &Arc<Self>) {
+        info!("abort requested: abort current task, if any");
+        self.abort_all_tasks(TurnAbortReason::UserRequested).await;
+    }
+
     fn interrupt_task_sync(&self) {
         if let Ok(mut active) = self.active_turn.try_lock()
             && let Some(at) = active.as_mut()
@@ -1254,6 +1954,7 @@ impl Session {
             for (_sub_id, task) in tasks {
                 task.handle.abort();
             }
+            self.idle.notify_waiters();
         }
     }
 
@@ -1268,6 +1969,12 @@ impl Session {
     fn show_raw_agent_reasoning(&self) -> bool {
         self.services.show_raw_agent_reasoning
     }
+
+    /// Optional behaviours enabled for this session, keyed by
+    /// [`FEATURE_REGISTRY`](crate::session_features::FEATURE_REGISTRY) name.
+    pub(crate) fn features(&self) -> &SessionFeatures {
+        &self.services.features
+    }
 }
 
 impl Drop for Session {
@@ -1291,6 +1998,9 @@ async fn submission_loop(
 ) {
     // Wrap once to avoid cloning TurnContext for each task.
     let mut turn_context = Arc::new(turn_context);
+    let end_guard = ConversationEndGuard::new(Arc::clone(&sess));
+    let mut end_reason = ConversationEndReason::Shutdown;
+    let mut shutdown_sub_id = None;
     // To break out of this loop, send Op::Shutdown.
     while let Ok(sub) = rx_sub.recv().await {
         debug!(?sub, "Submission");
@@ -1397,7 +2107,12 @@ async fn submission_loop(
                     .await;
                 }
             }
-            Op::UserInput { items } => {
+            Op::UserInput {
+                items,
+                client_context,
+                replay_of,
+            } => {
+                sess.remember_client_context(client_context.as_ref()).await;
                 turn_context
                     .client
                     .get_otel_event_manager()
@@ -1410,8 +2125,14 @@ async fn submission_loop(
                 // attempt to inject input into current task
                 if let Err(items) = sess.inject_input(items).await {
                     // no current task, spawn a new one
-                    sess.spawn_task(Arc::clone(&turn_context), sub.id, items, RegularTask)
-                        .await;
+                    sess.spawn_user_turn(
+                        Arc::clone(&turn_context),
+                        sub.id,
+                        items,
+                        client_context,
+                        replay_of,
+                    )
+                    .await;
                 }
             }
             Op::UserTurn {
@@ -1423,7 +2144,10 @@ async fn submission_loop(
                 effort,
                 summary,
                 final_output_json_schema,
+                client_context,
+                replay_of,
             } => {
+                sess.remember_client_context(client_context.as_ref()).await;
                 turn_context
                     .client
                     .get_otel_event_manager()
@@ -1518,8 +2242,14 @@ async fn submission_loop(
                     // and `final_output_json_schema` so the UI can open a phase
                     // lane that reflects the effective configuration.
                     // no current task, spawn a new one with the per-turn context
-                    sess.spawn_task(Arc::clone(&turn_context), sub.id, items, RegularTask)
-                        .await;
+                    sess.spawn_user_turn(
+                        Arc::clone(&turn_context),
+                        sub.id,
+                        items,
+                        client_context,
+                        replay_of,
+                    )
+                    .await;
                 }
             }
             Op::ExecApproval { id, decision } => match decision {
@@ -1646,38 +2376,102 @@ async fn submission_loop(
                     // the triggering token counts, `sub.id`, and the prompt
                     // items that will be summarized so the UI can show why
                     // tool/model calls were paused.
-                    sess.spawn_task(Arc::clone(&turn_context), sub.id, items, CompactTask)
-                        .await;
+                    sess.spawn_task(
+                        Arc::clone(&turn_context),
+                        sub.id,
+                        items,
+                        CompactTask::default(),
+                    )
+                    .await;
                 }
             }
-            Op::Shutdown => {
-                sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
-                info!("Shutting down Codex instance");
-
-                // Gracefully flush and shutdown rollout recorder on session end so tests
-                // that inspect the rollout file do not race with the background writer.
-                let recorder_opt = {
-                    let mut guard = sess.services.rollout.lock().await;
-                    guard.take()
-                };
-                if let Some(rec) = recorder_opt
-                    && let Err(e) = rec.shutdown().await
-                {
-                    warn!("failed to shutdown rollout recorder: {e}");
-                    let event = Event {
-                        id: sub.id.clone(),
-                        msg: EventMsg::Error(ErrorEvent {
-                            message: "Failed to shutdown rollout recorder".to_string(),
-                        }),
-                    };
-                    sess.send_event(event).await;
-                }
-
-                let event = Event {
-                    id: sub.id.clone(),
-                    msg: EventMsg::ShutdownComplete,
+            Op::RevertTask { sub_id, force } => {
+                let msg = sess.revert_task(&sub_id, force).await;
+                sess.send_event(Event { id: sub.id, msg }).await;
+            }
+            Op::GetDigest {
+                since_sequence,
+                since_time_ms,
+            } => {
+                let digest = sess
+                    .digest(DigestSince {
+                        sequence: since_sequence,
+                        time_ms: since_time_ms.map(u128::from),
+                    })
+                    .await;
+                sess.send_event(Event {
+                    id: sub.id,
+                    msg: EventMsg::Digest(digest),
+                })
+                .await;
+            }
+            Op::ExportTurn { sub_id } => {
+                let msg = sess.export_turn(&sub_id).await;
+                sess.send_event(Event { id: sub.id, msg }).await;
+            }
+            Op::Annotate { text, sub_id, tags } => {
+                sess.annotate(sub.id, text, sub_id, tags).await;
+            }
+            Op::UpdateTelemetryConfig { patch } => {
+                sess.update_telemetry_config(sub.id, patch).await;
+            }
+            Op::QueryRecentEvents { query } => {
+                let events = sess
+                    .visualizer
+                    .query_recent(&query)
+                    .into_iter()
+                    .map(RecentEvent::from)
+                    .collect();
+                sess.send_event(Event {
+                    id: sub.id,
+                    msg: EventMsg::RecentEvents(RecentEventsEvent { events }),
+                })
+                .await;
+            }
+            Op::ClearVisualizerPin { host_port } => {
+                let msg = match PinStore::new(&config.codex_home).clear(&host_port).await {
+                    Ok(true) => EventMsg::BackgroundEvent(BackgroundEventEvent {
+                        message: format!("Cleared the visualizer pin for {host_port}"),
+                    }),
+                    Ok(false) => EventMsg::BackgroundEvent(BackgroundEventEvent {
+                        message: format!("No visualizer pin for {host_port}"),
+                    }),
+                    Err(err) => EventMsg::Error(ErrorEvent {
+                        message: format!(
+                            "failed to clear the visualizer pin for {host_port}: {err}"
+                        ),
+                    }),
                 };
-                sess.send_event(event).await;
+                sess.send_event(Event { id: sub.id, msg }).await;
+            }
+            Op::SetFocus { label, paths } => {
+                sess.set_focus(sub.id, label, paths, &turn_context.cwd)
+                    .await;
+            }
+            Op::ClearFocus => {
+                sess.clear_focus(sub.id).await;
+            }
+            Op::SpawnTemplate {
+                name,
+                params,
+                client_context,
+            } => {
+                sess.spawn_template(
+                    Arc::clone(&turn_context),
+                    sub.id,
+                    name,
+                    params,
+                    client_context,
+                )
+                .await;
+            }
+            Op::ResolveRecoveredTask { action } => {
+                sess.resolve_recovered_task(Arc::clone(&turn_context), sub.id, action)
+                    .await;
+            }
+            Op::Shutdown => {
+                end_reason = ConversationEndReason::UserExit;
+                shutdown_sub_id = Some(sub.id);
                 break;
             }
             Op::GetPath => {
@@ -1707,7 +2501,11 @@ async fn submission_loop(
                 };
                 sess.send_event(event).await;
             }
-            Op::Review { review_request } => {
+            Op::Review {
+                review_request,
+                target,
+                continue_from_cursor,
+            } => {
                 // Visualization hook: Review tasks spin up a dedicated child
                 // session with isolated history. Emit an event with the
                 // `sub.id`, `review_request` metadata (target files, diffs),
@@ -1719,6 +2517,20 @@ async fn submission_loop(
                     turn_context.clone(),
                     sub.id,
                     review_request,
+                    target,
+                    continue_from_cursor,
+                )
+                .await;
+            }
+            Op::SetPaused { paused } => {
+                sess.set_paused(paused);
+            }
+            Op::WriteTimelineReport { path } => {
+                sess.spawn_background_task(
+                    Arc::clone(&turn_context),
+                    sub.id,
+                    Vec::new(),
+                    TimelineReportTask::new(path),
                 )
                 .await;
             }
@@ -1727,6 +2539,30 @@ async fn submission_loop(
             }
         }
     }
+    let report = sess
+        .shutdown(end_guard, end_reason, ShutdownTimeouts::default())
+        .await;
+    // Sent only once everything else has been flushed, so a client that
+    // reads the rollout file after `ShutdownComplete` sees all of it.
+    if let Some(sub_id) = shutdown_sub_id {
+        if !matches!(
+            report.outcome(ShutdownStage::FlushRollout),
+            Some(StageOutcome::Completed | StageOutcome::Skipped)
+        ) {
+            let event = Event {
+                id: sub_id.clone(),
+                msg: EventMsg::Error(ErrorEvent {
+                    message: "Failed to shutdown rollout recorder".to_string(),
+                }),
+            };
+            sess.send_event(event).await;
+        }
+        let event = Event {
+            id: sub_id,
+            msg: EventMsg::ShutdownComplete,
+        };
+        sess.send_event(event).await;
+    }
     debug!("Agent loop exited");
 }
 
@@ -1737,6 +2573,8 @@ async fn spawn_review_thread(
     parent_turn_context: Arc<TurnContext>,
     sub_id: String,
     review_request: ReviewRequest,
+    target: Option<ReviewTarget>,
+    continue_from_cursor: bool,
 ) {
     let model = config.review_model.clone();
     let review_model_family = find_family_for_model(&model)
@@ -1752,7 +2590,19 @@ async fn spawn_review_thread(
     });
 
     let base_instructions = REVIEW_PROMPT.to_string();
-    let review_prompt = review_request.prompt.clone();
+    let mut review_prompt = review_request.prompt.clone();
+    let slice = sess
+        .plan_review_slice(
+            &parent_turn_context.cwd,
+            &sub_id,
+            target.as_ref(),
+            continue_from_cursor,
+            config.review_slice_files,
+        )
+        .await;
+    if let Some(slice) = &slice {
+        review_prompt.push_str(&slice.prompt_suffix());
+    }
     let provider = parent_turn_context.client.get_provider();
     let auth_manager = parent_turn_context.client.get_auth_manager();
     let model_family = review_model_family.clone();
@@ -1807,7 +2657,8 @@ async fn spawn_review_thread(
 
     // Clone sub_id for the upcoming announcement before moving it into the task.
     let sub_id_for_event = sub_id.clone();
-    sess.spawn_task(tc.clone(), sub_id, input, ReviewTask).await;
+    sess.spawn_task(tc.clone(), sub_id, input, ReviewTask::from_env(slice))
+        .await;
 
     // Announce entering review mode so UIs can switch modes.
     sess.send_event(Event {
@@ -1839,24 +2690,36 @@ pub(crate) async fn run_task(
     turn_context: Arc<TurnContext>,
     sub_id: String,
     input: Vec<InputItem>,
-) -> Option<String> {
+    cancellation: &TaskCancellation,
+) -> TaskResult {
     if input.is_empty() {
-        return None;
+        return TaskResult::Completed(None);
     }
+    announce_task(&sess, &turn_context, &sub_id, &input).await;
+    run_task_input(sess, turn_context, sub_id, input, cancellation).await
+}
+
+/// Send `TaskStarted` and emit `task_started` for a task about to run
+/// `input`; [`run_task`] without [`run_task_input`].
+pub(crate) async fn announce_task(
+    sess: &Session,
+    turn_context: &TurnContext,
+    sub_id: &str,
+    input: &[InputItem],
+) {
     // Visualization hook: TaskStarted kicks off a new run loop. Emit an event
     // carrying `sub_id`, `turn_context.client.get_model()`, reasoning knobs,
     // and the serialized `input` so downstream latency metrics (model call
     // duration, approval wait, tool runtime) can be derived relative to this
     // moment.
     let event = Event {
-        id: sub_id.clone(),
+        id: sub_id.to_string(),
         msg: EventMsg::TaskStarted(TaskStartedEvent {
             model_context_window: turn_context.client.get_model_context_window(),
         }),
     };
     sess.send_event(event).await;
-    let input_serialized = serde_json::to_value(&input).unwrap_or(Value::Null);
-    let initial_input_for_turn: ResponseInputItem = ResponseInputItem::from(input);
+    let input_serialized = serde_json::to_value(input).unwrap_or(Value::Null);
     sess.emit_with_state(
         "task_started",
         json!({
@@ -1869,6 +2732,27 @@ pub(crate) async fn run_task(
         }),
     )
     .await;
+}
+
+/// Drive the model turn loop over `input` until the model is done with it,
+/// for a task already announced with [`announce_task`]. `input` is empty
+/// only for a task resumed on a history that already holds it. Once `cancellation` is cancelled the turn in flight is dropped
+/// before anything of it is recorded, and the task returns.
+pub(crate) async fn run_task_input(
+    sess: Arc<Session>,
+    turn_context: Arc<TurnContext>,
+    sub_id: String,
+    input: Vec<InputItem>,
+    cancellation: &TaskCancellation,
+) -> TaskResult {
+    let record_input = !input.is_empty();
+    let degraded_input = if turn_context.is_review_mode {
+        None
+    } else {
+        sess.degrade_image_input(&turn_context, &sub_id, &input)
+            .await
+    };
+    let initial_input_for_turn: ResponseInputItem = ResponseInputItem::from(input);
     // For review threads, keep an isolated in-memory history so the
     // model sees a fresh conversation without the parent session's history.
     // For normal turns, continue recording to the session history as before.
@@ -1878,17 +2762,29 @@ pub(crate) async fn run_task(
         // Seed review threads with environment context so the model knows the working directory.
         review_thread_history.extend(sess.build_initial_context(turn_context.as_ref()));
         review_thread_history.push(initial_input_for_turn.into());
-    } else {
+    } else if record_input {
         // Visualization hook: recording the user input updates both the in
         // memory history and the rollout file. Emit an event with the
         // serialized `initial_input_for_turn` (including role + content
         // metadata) so the UI can surface "memory updated" markers tied to
         // AGENTS.md derived prompts.
-        sess.record_input_and_rollout_usermsg(&initial_input_for_turn)
-            .await;
+        match degraded_input {
+            Some(degraded) => {
+                sess.record_degraded_input_and_rollout_usermsg(
+                    ResponseInputItem::from(degraded),
+                    &initial_input_for_turn,
+                )
+                .await;
+            }
+            None => {
+                sess.record_input_and_rollout_usermsg(&initial_input_for_turn)
+                    .await;
+            }
+        }
     }
 
     let mut last_agent_message: Option<String> = None;
+    let mut failure: Option<TaskError> = None;
     // Although from the perspective of codex.rs, TurnDiffTracker has the lifecycle of a Task which contains
     // many turns, from the perspective of the user, it is a single turn.
     // Visualization hook: expose this tracker so the UI can present a
@@ -1902,12 +2798,15 @@ pub(crate) async fn run_task(
         // Note that pending_input would be something like a message the user
         // submitted through the UI while the model was running. Though the UI
         // may support this, the model might not.
-        let pending_input = sess
-            .get_pending_input()
-            .await
-            .into_iter()
-            .map(ResponseItem::from)
-            .collect::<Vec<ResponseItem>>();
+        let mut pending_input = Vec::new();
+        for input in sess.get_pending_input().await {
+            pending_input.push(if is_review_mode {
+                ResponseInputItem::from(input).into()
+            } else {
+                sess.record_pending_input(&turn_context, &sub_id, input)
+                    .await
+            });
+        }
 
         // Construct the input that we will send to the model.
         //
@@ -1931,7 +2830,6 @@ pub(crate) async fn run_task(
             }
             review_thread_history.clone()
         } else {
-            sess.record_conversation_items(&pending_input).await;
             sess.turn_input_with_history(pending_input).await
         };
 
@@ -1957,15 +2855,19 @@ pub(crate) async fn run_task(
         // "turn started" marker containing `sub_id`, the prompt hash, and
         // `turn_input_messages` so latency/token charts can align with the raw
         // prompt content.
-        match run_turn(
+        let turn = run_turn(
             Arc::clone(&sess),
             Arc::clone(&turn_context),
             Arc::clone(&turn_diff_tracker),
             sub_id.clone(),
             turn_input,
-        )
-        .await
-        {
+            cancellation,
+        );
+        let turn_result = tokio::select! {
+            result = turn => result,
+            () = cancellation.cancelled() => return TaskResult::Completed(last_agent_message),
+        };
+        match turn_result {
             Ok(turn_output) => {
                 let TurnRunResult {
                     processed_items,
@@ -2102,13 +3004,17 @@ pub(crate) async fn run_task(
                         let current_tokens = total_usage_tokens
                             .map(|tokens| tokens.to_string())
                             .unwrap_or_else(|| "unknown".to_string());
+                        let message = format!(
+                            "Conversation is still above the token limit after automatic summarization (limit {limit_str}, current {current_tokens}). Please start a new session or trim your input."
+                        );
+                        failure = Some(TaskError {
+                            kind: TaskErrorKind::ContextOverflow,
+                            retryable: TaskErrorKind::ContextOverflow.is_retryable(),
+                            message: message.clone(),
+                        });
                         let event = Event {
                             id: sub_id.clone(),
-                            msg: EventMsg::Error(ErrorEvent {
-                                message: format!(
-                                    "Conversation is still above the token limit after automatic summarization (limit {limit_str}, current {current_tokens}). Please start a new session or trim your input."
-                                ),
-                            }),
+                            msg: EventMsg::Error(ErrorEvent { message }),
                         };
                         sess.send_event(event).await;
                         break;
@@ -2135,7 +3041,24 @@ pub(crate) async fn run_task(
                 continue;
             }
             Err(e) => {
+                if matches!(e, CodexErr::ContextWindowExceeded)
+                    && !is_review_mode
+                    && sess
+                        .queue_blocking_compaction(Arc::clone(&turn_context), &sub_id, Vec::new())
+                        .await
+                {
+                    // The compaction starts as soon as this task finishes,
+                    // and the task is resumed on the compacted history,
+                    // which holds its input, once it completes.
+                    sess.notify_background_event(
+                        &sub_id,
+                        "Context window is full; compacting the conversation before retrying.",
+                    )
+                    .await;
+                    break;
+                }
                 info!("Turn error: {e:#}");
+                failure = Some(e.to_task_error());
                 let event = Event {
                     id: sub_id.clone(),
                     msg: EventMsg::Error(ErrorEvent {
@@ -2149,6 +3072,10 @@ pub(crate) async fn run_task(
         }
     }
 
+    let touched = turn_diff_tracker.lock().await.touched_files();
+    let writes = TaskWrites::capture(&sub_id, touched).await;
+    sess.record_task_writes(writes).await;
+
     // If this was a review thread and we have a final assistant message,
     // try to parse it as a ReviewOutput.
     //
@@ -2165,7 +3092,10 @@ pub(crate) async fn run_task(
         .await;
     }
 
-    last_agent_message
+    match failure {
+        Some(error) => TaskResult::Failed(error),
+        None => TaskResult::Completed(last_agent_message),
+    }
 }
 
 /// Parse the review output; when not valid JSON, build a structured
@@ -2199,6 +3129,7 @@ async fn run_turn(
     turn_diff_tracker: SharedTurnDiffTracker,
     sub_id: String,
     input: Vec<ResponseItem>,
+    cancellation: &TaskCancellation,
 ) -> CodexResult<TurnRunResult> {
     // Visualization hook: snapshot the MCP/tool catalog each turn so the UI
     // can display which schemas/versions were available when a tool call was
@@ -2234,17 +3165,21 @@ async fn run_turn(
     // `prompt.input` (with role/source annotations), `prompt.tools`
     // definitions, `parallel_tool_calls`, and reasoning/sampling knobs so the
     // browser timeline can render an "LLM request envelope".
+    let task_template = sess.task_template(&sub_id).await;
     let prompt = Prompt {
         input,
         tools: router.specs(),
         parallel_tool_calls,
         base_instructions_override: turn_context.base_instructions.clone(),
         output_schema: turn_context.final_output_json_schema.clone(),
+        seed: sess.task_seed(&sub_id).await,
+        task_instructions: task_template.as_ref().map(|template| template.text.clone()),
     };
     let prompt_input_value = serde_json::to_value(&prompt.input).unwrap_or(Value::Null);
+    let prompt_input_content = prompt_content(&prompt.input, &turn_context.cwd).await;
     let base_override = prompt.base_instructions_override.clone();
     let output_schema = prompt.output_schema.clone();
-    sess.emit_with_state(
+    sess.emit_scoped_with_state(
         "llm_prompt_prepared",
         json!({
             "subId": sub_id,
@@ -2253,7 +3188,10 @@ async fn run_turn(
             "input": prompt_input_value,
             "baseInstructionsOverride": base_override,
             "outputSchema": output_schema,
+            "seed": prompt.seed,
+            "taskTemplate": task_template.as_deref(),
         }),
+        prompt_input_content,
     )
     .await;
 
@@ -2270,6 +3208,7 @@ async fn run_turn(
             Arc::clone(&turn_diff_tracker),
             &sub_id,
             &prompt,
+            cancellation,
         )
         .await
         {
@@ -2302,6 +3241,9 @@ async fn run_turn(
                 return Err(CodexErr::UsageLimitReached(e));
             }
             Err(CodexErr::UsageNotIncluded) => return Err(CodexErr::UsageNotIncluded),
+            // Credentials, request, and sandbox errors fail the same way on
+            // every attempt.
+            Err(e) if !e.task_error_kind().is_retryable() => return Err(e),
             Err(e) => {
                 // Use the configured provider-specific stream retry budget.
                 let max_retries = turn_context.client.get_provider().stream_max_retries();
@@ -2374,6 +3316,7 @@ async fn try_run_turn(
     turn_diff_tracker: SharedTurnDiffTracker,
     sub_id: &str,
     prompt: &Prompt,
+    cancellation: &TaskCancellation,
 ) -> CodexResult<TurnRunResult> {
     // call_ids that are part of this response.
     let completed_call_ids = prompt
@@ -2444,19 +3387,19 @@ async fn try_run_turn(
         summary: turn_context.client.get_reasoning_summary(),
     });
     sess.persist_rollout_items(&[rollout_item]).await;
-    sess.emit_with_state(
-        "turn_context_persisted",
-        json!({
-            "subId": sub_id,
-            "cwd": turn_context.cwd.display().to_string(),
-            "approvalPolicy": turn_context.approval_policy,
-            "sandboxPolicy": turn_context.sandbox_policy.clone(),
-            "model": turn_context.client.get_model(),
-            "reasoningEffort": turn_context.client.get_reasoning_effort(),
-            "reasoningSummary": turn_context.client.get_reasoning_summary(),
-        }),
-    )
-    .await;
+    let mut persisted = json!({
+        "subId": sub_id,
+        "approvalPolicy": turn_context.approval_policy,
+        "sandboxPolicy": turn_context.sandbox_policy.clone(),
+        "model": turn_context.client.get_model(),
+        "reasoningEffort": turn_context.client.get_reasoning_effort(),
+        "reasoningSummary": turn_context.client.get_reasoning_summary(),
+    });
+    CwdSnapshot::capture(&turn_context.cwd)
+        .await
+        .add_to(&mut persisted);
+    sess.emit_with_state("turn_context_persisted", persisted)
+        .await;
     // Visualization hook: this stream() call is the precise LLM request.
     // Capture the instant it begins and include the serialized `prompt` so
     // latency can be measured until the corresponding `ResponseEvent::Completed`
@@ -2487,6 +3430,10 @@ async fn try_run_turn(
     );
     let mut output: FuturesOrdered<BoxFuture<CodexResult<ProcessedResponseItem>>> =
         FuturesOrdered::new();
+    let mut reasoning_phase = ReasoningPhaseTracker::new(Instant::now());
+    // Whether this stream already produced output; saves a lookup of the
+    // task's timings on every later delta.
+    let mut responded = false;
 
     loop {
         // Poll the next item from the model stream. We must inspect *both* Ok and Err
@@ -2510,6 +3457,15 @@ async fn try_run_turn(
         match event {
             ResponseEvent::Created => {}
             ResponseEvent::OutputItemDone(item) => {
+                if !matches!(item, ResponseItem::Reasoning { .. }) {
+                    if let Some(phase) = reasoning_phase.finish(Instant::now()) {
+                        sess.on_reasoning_phase_finished(sub_id, phase).await;
+                    }
+                    if !responded {
+                        responded = true;
+                        sess.on_first_response(sub_id).await;
+                    }
+                }
                 // Visualization hook: tool call proposals surface here. Emit an
                 // event with `call.tool_name`, `call.call_id`, argument JSON,
                 // and the assigned `index` so the UI can draw per-call
@@ -2606,6 +3562,9 @@ async fn try_run_turn(
                 // stream. Emit an event with `token_usage`, elapsed stream
                 // duration, and accumulated diff summaries to drive usage and
                 // throughput charts.
+                if let Some(phase) = reasoning_phase.finish(Instant::now()) {
+                    sess.on_reasoning_phase_finished(sub_id, phase).await;
+                }
                 sess.update_token_usage_info(sub_id, turn_context.as_ref(), token_usage.as_ref())
                     .await;
 
@@ -2632,6 +3591,14 @@ async fn try_run_turn(
                 return Ok(result);
             }
             ResponseEvent::OutputTextDelta(delta) => {
+                cancellation.record(&delta);
+                if let Some(phase) = reasoning_phase.finish(Instant::now()) {
+                    sess.on_reasoning_phase_finished(sub_id, phase).await;
+                }
+                if !responded {
+                    responded = true;
+                    sess.on_first_response(sub_id).await;
+                }
                 // In review child threads, suppress assistant text deltas; the
                 // UI will show a selection popup from the final ReviewOutput.
                 if !turn_context.is_review_mode {
@@ -2649,6 +3616,7 @@ async fn try_run_turn(
                 }
             }
             ResponseEvent::ReasoningSummaryDelta(delta) => {
+                reasoning_phase.record_reasoning(&delta);
                 // Visualization hook: reasoning deltas power the "thought
                 // bubble" lane. Log the structured `delta` segments and their
                 // timestamps relative to tool calls to highlight cause/effect.
@@ -2670,6 +3638,7 @@ async fn try_run_turn(
                 sess.send_event(event).await;
             }
             ResponseEvent::ReasoningContentDelta(delta) => {
+                reasoning_phase.record_reasoning(&delta);
                 if sess.show_raw_agent_reasoning() {
                     // Visualization hook: raw reasoning is noisy but valuable
                     // for power users. Surface it in a collapsible lane with
@@ -2797,6 +3766,10 @@ pub(crate) async fn exit_review_mode(
         }),
     };
     session.send_event(event).await;
+    if let Some(out) = &review_output {
+        session.record_review_findings(out.findings.clone()).await;
+    }
+    let slice = session.finish_review_slice(review_output.is_some()).await;
 
     let mut user_message = String::new();
     if let Some(out) = review_output {
@@ -2809,6 +3782,15 @@ pub(crate) async fn exit_review_mode(
             let block = format_review_findings_block(&out.findings, None);
             findings_str.push_str(&format!("\n{block}"));
         }
+        if let Some(slice) = slice {
+            let label = slice.label();
+            let next = if slice.last < slice.total {
+                "continue the review to cover the remaining files"
+            } else {
+                "this was the last slice"
+            };
+            findings_str = format!("Reviewed {label} of the diff; {next}.\n{findings_str}");
+        }
         user_message.push_str(&format!(
             r#"<user_action>
   <context>User initiated a review task. Here's the full review output from reviewer model. User may select one or more comments to resolve.</context>
@@ -2851,18 +3833,40 @@ mod tests {
     use crate::config::ConfigOverrides;
     use crate::config::ConfigToml;
 
+    use crate::protocol::Annotation;
+    use crate::protocol::ColorDepth;
     use crate::protocol::CompactedItem;
+    use crate::protocol::ConversationEndedEvent;
+    use crate::protocol::EventDiscriminant;
     use crate::protocol::InitialHistory;
+    use crate::protocol::RecoveredTaskAction;
+    use crate::protocol::RecoveredToolCall;
     use crate::protocol::ResumedHistory;
+    use crate::protocol::ReviewCodeLocation;
+    use crate::protocol::ReviewLineRange;
+    use crate::protocol::TaskRecoveredIncompleteEvent;
+    use crate::shutdown::ShutdownReport;
+    use crate::shutdown::StageReport;
     use crate::state::TaskKind;
+    use crate::tasks::FirstResponseHistogram;
+    use crate::tasks::FollowUpTask;
+    use crate::tasks::RegularTask;
     use crate::tasks::SessionTask;
     use crate::tasks::SessionTaskContext;
+    use crate::tasks::TaskExecutionReport;
     use crate::tools::MODEL_FORMAT_HEAD_LINES;
     use crate::tools::MODEL_FORMAT_MAX_BYTES;
     use crate::tools::MODEL_FORMAT_MAX_LINES;
     use crate::tools::MODEL_FORMAT_TAIL_LINES;
     use crate::tools::ToolRouter;
+    use crate::tools::context::ToolInvocation;
+    use crate::tools::context::ToolOutput;
+    use crate::tools::context::ToolPayload;
     use crate::tools::handle_container_exec_with_params;
+    use crate::tools::registry::ToolHandler;
+    use crate::tools::registry::ToolKind;
+    use crate::tools::registry::ToolRegistry;
+    use crate::tools::telemetry::TelemetryScope;
     use crate::turn_diff_tracker::TurnDiffTracker;
     use codex_app_server_protocol::AuthMode;
     use codex_protocol::models::ContentItem;
@@ -3175,20 +4179,39 @@ mod tests {
             rollout: Mutex::new(None),
             user_shell: shell::Shell::Unknown,
             show_raw_agent_reasoning: config.show_raw_agent_reasoning,
+            warn_on_invalid_cwd: config.warn_on_invalid_cwd,
+            task_timeout: config.task_timeout,
+            task_retry: config.task_retry,
+            task_progress_interval: config.task_progress_interval,
+            task_input_chunk_size: config.task_input_chunk_size,
+            features: resolve_features(&config),
+            approval_limits: config.approval_limits,
+            paused: watch::channel(false).0,
             executor: Executor::new(ExecutorConfig::new(
                 turn_context.sandbox_policy.clone(),
                 turn_context.cwd.clone(),
                 None,
             )),
+            _conversation_lease: None,
+            task_templates: TaskTemplates::default(),
+            turn_templates_dir: config.turn_templates_dir.clone(),
+            image_degradation: config.image_degradation,
+            instrumentation: InstrumentationCoverage::default(),
+            snapshot_consistency: SnapshotConsistency::new(config.visualizer_consistency_checks),
         };
         let session = Session {
             conversation_id,
             tx_event,
             state: Mutex::new(SessionState::new()),
             active_turn: Mutex::new(None),
+            task_transitions: Mutex::new(()),
+            aborts_in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            background_tasks: BackgroundTasks::default(),
             services,
             next_internal_sub_id: AtomicU64::new(0),
-            visualizer: SessionVisualizer::new(AgentVisualizer::from_env(), conversation_id),
+            visualizer: SessionVisualizer::new(AgentVisualizer::default(), conversation_id),
+            event_broadcast: event_broadcast(),
         };
         (session, turn_context)
     }
@@ -3199,11 +4222,21 @@ mod tests {
         Arc<Session>,
         Arc<TurnContext>,
         async_channel::Receiver<Event>,
+    ) {
+        make_session_and_context_with_config_and_rx(ConfigToml::default())
+    }
+
+    fn make_session_and_context_with_config_and_rx(
+        config_toml: ConfigToml,
+    ) -> (
+        Arc<Session>,
+        Arc<TurnContext>,
+        async_channel::Receiver<Event>,
     ) {
         let (tx_event, rx_event) = async_channel::unbounded();
         let codex_home = tempfile::tempdir().expect("create temp dir");
         let config = Config::load_from_base_config_with_overrides(
-            ConfigToml::default(),
+            config_toml,
             ConfigOverrides::default(),
             codex_home.path().to_path_buf(),
         )
@@ -3249,24 +4282,57 @@ mod tests {
             rollout: Mutex::new(None),
             user_shell: shell::Shell::Unknown,
             show_raw_agent_reasoning: config.show_raw_agent_reasoning,
+            warn_on_invalid_cwd: config.warn_on_invalid_cwd,
+            task_timeout: config.task_timeout,
+            task_retry: config.task_retry,
+            task_progress_interval: config.task_progress_interval,
+            task_input_chunk_size: config.task_input_chunk_size,
+            features: resolve_features(&config),
+            approval_limits: config.approval_limits,
+            paused: watch::channel(false).0,
             executor: Executor::new(ExecutorConfig::new(
                 config.sandbox_policy.clone(),
                 config.cwd.clone(),
                 None,
             )),
+            _conversation_lease: None,
+            task_templates: TaskTemplates::default(),
+            turn_templates_dir: config.turn_templates_dir.clone(),
+            image_degradation: config.image_degradation,
+            instrumentation: InstrumentationCoverage::default(),
+            snapshot_consistency: SnapshotConsistency::new(config.visualizer_consistency_checks),
         };
         let session = Arc::new(Session {
             conversation_id,
             tx_event,
             state: Mutex::new(SessionState::new()),
             active_turn: Mutex::new(None),
+            task_transitions: Mutex::new(()),
+            aborts_in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            background_tasks: BackgroundTasks::default(),
             services,
             next_internal_sub_id: AtomicU64::new(0),
-            visualizer: SessionVisualizer::new(AgentVisualizer::from_env(), conversation_id),
+            visualizer: SessionVisualizer::new(AgentVisualizer::default(), conversation_id),
+            event_broadcast: event_broadcast(),
         });
         (session, turn_context, rx_event)
     }
 
+    #[tokio::test]
+    async fn state_snapshot_includes_session_features() {
+        let (session, _turn_context) = make_session_and_context();
+        let snapshot = session.visualization_state_snapshot().await;
+        assert_eq!(snapshot["features"], json!(session.features()));
+        assert_eq!(
+            session.features().keys().cloned().collect::<Vec<_>>(),
+            crate::session_features::FEATURE_REGISTRY
+                .iter()
+                .map(|spec| spec.name.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[derive(Clone, Copy)]
     struct NeverEndingTask(TaskKind);
 
@@ -3282,7 +4348,7 @@ mod tests {
             _ctx: Arc<TurnContext>,
             _sub_id: String,
             _input: Vec<InputItem>,
-        ) -> Option<String> {
+        ) -> TaskResult {
             loop {
                 sleep(Duration::from_secs(60)).await;
             }
@@ -3320,72 +4386,3196 @@ mod tests {
         assert!(rx.try_recv().is_err());
     }
 
-    #[tokio::test]
-    async fn abort_review_task_emits_exited_then_aborted_and_records_history() {
-        let (sess, tc, rx) = make_session_and_context_with_rx();
-        let sub_id = "sub-review".to_string();
-        let input = vec![InputItem::Text {
-            text: "start review".to_string(),
-        }];
+    /// Never ends, and reports how often it was asked for its progress.
+    #[derive(Default)]
+    struct SteppingTask(std::sync::atomic::AtomicU64);
+
+    #[async_trait::async_trait]
+    impl SessionTask for SteppingTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            std::future::pending().await
+        }
+
+        async fn progress(&self) -> Option<Value> {
+            let step = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            Some(json!({ "step": step }))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn running_task_progress_is_polled_until_it_ends() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
         sess.spawn_task(
             Arc::clone(&tc),
-            sub_id.clone(),
-            input,
-            NeverEndingTask(TaskKind::Review),
+            "sub-steps".to_string(),
+            Vec::new(),
+            SteppingTask::default(),
         )
         .await;
-
+        sleep(Duration::from_millis(4500)).await;
         sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+        sleep(Duration::from_secs(10)).await;
 
-        let first = rx.recv().await.expect("first event");
-        match first.msg {
-            EventMsg::ExitedReviewMode(ev) => assert!(ev.review_output.is_none()),
-            other => panic!("unexpected first event: {other:?}"),
-        }
-        let second = rx.recv().await.expect("second event");
-        match second.msg {
-            EventMsg::TurnAborted(e) => assert_eq!(TurnAbortReason::Interrupted, e.reason),
-            other => panic!("unexpected second event: {other:?}"),
+        let progress: Vec<Value> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "task_progress")
+            .map(|event| event.action)
+            .collect();
+        assert_eq!(
+            progress,
+            (1..=2)
+                .map(|step| json!({
+                    "subId": "sub-steps",
+                    "taskKind": "Regular",
+                    "progress": { "step": step },
+                }))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_task_is_polled_for_progress_once_per_tick() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        for sub_id in ["sub-a", "sub-b"] {
+            sess.spawn_task(
+                Arc::clone(&tc),
+                sub_id.to_string(),
+                Vec::new(),
+                SteppingTask::default(),
+            )
+            .await;
         }
+        sleep(Duration::from_millis(4500)).await;
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
 
-        let history = sess.history_snapshot().await;
-        let found = history.iter().any(|item| match item {
-            ResponseItem::Message { role, content, .. } if role == "user" => {
-                content.iter().any(|ci| match ci {
-                    ContentItem::InputText { text } => {
-                        text.contains("<user_action>")
-                            && text.contains("review")
-                            && text.contains("interrupted")
-                    }
-                    _ => false,
-                })
-            }
-            _ => false,
-        });
-        assert!(
-            found,
-            "synthetic review interruption not recorded in history"
+        let mut progress: Vec<(String, Value)> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "task_progress")
+            .map(|event| {
+                (
+                    event.action["subId"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    event.action["progress"]["step"].clone(),
+                )
+            })
+            .collect();
+        progress.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            progress,
+            vec![
+                ("sub-a".to_string(), json!(1)),
+                ("sub-a".to_string(), json!(2)),
+                ("sub-b".to_string(), json!(1)),
+                ("sub-b".to_string(), json!(2)),
+            ]
         );
     }
 
+    /// How an embedder wires Ctrl-C to [`Codex::request_abort`]; the
+    /// channel stands in for `tokio::signal::ctrl_c`.
     #[tokio::test]
-    async fn fatal_tool_error_stops_turn_and_reports_error() {
-        let (session, turn_context, _rx) = make_session_and_context_with_rx();
-        let router = ToolRouter::from_config(
-            &turn_context.tools_config,
-            Some(session.services.mcp_connection_manager.list_all_tools()),
+    async fn ctrl_c_handler_requests_an_abort() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-repl".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+
+        let (ctrl_c_tx, mut ctrl_c_rx) = tokio::sync::mpsc::channel::<()>(1);
+        let handler = tokio::spawn({
+            let sess = Arc::clone(&sess);
+            async move {
+                if ctrl_c_rx.recv().await.is_some() {
+                    sess.request_abort().await;
+                }
+            }
+        });
+        ctrl_c_tx.send(()).await.expect("handler is listening");
+        handler.await.expect("Ctrl-C handler");
+
+        let evt = rx.recv().await.expect("event");
+        match evt.msg {
+            EventMsg::TurnAborted(e) => assert_eq!(TurnAbortReason::UserRequested, e.reason),
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert_eq!(
+            last_visualizer_action(&sess, "task_aborted").map(|action| action["reason"].clone()),
+            Some(json!("UserRequested"))
         );
-        let item = ResponseItem::CustomToolCall {
-            id: None,
-            status: None,
-            call_id: "call-1".to_string(),
-            name: "shell".to_string(),
-            input: "{}".to_string(),
-        };
+    }
 
-        let call = ToolRouter::build_tool_call(session.as_ref(), item.clone())
-            .expect("build tool call")
-            .expect("tool call present");
+    /// Callers racing to spawn replace each other hundreds of times; each
+    /// spawn must find every task spawned before it already reported
+    /// aborted, in both streams.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn replaced_tasks_are_reported_aborted_before_their_replacement_spawns() {
+        const CALLERS: usize = 4;
+        const SPAWNS_PER_CALLER: usize = 100;
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let callers: Vec<_> = (0..CALLERS)
+            .map(|caller| {
+                let sess = Arc::clone(&sess);
+                let tc = Arc::clone(&tc);
+                tokio::spawn(async move {
+                    for n in 0..SPAWNS_PER_CALLER {
+                        sess.spawn_task(
+                            Arc::clone(&tc),
+                            format!("sub-{caller}-{n}"),
+                            Vec::new(),
+                            NeverEndingTask(TaskKind::Regular),
+                        )
+                        .await;
+                    }
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.await.expect("caller panicked");
+        }
+
+        let mut running = std::collections::BTreeSet::new();
+        let mut spawned = Vec::new();
+        let mut aborted = Vec::new();
+        for event in sess.visualizer.recent_events() {
+            let sub_id = event.action["subId"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            match event.action_type.as_str() {
+                "task_spawned" => {
+                    assert!(
+                        running.is_empty(),
+                        "{sub_id} spawned before {running:?} were reported aborted"
+                    );
+                    running.insert(sub_id.clone());
+                    spawned.push(sub_id);
+                }
+                "task_aborted" => {
+                    assert!(running.remove(&sub_id), "{sub_id} was not running");
+                    aborted.push(sub_id);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(spawned.len(), CALLERS * SPAWNS_PER_CALLER);
+        assert_eq!(aborted, spawned[..spawned.len() - 1].to_vec());
+
+        let mut turn_aborted = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let EventMsg::TurnAborted(_) = event.msg {
+                turn_aborted.push(event.id);
+            }
+        }
+        assert_eq!(turn_aborted, aborted);
+    }
+
+    /// Runs until aborted; its abort hook signals `entered` and then waits
+    /// for `release`.
+    #[derive(Clone, Default)]
+    struct SlowAbortTask {
+        entered: Arc<tokio::sync::Notify>,
+        release: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl SessionTask for SlowAbortTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            std::future::pending().await
+        }
+
+        async fn abort(&self, _session: Arc<SessionTaskContext>, _sub_id: &str) {
+            self.entered.notify_one();
+            self.release.notified().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_abort_hooks_run_without_holding_task_transitions() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let slow = SlowAbortTask::default();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-slow".to_string(),
+            Vec::new(),
+            slow.clone(),
+        )
+        .await;
+        let replacing = tokio::spawn({
+            let sess = Arc::clone(&sess);
+            let tc = Arc::clone(&tc);
+            async move {
+                sess.spawn_task(
+                    tc,
+                    "sub-next".to_string(),
+                    Vec::new(),
+                    NeverEndingTask(TaskKind::Regular),
+                )
+                .await;
+            }
+        });
+        slow.entered.notified().await;
+
+        let transitions_free = sess.task_transitions.try_lock().is_ok();
+        // The abort is not reported yet, so the session is not idle.
+        let idle = tokio::time::timeout(StdDuration::from_millis(50), sess.wait_for_idle())
+            .await
+            .is_ok();
+        slow.release.notify_one();
+        replacing.await.expect("spawn panicked");
+
+        let transitions: Vec<(String, Value)> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| matches!(event.action_type.as_str(), "task_spawned" | "task_aborted"))
+            .map(|event| (event.action_type.clone(), event.action["subId"].clone()))
+            .collect();
+        assert_eq!(
+            (transitions_free, idle, transitions),
+            (
+                true,
+                false,
+                vec![
+                    ("task_spawned".to_string(), json!("sub-slow")),
+                    ("task_aborted".to_string(), json!("sub-slow")),
+                    ("task_spawned".to_string(), json!("sub-next")),
+                ]
+            )
+        );
+    }
+
+    fn instrumentation_gaps(sess: &Session) -> Vec<Value> {
+        sess.visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "instrumentation_gap")
+            .map(|event| event.action)
+            .collect()
+    }
+
+    async fn spawn_and_abort_never_ending_task(sess: &Arc<Session>, tc: &Arc<TurnContext>) {
+        sess.spawn_task(
+            Arc::clone(tc),
+            "sub-regular".to_string(),
+            vec![InputItem::Text {
+                text: "hello".to_string(),
+            }],
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+    }
+
+    #[tokio::test]
+    async fn task_that_emitted_its_checklist_reports_no_instrumentation_gap() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        spawn_and_abort_never_ending_task(&sess, &tc).await;
+
+        assert_eq!(instrumentation_gaps(&sess), Vec::<Value>::new());
+    }
+
+    #[tokio::test]
+    async fn skipped_lifecycle_emission_is_reported_as_an_instrumentation_gap() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        sess.services.instrumentation.skip("task_aborted");
+        spawn_and_abort_never_ending_task(&sess, &tc).await;
+
+        assert_eq!(
+            instrumentation_gaps(&sess),
+            vec![json!({
+                "subId": "sub-regular",
+                "missing": ["task_aborted"],
+                "fired": ["task_spawned"],
+                "aborted": true,
+            })]
+        );
+    }
+
+    fn telemetry_inconsistencies(sess: &Session) -> Vec<Value> {
+        sess.visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "telemetry_inconsistency")
+            .map(|event| event.action)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lifecycle_snapshots_agree_with_the_active_turn() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        run_scripted_task(&sess, &tc, &rx, "sub-scripted", None, None).await;
+        // The replaced task is reported aborted while its replacement runs.
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-replaced".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+        spawn_and_abort_never_ending_task(&sess, &tc).await;
+
+        assert_eq!(telemetry_inconsistencies(&sess), Vec::<Value>::new());
+    }
+
+    #[tokio::test]
+    async fn desynced_snapshot_is_reported_as_a_telemetry_inconsistency() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        sess.services.snapshot_consistency.hide_tasks();
+        spawn_and_abort_never_ending_task(&sess, &tc).await;
+
+        // The abort's snapshot agrees: the task is gone from both views.
+        assert_eq!(
+            telemetry_inconsistencies(&sess),
+            vec![json!({
+                "actionType": "task_spawned",
+                "subId": "sub-regular",
+                "problems": [
+                    "snapshot lists 0 active tasks but 1 are running",
+                    "snapshot omits running task `sub-regular`",
+                ],
+                "snapshot": { "activeTasks": [] },
+                "authoritative": { "activeTasks": ["sub-regular"] },
+            })]
+        );
+    }
+
+    /// Returns from `run` once the gate is opened.
+    struct GatedTask(Arc<tokio::sync::Notify>);
+
+    #[async_trait::async_trait]
+    impl SessionTask for GatedTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            self.0.notified().await;
+            TaskResult::Completed(None)
+        }
+    }
+
+    /// Finishes with "done" after sleeping this long.
+    struct SleepingTask(StdDuration);
+
+    #[async_trait::async_trait]
+    impl SessionTask for SleepingTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            tokio::time::sleep(self.0).await;
+            TaskResult::Completed(Some("done".to_string()))
+        }
+    }
+
+    /// Fails with a retryable error until its third attempt, recording the
+    /// input of each.
+    #[derive(Clone, Default)]
+    struct FlakyTask(Arc<std::sync::Mutex<Vec<Vec<InputItem>>>>);
+
+    #[async_trait::async_trait]
+    impl SessionTask for FlakyTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            input: Vec<InputItem>,
+        ) -> TaskResult {
+            let attempt = match self.0.lock() {
+                Ok(mut attempts) => {
+                    attempts.push(input);
+                    attempts.len()
+                }
+                Err(_) => 0,
+            };
+            if attempt == 3 {
+                TaskResult::Completed(Some(format!("done on attempt {attempt}")))
+            } else {
+                TaskResult::Failed(TaskError {
+                    kind: TaskErrorKind::StreamInterrupted,
+                    retryable: true,
+                    message: format!("attempt {attempt} broke off"),
+                })
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_attempts_are_retried_on_the_original_input() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let input = vec![InputItem::Text {
+            text: "flaky".to_string(),
+        }];
+        let task = FlakyTask::default();
+        sess.spawn_task_with_retry(
+            Arc::clone(&tc),
+            "sub-flaky".to_string(),
+            input.clone(),
+            task.clone(),
+            None,
+            None,
+            crate::retry::RetryPolicy {
+                initial_delay: StdDuration::from_millis(100),
+                multiplier: 2.0,
+                max_delay: StdDuration::from_secs(5),
+                jitter: crate::retry::Jitter::None,
+                max_attempts: Some(5),
+            },
+        )
+        .await;
+
+        let complete = loop {
+            let event = tokio::time::timeout(StdDuration::from_secs(5), rx.recv())
+                .await
+                .expect("task completes")
+                .expect("event");
+            if let EventMsg::TaskComplete(complete) = event.msg {
+                break complete;
+            }
+        };
+        let retries: Vec<Value> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "task_retry_attempt")
+            .map(|event| event.action)
+            .collect();
+        let inputs = task
+            .0
+            .lock()
+            .map(|inputs| inputs.clone())
+            .unwrap_or_default();
+        assert_eq!(
+            (complete.last_agent_message, inputs, retries),
+            (
+                Some("done on attempt 3".to_string()),
+                vec![input; 3],
+                [(1, 100), (2, 200)]
+                    .map(|(attempt, delay_ms)| json!({
+                        "subId": "sub-flaky",
+                        "taskKind": "Regular",
+                        "attempt": attempt,
+                        "maxAttempts": 5,
+                        "delayMs": delay_ms,
+                    }))
+                    .to_vec(),
+            )
+        );
+    }
+
+    /// Fails with a retryable error on its first attempt and completes on
+    /// its retry, recording the turn context of each.
+    #[derive(Clone, Default)]
+    struct ContextTask(Arc<std::sync::Mutex<Vec<Arc<TurnContext>>>>);
+
+    #[async_trait::async_trait]
+    impl SessionTask for ContextTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            if let Ok(mut contexts) = self.0.lock() {
+                contexts.push(Arc::clone(&ctx));
+            }
+            TaskResult::Failed(TaskError {
+                kind: TaskErrorKind::RateLimited,
+                retryable: true,
+                message: "rate limited".to_string(),
+            })
+        }
+
+        async fn retry(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            if let Ok(mut contexts) = self.0.lock() {
+                contexts.push(Arc::clone(&ctx));
+            }
+            TaskResult::Completed(Some("retried".to_string()))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_run_through_the_retry_hook_on_a_fresh_turn_context() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let task = ContextTask::default();
+        let report = sess
+            .spawn_task_with_retry(
+                Arc::clone(&tc),
+                "sub-context".to_string(),
+                Vec::new(),
+                task.clone(),
+                None,
+                None,
+                crate::retry::RetryPolicy {
+                    jitter: crate::retry::Jitter::None,
+                    ..crate::retry::RetryPolicy::TASK
+                },
+            )
+            .await
+            .expect("spawned")
+            .await
+            .expect("completed");
+        let contexts = task
+            .0
+            .lock()
+            .map(|contexts| contexts.clone())
+            .unwrap_or_default();
+
+        assert_eq!(report.last_agent_message, Some("retried".to_string()));
+        let [first, second] = <[_; 2]>::try_from(contexts).expect("two attempts");
+        assert!(
+            !Arc::ptr_eq(&first, &second) && !Arc::ptr_eq(&first, &tc),
+            "every attempt gets its own turn context"
+        );
+    }
+
+    /// Fails on its first attempt with an error retrying cannot fix.
+    struct DeniedTask;
+
+    #[async_trait::async_trait]
+    impl SessionTask for DeniedTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            _session: Arc<SessionTaskContext>,
+            _ctx: Arc<TurnContext>,
+            _sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            TaskResult::Failed(TaskError {
+                kind: TaskErrorKind::SandboxDenied,
+                retryable: false,
+                message: "sandbox denied".to_string(),
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_tasks_report_their_error_and_unretryable_ones_run_once() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let handle = sess
+            .spawn_task_with_retry(
+                Arc::clone(&tc),
+                "sub-denied".to_string(),
+                Vec::new(),
+                DeniedTask,
+                None,
+                None,
+                crate::retry::RetryPolicy {
+                    initial_delay: StdDuration::from_millis(100),
+                    multiplier: 2.0,
+                    max_delay: StdDuration::from_secs(5),
+                    jitter: crate::retry::Jitter::None,
+                    max_attempts: Some(5),
+                },
+            )
+            .await
+            .expect("spawned");
+        let report = handle.await.expect("report");
+
+        let complete = loop {
+            let event = rx.recv().await.expect("event");
+            if let EventMsg::TaskComplete(complete) = event.msg {
+                break complete;
+            }
+        };
+        let error = TaskError {
+            kind: TaskErrorKind::SandboxDenied,
+            retryable: false,
+            message: "sandbox denied".to_string(),
+        };
+        assert_eq!(
+            (
+                report.error,
+                complete.error,
+                last_visualizer_action(&sess, "task_retry_attempt"),
+                last_visualizer_action(&sess, "task_completed")
+                    .map(|action| action["error"].clone()),
+            ),
+            (
+                Some(error.clone()),
+                Some(error.clone()),
+                None,
+                Some(json!(error)),
+            )
+        );
+    }
+
+    /// How a task running for `runs` under a `timeout` deadline ends: its
+    /// final message if it completed or the abort reason if not, plus its
+    /// `task_aborted` action. Fails if it both completes and is aborted.
+    async fn end_under_deadline(
+        runs: StdDuration,
+        timeout: StdDuration,
+    ) -> (Result<Option<String>, TurnAbortReason>, Option<Value>) {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        sess.spawn_task_with_timeout(
+            Arc::clone(&tc),
+            "sub-deadline".to_string(),
+            Vec::new(),
+            SleepingTask(runs),
+            timeout,
+        )
+        .await;
+        tokio::time::sleep(runs.max(timeout) * 2).await;
+
+        let mut ends = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event.msg {
+                EventMsg::TaskComplete(complete) => ends.push(Ok(complete.last_agent_message)),
+                EventMsg::TurnAborted(aborted) => ends.push(Err(aborted.reason)),
+                _ => {}
+            }
+        }
+        let [end] = <[_; 1]>::try_from(ends).expect("the task ends exactly once");
+        (end, last_visualizer_action(&sess, "task_aborted"))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_past_its_deadline_is_aborted_with_timeout() {
+        assert_eq!(
+            end_under_deadline(StdDuration::from_secs(10), StdDuration::from_secs(5)).await,
+            (
+                Err(TurnAbortReason::Timeout),
+                Some(json!({
+                    "subId": "sub-deadline",
+                    "taskKind": "Regular",
+                    "reason": "Timeout",
+                    "firstResponseMs": null,
+                    "abortedBeforeResponse": true,
+                    "timeoutMs": 5_000,
+                })),
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_finishing_as_its_deadline_fires_completes() {
+        let deadline = StdDuration::from_secs(5);
+        for runs in [deadline - StdDuration::from_millis(1), deadline] {
+            assert_eq!(
+                end_under_deadline(runs, deadline).await,
+                (Ok(Some("done".to_string())), None)
+            );
+        }
+    }
+
+    /// Emits two steps of a migration as custom events, then tries to pass
+    /// one off as a task lifecycle event and reports the rejection.
+    struct MigrationTool;
+
+    #[async_trait::async_trait]
+    impl ToolHandler for MigrationTool {
+        fn kind(&self) -> ToolKind {
+            ToolKind::Function
+        }
+
+        async fn handle(
+            &self,
+            invocation: ToolInvocation,
+        ) -> Result<ToolOutput, FunctionCallError> {
+            let telemetry = invocation.telemetry;
+            for step in 1..=2 {
+                telemetry
+                    .emit("migration.step_applied", json!({ "step": step, "of": 2 }))
+                    .await
+                    .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+            }
+            let rejected = telemetry
+                .emit("task_completed", json!({}))
+                .await
+                .map_or_else(|err| err.to_string(), |()| "accepted".to_string());
+            Ok(ToolOutput::Function {
+                content: rejected,
+                success: Some(true),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_events_are_namespaced_stamped_and_counted_per_task() {
+        use std::collections::HashMap;
+
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-1".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+        let registry = ToolRegistry::new(HashMap::from([(
+            "migrate".to_string(),
+            Arc::new(MigrationTool) as Arc<dyn ToolHandler>,
+        )]));
+        let response = registry
+            .dispatch(ToolInvocation {
+                session: Arc::clone(&sess),
+                turn: Arc::clone(&tc),
+                tracker: Arc::new(tokio::sync::Mutex::new(TurnDiffTracker::new())),
+                sub_id: "sub-1".to_string(),
+                call_id: "call-1".to_string(),
+                tool_name: "migrate".to_string(),
+                payload: ToolPayload::Function {
+                    arguments: "{}".to_string(),
+                },
+                telemetry: TelemetryScope::new(
+                    Arc::clone(&sess),
+                    "sub-1".to_string(),
+                    "call-1".to_string(),
+                    "migrate".to_string(),
+                ),
+            })
+            .await
+            .expect("dispatch");
+        assert_eq!(
+            response,
+            ResponseInputItem::FunctionCallOutput {
+                call_id: "call-1".to_string(),
+                output: FunctionCallOutputPayload {
+                    content: "custom event type `task_completed` uses the reserved prefix `task_`"
+                        .to_string(),
+                    success: Some(true),
+                },
+            }
+        );
+        sess.on_task_finished("sub-1".to_string(), TaskResult::Completed(None))
+            .await;
+
+        let events: Vec<(String, Value)> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| {
+                event.action_type.starts_with("custom.") || event.action_type == "task_completed"
+            })
+            .map(|event| (event.action_type, event.action))
+            .collect();
+        let step = |step: u64| {
+            (
+                "custom.migration.step_applied".to_string(),
+                json!({
+                    "subId": "sub-1",
+                    "callId": "call-1",
+                    "tool": "migrate",
+                    "payload": { "step": step, "of": 2 },
+                }),
+            )
+        };
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[..2], [step(1), step(2)]);
+        assert_eq!(events[2].1["customEvents"], json!({ "migration": 2 }));
+    }
+
+    #[tokio::test]
+    async fn tool_events_over_the_payload_cap_are_rejected() {
+        use crate::tools::telemetry::CustomEventError;
+        use crate::tools::telemetry::MAX_PAYLOAD_BYTES;
+
+        let (sess, _tc, _rx) = make_session_and_context_with_rx();
+        let telemetry = TelemetryScope::new(
+            Arc::clone(&sess),
+            "sub-1".to_string(),
+            "call-1".to_string(),
+            "migrate".to_string(),
+        );
+        // Two bytes of quotes on top of the string itself.
+        let payload = json!("x".repeat(MAX_PAYLOAD_BYTES - 1));
+
+        assert_eq!(
+            telemetry.emit("migration.log", payload).await,
+            Err(CustomEventError::PayloadTooLarge {
+                action_type: "migration.log".to_string(),
+                bytes: MAX_PAYLOAD_BYTES + 1,
+                max_bytes: MAX_PAYLOAD_BYTES,
+            })
+        );
+        assert!(
+            !sess
+                .visualizer
+                .recent_events()
+                .iter()
+                .any(|event| event.action_type.starts_with("custom."))
+        );
+    }
+
+    /// Announces itself, reports progress twice, and finishes.
+    struct ScriptedTask;
+
+    #[async_trait::async_trait]
+    impl SessionTask for ScriptedTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            session: Arc<SessionTaskContext>,
+            ctx: Arc<TurnContext>,
+            sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            let sess = session.clone_session();
+            sess.send_event(Event {
+                id: sub_id.clone(),
+                msg: EventMsg::TaskStarted(TaskStartedEvent {
+                    model_context_window: ctx.client.get_model_context_window(),
+                }),
+            })
+            .await;
+            sess.notify_background_event(&sub_id, "step one").await;
+            sess.notify_background_event(&sub_id, "step two").await;
+            TaskResult::Completed(Some("done".to_string()))
+        }
+    }
+
+    /// Raises two command approvals concurrently and reports how each one
+    /// resolved as its final message.
+    struct TwoApprovalsTask;
+
+    #[async_trait::async_trait]
+    impl SessionTask for TwoApprovalsTask {
+        fn kind(&self) -> TaskKind {
+            TaskKind::Regular
+        }
+
+        async fn run(
+            self: Arc<Self>,
+            session: Arc<SessionTaskContext>,
+            ctx: Arc<TurnContext>,
+            sub_id: String,
+            _input: Vec<InputItem>,
+        ) -> TaskResult {
+            let sess = session.clone_session();
+            let request = |call_id: &str| {
+                sess.request_command_approval(
+                    sub_id.clone(),
+                    call_id.to_string(),
+                    vec!["echo".to_string(), call_id.to_string()],
+                    ctx.cwd.clone(),
+                    None,
+                )
+            };
+            let (first, second) = tokio::join!(request("call-1"), request("call-2"));
+            TaskResult::Completed(Some(format!("{first:?}, {second:?}")))
+        }
+    }
+
+    async fn next_approval_request(rx: &async_channel::Receiver<Event>) -> String {
+        loop {
+            let event = rx.recv().await.expect("event");
+            if let EventMsg::ExecApprovalRequest(request) = event.msg {
+                return request.call_id;
+            }
+        }
+    }
+
+    fn conversation_ended_action(sess: &Session) -> Option<Value> {
+        sess.visualizer
+            .recent_events()
+            .into_iter()
+            .rev()
+            .find(|event| event.action_type == "conversation_ended")
+            .map(|event| event.action)
+    }
+
+    #[tokio::test]
+    async fn shutdown_emits_conversation_ended_as_user_exit() {
+        let (sess, tc) = make_session_and_context();
+        let sess = Arc::new(sess);
+        let codex_home = tempfile::tempdir().expect("create temp dir");
+        let config = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .expect("load default test config");
+        let (tx_sub, rx_sub) = async_channel::bounded(1);
+        let agent_loop = tokio::spawn(submission_loop(
+            Arc::clone(&sess),
+            tc,
+            Arc::new(config),
+            rx_sub,
+        ));
+
+        tx_sub
+            .send(Submission {
+                id: "shutdown".to_string(),
+                op: Op::Shutdown,
+            })
+            .await
+            .expect("submit shutdown");
+        agent_loop.await.expect("agent loop");
+
+        assert_eq!(
+            conversation_ended_action(&sess),
+            Some(json!({
+                "reason": { "kind": "user_exit" },
+                "lastSubId": null,
+                "totals": {
+                    "tasksStarted": 0,
+                    "historyItems": 0,
+                    "totalTokens": null,
+                    "firstResponseLatency": FirstResponseHistogram::default(),
+                },
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn panicking_session_loop_emits_conversation_ended_as_error() {
+        let (sess, _tc) = make_session_and_context();
+        let sess = Arc::new(sess);
+        {
+            let mut state = sess.state.lock().await;
+            state.tasks_started = 1;
+            state.last_task_sub_id = Some("sub-1".to_string());
+        }
+
+        let guard = ConversationEndGuard::new(Arc::clone(&sess));
+        let crashed = tokio::spawn(async move {
+            let _guard = guard;
+            panic!("simulated fatal session error");
+        })
+        .await;
+        assert!(crashed.is_err_and(|err| err.is_panic()));
+
+        assert_eq!(
+            conversation_ended_action(&sess),
+            Some(json!({
+                "reason": { "kind": "error", "summary": "session loop panicked" },
+                "lastSubId": "sub-1",
+                "totals": {
+                    "tasksStarted": 1,
+                    "historyItems": 0,
+                    "totalTokens": null,
+                    "firstResponseLatency": FirstResponseHistogram::default(),
+                },
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn filtered_event_streams_each_see_their_kinds() {
+        use futures::StreamExt;
+
+        async fn kinds_until_complete(mut stream: EventStream) -> Vec<EventDiscriminant> {
+            let mut kinds = Vec::new();
+            while let Some(event) = stream.next().await {
+                let kind = EventDiscriminant::from(&event.msg);
+                kinds.push(kind);
+                if kind == EventDiscriminant::TaskComplete {
+                    break;
+                }
+            }
+            kinds
+        }
+
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let progress = tokio::spawn(kinds_until_complete(sess.event_stream().filtered(&[
+            EventDiscriminant::BackgroundEvent,
+            EventDiscriminant::TaskComplete,
+        ])));
+        let lifecycle = tokio::spawn(kinds_until_complete(sess.event_stream().filtered(&[
+            EventDiscriminant::TaskStarted,
+            EventDiscriminant::TaskComplete,
+        ])));
+
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-1".to_string(),
+            Vec::new(),
+            ScriptedTask,
+        )
+        .await;
+
+        let timeout = std::time::Duration::from_secs(5);
+        assert_eq!(
+            tokio::time::timeout(timeout, progress)
+                .await
+                .expect("progress stream finishes")
+                .expect("progress task"),
+            vec![
+                EventDiscriminant::BackgroundEvent,
+                EventDiscriminant::BackgroundEvent,
+                EventDiscriminant::TaskComplete,
+            ]
+        );
+        assert_eq!(
+            tokio::time::timeout(timeout, lifecycle)
+                .await
+                .expect("lifecycle stream finishes")
+                .expect("lifecycle task"),
+            vec![
+                EventDiscriminant::TaskStarted,
+                EventDiscriminant::TaskComplete
+            ]
+        );
+
+        // The single-consumer channel still gets everything.
+        let mut delivered = Vec::new();
+        for _ in 0..4 {
+            let event = rx.recv().await.expect("event");
+            delivered.push(EventDiscriminant::from(&event.msg));
+        }
+        assert_eq!(
+            delivered,
+            vec![
+                EventDiscriminant::TaskStarted,
+                EventDiscriminant::BackgroundEvent,
+                EventDiscriminant::BackgroundEvent,
+                EventDiscriminant::TaskComplete,
+            ]
+        );
+    }
+
+    fn tui_client_context(terminal_cols: u16) -> ClientContext {
+        ClientContext {
+            name: "codex-tui".to_string(),
+            version: "1.2.3".to_string(),
+            terminal_cols: Some(terminal_cols),
+            terminal_rows: Some(40),
+            color_depth: Some(ColorDepth::Ansi256),
+            supports_images: false,
+        }
+    }
+
+    fn last_visualizer_action(sess: &Session, action_type: &str) -> Option<Value> {
+        sess.visualizer
+            .recent_events()
+            .into_iter()
+            .rev()
+            .find(|event| event.action_type == action_type)
+            .map(|event| event.action)
+    }
+
+    async fn run_scripted_task(
+        sess: &Arc<Session>,
+        tc: &Arc<TurnContext>,
+        rx: &async_channel::Receiver<Event>,
+        sub_id: &str,
+        client_context: Option<ClientContext>,
+        replay_of: Option<&str>,
+    ) {
+        sess.spawn_task_for_client(
+            Arc::clone(tc),
+            sub_id.to_string(),
+            Vec::new(),
+            ScriptedTask,
+            client_context,
+            replay_of.map(str::to_string),
+        )
+        .await;
+        loop {
+            let event = rx.recv().await.expect("event");
+            if matches!(event.msg, EventMsg::TaskComplete(_)) {
+                break;
+            }
+        }
+        // `task_completed` is emitted right after `TaskComplete` is sent.
+        while last_visualizer_action(sess, "task_completed")
+            .is_none_or(|action| action["subId"] != sub_id)
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn client_context_is_reported_only_when_submitted() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let expected = json!({
+            "name": "codex-tui",
+            "version": "1.2.3",
+            "terminal_cols": 120,
+            "terminal_rows": 40,
+            "color_depth": "ansi256",
+            "supports_images": false,
+        });
+
+        run_scripted_task(&sess, &tc, &rx, "with", Some(tui_client_context(120)), None).await;
+        let spawned = last_visualizer_action(&sess, "task_spawned").expect("task_spawned");
+        let completed = last_visualizer_action(&sess, "task_completed").expect("task_completed");
+        assert_eq!(spawned.get("clientContext"), Some(&expected));
+        assert_eq!(completed.get("clientContext"), Some(&expected));
+
+        run_scripted_task(&sess, &tc, &rx, "without", None, None).await;
+        let spawned = last_visualizer_action(&sess, "task_spawned").expect("task_spawned");
+        let completed = last_visualizer_action(&sess, "task_completed").expect("task_completed");
+        assert_eq!(spawned["subId"], "without");
+        assert_eq!(spawned.get("clientContext"), None);
+        assert_eq!(completed.get("clientContext"), None);
+    }
+
+    #[tokio::test]
+    async fn background_tasks_and_user_turns_keep_separate_lifecycles() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let gate = Arc::new(tokio::sync::Notify::new());
+        sess.spawn_background_task(
+            Arc::clone(&tc),
+            "bg-gated".to_string(),
+            Vec::new(),
+            GatedTask(Arc::clone(&gate)),
+        )
+        .await;
+        sess.spawn_background_task(
+            Arc::clone(&tc),
+            "bg-forever".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+
+        // A user turn spawned meanwhile neither replaces the background
+        // tasks nor sees them as running.
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "user".to_string(),
+            Vec::new(),
+            ScriptedTask,
+        )
+        .await;
+        let mut user_events = Vec::new();
+        loop {
+            let event = rx.recv().await.expect("event");
+            let done = matches!(event.msg, EventMsg::TaskComplete(_));
+            let msg = serde_json::to_value(&event.msg).expect("serialize");
+            user_events.push((event.id, msg["type"].clone()));
+            if done {
+                break;
+            }
+        }
+        assert_eq!(
+            user_events,
+            vec![
+                ("user".to_string(), json!("task_started")),
+                ("user".to_string(), json!("background_event")),
+                ("user".to_string(), json!("background_event")),
+                ("user".to_string(), json!("task_complete")),
+            ]
+        );
+        assert_eq!(
+            last_visualizer_action(&sess, "background_task_aborted"),
+            None
+        );
+
+        gate.notify_one();
+        let completed = tokio::time::timeout(StdDuration::from_secs(5), async {
+            loop {
+                if let Some(action) = last_visualizer_action(&sess, "background_task_completed") {
+                    return action;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("background task completes");
+        assert_eq!(completed["subId"], json!("bg-gated"));
+        assert!(sess.running_tasks().await.is_empty());
+
+        sess.abort_background_tasks(TurnAbortReason::Interrupted)
+            .await;
+        assert_eq!(
+            last_visualizer_action(&sess, "background_task_aborted"),
+            Some(json!({
+                "subId": "bg-forever",
+                "taskKind": "Regular",
+                "reason": "Interrupted",
+            }))
+        );
+        // Neither background task emitted a protocol event.
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn background_spawn_with_a_running_sub_id_is_rejected() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        for kind in [TaskKind::Regular, TaskKind::Review] {
+            sess.spawn_background_task(
+                Arc::clone(&tc),
+                "bg".to_string(),
+                Vec::new(),
+                NeverEndingTask(kind),
+            )
+            .await;
+        }
+        assert_eq!(
+            last_visualizer_action(&sess, "background_task_spawn_rejected"),
+            Some(json!({
+                "subId": "bg",
+                "taskKind": "Review",
+                "reason": { "type": "duplicateSubId" },
+            }))
+        );
+
+        // The first task kept its slot and is the one shutdown aborts.
+        sess.abort_background_tasks(TurnAbortReason::Interrupted)
+            .await;
+        assert_eq!(
+            last_visualizer_action(&sess, "background_task_aborted"),
+            Some(json!({
+                "subId": "bg",
+                "taskKind": "Regular",
+                "reason": "Interrupted",
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn timeline_reports_are_written_in_the_background_lane() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("timeline.html");
+        sess.spawn_background_task(
+            Arc::clone(&tc),
+            "report".to_string(),
+            Vec::new(),
+            TimelineReportTask::new(path.clone()),
+        )
+        .await;
+
+        let evt = rx.recv().await.expect("event");
+        assert_eq!(evt.id, "report");
+        match evt.msg {
+            EventMsg::BackgroundEvent(e) => assert_eq!(
+                e.message,
+                format!("Wrote timeline report to {}", path.display())
+            ),
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert!(path.is_file());
+        assert_eq!(
+            last_visualizer_action(&sess, "background_task_spawned")
+                .map(|action| action["taskKind"].clone()),
+            Some(json!("Report"))
+        );
+        // Not a turn: nothing else reached the client.
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn task_spawned_reports_reproducibility_and_replays_reuse_the_seed() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+
+        run_scripted_task(&sess, &tc, &rx, "original", None, None).await;
+        let original =
+            last_visualizer_action(&sess, "task_spawned").expect("task_spawned")["reproducibility"]
+                .clone();
+        let tools = ToolRouter::from_config(
+            &tc.tools_config,
+            Some(sess.services.mcp_connection_manager.list_all_tools()),
+        )
+        .specs();
+        let tool_names = original["tools"]
+            .as_array()
+            .expect("tools")
+            .iter()
+            .map(|tool| tool["name"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tool_names,
+            tools
+                .iter()
+                .map(|spec| json!(spec.name()))
+                .collect::<Vec<_>>()
+        );
+        let mut fields = original.clone();
+        let prompt_hash = fields["promptHash"].take();
+        fields["tools"].take();
+        assert_eq!(prompt_hash.as_str().map(str::len), Some(40));
+        assert_eq!(
+            fields,
+            json!({
+                "model": tc.client.get_model(),
+                "effort": tc.client.get_reasoning_effort(),
+                "summary": tc.client.get_reasoning_summary(),
+                "promptHash": null,
+                "tools": null,
+                "seed": original["seed"].as_u64().expect("seed"),
+                "seedForwarded": false,
+            })
+        );
+
+        // Nothing was added to the history, so the replay starts from the
+        // same prompt and reuses the original's seed.
+        run_scripted_task(&sess, &tc, &rx, "replay", None, Some("original")).await;
+        let replay =
+            last_visualizer_action(&sess, "task_spawned").expect("task_spawned")["reproducibility"]
+                .clone();
+        let mut expected = original;
+        expected["replayOf"] = json!("original");
+        assert_eq!(replay, expected);
+    }
+
+    #[tokio::test]
+    async fn digest_covers_only_activity_after_the_cursor() {
+        use crate::protocol::DigestError;
+        use crate::protocol::DigestEvent;
+        use crate::protocol::DigestTurn;
+        use crate::protocol::FileChange;
+        use crate::protocol::TokenUsageInfo;
+        use std::collections::HashMap;
+
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let written = dir.path().join("two.txt");
+        let set_total = |total_tokens: u64| {
+            let sess = Arc::clone(&sess);
+            async move {
+                sess.state.lock().await.token_info = Some(TokenUsageInfo {
+                    total_token_usage: TokenUsage {
+                        total_tokens,
+                        ..Default::default()
+                    },
+                    last_token_usage: TokenUsage::default(),
+                    model_context_window: None,
+                });
+            }
+        };
+
+        set_total(100).await;
+        run_scripted_task(&sess, &tc, &rx, "one", None, None).await;
+        let cursor = sess
+            .visualizer
+            .recent_events()
+            .last()
+            .map(|event| event.sequence);
+
+        let mut tracker = TurnDiffTracker::new();
+        tracker.on_patch_begin(&HashMap::from([(
+            written.clone(),
+            FileChange::Add {
+                content: "two\n".to_string(),
+            },
+        )]));
+        std::fs::write(&written, "two\n").expect("write file");
+        sess.record_task_writes(TaskWrites::capture("two", tracker.touched_files()).await)
+            .await;
+        sess.send_event(Event {
+            id: "two".to_string(),
+            msg: EventMsg::Error(ErrorEvent {
+                message: "boom".to_string(),
+            }),
+        })
+        .await;
+        set_total(250).await;
+        run_scripted_task(&sess, &tc, &rx, "two", None, None).await;
+        set_total(400).await;
+        run_scripted_task(&sess, &tc, &rx, "three", None, None).await;
+
+        let strip_times = |mut digest: DigestEvent| {
+            for turn in &mut digest.turns_completed {
+                turn.completed_at_ms = 0;
+            }
+            for error in &mut digest.errors {
+                error.at_ms = 0;
+            }
+            digest.through_sequence = None;
+            digest
+        };
+        let turn = |sub_id: &str, files_changed: Vec<PathBuf>| DigestTurn {
+            sub_id: sub_id.to_string(),
+            completed_at_ms: 0,
+            last_agent_message: Some("done".to_string()),
+            files_changed,
+            error: None,
+            focus: None,
+            template: None,
+        };
+        let tokens = |total_tokens| TokenUsage {
+            total_tokens,
+            ..Default::default()
+        };
+        let next = sess.visualizer.next_sequence();
+
+        let since_cursor = sess
+            .digest(DigestSince {
+                sequence: cursor,
+                time_ms: None,
+            })
+            .await;
+        assert_eq!(since_cursor.through_sequence, Some(next - 1));
+        assert_eq!(
+            strip_times(since_cursor),
+            DigestEvent {
+                through_sequence: None,
+                complete: true,
+                turns_completed: vec![
+                    turn("two", vec![written.clone()]),
+                    turn("three", Vec::new()),
+                ],
+                files_changed: vec![written.clone()],
+                tokens_spent: tokens(300),
+                errors: vec![DigestError {
+                    sub_id: "two".to_string(),
+                    at_ms: 0,
+                    message: "boom".to_string(),
+                }],
+                annotations: Vec::new(),
+                running_task: None,
+                by_focus: Vec::new(),
+                aggregated_turns: Vec::new(),
+            }
+        );
+
+        let everything = sess.digest(DigestSince::default()).await;
+        assert_eq!(
+            strip_times(everything),
+            DigestEvent {
+                through_sequence: None,
+                complete: true,
+                turns_completed: vec![
+                    turn("one", Vec::new()),
+                    turn("two", vec![written.clone()]),
+                    turn("three", Vec::new()),
+                ],
+                files_changed: vec![written],
+                tokens_spent: tokens(400),
+                errors: vec![DigestError {
+                    sub_id: "two".to_string(),
+                    at_ms: 0,
+                    message: "boom".to_string(),
+                }],
+                annotations: Vec::new(),
+                running_task: None,
+                by_focus: Vec::new(),
+                aggregated_turns: Vec::new(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot_keeps_the_latest_submitted_client_context() {
+        let (sess, _tc) = make_session_and_context();
+        assert_eq!(
+            sess.visualization_state_snapshot()
+                .await
+                .get("clientContext"),
+            None
+        );
+
+        sess.remember_client_context(Some(&tui_client_context(80)))
+            .await;
+        sess.remember_client_context(Some(&tui_client_context(120)))
+            .await;
+        sess.remember_client_context(None).await;
+
+        let snapshot = sess.visualization_state_snapshot().await;
+        assert_eq!(
+            snapshot.get("clientContext"),
+            Some(&json!(tui_client_context(120)))
+        );
+    }
+
+    fn review_finding(title: &str) -> ReviewFinding {
+        ReviewFinding {
+            title: title.to_string(),
+            body: "details".to_string(),
+            confidence_score: 0.5,
+            priority: 1,
+            code_location: ReviewCodeLocation {
+                absolute_file_path: PathBuf::from("/repo/src/lib.rs"),
+                line_range: ReviewLineRange { start: 3, end: 7 },
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_restores_the_latest_review_findings() {
+        let (session, turn_context) = make_session_and_context();
+        let latest = vec![review_finding("latest one"), review_finding("latest two")];
+
+        session
+            .record_initial_history(
+                &turn_context,
+                InitialHistory::Resumed(ResumedHistory {
+                    conversation_id: ConversationId::default(),
+                    history: vec![
+                        RolloutItem::ReviewFindings(ReviewFindingsItem {
+                            findings: vec![review_finding("stale")],
+                        }),
+                        RolloutItem::ReviewFindings(ReviewFindingsItem {
+                            findings: latest.clone(),
+                        }),
+                    ],
+                    rollout_path: PathBuf::from("/tmp/resume.jsonl"),
+                }),
+            )
+            .await;
+
+        assert_eq!(session.state.lock().await.review_findings, latest);
+        assert_eq!(
+            last_visualizer_action(&session, "findings_restored"),
+            Some(json!({ "count": 2 }))
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_without_review_findings_restores_nothing() {
+        let (session, turn_context) = make_session_and_context();
+        let (rollout_items, _) = sample_rollout(&session, &turn_context);
+
+        session
+            .record_initial_history(
+                &turn_context,
+                InitialHistory::Resumed(ResumedHistory {
+                    conversation_id: ConversationId::default(),
+                    history: rollout_items,
+                    rollout_path: PathBuf::from("/tmp/resume.jsonl"),
+                }),
+            )
+            .await;
+
+        assert_eq!(session.state.lock().await.review_findings, Vec::new());
+        assert_eq!(last_visualizer_action(&session, "findings_restored"), None);
+    }
+
+    #[tokio::test]
+    async fn resume_reports_the_task_a_crash_cut_short() {
+        use crate::protocol::FileChange;
+        use std::collections::HashMap;
+
+        let codex_home = tempfile::tempdir().expect("create temp dir");
+        let config = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .expect("load default test config");
+        let (session, _turn_context, _rx) = make_session_and_context_with_rx();
+        let recorder = RolloutRecorder::new(
+            &config,
+            RolloutRecorderParams::new(ConversationId::default(), None, SessionSource::Exec),
+        )
+        .await
+        .expect("create rollout recorder");
+        let rollout_path = recorder.get_rollout_path();
+        *session.services.rollout.lock().await = Some(recorder.clone());
+
+        let patched = PathBuf::from("/repo/src/lib.rs");
+        let events = [
+            EventMsg::TaskStarted(TaskStartedEvent {
+                model_context_window: None,
+            }),
+            EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
+                call_id: "patch-1".to_string(),
+                auto_approved: true,
+                changes: HashMap::from([(
+                    patched.clone(),
+                    FileChange::Add {
+                        content: "pub fn f() {}\n".to_string(),
+                    },
+                )]),
+            }),
+            EventMsg::PatchApplyEnd(PatchApplyEndEvent {
+                call_id: "patch-1".to_string(),
+                stdout: String::new(),
+                stderr: String::new(),
+                success: true,
+            }),
+        ];
+        for msg in events {
+            session
+                .send_event(Event {
+                    id: "task-1".to_string(),
+                    msg,
+                })
+                .await;
+        }
+        session
+            .persist_rollout_items(&[RolloutItem::ResponseItem(ResponseItem::FunctionCall {
+                id: None,
+                name: "shell".to_string(),
+                arguments: "{}".to_string(),
+                call_id: "shell-1".to_string(),
+            })])
+            .await;
+        // The process dies here, before the task completes.
+        recorder.flush().await.expect("flush rollout");
+        drop(session);
+
+        let history = RolloutRecorder::get_rollout_history(&rollout_path)
+            .await
+            .expect("read rollout");
+        let (resumed, turn_context, rx) = make_session_and_context_with_rx();
+        resumed.record_initial_history(&turn_context, history).await;
+        resumed.report_recovered_task(INITIAL_SUBMIT_ID).await;
+
+        let expected = TaskRecoveredIncompleteEvent {
+            sub_id: "task-1".to_string(),
+            message: "The previous session ended before task task-1 finished (1 file(s) patched, 0 command(s) run, 1 tool call(s) without a result).".to_string(),
+            patched_paths: vec![patched],
+            completed_commands: Vec::new(),
+            pending_calls: vec![RecoveredToolCall {
+                call_id: "shell-1".to_string(),
+                name: "shell".to_string(),
+            }],
+        };
+        let reported = rx.recv().await.expect("recovery event");
+        assert_eq!(
+            serde_json::to_value(&reported).expect("serialize"),
+            serde_json::to_value(Event {
+                id: INITIAL_SUBMIT_ID.to_string(),
+                msg: EventMsg::TaskRecoveredIncomplete(expected.clone()),
+            })
+            .expect("serialize")
+        );
+        assert_eq!(
+            crate::task_recovery::continuation_prompt(&expected),
+            "The previous session ended while a task was still in progress. Continue that task.\n\n\
+             It had already patched these files:\n- /repo/src/lib.rs\n\n\
+             These tool calls never returned, so their effects are unknown:\n- shell (shell-1)\n\n\
+             Check the current state of the workspace before making further changes."
+        );
+
+        resumed
+            .resolve_recovered_task(
+                Arc::clone(&turn_context),
+                "resolve-1".to_string(),
+                RecoveredTaskAction::Abandon,
+            )
+            .await;
+        assert_eq!(resumed.state.lock().await.recovered_task, None);
+        resumed
+            .resolve_recovered_task(
+                turn_context,
+                "resolve-2".to_string(),
+                RecoveredTaskAction::Abandon,
+            )
+            .await;
+        let error = rx.recv().await.expect("error event");
+        assert_eq!(
+            serde_json::to_value(&error.msg).expect("serialize"),
+            json!({ "type": "error", "message": "no recovered task to resolve" })
+        );
+    }
+
+    #[tokio::test]
+    async fn annotations_are_persisted_emitted_and_exported() {
+        use futures::StreamExt;
+
+        let codex_home = tempfile::tempdir().expect("create temp dir");
+        let config = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .expect("load default test config");
+        let (session, turn_context, _rx) = make_session_and_context_with_rx();
+        let recorder = RolloutRecorder::new(
+            &config,
+            RolloutRecorderParams::new(ConversationId::default(), None, SessionSource::Exec),
+        )
+        .await
+        .expect("create rollout recorder");
+        *session.services.rollout.lock().await = Some(recorder.clone());
+        let mut errors = session.event_stream().filtered(&[EventDiscriminant::Error]);
+
+        session
+            .annotate(
+                "op-1".to_string(),
+                "baseline looks fine".to_string(),
+                None,
+                Vec::new(),
+            )
+            .await;
+        session
+            .spawn_task(
+                Arc::clone(&turn_context),
+                "task-1".to_string(),
+                Vec::new(),
+                NeverEndingTask(TaskKind::Regular),
+            )
+            .await;
+        session
+            .annotate(
+                "op-2".to_string(),
+                "this is where it went <wrong>".to_string(),
+                Some("task-1".to_string()),
+                vec!["regression".to_string()],
+            )
+            .await;
+        session
+            .annotate("op-3".to_string(), " ".to_string(), None, Vec::new())
+            .await;
+
+        let error = errors.next().await.expect("error event");
+        assert_eq!(
+            serde_json::to_value(&error).expect("serialize"),
+            json!({
+                "id": "op-3",
+                "msg": { "type": "error", "message": "invalid annotation: annotation text is empty" },
+            })
+        );
+
+        let annotations = session.digest(DigestSince::default()).await.annotations;
+        let at_ms: Vec<u64> = annotations
+            .iter()
+            .map(|annotation| annotation.at_ms)
+            .collect();
+        assert_eq!(
+            annotations,
+            vec![
+                Annotation {
+                    text: "baseline looks fine".to_string(),
+                    sub_id: None,
+                    tags: Vec::new(),
+                    at_ms: at_ms[0],
+                },
+                Annotation {
+                    text: "this is where it went <wrong>".to_string(),
+                    sub_id: Some("task-1".to_string()),
+                    tags: vec!["regression".to_string()],
+                    at_ms: at_ms[1],
+                },
+            ]
+        );
+        assert_eq!(
+            last_visualizer_action(&session, "annotation"),
+            Some(json!({
+                "subId": "task-1",
+                "text": "this is where it went <wrong>",
+                "tags": ["regression"],
+                "atMs": at_ms[1],
+            }))
+        );
+
+        recorder.flush().await.expect("flush rollout");
+        let persisted: Vec<Annotation> =
+            RolloutRecorder::get_rollout_history(&recorder.get_rollout_path())
+                .await
+                .expect("read rollout")
+                .get_rollout_items()
+                .into_iter()
+                .filter_map(|item| match item {
+                    RolloutItem::Annotation(annotation) => Some(annotation),
+                    _ => None,
+                })
+                .collect();
+        assert_eq!(persisted, annotations);
+
+        let html = render_html(&session.visualizer.recent_events());
+        assert_eq!(html.matches("<tr class=\"annotation\">").count(), 2);
+        assert!(html.contains(
+            "<td>task-1</td><td>this is where it went &lt;wrong&gt;</td><td>regression</td>"
+        ));
+    }
+
+    #[tokio::test]
+    async fn telemetry_rules_swap_mid_task_without_touching_the_sequence() {
+        use crate::protocol::TelemetryConfigPatch;
+        use crate::visualizer::VisualizerEvent;
+        use futures::StreamExt;
+
+        let (session, turn_context, _rx) = make_session_and_context_with_rx();
+        let mut rejections = session
+            .event_stream()
+            .filtered(&[EventDiscriminant::TelemetryConfigRejected]);
+        session
+            .spawn_task(
+                Arc::clone(&turn_context),
+                "task-1".to_string(),
+                Vec::new(),
+                NeverEndingTask(TaskKind::Regular),
+            )
+            .await;
+
+        let first = session.visualizer.next_sequence();
+        let secret = json!({ "text": "token sk-abc123" });
+        session
+            .emit_with_state("assistant_preview", secret.clone())
+            .await;
+        session.emit_with_state("reasoning_phase", json!({})).await;
+        session
+            .update_telemetry_config(
+                "op-1".to_string(),
+                TelemetryConfigPatch {
+                    exclude_action_types: Some(vec!["reasoning_phase".to_string()]),
+                    redact_patterns: Some(vec!["sk-[a-z0-9]+".to_string()]),
+                    ..Default::default()
+                },
+            )
+            .await;
+        session
+            .emit_with_state("assistant_preview", secret.clone())
+            .await;
+        session.emit_with_state("reasoning_phase", json!({})).await;
+        session
+            .update_telemetry_config(
+                "op-2".to_string(),
+                TelemetryConfigPatch {
+                    exclude_action_types: Some(Vec::new()),
+                    redact_patterns: Some(vec!["(sk-".to_string()]),
+                    ..Default::default()
+                },
+            )
+            .await;
+        session.emit_with_state("reasoning_phase", json!({})).await;
+
+        let rejection = match rejections.next().await.map(|event| event.msg) {
+            Some(EventMsg::TelemetryConfigRejected(rejection)) => rejection,
+            other => panic!("expected a rejection, got {other:?}"),
+        };
+        assert_eq!(
+            rejection
+                .errors
+                .into_iter()
+                .map(|error| error.key)
+                .collect::<Vec<_>>(),
+            vec!["redact_patterns[0]".to_string()]
+        );
+        let recent: Vec<VisualizerEvent> = session
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.sequence >= first)
+            .collect();
+        // Filtered events never got a sequence number.
+        let sequences: Vec<u64> = recent.iter().map(|event| event.sequence).collect();
+        let expected: Vec<u64> = (first..first + sequences.len() as u64).collect();
+        assert_eq!(sequences, expected);
+
+        let emitted: Vec<(String, Value)> = recent
+            .into_iter()
+            .filter(|event| {
+                [
+                    "assistant_preview",
+                    "reasoning_phase",
+                    "telemetry_config_updated",
+                ]
+                .contains(&event.action_type.as_str())
+            })
+            .map(|event| (event.action_type, event.action))
+            .collect();
+        assert_eq!(
+            emitted,
+            vec![
+                ("assistant_preview".to_string(), secret),
+                ("reasoning_phase".to_string(), json!({})),
+                (
+                    "telemetry_config_updated".to_string(),
+                    json!({ "changedKeys": ["exclude_action_types", "redact_patterns"] }),
+                ),
+                (
+                    "assistant_preview".to_string(),
+                    json!({ "text": "token [REDACTED]" }),
+                ),
+                // The rejected update left `reasoning_phase` excluded.
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn filter_swaps_mid_task_apply_from_the_next_event() {
+        use crate::protocol::TelemetryConfigPatch;
+
+        let (session, turn_context, _rx) = make_session_and_context_with_rx();
+        session
+            .spawn_task(
+                Arc::clone(&turn_context),
+                "task-1".to_string(),
+                Vec::new(),
+                NeverEndingTask(TaskKind::Regular),
+            )
+            .await;
+        let exclude = |action_types: &[&str]| TelemetryConfigPatch {
+            exclude_action_types: Some(action_types.iter().map(|t| t.to_string()).collect()),
+            ..Default::default()
+        };
+        let emit_both = |step: u64| {
+            let session = Arc::clone(&session);
+            async move {
+                for action_type in ["assistant_preview", "reasoning_phase"] {
+                    session
+                        .emit_with_state(action_type, json!({ "step": step }))
+                        .await;
+                }
+            }
+        };
+
+        let first = session.visualizer.next_sequence();
+        emit_both(1).await;
+        session
+            .update_telemetry_config("op-1".to_string(), exclude(&["reasoning_phase"]))
+            .await;
+        emit_both(2).await;
+        session
+            .update_telemetry_config("op-2".to_string(), exclude(&["assistant_preview"]))
+            .await;
+        emit_both(3).await;
+
+        let emitted: Vec<(String, Value)> = session
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| {
+                event.sequence >= first
+                    && ["assistant_preview", "reasoning_phase"]
+                        .contains(&event.action_type.as_str())
+            })
+            .map(|event| (event.action_type, event.action["step"].clone()))
+            .collect();
+        assert_eq!(
+            emitted,
+            vec![
+                ("assistant_preview".to_string(), json!(1)),
+                ("reasoning_phase".to_string(), json!(1)),
+                ("assistant_preview".to_string(), json!(2)),
+                ("reasoning_phase".to_string(), json!(3)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn first_response_latency_is_measured_from_spawn() {
+        use crate::WireApi;
+        use crate::model_provider_info::create_oss_provider_with_base_url;
+        use core_test_support::responses::ev_assistant_message;
+        use core_test_support::responses::ev_completed;
+        use core_test_support::responses::ev_response_created;
+        use core_test_support::responses::sse;
+        use core_test_support::responses::sse_response;
+        use core_test_support::responses::start_mock_server;
+        use core_test_support::skip_if_no_network;
+        use std::collections::HashMap;
+        use wiremock::Mock;
+        use wiremock::matchers::method;
+
+        skip_if_no_network!();
+
+        // The model takes this long to start streaming.
+        const DELAY_MS: u64 = 300;
+        let server = start_mock_server().await;
+        let body = sse(vec![
+            ev_response_created("resp-1"),
+            ev_assistant_message("msg-1", "hello"),
+            ev_completed("resp-1"),
+        ]);
+        Mock::given(method("POST"))
+            .respond_with(sse_response(body).set_delay(StdDuration::from_millis(DELAY_MS)))
+            .mount(&server)
+            .await;
+        let provider = ModelProviderInfo {
+            wire_api: WireApi::Responses,
+            request_max_retries: Some(0),
+            stream_max_retries: Some(0),
+            ..create_oss_provider_with_base_url(&format!("{}/v1", server.uri()))
+        };
+        let (sess, tc, rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            model_provider: Some("mock".to_string()),
+            model_providers: HashMap::from([("mock".to_string(), provider)]),
+            ..Default::default()
+        });
+
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-1".to_string(),
+            vec![InputItem::Text {
+                text: "hi".to_string(),
+            }],
+            RegularTask::default(),
+        )
+        .await;
+        loop {
+            match rx.recv().await.expect("event").msg {
+                EventMsg::TaskComplete(_) => break,
+                EventMsg::Error(error) => panic!("task failed: {}", error.message),
+                _ => {}
+            }
+        }
+        while last_visualizer_action(&sess, "task_completed").is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        let measured = last_visualizer_action(&sess, "first_response_latency")
+            .expect("first_response_latency");
+        let latency_ms = measured["firstResponseMs"]
+            .as_u64()
+            .expect("firstResponseMs");
+        assert!(
+            (DELAY_MS..DELAY_MS + 5_000).contains(&latency_ms),
+            "first response after {latency_ms}ms"
+        );
+        let completed = last_visualizer_action(&sess, "task_completed").expect("task_completed");
+        assert_eq!(
+            (
+                measured["subId"].clone(),
+                completed["firstResponseMs"].clone()
+            ),
+            (json!("sub-1"), json!(latency_ms))
+        );
+    }
+
+    #[tokio::test]
+    async fn compaction_aborted_mid_request_reports_its_cancellation_and_keeps_the_history() {
+        use crate::WireApi;
+        use crate::model_provider_info::create_oss_provider_with_base_url;
+        use core_test_support::responses::ev_assistant_message;
+        use core_test_support::responses::ev_completed;
+        use core_test_support::responses::ev_response_created;
+        use core_test_support::responses::sse;
+        use core_test_support::responses::sse_response;
+        use core_test_support::responses::start_mock_server;
+        use core_test_support::skip_if_no_network;
+        use std::collections::HashMap;
+        use wiremock::Mock;
+        use wiremock::matchers::method;
+
+        skip_if_no_network!();
+
+        // Never answered before the abort.
+        let server = start_mock_server().await;
+        let body = sse(vec![
+            ev_response_created("resp-1"),
+            ev_assistant_message("msg-1", "summary"),
+            ev_completed("resp-1"),
+        ]);
+        Mock::given(method("POST"))
+            .respond_with(sse_response(body).set_delay(StdDuration::from_secs(60)))
+            .mount(&server)
+            .await;
+        let provider = ModelProviderInfo {
+            wire_api: WireApi::Responses,
+            request_max_retries: Some(0),
+            stream_max_retries: Some(0),
+            ..create_oss_provider_with_base_url(&format!("{}/v1", server.uri()))
+        };
+        let (sess, tc, _rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            model_provider: Some("mock".to_string()),
+            model_providers: HashMap::from([("mock".to_string(), provider)]),
+            ..Default::default()
+        });
+        sess.record_into_history(&[ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::InputText {
+                text: "keep me".to_string(),
+            }],
+        }])
+        .await;
+        let history = sess.history_snapshot().await;
+
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-compact".to_string(),
+            vec![InputItem::Text {
+                text: compact::SUMMARIZATION_PROMPT.to_string(),
+            }],
+            CompactTask::default(),
+        )
+        .await;
+        while server
+            .received_requests()
+            .await
+            .is_none_or(|requests| requests.is_empty())
+        {
+            sleep(StdDuration::from_millis(10)).await;
+        }
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+
+        assert_eq!(
+            (
+                last_visualizer_action(&sess, "task_aborted")
+                    .map(|action| action["cancellation"].clone()),
+                sess.history_snapshot().await,
+            ),
+            (
+                Some(json!({ "reason": "abort", "bytesProcessed": 0 })),
+                history,
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_input_runs_in_batches_and_completes_once() {
+        use crate::WireApi;
+        use crate::model_provider_info::create_oss_provider_with_base_url;
+        use core_test_support::responses::ev_assistant_message;
+        use core_test_support::responses::ev_completed;
+        use core_test_support::responses::ev_response_created;
+        use core_test_support::responses::mount_sse_sequence;
+        use core_test_support::responses::sse;
+        use core_test_support::responses::start_mock_server;
+        use core_test_support::skip_if_no_network;
+        use std::collections::HashMap;
+
+        skip_if_no_network!();
+
+        let server = start_mock_server().await;
+        let bodies = ["first", "second", "third"]
+            .into_iter()
+            .map(|text| {
+                sse(vec![
+                    ev_response_created("resp"),
+                    ev_assistant_message("msg", text),
+                    ev_completed("resp"),
+                ])
+            })
+            .collect();
+        let requests = mount_sse_sequence(&server, bodies).await;
+        let provider = ModelProviderInfo {
+            wire_api: WireApi::Responses,
+            request_max_retries: Some(0),
+            stream_max_retries: Some(0),
+            ..create_oss_provider_with_base_url(&format!("{}/v1", server.uri()))
+        };
+        let (sess, tc, rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            model_provider: Some("mock".to_string()),
+            model_providers: HashMap::from([("mock".to_string(), provider)]),
+            task_input_chunk_size: Some(2),
+            ..Default::default()
+        });
+
+        let input = (0..5)
+            .map(|item| InputItem::Text {
+                text: format!("item {item}"),
+            })
+            .collect();
+        sess.spawn_user_turn(Arc::clone(&tc), "sub-1".to_string(), input, None, None)
+            .await;
+        // The first `TaskComplete` has to be the only one, after all batches.
+        let mut started = 0;
+        let complete = loop {
+            match rx.recv().await.expect("event").msg {
+                EventMsg::TaskStarted(_) => started += 1,
+                EventMsg::TaskComplete(complete) => break complete,
+                EventMsg::Error(error) => panic!("task failed: {}", error.message),
+                _ => {}
+            }
+        };
+        while last_visualizer_action(&sess, "task_completed").is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        let batches: Vec<Value> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "task_progress")
+            .map(|event| event.action["progress"].clone())
+            .collect();
+        assert_eq!(
+            (
+                started,
+                complete.last_agent_message,
+                requests.requests().len(),
+                batches,
+            ),
+            (
+                1,
+                Some("first\n\nsecond\n\nthird".to_string()),
+                3,
+                vec![
+                    json!({ "batchIndex": 0, "batchCount": 3 }),
+                    json!({ "batchIndex": 1, "batchCount": 3 }),
+                    json!({ "batchIndex": 2, "batchCount": 3 }),
+                ],
+            )
+        );
+        // The last batch sent only what was left of the input.
+        let last_input = requests.requests()[2].input();
+        assert_eq!(
+            last_input.last().map(|item| item["content"].clone()),
+            Some(json!([{ "type": "input_text", "text": "item 4" }]))
+        );
+    }
+
+    #[tokio::test]
+    async fn tasks_aborted_before_responding_have_no_latency() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let mut aborted = Vec::new();
+        for (sub_id, responds) in [("silent", false), ("responded", true)] {
+            sess.spawn_task(
+                Arc::clone(&tc),
+                sub_id.to_string(),
+                Vec::new(),
+                NeverEndingTask(TaskKind::Regular),
+            )
+            .await;
+            if responds {
+                sess.on_first_response(sub_id).await;
+            }
+            sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+            let action = last_visualizer_action(&sess, "task_aborted").expect("task_aborted");
+            aborted.push((
+                action["subId"].clone(),
+                action["firstResponseMs"].is_u64(),
+                action["abortedBeforeResponse"].clone(),
+            ));
+        }
+        assert_eq!(
+            aborted,
+            vec![
+                (json!("silent"), false, json!(true)),
+                (json!("responded"), true, json!(false)),
+            ]
+        );
+
+        let histogram = serde_json::to_value(&sess.state.lock().await.first_response_latency)
+            .expect("serialize");
+        let recorded: u64 = histogram["buckets"]
+            .as_array()
+            .expect("buckets")
+            .iter()
+            .filter_map(|bucket| bucket["count"].as_u64())
+            .sum();
+        assert_eq!(
+            (recorded, histogram["abortedBeforeResponse"].clone()),
+            (1, json!(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn completed_review_keeps_its_findings() {
+        let (session, _turn_context) = make_session_and_context();
+        let session = Arc::new(session);
+        let findings = vec![review_finding("off by one")];
+
+        exit_review_mode(
+            Arc::clone(&session),
+            "review".to_string(),
+            Some(ReviewOutputEvent {
+                findings: findings.clone(),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(session.state.lock().await.review_findings, findings);
+
+        // An interrupted review has no output and keeps the previous findings.
+        exit_review_mode(Arc::clone(&session), "review-2".to_string(), None).await;
+        assert_eq!(session.state.lock().await.review_findings, findings);
+    }
+
+    fn stage(stage: ShutdownStage, outcome: StageOutcome) -> StageReport {
+        StageReport { stage, outcome }
+    }
+
+    /// Starts a task against an in-memory relay, makes every send fail
+    /// while it runs, and returns the session with a stream of its aborts
+    /// and errors from before the failure, and the sequence number of the
+    /// first event lost.
+    async fn fail_sink_mid_task(strict: bool) -> (Arc<Session>, EventStream, u64) {
+        use crate::visualizer::failpoints::Failpoint;
+        use crate::visualizer::failpoints::Failpoints;
+        use crate::visualizer::failpoints::Injection;
+        use crate::visualizer::failpoints::visualizer_with_failpoints;
+
+        let failpoints = Failpoints::default();
+        let visualizer = visualizer_with_failpoints(&failpoints);
+        let visualizer = if strict {
+            visualizer.with_strict_delivery(StdDuration::from_millis(200))
+        } else {
+            visualizer
+        };
+        let (mut sess, tc) = make_session_and_context();
+        sess.visualizer = SessionVisualizer::new(visualizer, sess.conversation_id);
+        let sess = Arc::new(sess);
+        sess.watch_telemetry_failures();
+        let events = sess
+            .event_stream()
+            .filtered(&[EventDiscriminant::TurnAborted, EventDiscriminant::Error]);
+        sess.spawn_task(
+            Arc::new(tc),
+            "running".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+
+        failpoints.arm(Failpoint::Send, vec![Injection::Fail; 1000]);
+        let lost = sess.visualizer.next_sequence();
+        sess.send_event(Event {
+            id: "running".to_string(),
+            msg: EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
+                call_id: "patch-1".to_string(),
+                auto_approved: true,
+                changes: std::collections::HashMap::new(),
+            }),
+        })
+        .await;
+        (sess, events, lost)
+    }
+
+    #[tokio::test]
+    async fn strict_telemetry_aborts_the_task_when_a_lifecycle_event_is_lost() {
+        use futures::StreamExt;
+
+        let (sess, events, lost) = fail_sink_mid_task(true).await;
+        let events: Vec<Value> = tokio::time::timeout(
+            StdDuration::from_secs(5),
+            events.take(2).collect::<Vec<_>>(),
+        )
+        .await
+        .expect("abort and error before timeout")
+        .iter()
+        .map(|event| serde_json::to_value(event).expect("serialize"))
+        .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                json!({
+                    "id": "running",
+                    "msg": { "type": "turn_aborted", "reason": "telemetry_failure" },
+                }),
+                json!({
+                    "id": "running",
+                    "msg": {
+                        "type": "error",
+                        "message": format!(
+                            "strict telemetry: lifecycle event `protocol_event` (sequence {lost}) was not delivered within 200ms; the audit stream is incomplete, so running tasks were aborted"
+                        ),
+                    },
+                }),
+            ]
+        );
+        assert!(sess.active_turn.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn best_effort_telemetry_keeps_the_task_running_when_the_sink_fails() {
+        use futures::StreamExt;
+
+        let (sess, mut events, _) = fail_sink_mid_task(false).await;
+
+        assert!(
+            tokio::time::timeout(StdDuration::from_millis(500), events.next())
+                .await
+                .is_err(),
+            "no abort or error"
+        );
+        assert!(sess.active_turn.lock().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn shutdown_runs_stages_in_order_past_a_slow_rollout_and_sink() {
+        // Accepts connections but never answers the websocket handshake.
+        let stalled_sink = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let url = format!("ws://{}", stalled_sink.local_addr().expect("addr"));
+
+        let (mut sess, tc) = make_session_and_context();
+        sess.visualizer = SessionVisualizer::new(
+            AgentVisualizer::new(
+                Some(url),
+                TelemetryFidelity::Full,
+                None,
+                RetentionRules::default(),
+                TimestampEncoding::Number,
+                TrustedRoots::default(),
+            ),
+            sess.conversation_id,
+        );
+        *sess.services.rollout.lock().await =
+            Some(RolloutRecorder::slow_for_test(StdDuration::from_secs(5)));
+        let sess = Arc::new(sess);
+        let tc = Arc::new(tc);
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "running".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+
+        let report = sess
+            .shutdown(
+                ConversationEndGuard::new(Arc::clone(&sess)),
+                ConversationEndReason::UserExit,
+                ShutdownTimeouts {
+                    drain_tasks: StdDuration::from_secs(5),
+                    flush_rollout: StdDuration::from_millis(50),
+                    flush_visualizer: StdDuration::from_millis(50),
+                    close_visualizer: StdDuration::from_millis(50),
+                },
+            )
+            .await;
+
+        assert_eq!(
+            report,
+            ShutdownReport {
+                stages: vec![
+                    stage(ShutdownStage::GateSpawns, StageOutcome::Completed),
+                    stage(ShutdownStage::DrainTasks, StageOutcome::Completed),
+                    stage(ShutdownStage::TerminalEvents, StageOutcome::Completed),
+                    stage(ShutdownStage::FlushRollout, StageOutcome::TimedOut),
+                    stage(ShutdownStage::FlushVisualizer, StageOutcome::TimedOut),
+                ],
+            }
+        );
+        assert!(sess.active_turn.lock().await.is_none());
+        assert!(sess.services.rollout.lock().await.is_none());
+
+        let events = sess.visualizer.recent_events();
+        let last_two: Vec<&str> = events
+            .iter()
+            .rev()
+            .take(2)
+            .map(|event| event.action_type.as_str())
+            .collect();
+        assert_eq!(last_two, vec!["shutdown_report", "conversation_ended"]);
+        assert_eq!(
+            events.last().map(|event| event.action.clone()),
+            Some(json!({
+                "stages": [
+                    { "stage": "gateSpawns", "outcome": "completed" },
+                    { "stage": "drainTasks", "outcome": "completed" },
+                    { "stage": "terminalEvents", "outcome": "completed" },
+                    { "stage": "flushRollout", "outcome": "timedOut" },
+                    { "stage": "flushVisualizer", "outcome": "timedOut" },
+                ],
+            }))
+        );
+
+        // Spawns are refused and nothing is emitted after the report.
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "late".to_string(),
+            Vec::new(),
+            NeverEndingTask(TaskKind::Regular),
+        )
+        .await;
+        assert!(sess.active_turn.lock().await.is_none());
+        assert_eq!(sess.visualizer.recent_events().len(), events.len());
+    }
+
+    #[tokio::test]
+    async fn shutdown_skips_stages_with_nothing_to_flush() {
+        let (sess, _tc) = make_session_and_context();
+        *sess.services.rollout.lock().await =
+            Some(RolloutRecorder::slow_for_test(StdDuration::ZERO));
+        let sess = Arc::new(sess);
+
+        let report = sess
+            .shutdown(
+                ConversationEndGuard::new(Arc::clone(&sess)),
+                ConversationEndReason::Shutdown,
+                ShutdownTimeouts::default(),
+            )
+            .await;
+
+        assert_eq!(
+            report,
+            ShutdownReport {
+                stages: vec![
+                    stage(ShutdownStage::GateSpawns, StageOutcome::Completed),
+                    stage(ShutdownStage::DrainTasks, StageOutcome::Completed),
+                    stage(ShutdownStage::TerminalEvents, StageOutcome::Completed),
+                    stage(ShutdownStage::FlushRollout, StageOutcome::Completed),
+                    stage(ShutdownStage::FlushVisualizer, StageOutcome::Skipped),
+                ],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_confirms_conversation_ended_reached_the_sink() {
+        use crate::visualizer::failpoints::Failpoints;
+        use crate::visualizer::failpoints::recorded_visualizer_with_failpoints;
+
+        let (visualizer, sink) = recorded_visualizer_with_failpoints(&Failpoints::default());
+        let (mut sess, _tc) = make_session_and_context();
+        sess.visualizer = SessionVisualizer::new(visualizer, sess.conversation_id);
+        let (tx_event, rx_event) = async_channel::unbounded();
+        sess.tx_event = tx_event;
+        let sess = Arc::new(sess);
+
+        let report = sess
+            .shutdown(
+                ConversationEndGuard::new(Arc::clone(&sess)),
+                ConversationEndReason::UserExit,
+                ShutdownTimeouts::default(),
+            )
+            .await;
+
+        assert_eq!(
+            report.outcome(ShutdownStage::FlushVisualizer),
+            Some(StageOutcome::Completed)
+        );
+        let ended = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .find(|event| event.action_type == "conversation_ended")
+            .expect("conversation_ended");
+        let delivered = sink
+            .lock()
+            .map(|log| log.delivered.clone())
+            .unwrap_or_default();
+        assert!(delivered.contains(&ended.sequence));
+
+        let mut ended_events = Vec::new();
+        while let Ok(event) = rx_event.try_recv() {
+            if let EventMsg::ConversationEnded(ev) = event.msg {
+                ended_events.push((event.id, ev));
+            }
+        }
+        assert_eq!(
+            ended_events,
+            vec![(
+                INITIAL_SUBMIT_ID.to_string(),
+                ConversationEndedEvent {
+                    reason: ConversationEndReason::UserExit,
+                    last_sub_id: None,
+                    tasks_started: 0,
+                    total_tokens: None,
+                },
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn blocking_compaction_is_queued_once_per_task() {
+        let (sess, tc) = make_session_and_context();
+        let tc = Arc::new(tc);
+        let input = vec![InputItem::Text {
+            text: "too big".to_string(),
+        }];
+
+        assert!(
+            sess.queue_blocking_compaction(Arc::clone(&tc), "sub-1", input.clone())
+                .await
+        );
+        assert!(matches!(
+            sess.state.lock().await.follow_up,
+            Some(FollowUpTask::BlockingCompaction { ref sub_id, .. }) if sub_id == "sub-1"
+        ));
+
+        // The resumed task overflowing again must not loop through compaction.
+        sess.state.lock().await.follow_up = None;
+        assert!(
+            !sess
+                .queue_blocking_compaction(Arc::clone(&tc), "sub-1", input.clone())
+                .await
+        );
+        assert!(sess.state.lock().await.follow_up.is_none());
+
+        assert!(
+            sess.queue_blocking_compaction(Arc::clone(&tc), "sub-2", input.clone())
+                .await
+        );
+
+        // Once it ended, the task is no longer taken for a resumed one.
+        assert!(
+            !sess
+                .queue_blocking_compaction(Arc::clone(&tc), "sub-2", input.clone())
+                .await
+        );
+        sess.end_blocking_compaction("sub-2").await;
+        assert!(sess.queue_blocking_compaction(tc, "sub-2", input).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_approval_times_out_and_task_proceeds() {
+        use crate::config_types::ApprovalLimitsToml;
+
+        let (sess, tc, rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            approvals: ApprovalLimitsToml {
+                max_outstanding_per_task: Some(1),
+                timeout_secs: Some(30),
+            },
+            ..Default::default()
+        });
+        let started = tokio::time::Instant::now();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-approvals".to_string(),
+            Vec::new(),
+            TwoApprovalsTask,
+        )
+        .await;
+
+        // The cap holds the second request back until the first expires.
+        assert_eq!(next_approval_request(&rx).await, "call-1");
+        assert_eq!(next_approval_request(&rx).await, "call-2");
+        assert_eq!(started.elapsed(), StdDuration::from_secs(30));
+
+        sess.notify_approval("sub-approvals", ReviewDecision::Approved)
+            .await;
+        let last_agent_message = loop {
+            let event = rx.recv().await.expect("event");
+            if let EventMsg::TaskComplete(complete) = event.msg {
+                break complete.last_agent_message;
+            }
+        };
+        assert_eq!(
+            last_agent_message,
+            Some("ApprovalTimedOut, Decided(Approved)".to_string())
+        );
+
+        let expired: Vec<Value> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "approval_expired")
+            .map(|event| event.action)
+            .collect();
+        assert_eq!(
+            expired,
+            vec![json!({
+                "subId": "sub-approvals",
+                "callId": "call-1",
+                "timeoutMs": 30_000,
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn approvals_in_flight_together_are_answered_by_call_id() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-approvals".to_string(),
+            Vec::new(),
+            TwoApprovalsTask,
+        )
+        .await;
+        let mut requested = vec![
+            next_approval_request(&rx).await,
+            next_approval_request(&rx).await,
+        ];
+        requested.sort();
+        assert_eq!(requested, vec!["call-1", "call-2"]);
+
+        sess.notify_approval("call-2", ReviewDecision::Denied).await;
+        sess.notify_approval("call-1", ReviewDecision::Approved)
+            .await;
+        let last_agent_message = loop {
+            let event = rx.recv().await.expect("event");
+            if let EventMsg::TaskComplete(complete) = event.msg {
+                break complete.last_agent_message;
+            }
+        };
+        assert_eq!(
+            last_agent_message,
+            Some("Decided(Approved), Decided(Denied)".to_string())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn approval_timeout_waits_while_session_paused() {
+        use crate::config_types::ApprovalLimitsToml;
+
+        let (sess, tc, rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            approvals: ApprovalLimitsToml {
+                max_outstanding_per_task: None,
+                timeout_secs: Some(30),
+            },
+            ..Default::default()
+        });
+        *sess.active_turn.lock().await = Some(ActiveTurn::default());
+        sess.set_paused(true);
+        let started = tokio::time::Instant::now();
+        let pending = tokio::spawn({
+            let sess = Arc::clone(&sess);
+            let cwd = tc.cwd.clone();
+            async move {
+                sess.request_command_approval(
+                    "sub-paused".to_string(),
+                    "call-1".to_string(),
+                    vec!["true".to_string()],
+                    cwd,
+                    None,
+                )
+                .await
+            }
+        });
+        assert_eq!(next_approval_request(&rx).await, "call-1");
+
+        tokio::time::sleep(StdDuration::from_secs(300)).await;
+        assert!(!pending.is_finished());
+
+        sess.set_paused(false);
+        assert_eq!(
+            pending.await.expect("approval task"),
+            ApprovalResolution::ApprovalTimedOut
+        );
+        assert_eq!(started.elapsed(), StdDuration::from_secs(330));
+    }
+
+    #[tokio::test]
+    async fn reviews_are_rejected_once_an_approval_outlives_their_timeout() {
+        use crate::WireApi;
+        use crate::model_provider_info::create_oss_provider_with_base_url;
+        use core_test_support::responses::ev_completed;
+        use core_test_support::responses::ev_response_created;
+        use core_test_support::responses::sse;
+        use core_test_support::responses::sse_response;
+        use core_test_support::responses::start_mock_server;
+        use core_test_support::skip_if_no_network;
+        use std::collections::HashMap;
+        use wiremock::Mock;
+        use wiremock::matchers::method;
+
+        skip_if_no_network!();
+
+        // The review's model request never answers in time, so it is still
+        // running when its approval request expires.
+        let server = start_mock_server().await;
+        let body = sse(vec![ev_response_created("resp"), ev_completed("resp")]);
+        Mock::given(method("POST"))
+            .respond_with(sse_response(body).set_delay(StdDuration::from_secs(3_600)))
+            .mount(&server)
+            .await;
+        let provider = ModelProviderInfo {
+            wire_api: WireApi::Responses,
+            request_max_retries: Some(0),
+            stream_max_retries: Some(0),
+            ..create_oss_provider_with_base_url(&format!("{}/v1", server.uri()))
+        };
+        let (sess, tc, rx) = make_session_and_context_with_config_and_rx(ConfigToml {
+            model_provider: Some("mock".to_string()),
+            model_providers: HashMap::from([("mock".to_string(), provider)]),
+            ..Default::default()
+        });
+        let review =
+            ReviewTask::sliced(None).with_approval_timeout(Some(StdDuration::from_secs(60)));
+        let report = sess
+            .spawn_task(
+                Arc::clone(&tc),
+                "review-1".to_string(),
+                vec![InputItem::Text {
+                    text: "review my changes".to_string(),
+                }],
+                review,
+            )
+            .await
+            .expect("spawned");
+        let approval = tokio::spawn({
+            let sess = Arc::clone(&sess);
+            let cwd = tc.cwd.clone();
+            async move {
+                sess.request_command_approval(
+                    "review-1".to_string(),
+                    "call-1".to_string(),
+                    vec!["cargo".to_string(), "test".to_string()],
+                    cwd,
+                    None,
+                )
+                .await
+            }
+        });
+        assert_eq!(next_approval_request(&rx).await, "call-1");
+
+        tokio::time::pause();
+        tokio::time::advance(StdDuration::from_secs(59)).await;
+        assert!(!approval.is_finished());
+        tokio::time::advance(StdDuration::from_secs(2)).await;
+        assert_eq!(
+            approval.await.expect("approval task"),
+            ApprovalResolution::ApprovalTimedOut
+        );
+
+        let report = report.await.expect("report");
+        let mut ends = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event.msg {
+                EventMsg::TaskComplete(_) => ends.push(None),
+                EventMsg::TurnAborted(aborted) => ends.push(Some(aborted.reason)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            (
+                report.error.map(|error| (error.kind, error.retryable)),
+                ends,
+            ),
+            (
+                Some((TaskErrorKind::ApprovalTimeout, false)),
+                vec![Some(TurnAbortReason::ApprovalTimeout)],
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn aborting_the_turn_reports_the_approvals_it_leaves_unanswered() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-gated".to_string(),
+            Vec::new(),
+            GatedTask(Arc::new(tokio::sync::Notify::new())),
+        )
+        .await;
+        let turn_state = {
+            let active = sess.active_turn.lock().await;
+            let turn = active.as_ref().expect("active turn");
+            assert!(!turn.is_empty().await);
+            Arc::clone(&turn.turn_state)
+        };
+        let (tx, _rx_approve) = oneshot::channel();
+        turn_state.lock().await.insert_pending_approval(
+            "sub-gated".to_string(),
+            "call-gated".to_string(),
+            tx,
+        );
+
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+        assert_eq!(
+            (
+                sess.active_turn.lock().await.is_none(),
+                turn_state.lock().await.is_empty(),
+                last_visualizer_action(&sess, "pending_approvals_cleared"),
+            ),
+            (true, true, Some(json!({ "subIds": ["sub-gated"] })))
+        );
+    }
+
+    #[tokio::test]
+    async fn spawn_task_handle_yields_the_report_unless_aborted() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let input = vec![InputItem::Text {
+            text: "sleep".to_string(),
+        }];
+        let completed = sess
+            .spawn_task(
+                Arc::clone(&tc),
+                "sub-sleeping".to_string(),
+                input,
+                SleepingTask(StdDuration::from_millis(10)),
+            )
+            .await
+            .expect("spawned")
+            .await
+            .expect("completed");
+        assert!(completed.duration >= StdDuration::from_millis(10));
+        assert_eq!(
+            completed,
+            TaskExecutionReport {
+                sub_id: "sub-sleeping".to_string(),
+                task_kind: TaskKind::Regular,
+                duration: completed.duration,
+                input_item_count: 1,
+                last_agent_message: Some("done".to_string()),
+                error: None,
+            }
+        );
+
+        let gated = sess
+            .spawn_task(
+                Arc::clone(&tc),
+                "sub-gated".to_string(),
+                Vec::new(),
+                GatedTask(Arc::new(tokio::sync::Notify::new())),
+            )
+            .await
+            .expect("spawned");
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+        assert!(gated.await.is_err_and(|err| err.is_cancelled()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timed_out_task_handle_reports_the_timeout() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let report = sess
+            .spawn_task_with_timeout(
+                Arc::clone(&tc),
+                "sub-deadline".to_string(),
+                Vec::new(),
+                GatedTask(Arc::new(tokio::sync::Notify::new())),
+                StdDuration::from_secs(5),
+            )
+            .await
+            .expect("spawned")
+            .await
+            .expect("the handle yields a report");
+        assert_eq!(
+            (report.last_agent_message, report.error),
+            (
+                None,
+                Some(TaskError {
+                    kind: TaskErrorKind::Timeout,
+                    retryable: false,
+                    message: "task ran past its 5s deadline".to_string(),
+                })
+            )
+        );
+        sess.wait_for_idle().await;
+        assert_eq!(
+            last_visualizer_action(&sess, "task_aborted").map(|action| action["reason"].clone()),
+            Some(json!("Timeout"))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_idle_resolves_once_the_last_task_ended_and_was_reported() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        // What ended since the last call, and the last visualizer event
+        // saying so.
+        let ends = |action_type: &str| {
+            let mut ends = Vec::new();
+            while let Ok(event) = rx.try_recv() {
+                match event.msg {
+                    EventMsg::TaskComplete(complete) => {
+                        ends.push(Ok(complete.last_agent_message));
+                    }
+                    EventMsg::TurnAborted(aborted) => ends.push(Err(aborted.reason)),
+                    _ => {}
+                }
+            }
+            let reported =
+                last_visualizer_action(&sess, action_type).map(|action| action["subId"].clone());
+            (ends, reported)
+        };
+        // Idle from the start.
+        sess.wait_for_idle().await;
+
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-sleeping".to_string(),
+            Vec::new(),
+            SleepingTask(StdDuration::from_secs(1)),
+        )
+        .await;
+        sess.wait_for_idle().await;
+        let completed = ends("task_completed");
+
+        sess.spawn_task_with_timeout(
+            Arc::clone(&tc),
+            "sub-gated".to_string(),
+            Vec::new(),
+            GatedTask(Arc::new(tokio::sync::Notify::new())),
+            StdDuration::from_secs(5),
+        )
+        .await;
+        sess.wait_for_idle().await;
+        let aborted = ends("task_aborted");
+
+        assert_eq!(
+            (completed, aborted),
+            (
+                (
+                    vec![Ok(Some("done".to_string()))],
+                    Some(json!("sub-sleeping"))
+                ),
+                (
+                    vec![Err(TurnAbortReason::Timeout)],
+                    Some(json!("sub-gated"))
+                ),
+            )
+        );
+        assert!(sess.active_turn.lock().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn active_task_count_drops_when_one_of_two_tasks_is_aborted() {
+        let (sess, tc, _rx) = make_session_and_context_with_rx();
+        let gate = Arc::new(tokio::sync::Notify::new());
+        sess.spawn_task_with_timeout(
+            Arc::clone(&tc),
+            "sub-deadline".to_string(),
+            Vec::new(),
+            GatedTask(Arc::clone(&gate)),
+            StdDuration::from_secs(5),
+        )
+        .await;
+        // Spawning replaces the active turn's tasks, so the second one is
+        // registered next to the first by hand.
+        {
+            let mut active = sess.active_turn.lock().await;
+            let turn = active.as_mut().expect("active turn");
+            let mut review = turn
+                .tasks
+                .get("sub-deadline")
+                .cloned()
+                .expect("task registered");
+            review.kind = TaskKind::Review;
+            turn.add_task("sub-review".to_string(), review);
+        }
+        assert_eq!(
+            (sess.active_task_count().await, sess.task_kinds().await),
+            (2, vec![TaskKind::Regular, TaskKind::Review])
+        );
+
+        tokio::time::sleep(StdDuration::from_secs(10)).await;
+        assert_eq!(
+            (sess.active_task_count().await, sess.task_kinds().await),
+            (1, vec![TaskKind::Review])
+        );
+    }
+
+    #[tokio::test]
+    async fn finished_task_reports_finishing_until_deregistered() {
+        use crate::state::RunningTaskStatus;
+        use crate::state::TaskStatus;
+
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let gate = Arc::new(tokio::sync::Notify::new());
+        let status = |status| RunningTaskStatus {
+            sub_id: "sub-gated".to_string(),
+            kind: TaskKind::Regular,
+            status,
+        };
+        sess.spawn_task(
+            Arc::clone(&tc),
+            "sub-gated".to_string(),
+            Vec::new(),
+            GatedTask(Arc::clone(&gate)),
+        )
+        .await;
+        assert_eq!(
+            sess.running_tasks().await,
+            vec![status(TaskStatus::Running)]
+        );
+
+        // Holding the active-turn lock gates `on_task_finished`, which must
+        // take it to deregister the task.
+        let active = sess.active_turn.lock().await;
+        let task = active
+            .as_ref()
+            .and_then(|at| at.tasks.get("sub-gated"))
+            .cloned()
+            .expect("task registered");
+        gate.notify_one();
+        tokio::time::timeout(StdDuration::from_secs(5), async {
+            while task.status() != TaskStatus::Finishing {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("run returned");
+        assert_eq!(
+            active.as_ref().map(ActiveTurn::task_statuses),
+            Some(vec![status(TaskStatus::Finishing)])
+        );
+        drop(active);
+
+        let evt = tokio::time::timeout(StdDuration::from_secs(5), rx.recv())
+            .await
+            .expect("completion in time")
+            .expect("event");
+        assert!(matches!(evt.msg, EventMsg::TaskComplete(_)));
+        assert_eq!(sess.running_tasks().await, Vec::new());
+    }
+
+    #[tokio::test]
+    async fn abort_review_task_emits_exited_then_aborted_and_records_history() {
+        let (sess, tc, rx) = make_session_and_context_with_rx();
+        let sub_id = "sub-review".to_string();
+        let input = vec![InputItem::Text {
+            text: "start review".to_string(),
+        }];
+        sess.spawn_task(
+            Arc::clone(&tc),
+            sub_id.clone(),
+            input,
+            NeverEndingTask(TaskKind::Review),
+        )
+        .await;
+
+        sess.abort_all_tasks(TurnAbortReason::Interrupted).await;
+
+        let first = rx.recv().await.expect("first event");
+        match first.msg {
+            EventMsg::ExitedReviewMode(ev) => assert!(ev.review_output.is_none()),
+            other => panic!("unexpected first event: {other:?}"),
+        }
+        let second = rx.recv().await.expect("second event");
+        match second.msg {
+            EventMsg::TurnAborted(e) => assert_eq!(TurnAbortReason::Interrupted, e.reason),
+            other => panic!("unexpected second event: {other:?}"),
+        }
+
+        let history = sess.history_snapshot().await;
+        let found = history.iter().any(|item| match item {
+            ResponseItem::Message { role, content, .. } if role == "user" => {
+                content.iter().any(|ci| match ci {
+                    ContentItem::InputText { text } => {
+                        text.contains("<user_action>")
+                            && text.contains("review")
+                            && text.contains("interrupted")
+                    }
+                    _ => false,
+                })
+            }
+            _ => false,
+        });
+        assert!(
+            found,
+            "synthetic review interruption not recorded in history"
+        );
+    }
+
+    #[tokio::test]
+    async fn fatal_tool_error_stops_turn_and_reports_error() {
+        let (session, turn_context, _rx) = make_session_and_context_with_rx();
+        let router = ToolRouter::from_config(
+            &turn_context.tools_config,
+            Some(session.services.mcp_connection_manager.list_all_tools()),
+        );
+        let item = ResponseItem::CustomToolCall {
+            id: None,
+            status: None,
+            call_id: "call-1".to_string(),
+            name: "shell".to_string(),
+            input: "{}".to_string(),
+        };
+
+        let call = ToolRouter::build_tool_call(session.as_ref(), item.clone())
+            .expect("build tool call")
+            .expect("tool call present");
         let tracker = Arc::new(tokio::sync::Mutex::new(TurnDiffTracker::new()));
         let err = router
             .dispatch_tool_call(
@@ -3612,4 +7802,97 @@ mod tests {
         pretty_assertions::assert_eq!(exec_output.metadata, ResponseExecMetadata { exit_code: 0 });
         assert!(exec_output.output.contains("hi"));
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn git_commit_via_exec_emits_commit_event() {
+        use crate::exec::ExecParams;
+        use crate::protocol::AskForApproval;
+        use crate::protocol::SandboxPolicy;
+        use crate::turn_diff_tracker::TurnDiffTracker;
+        use std::collections::HashMap;
+        use std::process::Command;
+
+        let repo = tempfile::tempdir().expect("create temp repo");
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(repo.path())
+                .output()
+                .expect("run git");
+            assert!(output.status.success(), "git {args:?} failed");
+            String::from_utf8(output.stdout)
+                .expect("utf8")
+                .trim()
+                .to_string()
+        };
+        git(&["init", "-q"]);
+        git(&["config", "user.name", "Test User"]);
+        git(&["config", "user.email", "test@example.com"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Initial commit"]);
+        std::fs::write(repo.path().join("greeting.txt"), "hello").expect("write file");
+        std::fs::write(repo.path().join("farewell.txt"), "bye").expect("write file");
+
+        let (session, mut turn_context) = make_session_and_context();
+        turn_context.approval_policy = AskForApproval::Never;
+        turn_context.sandbox_policy = SandboxPolicy::DangerFullAccess;
+        let session = Arc::new(session);
+        let turn_context = Arc::new(turn_context);
+        let turn_diff_tracker = Arc::new(tokio::sync::Mutex::new(TurnDiffTracker::new()));
+        let run = |script: &str, call_id: &str| {
+            let params = ExecParams {
+                command: vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()],
+                cwd: repo.path().to_path_buf(),
+                timeout_ms: Some(10_000),
+                env: std::env::vars()
+                    .filter(|(key, _)| key == "PATH" || key == "HOME")
+                    .collect::<HashMap<_, _>>(),
+                with_escalated_permissions: None,
+                justification: None,
+            };
+            handle_container_exec_with_params(
+                "shell",
+                params,
+                Arc::clone(&session),
+                Arc::clone(&turn_context),
+                Arc::clone(&turn_diff_tracker),
+                "sub-git".to_string(),
+                call_id.to_string(),
+            )
+        };
+        let commit_events = || {
+            session
+                .visualizer
+                .recent_events()
+                .into_iter()
+                .filter(|event| event.action_type == "git_commit_created")
+                .map(|event| event.action)
+                .collect::<Vec<_>>()
+        };
+
+        run(
+            "git add . && git commit -q -m 'Add greeting'",
+            "call-commit",
+        )
+        .await
+        .expect("commit succeeds");
+        let sha = git(&["rev-parse", "HEAD"]);
+        assert_eq!(
+            commit_events(),
+            vec![json!({
+                "subId": "sub-git",
+                "callId": "call-commit",
+                "sha": sha,
+                "subject": "Add greeting",
+                "filesChanged": 2,
+            })]
+        );
+
+        // Commits made on a detached HEAD are not attributed to the branch.
+        git(&["checkout", "-q", "--detach"]);
+        run("git commit -q --allow-empty -m 'Detached'", "call-detached")
+            .await
+            .expect("detached commit succeeds");
+        assert_eq!(commit_events().len(), 1);
+    }
 }
@@ -1,4 +1,8 @@
 use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 
 use super::Session;
 use super::TurnContext;
@@ -14,8 +18,12 @@ use crate::protocol::Event;
 use crate::protocol::EventMsg;
 use crate::protocol::InputItem;
 use crate::protocol::InputMessageKind;
+use crate::protocol::TaskError;
 use crate::protocol::TaskStartedEvent;
 use crate::protocol::TurnContextItem;
+use crate::state::TaskKind;
+use crate::tasks::TaskCancellation;
+use crate::tasks::TaskResult;
 use crate::truncate::truncate_middle;
 use crate::util::backoff;
 use askama::Template;
@@ -24,9 +32,12 @@ use codex_protocol::models::ResponseInputItem;
 use codex_protocol::models::ResponseItem;
 use codex_protocol::protocol::RolloutItem;
 use futures::prelude::*;
+use serde_json::Value;
+use serde_json::json;
 
 pub const SUMMARIZATION_PROMPT: &str = include_str!("../../templates/compact/prompt.md");
 const COMPACT_USER_MESSAGE_MAX_TOKENS: usize = 20_000;
+const APPROX_BYTES_PER_TOKEN: u64 = 4;
 
 #[derive(Template)]
 #[template(path = "compact/history_bridge.md", escape = "none")]
@@ -35,6 +46,51 @@ struct HistoryBridgeTemplate<'a> {
     summary_text: &'a str,
 }
 
+/// How much of its summary a compaction has received, shared with its
+/// [`CompactTask`](crate::tasks::CompactTask), which reports it as
+/// `task_progress`.
+#[derive(Debug, Default)]
+pub(crate) struct CompactionProgress {
+    /// Summary text streamed so far, across attempts, so the estimate
+    /// never goes down.
+    streamed_bytes: AtomicU64,
+    /// Output tokens the provider reported once the summary completed.
+    total_tokens: OnceLock<u64>,
+    /// History length when the compaction started, which an abort rolls
+    /// the history back to.
+    history_len: OnceLock<usize>,
+    /// Set once the summary replaced the history; there is nothing to roll
+    /// back from then on.
+    compacted: AtomicBool,
+}
+
+impl CompactionProgress {
+    fn record_delta(&self, delta: &str) {
+        self.streamed_bytes
+            .fetch_add(delta.len() as u64, Ordering::Relaxed);
+    }
+
+    fn complete(&self, output_tokens: u64) {
+        let _ = self.total_tokens.set(output_tokens);
+    }
+
+    /// `{ tokensProcessed, tokensTotal }`: the summary tokens received so
+    /// far, estimated from their size, and the count the provider reported
+    /// once the summary completed, `null` before. `None` until the summary
+    /// starts.
+    pub(crate) fn to_json(&self) -> Option<Value> {
+        let streamed_bytes = self.streamed_bytes.load(Ordering::Relaxed);
+        let total_tokens = self.total_tokens.get().copied();
+        if streamed_bytes == 0 && total_tokens.is_none() {
+            return None;
+        }
+        Some(json!({
+            "tokensProcessed": streamed_bytes.div_ceil(APPROX_BYTES_PER_TOKEN),
+            "tokensTotal": total_tokens,
+        }))
+    }
+}
+
 pub(crate) async fn run_inline_auto_compact_task(
     sess: Arc<Session>,
     turn_context: Arc<TurnContext>,
@@ -43,7 +99,12 @@ pub(crate) async fn run_inline_auto_compact_task(
     let input = vec![InputItem::Text {
         text: SUMMARIZATION_PROMPT.to_string(),
     }];
-    run_compact_task_inner(sess, turn_context, sub_id, input).await;
+    // The task the compaction runs inline in reports nothing of it, and
+    // stops it by dropping it.
+    let progress = CompactionProgress::default();
+    let cancellation = TaskCancellation::default();
+    let _ =
+        run_compact_task_inner(sess, turn_context, sub_id, input, &progress, &cancellation).await;
 }
 
 pub(crate) async fn run_compact_task(
@@ -51,7 +112,9 @@ pub(crate) async fn run_compact_task(
     turn_context: Arc<TurnContext>,
     sub_id: String,
     input: Vec<InputItem>,
-) -> Option<String> {
+    progress: &CompactionProgress,
+    cancellation: &TaskCancellation,
+) -> TaskResult {
     let start_event = Event {
         id: sub_id.clone(),
         msg: EventMsg::TaskStarted(TaskStartedEvent {
@@ -59,16 +122,32 @@ pub(crate) async fn run_compact_task(
         }),
     };
     sess.send_event(start_event).await;
-    run_compact_task_inner(sess.clone(), turn_context, sub_id.clone(), input).await;
-    None
+    match run_compact_task_inner(
+        sess.clone(),
+        turn_context,
+        sub_id.clone(),
+        input,
+        progress,
+        cancellation,
+    )
+    .await
+    {
+        Ok(()) => TaskResult::Completed(None),
+        Err(error) => TaskResult::Failed(error),
+    }
 }
 
+/// Returns the error the compaction ended on, once reported to the client.
+/// Once `cancellation` is cancelled nothing more is written to the history;
+/// see [`discard_partial_compaction`].
 async fn run_compact_task_inner(
     sess: Arc<Session>,
     turn_context: Arc<TurnContext>,
     sub_id: String,
     input: Vec<InputItem>,
-) {
+    progress: &CompactionProgress,
+    cancellation: &TaskCancellation,
+) -> Result<(), TaskError> {
     let initial_input_for_turn: ResponseInputItem = ResponseInputItem::from(input);
     let mut turn_input = sess
         .turn_input_with_history(vec![initial_input_for_turn.clone().into()])
@@ -88,13 +167,34 @@ async fn run_compact_task_inner(
     });
     sess.persist_rollout_items(&[rollout_item]).await;
 
+    // Inline compaction runs inside a regular task, so the template comes
+    // from the compaction's own kind rather than the running task.
+    let task_instructions = sess
+        .services
+        .task_templates
+        .load(&TaskKind::Compact, &turn_context.cwd)
+        .map(|template| template.text.clone());
+    let _ = progress
+        .history_len
+        .set(sess.state.lock().await.history_len());
     loop {
         let prompt = Prompt {
             input: turn_input.clone(),
+            seed: sess.task_seed(&sub_id).await,
+            task_instructions: task_instructions.clone(),
             ..Default::default()
         };
-        let attempt_result =
-            drain_to_completed(&sess, turn_context.as_ref(), &sub_id, &prompt).await;
+        let attempt_result = tokio::select! {
+            result = drain_to_completed(
+                &sess,
+                turn_context.as_ref(),
+                &sub_id,
+                &prompt,
+                progress,
+                cancellation,
+            ) => result,
+            () = cancellation.cancelled() => Err(CodexErr::Interrupted),
+        };
 
         match attempt_result {
             Ok(()) => {
@@ -110,7 +210,7 @@ async fn run_compact_task_inner(
                 break;
             }
             Err(CodexErr::Interrupted) => {
-                return;
+                return Ok(());
             }
             Err(e @ CodexErr::ContextWindowExceeded) => {
                 if turn_input.len() > 1 {
@@ -128,10 +228,10 @@ async fn run_compact_task_inner(
                     }),
                 };
                 sess.send_event(event).await;
-                return;
+                return Err(e.to_task_error());
             }
             Err(e) => {
-                if retries < max_retries {
+                if e.task_error_kind().is_retryable() && retries < max_retries {
                     retries += 1;
                     let delay = backoff(retries);
                     sess.notify_stream_error(
@@ -141,8 +241,10 @@ async fn run_compact_task_inner(
                         ),
                     )
                     .await;
-                    tokio::time::sleep(delay).await;
-                    continue;
+                    tokio::select! {
+                        () = tokio::time::sleep(delay) => continue,
+                        () = cancellation.cancelled() => return Ok(()),
+                    }
                 } else {
                     let event = Event {
                         id: sub_id.clone(),
@@ -151,7 +253,7 @@ async fn run_compact_task_inner(
                         }),
                     };
                     sess.send_event(event).await;
-                    return;
+                    return Err(e.to_task_error());
                 }
             }
         }
@@ -162,7 +264,16 @@ async fn run_compact_task_inner(
     let user_messages = collect_user_messages(&history_snapshot);
     let initial_context = sess.build_initial_context(turn_context.as_ref());
     let new_history = build_compacted_history(initial_context, &user_messages, &summary_text);
-    sess.replace_history(new_history).await;
+    {
+        // Checked under the lock the abort rolls the history back under, so
+        // exactly one of the two happens.
+        let mut state = sess.state.lock().await;
+        if cancellation.is_cancelled() {
+            return Ok(());
+        }
+        state.replace_history(new_history);
+        progress.compacted.store(true, Ordering::Relaxed);
+    }
 
     let rollout_item = RolloutItem::Compacted(CompactedItem {
         message: summary_text.clone(),
@@ -176,6 +287,25 @@ async fn run_compact_task_inner(
         }),
     };
     sess.send_event(event).await;
+    Ok(())
+}
+
+/// Drop what an aborted compaction recorded into the history, unless its
+/// summary already replaced the history. `cancellation` must have been
+/// cancelled first, so the compaction records nothing after.
+pub(crate) async fn discard_partial_compaction(
+    sess: &Session,
+    progress: &CompactionProgress,
+    cancellation: &TaskCancellation,
+) {
+    debug_assert!(cancellation.is_cancelled());
+    let Some(&len) = progress.history_len.get() else {
+        return;
+    };
+    let mut state = sess.state.lock().await;
+    if !progress.compacted.load(Ordering::Relaxed) {
+        state.truncate_history(len);
+    }
 }
 
 pub fn content_items_to_text(content: &[ContentItem]) -> Option<String> {
@@ -259,6 +389,8 @@ async fn drain_to_completed(
     turn_context: &TurnContext,
     sub_id: &str,
     prompt: &Prompt,
+    progress: &CompactionProgress,
+    cancellation: &TaskCancellation,
 ) -> CodexResult<()> {
     let mut stream = turn_context.client.clone().stream(prompt).await?;
     loop {
@@ -271,12 +403,23 @@ async fn drain_to_completed(
         };
         match event {
             Ok(ResponseEvent::OutputItemDone(item)) => {
-                sess.record_into_history(std::slice::from_ref(&item)).await;
+                let mut state = sess.state.lock().await;
+                if cancellation.is_cancelled() {
+                    return Err(CodexErr::Interrupted);
+                }
+                state.record_items(std::slice::from_ref(&item));
+            }
+            Ok(ResponseEvent::OutputTextDelta(delta)) => {
+                progress.record_delta(&delta);
+                cancellation.record(&delta);
             }
             Ok(ResponseEvent::RateLimits(snapshot)) => {
                 sess.update_rate_limits(sub_id, snapshot).await;
             }
             Ok(ResponseEvent::Completed { token_usage, .. }) => {
+                if let Some(usage) = &token_usage {
+                    progress.complete(usage.output_tokens);
+                }
                 sess.update_token_usage_info(sub_id, turn_context, token_usage.as_ref())
                     .await;
                 return Ok(());
@@ -290,8 +433,63 @@ async fn drain_to_completed(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::codex::tests::make_session_and_context;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn compaction_progress_grows_until_the_provider_reports_the_total() {
+        let progress = CompactionProgress::default();
+        let before = progress.to_json();
+        progress.record_delta(&"a".repeat(40));
+        let first = progress.to_json();
+        progress.record_delta(&"b".repeat(18));
+        let second = progress.to_json();
+        progress.complete(16);
+
+        assert_eq!(
+            (before, first, second, progress.to_json()),
+            (
+                None,
+                Some(json!({ "tokensProcessed": 10, "tokensTotal": null })),
+                Some(json!({ "tokensProcessed": 15, "tokensTotal": null })),
+                Some(json!({ "tokensProcessed": 15, "tokensTotal": 16 })),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn aborted_compaction_drops_its_partial_summary_unless_it_compacted() {
+        let (sess, _turn_context) = make_session_and_context();
+        let message = |text: &str| ResponseItem::Message {
+            id: None,
+            role: "assistant".to_string(),
+            content: vec![ContentItem::OutputText {
+                text: text.to_string(),
+            }],
+        };
+        let progress = CompactionProgress::default();
+        let cancellation = TaskCancellation::default();
+        cancellation.cancel();
+        sess.record_into_history(&[message("earlier")]).await;
+        let _ = progress.history_len.set(1);
+        sess.record_into_history(&[message("partial summary")])
+            .await;
+
+        discard_partial_compaction(&sess, &progress, &cancellation).await;
+        let rolled_back = sess.history_snapshot().await;
+        sess.record_into_history(&[message("summary")]).await;
+        progress.compacted.store(true, Ordering::Relaxed);
+        discard_partial_compaction(&sess, &progress, &cancellation).await;
+
+        assert_eq!(
+            (rolled_back, sess.history_snapshot().await),
+            (
+                vec![message("earlier")],
+                vec![message("earlier"), message("summary")],
+            )
+        );
+    }
+
     #[test]
     fn content_items_to_text_joins_non_empty_segments() {
         let items = vec![
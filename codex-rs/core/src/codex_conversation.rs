@@ -1,5 +1,6 @@
 use crate::codex::Codex;
 use crate::error::Result as CodexResult;
+use crate::event_stream::EventStream;
 use crate::protocol::Event;
 use crate::protocol::Op;
 use crate::protocol::Submission;
@@ -27,4 +28,29 @@ impl CodexConversation {
     pub async fn next_event(&self) -> CodexResult<Event> {
         self.codex.next_event().await
     }
+
+    /// See [`Codex::event_stream`].
+    pub fn event_stream(&self) -> EventStream {
+        self.codex.event_stream()
+    }
+
+    /// See [`Codex::wait_for_idle`].
+    pub async fn wait_for_idle(&self) {
+        self.codex.wait_for_idle().await
+    }
+
+    /// See [`Codex::request_abort`].
+    pub async fn request_abort(&self) {
+        self.codex.request_abort().await
+    }
+
+    /// See [`Codex::active_task_count`].
+    pub async fn active_task_count(&self) -> usize {
+        self.codex.active_task_count().await
+    }
+
+    /// See [`Codex::task_kinds`].
+    pub async fn task_kinds(&self) -> Vec<String> {
+        self.codex.task_kinds().await
+    }
 }
@@ -3,8 +3,12 @@ pub use crate::config_loader::load_config_as_toml;
 use crate::config_loader::load_config_layers_with_overrides;
 use crate::config_loader::merge_toml_values;
 use crate::config_profile::ConfigProfile;
+use crate::config_types::ApprovalLimits;
+use crate::config_types::ApprovalLimitsToml;
+use crate::config_types::ConversationLeaseConflict;
 use crate::config_types::DEFAULT_OTEL_ENVIRONMENT;
 use crate::config_types::History;
+use crate::config_types::ImageDegradation;
 use crate::config_types::McpServerConfig;
 use crate::config_types::McpServerTransportConfig;
 use crate::config_types::Notifications;
@@ -12,11 +16,24 @@ use crate::config_types::OtelConfig;
 use crate::config_types::OtelConfigToml;
 use crate::config_types::OtelExporterKind;
 use crate::config_types::ReasoningSummaryFormat;
+use crate::config_types::RetryToml;
 use crate::config_types::SandboxWorkspaceWrite;
 use crate::config_types::ShellEnvironmentPolicy;
 use crate::config_types::ShellEnvironmentPolicyToml;
+use crate::config_types::TaskTemplatePaths;
 use crate::config_types::Tui;
 use crate::config_types::UriBasedFileOpener;
+use crate::config_types::VisualizerBatch;
+use crate::config_types::VisualizerChunks;
+use crate::config_types::VisualizerConnect;
+use crate::config_types::VisualizerDropPolicy;
+use crate::config_types::VisualizerReplay;
+use crate::config_types::VisualizerRetention;
+use crate::config_types::VisualizerRetentionToml;
+use crate::config_types::VisualizerTls;
+use crate::config_types::VisualizerTokenPlacement;
+use crate::digest::DEFAULT_RETAINED_TURNS;
+use crate::git_info::get_git_repo_root;
 use crate::git_info::resolve_root_git_project_for_trust;
 use crate::model_family::ModelFamily;
 use crate::model_family::derive_default_model_family;
@@ -26,6 +43,10 @@ use crate::model_provider_info::built_in_model_providers;
 use crate::openai_model_info::get_model_info;
 use crate::protocol::AskForApproval;
 use crate::protocol::SandboxPolicy;
+use crate::retry::RetryPolicy;
+use crate::turn_templates::DEFAULT_TURN_TEMPLATES_DIR;
+use crate::visualizer::DEFAULT_MAX_CONNECT_FAILURES;
+use crate::visualizer::DEFAULT_MAX_EVENT_BYTES;
 use anyhow::Context;
 use codex_app_server_protocol::Tools;
 use codex_app_server_protocol::UserSavedConfig;
@@ -39,8 +60,10 @@ use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::io::ErrorKind;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use tempfile::NamedTempFile;
 use toml::Value as TomlValue;
@@ -63,6 +86,19 @@ pub(crate) const PROJECT_DOC_MAX_BYTES: usize = 32 * 1024; // 32 KiB
 
 pub(crate) const CONFIG_TOML_FILE: &str = "config.toml";
 
+/// Default idle period before the visualizer forwarder task stops.
+const DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS: u64 = 300;
+
+const DEFAULT_VISUALIZER_KEEPALIVE_SECS: u64 = 15;
+
+const DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS: u64 = 10;
+
+/// Files per slice when a review's diff is too large to review at once.
+const DEFAULT_REVIEW_SLICE_FILES: usize = 40;
+
+/// Default period between polls of running tasks' progress.
+const DEFAULT_TASK_PROGRESS_INTERVAL_MS: u64 = 2000;
+
 /// Application configuration loaded from disk and merged with overrides.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Config {
@@ -229,6 +265,152 @@ pub struct Config {
     /// or placeholder replacement will occur for fast keypress bursts.
     pub disable_paste_burst: bool,
 
+    /// When true, a task whose working directory fails spawn-time validation
+    /// (missing, not a directory, unreadable) is started anyway with a
+    /// warning instead of being rejected.
+    pub warn_on_invalid_cwd: bool,
+
+    /// When true, visualizer events sent to a non-loopback relay have
+    /// timestamps rounded, durations bucketed, paths reduced to extension
+    /// and depth, and other strings replaced by salted hashes.
+    pub coarse_visualizer_telemetry: bool,
+
+    /// Stop the visualizer forwarder task after this long without events; it
+    /// restarts on the next event. `None` keeps it running.
+    pub visualizer_idle_shutdown: Option<Duration>,
+
+    /// Per-action-type limits on the visualizer events kept in memory.
+    /// Action types without an entry are kept until the ring evicts them.
+    pub visualizer_retention: HashMap<String, VisualizerRetention>,
+
+    /// When true, visualizer events carry `timestampMs` as a decimal string
+    /// instead of a number, for consumers that mangle large numbers.
+    pub visualizer_string_timestamps: bool,
+
+    /// Directories whose file contents visualizer events may carry to a
+    /// non-loopback relay; contents of other files are omitted. Defaults to
+    /// the git repository containing `cwd`, or `cwd` itself.
+    pub visualizer_trusted_roots: Vec<PathBuf>,
+
+    /// When true and a relay is configured, task lifecycle, approval, and
+    /// patch events wait for delivery, and a lost one aborts running tasks
+    /// with `TurnAbortReason::TelemetryFailure`.
+    pub visualizer_strict_telemetry: bool,
+
+    /// When true, the `state` snapshot of each task lifecycle event is
+    /// checked against the active turn, and a disagreement is emitted as
+    /// `telemetry_inconsistency`. Defaults to on in debug builds.
+    pub visualizer_consistency_checks: bool,
+
+    /// When set and a relay is configured, lifecycle events are persisted
+    /// here before they are sent and delivered at least once, across
+    /// restarts. One that cannot be persisted fails running tasks like
+    /// strict telemetry does. A relative path is resolved against
+    /// `codex_home`.
+    pub visualizer_durable_queue_dir: Option<PathBuf>,
+
+    /// What to do when resuming a conversation whose rollout another
+    /// running session holds the lease on.
+    pub conversation_lease_conflict: ConversationLeaseConflict,
+
+    /// Where each kind of task looks for its instructions template.
+    pub task_templates: TaskTemplatePaths,
+
+    /// Directory of the turn templates spawned with `Op::SpawnTemplate`,
+    /// relative to the session's cwd unless absolute.
+    pub turn_templates_dir: PathBuf,
+
+    /// How image inputs are reduced under context pressure.
+    pub image_degradation: ImageDegradation,
+
+    /// Reviews of diffs with more changed files than this run in slices of
+    /// this many files. `0` always reviews the whole diff at once.
+    pub review_slice_files: usize,
+
+    /// How the visualizer forwarders reconnect after losing the relay.
+    pub visualizer_reconnect: RetryPolicy,
+
+    /// How a user turn that failed with a retryable error is run again.
+    /// `None` lets it fail.
+    pub task_retry: Option<RetryPolicy>,
+
+    /// Failed connects in a row after which the visualizer gives up on its
+    /// relay for the rest of the session. `None` never gives up.
+    pub visualizer_max_connect_failures: Option<u32>,
+
+    /// Ping an idle visualizer relay this often, and reconnect if the pong
+    /// takes longer than `visualizer_keepalive_timeout`. `None` heartbeats
+    /// only in the eager connect modes, without waiting for the pong.
+    pub visualizer_keepalive: Option<Duration>,
+
+    /// How long a keepalive ping waits for its pong.
+    pub visualizer_keepalive_timeout: Duration,
+
+    /// How `wss` visualizer relays are authenticated.
+    pub visualizer_tls: VisualizerTls,
+
+    /// Where `CODEX_VISUALIZER_TOKEN` is sent to the relay.
+    pub visualizer_token_placement: VisualizerTokenPlacement,
+
+    /// PEM bundle of root CAs trusted for `wss` visualizer relays on top
+    /// of the platform's. A relative path is resolved against
+    /// `codex_home`, as are the client certificate and key.
+    pub visualizer_tls_ca_file: Option<PathBuf>,
+
+    /// PEM certificate chain presented to `wss` visualizer relays that
+    /// require mutual TLS, with `visualizer_tls_client_key`.
+    pub visualizer_tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of `visualizer_tls_client_cert`.
+    pub visualizer_tls_client_key: Option<PathBuf>,
+
+    /// Gzip large visualizer frames and send them as binary frames.
+    pub visualizer_compression: bool,
+
+    /// Whether the visualizer connects when the session starts rather
+    /// than on its first event.
+    pub visualizer_connect: VisualizerConnect,
+
+    /// What happens to visualizer events emitted while its queue is full.
+    pub visualizer_drop_policy: VisualizerDropPolicy,
+
+    /// Fixed capacity of the visualizer queue, in events. When unset the
+    /// queue starts at 256 events and grows for bursts.
+    pub visualizer_queue_capacity: Option<usize>,
+
+    /// Serialized size above which a visualizer event not sent in chunks
+    /// is truncated. `None` sends events whole.
+    pub visualizer_max_event_bytes: Option<usize>,
+
+    /// Whether and how oversized visualizer events are sent in chunks.
+    pub visualizer_chunks: VisualizerChunks,
+
+    /// Whether and how visualizer events are sent in batches.
+    pub visualizer_batch: VisualizerBatch,
+
+    /// Whether and how many recent visualizer events are replayed to a
+    /// relay that connects.
+    pub visualizer_replay: VisualizerReplay,
+
+    /// Finished tasks kept in full for `Op::GetDigest` and `Op::ExportTurn`;
+    /// older ones are folded into hourly aggregates.
+    pub digest_retained_turns: usize,
+
+    /// Abort a task spawned for user input, and its resumption after a
+    /// compaction, once it has run this long. `None` sets no deadline.
+    pub task_timeout: Option<Duration>,
+
+    /// How often running tasks are asked for their progress, which is
+    /// emitted as `task_progress`. `None` never asks.
+    pub task_progress_interval: Option<Duration>,
+
+    /// Input items of a user turn sent to the model at a time; a turn with
+    /// more runs them in batches of this many. `None` sends them all at once.
+    pub task_input_chunk_size: Option<NonZeroUsize>,
+
+    /// Per-task cap on outstanding approval requests and their timeout.
+    pub approval_limits: ApprovalLimits,
+
     /// OTEL configuration (exporter type, endpoint, headers, etc.).
     pub otel: crate::config_types::OtelConfig,
 }
@@ -812,6 +994,133 @@ pub struct ConfigToml {
     /// or placeholder replacement will occur for fast keypress bursts.
     pub disable_paste_burst: Option<bool>,
 
+    /// Downgrade spawn-time working directory validation failures to warnings.
+    pub warn_on_invalid_cwd: Option<bool>,
+
+    /// Coarsen visualizer telemetry sent to non-loopback relays.
+    pub coarse_visualizer_telemetry: Option<bool>,
+
+    /// Seconds without visualizer events before the forwarder task stops;
+    /// `0` keeps it running.
+    pub visualizer_idle_shutdown_secs: Option<u64>,
+
+    /// Per-action-type limits on kept visualizer events.
+    #[serde(default)]
+    pub visualizer_retention: HashMap<String, VisualizerRetentionToml>,
+
+    /// Send visualizer timestamps as strings.
+    pub visualizer_string_timestamps: Option<bool>,
+
+    /// Directories whose file contents may reach remote visualizer relays.
+    pub visualizer_trusted_roots: Option<Vec<PathBuf>>,
+
+    /// Fail the session when a lifecycle visualizer event is lost.
+    pub visualizer_strict_telemetry: Option<bool>,
+
+    /// Check lifecycle event state snapshots against the active turn.
+    pub visualizer_consistency_checks: Option<bool>,
+
+    /// Directory of the durable queue for lifecycle visualizer events.
+    pub visualizer_durable_queue_dir: Option<PathBuf>,
+
+    /// `refuse` (default) or `fork` when another session holds the lease on
+    /// a resumed conversation.
+    pub conversation_lease_conflict: Option<ConversationLeaseConflict>,
+
+    /// Instructions template paths per task kind.
+    #[serde(default)]
+    pub task_templates: TaskTemplatePaths,
+
+    /// Directory of the turn templates; see `turn_templates`.
+    pub turn_templates_dir: Option<PathBuf>,
+
+    /// Downscaling and dropping of image inputs under context pressure.
+    #[serde(default)]
+    pub image_degradation: ImageDegradation,
+
+    /// Files per review slice (default 40, `0` = no slicing).
+    pub review_slice_files: Option<usize>,
+
+    /// Retry schedules, one `[retry.<consumer>]` table each.
+    #[serde(default)]
+    pub retry: RetryToml,
+
+    /// `verify` (default) or `trust-on-first-use` for `wss` visualizer
+    /// relays.
+    pub visualizer_tls: Option<VisualizerTls>,
+
+    /// `header` (default) or `query`: where `CODEX_VISUALIZER_TOKEN` is
+    /// sent to the relay.
+    pub visualizer_token_placement: Option<VisualizerTokenPlacement>,
+
+    /// PEM bundle of extra root CAs for `wss` visualizer relays.
+    pub visualizer_tls_ca_file: Option<PathBuf>,
+
+    /// PEM client certificate chain for mutual TLS with the relay.
+    pub visualizer_tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate.
+    pub visualizer_tls_client_key: Option<PathBuf>,
+
+    /// Gzip visualizer frames of 1 KiB or more (default false).
+    pub visualizer_compression: Option<bool>,
+
+    /// Failed relay connects in a row before the visualizer is disabled
+    /// (default 50); `0` never gives up.
+    pub visualizer_max_connect_failures: Option<u32>,
+
+    /// Seconds between keepalive pings of an idle relay (default 15); `0`
+    /// turns them off.
+    pub visualizer_keepalive_secs: Option<u64>,
+
+    /// Seconds a keepalive ping waits for its pong (default 10).
+    pub visualizer_keepalive_timeout_secs: Option<u64>,
+
+    /// `lazy` (default), `eager`, or `eager-idle-shutdown`.
+    pub visualizer_connect: Option<VisualizerConnect>,
+
+    /// `drop-newest` (default), `drop-oldest`, or `block`.
+    pub visualizer_drop_policy: Option<VisualizerDropPolicy>,
+
+    /// Fixed capacity of the visualizer queue, in events.
+    pub visualizer_queue_capacity: Option<usize>,
+
+    /// Bytes a visualizer event may serialize to before it is truncated
+    /// (default 256 KiB); `0` never truncates.
+    pub visualizer_max_event_bytes: Option<usize>,
+
+    /// Chunking of oversized visualizer events.
+    #[serde(default)]
+    pub visualizer_chunks: VisualizerChunks,
+
+    /// Batching of visualizer events.
+    #[serde(default)]
+    pub visualizer_batch: VisualizerBatch,
+
+    /// Replay of recent visualizer events on connect.
+    #[serde(default)]
+    pub visualizer_replay: VisualizerReplay,
+
+    /// Finished tasks kept in full by the digest (default 512).
+    pub digest_retained_turns: Option<usize>,
+
+    /// Seconds a task for user input may run before it is aborted; unset
+    /// or `0` sets no deadline.
+    pub task_timeout_secs: Option<u64>,
+
+    /// Milliseconds between polls of running tasks' progress (default
+    /// 2000); `0` turns polling off.
+    pub task_progress_interval_ms: Option<u64>,
+
+    /// Most input items of a user turn sent to the model in one request; a
+    /// turn with more runs them in batches. Unset or `0` sends them all at
+    /// once.
+    pub task_input_chunk_size: Option<usize>,
+
+    /// Limits on outstanding approval requests.
+    #[serde(default)]
+    pub approvals: ApprovalLimitsToml,
+
     /// OTEL configuration.
     pub otel: Option<crate::config_types::OtelConfigToml>,
 
@@ -1104,6 +1413,28 @@ impl Config {
             .or(cfg.review_model)
             .unwrap_or_else(default_review_model);
 
+        // Relative visualizer files live under codex_home.
+        let visualizer_durable_queue_dir = cfg
+            .visualizer_durable_queue_dir
+            .map(|dir| codex_home.join(dir));
+        let visualizer_tls_ca_file = cfg.visualizer_tls_ca_file.map(|path| codex_home.join(path));
+        let visualizer_tls_client_cert = cfg
+            .visualizer_tls_client_cert
+            .map(|path| codex_home.join(path));
+        let visualizer_tls_client_key = cfg
+            .visualizer_tls_client_key
+            .map(|path| codex_home.join(path));
+
+        // Relative roots are taken relative to the effective cwd, like other
+        // path-like config values.
+        let visualizer_trusted_roots = match cfg.visualizer_trusted_roots {
+            Some(roots) => roots
+                .into_iter()
+                .map(|root| resolved_cwd.join(root))
+                .collect(),
+            None => vec![get_git_repo_root(&resolved_cwd).unwrap_or_else(|| resolved_cwd.clone())],
+        };
+
         let config = Self {
             model,
             review_model,
@@ -1180,6 +1511,85 @@ impl Config {
             active_profile: active_profile_name,
             windows_wsl_setup_acknowledged: cfg.windows_wsl_setup_acknowledged.unwrap_or(false),
             disable_paste_burst: cfg.disable_paste_burst.unwrap_or(false),
+            warn_on_invalid_cwd: cfg.warn_on_invalid_cwd.unwrap_or(false),
+            coarse_visualizer_telemetry: cfg.coarse_visualizer_telemetry.unwrap_or(false),
+            visualizer_idle_shutdown: match cfg
+                .visualizer_idle_shutdown_secs
+                .unwrap_or(DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS)
+            {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            visualizer_retention: cfg
+                .visualizer_retention
+                .into_iter()
+                .map(|(action_type, retention)| (action_type, retention.into()))
+                .collect(),
+            visualizer_string_timestamps: cfg.visualizer_string_timestamps.unwrap_or(false),
+            visualizer_trusted_roots,
+            visualizer_strict_telemetry: cfg.visualizer_strict_telemetry.unwrap_or(false),
+            visualizer_consistency_checks: cfg
+                .visualizer_consistency_checks
+                .unwrap_or(cfg!(debug_assertions)),
+            visualizer_durable_queue_dir,
+            conversation_lease_conflict: cfg.conversation_lease_conflict.unwrap_or_default(),
+            task_templates: cfg.task_templates,
+            turn_templates_dir: cfg
+                .turn_templates_dir
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_TURN_TEMPLATES_DIR)),
+            image_degradation: cfg.image_degradation,
+            review_slice_files: cfg.review_slice_files.unwrap_or(DEFAULT_REVIEW_SLICE_FILES),
+            visualizer_reconnect: cfg
+                .retry
+                .visualizer
+                .resolve(RetryPolicy::VISUALIZER_RECONNECT),
+            task_retry: cfg.retry.task.map(|task| task.resolve(RetryPolicy::TASK)),
+            visualizer_max_connect_failures: cfg
+                .visualizer_max_connect_failures
+                .map_or(Some(DEFAULT_MAX_CONNECT_FAILURES), |max| {
+                    (max > 0).then_some(max)
+                }),
+            visualizer_keepalive: match cfg
+                .visualizer_keepalive_secs
+                .unwrap_or(DEFAULT_VISUALIZER_KEEPALIVE_SECS)
+            {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            visualizer_keepalive_timeout: Duration::from_secs(
+                cfg.visualizer_keepalive_timeout_secs
+                    .unwrap_or(DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS),
+            ),
+            visualizer_tls: cfg.visualizer_tls.unwrap_or_default(),
+            visualizer_token_placement: cfg.visualizer_token_placement.unwrap_or_default(),
+            visualizer_tls_ca_file,
+            visualizer_tls_client_cert,
+            visualizer_tls_client_key,
+            visualizer_compression: cfg.visualizer_compression.unwrap_or(false),
+            visualizer_connect: cfg.visualizer_connect.unwrap_or_default(),
+            visualizer_drop_policy: cfg.visualizer_drop_policy.unwrap_or_default(),
+            visualizer_queue_capacity: cfg.visualizer_queue_capacity,
+            visualizer_max_event_bytes: cfg
+                .visualizer_max_event_bytes
+                .map_or(Some(DEFAULT_MAX_EVENT_BYTES), |max| {
+                    (max > 0).then_some(max)
+                }),
+            visualizer_chunks: cfg.visualizer_chunks,
+            visualizer_batch: cfg.visualizer_batch,
+            visualizer_replay: cfg.visualizer_replay,
+            digest_retained_turns: cfg.digest_retained_turns.unwrap_or(DEFAULT_RETAINED_TURNS),
+            task_timeout: cfg
+                .task_timeout_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            task_progress_interval: Some(
+                cfg.task_progress_interval_ms
+                    .unwrap_or(DEFAULT_TASK_PROGRESS_INTERVAL_MS),
+            )
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+            task_input_chunk_size: cfg.task_input_chunk_size.and_then(NonZeroUsize::new),
+            approval_limits: cfg.approvals.into(),
             tui_notifications: cfg
                 .tui
                 .as_ref()
@@ -1361,6 +1771,90 @@ persistence = "none"
         assert_eq!(tui.notifications, Notifications::Enabled(false));
     }
 
+    #[test]
+    fn visualizer_retention_tables_parse_per_action_type() {
+        let cfg = r#"
+[visualizer_retention.exec_output]
+max_count = 500
+
+[visualizer_retention.reasoning_phase]
+max_age_secs = 60
+"#;
+
+        let parsed = toml::from_str::<ConfigToml>(cfg).expect("retention tables should parse");
+        let retention: HashMap<String, VisualizerRetention> = parsed
+            .visualizer_retention
+            .into_iter()
+            .map(|(action_type, retention)| (action_type, retention.into()))
+            .collect();
+
+        assert_eq!(
+            retention,
+            HashMap::from([
+                (
+                    "exec_output".to_string(),
+                    VisualizerRetention {
+                        max_count: Some(500),
+                        max_age: None,
+                    },
+                ),
+                (
+                    "reasoning_phase".to_string(),
+                    VisualizerRetention {
+                        max_count: None,
+                        max_age: Some(Duration::from_secs(60)),
+                    },
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn retry_tables_override_only_the_keys_they_set() {
+        let cfg = r#"
+[retry.visualizer]
+multiplier = 2.0
+max_delay_ms = 30000
+jitter = { proportional = 0.1 }
+max_attempts = 10
+"#;
+
+        let parsed = toml::from_str::<ConfigToml>(cfg).expect("retry tables should parse");
+        assert_eq!(
+            parsed
+                .retry
+                .visualizer
+                .resolve(RetryPolicy::VISUALIZER_RECONNECT),
+            RetryPolicy {
+                multiplier: 2.0,
+                max_delay: Duration::from_secs(30),
+                jitter: crate::retry::Jitter::Proportional(0.1),
+                max_attempts: Some(10),
+                ..RetryPolicy::VISUALIZER_RECONNECT
+            }
+        );
+    }
+
+    #[test]
+    fn task_retry_is_off_without_its_table() {
+        let off = toml::from_str::<ConfigToml>("").expect("empty config should parse");
+        let on = toml::from_str::<ConfigToml>("[retry.task]\nmax_attempts = 5\n")
+            .expect("retry table should parse");
+        assert_eq!(
+            (
+                off.retry.task.map(|task| task.resolve(RetryPolicy::TASK)),
+                on.retry.task.map(|task| task.resolve(RetryPolicy::TASK)),
+            ),
+            (
+                None,
+                Some(RetryPolicy {
+                    max_attempts: Some(5),
+                    ..RetryPolicy::TASK
+                }),
+            )
+        );
+    }
+
     #[test]
     fn test_sandbox_config_parsing() {
         let sandbox_full_access = r#"
@@ -2123,6 +2617,49 @@ model_verbosity = "high"
                 active_profile: Some("o3".to_string()),
                 windows_wsl_setup_acknowledged: false,
                 disable_paste_burst: false,
+                warn_on_invalid_cwd: false,
+                coarse_visualizer_telemetry: false,
+                visualizer_idle_shutdown: Some(Duration::from_secs(
+                    DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS
+                )),
+                visualizer_retention: HashMap::new(),
+                visualizer_string_timestamps: false,
+                visualizer_trusted_roots: vec![fixture.cwd()],
+                visualizer_strict_telemetry: false,
+                visualizer_consistency_checks: cfg!(debug_assertions),
+                visualizer_durable_queue_dir: None,
+                conversation_lease_conflict: ConversationLeaseConflict::Refuse,
+                task_templates: TaskTemplatePaths::default(),
+                turn_templates_dir: PathBuf::from(DEFAULT_TURN_TEMPLATES_DIR),
+                image_degradation: ImageDegradation::default(),
+                review_slice_files: DEFAULT_REVIEW_SLICE_FILES,
+                visualizer_reconnect: RetryPolicy::VISUALIZER_RECONNECT,
+                task_retry: None,
+                visualizer_max_connect_failures: Some(DEFAULT_MAX_CONNECT_FAILURES),
+                visualizer_keepalive: Some(Duration::from_secs(DEFAULT_VISUALIZER_KEEPALIVE_SECS)),
+                visualizer_keepalive_timeout: Duration::from_secs(
+                    DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS
+                ),
+                visualizer_tls: VisualizerTls::Verify,
+                visualizer_token_placement: VisualizerTokenPlacement::Header,
+                visualizer_tls_ca_file: None,
+                visualizer_tls_client_cert: None,
+                visualizer_tls_client_key: None,
+                visualizer_compression: false,
+                visualizer_connect: VisualizerConnect::Lazy,
+                visualizer_drop_policy: VisualizerDropPolicy::DropNewest,
+                visualizer_queue_capacity: None,
+                visualizer_max_event_bytes: Some(DEFAULT_MAX_EVENT_BYTES),
+                visualizer_chunks: VisualizerChunks::default(),
+                visualizer_batch: VisualizerBatch::default(),
+                visualizer_replay: VisualizerReplay::default(),
+                digest_retained_turns: DEFAULT_RETAINED_TURNS,
+                task_timeout: None,
+                task_progress_interval: Some(Duration::from_millis(
+                    DEFAULT_TASK_PROGRESS_INTERVAL_MS
+                )),
+                task_input_chunk_size: None,
+                approval_limits: ApprovalLimits::default(),
                 tui_notifications: Default::default(),
                 otel: OtelConfig::default(),
             },
@@ -2186,6 +2723,47 @@ model_verbosity = "high"
             active_profile: Some("gpt3".to_string()),
             windows_wsl_setup_acknowledged: false,
             disable_paste_burst: false,
+            warn_on_invalid_cwd: false,
+            coarse_visualizer_telemetry: false,
+            visualizer_idle_shutdown: Some(Duration::from_secs(
+                DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS,
+            )),
+            visualizer_retention: HashMap::new(),
+            visualizer_string_timestamps: false,
+            visualizer_trusted_roots: vec![fixture.cwd()],
+            visualizer_strict_telemetry: false,
+            visualizer_consistency_checks: cfg!(debug_assertions),
+            visualizer_durable_queue_dir: None,
+            conversation_lease_conflict: ConversationLeaseConflict::Refuse,
+            task_templates: TaskTemplatePaths::default(),
+            turn_templates_dir: PathBuf::from(DEFAULT_TURN_TEMPLATES_DIR),
+            image_degradation: ImageDegradation::default(),
+            review_slice_files: DEFAULT_REVIEW_SLICE_FILES,
+            visualizer_reconnect: RetryPolicy::VISUALIZER_RECONNECT,
+            task_retry: None,
+            visualizer_max_connect_failures: Some(DEFAULT_MAX_CONNECT_FAILURES),
+            visualizer_keepalive: Some(Duration::from_secs(DEFAULT_VISUALIZER_KEEPALIVE_SECS)),
+            visualizer_keepalive_timeout: Duration::from_secs(
+                DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS,
+            ),
+            visualizer_tls: VisualizerTls::Verify,
+            visualizer_token_placement: VisualizerTokenPlacement::Header,
+            visualizer_tls_ca_file: None,
+            visualizer_tls_client_cert: None,
+            visualizer_tls_client_key: None,
+            visualizer_compression: false,
+            visualizer_connect: VisualizerConnect::Lazy,
+            visualizer_drop_policy: VisualizerDropPolicy::DropNewest,
+            visualizer_queue_capacity: None,
+            visualizer_max_event_bytes: Some(DEFAULT_MAX_EVENT_BYTES),
+            visualizer_chunks: VisualizerChunks::default(),
+            visualizer_batch: VisualizerBatch::default(),
+            visualizer_replay: VisualizerReplay::default(),
+            digest_retained_turns: DEFAULT_RETAINED_TURNS,
+            task_timeout: None,
+            task_progress_interval: Some(Duration::from_millis(DEFAULT_TASK_PROGRESS_INTERVAL_MS)),
+            task_input_chunk_size: None,
+            approval_limits: ApprovalLimits::default(),
             tui_notifications: Default::default(),
             otel: OtelConfig::default(),
         };
@@ -2264,6 +2842,47 @@ model_verbosity = "high"
             active_profile: Some("zdr".to_string()),
             windows_wsl_setup_acknowledged: false,
             disable_paste_burst: false,
+            warn_on_invalid_cwd: false,
+            coarse_visualizer_telemetry: false,
+            visualizer_idle_shutdown: Some(Duration::from_secs(
+                DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS,
+            )),
+            visualizer_retention: HashMap::new(),
+            visualizer_string_timestamps: false,
+            visualizer_trusted_roots: vec![fixture.cwd()],
+            visualizer_strict_telemetry: false,
+            visualizer_consistency_checks: cfg!(debug_assertions),
+            visualizer_durable_queue_dir: None,
+            conversation_lease_conflict: ConversationLeaseConflict::Refuse,
+            task_templates: TaskTemplatePaths::default(),
+            turn_templates_dir: PathBuf::from(DEFAULT_TURN_TEMPLATES_DIR),
+            image_degradation: ImageDegradation::default(),
+            review_slice_files: DEFAULT_REVIEW_SLICE_FILES,
+            visualizer_reconnect: RetryPolicy::VISUALIZER_RECONNECT,
+            task_retry: None,
+            visualizer_max_connect_failures: Some(DEFAULT_MAX_CONNECT_FAILURES),
+            visualizer_keepalive: Some(Duration::from_secs(DEFAULT_VISUALIZER_KEEPALIVE_SECS)),
+            visualizer_keepalive_timeout: Duration::from_secs(
+                DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS,
+            ),
+            visualizer_tls: VisualizerTls::Verify,
+            visualizer_token_placement: VisualizerTokenPlacement::Header,
+            visualizer_tls_ca_file: None,
+            visualizer_tls_client_cert: None,
+            visualizer_tls_client_key: None,
+            visualizer_compression: false,
+            visualizer_connect: VisualizerConnect::Lazy,
+            visualizer_drop_policy: VisualizerDropPolicy::DropNewest,
+            visualizer_queue_capacity: None,
+            visualizer_max_event_bytes: Some(DEFAULT_MAX_EVENT_BYTES),
+            visualizer_chunks: VisualizerChunks::default(),
+            visualizer_batch: VisualizerBatch::default(),
+            visualizer_replay: VisualizerReplay::default(),
+            digest_retained_turns: DEFAULT_RETAINED_TURNS,
+            task_timeout: None,
+            task_progress_interval: Some(Duration::from_millis(DEFAULT_TASK_PROGRESS_INTERVAL_MS)),
+            task_input_chunk_size: None,
+            approval_limits: ApprovalLimits::default(),
             tui_notifications: Default::default(),
             otel: OtelConfig::default(),
         };
@@ -2328,6 +2947,47 @@ model_verbosity = "high"
             active_profile: Some("gpt5".to_string()),
             windows_wsl_setup_acknowledged: false,
             disable_paste_burst: false,
+            warn_on_invalid_cwd: false,
+            coarse_visualizer_telemetry: false,
+            visualizer_idle_shutdown: Some(Duration::from_secs(
+                DEFAULT_VISUALIZER_IDLE_SHUTDOWN_SECS,
+            )),
+            visualizer_retention: HashMap::new(),
+            visualizer_string_timestamps: false,
+            visualizer_trusted_roots: vec![fixture.cwd()],
+            visualizer_strict_telemetry: false,
+            visualizer_consistency_checks: cfg!(debug_assertions),
+            visualizer_durable_queue_dir: None,
+            conversation_lease_conflict: ConversationLeaseConflict::Refuse,
+            task_templates: TaskTemplatePaths::default(),
+            turn_templates_dir: PathBuf::from(DEFAULT_TURN_TEMPLATES_DIR),
+            image_degradation: ImageDegradation::default(),
+            review_slice_files: DEFAULT_REVIEW_SLICE_FILES,
+            visualizer_reconnect: RetryPolicy::VISUALIZER_RECONNECT,
+            task_retry: None,
+            visualizer_max_connect_failures: Some(DEFAULT_MAX_CONNECT_FAILURES),
+            visualizer_keepalive: Some(Duration::from_secs(DEFAULT_VISUALIZER_KEEPALIVE_SECS)),
+            visualizer_keepalive_timeout: Duration::from_secs(
+                DEFAULT_VISUALIZER_KEEPALIVE_TIMEOUT_SECS,
+            ),
+            visualizer_tls: VisualizerTls::Verify,
+            visualizer_token_placement: VisualizerTokenPlacement::Header,
+            visualizer_tls_ca_file: None,
+            visualizer_tls_client_cert: None,
+            visualizer_tls_client_key: None,
+            visualizer_compression: false,
+            visualizer_connect: VisualizerConnect::Lazy,
+            visualizer_drop_policy: VisualizerDropPolicy::DropNewest,
+            visualizer_queue_capacity: None,
+            visualizer_max_event_bytes: Some(DEFAULT_MAX_EVENT_BYTES),
+            visualizer_chunks: VisualizerChunks::default(),
+            visualizer_batch: VisualizerBatch::default(),
+            visualizer_replay: VisualizerReplay::default(),
+            digest_retained_turns: DEFAULT_RETAINED_TURNS,
+            task_timeout: None,
+            task_progress_interval: Some(Duration::from_millis(DEFAULT_TASK_PROGRESS_INTERVAL_MS)),
+            task_input_chunk_size: None,
+            approval_limits: ApprovalLimits::default(),
             tui_notifications: Default::default(),
             otel: OtelConfig::default(),
         };
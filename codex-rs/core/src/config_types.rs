@@ -5,6 +5,7 @@
 
 use serde::Deserializer;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::time::Duration;
 use wildmatch::WildMatchPattern;
@@ -13,6 +14,9 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde::de::Error as SerdeError;
 
+use crate::retry::Jitter;
+use crate::retry::RetryPolicy;
+
 pub const DEFAULT_OTEL_ENVIRONMENT: &str = "dev";
 
 #[derive(Serialize, Debug, Clone, PartialEq)]
@@ -360,6 +364,321 @@ pub enum ShellEnvironmentPolicyInherit {
     None,
 }
 
+/// Limits on approval requests raised while a task runs, from the
+/// `[approvals]` table.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ApprovalLimitsToml {
+    /// Maximum approval requests a single task may have outstanding at once.
+    pub max_outstanding_per_task: Option<usize>,
+
+    /// Seconds an approval request may go unanswered before it is denied.
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ApprovalLimits {
+    /// Further tool calls needing approval wait until one of the task's
+    /// outstanding requests resolves. `None` means no cap.
+    pub max_outstanding_per_task: Option<NonZeroUsize>,
+
+    /// Unanswered requests resolve as timed out (a denial) after this much
+    /// unpaused time. `None` waits indefinitely.
+    pub timeout: Option<Duration>,
+}
+
+impl From<ApprovalLimitsToml> for ApprovalLimits {
+    fn from(toml: ApprovalLimitsToml) -> Self {
+        Self {
+            max_outstanding_per_task: toml.max_outstanding_per_task.and_then(NonZeroUsize::new),
+            timeout: toml
+                .timeout_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+/// What a session does when another process holds the lease on the
+/// conversation it resumes.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConversationLeaseConflict {
+    /// Refuse to start.
+    #[default]
+    Refuse,
+    /// Start anyway, reporting a distinct conversation identity to the
+    /// visualizer.
+    Fork,
+}
+
+/// How the visualizer authenticates a `wss` relay.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum VisualizerTls {
+    /// Require a certificate chain to one of the platform's trusted roots.
+    #[default]
+    Verify,
+    /// Pin the key of the first certificate each `host:port` presents, in
+    /// `visualizer_pins.json` under `CODEX_HOME`, and refuse any other
+    /// key from it after.
+    TrustOnFirstUse,
+}
+
+/// Where the visualizer sends `CODEX_VISUALIZER_TOKEN` to the relay.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum VisualizerTokenPlacement {
+    /// In an `Authorization: Bearer <token>` header of the handshake.
+    #[default]
+    Header,
+    /// As a `token` query parameter of the relay URL, for relays behind
+    /// proxies that strip the header.
+    Query,
+}
+
+/// When the visualizer forwarder opens its relay connection.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum VisualizerConnect {
+    /// On the first event, parking after `visualizer_idle_shutdown_secs`
+    /// without events.
+    #[default]
+    Lazy,
+    /// When the session starts, keeping the connection open with
+    /// heartbeats for the rest of the session.
+    Eager,
+    /// When the session starts, with heartbeats while idle, but parking
+    /// after `visualizer_idle_shutdown_secs` like `lazy`; the next event
+    /// reconnects.
+    EagerIdleShutdown,
+}
+
+/// What the visualizer does with an event that does not fit in its queue
+/// once the queue can grow no further.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum VisualizerDropPolicy {
+    /// Drop the new event.
+    #[default]
+    DropNewest,
+    /// Evict the oldest queued events, other than task lifecycle events, to
+    /// make room for it.
+    DropOldest,
+    /// Make the emitting task wait until the forwarder has made room.
+    /// Events queued where waiting is impossible, such as the last events
+    /// of a teardown, are dropped as under `drop-newest`.
+    Block,
+}
+
+/// How image inputs are reduced when they would push a turn over the
+/// context budget, from the `[image_degradation]` table.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct ImageDegradation {
+    /// Images with more pixels than this are downscaled to fit first. `0`
+    /// skips this stage.
+    pub max_pixels: u64,
+
+    /// If that is not enough, all but this many of the most recent images
+    /// are dropped.
+    pub keep_recent: usize,
+}
+
+impl Default for ImageDegradation {
+    fn default() -> Self {
+        Self {
+            max_pixels: 1024 * 1024,
+            keep_recent: 2,
+        }
+    }
+}
+
+/// Splitting of oversized visualizer events into `event_chunk` frames,
+/// from the `[visualizer_chunks]` table.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct VisualizerChunks {
+    /// Off by default, since every consumer of the relay has to reassemble
+    /// the chunks.
+    pub enabled: bool,
+
+    /// Events serializing to more bytes than this are sent in frames of at
+    /// most this many bytes.
+    pub frame_bytes: usize,
+
+    /// Events serializing to more bytes than this are truncated to fit one
+    /// frame instead.
+    pub max_event_bytes: usize,
+}
+
+impl Default for VisualizerChunks {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frame_bytes: 1024 * 1024,
+            max_event_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Batching of visualizer events into one frame, from the
+/// `[visualizer_batch]` table.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct VisualizerBatch {
+    /// Off by default, since every consumer of the relay has to accept
+    /// frames holding an array of events.
+    pub enabled: bool,
+
+    /// How long after its first event a batch is sent, in milliseconds.
+    pub interval_ms: u64,
+
+    /// A batch holding this many events is sent before the interval is up.
+    pub max_events: usize,
+
+    /// Largest frame a batch is written as; larger batches are split over
+    /// several frames.
+    pub max_bytes: usize,
+}
+
+impl Default for VisualizerBatch {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: 50,
+            max_events: 100,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Visualizer events dropped while no relay could be reached, replayed to
+/// the next one that connects, from the `[visualizer_replay]` table. Off
+/// unless enabled.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct VisualizerReplay {
+    pub enabled: bool,
+
+    /// Most events kept for replay.
+    pub max_events: usize,
+
+    /// Events older than this many seconds are not replayed.
+    pub max_age_secs: u64,
+}
+
+impl Default for VisualizerReplay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_events: 500,
+            max_age_secs: 5 * 60,
+        }
+    }
+}
+
+/// Workspace files appended to the instructions of each kind of task, from
+/// the `[task_templates]` table. Relative paths resolve against the task's
+/// cwd; a missing file means no template.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TaskTemplatePaths {
+    pub regular: PathBuf,
+    pub review: PathBuf,
+    pub compact: PathBuf,
+}
+
+impl Default for TaskTemplatePaths {
+    fn default() -> Self {
+        Self {
+            regular: PathBuf::from(".codex/templates/regular.md"),
+            review: PathBuf::from(".codex/templates/review.md"),
+            compact: PathBuf::from(".codex/templates/compact.md"),
+        }
+    }
+}
+
+/// How long visualizer events of one action type are kept, from a
+/// `[visualizer_retention.<action_type>]` table.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VisualizerRetentionToml {
+    /// Keep at most this many events of the type; `0` keeps none.
+    pub max_count: Option<usize>,
+
+    /// Drop events of the type once they are this many seconds old.
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VisualizerRetention {
+    /// `None` keeps every event of the type that fits in the recent-event
+    /// ring; `Some(0)` keeps none of them.
+    pub max_count: Option<usize>,
+
+    /// `None` keeps events of the type regardless of age.
+    pub max_age: Option<Duration>,
+}
+
+impl From<VisualizerRetentionToml> for VisualizerRetention {
+    fn from(toml: VisualizerRetentionToml) -> Self {
+        Self {
+            max_count: toml.max_count,
+            max_age: toml.max_age_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+/// A retry schedule, from a `[retry.<consumer>]` table. Every consumer
+/// reads the same keys; unset ones keep that consumer's own defaults.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct RetryPolicyToml {
+    /// Milliseconds before the first retry.
+    pub initial_delay_ms: Option<u64>,
+
+    /// Growth of the delay from one retry to the next; `1.0` keeps it fixed.
+    pub multiplier: Option<f64>,
+
+    /// Milliseconds no single delay exceeds, jitter included.
+    pub max_delay_ms: Option<u64>,
+
+    /// `"none"`, `"full"`, or `{ proportional = <fraction> }`.
+    pub jitter: Option<Jitter>,
+
+    /// Attempts in total, counting the first; `0` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl RetryPolicyToml {
+    pub fn resolve(self, defaults: RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: self
+                .initial_delay_ms
+                .map_or(defaults.initial_delay, Duration::from_millis),
+            multiplier: self.multiplier.unwrap_or(defaults.multiplier),
+            max_delay: self
+                .max_delay_ms
+                .map_or(defaults.max_delay, Duration::from_millis),
+            jitter: self.jitter.unwrap_or(defaults.jitter),
+            max_attempts: match self.max_attempts {
+                Some(0) => None,
+                Some(max_attempts) => Some(max_attempts),
+                None => defaults.max_attempts,
+            },
+        }
+    }
+}
+
+/// The `[retry]` table: one [`RetryPolicyToml`] per consumer.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct RetryToml {
+    /// How the visualizer forwarders reconnect to the relay.
+    pub visualizer: RetryPolicyToml,
+
+    /// How a failed user turn is run again; without the table it is not.
+    pub task: Option<RetryPolicyToml>,
+}
+
 /// Policy for building the `env` when spawning a process via either the
 /// `shell` or `local_shell` tool.
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
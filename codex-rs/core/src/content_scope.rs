@@ -0,0 +1,363 @@
+//! Tags the file contents in visualizer payloads, so remote sinks only get
+//! contents of files inside the trusted roots (see `visualizer::scope`).
+//!
+//! Tagged are patch bodies and the output of commands that read files:
+//! `read_file` calls, and exec commands (`cat`, `head`, `sed -n`, ... as
+//! classified by `parse_command`), both in their protocol events and in the
+//! prompts they end up in. Output is attributed to every path argument of
+//! such a command, resolved against the exec's cwd, so output of a command
+//! that also reads one outside file is omitted as a whole.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use codex_protocol::models::LocalShellAction;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::models::ShellToolCallParams;
+use codex_protocol::parse_command::ParsedCommand;
+
+use crate::codex::Session;
+use crate::parse_command::parse_command;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::FileChange;
+use crate::visualizer::ContentField;
+
+/// Files read by a running exec command.
+pub(crate) struct ExecReads {
+    /// The task that runs the command, so its entries can be dropped when
+    /// the task ends without the command's end event.
+    sub_id: String,
+    paths: Vec<PathBuf>,
+}
+
+impl Session {
+    /// Content fields of the `protocol_event` payload for `event`. Exec
+    /// begin events only record which files the command reads, so the
+    /// output and end events can be tagged.
+    pub(crate) async fn protocol_event_content(&self, event: &Event) -> Vec<ContentField> {
+        let fields = match &event.msg {
+            EventMsg::PatchApplyBegin(begin) => patch_content(&begin.changes),
+            EventMsg::ApplyPatchApprovalRequest(request) => patch_content(&request.changes),
+            EventMsg::ExecCommandBegin(begin) => {
+                let paths = read_paths(&begin.parsed_cmd, &begin.cwd);
+                if !paths.is_empty() {
+                    self.state.lock().await.exec_reads.insert(
+                        begin.call_id.clone(),
+                        ExecReads {
+                            sub_id: event.id.clone(),
+                            paths,
+                        },
+                    );
+                }
+                Vec::new()
+            }
+            EventMsg::ExecCommandOutputDelta(delta) => {
+                let state = self.state.lock().await;
+                let reads = state.exec_reads.get(&delta.call_id);
+                fields_for(
+                    reads.map(|reads| reads.paths.as_slice()),
+                    &["event", "msg", "chunk"],
+                )
+            }
+            EventMsg::ExecCommandEnd(end) => {
+                let reads = self.state.lock().await.exec_reads.remove(&end.call_id);
+                ["stdout", "stderr", "aggregated_output", "formatted_output"]
+                    .into_iter()
+                    .flat_map(|field| {
+                        fields_for(
+                            reads.as_ref().map(|reads| reads.paths.as_slice()),
+                            &["event", "msg", field],
+                        )
+                    })
+                    .collect()
+            }
+            EventMsg::TaskComplete(_) | EventMsg::TurnAborted(_) => {
+                self.state
+                    .lock()
+                    .await
+                    .exec_reads
+                    .retain(|_, reads| reads.sub_id != event.id);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        ContentField::tag_all(fields).await
+    }
+}
+
+/// Content fields of the `llm_prompt_prepared` payload, whose `input` is
+/// the serialized `input`: the outputs of `read_file` calls and of shell
+/// calls that read files.
+pub(crate) async fn prompt_content(input: &[ResponseItem], cwd: &Path) -> Vec<ContentField> {
+    let reads: Vec<(&str, Vec<PathBuf>)> = input
+        .iter()
+        .filter_map(|item| call_reads(item, cwd))
+        .filter(|(_, paths)| !paths.is_empty())
+        .collect();
+    let fields = input
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| match item {
+            ResponseItem::FunctionCallOutput { call_id, .. } => reads
+                .iter()
+                .find(|(read_call_id, _)| read_call_id == call_id)
+                .map(|(_, paths)| (index, paths)),
+            _ => None,
+        })
+        .flat_map(|(index, paths)| {
+            fields_for(
+                Some(paths.as_slice()),
+                &["input", index.to_string().as_str(), "output"],
+            )
+        })
+        .collect();
+    ContentField::tag_all(fields).await
+}
+
+/// The call id of `item` and the files its output holds, if it is a call
+/// that reads files.
+fn call_reads<'a>(item: &'a ResponseItem, cwd: &Path) -> Option<(&'a str, Vec<PathBuf>)> {
+    match item {
+        ResponseItem::FunctionCall {
+            name,
+            arguments,
+            call_id,
+            ..
+        } if name == "read_file" => {
+            let arguments: serde_json::Value = serde_json::from_str(arguments).ok()?;
+            let path = cwd.join(arguments.get("file_path")?.as_str()?);
+            Some((call_id.as_str(), vec![path]))
+        }
+        ResponseItem::FunctionCall {
+            name,
+            arguments,
+            call_id,
+            ..
+        } if name == "shell" || name == "container.exec" => {
+            let params: ShellToolCallParams = serde_json::from_str(arguments).ok()?;
+            let cwd = cwd.join(params.workdir.unwrap_or_default());
+            Some((call_id.as_str(), command_reads(&params.command, &cwd)))
+        }
+        ResponseItem::LocalShellCall {
+            id,
+            call_id,
+            action: LocalShellAction::Exec(action),
+            ..
+        } => {
+            let call_id = call_id.as_deref().or(id.as_deref())?;
+            let cwd = cwd.join(action.working_directory.as_deref().unwrap_or_default());
+            Some((call_id, command_reads(&action.command, &cwd)))
+        }
+        _ => None,
+    }
+}
+
+fn command_reads(command: &[String], cwd: &Path) -> Vec<PathBuf> {
+    let parsed: Vec<ParsedCommand> = parse_command(command).into_iter().map(Into::into).collect();
+    read_paths(&parsed, cwd)
+}
+
+fn patch_content<'a>(
+    changes: impl IntoIterator<Item = (&'a PathBuf, &'a FileChange)>,
+) -> Vec<(Vec<String>, PathBuf)> {
+    changes
+        .into_iter()
+        .map(|(path, change)| {
+            let key = path.to_string_lossy();
+            let body: &[&str] = match change {
+                FileChange::Add { .. } => &["add", "content"],
+                FileChange::Delete { .. } => &["delete", "content"],
+                FileChange::Update { .. } => &["update", "unified_diff"],
+            };
+            let segments = ["event", "msg", "changes", key.as_ref()]
+                .into_iter()
+                .chain(body.iter().copied())
+                .map(str::to_string)
+                .collect();
+            (segments, path.clone())
+        })
+        .collect()
+}
+
+fn fields_for(paths: Option<&[PathBuf]>, segments: &[&str]) -> Vec<(Vec<String>, PathBuf)> {
+    paths
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            let segments = segments.iter().copied().map(str::to_string).collect();
+            (segments, path.clone())
+        })
+        .collect()
+}
+
+/// Files read by the `Read` parts of a command, resolved against `cwd`.
+/// Every non-flag argument counts, so e.g. the `1,10p` of `sed -n 1,10p`
+/// is taken for a file in `cwd`; that never makes content omitted.
+fn read_paths(parsed: &[ParsedCommand], cwd: &Path) -> Vec<PathBuf> {
+    parsed
+        .iter()
+        .filter_map(|command| match command {
+            ParsedCommand::Read { cmd, .. } => shlex::split(cmd),
+            _ => None,
+        })
+        .flat_map(|argv| argv.into_iter().skip(1))
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| match arg.strip_prefix("~/").zip(dirs::home_dir()) {
+            Some((rest, home)) => home.join(rest),
+            None => cwd.join(&arg),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+
+    use super::*;
+    use crate::codex::make_session_and_context;
+    use crate::protocol::ExecCommandBeginEvent;
+    use crate::protocol::PatchApplyBeginEvent;
+    use crate::protocol::TaskCompleteEvent;
+    use codex_protocol::models::FunctionCallOutputPayload;
+
+    #[tokio::test]
+    async fn patch_pointers_address_the_serialized_bodies() {
+        let changes = HashMap::from([
+            (
+                PathBuf::from("/repo/src/new.rs"),
+                FileChange::Add {
+                    content: "fn main() {}\n".to_string(),
+                },
+            ),
+            (
+                PathBuf::from("/etc/hosts"),
+                FileChange::Update {
+                    unified_diff: "+127.0.0.1 relay\n".to_string(),
+                    move_path: None,
+                },
+            ),
+        ]);
+        let event = Event {
+            id: "sub-1".to_string(),
+            msg: EventMsg::PatchApplyBegin(PatchApplyBeginEvent {
+                call_id: "call-1".to_string(),
+                auto_approved: true,
+                changes: changes.clone(),
+            }),
+        };
+        let payload = json!({ "event": event });
+
+        let mut bodies: Vec<Value> = ContentField::tag_all(patch_content(&changes))
+            .await
+            .iter()
+            .map(|field| payload.pointer(&field.pointer).cloned().unwrap_or_default())
+            .collect();
+        bodies.sort_by_key(Value::to_string);
+        assert_eq!(
+            bodies,
+            vec![json!("+127.0.0.1 relay\n"), json!("fn main() {}\n")]
+        );
+    }
+
+    #[test]
+    fn read_paths_take_file_arguments_of_read_commands_only() {
+        let parsed = vec![
+            ParsedCommand::Read {
+                cmd: "head -5 /etc/hosts".to_string(),
+                name: "hosts".to_string(),
+            },
+            ParsedCommand::Read {
+                cmd: "cat ../notes.txt".to_string(),
+                name: "notes.txt".to_string(),
+            },
+            ParsedCommand::ListFiles {
+                cmd: "ls /secret".to_string(),
+                path: Some("/secret".to_string()),
+            },
+        ];
+
+        assert_eq!(
+            read_paths(&parsed, Path::new("/repo")),
+            vec![
+                PathBuf::from("/etc/hosts"),
+                PathBuf::from("/repo/../notes.txt"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn shell_reads_in_prompts_are_tagged_with_their_files() {
+        let call = |call_id: &str, arguments: serde_json::Value| ResponseItem::FunctionCall {
+            id: None,
+            name: "shell".to_string(),
+            arguments: arguments.to_string(),
+            call_id: call_id.to_string(),
+        };
+        let output = |call_id: &str| ResponseItem::FunctionCallOutput {
+            call_id: call_id.to_string(),
+            output: FunctionCallOutputPayload {
+                content: "contents".to_string(),
+                success: Some(true),
+            },
+        };
+        let input = vec![
+            call("call-cat", json!({ "command": ["cat", "/etc/hosts"] })),
+            output("call-cat"),
+            call("call-ls", json!({ "command": ["ls"], "workdir": "/repo" })),
+            output("call-ls"),
+        ];
+
+        let fields = prompt_content(&input, Path::new("/repo")).await;
+        assert_eq!(
+            fields
+                .iter()
+                .map(|field| field.pointer.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/input/1/output"]
+        );
+        assert!(fields[0].path.ends_with("hosts"));
+    }
+
+    #[tokio::test]
+    async fn reads_of_execs_that_never_end_are_dropped_with_their_task() {
+        let (session, _turn_context) = make_session_and_context();
+        let begin = |sub_id: &str, call_id: &str| Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::ExecCommandBegin(ExecCommandBeginEvent {
+                call_id: call_id.to_string(),
+                command: vec!["cat".to_string(), "notes.txt".to_string()],
+                cwd: PathBuf::from("/repo"),
+                parsed_cmd: vec![ParsedCommand::Read {
+                    cmd: "cat notes.txt".to_string(),
+                    name: "notes.txt".to_string(),
+                }],
+            }),
+        };
+        session
+            .protocol_event_content(&begin("sub-1", "call-1"))
+            .await;
+        session
+            .protocol_event_content(&begin("sub-2", "call-2"))
+            .await;
+
+        session
+            .protocol_event_content(&Event {
+                id: "sub-1".to_string(),
+                msg: EventMsg::TaskComplete(TaskCompleteEvent {
+                    last_agent_message: None,
+                    error: None,
+                }),
+            })
+            .await;
+
+        let state = session.state.lock().await;
+        let mut running: Vec<&str> = state.exec_reads.keys().map(String::as_str).collect();
+        running.sort();
+        assert_eq!(running, vec!["call-2"]);
+    }
+}
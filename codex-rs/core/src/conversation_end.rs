@@ -0,0 +1,152 @@
+//! The final `conversation_ended` visualizer event and its
+//! `EventMsg::ConversationEnded` counterpart, so stream consumers and
+//! clients can tell a clean exit from a crash.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+use serde_json::json;
+
+use crate::codex::INITIAL_SUBMIT_ID;
+use crate::codex::Session;
+use crate::protocol::ConversationEndReason;
+use crate::protocol::ConversationEndedEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::state::SessionState;
+
+fn conversation_ended_payload(
+    reason: &ConversationEndReason,
+    state: Option<&SessionState>,
+) -> Value {
+    let mut payload = json!({ "reason": reason });
+    if let Some(state) = state {
+        payload["lastSubId"] = json!(state.last_task_sub_id);
+        payload["totals"] = json!({
+            "tasksStarted": state.tasks_started,
+            "historyItems": state.history.len(),
+            "totalTokens": state
+                .token_info
+                .as_ref()
+                .map(|info| info.total_token_usage.total_tokens),
+            "firstResponseLatency": state.first_response_latency,
+        });
+    }
+    payload
+}
+
+fn conversation_ended_event(reason: ConversationEndReason, state: Option<&SessionState>) -> Event {
+    let event = ConversationEndedEvent {
+        reason,
+        last_sub_id: state.and_then(|state| state.last_task_sub_id.clone()),
+        tasks_started: state.map_or(0, |state| state.tasks_started),
+        total_tokens: state
+            .and_then(|state| state.token_info.as_ref())
+            .map(|info| info.total_token_usage.total_tokens),
+    };
+    Event {
+        id: INITIAL_SUBMIT_ID.to_owned(),
+        msg: EventMsg::ConversationEnded(event),
+    }
+}
+
+/// Emits `conversation_ended` and `EventMsg::ConversationEnded` exactly
+/// once for the session loop that owns it. [`Session::shutdown`] calls
+/// [`Self::end`] on every orderly exit; if the guard is dropped without
+/// that, most likely because the loop panicked, it makes a best-effort
+/// emission classified as an error.
+pub(crate) struct ConversationEndGuard {
+    sess: Option<Arc<Session>>,
+}
+
+impl ConversationEndGuard {
+    pub(crate) fn new(sess: Arc<Session>) -> Self {
+        Self { sess: Some(sess) }
+    }
+
+    /// Send `EventMsg::ConversationEnded` and queue `conversation_ended`
+    /// after it, so the latter stays the last visualizer event before the
+    /// shutdown report. Its delivery is confirmed later, by the shutdown's
+    /// visualizer flush.
+    pub(crate) async fn end(mut self, reason: ConversationEndReason) {
+        let Some(sess) = self.sess.take() else {
+            return;
+        };
+        let (payload, event) = {
+            let state = sess.state.lock().await;
+            (
+                conversation_ended_payload(&reason, Some(&state)),
+                conversation_ended_event(reason, Some(&state)),
+            )
+        };
+        sess.send_event(event).await;
+        sess.visualizer.enqueue("conversation_ended", payload);
+    }
+}
+
+impl Drop for ConversationEndGuard {
+    fn drop(&mut self) {
+        let Some(sess) = self.sess.take() else {
+            return;
+        };
+        let summary = if std::thread::panicking() {
+            "session loop panicked"
+        } else {
+            "session loop stopped without ending the conversation"
+        };
+        let reason = ConversationEndReason::Error {
+            summary: summary.to_string(),
+        };
+        let (payload, event) = match sess.state.try_lock() {
+            Ok(state) => (
+                conversation_ended_payload(&reason, Some(&state)),
+                conversation_ended_event(reason, Some(&state)),
+            ),
+            Err(_) => (
+                conversation_ended_payload(&reason, None),
+                conversation_ended_event(reason, None),
+            ),
+        };
+        sess.visualizer.enqueue("conversation_ended", payload);
+        // Sending is async; without a runtime to run it on, clients only see
+        // the event stream close.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move { sess.send_event(event).await });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::FirstResponseHistogram;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn payload_classifies_reason_and_reports_totals() {
+        let mut state = SessionState::new();
+        state.tasks_started = 2;
+        state.last_task_sub_id = Some("sub-2".to_string());
+
+        let reason = ConversationEndReason::Error {
+            summary: "boom".to_string(),
+        };
+        assert_eq!(
+            conversation_ended_payload(&reason, Some(&state)),
+            json!({
+                "reason": { "kind": "error", "summary": "boom" },
+                "lastSubId": "sub-2",
+                "totals": {
+                    "tasksStarted": 2,
+                    "historyItems": 0,
+                    "totalTokens": null,
+                    "firstResponseLatency": FirstResponseHistogram::default(),
+                },
+            })
+        );
+        assert_eq!(
+            conversation_ended_payload(&ConversationEndReason::UserExit, None),
+            json!({ "reason": { "kind": "user_exit" } })
+        );
+    }
+}
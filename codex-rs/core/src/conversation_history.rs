@@ -1,15 +1,20 @@
 use codex_protocol::models::ResponseItem;
+use sha1::Digest;
+use sha1::Sha1;
 
 /// Transcript of conversation history
 #[derive(Debug, Clone, Default)]
 pub(crate) struct ConversationHistory {
     /// The oldest items are at the beginning of the vector.
     items: Vec<ResponseItem>,
+    /// `hashes[i]` chains the hash of `items[..=i]`, so hashing the history
+    /// only ever hashes the items appended since.
+    hashes: Vec<[u8; 20]>,
 }
 
 impl ConversationHistory {
     pub(crate) fn new() -> Self {
-        Self { items: Vec::new() }
+        Self::default()
     }
 
     /// Returns a clone of the contents in the transcript.
@@ -32,12 +37,36 @@ impl ConversationHistory {
                 continue;
             }
 
-            self.items.push(item.clone());
+            self.push(item.clone());
         }
     }
 
     pub(crate) fn replace(&mut self, items: Vec<ResponseItem>) {
-        self.items = items;
+        self.items.clear();
+        self.hashes.clear();
+        for item in items {
+            self.push(item);
+        }
+    }
+
+    /// Drop every item after the first `len`.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.items.truncate(len);
+        self.hashes.truncate(len);
+    }
+
+    /// Hex SHA-1 identifying the current contents.
+    pub(crate) fn hash(&self) -> String {
+        let hash = self.hashes.last().copied().unwrap_or_default();
+        hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn push(&mut self, item: ResponseItem) {
+        let mut hasher = Sha1::new();
+        hasher.update(self.hashes.last().copied().unwrap_or_default());
+        hasher.update(serde_json::to_vec(&item).unwrap_or_default());
+        self.hashes.push(hasher.finalize().into());
+        self.items.push(item);
     }
 }
 
@@ -121,4 +150,23 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn hash_follows_the_contents() {
+        let mut h = ConversationHistory::new();
+        let empty = h.hash();
+        h.record_items([&user_msg("hi")]);
+        let after_hi = h.hash();
+        h.record_items([&assistant_msg("hello")]);
+        assert_ne!(h.hash(), after_hi);
+
+        let mut replaced = ConversationHistory::new();
+        replaced.replace(h.contents());
+        assert_eq!(replaced.hash(), h.hash());
+
+        h.truncate(1);
+        assert_eq!(h.hash(), after_hi);
+        h.truncate(0);
+        assert_eq!(h.hash(), empty);
+    }
 }
@@ -0,0 +1,585 @@
+//! One running session per conversation (`conversation_lease_conflict`).
+//!
+//! A session writes a lease file next to its rollout naming its process and
+//! producer, and refreshes the heartbeat in it every [`HEARTBEAT_INTERVAL`].
+//! A session resuming that rollout while the lease is fresh emits a
+//! `conversation_lease_conflict` warning and then either refuses to start
+//! (the default) or, with `fork`, starts without the lease and with a
+//! suffix on its conversation id in visualizer events, so consumers keep the
+//! two streams apart. A lease without a heartbeat for [`STALE_AFTER`] was
+//! left by a session that died; the next session takes it over. So does a
+//! session of the same process, which resumes a conversation only after
+//! the caller decided to. Either takeover is reported as
+//! `conversation_lease_reclaimed`.
+//!
+//! Every read and replacement of the lease happens under a lock on a file
+//! beside it, so two sessions cannot both take the same lease.
+
+use std::fmt;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use codex_protocol::ConversationId;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use tokio::task::AbortHandle;
+use tracing::debug;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config_types::ConversationLeaseConflict;
+use crate::visualizer::AgentVisualizer;
+use crate::visualizer::now_ms;
+
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+pub(crate) const STALE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LeaseRecord {
+    pub(crate) pid: u32,
+    pub(crate) producer_id: String,
+    pub(crate) acquired_at_ms: u128,
+    pub(crate) heartbeat_ms: u128,
+}
+
+impl LeaseRecord {
+    fn is_stale(&self, now_ms: u128) -> bool {
+        now_ms.saturating_sub(self.heartbeat_ms) > STALE_AFTER.as_millis()
+    }
+}
+
+/// The conversation is leased to another running session and
+/// `conversation_lease_conflict` is `refuse`.
+#[derive(Debug)]
+pub(crate) struct LeaseHeld {
+    pub(crate) holder: LeaseRecord,
+}
+
+impl fmt::Display for LeaseHeld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "this conversation is already open in another session (pid {}); close it, or set `conversation_lease_conflict = \"fork\"` to continue anyway",
+            self.holder.pid
+        )
+    }
+}
+
+impl std::error::Error for LeaseHeld {}
+
+/// A held lease. Dropping it stops the heartbeat and removes the lease
+/// file, unless another session has taken it over meanwhile.
+pub(crate) struct ConversationLease {
+    path: PathBuf,
+    producer_id: String,
+    heartbeat: AbortHandle,
+}
+
+impl Drop for ConversationLease {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        if let Err(err) = release(&self.path, &self.producer_id) {
+            debug!("failed to remove conversation lease: {err}");
+        }
+    }
+}
+
+/// Lease the conversation recorded at `rollout_path` for a starting
+/// session, applying `policy` if a live session holds it already. Returns
+/// the lease, if taken, and the visualizer the session should use.
+pub(crate) async fn claim(
+    rollout_path: &Path,
+    conversation_id: ConversationId,
+    policy: ConversationLeaseConflict,
+    visualizer: AgentVisualizer,
+) -> anyhow::Result<(Option<ConversationLease>, AgentVisualizer)> {
+    claim_as(
+        std::process::id(),
+        rollout_path,
+        conversation_id,
+        policy,
+        visualizer,
+    )
+    .await
+}
+
+/// [`claim`] on behalf of process `pid`.
+async fn claim_as(
+    pid: u32,
+    rollout_path: &Path,
+    conversation_id: ConversationId,
+    policy: ConversationLeaseConflict,
+    visualizer: AgentVisualizer,
+) -> anyhow::Result<(Option<ConversationLease>, AgentVisualizer)> {
+    let path = lease_path(rollout_path);
+    let producer_id = Uuid::new_v4().to_string();
+    let acquired = {
+        let path = path.clone();
+        let producer_id = producer_id.clone();
+        tokio::task::spawn_blocking(move || acquire(&path, pid, &producer_id, now_ms()))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)))
+    };
+    let held = match acquired {
+        Ok(Acquired::Fresh) => None,
+        Ok(Acquired::Reclaimed { previous, reason }) => {
+            if reason == ReclaimReason::SameProcess {
+                warn!(
+                    "taking over conversation lease {} from another session of this process",
+                    path.display()
+                );
+            }
+            visualizer
+                .emit(
+                    Some(conversation_id),
+                    "conversation_lease_reclaimed",
+                    json!({
+                        "stalePid": previous.pid,
+                        "staleProducerId": previous.producer_id,
+                        "staleHeartbeatMs": previous.heartbeat_ms,
+                        "reason": reason.as_str(),
+                    }),
+                    None,
+                )
+                .await;
+            None
+        }
+        Ok(Acquired::Held(holder)) => Some(holder),
+        Err(err) => {
+            // The lease only guards telemetry; never block a session on it.
+            warn!(
+                "failed to write conversation lease {}: {err}",
+                path.display()
+            );
+            return Ok((None, visualizer));
+        }
+    };
+
+    let Some(holder) = held else {
+        let heartbeat = tokio::spawn(heartbeat(path.clone(), producer_id.clone()));
+        let lease = ConversationLease {
+            path,
+            producer_id,
+            heartbeat: heartbeat.abort_handle(),
+        };
+        return Ok((Some(lease), visualizer));
+    };
+
+    let (visualizer, fork_suffix) = match policy {
+        ConversationLeaseConflict::Refuse => (visualizer, None),
+        ConversationLeaseConflict::Fork => {
+            let suffix = format!("~fork-{}", &producer_id[..8]);
+            (visualizer.with_fork_suffix(suffix.clone()), Some(suffix))
+        }
+    };
+    visualizer
+        .emit(
+            Some(conversation_id),
+            "conversation_lease_conflict",
+            json!({
+                "holderPid": holder.pid,
+                "holderProducerId": holder.producer_id,
+                "holderHeartbeatMs": holder.heartbeat_ms,
+                "policy": match policy {
+                    ConversationLeaseConflict::Refuse => "refuse",
+                    ConversationLeaseConflict::Fork => "fork",
+                },
+                "forkSuffix": fork_suffix,
+            }),
+            None,
+        )
+        .await;
+    match policy {
+        ConversationLeaseConflict::Refuse => Err(LeaseHeld { holder }.into()),
+        ConversationLeaseConflict::Fork => Ok((None, visualizer)),
+    }
+}
+
+fn lease_path(rollout_path: &Path) -> PathBuf {
+    let mut path = rollout_path.as_os_str().to_owned();
+    path.push(".lease");
+    PathBuf::from(path)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Acquired {
+    Fresh,
+    Reclaimed {
+        previous: LeaseRecord,
+        reason: ReclaimReason,
+    },
+    Held(LeaseRecord),
+}
+
+/// Why a lease held by another session was taken over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReclaimReason {
+    /// Its heartbeat stopped.
+    Stale,
+    /// It belongs to a session of the same process.
+    SameProcess,
+}
+
+impl ReclaimReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReclaimReason::Stale => "stale",
+            ReclaimReason::SameProcess => "sameProcess",
+        }
+    }
+}
+
+fn acquire(path: &Path, pid: u32, producer_id: &str, now_ms: u128) -> io::Result<Acquired> {
+    let record = LeaseRecord {
+        pid,
+        producer_id: producer_id.to_string(),
+        acquired_at_ms: now_ms,
+        heartbeat_ms: now_ms,
+    };
+    let contents = serde_json::to_vec(&record)?;
+    let _lock = lock(path)?;
+    let acquired = match read(path) {
+        Ok(None) => Acquired::Fresh,
+        Ok(Some(previous)) if previous.is_stale(now_ms) => Acquired::Reclaimed {
+            previous,
+            reason: ReclaimReason::Stale,
+        },
+        Ok(Some(previous)) if previous.pid == pid => Acquired::Reclaimed {
+            previous,
+            reason: ReclaimReason::SameProcess,
+        },
+        Ok(Some(holder)) => return Ok(Acquired::Held(holder)),
+        // An unreadable lease is as good as a stale one.
+        Err(err) => {
+            debug!("replacing unreadable conversation lease: {err}");
+            Acquired::Fresh
+        }
+    };
+    write(path, &contents)?;
+    Ok(acquired)
+}
+
+/// Remove the lease at `path` if it is still `producer_id`'s.
+fn release(path: &Path, producer_id: &str) -> io::Result<()> {
+    let _lock = lock(path)?;
+    if read(path)?.is_some_and(|record| record.producer_id == producer_id) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Refresh the heartbeat of the lease at `path`. Returns `false` if the
+/// lease is no longer `producer_id`'s.
+fn refresh(path: &Path, producer_id: &str) -> io::Result<bool> {
+    let _lock = lock(path)?;
+    let Some(mut record) = read(path)?.filter(|record| record.producer_id == producer_id) else {
+        return Ok(false);
+    };
+    record.heartbeat_ms = now_ms();
+    write(path, &serde_json::to_vec(&record)?)?;
+    Ok(true)
+}
+
+/// Take the lock guarding the lease at `path`, waiting for other sessions
+/// to release it; it is held until the returned file is dropped.
+fn lock(path: &Path) -> io::Result<fs::File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// The lease at `path`, or `None` if there is none.
+fn read(path: &Path) -> io::Result<Option<LeaseRecord>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_data()?;
+    fs::rename(&tmp, path)
+}
+
+/// Refresh the heartbeat until the task is aborted or the lease is no
+/// longer ours.
+async fn heartbeat(path: PathBuf, producer_id: String) {
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        let refreshed = {
+            let path = path.clone();
+            let producer_id = producer_id.clone();
+            tokio::task::spawn_blocking(move || refresh(&path, &producer_id))
+                .await
+                .unwrap_or_else(|err| Err(io::Error::other(err)))
+        };
+        match refreshed {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("conversation lease {} was taken over", path.display());
+                return;
+            }
+            Err(err) => {
+                warn!(
+                    "failed to refresh conversation lease {}: {err}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Two processes resuming the same rollout.
+    const FIRST_PID: u32 = 1001;
+    const SECOND_PID: u32 = 1002;
+
+    fn rollout(dir: &tempfile::TempDir) -> PathBuf {
+        dir.path()
+            .join("rollout-2025-01-01T00-00-00-conversation.jsonl")
+    }
+
+    async fn start(
+        pid: u32,
+        dir: &tempfile::TempDir,
+        conversation_id: ConversationId,
+        policy: ConversationLeaseConflict,
+    ) -> anyhow::Result<(Option<ConversationLease>, AgentVisualizer)> {
+        claim_as(
+            pid,
+            &rollout(dir),
+            conversation_id,
+            policy,
+            AgentVisualizer::default(),
+        )
+        .await
+    }
+
+    /// Wire `conversationId` and action of the last event of `action_type`.
+    fn last_event(visualizer: &AgentVisualizer, action_type: &str) -> Option<(Value, Value)> {
+        let event = visualizer
+            .recent_events()
+            .into_iter()
+            .rfind(|event| event.action_type == action_type)?;
+        let wire = serde_json::to_value(&event).ok()?;
+        Some((wire["conversationId"].clone(), event.action))
+    }
+
+    #[tokio::test]
+    async fn second_process_is_refused_while_the_lease_is_fresh() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let conversation_id = ConversationId::new();
+        let refuse = ConversationLeaseConflict::Refuse;
+        let (first, _) = start(FIRST_PID, &dir, conversation_id, refuse)
+            .await
+            .expect("first session starts");
+        assert!(first.is_some());
+
+        let visualizer = AgentVisualizer::default();
+        let refused = claim_as(
+            SECOND_PID,
+            &rollout(&dir),
+            conversation_id,
+            refuse,
+            visualizer.clone(),
+        )
+        .await;
+        let holder = refused
+            .err()
+            .and_then(|err| err.downcast::<LeaseHeld>().ok())
+            .map(|held| held.holder.pid);
+        assert_eq!(holder, Some(FIRST_PID));
+        let warning = last_event(&visualizer, "conversation_lease_conflict")
+            .map(|(_, action)| (action["policy"].clone(), action["forkSuffix"].clone()));
+        assert_eq!(warning, Some((json!("refuse"), Value::Null)));
+
+        // Ending the first session frees the conversation.
+        drop(first);
+        let (next, _) = start(SECOND_PID, &dir, conversation_id, refuse)
+            .await
+            .expect("session starts once the lease is released");
+        assert!(next.is_some());
+    }
+
+    #[tokio::test]
+    async fn forked_process_reports_a_distinct_conversation_identity() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let conversation_id = ConversationId::new();
+        let (_first, first_visualizer) = start(
+            FIRST_PID,
+            &dir,
+            conversation_id,
+            ConversationLeaseConflict::Refuse,
+        )
+        .await
+        .expect("first session starts");
+        let (lease, fork_visualizer) = start(
+            SECOND_PID,
+            &dir,
+            conversation_id,
+            ConversationLeaseConflict::Fork,
+        )
+        .await
+        .expect("forked session starts");
+        assert!(lease.is_none());
+
+        for visualizer in [&first_visualizer, &fork_visualizer] {
+            visualizer
+                .emit(Some(conversation_id), "task_started", json!({}), None)
+                .await;
+        }
+        let first_identity = last_event(&first_visualizer, "task_started").map(|(id, _)| id);
+        assert_eq!(first_identity, Some(json!(conversation_id.to_string())));
+
+        let (warning_identity, warning) =
+            last_event(&fork_visualizer, "conversation_lease_conflict").expect("warning");
+        let suffix = warning["forkSuffix"]
+            .as_str()
+            .expect("fork suffix")
+            .to_string();
+        assert!(suffix.starts_with("~fork-"));
+        let forked = json!(format!("{conversation_id}{suffix}"));
+        assert_eq!(warning_identity, forked);
+        let task_identity = last_event(&fork_visualizer, "task_started").map(|(id, _)| id);
+        assert_eq!(task_identity, Some(forked));
+    }
+
+    #[tokio::test]
+    async fn stale_lease_is_reclaimed() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let conversation_id = ConversationId::new();
+        let stale_at = now_ms() - STALE_AFTER.as_millis() - 1;
+        let stale = LeaseRecord {
+            pid: FIRST_PID,
+            producer_id: "crashed".to_string(),
+            acquired_at_ms: stale_at,
+            heartbeat_ms: stale_at,
+        };
+        let path = lease_path(&rollout(&dir));
+        write(&path, &serde_json::to_vec(&stale).expect("serialize")).expect("write lease");
+
+        let (lease, visualizer) = start(
+            SECOND_PID,
+            &dir,
+            conversation_id,
+            ConversationLeaseConflict::Refuse,
+        )
+        .await
+        .expect("stale lease does not block");
+        let lease = lease.expect("lease taken over");
+        let holder = read(&path)
+            .ok()
+            .flatten()
+            .map(|record| (record.pid, record.producer_id));
+        assert_eq!(holder, Some((SECOND_PID, lease.producer_id.clone())));
+        let reclaimed = last_event(&visualizer, "conversation_lease_reclaimed")
+            .map(|(_, action)| (action["staleProducerId"].clone(), action["reason"].clone()));
+        assert_eq!(reclaimed, Some((json!("crashed"), json!("stale"))));
+
+        drop(lease);
+        assert_eq!(read(&path).ok(), Some(None));
+    }
+
+    #[tokio::test]
+    async fn sessions_of_one_process_do_not_conflict() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let conversation_id = ConversationId::new();
+        let refuse = ConversationLeaseConflict::Refuse;
+        let (first, _) = start(FIRST_PID, &dir, conversation_id, refuse)
+            .await
+            .expect("first session starts");
+        let (second, visualizer) = start(FIRST_PID, &dir, conversation_id, refuse)
+            .await
+            .expect("same process resumes");
+        assert!(second.is_some());
+        let reclaimed = last_event(&visualizer, "conversation_lease_reclaimed")
+            .map(|(_, action)| (action["staleProducerId"].clone(), action["reason"].clone()));
+        assert_eq!(
+            reclaimed,
+            Some((
+                json!(first.as_ref().map(|lease| lease.producer_id.clone())),
+                json!("sameProcess")
+            ))
+        );
+
+        // The first session no longer holds the lease, so ending it leaves
+        // the second one's in place.
+        drop(first);
+        let holder = read(&lease_path(&rollout(&dir)))
+            .ok()
+            .flatten()
+            .map(|record| record.producer_id);
+        assert_eq!(
+            holder,
+            second.as_ref().map(|lease| lease.producer_id.clone())
+        );
+    }
+
+    #[test]
+    fn a_stale_lease_is_reclaimed_by_one_session_only() {
+        const SESSIONS: u32 = 8;
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = lease_path(&rollout(&dir));
+        let stale_at = now_ms() - STALE_AFTER.as_millis() - 1;
+        let stale = LeaseRecord {
+            pid: FIRST_PID,
+            producer_id: "crashed".to_string(),
+            acquired_at_ms: stale_at,
+            heartbeat_ms: stale_at,
+        };
+        write(&path, &serde_json::to_vec(&stale).expect("serialize")).expect("write lease");
+
+        let barrier = std::sync::Barrier::new(SESSIONS as usize);
+        let outcomes: Vec<Acquired> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..SESSIONS)
+                .map(|n| {
+                    let (path, barrier) = (&path, &barrier);
+                    scope.spawn(move || {
+                        barrier.wait();
+                        acquire(path, SECOND_PID + n, &format!("session-{n}"), now_ms())
+                            .expect("acquire")
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("session thread"))
+                .collect()
+        });
+
+        let reclaimed = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, Acquired::Reclaimed { .. }))
+            .count();
+        let held = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, Acquired::Held(_)))
+            .count();
+        assert_eq!((reclaimed, held), (1, SESSIONS as usize - 1));
+    }
+}
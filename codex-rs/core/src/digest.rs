@@ -0,0 +1,904 @@
+//! "What changed since my last look" digests for `Op::GetDigest`.
+//!
+//! Completed turns, errors, and annotations are kept in a bounded activity log, each
+//! stamped with its position in the visualizer stream (how many events had
+//! been emitted when it happened) and its wall-clock time, so a client can
+//! use either as its cursor. Every completed turn also checkpoints the
+//! session's token total; the tokens spent after a cursor are the current
+//! total minus the last checkpoint before it.
+//!
+//! Only the most recent tasks are kept in full. Whenever the session goes
+//! idle, older completed and aborted tasks are folded, a batch at a time,
+//! into per-hour aggregates, which digests report alongside the full turns.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::codex::Session;
+use crate::protocol::Annotation;
+use crate::protocol::DigestError;
+use crate::protocol::DigestEvent;
+use crate::protocol::DigestFocus;
+use crate::protocol::DigestHourAggregate;
+use crate::protocol::DigestTask;
+use crate::protocol::DigestTurn;
+use crate::protocol::ErrorEvent;
+use crate::protocol::EventMsg;
+use crate::protocol::TaskError;
+use crate::protocol::TokenUsage;
+use crate::protocol::TurnTemplateUse;
+use crate::state::TaskKind;
+use crate::state::TaskStatus;
+use crate::visualizer::now_ms;
+
+/// Beyond the retained tasks, oldest activity is discarded past this many
+/// records; digests reaching further back are reported as incomplete.
+const MAX_ACTIVITY_RECORDS: usize = 1024;
+
+/// Oldest hourly aggregates, a month's worth, are discarded beyond this.
+const MAX_HOUR_AGGREGATES: usize = 24 * 30;
+
+/// Tasks folded into aggregates per step of idle eviction; the state lock
+/// is released between steps.
+const EVICTION_BATCH: usize = 64;
+
+const HOUR_MS: u128 = 60 * 60 * 1000;
+
+/// Tasks kept in full unless `digest_retained_turns` says otherwise.
+pub(crate) const DEFAULT_RETAINED_TURNS: usize = 512;
+
+/// Where a digest starts. Unset fields do not restrict it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DigestSince {
+    pub(crate) sequence: Option<u64>,
+    pub(crate) time_ms: Option<u128>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    /// Visualizer events emitted before this point.
+    emitted: u64,
+    time_ms: u128,
+}
+
+impl Position {
+    /// Whether this point comes after everything the client has seen: after
+    /// the event numbered `since.sequence` and after `since.time_ms`.
+    fn after(&self, since: &DigestSince) -> bool {
+        since.sequence.is_none_or(|seen| self.emitted > seen)
+            && since.time_ms.is_none_or(|seen| self.time_ms > seen)
+    }
+}
+
+/// What every finished task, completed or aborted, contributes to its
+/// hour's aggregate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaskOutcome {
+    pub(crate) kind: TaskKind,
+    /// From spawn to completion or abort.
+    pub(crate) duration: Duration,
+    /// Label of the focus the task ran under.
+    pub(crate) focus: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Activity {
+    Turn {
+        turn: DigestTurn,
+        /// Session token total once the turn finished.
+        total_tokens: TokenUsage,
+        outcome: TaskOutcome,
+    },
+    /// Only reported once aggregated.
+    Aborted {
+        sub_id: String,
+        outcome: TaskOutcome,
+    },
+    Error(DigestError),
+    Annotation(Annotation),
+}
+
+impl Activity {
+    fn is_task(&self) -> bool {
+        matches!(self, Activity::Turn { .. } | Activity::Aborted { .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Record {
+    at: Position,
+    activity: Activity,
+}
+
+#[derive(Debug)]
+struct HourAggregate {
+    summary: DigestHourAggregate,
+    /// Position of the newest task folded in.
+    through: Position,
+    /// Session token total after the newest turn folded in.
+    total_tokens: Option<TokenUsage>,
+    sub_ids: HashSet<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ActivityLog {
+    records: VecDeque<Record>,
+    /// Oldest first.
+    aggregates: VecDeque<HourAggregate>,
+    /// Completed and aborted tasks kept in full.
+    retained_tasks: usize,
+    /// Position of the newest discarded record or aggregate.
+    discarded_through: Option<Position>,
+    /// Token checkpoint of the newest discarded turn.
+    discarded_tokens: TokenUsage,
+}
+
+impl Default for ActivityLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETAINED_TURNS)
+    }
+}
+
+impl ActivityLog {
+    pub(crate) fn new(retained_tasks: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            aggregates: VecDeque::new(),
+            retained_tasks,
+            discarded_through: None,
+            discarded_tokens: TokenUsage::default(),
+        }
+    }
+
+    fn push(&mut self, at: Position, activity: Activity) {
+        if self.records.len() >= self.retained_tasks.saturating_add(MAX_ACTIVITY_RECORDS)
+            && let Some(oldest) = self.records.pop_front()
+        {
+            self.fold(oldest);
+        }
+        self.records.push_back(Record { at, activity });
+    }
+
+    /// Fold up to one batch of the oldest tasks beyond the retained ones
+    /// into their hours' aggregates. Returns whether more are left to fold.
+    fn evict(&mut self) -> bool {
+        let mut tasks = self
+            .records
+            .iter()
+            .filter(|record| record.activity.is_task())
+            .count();
+        let mut folded = 0;
+        while tasks > self.retained_tasks {
+            if folded == EVICTION_BATCH {
+                return true;
+            }
+            let Some(oldest) = self
+                .records
+                .iter()
+                .position(|record| record.activity.is_task())
+                .and_then(|index| self.records.remove(index))
+            else {
+                break;
+            };
+            self.fold(oldest);
+            tasks -= 1;
+            folded += 1;
+        }
+        false
+    }
+
+    /// Add a task to its hour's aggregate; anything else is discarded.
+    fn fold(&mut self, record: Record) {
+        let (sub_id, outcome, files_changed, total_tokens) = match record.activity {
+            Activity::Turn {
+                turn,
+                total_tokens,
+                outcome,
+            } => (
+                turn.sub_id,
+                outcome,
+                turn.files_changed.len(),
+                Some(total_tokens),
+            ),
+            // Aborted tasks are the ones without a token checkpoint.
+            Activity::Aborted { sub_id, outcome } => (sub_id, outcome, 0, None),
+            Activity::Error(_) | Activity::Annotation(_) => {
+                self.discarded_through = Some(record.at);
+                return;
+            }
+        };
+        let hour_start_ms = record.at.time_ms - record.at.time_ms % HOUR_MS;
+        if self
+            .aggregates
+            .back()
+            .is_none_or(|aggregate| aggregate.summary.hour_start_ms != hour_start_ms)
+        {
+            if self.aggregates.len() == MAX_HOUR_AGGREGATES
+                && let Some(oldest) = self.aggregates.pop_front()
+            {
+                self.discarded_through = Some(oldest.through);
+                if let Some(total_tokens) = oldest.total_tokens {
+                    self.discarded_tokens = total_tokens;
+                }
+            }
+            self.aggregates.push_back(HourAggregate {
+                summary: DigestHourAggregate {
+                    hour_start_ms,
+                    ..Default::default()
+                },
+                through: record.at,
+                total_tokens: None,
+                sub_ids: HashSet::new(),
+            });
+        }
+        let Some(aggregate) = self.aggregates.back_mut() else {
+            return;
+        };
+        let summary = &mut aggregate.summary;
+        *summary
+            .tasks_by_kind
+            .entry(format!("{:?}", outcome.kind))
+            .or_default() += 1;
+        if total_tokens.is_none() {
+            summary.aborted += 1;
+        }
+        summary.total_duration_ms += outcome.duration.as_millis() as u64;
+        summary.files_changed += files_changed;
+        if let Some(label) = outcome.focus {
+            *summary.tasks_by_focus.entry(label.clone()).or_default() += 1;
+            *summary.files_changed_by_focus.entry(label).or_default() += files_changed;
+        }
+        aggregate.through = record.at;
+        if let Some(total_tokens) = total_tokens {
+            aggregate.total_tokens = Some(total_tokens);
+        }
+        aggregate.sub_ids.insert(sub_id);
+    }
+
+    /// The full turn of the completed task `sub_id`.
+    fn export(&self, sub_id: &str) -> Result<DigestTurn, String> {
+        for record in self.records.iter().rev() {
+            match &record.activity {
+                Activity::Turn { turn, .. } if turn.sub_id == sub_id => return Ok(turn.clone()),
+                Activity::Aborted {
+                    sub_id: aborted, ..
+                } if aborted == sub_id => {
+                    return Err(format!("task `{sub_id}` was aborted and has no turn"));
+                }
+                _ => {}
+            }
+        }
+        match self
+            .aggregates
+            .iter()
+            .find(|aggregate| aggregate.sub_ids.contains(sub_id))
+        {
+            Some(aggregate) => Err(format!(
+                "turn `{sub_id}` is no longer kept in full; it was aggregated into the hour starting at {} ms, reported in the digest's `aggregated_turns`",
+                aggregate.summary.hour_start_ms
+            )),
+            None => Err(format!("no turn `{sub_id}` in this session")),
+        }
+    }
+
+    /// Everything after `since`, with tokens spent measured against
+    /// `total_tokens`. `running_task` and `through_sequence` are left for
+    /// the caller.
+    fn digest(&self, since: &DigestSince, total_tokens: &TokenUsage) -> DigestEvent {
+        let mut baseline = self.discarded_tokens.clone();
+        let mut aggregated_turns = Vec::new();
+        for aggregate in &self.aggregates {
+            if aggregate.through.after(since) {
+                aggregated_turns.push(aggregate.summary.clone());
+            } else if let Some(checkpoint) = &aggregate.total_tokens {
+                baseline = checkpoint.clone();
+            }
+        }
+        let mut turns_completed = Vec::new();
+        let mut errors = Vec::new();
+        let mut annotations = Vec::new();
+        let mut focus_of: HashMap<&str, &str> = HashMap::new();
+        let mut by_focus = FocusPartitions::default();
+        // Checkpoint of the turn before the one at hand, so that each
+        // included turn is charged what the session spent since then.
+        let mut checkpoint = baseline.clone();
+        for record in &self.records {
+            let included = record.at.after(since);
+            match &record.activity {
+                Activity::Turn {
+                    turn,
+                    total_tokens,
+                    outcome,
+                } => {
+                    if let Some(label) = &outcome.focus {
+                        focus_of.insert(&turn.sub_id, label);
+                    }
+                    if included {
+                        if let Some(label) = &outcome.focus {
+                            let entry = by_focus.entry(label);
+                            entry.turns += 1;
+                            entry
+                                .files_changed
+                                .extend(turn.files_changed.iter().cloned());
+                            entry
+                                .tokens_spent
+                                .add_assign(&token_delta(total_tokens, &checkpoint));
+                        }
+                        turns_completed.push(turn.clone());
+                    } else {
+                        baseline = total_tokens.clone();
+                    }
+                    checkpoint = total_tokens.clone();
+                }
+                Activity::Aborted { sub_id, outcome } => {
+                    if let Some(label) = &outcome.focus {
+                        focus_of.insert(sub_id, label);
+                    }
+                }
+                Activity::Error(error) if included => errors.push(error.clone()),
+                Activity::Error(_) => {}
+                Activity::Annotation(annotation) if included => {
+                    annotations.push(annotation.clone());
+                }
+                Activity::Annotation(_) => {}
+            }
+        }
+        let files_changed: BTreeSet<_> = turns_completed
+            .iter()
+            .flat_map(|turn| turn.files_changed.iter().cloned())
+            .collect();
+        // Errors and annotations count towards the focus of their task;
+        // those of tasks not finished yet, or of no task, are left out.
+        for error in &errors {
+            if let Some(label) = focus_of.get(error.sub_id.as_str()) {
+                by_focus.entry(label).errors.push(error.clone());
+            }
+        }
+        for annotation in &annotations {
+            if let Some(label) = annotation
+                .sub_id
+                .as_deref()
+                .and_then(|sub_id| focus_of.get(sub_id))
+            {
+                by_focus.entry(label).annotations.push(annotation.clone());
+            }
+        }
+        let mut by_focus = by_focus.0;
+        for entry in &mut by_focus {
+            entry.files_changed.sort();
+            entry.files_changed.dedup();
+        }
+        DigestEvent {
+            through_sequence: None,
+            complete: self
+                .discarded_through
+                .is_none_or(|discarded| !discarded.after(since)),
+            turns_completed,
+            files_changed: files_changed.into_iter().collect(),
+            tokens_spent: token_delta(total_tokens, &baseline),
+            errors,
+            annotations,
+            running_task: None,
+            by_focus,
+            aggregated_turns,
+        }
+    }
+}
+
+/// `DigestFocus` entries in order of first appearance.
+#[derive(Default)]
+struct FocusPartitions(Vec<DigestFocus>);
+
+impl FocusPartitions {
+    fn entry(&mut self, label: &str) -> &mut DigestFocus {
+        let index = match self.0.iter().position(|entry| entry.focus == label) {
+            Some(index) => index,
+            None => {
+                self.0.push(DigestFocus {
+                    focus: label.to_string(),
+                    turns: 0,
+                    files_changed: Vec::new(),
+                    tokens_spent: TokenUsage::default(),
+                    errors: Vec::new(),
+                    annotations: Vec::new(),
+                });
+                self.0.len() - 1
+            }
+        };
+        &mut self.0[index]
+    }
+}
+
+fn token_delta(total: &TokenUsage, baseline: &TokenUsage) -> TokenUsage {
+    TokenUsage {
+        input_tokens: total.input_tokens.saturating_sub(baseline.input_tokens),
+        cached_input_tokens: total
+            .cached_input_tokens
+            .saturating_sub(baseline.cached_input_tokens),
+        output_tokens: total.output_tokens.saturating_sub(baseline.output_tokens),
+        reasoning_output_tokens: total
+            .reasoning_output_tokens
+            .saturating_sub(baseline.reasoning_output_tokens),
+        total_tokens: total.total_tokens.saturating_sub(baseline.total_tokens),
+    }
+}
+
+impl Session {
+    fn activity_position(&self) -> Position {
+        Position {
+            emitted: self.visualizer.next_sequence(),
+            time_ms: now_ms(),
+        }
+    }
+
+    /// Record a finished task, spawned from `template`, before its
+    /// `TaskComplete` is emitted.
+    pub(crate) async fn record_turn_completed(
+        &self,
+        sub_id: &str,
+        last_agent_message: Option<String>,
+        template: Option<TurnTemplateUse>,
+        outcome: TaskOutcome,
+        error: Option<TaskError>,
+    ) {
+        let at = self.activity_position();
+        let mut state = self.state.lock().await;
+        let mut files_changed: Vec<_> = state
+            .task_writes
+            .iter()
+            .filter(|writes| writes.sub_id() == sub_id)
+            .flat_map(|writes| writes.changed_paths().cloned())
+            .collect();
+        files_changed.sort();
+        files_changed.dedup();
+        let total_tokens = state
+            .token_info
+            .as_ref()
+            .map(|info| info.total_token_usage.clone())
+            .unwrap_or_default();
+        let turn = DigestTurn {
+            sub_id: sub_id.to_string(),
+            completed_at_ms: at.time_ms,
+            last_agent_message,
+            files_changed,
+            error,
+            focus: outcome.focus.clone(),
+            template,
+        };
+        state.activity.push(
+            at,
+            Activity::Turn {
+                turn,
+                total_tokens,
+                outcome,
+            },
+        );
+    }
+
+    /// Record an aborted task, for the aggregates.
+    pub(crate) async fn record_task_aborted(&self, sub_id: &str, outcome: TaskOutcome) {
+        let at = self.activity_position();
+        self.state.lock().await.activity.push(
+            at,
+            Activity::Aborted {
+                sub_id: sub_id.to_string(),
+                outcome,
+            },
+        );
+    }
+
+    /// Fold tasks beyond the retained ones into aggregates, a batch at a
+    /// time, for as long as no task is running.
+    pub(crate) async fn evict_activity_when_idle(&self) {
+        loop {
+            if self.active_turn.lock().await.is_some() {
+                return;
+            }
+            if !self.state.lock().await.activity.evict() {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Handle `Op::ExportTurn`, returning the reply.
+    pub(crate) async fn export_turn(&self, sub_id: &str) -> EventMsg {
+        match self.state.lock().await.activity.export(sub_id) {
+            Ok(turn) => EventMsg::TurnExported(turn),
+            Err(message) => EventMsg::Error(ErrorEvent { message }),
+        }
+    }
+
+    pub(crate) async fn record_error(&self, sub_id: &str, message: &str) {
+        let at = self.activity_position();
+        let error = DigestError {
+            sub_id: sub_id.to_string(),
+            at_ms: at.time_ms,
+            message: message.to_string(),
+        };
+        self.state
+            .lock()
+            .await
+            .activity
+            .push(at, Activity::Error(error));
+    }
+
+    /// Record an annotation added with `Op::Annotate`.
+    pub(crate) async fn record_annotation(&self, annotation: Annotation) {
+        let at = self.activity_position();
+        self.state
+            .lock()
+            .await
+            .activity
+            .push(at, Activity::Annotation(annotation));
+    }
+
+    /// Handle `Op::GetDigest`, returning the reply.
+    pub(crate) async fn digest(&self, since: DigestSince) -> DigestEvent {
+        let through_sequence = self.visualizer.next_sequence().checked_sub(1);
+        let mut digest = {
+            let state = self.state.lock().await;
+            let total_tokens = state
+                .token_info
+                .as_ref()
+                .map(|info| info.total_token_usage.clone())
+                .unwrap_or_default();
+            state.activity.digest(&since, &total_tokens)
+        };
+        digest.through_sequence = through_sequence;
+        digest.running_task = self
+            .running_tasks()
+            .await
+            .into_iter()
+            .find(|task| task.status == TaskStatus::Running)
+            .map(|task| DigestTask {
+                sub_id: task.sub_id,
+                kind: format!("{:?}", task.kind),
+            });
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn tokens(total_tokens: u64) -> TokenUsage {
+        TokenUsage {
+            total_tokens,
+            ..Default::default()
+        }
+    }
+
+    fn turn(sub_id: &str) -> DigestTurn {
+        DigestTurn {
+            sub_id: sub_id.to_string(),
+            completed_at_ms: 0,
+            last_agent_message: None,
+            files_changed: Vec::new(),
+            error: None,
+            focus: None,
+            template: None,
+        }
+    }
+
+    fn at(n: u64) -> Position {
+        Position {
+            emitted: n * 10,
+            time_ms: u128::from(n) * 60_000,
+        }
+    }
+
+    /// Task `n` of a synthetic session with one task a minute: every fifth
+    /// is aborted, every third a review, and completed task `n` changed
+    /// `n % 3` files.
+    fn task(n: u64) -> Activity {
+        let outcome = TaskOutcome {
+            kind: if n % 3 == 0 {
+                TaskKind::Review
+            } else {
+                TaskKind::Regular
+            },
+            duration: Duration::from_millis(n),
+            focus: None,
+        };
+        if n % 5 == 0 {
+            return Activity::Aborted {
+                sub_id: n.to_string(),
+                outcome,
+            };
+        }
+        Activity::Turn {
+            turn: DigestTurn {
+                files_changed: (0..n % 3)
+                    .map(|file| PathBuf::from(format!("/repo/{n}-{file}.rs")))
+                    .collect(),
+                ..turn(&n.to_string())
+            },
+            total_tokens: tokens(n * 100),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn discarded_activity_after_the_cursor_marks_the_digest_incomplete() {
+        // One turn an hour, enough for the first to be folded and then
+        // discarded with the oldest aggregate.
+        let turns = (MAX_ACTIVITY_RECORDS + MAX_HOUR_AGGREGATES) as u64;
+        let mut log = ActivityLog::new(0);
+        for n in 0..=turns {
+            log.push(
+                Position {
+                    emitted: n * 10,
+                    time_ms: u128::from(n) * HOUR_MS,
+                },
+                Activity::Turn {
+                    turn: turn(&n.to_string()),
+                    total_tokens: tokens(n * 100),
+                    outcome: TaskOutcome {
+                        kind: TaskKind::Regular,
+                        duration: Duration::ZERO,
+                        focus: None,
+                    },
+                },
+            );
+        }
+        let total = tokens(turns * 100);
+
+        // The first turn (at position 0) was discarded; a cursor past it
+        // still gets a complete digest.
+        let recent = log.digest(
+            &DigestSince {
+                sequence: Some(5),
+                time_ms: None,
+            },
+            &total,
+        );
+        assert_eq!(
+            (
+                recent.complete,
+                recent.turns_completed.len(),
+                recent.tokens_spent
+            ),
+            (true, MAX_ACTIVITY_RECORDS, total.clone())
+        );
+
+        let everything = log.digest(&DigestSince::default(), &total);
+        assert_eq!(
+            (
+                everything.complete,
+                everything.turns_completed.len(),
+                everything.tokens_spent
+            ),
+            (false, MAX_ACTIVITY_RECORDS, total)
+        );
+    }
+
+    #[test]
+    fn discarded_errors_after_the_cursor_mark_the_digest_incomplete() {
+        let mut log = ActivityLog::new(0);
+        for n in 0..=MAX_ACTIVITY_RECORDS as u64 {
+            log.push(
+                at(n),
+                Activity::Error(DigestError {
+                    sub_id: n.to_string(),
+                    at_ms: 0,
+                    message: "boom".to_string(),
+                }),
+            );
+        }
+        let total = tokens(0);
+
+        // The first error (at position 0) was discarded; a cursor past it
+        // still gets a complete digest.
+        let recent = log.digest(
+            &DigestSince {
+                sequence: Some(5),
+                time_ms: None,
+            },
+            &total,
+        );
+        assert_eq!(
+            (recent.complete, recent.errors.len()),
+            (true, MAX_ACTIVITY_RECORDS)
+        );
+
+        let everything = log.digest(&DigestSince::default(), &total);
+        assert_eq!(
+            (everything.complete, everything.errors.len()),
+            (false, MAX_ACTIVITY_RECORDS)
+        );
+    }
+
+    #[test]
+    fn idle_eviction_folds_old_tasks_into_hourly_aggregates() {
+        const TASKS: u64 = 150;
+        const RETAINED: usize = 10;
+        let mut log = ActivityLog::new(RETAINED);
+        for n in 0..TASKS {
+            log.push(at(n), task(n));
+        }
+
+        // 140 tasks to fold take three batches.
+        let steps: Vec<bool> = std::iter::from_fn(|| Some(log.evict())).take(4).collect();
+        assert_eq!(steps, vec![true, true, false, false]);
+
+        let evicted = TASKS - RETAINED as u64;
+        let mut expected: Vec<DigestHourAggregate> = Vec::new();
+        for n in 0..evicted {
+            let hour_start_ms = u128::from(n / 60) * HOUR_MS;
+            if expected
+                .last()
+                .is_none_or(|hour| hour.hour_start_ms != hour_start_ms)
+            {
+                expected.push(DigestHourAggregate {
+                    hour_start_ms,
+                    ..Default::default()
+                });
+            }
+            let Some(hour) = expected.last_mut() else {
+                unreachable!();
+            };
+            let kind = if n % 3 == 0 { "Review" } else { "Regular" };
+            *hour.tasks_by_kind.entry(kind.to_string()).or_default() += 1;
+            hour.total_duration_ms += n;
+            if n % 5 == 0 {
+                hour.aborted += 1;
+            } else {
+                hour.files_changed += (n % 3) as usize;
+            }
+        }
+        assert_eq!(
+            expected
+                .iter()
+                .map(|hour| (hour.tasks_by_kind.values().sum::<usize>(), hour.aborted))
+                .collect::<Vec<_>>(),
+            vec![(60, 12), (60, 12), (20, 4)]
+        );
+
+        let total = tokens(TASKS * 100);
+        let everything = log.digest(&DigestSince::default(), &total);
+        let full_turns: Vec<String> = everything
+            .turns_completed
+            .iter()
+            .map(|turn| turn.sub_id.clone())
+            .collect();
+        assert_eq!(
+            (
+                everything.aggregated_turns,
+                full_turns,
+                everything.tokens_spent
+            ),
+            (
+                expected.clone(),
+                ["141", "142", "143", "144", "146", "147", "148", "149"]
+                    .map(str::to_string)
+                    .to_vec(),
+                total.clone(),
+            )
+        );
+
+        // A cursor inside the last aggregated hour includes that hour whole;
+        // one past every aggregated task measures tokens from the newest
+        // aggregated turn.
+        let straddling = log.digest(
+            &DigestSince {
+                sequence: Some(at(125).emitted),
+                time_ms: None,
+            },
+            &total,
+        );
+        assert_eq!(straddling.aggregated_turns, expected[2..].to_vec());
+        let past = log.digest(
+            &DigestSince {
+                sequence: Some(at(evicted - 1).emitted),
+                time_ms: None,
+            },
+            &total,
+        );
+        assert_eq!(
+            (past.aggregated_turns, past.tokens_spent),
+            (Vec::new(), tokens(TASKS * 100 - 139 * 100))
+        );
+    }
+
+    #[test]
+    fn exporting_an_aggregated_turn_says_where_it_went() {
+        let mut log = ActivityLog::new(2);
+        for n in 1..=5 {
+            log.push(at(n), task(n));
+        }
+        while log.evict() {}
+
+        assert_eq!(
+            [log.export("4"), log.export("5"), log.export("1"), log.export("9")],
+            [
+                Ok(match task(4) {
+                    Activity::Turn { turn, .. } => turn,
+                    _ => unreachable!(),
+                }),
+                Err("task `5` was aborted and has no turn".to_string()),
+                Err("turn `1` is no longer kept in full; it was aggregated into the hour starting at 0 ms, reported in the digest's `aggregated_turns`".to_string()),
+                Err("no turn `9` in this session".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn focus_partitions_carry_their_own_tokens_errors_and_annotations() {
+        let mut log = ActivityLog::new(0);
+        let focused =
+            |sub_id: &str, focus: Option<&str>, files: &[&str], tokens_after: u64| Activity::Turn {
+                turn: DigestTurn {
+                    files_changed: files.iter().map(PathBuf::from).collect(),
+                    focus: focus.map(str::to_string),
+                    ..turn(sub_id)
+                },
+                total_tokens: tokens(tokens_after),
+                outcome: TaskOutcome {
+                    kind: TaskKind::Regular,
+                    duration: Duration::ZERO,
+                    focus: focus.map(str::to_string),
+                },
+            };
+        let error = |sub_id: &str| DigestError {
+            sub_id: sub_id.to_string(),
+            at_ms: 0,
+            message: format!("{sub_id} failed"),
+        };
+        let annotation = |sub_id: &str| Annotation {
+            text: format!("about {sub_id}"),
+            sub_id: Some(sub_id.to_string()),
+            tags: Vec::new(),
+            at_ms: 0,
+        };
+        log.push(at(1), focused("1", Some("parser"), &["/repo/a.rs"], 100));
+        log.push(at(2), Activity::Error(error("2")));
+        log.push(at(3), focused("2", Some("ci"), &["/repo/ci.yml"], 250));
+        log.push(at(4), focused("3", None, &["/repo/b.rs"], 300));
+        log.push(at(5), Activity::Annotation(annotation("1")));
+        log.push(at(6), focused("4", Some("parser"), &["/repo/a.rs"], 700));
+        log.push(at(7), Activity::Error(error("5")));
+
+        let digest = log.digest(&DigestSince::default(), &tokens(800));
+        assert_eq!(
+            digest.by_focus,
+            vec![
+                DigestFocus {
+                    focus: "parser".to_string(),
+                    turns: 2,
+                    files_changed: vec![PathBuf::from("/repo/a.rs")],
+                    tokens_spent: tokens(100 + 400),
+                    errors: Vec::new(),
+                    annotations: vec![annotation("1")],
+                },
+                DigestFocus {
+                    focus: "ci".to_string(),
+                    turns: 1,
+                    files_changed: vec![PathBuf::from("/repo/ci.yml")],
+                    tokens_spent: tokens(150),
+                    errors: vec![error("2")],
+                    annotations: Vec::new(),
+                },
+            ]
+        );
+
+        while log.evict() {}
+        let summary = &log.aggregates[0].summary;
+        assert_eq!(
+            (&summary.tasks_by_focus, &summary.files_changed_by_focus),
+            (
+                &BTreeMap::from([("ci".to_string(), 1), ("parser".to_string(), 2)]),
+                &BTreeMap::from([("ci".to_string(), 1), ("parser".to_string(), 2)]),
+            )
+        );
+    }
+}
@@ -4,6 +4,8 @@ use crate::token_data::PlanType;
 use crate::truncate::truncate_middle;
 use codex_protocol::ConversationId;
 use codex_protocol::protocol::RateLimitSnapshot;
+use codex_protocol::protocol::TaskError;
+use codex_protocol::protocol::TaskErrorKind;
 use reqwest::StatusCode;
 use serde_json;
 use std::io;
@@ -305,6 +307,62 @@ impl CodexErr {
     pub fn downcast_ref<T: std::any::Any>(&self) -> Option<&T> {
         (self as &dyn std::any::Any).downcast_ref::<T>()
     }
+
+    /// How this error ending a task is reported to clients, and whether the
+    /// turn loop retries it. Errors without a better fit are `Internal`.
+    pub fn task_error_kind(&self) -> TaskErrorKind {
+        match self {
+            CodexErr::EnvVar(_) | CodexErr::UsageNotIncluded => TaskErrorKind::ProviderAuth,
+            CodexErr::UsageLimitReached(_) => TaskErrorKind::RateLimited,
+            CodexErr::ContextWindowExceeded => TaskErrorKind::ContextOverflow,
+            CodexErr::Stream(..) | CodexErr::InternalServerError => {
+                TaskErrorKind::StreamInterrupted
+            }
+            CodexErr::UnexpectedStatus(err) => status_error_kind(err.status, &err.body),
+            CodexErr::RetryLimit(err) => status_error_kind(err.status, ""),
+            CodexErr::Reqwest(err) => match err.status() {
+                Some(status) => status_error_kind(status, ""),
+                None if err.is_timeout() || err.is_connect() || err.is_body() => {
+                    TaskErrorKind::StreamInterrupted
+                }
+                None => TaskErrorKind::Internal,
+            },
+            CodexErr::Sandbox(SandboxErr::Timeout { .. } | SandboxErr::Signal(_))
+            | CodexErr::Timeout
+            | CodexErr::Spawn => TaskErrorKind::ToolFailure,
+            CodexErr::Sandbox(_) | CodexErr::LandlockSandboxExecutableNotProvided => {
+                TaskErrorKind::SandboxDenied
+            }
+            #[cfg(target_os = "linux")]
+            CodexErr::LandlockRuleset(_) | CodexErr::LandlockPathFd(_) => {
+                TaskErrorKind::SandboxDenied
+            }
+            _ => TaskErrorKind::Internal,
+        }
+    }
+
+    /// This error as reported with the completion of the task it ended.
+    pub fn to_task_error(&self) -> TaskError {
+        let kind = self.task_error_kind();
+        TaskError {
+            kind,
+            retryable: kind.is_retryable(),
+            message: get_error_message_ui(self),
+        }
+    }
+}
+
+fn status_error_kind(status: StatusCode, body: &str) -> TaskErrorKind {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TaskErrorKind::ProviderAuth,
+        StatusCode::TOO_MANY_REQUESTS => TaskErrorKind::RateLimited,
+        StatusCode::PAYLOAD_TOO_LARGE => TaskErrorKind::ContextOverflow,
+        StatusCode::BAD_REQUEST if body.contains("context_length_exceeded") => {
+            TaskErrorKind::ContextOverflow
+        }
+        status if status.is_server_error() => TaskErrorKind::StreamInterrupted,
+        _ => TaskErrorKind::Internal,
+    }
 }
 
 pub fn get_error_message_ui(e: &CodexErr) -> String {
@@ -558,4 +616,153 @@ mod tests {
             "You've hit your usage limit. Try again in less than a minute."
         );
     }
+
+    fn exec_output() -> Box<ExecToolCallOutput> {
+        Box::new(ExecToolCallOutput {
+            exit_code: 1,
+            stdout: StreamOutput::new(String::new()),
+            stderr: StreamOutput::new("denied".to_string()),
+            aggregated_output: StreamOutput::new(String::new()),
+            duration: Duration::from_millis(5),
+            timed_out: false,
+        })
+    }
+
+    fn unexpected_status(status: StatusCode, body: &str) -> CodexErr {
+        CodexErr::UnexpectedStatus(UnexpectedResponseError {
+            status,
+            body: body.to_string(),
+            request_id: None,
+        })
+    }
+
+    #[test]
+    fn task_errors_are_classified_by_what_the_client_can_do() {
+        let cases = vec![
+            (
+                CodexErr::Stream("connection reset".to_string(), None),
+                TaskErrorKind::StreamInterrupted,
+            ),
+            (
+                CodexErr::InternalServerError,
+                TaskErrorKind::StreamInterrupted,
+            ),
+            (
+                unexpected_status(StatusCode::BAD_GATEWAY, "upstream"),
+                TaskErrorKind::StreamInterrupted,
+            ),
+            (
+                unexpected_status(StatusCode::UNAUTHORIZED, "invalid api key"),
+                TaskErrorKind::ProviderAuth,
+            ),
+            (
+                unexpected_status(StatusCode::FORBIDDEN, "model not allowed"),
+                TaskErrorKind::ProviderAuth,
+            ),
+            (
+                CodexErr::EnvVar(EnvVarError {
+                    var: "OPENAI_API_KEY".to_string(),
+                    instructions: None,
+                }),
+                TaskErrorKind::ProviderAuth,
+            ),
+            (CodexErr::UsageNotIncluded, TaskErrorKind::ProviderAuth),
+            (
+                unexpected_status(StatusCode::TOO_MANY_REQUESTS, "slow down"),
+                TaskErrorKind::RateLimited,
+            ),
+            (
+                CodexErr::RetryLimit(RetryLimitReachedError {
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    request_id: None,
+                }),
+                TaskErrorKind::RateLimited,
+            ),
+            (
+                CodexErr::UsageLimitReached(UsageLimitReachedError {
+                    plan_type: None,
+                    resets_in_seconds: Some(60),
+                    rate_limits: None,
+                }),
+                TaskErrorKind::RateLimited,
+            ),
+            (
+                CodexErr::ContextWindowExceeded,
+                TaskErrorKind::ContextOverflow,
+            ),
+            (
+                unexpected_status(
+                    StatusCode::BAD_REQUEST,
+                    r#"{"error":{"code":"context_length_exceeded"}}"#,
+                ),
+                TaskErrorKind::ContextOverflow,
+            ),
+            (
+                unexpected_status(StatusCode::BAD_REQUEST, "unknown parameter"),
+                TaskErrorKind::Internal,
+            ),
+            (
+                CodexErr::Sandbox(SandboxErr::Denied {
+                    output: exec_output(),
+                }),
+                TaskErrorKind::SandboxDenied,
+            ),
+            (
+                CodexErr::Sandbox(SandboxErr::LandlockRestrict),
+                TaskErrorKind::SandboxDenied,
+            ),
+            (
+                CodexErr::LandlockSandboxExecutableNotProvided,
+                TaskErrorKind::SandboxDenied,
+            ),
+            (
+                CodexErr::Sandbox(SandboxErr::Timeout {
+                    output: exec_output(),
+                }),
+                TaskErrorKind::ToolFailure,
+            ),
+            (
+                CodexErr::Sandbox(SandboxErr::Signal(9)),
+                TaskErrorKind::ToolFailure,
+            ),
+            (CodexErr::Spawn, TaskErrorKind::ToolFailure),
+            (CodexErr::Timeout, TaskErrorKind::ToolFailure),
+            (
+                CodexErr::Fatal("bad state".to_string()),
+                TaskErrorKind::Internal,
+            ),
+            (CodexErr::InternalAgentDied, TaskErrorKind::Internal),
+            (
+                CodexErr::Io(io::Error::other("disk full")),
+                TaskErrorKind::Internal,
+            ),
+            (
+                CodexErr::UnsupportedOperation("compact".to_string()),
+                TaskErrorKind::Internal,
+            ),
+        ];
+        for (err, kind) in cases {
+            assert_eq!(err.task_error_kind(), kind, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn only_transient_kinds_are_retryable() {
+        assert_eq!(
+            CodexErr::Stream("connection reset".to_string(), None).to_task_error(),
+            TaskError {
+                kind: TaskErrorKind::StreamInterrupted,
+                retryable: true,
+                message: "stream disconnected before completion: connection reset".to_string(),
+            }
+        );
+        assert_eq!(
+            CodexErr::ContextWindowExceeded.to_task_error(),
+            TaskError {
+                kind: TaskErrorKind::ContextOverflow,
+                retryable: false,
+                message: CodexErr::ContextWindowExceeded.to_string(),
+            }
+        );
+    }
 }
@@ -0,0 +1,83 @@
+//! Broadcast-backed [`Stream`] of session events for embedders.
+//!
+//! Every [`EventStream`] sees every event sent after it was created, in
+//! order, independently of [`Codex::next_event`](crate::codex::Codex::next_event)
+//! and of other streams. Events are shared between streams and only cloned
+//! for a stream that wants them, after [`EventStream::filtered`] has been
+//! applied.
+//!
+//! A stream that falls more than [`EVENT_STREAM_CAPACITY`] events behind
+//! skips the oldest ones it missed (the number is logged) and carries on with
+//! the oldest event still buffered; it never slows the session down. The
+//! stream ends once the session and its [`Codex`](crate::codex::Codex) handle
+//! are both gone.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use futures::Stream;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::warn;
+
+use crate::protocol::Event;
+use crate::protocol::EventDiscriminant;
+
+/// Events buffered per session for streams that have not caught up yet.
+pub const EVENT_STREAM_CAPACITY: usize = 1024;
+
+pub(crate) fn event_broadcast() -> broadcast::Sender<Arc<Event>> {
+    broadcast::channel(EVENT_STREAM_CAPACITY).0
+}
+
+/// Returned by [`Codex::event_stream`](crate::codex::Codex::event_stream).
+pub struct EventStream {
+    inner: BroadcastStream<Arc<Event>>,
+    kinds: Option<Vec<EventDiscriminant>>,
+}
+
+impl EventStream {
+    pub(crate) fn new(sender: &broadcast::Sender<Arc<Event>>) -> Self {
+        Self {
+            inner: BroadcastStream::new(sender.subscribe()),
+            kinds: None,
+        }
+    }
+
+    /// Only yield events whose [`EventMsg`](crate::protocol::EventMsg) is
+    /// one of `kinds`. Other events are skipped without being cloned.
+    pub fn filtered(mut self, kinds: &[EventDiscriminant]) -> Self {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    fn wants(&self, event: &Event) -> bool {
+        self.kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.contains(&EventDiscriminant::from(&event.msg)))
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => {
+                    if self.wants(&event) {
+                        return Poll::Ready(Some(Event::clone(&event)));
+                    }
+                }
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(skipped)))) => {
+                    warn!("event stream fell behind; skipped {skipped} events");
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
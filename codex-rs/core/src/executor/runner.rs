@@ -6,6 +6,8 @@ use std::time::Duration;
 use super::backends::ExecutionMode;
 use super::backends::backend_for_mode;
 use super::cache::ApprovalCache;
+use crate::approvals::APPROVAL_TIMED_OUT_MESSAGE;
+use crate::approvals::ApprovalResolution;
 use crate::codex::Session;
 use crate::error::CodexErr;
 use crate::error::SandboxErr;
@@ -24,7 +26,6 @@ use crate::protocol::ReviewDecision;
 use crate::protocol::SandboxPolicy;
 use crate::shell;
 use crate::tools::context::ExecCommandContext;
-use codex_otel::otel_event_manager::ToolDecisionSource;
 
 #[derive(Clone, Debug)]
 pub(crate) struct ExecutorConfig {
@@ -173,7 +174,7 @@ impl Executor {
                 format!("Execution failed: {sandbox_error}"),
             )
             .await;
-        let decision = session
+        let resolution = session
             .request_command_approval(
                 context.sub_id.to_string(),
                 context.call_id.to_string(),
@@ -186,9 +187,15 @@ impl Executor {
         context.otel_event_manager.tool_decision(
             &context.tool_name,
             &context.call_id,
-            decision,
-            ToolDecisionSource::User,
+            resolution.decision(),
+            resolution.decision_source(),
         );
+        let decision = match resolution {
+            ApprovalResolution::Decided(decision) => decision,
+            ApprovalResolution::ApprovalTimedOut => {
+                return Err(ExecError::rejection(APPROVAL_TIMED_OUT_MESSAGE));
+            }
+        };
         match decision {
             ReviewDecision::Approved | ReviewDecision::ApprovedForSession => {
                 if matches!(decision, ReviewDecision::ApprovedForSession) {
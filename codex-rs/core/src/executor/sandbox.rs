@@ -1,4 +1,6 @@
 use crate::apply_patch::ApplyPatchExec;
+use crate::approvals::APPROVAL_TIMED_OUT_MESSAGE;
+use crate::approvals::ApprovalResolution;
 use crate::codex::Session;
 use crate::exec::SandboxType;
 use crate::executor::ExecutionMode;
@@ -129,7 +131,7 @@ async fn select_shell_sandbox(
             Ok(decision)
         }
         SafetyCheck::AskUser => {
-            let decision = session
+            let resolution = session
                 .request_command_approval(
                     sub_id.to_string(),
                     call_id.to_string(),
@@ -142,13 +144,20 @@ async fn select_shell_sandbox(
             otel_event_manager.tool_decision(
                 "local_shell",
                 call_id,
-                decision,
-                ToolDecisionSource::User,
+                resolution.decision(),
+                resolution.decision_source(),
             );
-            match decision {
-                ReviewDecision::Approved => Ok(SandboxDecision::user_override(false)),
-                ReviewDecision::ApprovedForSession => Ok(SandboxDecision::user_override(true)),
-                ReviewDecision::Denied | ReviewDecision::Abort => {
+            match resolution {
+                ApprovalResolution::ApprovalTimedOut => {
+                    Err(ExecError::rejection(APPROVAL_TIMED_OUT_MESSAGE))
+                }
+                ApprovalResolution::Decided(ReviewDecision::Approved) => {
+                    Ok(SandboxDecision::user_override(false))
+                }
+                ApprovalResolution::Decided(ReviewDecision::ApprovedForSession) => {
+                    Ok(SandboxDecision::user_override(true))
+                }
+                ApprovalResolution::Decided(ReviewDecision::Denied | ReviewDecision::Abort) => {
                     Err(ExecError::rejection("exec command rejected by user"))
                 }
             }
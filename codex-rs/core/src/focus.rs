@@ -0,0 +1,254 @@
+//! The paths the session's current work concerns, declared with
+//! `Op::SetFocus`.
+//!
+//! A task keeps the focus that was set when it was spawned: its label is
+//! reported in `task_spawned` and in the task's digest turn, and a patch of
+//! the task touching files outside the focus is reported as a
+//! `focus_drift` visualizer event. Drift is informational; the patch is
+//! applied as usual.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::codex::Session;
+use crate::protocol::BackgroundEventEvent;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::FileChange;
+
+const MAX_LABEL_CHARS: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Focus {
+    pub(crate) label: String,
+    /// Absolute; a path covers itself and everything below it.
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+impl Focus {
+    /// A focus on `paths`, relative ones resolved against `cwd`.
+    fn new(label: String, paths: Vec<PathBuf>, cwd: &Path) -> Result<Self, String> {
+        let label = label.trim().to_string();
+        if label.is_empty() {
+            return Err("focus label is empty".to_string());
+        }
+        let chars = label.chars().count();
+        if chars > MAX_LABEL_CHARS {
+            return Err(format!(
+                "focus label is {chars} characters; the limit is {MAX_LABEL_CHARS}"
+            ));
+        }
+        if paths.is_empty() {
+            return Err(format!("focus `{label}` lists no paths"));
+        }
+        let mut paths: Vec<_> = paths.into_iter().map(|path| cwd.join(path)).collect();
+        paths.sort();
+        paths.dedup();
+        Ok(Self { label, paths })
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        self.paths.iter().any(|focus| path.starts_with(focus))
+    }
+
+    /// Paths created, changed, deleted or moved to by `changes` that lie
+    /// outside the focus, sorted.
+    pub(crate) fn outside(&self, changes: &HashMap<PathBuf, FileChange>) -> Vec<PathBuf> {
+        let mut outside: Vec<_> = changes
+            .iter()
+            .flat_map(|(path, change)| {
+                let moved_to = match change {
+                    FileChange::Update { move_path, .. } => move_path.as_ref(),
+                    FileChange::Add { .. } | FileChange::Delete { .. } => None,
+                };
+                std::iter::once(path).chain(moved_to)
+            })
+            .filter(|path| !self.covers(path))
+            .cloned()
+            .collect();
+        outside.sort();
+        outside.dedup();
+        outside
+    }
+}
+
+impl Session {
+    /// Handle `Op::SetFocus` submitted as `op_id`, replacing any earlier
+    /// focus.
+    pub(crate) async fn set_focus(
+        &self,
+        op_id: String,
+        label: String,
+        paths: Vec<PathBuf>,
+        cwd: &Path,
+    ) {
+        let focus = match Focus::new(label, paths, cwd) {
+            Ok(focus) => focus,
+            Err(message) => {
+                self.send_event(Event {
+                    id: op_id,
+                    msg: EventMsg::Error(ErrorEvent {
+                        message: format!("invalid focus: {message}"),
+                    }),
+                })
+                .await;
+                return;
+            }
+        };
+        let replaced = self.state.lock().await.focus.replace(focus.clone());
+        self.emit_with_state(
+            "focus_changed",
+            json!({
+                "label": focus.label,
+                "paths": focus.paths,
+                "replaced": replaced.map(|replaced| replaced.label),
+            }),
+        )
+        .await;
+        self.send_event(Event {
+            id: op_id,
+            msg: EventMsg::BackgroundEvent(BackgroundEventEvent {
+                message: format!("Focus set to `{}`", focus.label),
+            }),
+        })
+        .await;
+    }
+
+    /// Handle `Op::ClearFocus` submitted as `op_id`.
+    pub(crate) async fn clear_focus(&self, op_id: String) {
+        let cleared = self.state.lock().await.focus.take();
+        let message = match &cleared {
+            Some(focus) => format!("Cleared focus `{}`", focus.label),
+            None => "No focus was set".to_string(),
+        };
+        if let Some(focus) = cleared {
+            self.emit_with_state(
+                "focus_changed",
+                json!({
+                    "label": null,
+                    "paths": [],
+                    "replaced": focus.label,
+                }),
+            )
+            .await;
+        }
+        self.send_event(Event {
+            id: op_id,
+            msg: EventMsg::BackgroundEvent(BackgroundEventEvent { message }),
+        })
+        .await;
+    }
+
+    /// Emit `focus_drift` if the patch of the running task `sub_id`
+    /// touches files outside the task's focus.
+    pub(crate) async fn check_focus_drift(
+        &self,
+        sub_id: &str,
+        call_id: &str,
+        changes: &HashMap<PathBuf, FileChange>,
+    ) {
+        let focus = {
+            let active = self.active_turn.lock().await;
+            active
+                .as_ref()
+                .and_then(|at| at.tasks.get(sub_id))
+                .and_then(|task| task.focus.clone())
+        };
+        let Some(focus) = focus else {
+            return;
+        };
+        let outside = focus.outside(changes);
+        if outside.is_empty() {
+            return;
+        }
+        self.emit_with_state(
+            "focus_drift",
+            json!({
+                "subId": sub_id,
+                "callId": call_id,
+                "focus": focus.label,
+                "focusPaths": focus.paths,
+                "outside": outside,
+            }),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn relative_paths_resolve_against_the_cwd() {
+        let focus = Focus::new(
+            " parser ".to_string(),
+            vec![PathBuf::from("src/parser"), PathBuf::from("/repo/docs")],
+            Path::new("/repo"),
+        );
+        assert_eq!(
+            focus,
+            Ok(Focus {
+                label: "parser".to_string(),
+                paths: vec![
+                    PathBuf::from("/repo/docs"),
+                    PathBuf::from("/repo/src/parser")
+                ],
+            })
+        );
+        assert_eq!(
+            Focus::new(
+                "  ".to_string(),
+                vec![PathBuf::from("src")],
+                Path::new("/repo")
+            ),
+            Err("focus label is empty".to_string())
+        );
+        assert_eq!(
+            Focus::new("parser".to_string(), Vec::new(), Path::new("/repo")),
+            Err("focus `parser` lists no paths".to_string())
+        );
+    }
+
+    #[test]
+    fn moves_out_of_the_focus_drift() {
+        let focus = Focus {
+            label: "parser".to_string(),
+            paths: vec![PathBuf::from("/repo/src/parser")],
+        };
+        let changes = HashMap::from([
+            (
+                PathBuf::from("/repo/src/parser/lexer.rs"),
+                FileChange::Update {
+                    unified_diff: String::new(),
+                    move_path: Some(PathBuf::from("/repo/src/lexer.rs")),
+                },
+            ),
+            (
+                PathBuf::from("/repo/src/parser/ast.rs"),
+                FileChange::Add {
+                    content: String::new(),
+                },
+            ),
+            (
+                PathBuf::from("/repo/src/parser_old.rs"),
+                FileChange::Delete {
+                    content: String::new(),
+                },
+            ),
+        ]);
+        assert_eq!(
+            focus.outside(&changes),
+            vec![
+                PathBuf::from("/repo/src/lexer.rs"),
+                PathBuf::from("/repo/src/parser_old.rs"),
+            ]
+        );
+    }
+}
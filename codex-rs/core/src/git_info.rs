@@ -4,6 +4,7 @@ use std::path::PathBuf;
 
 use codex_app_server_protocol::GitSha;
 use codex_protocol::protocol::GitInfo;
+use codex_protocol::protocol::ReviewTarget;
 use futures::future::join_all;
 use serde::Deserialize;
 use serde::Serialize;
@@ -585,6 +586,187 @@ pub async fn current_branch_name(cwd: &Path) -> Option<String> {
         .filter(|name| !name.is_empty())
 }
 
+/// Paths, relative to the repository root, that `target` changes, in
+/// sorted order. Uncommitted changes include untracked files. `None`
+/// outside a git repository, or if the branch or commit does not resolve.
+pub async fn changed_files(cwd: &Path, target: &ReviewTarget) -> Option<Vec<String>> {
+    let listings = match target {
+        ReviewTarget::UncommittedChanges => {
+            let diff = ["diff", "--name-only", "HEAD"];
+            let untracked = ["ls-files", "--others", "--exclude-standard", "--full-name"];
+            vec![
+                run_git_command_with_timeout(&diff, cwd).await?,
+                run_git_command_with_timeout(&untracked, cwd).await?,
+            ]
+        }
+        // Anything else starting with a dash would be taken for an option.
+        ReviewTarget::BaseBranch { branch } if !branch.starts_with('-') => {
+            let range = format!("{branch}...HEAD");
+            let diff = ["diff", "--name-only", range.as_str()];
+            vec![run_git_command_with_timeout(&diff, cwd).await?]
+        }
+        ReviewTarget::Commit { sha } if !sha.starts_with('-') => {
+            let diff_tree = [
+                "diff-tree",
+                "--no-commit-id",
+                "--name-only",
+                "-r",
+                "--root",
+                sha.as_str(),
+            ];
+            vec![run_git_command_with_timeout(&diff_tree, cwd).await?]
+        }
+        ReviewTarget::BaseBranch { .. } | ReviewTarget::Commit { .. } => return None,
+    };
+    if !listings.iter().all(|listing| listing.status.success()) {
+        return None;
+    }
+    let mut files: Vec<String> = listings
+        .iter()
+        .flat_map(|listing| {
+            String::from_utf8_lossy(&listing.stdout)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|file| !file.is_empty())
+        .collect();
+    files.sort();
+    files.dedup();
+    Some(files)
+}
+
+/// Git subcommands that can move the current branch to a new commit.
+const COMMITTING_SUBCOMMANDS: &[&str] = &[
+    "am",
+    "cherry-pick",
+    "commit",
+    "merge",
+    "pull",
+    "rebase",
+    "revert",
+];
+
+/// Cheap pre-filter: does `command` (argv, possibly wrapping a shell script)
+/// look like it runs a git subcommand that can create commits?
+pub fn may_create_commit(command: &[String]) -> bool {
+    let mut after_git = false;
+    for token in command
+        .iter()
+        .flat_map(|arg| arg.split(|c: char| c.is_whitespace() || ";&|()".contains(c)))
+    {
+        if token == "git" || token.ends_with("/git") {
+            after_git = true;
+        } else if after_git && COMMITTING_SUBCOMMANDS.contains(&token) {
+            return true;
+        }
+    }
+    false
+}
+
+/// The branch HEAD is attached to and the commit it points at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttachedHead {
+    pub branch_ref: String,
+    pub sha: String,
+}
+
+/// Returns `None` outside a repository, on a detached HEAD, or while a
+/// rebase is in progress, so the caller never attributes those HEAD moves
+/// to a new commit.
+pub async fn attached_head(cwd: &Path) -> Option<AttachedHead> {
+    let (branch_ref, sha, git_dir) = tokio::join!(
+        run_git_command_with_timeout(&["symbolic-ref", "-q", "HEAD"], cwd),
+        run_git_command_with_timeout(&["rev-parse", "HEAD"], cwd),
+        run_git_command_with_timeout(&["rev-parse", "--absolute-git-dir"], cwd),
+    );
+    let branch_ref = successful_stdout(branch_ref?)?;
+    let sha = successful_stdout(sha?)?;
+    let git_dir = PathBuf::from(successful_stdout(git_dir?)?);
+    if git_dir.join("rebase-merge").exists() || git_dir.join("rebase-apply").exists() {
+        return None;
+    }
+    Some(AttachedHead { branch_ref, sha })
+}
+
+/// A commit that moved the current branch forward.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatedCommit {
+    pub sha: String,
+    pub subject: String,
+    /// Files changed between the previous branch tip and `sha`.
+    pub files_changed: usize,
+}
+
+/// Compare HEAD against `before` and report the new tip if the same branch
+/// has moved forward to a descendant of the old tip by making a commit.
+/// Checkouts, resets, fast-forwards, and anything that leaves HEAD detached
+/// or mid-rebase yield `None`.
+pub async fn commit_created_since(cwd: &Path, before: &AttachedHead) -> Option<CreatedCommit> {
+    let after = attached_head(cwd).await?;
+    if after.branch_ref != before.branch_ref || after.sha == before.sha {
+        return None;
+    }
+    let is_descendant = run_git_command_with_timeout(
+        &["merge-base", "--is-ancestor", &before.sha, &after.sha],
+        cwd,
+    )
+    .await?
+    .status
+    .success();
+    if !is_descendant || !last_update_made_commit(cwd, &after.branch_ref).await {
+        return None;
+    }
+    let log_args = ["log", "-1", "--format=%s", after.sha.as_str()];
+    let diff_args = [
+        "diff",
+        "--name-only",
+        before.sha.as_str(),
+        after.sha.as_str(),
+    ];
+    let (subject, files) = tokio::join!(
+        run_git_command_with_timeout(&log_args, cwd),
+        run_git_command_with_timeout(&diff_args, cwd),
+    );
+    let files_changed = successful_stdout(files?)?.lines().count();
+    Some(CreatedCommit {
+        sha: after.sha,
+        subject: successful_stdout(subject?)?,
+        files_changed,
+    })
+}
+
+/// Reflog subjects of the branch updates that make a commit, rather than
+/// move the branch onto commits that already existed.
+const COMMIT_REFLOG_PREFIXES: &[&str] = &["commit", "cherry-pick", "revert", "am"];
+
+/// Whether the latest update of `branch_ref` made a commit, as opposed to
+/// fast-forwarding or resetting it. Without a reflog there is nothing to
+/// tell them apart by, so the update counts.
+async fn last_update_made_commit(cwd: &Path, branch_ref: &str) -> bool {
+    let Some(subject) =
+        run_git_command_with_timeout(&["reflog", "-1", "--format=%gs", branch_ref], cwd)
+            .await
+            .and_then(successful_stdout)
+            .filter(|subject| !subject.is_empty())
+    else {
+        return true;
+    };
+    COMMIT_REFLOG_PREFIXES
+        .iter()
+        .any(|prefix| subject.starts_with(prefix))
+        || subject.contains("Merge made by")
+}
+
+fn successful_stdout(output: std::process::Output) -> Option<String> {
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -727,6 +909,60 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_changed_files_follow_the_review_target() {
+        skip_if_sandbox!();
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = create_test_git_repo(&temp_dir).await;
+        let git = |args: &'static [&'static str]| {
+            let repo_path = repo_path.clone();
+            async move {
+                Command::new("git")
+                    .args(args)
+                    .current_dir(&repo_path)
+                    .output()
+                    .await
+                    .expect("git")
+            }
+        };
+        let base = String::from_utf8(git(&["rev-parse", "--abbrev-ref", "HEAD"]).await.stdout)
+            .expect("branch name")
+            .trim()
+            .to_string();
+        git(&["checkout", "-q", "-b", "feature"]).await;
+        fs::write(repo_path.join("committed.txt"), "committed").unwrap();
+        git(&["add", "committed.txt"]).await;
+        git(&["commit", "-q", "-m", "feature change"]).await;
+        let sha = String::from_utf8(git(&["rev-parse", "HEAD"]).await.stdout)
+            .expect("sha")
+            .trim()
+            .to_string();
+        fs::write(repo_path.join("test.txt"), "edited").unwrap();
+        fs::write(repo_path.join("untracked.txt"), "new").unwrap();
+
+        assert_eq!(
+            (
+                changed_files(&repo_path, &ReviewTarget::UncommittedChanges).await,
+                changed_files(&repo_path, &ReviewTarget::BaseBranch { branch: base }).await,
+                changed_files(&repo_path, &ReviewTarget::Commit { sha }).await,
+                changed_files(
+                    &repo_path,
+                    &ReviewTarget::Commit {
+                        sha: "--output=/tmp/x".to_string()
+                    }
+                )
+                .await,
+            ),
+            (
+                Some(vec!["test.txt".to_string(), "untracked.txt".to_string()]),
+                Some(vec!["committed.txt".to_string()]),
+                Some(vec!["committed.txt".to_string()]),
+                None,
+            )
+        );
+    }
+
     async fn create_test_git_repo_with_remote(temp_dir: &TempDir) -> (PathBuf, String) {
         let repo_path = create_test_git_repo(temp_dir).await;
         let remote_path = temp_dir.path().join("remote.git");
@@ -1076,6 +1312,105 @@ mod tests {
         assert!(state.diff.contains("updated"));
     }
 
+    async fn git(repo_path: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .await
+            .expect("run git");
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout)
+            .expect("utf8")
+            .trim()
+            .to_string()
+    }
+
+    #[test]
+    fn test_may_create_commit() {
+        let argv = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert!(may_create_commit(&argv(&["git", "commit", "-m", "x"])));
+        assert!(may_create_commit(&argv(&[
+            "bash",
+            "-lc",
+            "git add . && git -c user.name=a commit -m x"
+        ])));
+        assert!(may_create_commit(&argv(&[
+            "/usr/bin/git",
+            "merge",
+            "topic"
+        ])));
+        assert!(!may_create_commit(&argv(&["git", "status"])));
+        assert!(!may_create_commit(&argv(&["echo", "commit"])));
+    }
+
+    #[tokio::test]
+    async fn test_attached_head_ignores_detached_and_rebase() {
+        skip_if_sandbox!();
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = create_test_git_repo(&temp_dir).await;
+        let sha = git(&repo_path, &["rev-parse", "HEAD"]).await;
+        let branch_ref = git(&repo_path, &["symbolic-ref", "HEAD"]).await;
+
+        assert_eq!(
+            attached_head(&repo_path).await,
+            Some(AttachedHead {
+                branch_ref,
+                sha: sha.clone()
+            })
+        );
+
+        let rebase_dir = repo_path.join(".git").join("rebase-merge");
+        fs::create_dir(&rebase_dir).expect("create rebase-merge");
+        assert_eq!(attached_head(&repo_path).await, None);
+        fs::remove_dir(&rebase_dir).expect("remove rebase-merge");
+
+        git(&repo_path, &["checkout", "-q", "--detach"]).await;
+        assert_eq!(attached_head(&repo_path).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_commit_created_since_only_reports_forward_moves() {
+        skip_if_sandbox!();
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = create_test_git_repo(&temp_dir).await;
+        let initial = attached_head(&repo_path).await.expect("attached head");
+
+        fs::write(repo_path.join("a.txt"), "a").expect("write a");
+        fs::write(repo_path.join("b.txt"), "b").expect("write b");
+        git(&repo_path, &["add", "."]).await;
+        git(&repo_path, &["commit", "-q", "-m", "Add two files"]).await;
+        let sha = git(&repo_path, &["rev-parse", "HEAD"]).await;
+        assert_eq!(
+            commit_created_since(&repo_path, &initial).await,
+            Some(CreatedCommit {
+                sha,
+                subject: "Add two files".to_string(),
+                files_changed: 2,
+            })
+        );
+
+        let tip = attached_head(&repo_path).await.expect("attached head");
+        git(&repo_path, &["reset", "-q", "--hard", &initial.sha]).await;
+        assert_eq!(commit_created_since(&repo_path, &tip).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_commit_created_since_ignores_fast_forwards() {
+        skip_if_sandbox!();
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let repo_path = create_test_git_repo(&temp_dir).await;
+        git(&repo_path, &["checkout", "-q", "-b", "topic"]).await;
+        fs::write(repo_path.join("a.txt"), "a").expect("write a");
+        git(&repo_path, &["add", "."]).await;
+        git(&repo_path, &["commit", "-q", "-m", "Add a file"]).await;
+        git(&repo_path, &["checkout", "-q", "-"]).await;
+        let before = attached_head(&repo_path).await.expect("attached head");
+
+        git(&repo_path, &["merge", "-q", "--ff-only", "topic"]).await;
+        assert_eq!(commit_created_since(&repo_path, &before).await, None);
+    }
+
     #[test]
     fn test_git_info_serialization() {
         let git_info = GitInfo {
@@ -0,0 +1,369 @@
+//! Keeps large image inputs from pushing a turn straight into compaction.
+//!
+//! Before a user turn's first request, and whenever input sent during the
+//! turn is picked up, that input is estimated against the context budget. When the images are what takes it over, they are reduced
+//! in stages until the turn fits: images above
+//! [`ImageDegradation::max_pixels`] are downscaled, then all but the
+//! [`ImageDegradation::keep_recent`] most recent ones are dropped. Callers
+//! send the reduced input to the model but keep the original in the rollout.
+
+use std::io::Cursor;
+
+use base64::Engine;
+use image::ImageFormat;
+use image::ImageReader;
+use image::imageops::FilterType;
+use serde::Serialize;
+
+use crate::config_types::ImageDegradation;
+use crate::protocol::InputItem;
+
+/// Rough, provider-agnostic cost of an image: one token per this many
+/// pixels.
+const PIXELS_PER_TOKEN: u64 = 750;
+
+/// Cost assumed for images that cannot be decoded, such as remote URLs.
+const UNKNOWN_IMAGE_TOKENS: u64 = 1_500;
+
+/// What [`degrade`] did to an input, for the `input_degraded` event and the
+/// notice shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InputDegradation {
+    pub(crate) budget_tokens: u64,
+    pub(crate) estimated_tokens_before: u64,
+    pub(crate) estimated_tokens_after: u64,
+    pub(crate) stages: Vec<DegradationStage>,
+}
+
+/// One step of the ladder. Indices are positions in the submitted input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub(crate) enum DegradationStage {
+    #[serde(rename_all = "camelCase")]
+    Downscale { images: Vec<DownscaledImage> },
+    #[serde(rename_all = "camelCase")]
+    Drop {
+        keep_recent: usize,
+        dropped: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct DownscaledImage {
+    pub(crate) index: usize,
+    pub(crate) from: [u32; 2],
+    pub(crate) to: [u32; 2],
+}
+
+impl InputDegradation {
+    /// A one-paragraph account of what was reduced.
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        for stage in &self.stages {
+            match stage {
+                DegradationStage::Downscale { images } => {
+                    for DownscaledImage { index, from, to } in images {
+                        parts.push(format!(
+                            "downscaled item {} from {}x{} to {}x{}",
+                            index + 1,
+                            from[0],
+                            from[1],
+                            to[0],
+                            to[1]
+                        ));
+                    }
+                }
+                DegradationStage::Drop {
+                    keep_recent,
+                    dropped,
+                } => {
+                    let dropped = dropped
+                        .iter()
+                        .map(|index| (index + 1).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    parts.push(format!(
+                        "dropped item(s) {dropped}, keeping the {keep_recent} most recent image(s)"
+                    ));
+                }
+            }
+        }
+        format!(
+            "Reduced image input to fit the context budget (about {} of {} tokens, down from {}): {}. The original images are still saved in the session.",
+            self.estimated_tokens_after,
+            self.budget_tokens,
+            self.estimated_tokens_before,
+            parts.join("; ")
+        )
+    }
+}
+
+struct Item {
+    item: InputItem,
+    /// Encoded bytes and dimensions of an image item, when they could be
+    /// read. Pixels are only decoded to downscale.
+    image: Option<(Vec<u8>, [u32; 2])>,
+}
+
+impl Item {
+    fn new(item: &InputItem) -> Self {
+        let image = image_bytes(item).and_then(|bytes| {
+            let (width, height) = ImageReader::new(Cursor::new(&bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()?;
+            Some((bytes, [width, height]))
+        });
+        Self {
+            item: item.clone(),
+            image,
+        }
+    }
+
+    fn is_image(&self) -> bool {
+        !matches!(self.item, InputItem::Text { .. })
+    }
+
+    fn tokens(&self) -> u64 {
+        match (&self.item, &self.image) {
+            (InputItem::Text { text }, _) => (text.len() as u64).div_ceil(4),
+            (_, Some((_, size))) => pixels(*size).div_ceil(PIXELS_PER_TOKEN),
+            (_, None) => UNKNOWN_IMAGE_TOKENS,
+        }
+    }
+}
+
+/// Reduce the images in `input` when sending it on top of `used_tokens`
+/// would exceed `budget_tokens` but the input's text alone would not.
+/// Returns `None` when there is no such pressure or nothing could be done.
+pub(crate) fn degrade(
+    input: &[InputItem],
+    used_tokens: u64,
+    budget_tokens: u64,
+    ladder: ImageDegradation,
+) -> Option<(Vec<InputItem>, InputDegradation)> {
+    let mut items: Vec<Item> = input.iter().map(Item::new).collect();
+    let estimate = |items: &[Item]| used_tokens + items.iter().map(Item::tokens).sum::<u64>();
+    let before = estimate(&items);
+    let text_only = used_tokens
+        + items
+            .iter()
+            .filter(|item| !item.is_image())
+            .map(Item::tokens)
+            .sum::<u64>();
+    if before <= budget_tokens || text_only > budget_tokens {
+        return None;
+    }
+
+    let mut stages = Vec::new();
+    if ladder.max_pixels > 0 {
+        let images: Vec<DownscaledImage> = items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                downscale(item, ladder.max_pixels).map(|(from, to)| DownscaledImage {
+                    index,
+                    from,
+                    to,
+                })
+            })
+            .collect();
+        if !images.is_empty() {
+            stages.push(DegradationStage::Downscale { images });
+        }
+    }
+
+    if estimate(&items) > budget_tokens {
+        let image_indices: Vec<usize> = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.is_image())
+            .map(|(index, _)| index)
+            .collect();
+        let drop_count = image_indices.len().saturating_sub(ladder.keep_recent);
+        if drop_count > 0 {
+            stages.push(DegradationStage::Drop {
+                keep_recent: ladder.keep_recent,
+                dropped: image_indices[..drop_count].to_vec(),
+            });
+        }
+    }
+
+    if stages.is_empty() {
+        return None;
+    }
+    let dropped: &[usize] = stages
+        .iter()
+        .find_map(|stage| match stage {
+            DegradationStage::Drop { dropped, .. } => Some(dropped.as_slice()),
+            DegradationStage::Downscale { .. } => None,
+        })
+        .unwrap_or_default();
+    let kept: Vec<Item> = items
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !dropped.contains(index))
+        .map(|(_, item)| item)
+        .collect();
+    let degradation = InputDegradation {
+        budget_tokens,
+        estimated_tokens_before: before,
+        estimated_tokens_after: estimate(&kept),
+        stages,
+    };
+    Some((
+        kept.into_iter().map(|item| item.item).collect(),
+        degradation,
+    ))
+}
+
+/// Shrink `item` in place to at most `max_pixels`, returning its old and
+/// new dimensions if it was larger. The image keeps its format when it can
+/// be written back in it, and becomes a PNG otherwise.
+fn downscale(item: &mut Item, max_pixels: u64) -> Option<([u32; 2], [u32; 2])> {
+    let (bytes, from) = item.image.as_ref()?;
+    let from = *from;
+    let pixels = pixels(from);
+    if pixels <= max_pixels {
+        return None;
+    }
+    let scale = (max_pixels as f64 / pixels as f64).sqrt();
+    let to = from.map(|side| ((f64::from(side) * scale) as u32).max(1));
+    let resized =
+        image::load_from_memory(bytes)
+            .ok()?
+            .resize_exact(to[0], to[1], FilterType::Triangle);
+    let format = image::guess_format(bytes)
+        .ok()
+        .filter(|format| format.writing_enabled())
+        .unwrap_or(ImageFormat::Png);
+    let mut encoded = Cursor::new(Vec::new());
+    resized.write_to(&mut encoded, format).ok()?;
+    let encoded = encoded.into_inner();
+    let data = base64::engine::general_purpose::STANDARD.encode(&encoded);
+    *item = Item {
+        item: InputItem::Image {
+            image_url: format!("data:{};base64,{data}", format.to_mime_type()),
+        },
+        image: Some((encoded, to)),
+    };
+    Some((from, to))
+}
+
+fn pixels([width, height]: [u32; 2]) -> u64 {
+    u64::from(width) * u64::from(height)
+}
+
+fn image_bytes(item: &InputItem) -> Option<Vec<u8>> {
+    match item {
+        InputItem::Text { .. } => None,
+        InputItem::LocalImage { path } => std::fs::read(path).ok(),
+        InputItem::Image { image_url } => {
+            let (_, data) = image_url.strip_prefix("data:")?.split_once(";base64,")?;
+            base64::engine::general_purpose::STANDARD.decode(data).ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const LADDER: ImageDegradation = ImageDegradation {
+        max_pixels: 100 * 100,
+        keep_recent: 1,
+    };
+
+    /// A text item followed by three 300x200 PNG files.
+    fn input(dir: &TempDir) -> Vec<InputItem> {
+        let mut input = vec![InputItem::Text {
+            text: "what changed between these?".to_string(),
+        }];
+        for name in ["a.png", "b.png", "c.png"] {
+            let path = dir.path().join(name);
+            RgbImage::new(300, 200).save(&path).expect("write fixture");
+            input.push(InputItem::LocalImage { path });
+        }
+        input
+    }
+
+    fn downscaled(index: usize) -> DownscaledImage {
+        DownscaledImage {
+            index,
+            from: [300, 200],
+            to: [122, 81],
+        }
+    }
+
+    #[test]
+    fn leaves_input_that_fits_or_overflows_on_text_alone() {
+        let dir = TempDir::new().expect("tempdir");
+        let input = input(&dir);
+
+        assert_eq!(degrade(&input, 0, 1_000, LADDER), None);
+        assert_eq!(degrade(&input, 1_000, 1_000, LADDER), None);
+    }
+
+    #[test]
+    fn downscales_before_dropping() {
+        let dir = TempDir::new().expect("tempdir");
+        let input = input(&dir);
+
+        // 3 * 80 image tokens before, 3 * 14 after downscaling.
+        let (degraded, degradation) = degrade(&input, 0, 60, LADDER).expect("degraded");
+        assert_eq!(
+            degradation,
+            InputDegradation {
+                budget_tokens: 60,
+                estimated_tokens_before: 247,
+                estimated_tokens_after: 49,
+                stages: vec![DegradationStage::Downscale {
+                    images: vec![downscaled(1), downscaled(2), downscaled(3)],
+                }],
+            }
+        );
+        assert_eq!(degraded.len(), 4);
+
+        let (degraded, degradation) = degrade(&input, 0, 30, LADDER).expect("degraded");
+        assert_eq!(
+            degradation,
+            InputDegradation {
+                budget_tokens: 30,
+                estimated_tokens_before: 247,
+                estimated_tokens_after: 21,
+                stages: vec![
+                    DegradationStage::Downscale {
+                        images: vec![downscaled(1), downscaled(2), downscaled(3)],
+                    },
+                    DegradationStage::Drop {
+                        keep_recent: 1,
+                        dropped: vec![1, 2],
+                    },
+                ],
+            }
+        );
+        assert_eq!(degraded[0], input[0]);
+        assert_eq!(degraded.len(), 2);
+    }
+
+    #[test]
+    fn downscaled_images_keep_their_format() {
+        let dir = TempDir::new().expect("tempdir");
+        let path = dir.path().join("photo.jpg");
+        RgbImage::new(300, 200).save(&path).expect("write fixture");
+        let input = vec![InputItem::LocalImage { path }];
+
+        let (degraded, _) = degrade(&input, 0, 60, LADDER).expect("degraded");
+        let [InputItem::Image { image_url }] = degraded.as_slice() else {
+            panic!("expected one inline image, got {degraded:?}");
+        };
+        assert!(image_url.starts_with("data:image/jpeg;base64,"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Which [`INSTRUMENTATION_CHECKLIST`] actions each running task has
+//! emitted, so a refactor that silently drops an emit site shows up as an
+//! `instrumentation_gap` when the task is torn down instead of as a blank
+//! timeline.
+//!
+//! A fast task can be torn down before `spawn_task` has emitted its
+//! `task_spawned`, so a task torn down while still spawning is only checked
+//! once the spawn finishes.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+#[cfg(test)]
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+use crate::session_features::FinishedTask;
+use crate::session_features::INSTRUMENTATION_CHECKLIST;
+use crate::session_features::missing_instrumentation;
+
+/// A task torn down without some of its required actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InstrumentationGap {
+    pub(crate) missing: Vec<&'static str>,
+    pub(crate) fired: Vec<&'static str>,
+    pub(crate) aborted: bool,
+}
+
+#[derive(Default)]
+struct TaskCoverage {
+    fired: BTreeSet<&'static str>,
+    spawning: bool,
+    torn_down: Option<FinishedTask>,
+}
+
+impl TaskCoverage {
+    fn gap(self, task: FinishedTask) -> Option<InstrumentationGap> {
+        let missing = missing_instrumentation(task, &self.fired);
+        (!missing.is_empty()).then(|| InstrumentationGap {
+            missing,
+            fired: self.fired.into_iter().collect(),
+            aborted: task.aborted,
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct InstrumentationCoverage {
+    /// By `subId`.
+    tasks: Mutex<HashMap<String, TaskCoverage>>,
+    /// Action types whose emission is suppressed, to simulate a regression.
+    #[cfg(test)]
+    skipped: Mutex<HashSet<String>>,
+}
+
+impl InstrumentationCoverage {
+    /// Start tracking `sub_id`, whose spawn is under way.
+    pub(crate) fn spawning(&self, sub_id: &str) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.insert(
+                sub_id.to_string(),
+                TaskCoverage {
+                    spawning: true,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    /// The spawn of `sub_id` finished; reports its gap if it was already
+    /// torn down.
+    pub(crate) fn spawned(&self, sub_id: &str) -> Option<InstrumentationGap> {
+        let mut tasks = self.tasks.lock().ok()?;
+        let coverage = tasks.get_mut(sub_id)?;
+        coverage.spawning = false;
+        let task = coverage.torn_down?;
+        tasks.remove(sub_id)?.gap(task)
+    }
+
+    /// Note an emitted action if it is on the checklist and names a tracked
+    /// task.
+    pub(crate) fn record(&self, action_type: &str, action: &Value) {
+        let Some(point) = INSTRUMENTATION_CHECKLIST
+            .iter()
+            .find(|point| point.action_type == action_type)
+        else {
+            return;
+        };
+        let Some(sub_id) = action.get("subId").and_then(Value::as_str) else {
+            return;
+        };
+        if let Ok(mut tasks) = self.tasks.lock()
+            && let Some(coverage) = tasks.get_mut(sub_id)
+        {
+            coverage.fired.insert(point.action_type);
+        }
+    }
+
+    /// `sub_id` was torn down as `task`: forget it and report what it
+    /// failed to emit, unless its spawn is still finishing.
+    pub(crate) fn torn_down(&self, sub_id: &str, task: FinishedTask) -> Option<InstrumentationGap> {
+        let mut tasks = self.tasks.lock().ok()?;
+        if let Some(coverage) = tasks.get_mut(sub_id)
+            && coverage.spawning
+        {
+            coverage.torn_down = Some(task);
+            return None;
+        }
+        tasks.remove(sub_id).unwrap_or_default().gap(task)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn skip(&self, action_type: &str) {
+        if let Ok(mut skipped) = self.skipped.lock() {
+            skipped.insert(action_type.to_string());
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_skipped(&self, action_type: &str) -> bool {
+        self.skipped
+            .lock()
+            .is_ok_and(|skipped| skipped.contains(action_type))
+    }
+}
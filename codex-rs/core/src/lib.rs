@@ -5,7 +5,9 @@
 // the TUI or the tracing stack).
 #![deny(clippy::print_stdout, clippy::print_stderr)]
 
+mod annotations;
 mod apply_patch;
+mod approvals;
 pub mod auth;
 pub mod bash;
 mod chat_completions;
@@ -21,16 +23,26 @@ pub mod config_edit;
 pub mod config_loader;
 pub mod config_profile;
 pub mod config_types;
+mod content_scope;
+mod conversation_end;
 mod conversation_history;
+mod conversation_lease;
 pub mod custom_prompts;
+mod digest;
 mod environment_context;
 pub mod error;
+mod event_stream;
+pub use event_stream::EVENT_STREAM_CAPACITY;
+pub use event_stream::EventStream;
 pub mod exec;
 mod exec_command;
 pub mod exec_env;
 pub mod executor;
 mod flags;
+mod focus;
 pub mod git_info;
+mod image_degradation;
+mod instrumentation;
 pub mod landlock;
 pub mod mcp;
 mod mcp_connection_manager;
@@ -38,7 +50,10 @@ mod mcp_tool_call;
 mod message_history;
 mod model_provider_info;
 pub mod parse_command;
+mod task_recovery;
+mod task_revert;
 mod truncate;
+mod turn_templates;
 mod unified_exec;
 mod user_instructions;
 pub use model_provider_info::BUILT_IN_OSS_MODEL_PROVIDER_ID;
@@ -49,6 +64,7 @@ pub use model_provider_info::create_oss_provider_with_base_url;
 mod conversation_manager;
 mod event_mapping;
 pub mod review_format;
+mod review_slices;
 pub use codex_protocol::protocol::InitialHistory;
 pub use conversation_manager::ConversationManager;
 pub use conversation_manager::NewConversation;
@@ -60,15 +76,19 @@ pub mod model_family;
 mod openai_model_info;
 mod openai_tools;
 pub mod project_doc;
+pub mod retry;
 mod rollout;
 pub(crate) mod safety;
 pub mod seatbelt;
+mod session_features;
 pub mod shell;
+mod shutdown;
+mod snapshot_consistency;
 pub mod spawn;
 pub mod terminal;
 mod tools;
 pub mod turn_diff_tracker;
-mod visualizer;
+pub mod visualizer;
 pub use rollout::ARCHIVED_SESSIONS_SUBDIR;
 pub use rollout::INTERACTIVE_SESSION_SOURCES;
 pub use rollout::RolloutRecorder;
@@ -80,7 +100,9 @@ pub use rollout::list::ConversationsPage;
 pub use rollout::list::Cursor;
 mod function_tool;
 mod state;
+mod strict_telemetry;
 mod tasks;
+mod telemetry_config;
 mod user_notification;
 pub mod util;
 
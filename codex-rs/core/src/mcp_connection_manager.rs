@@ -17,6 +17,7 @@ use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 use codex_mcp_client::McpClient;
+use codex_mcp_client::ProgressSender;
 use codex_rmcp_client::OAuthCredentialsStoreMode;
 use codex_rmcp_client::RmcpClient;
 use mcp_types::ClientCapabilities;
@@ -153,10 +154,20 @@ impl McpClientAdapter {
         name: String,
         arguments: Option<serde_json::Value>,
         timeout: Option<Duration>,
+        progress: Option<ProgressSender>,
     ) -> Result<mcp_types::CallToolResult> {
-        match self {
-            McpClientAdapter::Legacy(client) => client.call_tool(name, arguments, timeout).await,
-            McpClientAdapter::Rmcp(client) => client.call_tool(name, arguments, timeout).await,
+        match (self, progress) {
+            (McpClientAdapter::Legacy(client), Some(progress)) => {
+                client
+                    .call_tool_with_progress(name, arguments, timeout, progress)
+                    .await
+            }
+            (McpClientAdapter::Legacy(client), None) => {
+                client.call_tool(name, arguments, timeout).await
+            }
+            // The rmcp client does not ask for progress yet; dropping the
+            // sender tells the caller none is coming.
+            (McpClientAdapter::Rmcp(client), _) => client.call_tool(name, arguments, timeout).await,
         }
     }
 }
@@ -327,12 +338,14 @@ impl McpConnectionManager {
             .collect()
     }
 
-    /// Invoke the tool indicated by the (server, tool) pair.
+    /// Invoke the tool indicated by the (server, tool) pair. Progress
+    /// notifications the server sends for the call go to `progress`.
     pub async fn call_tool(
         &self,
         server: &str,
         tool: &str,
         arguments: Option<serde_json::Value>,
+        progress: Option<ProgressSender>,
     ) -> Result<mcp_types::CallToolResult> {
         let managed = self
             .clients
@@ -342,7 +355,7 @@ impl McpConnectionManager {
         let timeout = managed.tool_timeout;
 
         client
-            .call_tool(tool.to_string(), arguments, timeout)
+            .call_tool(tool.to_string(), arguments, timeout, progress)
             .await
             .with_context(|| format!("tool call failed for `{server}/{tool}`"))
     }
@@ -1,5 +1,9 @@
-use std::time::Instant;
+use std::time::Duration;
 
+use mcp_types::ProgressNotificationParams;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tracing::error;
 
 use crate::codex::Session;
@@ -11,6 +15,14 @@ use crate::protocol::McpToolCallEndEvent;
 use codex_protocol::models::FunctionCallOutputPayload;
 use codex_protocol::models::ResponseInputItem;
 
+/// `tool_call_progress` events of one call are at least this far apart;
+/// updates in between are only counted.
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A `tool_call_progress` event still queued this long after it was
+/// emitted is dropped; a later update or the call finishing supersedes it.
+const PROGRESS_EVENT_TTL: Duration = Duration::from_secs(30);
+
 /// Handles the specified tool call dispatches the appropriate
 /// `McpToolCallBegin` and `McpToolCallEnd` events to the `Session`.
 pub(crate) async fn handle_mcp_tool_call(
@@ -52,13 +64,30 @@ pub(crate) async fn handle_mcp_tool_call(
         invocation: invocation.clone(),
     });
     notify_mcp_tool_call_event(sess, sub_id, tool_call_begin_event).await;
+    let call = json!({
+        "subId": sub_id,
+        "callId": call_id,
+        "server": server,
+        "tool": tool_name,
+    });
+    sess.visualizer
+        .emit("tool_call_started", call.clone(), None)
+        .await;
 
     let start = Instant::now();
-    // Perform the tool call.
-    let result = sess
-        .call_tool(&server, &tool_name, arguments_value.clone())
-        .await
-        .map_err(|e| format!("tool call error: {e}"));
+    // Perform the tool call, forwarding its progress notifications while it
+    // runs. The sender is dropped when the call returns, ending the forward.
+    let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+    let (result, progress_updates) = tokio::join!(
+        sess.call_tool(
+            &server,
+            &tool_name,
+            arguments_value.clone(),
+            Some(progress_tx)
+        ),
+        forward_progress(sess, &call, start, PROGRESS_EVENT_INTERVAL, progress_rx),
+    );
+    let result = result.map_err(|e| format!("tool call error: {e}"));
     let tool_call_end_event = EventMsg::McpToolCallEnd(McpToolCallEndEvent {
         call_id: call_id.clone(),
         invocation,
@@ -67,6 +96,13 @@ pub(crate) async fn handle_mcp_tool_call(
     });
 
     notify_mcp_tool_call_event(sess, sub_id, tool_call_end_event.clone()).await;
+    let mut finished = call;
+    finished["durationMs"] = json!(start.elapsed().as_millis());
+    finished["success"] = json!(result.as_ref().is_ok_and(|r| r.is_error != Some(true)));
+    finished["progressUpdates"] = json!(progress_updates);
+    sess.visualizer
+        .emit("tool_call_finished", finished, None)
+        .await;
 
     ResponseInputItem::McpToolCallOutput { call_id, result }
 }
@@ -78,3 +114,136 @@ async fn notify_mcp_tool_call_event(sess: &Session, sub_id: &str, event: EventMs
     })
     .await;
 }
+
+/// Rate limit for one call's progress events.
+struct ProgressThrottle {
+    interval: Duration,
+    last_emitted: Option<Instant>,
+    updates: u64,
+}
+
+impl ProgressThrottle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_emitted: None,
+            updates: 0,
+        }
+    }
+
+    /// Count an update received at `now` and decide whether to emit it.
+    fn admit(&mut self, now: Instant) -> bool {
+        self.updates += 1;
+        let emit = self
+            .last_emitted
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.interval);
+        if emit {
+            self.last_emitted = Some(now);
+        }
+        emit
+    }
+}
+
+/// Emit `tool_call_progress` for the updates in `progress`, throttled to
+/// one per `interval`, until the channel closes. Returns how many updates
+/// were received.
+async fn forward_progress(
+    sess: &Session,
+    call: &serde_json::Value,
+    start: Instant,
+    interval: Duration,
+    mut progress: mpsc::UnboundedReceiver<ProgressNotificationParams>,
+) -> u64 {
+    let mut throttle = ProgressThrottle::new(interval);
+    while let Some(update) = progress.recv().await {
+        let now = Instant::now();
+        if !throttle.admit(now) {
+            continue;
+        }
+        let mut action = call.clone();
+        action["progress"] = json!(update.progress);
+        action["update"] = json!(throttle.updates);
+        action["elapsedMs"] = json!(now.saturating_duration_since(start).as_millis());
+        if let Some(total) = update.total.filter(|total| *total > 0.0) {
+            action["total"] = json!(total);
+            action["fraction"] = json!((update.progress / total).clamp(0.0, 1.0));
+        }
+        if let Some(message) = update.message {
+            action["message"] = json!(message);
+        }
+        sess.visualizer
+            .emit_with_ttl("tool_call_progress", action, None, PROGRESS_EVENT_TTL)
+            .await;
+    }
+    throttle.updates
+}
+
+#[cfg(test)]
+mod tests {
+    use mcp_types::ProgressToken;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::codex::make_session_and_context;
+
+    fn update(progress: f64) -> ProgressNotificationParams {
+        ProgressNotificationParams {
+            message: None,
+            progress,
+            progress_token: ProgressToken::Integer(1),
+            total: Some(6.0),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn progress_is_throttled_per_call_and_every_update_is_counted() {
+        let (sess, _tc) = make_session_and_context();
+        let call =
+            json!({ "subId": "sub-1", "callId": "call-1", "server": "build", "tool": "run" });
+        let (tx, rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+        let producer = async move {
+            for (at_ms, progress) in [
+                (0, 1.0),
+                (100, 2.0),
+                (200, 3.0),
+                (600, 4.0),
+                (700, 5.0),
+                (1200, 6.0),
+            ] {
+                tokio::time::sleep_until(start + Duration::from_millis(at_ms)).await;
+                let _ = tx.send(update(progress));
+            }
+        };
+
+        let ((), updates) = tokio::join!(
+            producer,
+            forward_progress(&sess, &call, start, Duration::from_millis(500), rx)
+        );
+
+        let emitted: Vec<Value> = sess
+            .visualizer
+            .recent_events()
+            .into_iter()
+            .filter(|event| event.action_type == "tool_call_progress")
+            .map(|event| {
+                json!({
+                    "progress": event.action["progress"],
+                    "update": event.action["update"],
+                    "elapsedMs": event.action["elapsedMs"],
+                    "fraction": event.action["fraction"],
+                })
+            })
+            .collect();
+        assert_eq!(updates, 6);
+        assert_eq!(
+            emitted,
+            vec![
+                json!({ "progress": 1.0, "update": 1, "elapsedMs": 0, "fraction": 1.0 / 6.0 }),
+                json!({ "progress": 4.0, "update": 4, "elapsedMs": 600, "fraction": 4.0 / 6.0 }),
+                json!({ "progress": 6.0, "update": 6, "elapsedMs": 1200, "fraction": 1.0 }),
+            ]
+        );
+    }
+}
@@ -245,6 +245,12 @@ impl ModelProviderInfo {
             .map(Duration::from_millis)
             .unwrap_or(Duration::from_millis(DEFAULT_STREAM_IDLE_TIMEOUT_MS))
     }
+
+    /// Whether requests to this provider carry [`crate::Prompt::seed`]. The
+    /// Responses API has no seed parameter.
+    pub fn accepts_seed(&self) -> bool {
+        matches!(self.wire_api, WireApi::Chat)
+    }
 }
 
 const DEFAULT_OLLAMA_PORT: u32 = 11434;
@@ -0,0 +1,328 @@
+//! Retry schedules shared by everything that reconnects or resends.
+//!
+//! A [`RetryPolicy`] describes the whole schedule: the delay before the
+//! first retry, how it grows, the cap on any one delay, how it is jittered,
+//! and how many attempts are made in total. [`RetryPolicy::run_with_retry`]
+//! drives an operation through it. Delays are slept on the tokio clock, so
+//! under `tokio::time::pause` a schedule runs instantly and exactly.
+
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Each further retry waits this many times longer than the one before.
+    pub multiplier: f64,
+    /// No delay, jitter included, is longer than this.
+    pub max_delay: Duration,
+    pub jitter: Jitter,
+    /// Attempts in total, counting the first. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+/// How a delay is randomized before the cap applies.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Jitter {
+    /// Wait exactly the computed delay.
+    #[default]
+    None,
+    /// Wait anywhere between zero and the computed delay.
+    Full,
+    /// Wait the computed delay scaled by a random factor within this
+    /// fraction of 1, e.g. `0.1` for 90–110%.
+    Proportional(f64),
+}
+
+impl RetryPolicy {
+    /// The visualizer forwarders' reconnect schedule: from 100ms, doubling
+    /// up to 30s, each delay within 25% either way so sessions on one relay
+    /// do not reconnect in lockstep, for as long as it takes.
+    pub const VISUALIZER_RECONNECT: Self = Self {
+        initial_delay: Duration::from_millis(100),
+        multiplier: 2.0,
+        max_delay: Duration::from_secs(30),
+        jitter: Jitter::Proportional(0.25),
+        max_attempts: None,
+    };
+
+    /// The default schedule of a `[retry.task]` table: three attempts in
+    /// all, 1s and then 2s apart, each delay within 25% either way.
+    pub const TASK: Self = Self {
+        initial_delay: Duration::from_secs(1),
+        multiplier: 2.0,
+        max_delay: Duration::from_secs(30),
+        jitter: Jitter::Proportional(0.25),
+        max_attempts: Some(3),
+    };
+
+    /// Whether the policy allows an `attempt`th attempt (1-based).
+    pub fn allows(&self, attempt: u32) -> bool {
+        self.max_attempts
+            .is_none_or(|max_attempts| attempt <= max_attempts.max(1))
+    }
+
+    /// The delay before the `retry`th retry (1-based).
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay_with(retry, &mut rand::rng())
+    }
+
+    /// [`Self::delay`], drawing jitter from `rng`.
+    pub fn delay_with(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        let exponent = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let cap = self.max_delay.as_secs_f64();
+        // `min` also maps a NaN from a nonsensical multiplier to the cap.
+        let base = (self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent)).min(cap);
+        let base = base.max(0.0);
+        let jittered = match self.jitter {
+            Jitter::None => base,
+            Jitter::Full => rng.random_range(0.0..=base),
+            Jitter::Proportional(fraction) => {
+                let fraction = if fraction.is_nan() {
+                    0.0
+                } else {
+                    fraction.clamp(0.0, 1.0)
+                };
+                base * rng.random_range(1.0 - fraction..=1.0 + fraction)
+            }
+        };
+        Duration::try_from_secs_f64(jittered)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Run `op` until it succeeds, fails with an error `is_retryable`
+    /// rejects, or the policy runs out of attempts; the last error is
+    /// returned. `op` is passed the 1-based attempt number.
+    pub async fn run_with_retry<T, E, F, Fut>(
+        &self,
+        op: F,
+        is_retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.resume_with_retry(0, op, is_retryable, |_, _| {}).await
+    }
+
+    /// [`Self::run_with_retry`] for a caller that already retried `retries`
+    /// times: the delays continue from there, while attempts are counted
+    /// from 1 again. `on_retry` is passed each error about to be retried
+    /// and the delay before the retry.
+    pub async fn resume_with_retry<T, E, F, Fut>(
+        &self,
+        retries: u32,
+        mut op: F,
+        is_retryable: impl Fn(&E) -> bool,
+        mut on_retry: impl FnMut(&E, Duration),
+    ) -> Result<T, E>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op(attempt).await {
+                Ok(value) => return Ok(value),
+                Err(err) if is_retryable(&err) && self.allows(attempt + 1) => {
+                    let delay = self.delay(retries.saturating_add(attempt));
+                    on_retry(&err, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use pretty_assertions::assert_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn exponential(max_attempts: Option<u32>) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_millis(500),
+            jitter: Jitter::None,
+            max_attempts,
+        }
+    }
+
+    fn random_policy(rng: &mut StdRng) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(rng.random_range(0..10_000)),
+            multiplier: rng.random_range(0.0..10.0),
+            max_delay: Duration::from_millis(rng.random_range(0..60_000)),
+            jitter: match rng.random_range(0..3) {
+                0 => Jitter::None,
+                1 => Jitter::Full,
+                _ => Jitter::Proportional(rng.random_range(0.0..2.0)),
+            },
+            max_attempts: match rng.random_range(0..4) {
+                0 => None,
+                _ => Some(rng.random_range(0..8)),
+            },
+        }
+    }
+
+    /// Attempt times, relative to the first, of a run whose op always fails.
+    async fn failing_attempts(policy: RetryPolicy) -> Vec<Duration> {
+        let start = Instant::now();
+        let attempts = RefCell::new(Vec::new());
+        let result: Result<(), ()> = policy
+            .run_with_retry(
+                |_| {
+                    attempts.borrow_mut().push(start.elapsed());
+                    async { Err(()) }
+                },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Err(()));
+        attempts.into_inner()
+    }
+
+    #[test]
+    fn exponential_delays_grow_until_the_cap() {
+        let policy = exponential(None);
+        let delays: Vec<Duration> = (1..=5).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 500, 500]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn visualizer_reconnect_doubles_from_a_tenth_of_a_second_to_thirty() {
+        let unjittered = RetryPolicy {
+            jitter: Jitter::None,
+            ..RetryPolicy::VISUALIZER_RECONNECT
+        };
+        let delays: Vec<Duration> = (1..=12).map(|retry| unjittered.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [
+                100, 200, 400, 800, 1_600, 3_200, 6_400, 12_800, 25_600, 30_000, 30_000, 30_000
+            ]
+            .map(Duration::from_millis)
+            .to_vec()
+        );
+        assert!(RetryPolicy::VISUALIZER_RECONNECT.allows(u32::MAX));
+
+        let mut rng = StdRng::seed_from_u64(0x0b5e);
+        for (retry, delay) in (1..=12).zip(delays) {
+            let jittered = RetryPolicy::VISUALIZER_RECONNECT.delay_with(retry, &mut rng);
+            assert!(
+                jittered >= delay.mul_f64(0.75) && jittered <= delay.mul_f64(1.25),
+                "retry {retry} waited {jittered:?}, not within 25% of {delay:?}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runs_on_the_schedule_and_stops_at_max_attempts() {
+        assert_eq!(
+            failing_attempts(exponential(Some(4))).await,
+            [0, 100, 300, 700].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn non_retryable_errors_and_successes_end_the_run() {
+        let policy = exponential(None);
+        let mut calls = 0;
+        let result: Result<(), &str> = policy
+            .run_with_retry(
+                |_| {
+                    calls += 1;
+                    async { Err("fatal") }
+                },
+                |err| *err != "fatal",
+            )
+            .await;
+        assert_eq!((result, calls), (Err("fatal"), 1));
+
+        let result: Result<u32, &str> = policy
+            .run_with_retry(
+                |attempt| async move {
+                    if attempt < 3 {
+                        Err("transient")
+                    } else {
+                        Ok(attempt)
+                    }
+                },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Ok(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumed_runs_continue_the_schedule() {
+        let mut delays = Vec::new();
+        let result: Result<(), u32> = exponential(Some(3))
+            .resume_with_retry(
+                2,
+                |attempt| async move { Err(attempt) },
+                |_| true,
+                |attempt, delay| delays.push((*attempt, delay)),
+            )
+            .await;
+        assert_eq!(
+            (result, delays),
+            (
+                Err(3),
+                vec![
+                    (1, Duration::from_millis(400)),
+                    (2, Duration::from_millis(500)),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn delays_never_exceed_the_cap() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..1_000 {
+            let policy = random_policy(&mut rng);
+            for retry in [1, 2, 3, 5, 8, 13, 64, 1_000, u32::MAX] {
+                let delay = policy.delay_with(retry, &mut rng);
+                assert!(
+                    delay <= policy.max_delay,
+                    "{policy:?} waited {delay:?} before retry {retry}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn attempts_never_exceed_the_max() {
+        let mut rng = StdRng::seed_from_u64(0xa77e);
+        for _ in 0..200 {
+            let policy = RetryPolicy {
+                max_attempts: Some(rng.random_range(0..16)),
+                ..random_policy(&mut rng)
+            };
+            let attempts = failing_attempts(policy).await.len();
+            let max_attempts = policy.max_attempts.unwrap_or_default();
+            assert_eq!(attempts, max_attempts.max(1) as usize, "{policy:?}");
+        }
+    }
+}
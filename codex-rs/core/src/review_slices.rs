@@ -0,0 +1,181 @@
+//! Reviews of diffs too large for one review task run in slices of files.
+//!
+//! The session keeps a [`ReviewCursor`]: a hash of the ordered list of
+//! changed files and how many of them earlier slices fully reviewed. A
+//! review submitted with `continue` starts where the cursor points; one that
+//! finds the file list changed starts over from the first file, with a
+//! warning.
+
+use sha1::Digest;
+use sha1::Sha1;
+
+/// Where sliced reviews of the current diff got to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReviewCursor {
+    files_hash: String,
+    file_count: usize,
+    /// Files, in order, covered by finished slices.
+    reviewed: usize,
+    /// The slice a running review covers.
+    in_progress: Option<ReviewSlice>,
+}
+
+/// The files one review task covers. `first` and `last` are 1-based and
+/// inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReviewSlice {
+    pub(crate) first: usize,
+    pub(crate) last: usize,
+    pub(crate) total: usize,
+    pub(crate) files: Vec<String>,
+}
+
+impl ReviewSlice {
+    /// E.g. `files 41–80 of 312`.
+    pub(crate) fn label(&self) -> String {
+        format!("files {}–{} of {}", self.first, self.last, self.total)
+    }
+
+    /// Appended to the review prompt so the reviewer stays within the slice.
+    pub(crate) fn prompt_suffix(&self) -> String {
+        let files = self
+            .files
+            .iter()
+            .map(|file| format!("- {file}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "\n\nThis diff is reviewed in slices. Review only these files ({}) and ignore the rest of the diff:\n{files}",
+            self.label()
+        )
+    }
+}
+
+/// What the next review covers, as decided by [`plan`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct SlicePlan {
+    /// `None` reviews the whole diff in one task.
+    pub(crate) slice: Option<ReviewSlice>,
+    pub(crate) cursor: Option<ReviewCursor>,
+    pub(crate) warning: Option<String>,
+}
+
+/// Plan a review of the changed `files` in slices of `slice_files`,
+/// continuing from `cursor` when `resume` is set. Diffs that fit in one
+/// slice are only sliced when continuing.
+pub(crate) fn plan(
+    cursor: Option<&ReviewCursor>,
+    files: Vec<String>,
+    resume: bool,
+    slice_files: usize,
+) -> SlicePlan {
+    if slice_files == 0 || files.is_empty() || (!resume && files.len() <= slice_files) {
+        return SlicePlan::default();
+    }
+    let files_hash = format!("{:x}", Sha1::digest(files.join("\n").as_bytes()));
+    let (mut start, mut warning) = match cursor {
+        Some(cursor) if resume && cursor.files_hash != files_hash => (
+            0,
+            Some(format!(
+                "The diff changed since the last review slice ({} files then, {} now), so the review cursor was reset; reviewing from the first file.",
+                cursor.file_count,
+                files.len()
+            )),
+        ),
+        Some(cursor) if resume => (cursor.reviewed, None),
+        None if resume => (
+            0,
+            Some(
+                "There is no earlier review slice to continue; reviewing from the first file."
+                    .to_string(),
+            ),
+        ),
+        _ => (0, None),
+    };
+    if start >= files.len() {
+        start = 0;
+        warning = Some(
+            "Every file in the diff was already reviewed; reviewing from the first file again."
+                .to_string(),
+        );
+    }
+    let end = (start + slice_files).min(files.len());
+    let slice = ReviewSlice {
+        first: start + 1,
+        last: end,
+        total: files.len(),
+        files: files[start..end].to_vec(),
+    };
+    SlicePlan {
+        cursor: Some(ReviewCursor {
+            files_hash,
+            file_count: files.len(),
+            reviewed: start,
+            in_progress: Some(slice.clone()),
+        }),
+        slice: Some(slice),
+        warning,
+    }
+}
+
+impl ReviewCursor {
+    /// End the running slice, moving the cursor past it if the review
+    /// `completed`. Returns the slice that ended.
+    pub(crate) fn finish(&mut self, completed: bool) -> Option<ReviewSlice> {
+        let slice = self.in_progress.take()?;
+        if completed {
+            self.reviewed = slice.last;
+        }
+        Some(slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn files(count: usize) -> Vec<String> {
+        (1..=count).map(|n| format!("src/file{n}.rs")).collect()
+    }
+
+    fn covered(plan: &SlicePlan) -> Option<(usize, usize, usize)> {
+        plan.slice
+            .as_ref()
+            .map(|slice| (slice.first, slice.last, slice.total))
+    }
+
+    #[test]
+    fn small_diffs_are_not_sliced_unless_continuing() {
+        assert_eq!(plan(None, files(3), false, 3), SlicePlan::default());
+        assert_eq!(covered(&plan(None, files(3), true, 3)), Some((1, 3, 3)));
+    }
+
+    #[test]
+    fn an_interrupted_slice_is_reviewed_again() {
+        let mut cursor = plan(None, files(5), false, 2).cursor.expect("cursor");
+        cursor.finish(false);
+
+        assert_eq!(
+            covered(&plan(Some(&cursor), files(5), true, 2)),
+            Some((1, 2, 5))
+        );
+    }
+
+    #[test]
+    fn wraps_around_once_every_file_was_reviewed() {
+        let mut cursor = plan(None, files(3), true, 3).cursor.expect("cursor");
+        cursor.finish(true);
+
+        let next = plan(Some(&cursor), files(3), true, 3);
+        assert_eq!(covered(&next), Some((1, 3, 3)));
+        assert_eq!(
+            next.warning,
+            Some(
+                "Every file in the diff was already reviewed; reviewing from the first file again."
+                    .to_string()
+            )
+        );
+    }
+}
@@ -375,7 +375,10 @@ async fn read_head_and_tail(
             RolloutItem::TurnContext(_) => {
                 // Not included in `head`; skip.
             }
-            RolloutItem::Compacted(_) => {
+            RolloutItem::Compacted(_)
+            | RolloutItem::ReviewFindings(_)
+            | RolloutItem::TaskCheckpoint(_)
+            | RolloutItem::Annotation(_) => {
                 // Not included in `head`; skip.
             }
             RolloutItem::EventMsg(ev) => {
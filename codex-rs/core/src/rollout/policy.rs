@@ -9,9 +9,12 @@ pub(crate) fn is_persisted_response_item(item: &RolloutItem) -> bool {
         RolloutItem::ResponseItem(item) => should_persist_response_item(item),
         RolloutItem::EventMsg(ev) => should_persist_event_msg(ev),
         // Persist Codex executive markers so we can analyze flows (e.g., compaction, API turns).
-        RolloutItem::Compacted(_) | RolloutItem::TurnContext(_) | RolloutItem::SessionMeta(_) => {
-            true
-        }
+        RolloutItem::Compacted(_)
+        | RolloutItem::TurnContext(_)
+        | RolloutItem::SessionMeta(_)
+        | RolloutItem::ReviewFindings(_)
+        | RolloutItem::TaskCheckpoint(_)
+        | RolloutItem::Annotation(_) => true,
     }
 }
 
@@ -65,10 +68,17 @@ pub(crate) fn should_persist_event_msg(ev: &EventMsg) -> bool {
         | EventMsg::PatchApplyBegin(_)
         | EventMsg::PatchApplyEnd(_)
         | EventMsg::TurnDiff(_)
+        | EventMsg::TaskReverted(_)
+        | EventMsg::Digest(_)
+        | EventMsg::TurnExported(_)
+        | EventMsg::TaskRecoveredIncomplete(_)
+        | EventMsg::TelemetryConfigRejected(_)
+        | EventMsg::RecentEvents(_)
         | EventMsg::GetHistoryEntryResponse(_)
         | EventMsg::McpListToolsResponse(_)
         | EventMsg::ListCustomPromptsResponse(_)
         | EventMsg::PlanUpdate(_)
+        | EventMsg::ConversationEnded(_)
         | EventMsg::ShutdownComplete
         | EventMsg::ViewImageToolCall(_)
         | EventMsg::ConversationPath(_) => false,
@@ -27,6 +27,7 @@ use crate::config::Config;
 use crate::default_client::originator;
 use crate::git_info::collect_git_info;
 use codex_protocol::protocol::InitialHistory;
+use codex_protocol::protocol::ROLLOUT_FORMAT_VERSION;
 use codex_protocol::protocol::ResumedHistory;
 use codex_protocol::protocol::RolloutItem;
 use codex_protocol::protocol::RolloutLine;
@@ -137,6 +138,7 @@ impl RolloutRecorder {
                         cli_version: env!("CARGO_PKG_VERSION").to_string(),
                         instructions,
                         source,
+                        format_version: ROLLOUT_FORMAT_VERSION,
                     }),
                 )
             }
@@ -240,6 +242,15 @@ impl RolloutRecorder {
                     RolloutItem::EventMsg(_ev) => {
                         items.push(RolloutItem::EventMsg(_ev));
                     }
+                    RolloutItem::ReviewFindings(item) => {
+                        items.push(RolloutItem::ReviewFindings(item));
+                    }
+                    RolloutItem::TaskCheckpoint(item) => {
+                        items.push(RolloutItem::TaskCheckpoint(item));
+                    }
+                    RolloutItem::Annotation(item) => {
+                        items.push(RolloutItem::Annotation(item));
+                    }
                 },
                 Err(e) => {
                     warn!("failed to parse rollout line: {v:?}, error: {e}");
@@ -271,6 +282,28 @@ impl RolloutRecorder {
         self.rollout_path.clone()
     }
 
+    /// A recorder whose writer discards items and takes `delay` to
+    /// acknowledge each flush or shutdown.
+    #[cfg(test)]
+    pub(crate) fn slow_for_test(delay: std::time::Duration) -> Self {
+        let (tx, mut rx) = mpsc::channel::<RolloutCmd>(256);
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    RolloutCmd::AddItems(_) => {}
+                    RolloutCmd::Flush { ack } | RolloutCmd::Shutdown { ack } => {
+                        tokio::time::sleep(delay).await;
+                        let _ = ack.send(());
+                    }
+                }
+            }
+        });
+        Self {
+            tx,
+            rollout_path: PathBuf::from("/tmp/slow-rollout.jsonl"),
+        }
+    }
+
     pub async fn shutdown(&self) -> std::io::Result<()> {
         let (tx_done, rx_done) = oneshot::channel();
         match self.tx.send(RolloutCmd::Shutdown { ack: tx_done }).await {
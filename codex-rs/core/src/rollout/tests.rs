@@ -13,6 +13,7 @@ use time::macros::format_description;
 use uuid::Uuid;
 
 use crate::rollout::INTERACTIVE_SESSION_SOURCES;
+use crate::rollout::RolloutRecorder;
 use crate::rollout::list::ConversationItem;
 use crate::rollout::list::ConversationsPage;
 use crate::rollout::list::Cursor;
@@ -24,7 +25,9 @@ use codex_protocol::models::ContentItem;
 use codex_protocol::models::ResponseItem;
 use codex_protocol::protocol::CompactedItem;
 use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::InitialHistory;
 use codex_protocol::protocol::InputMessageKind;
+use codex_protocol::protocol::ROLLOUT_FORMAT_VERSION;
 use codex_protocol::protocol::RolloutItem;
 use codex_protocol::protocol::RolloutLine;
 use codex_protocol::protocol::SessionMeta;
@@ -533,6 +536,7 @@ async fn test_tail_includes_last_response_items() -> Result<()> {
                 originator: "test_originator".into(),
                 cli_version: "test_version".into(),
                 source: SessionSource::VSCode,
+                format_version: ROLLOUT_FORMAT_VERSION,
             },
             git: None,
         }),
@@ -617,6 +621,7 @@ async fn test_tail_handles_short_sessions() -> Result<()> {
                 originator: "test_originator".into(),
                 cli_version: "test_version".into(),
                 source: SessionSource::VSCode,
+                format_version: ROLLOUT_FORMAT_VERSION,
             },
             git: None,
         }),
@@ -702,6 +707,7 @@ async fn test_tail_skips_trailing_non_responses() -> Result<()> {
                 originator: "test_originator".into(),
                 cli_version: "test_version".into(),
                 source: SessionSource::VSCode,
+                format_version: ROLLOUT_FORMAT_VERSION,
             },
             git: None,
         }),
@@ -925,3 +931,62 @@ async fn test_source_filter_excludes_non_matching_sessions() {
         path.ends_with("rollout-2025-08-01T10-00-00-00000000-0000-0000-0000-00000000004d.jsonl")
     }));
 }
+
+#[tokio::test]
+async fn unversioned_rollout_resumes_with_review_findings() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("rollout.jsonl");
+    let uuid = Uuid::from_u128(7);
+    let ts = "2025-08-03T10-00-00";
+    let findings = serde_json::json!({
+        "findings": [{
+            "title": "Off by one",
+            "body": "The loop skips the last item.",
+            "confidence_score": 0.5,
+            "priority": 1,
+            "code_location": {
+                "absolute_file_path": "/repo/src/lib.rs",
+                "line_range": { "start": 3, "end": 7 },
+            },
+        }],
+    });
+    let lines = [
+        serde_json::json!({
+            "timestamp": ts,
+            "type": "session_meta",
+            "payload": {
+                "id": uuid,
+                "timestamp": ts,
+                "instructions": null,
+                "cwd": ".",
+                "originator": "test_originator",
+                "cli_version": "test_version",
+            },
+        }),
+        serde_json::json!({
+            "timestamp": ts,
+            "type": "review_findings",
+            "payload": findings,
+        }),
+    ];
+    let mut file = File::create(&path).unwrap();
+    for line in lines {
+        writeln!(file, "{line}").unwrap();
+    }
+    drop(file);
+
+    let InitialHistory::Resumed(resumed) =
+        RolloutRecorder::get_rollout_history(&path).await.unwrap()
+    else {
+        panic!("expected a resumed history");
+    };
+    let [
+        RolloutItem::SessionMeta(meta),
+        RolloutItem::ReviewFindings(item),
+    ] = resumed.history.as_slice()
+    else {
+        panic!("unexpected rollout items: {:?}", resumed.history);
+    };
+    assert_eq!(meta.meta.format_version, 0);
+    assert_eq!(serde_json::to_value(item).unwrap(), findings);
+}
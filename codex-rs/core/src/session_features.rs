@@ -0,0 +1,206 @@
+//! Optional behaviours a running session has enabled, advertised to the
+//! visualizer so consumers can render controls that match this build and
+//! configuration, and the instrumentation every task is expected to emit.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::config::Config;
+
+/// On/off state of every entry in [`FEATURE_REGISTRY`], keyed by name.
+pub(crate) type SessionFeatures = BTreeMap<String, bool>;
+
+pub(crate) struct FeatureSpec {
+    pub(crate) name: &'static str,
+    enabled: fn(&Config) -> bool,
+}
+
+/// Every feature name that may appear in a [`SessionFeatures`] map, kept in
+/// sorted order. Emit sites must go through [`resolve_features`] rather than
+/// spelling names out themselves.
+pub(crate) const FEATURE_REGISTRY: &[FeatureSpec] = &[
+    FeatureSpec {
+        name: "apply_patch_tool",
+        enabled: |config| config.include_apply_patch_tool,
+    },
+    FeatureSpec {
+        name: "compressed_frames",
+        enabled: |config| config.visualizer_compression,
+    },
+    FeatureSpec {
+        name: "event_batches",
+        enabled: |config| config.visualizer_batch.enabled,
+    },
+    FeatureSpec {
+        name: "event_chunks",
+        enabled: |config| config.visualizer_chunks.enabled,
+    },
+    // Guaranteed rather than configured: every `TurnAborted` and
+    // `task_aborted` of the tasks a spawn replaces is sent before that
+    // spawn's `task_spawned` and before anything the new task sends.
+    FeatureSpec {
+        name: "ordered_task_replacement",
+        enabled: |_| true,
+    },
+    FeatureSpec {
+        name: "plan_tool",
+        enabled: |config| config.include_plan_tool,
+    },
+    FeatureSpec {
+        name: "platform_sandbox",
+        enabled: |_| cfg!(any(target_os = "macos", target_os = "linux")),
+    },
+    FeatureSpec {
+        name: "raw_agent_reasoning",
+        enabled: |config| config.show_raw_agent_reasoning,
+    },
+    FeatureSpec {
+        name: "rmcp_client",
+        enabled: |config| config.use_experimental_use_rmcp_client,
+    },
+    FeatureSpec {
+        name: "streamable_shell_tool",
+        enabled: |config| config.use_experimental_streamable_shell_tool,
+    },
+    FeatureSpec {
+        name: "strict_cwd_validation",
+        enabled: |config| !config.warn_on_invalid_cwd,
+    },
+    FeatureSpec {
+        name: "unified_exec_tool",
+        enabled: |config| config.use_experimental_unified_exec_tool,
+    },
+    FeatureSpec {
+        name: "view_image_tool",
+        enabled: |config| config.include_view_image_tool,
+    },
+    FeatureSpec {
+        name: "web_search_tool",
+        enabled: |config| config.tools_web_search_request,
+    },
+];
+
+pub(crate) fn resolve_features(config: &Config) -> SessionFeatures {
+    FEATURE_REGISTRY
+        .iter()
+        .map(|spec| (spec.name.to_string(), (spec.enabled)(config)))
+        .collect()
+}
+
+/// What the [`INSTRUMENTATION_CHECKLIST`] needs to know about a task that
+/// is being torn down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FinishedTask {
+    /// Whether the task runs the model turn loop (`run_task`), which emits
+    /// `task_started` and a `tool_catalog_snapshot` per turn.
+    pub(crate) runs_turn_loop: bool,
+    pub(crate) aborted: bool,
+}
+
+pub(crate) struct InstrumentationPoint {
+    pub(crate) action_type: &'static str,
+    required: fn(FinishedTask) -> bool,
+}
+
+/// Visualizer actions a task must have emitted, under its `subId`, by the
+/// time it is torn down. A task aborted early may not have started, so only
+/// its spawn and abort are required.
+pub(crate) const INSTRUMENTATION_CHECKLIST: &[InstrumentationPoint] = &[
+    InstrumentationPoint {
+        action_type: "task_spawned",
+        required: |_| true,
+    },
+    InstrumentationPoint {
+        action_type: "task_started",
+        required: |task| task.runs_turn_loop && !task.aborted,
+    },
+    InstrumentationPoint {
+        action_type: "tool_catalog_snapshot",
+        required: |task| task.runs_turn_loop && !task.aborted,
+    },
+    InstrumentationPoint {
+        action_type: "task_completed",
+        required: |task| !task.aborted,
+    },
+    InstrumentationPoint {
+        action_type: "task_aborted",
+        required: |task| task.aborted,
+    },
+];
+
+/// Checklist action types `task` was required to emit but did not, in
+/// checklist order.
+pub(crate) fn missing_instrumentation(
+    task: FinishedTask,
+    fired: &BTreeSet<&'static str>,
+) -> Vec<&'static str> {
+    INSTRUMENTATION_CHECKLIST
+        .iter()
+        .filter(|point| (point.required)(task) && !fired.contains(point.action_type))
+        .map(|point| point.action_type)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigOverrides;
+    use crate::config::ConfigToml;
+    use pretty_assertions::assert_eq;
+
+    fn default_config() -> Config {
+        let codex_home = tempfile::tempdir().expect("create temp dir");
+        Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )
+        .expect("load default test config")
+    }
+
+    #[test]
+    fn registry_names_are_unique() {
+        let features = resolve_features(&default_config());
+        assert_eq!(features.len(), FEATURE_REGISTRY.len());
+    }
+
+    #[test]
+    fn features_follow_config_flags() {
+        let mut config = default_config();
+        config.include_plan_tool = false;
+        config.show_raw_agent_reasoning = false;
+        let before = resolve_features(&config);
+
+        config.include_plan_tool = true;
+        config.show_raw_agent_reasoning = true;
+        let after = resolve_features(&config);
+
+        let mut expected = before.clone();
+        expected.insert("plan_tool".to_string(), true);
+        expected.insert("raw_agent_reasoning".to_string(), true);
+        assert_eq!(after, expected);
+        assert_eq!(before.get("plan_tool"), Some(&false));
+        assert_eq!(before.get("raw_agent_reasoning"), Some(&false));
+    }
+
+    #[test]
+    fn turn_loop_points_are_only_required_of_completed_turn_loops() {
+        let fired = BTreeSet::from(["task_spawned"]);
+        let missing = |runs_turn_loop, aborted| {
+            missing_instrumentation(
+                FinishedTask {
+                    runs_turn_loop,
+                    aborted,
+                },
+                &fired,
+            )
+        };
+
+        assert_eq!(
+            missing(true, false),
+            vec!["task_started", "tool_catalog_snapshot", "task_completed"]
+        );
+        assert_eq!(missing(false, false), vec!["task_completed"]);
+        assert_eq!(missing(true, true), vec!["task_aborted"]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Ordered session teardown.
+//!
+//! Each stage runs only after the previous one has finished or timed out,
+//! so whatever a stage produces is covered by the flushes after it:
+//!
+//! 1. refuse new task spawns;
+//! 2. abort running tasks, so their `TurnAborted` events reach the rollout,
+//!    and background tasks;
+//! 3. send `EventMsg::ConversationEnded` and queue `conversation_ended`;
+//! 4. shut down the rollout writer;
+//! 5. wait until the visualizer has delivered everything queued so far;
+//! 6. queue the [`ShutdownReport`] as the session's last visualizer event,
+//!    close the visualizer and wait for it to deliver the report and close
+//!    its websocket.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+use tracing::info;
+use tracing::warn;
+
+use crate::codex::Session;
+use crate::conversation_end::ConversationEndGuard;
+use crate::protocol::ConversationEndReason;
+use crate::protocol::TurnAbortReason;
+use crate::visualizer::VisualizerStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ShutdownTimeouts {
+    pub(crate) drain_tasks: Duration,
+    pub(crate) flush_rollout: Duration,
+    pub(crate) flush_visualizer: Duration,
+    pub(crate) close_visualizer: Duration,
+}
+
+impl Default for ShutdownTimeouts {
+    fn default() -> Self {
+        Self {
+            drain_tasks: Duration::from_secs(5),
+            flush_rollout: Duration::from_secs(5),
+            flush_visualizer: Duration::from_secs(3),
+            close_visualizer: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ShutdownStage {
+    GateSpawns,
+    DrainTasks,
+    TerminalEvents,
+    FlushRollout,
+    FlushVisualizer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase")]
+pub(crate) enum StageOutcome {
+    Completed,
+    TimedOut,
+    Failed {
+        error: String,
+    },
+    /// Nothing to do, e.g. no rollout recorder or no visualizer sink.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct StageReport {
+    pub(crate) stage: ShutdownStage,
+    #[serde(flatten)]
+    pub(crate) outcome: StageOutcome,
+}
+
+/// What each shutdown stage did, in the order the stages ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct ShutdownReport {
+    pub(crate) stages: Vec<StageReport>,
+}
+
+impl ShutdownReport {
+    fn record(&mut self, stage: ShutdownStage, outcome: StageOutcome) {
+        if !matches!(outcome, StageOutcome::Completed | StageOutcome::Skipped) {
+            warn!("shutdown stage {stage:?} did not complete: {outcome:?}");
+        }
+        self.stages.push(StageReport { stage, outcome });
+    }
+
+    pub(crate) fn outcome(&self, stage: ShutdownStage) -> Option<&StageOutcome> {
+        self.stages
+            .iter()
+            .find(|report| report.stage == stage)
+            .map(|report| &report.outcome)
+    }
+}
+
+async fn bounded<T>(timeout: Duration, stage: impl Future<Output = T>) -> Option<T> {
+    tokio::time::timeout(timeout, stage).await.ok()
+}
+
+impl Session {
+    /// Tear the session down in the order described in the module docs.
+    /// Never fails: a stage that errors or times out is recorded in the
+    /// report and the remaining stages still run.
+    pub(crate) async fn shutdown(
+        self: &Arc<Self>,
+        end: ConversationEndGuard,
+        reason: ConversationEndReason,
+        timeouts: ShutdownTimeouts,
+    ) -> ShutdownReport {
+        info!("Shutting down Codex instance");
+        let mut report = ShutdownReport::default();
+
+        self.state.lock().await.shutting_down = true;
+        report.record(ShutdownStage::GateSpawns, StageOutcome::Completed);
+
+        let drained = bounded(timeouts.drain_tasks, async {
+            self.abort_all_tasks(TurnAbortReason::Interrupted).await;
+            self.abort_background_tasks(TurnAbortReason::Interrupted)
+                .await;
+        })
+        .await;
+        report.record(
+            ShutdownStage::DrainTasks,
+            drained.map_or(StageOutcome::TimedOut, |()| StageOutcome::Completed),
+        );
+
+        end.end(reason).await;
+        report.record(ShutdownStage::TerminalEvents, StageOutcome::Completed);
+
+        // Taking the recorder also stops later events from being persisted
+        // behind the writer's back.
+        let recorder = self.services.rollout.lock().await.take();
+        let rollout = match recorder {
+            None => StageOutcome::Skipped,
+            Some(recorder) => match bounded(timeouts.flush_rollout, recorder.shutdown()).await {
+                Some(Ok(())) => StageOutcome::Completed,
+                Some(Err(err)) => StageOutcome::Failed {
+                    error: err.to_string(),
+                },
+                None => StageOutcome::TimedOut,
+            },
+        };
+        report.record(ShutdownStage::FlushRollout, rollout);
+
+        // A forwarder that gave up will not deliver anything; waiting for it
+        // would only add the timeout to the shutdown.
+        let visualizer = match self.visualizer.status() {
+            VisualizerStatus::Failed { panics } => StageOutcome::Failed {
+                error: format!("visualizer forwarder stopped after {} panics", panics.len()),
+            },
+            VisualizerStatus::PinRejected { mismatch } => StageOutcome::Failed {
+                error: mismatch.to_string(),
+            },
+            VisualizerStatus::AuthRejected { status } => StageOutcome::Failed {
+                error: format!("visualizer relay rejected authentication (HTTP {status})"),
+            },
+            VisualizerStatus::Abandoned { connect_failures } => StageOutcome::Failed {
+                error: format!(
+                    "visualizer relay given up on after {connect_failures} failed connects in a row"
+                ),
+            },
+            VisualizerStatus::Disabled | VisualizerStatus::Running { .. } => {
+                match self.visualizer.flush(timeouts.flush_visualizer).await {
+                    None => StageOutcome::Skipped,
+                    Some(true) => StageOutcome::Completed,
+                    Some(false) => StageOutcome::TimedOut,
+                }
+            }
+        };
+        report.record(ShutdownStage::FlushVisualizer, visualizer);
+
+        // Best effort: the flush above has already confirmed everything
+        // before this, and nothing is emitted after it. Waiting for the
+        // close keeps a short-lived process, like `codex exec`, from exiting
+        // before the report and the close frame are written; what is still
+        // undelivered then is logged.
+        self.visualizer.enqueue("shutdown_report", json!(report));
+        self.visualizer.shutdown(timeouts.close_visualizer).await;
+        report
+    }
+}
@@ -0,0 +1,234 @@
+//! Checks that the `state` snapshot stamped on a task lifecycle event
+//! agrees with the session's own task bookkeeping at emit time
+//! (`visualizer_consistency_checks`).
+//!
+//! Emit sites capture their snapshot separately from the event itself, so a
+//! snapshot can claim no running tasks while the event it rides on is about
+//! a live one. Each checked event compares the tasks its snapshot lists
+//! against the tasks on the active turn, and a completed or aborted task
+//! must be missing from both; a disagreement is emitted as
+//! `telemetry_inconsistency` with both views instead of going unnoticed.
+//! The check is one lock and a comparison of a handful of ids, so it can
+//! stay on outside debug builds.
+
+#[cfg(test)]
+use std::sync::atomic::AtomicBool;
+#[cfg(test)]
+use std::sync::atomic::Ordering;
+
+use serde_json::Value;
+use serde_json::json;
+
+/// Lifecycle actions whose snapshot is checked.
+const CHECKED_ACTIONS: &[&str] = &[
+    "task_spawned",
+    "task_started",
+    "task_completed",
+    "task_aborted",
+];
+
+/// Lifecycle actions emitted once their task has left the active turn.
+const FINISHED_ACTIONS: &[&str] = &["task_completed", "task_aborted"];
+
+/// A snapshot that disagrees with the active turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Inconsistency {
+    pub(crate) problems: Vec<String>,
+    /// Task ids the snapshot lists as active, sorted.
+    pub(crate) snapshot_tasks: Vec<String>,
+    /// Task ids registered on the active turn, sorted.
+    pub(crate) running_tasks: Vec<String>,
+}
+
+impl Inconsistency {
+    /// Payload of the `telemetry_inconsistency` event reporting this for
+    /// the event `action_type`.
+    pub(crate) fn to_json(&self, action_type: &str, sub_id: Option<&str>) -> Value {
+        json!({
+            "actionType": action_type,
+            "subId": sub_id,
+            "problems": self.problems,
+            "snapshot": { "activeTasks": self.snapshot_tasks },
+            "authoritative": { "activeTasks": self.running_tasks },
+        })
+    }
+}
+
+pub(crate) struct SnapshotConsistency {
+    enabled: bool,
+    /// Leave running tasks out of snapshots, to simulate a desynced emit
+    /// site.
+    #[cfg(test)]
+    hide_tasks: AtomicBool,
+}
+
+impl SnapshotConsistency {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            #[cfg(test)]
+            hide_tasks: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether events of `action_type` are checked.
+    pub(crate) fn checks(&self, action_type: &str) -> bool {
+        self.enabled && CHECKED_ACTIONS.contains(&action_type)
+    }
+
+    /// Compare `snapshot`, stamped on an `action_type` event for the task
+    /// `sub_id`, with the tasks `running` on the active turn.
+    pub(crate) fn check(
+        &self,
+        action_type: &str,
+        sub_id: Option<&str>,
+        snapshot: &Value,
+        mut running: Vec<String>,
+    ) -> Option<Inconsistency> {
+        let mut snapshot_tasks: Vec<String> = snapshot["activeTasks"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|task| task["subId"].as_str().map(str::to_string))
+            .collect();
+        snapshot_tasks.sort();
+        running.sort();
+
+        let mut problems = Vec::new();
+        if snapshot_tasks.len() != running.len() {
+            problems.push(format!(
+                "snapshot lists {} active tasks but {} are running",
+                snapshot_tasks.len(),
+                running.len()
+            ));
+        }
+        if let Some(sub_id) = sub_id {
+            let in_snapshot = snapshot_tasks.iter().any(|task| task == sub_id);
+            let is_running = running.iter().any(|task| task == sub_id);
+            if FINISHED_ACTIONS.contains(&action_type) {
+                if in_snapshot {
+                    problems.push(format!("snapshot lists finished task `{sub_id}` as active"));
+                }
+                if is_running {
+                    problems.push(format!("finished task `{sub_id}` is still running"));
+                }
+            } else if is_running && !in_snapshot {
+                problems.push(format!("snapshot omits running task `{sub_id}`"));
+            } else if in_snapshot && !is_running {
+                problems.push(format!(
+                    "snapshot lists task `{sub_id}`, which is not running"
+                ));
+            }
+        }
+        if problems.is_empty() && snapshot_tasks != running {
+            problems.push("snapshot and active turn list different tasks".to_string());
+        }
+
+        (!problems.is_empty()).then_some(Inconsistency {
+            problems,
+            snapshot_tasks,
+            running_tasks: running,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn hide_tasks(&self) {
+        self.hide_tasks.store(true, Ordering::Relaxed);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn hides_tasks(&self) -> bool {
+        self.hide_tasks.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn snapshot(sub_ids: &[&str]) -> Value {
+        json!({
+            "activeTasks": sub_ids
+                .iter()
+                .map(|sub_id| json!({ "subId": sub_id, "kind": "Regular" }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn running(sub_ids: &[&str]) -> Vec<String> {
+        sub_ids.iter().map(|sub_id| sub_id.to_string()).collect()
+    }
+
+    #[test]
+    fn agreeing_views_are_consistent() {
+        let checks = SnapshotConsistency::new(true);
+        assert_eq!(
+            checks.check(
+                "task_spawned",
+                Some("1"),
+                &snapshot(&["1"]),
+                running(&["1"])
+            ),
+            None
+        );
+        // A task that finished before its spawn was reported is in neither.
+        assert_eq!(
+            checks.check("task_spawned", Some("1"), &snapshot(&[]), running(&[])),
+            None
+        );
+        assert_eq!(
+            checks.check(
+                "task_completed",
+                Some("1"),
+                &snapshot(&["2"]),
+                running(&["2"])
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn stale_snapshot_of_a_live_task_is_inconsistent() {
+        let checks = SnapshotConsistency::new(true);
+        assert_eq!(
+            checks.check("task_spawned", Some("1"), &snapshot(&[]), running(&["1"])),
+            Some(Inconsistency {
+                problems: vec![
+                    "snapshot lists 0 active tasks but 1 are running".to_string(),
+                    "snapshot omits running task `1`".to_string(),
+                ],
+                snapshot_tasks: Vec::new(),
+                running_tasks: running(&["1"]),
+            })
+        );
+    }
+
+    #[test]
+    fn finished_task_listed_as_active_is_inconsistent() {
+        let checks = SnapshotConsistency::new(true);
+        assert_eq!(
+            checks.check("task_completed", Some("1"), &snapshot(&["1"]), running(&[])),
+            Some(Inconsistency {
+                problems: vec![
+                    "snapshot lists 1 active tasks but 0 are running".to_string(),
+                    "snapshot lists finished task `1` as active".to_string(),
+                ],
+                snapshot_tasks: running(&["1"]),
+                running_tasks: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn only_lifecycle_actions_are_checked_when_enabled() {
+        assert_eq!(
+            (
+                SnapshotConsistency::new(true).checks("task_spawned"),
+                SnapshotConsistency::new(true).checks("exec_output"),
+                SnapshotConsistency::new(false).checks("task_spawned"),
+            ),
+            (true, false, false)
+        );
+    }
+}
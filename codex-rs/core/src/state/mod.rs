@@ -6,4 +6,6 @@ pub(crate) use service::SessionServices;
 pub(crate) use session::SessionState;
 pub(crate) use turn::ActiveTurn;
 pub(crate) use turn::RunningTask;
+pub(crate) use turn::RunningTaskStatus;
 pub(crate) use turn::TaskKind;
+pub(crate) use turn::TaskStatus;
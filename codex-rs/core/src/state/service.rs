@@ -1,10 +1,22 @@
 use crate::RolloutRecorder;
+use crate::config_types::ApprovalLimits;
+use crate::config_types::ImageDegradation;
+use crate::conversation_lease::ConversationLease;
 use crate::exec_command::ExecSessionManager;
 use crate::executor::Executor;
+use crate::instrumentation::InstrumentationCoverage;
 use crate::mcp_connection_manager::McpConnectionManager;
+use crate::retry::RetryPolicy;
+use crate::session_features::SessionFeatures;
+use crate::snapshot_consistency::SnapshotConsistency;
+use crate::tasks::TaskTemplates;
 use crate::unified_exec::UnifiedExecSessionManager;
 use crate::user_notification::UserNotifier;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::sync::watch;
 
 pub(crate) struct SessionServices {
     pub(crate) mcp_connection_manager: McpConnectionManager,
@@ -14,5 +26,28 @@ pub(crate) struct SessionServices {
     pub(crate) rollout: Mutex<Option<RolloutRecorder>>,
     pub(crate) user_shell: crate::shell::Shell,
     pub(crate) show_raw_agent_reasoning: bool,
+    pub(crate) warn_on_invalid_cwd: bool,
+    /// Deadline of tasks spawned for user input; see `Config::task_timeout`.
+    pub(crate) task_timeout: Option<Duration>,
+    /// How failed user turns are retried; see `Config::task_retry`.
+    pub(crate) task_retry: Option<RetryPolicy>,
+    /// How often running tasks are asked for their progress; see
+    /// `Config::task_progress_interval`.
+    pub(crate) task_progress_interval: Option<Duration>,
+    /// See `Config::task_input_chunk_size`.
+    pub(crate) task_input_chunk_size: Option<NonZeroUsize>,
+    pub(crate) features: SessionFeatures,
+    pub(crate) approval_limits: ApprovalLimits,
+    /// Whether the session is paused; approval timeouts do not count down
+    /// while this is `true`.
+    pub(crate) paused: watch::Sender<bool>,
     pub(crate) executor: Executor,
+    /// Held while the session runs; see `conversation_lease`.
+    pub(crate) _conversation_lease: Option<ConversationLease>,
+    pub(crate) task_templates: TaskTemplates,
+    /// See `Config::turn_templates_dir`.
+    pub(crate) turn_templates_dir: PathBuf,
+    pub(crate) image_degradation: ImageDegradation,
+    pub(crate) instrumentation: InstrumentationCoverage,
+    pub(crate) snapshot_consistency: SnapshotConsistency,
 }
@@ -1,11 +1,25 @@
 //! Session-wide mutable state.
 
+use std::collections::HashMap;
+
 use codex_protocol::models::ResponseItem;
 
+use crate::content_scope::ExecReads;
 use crate::conversation_history::ConversationHistory;
+use crate::digest::ActivityLog;
+use crate::focus::Focus;
+use crate::protocol::ClientContext;
 use crate::protocol::RateLimitSnapshot;
+use crate::protocol::ReviewFinding;
+use crate::protocol::TaskRecoveredIncompleteEvent;
 use crate::protocol::TokenUsage;
 use crate::protocol::TokenUsageInfo;
+use crate::review_slices::ReviewCursor;
+use crate::task_recovery::InFlightCalls;
+use crate::task_revert::TaskWrites;
+use crate::tasks::FirstResponseHistogram;
+use crate::tasks::FollowUpTask;
+use crate::tasks::TurnSeeds;
 
 /// Persistent, session-scoped state previously stored directly on `Session`.
 #[derive(Default)]
@@ -13,6 +27,41 @@ pub(crate) struct SessionState {
     pub(crate) history: ConversationHistory,
     pub(crate) token_info: Option<TokenUsageInfo>,
     pub(crate) latest_rate_limits: Option<RateLimitSnapshot>,
+    /// Task to start once the running one finishes.
+    pub(crate) follow_up: Option<FollowUpTask>,
+    /// User task blocked on a compaction, or resuming after one, until it
+    /// ends; it is not compacted for a second time.
+    pub(crate) last_blocking_compaction_for: Option<String>,
+    /// Tasks spawned over the session's lifetime.
+    pub(crate) tasks_started: u64,
+    pub(crate) last_task_sub_id: Option<String>,
+    /// File changes of recently finished tasks, oldest first, for reverts.
+    pub(crate) task_writes: Vec<TaskWrites>,
+    /// Most recent client context attached to a submission. Submissions
+    /// without one leave it unchanged.
+    pub(crate) client_context: Option<ClientContext>,
+    /// Findings of the most recent completed review, also restored on resume.
+    pub(crate) review_findings: Vec<ReviewFinding>,
+    /// Progress of sliced reviews through the current diff.
+    pub(crate) review_cursor: Option<ReviewCursor>,
+    /// Set once shutdown starts; no new tasks are spawned after that.
+    pub(crate) shutting_down: bool,
+    /// Sampling seeds of recently spawned tasks, so a replay can reuse the
+    /// seed of the turn it replays.
+    pub(crate) turn_seeds: TurnSeeds,
+    /// Completed turns and errors, for `Op::GetDigest`.
+    pub(crate) activity: ActivityLog,
+    /// Files read by running exec commands, by call id, for tagging their
+    /// output in visualizer events.
+    pub(crate) exec_reads: HashMap<String, ExecReads>,
+    /// Begin events of running patch and exec calls, for task checkpoints.
+    pub(crate) in_flight_calls: InFlightCalls,
+    /// Unfinished task of the resumed rollout, until the client resolves it.
+    pub(crate) recovered_task: Option<TaskRecoveredIncompleteEvent>,
+    /// How long finished tasks took to first respond.
+    pub(crate) first_response_latency: FirstResponseHistogram,
+    /// Set with `Op::SetFocus`; applies to tasks spawned after it.
+    pub(crate) focus: Option<Focus>,
 }
 
 impl SessionState {
@@ -37,10 +86,22 @@ impl SessionState {
         self.history.contents()
     }
 
+    pub(crate) fn history_hash(&self) -> String {
+        self.history.hash()
+    }
+
     pub(crate) fn replace_history(&mut self, items: Vec<ResponseItem>) {
         self.history.replace(items);
     }
 
+    pub(crate) fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub(crate) fn truncate_history(&mut self, len: usize) {
+        self.history.truncate(len);
+    }
+
     // Token/rate limit helpers
     pub(crate) fn update_token_info_from_usage(
         &mut self,
@@ -1,16 +1,27 @@
 //! Turn-scoped state and active turn metadata scaffolding.
 
 use indexmap::IndexMap;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
 use tokio::task::AbortHandle;
 
-use codex_protocol::models::ResponseInputItem;
 use tokio::sync::oneshot;
 
+use crate::focus::Focus;
+use crate::protocol::ClientContext;
+use crate::protocol::InputItem;
 use crate::protocol::ReviewDecision;
+use crate::protocol::TurnTemplateUse;
 use crate::tasks::SessionTask;
+use crate::tasks::SharedTaskTimings;
+use crate::tasks::TaskTemplate;
 
 /// Metadata about the currently running turn.
 pub(crate) struct ActiveTurn {
@@ -32,6 +43,23 @@ pub(crate) enum TaskKind {
     Regular,
     Review,
     Compact,
+    /// System work in the background lane; see `tasks::BackgroundTasks`.
+    Report,
+}
+
+/// Lifecycle of a registered task as seen by status queries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum TaskStatus {
+    Running,
+    /// `run` has returned and completion bookkeeping is in flight.
+    Finishing,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct RunningTaskStatus {
+    pub(crate) sub_id: String,
+    pub(crate) kind: TaskKind,
+    pub(crate) status: TaskStatus,
 }
 
 #[derive(Clone)]
@@ -39,6 +67,35 @@ pub(crate) struct RunningTask {
     pub(crate) handle: AbortHandle,
     pub(crate) kind: TaskKind,
     pub(crate) task: Arc<dyn SessionTask>,
+    pub(crate) timings: SharedTaskTimings,
+    /// Set by the spawn wrapper as soon as `run` returns, before
+    /// `on_task_finished` gets a chance to deregister the task.
+    pub(crate) run_returned: Arc<AtomicBool>,
+    /// The client that submitted the input this task runs on, if it said.
+    pub(crate) client_context: Option<ClientContext>,
+    /// Sampling seed sent with the task's model requests.
+    pub(crate) seed: u64,
+    /// Instructions template appended to the task's prompts.
+    pub(crate) template: Option<Arc<TaskTemplate>>,
+    /// Session focus when the task was spawned.
+    pub(crate) focus: Option<Focus>,
+    /// Turn template the task's input was rendered from.
+    pub(crate) turn_template: Option<TurnTemplateUse>,
+    /// Deadline the task was spawned with.
+    pub(crate) timeout: Option<Duration>,
+    /// Custom events emitted by the task's tools, by namespace; see
+    /// `tools::telemetry`.
+    pub(crate) custom_events: BTreeMap<String, u64>,
+}
+
+impl RunningTask {
+    pub(crate) fn status(&self) -> TaskStatus {
+        if self.run_returned.load(Ordering::Acquire) || self.handle.is_finished() {
+            TaskStatus::Finishing
+        } else {
+            TaskStatus::Running
+        }
+    }
 }
 
 impl ActiveTurn {
@@ -54,41 +111,107 @@ impl ActiveTurn {
     pub(crate) fn drain_tasks(&mut self) -> IndexMap<String, RunningTask> {
         std::mem::take(&mut self.tasks)
     }
+
+    pub(crate) fn task_statuses(&self) -> Vec<RunningTaskStatus> {
+        self.tasks
+            .iter()
+            .map(|(sub_id, task)| RunningTaskStatus {
+                sub_id: sub_id.clone(),
+                kind: task.kind,
+                status: task.status(),
+            })
+            .collect()
+    }
+}
+
+/// An approval request awaiting its answer.
+struct PendingApproval {
+    sub_id: String,
+    tx: oneshot::Sender<ReviewDecision>,
 }
 
 /// Mutable state for a single turn.
 #[derive(Default)]
 pub(crate) struct TurnState {
-    pending_approvals: HashMap<String, oneshot::Sender<ReviewDecision>>,
-    pending_input: Vec<ResponseInputItem>,
+    /// Keyed by `call_id`, in the order the requests were sent.
+    pending_approvals: IndexMap<String, PendingApproval>,
+    approval_slots: HashMap<String, Arc<Semaphore>>,
+    pending_input: Vec<Vec<InputItem>>,
 }
 
 impl TurnState {
+    /// Semaphore bounding how many approval requests the task `sub_id` may
+    /// have outstanding at once.
+    pub(crate) fn approval_slots(&mut self, sub_id: &str, max: NonZeroUsize) -> Arc<Semaphore> {
+        Arc::clone(
+            self.approval_slots
+                .entry(sub_id.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(max.get()))),
+        )
+    }
+
     pub(crate) fn insert_pending_approval(
         &mut self,
-        key: String,
+        sub_id: String,
+        call_id: String,
         tx: oneshot::Sender<ReviewDecision>,
     ) -> Option<oneshot::Sender<ReviewDecision>> {
-        self.pending_approvals.insert(key, tx)
+        self.pending_approvals
+            .insert(call_id, PendingApproval { sub_id, tx })
+            .map(|pending| pending.tx)
     }
 
     pub(crate) fn remove_pending_approval(
         &mut self,
-        key: &str,
+        call_id: &str,
+    ) -> Option<oneshot::Sender<ReviewDecision>> {
+        self.pending_approvals
+            .shift_remove(call_id)
+            .map(|pending| pending.tx)
+    }
+
+    /// Remove the approval an answer with `id` is for: the request with that
+    /// call id or, for clients answering with the id of the submission, the
+    /// oldest outstanding request of the task with that sub id.
+    pub(crate) fn take_pending_approval(
+        &mut self,
+        id: &str,
     ) -> Option<oneshot::Sender<ReviewDecision>> {
-        self.pending_approvals.remove(key)
+        let index = self.pending_approvals.get_index_of(id).or_else(|| {
+            self.pending_approvals
+                .values()
+                .position(|pending| pending.sub_id == id)
+        })?;
+        self.pending_approvals
+            .shift_remove_index(index)
+            .map(|(_, pending)| pending.tx)
     }
 
-    pub(crate) fn clear_pending(&mut self) {
-        self.pending_approvals.clear();
+    /// Drop every pending approval and input, returning the sub ids whose
+    /// approval requests now go unanswered, sorted.
+    pub(crate) fn clear_pending(&mut self) -> Vec<String> {
+        let mut unanswered: Vec<String> = self
+            .pending_approvals
+            .drain(..)
+            .map(|(_, pending)| pending.sub_id)
+            .collect();
+        unanswered.sort();
+        unanswered.dedup();
+        self.approval_slots.clear();
         self.pending_input.clear();
+        unanswered
+    }
+
+    /// Whether no approval or input is pending.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending_approvals.is_empty() && self.pending_input.is_empty()
     }
 
-    pub(crate) fn push_pending_input(&mut self, input: ResponseInputItem) {
+    pub(crate) fn push_pending_input(&mut self, input: Vec<InputItem>) {
         self.pending_input.push(input);
     }
 
-    pub(crate) fn take_pending_input(&mut self) -> Vec<ResponseInputItem> {
+    pub(crate) fn take_pending_input(&mut self) -> Vec<Vec<InputItem>> {
         if self.pending_input.is_empty() {
             Vec::with_capacity(0)
         } else {
@@ -105,9 +228,16 @@ impl TurnState {
 
 impl ActiveTurn {
     /// Clear any pending approvals and input buffered for the current turn.
-    pub(crate) async fn clear_pending(&self) {
+    /// Returns the sub ids whose approval requests were dropped; see
+    /// [`TurnState::clear_pending`].
+    pub(crate) async fn clear_pending(&self) -> Vec<String> {
         let mut ts = self.turn_state.lock().await;
-        ts.clear_pending();
+        ts.clear_pending()
+    }
+
+    /// Whether the turn has neither tasks nor pending approvals or input.
+    pub(crate) async fn is_empty(&self) -> bool {
+        self.tasks.is_empty() && self.turn_state.lock().await.is_empty()
     }
 
     /// Best-effort, non-blocking variant for synchronous contexts (Drop/interrupt).
@@ -0,0 +1,62 @@
+//! Session side of strict telemetry (`visualizer_strict_telemetry`), and
+//! of the durable queue (`visualizer_durable_queue_dir`).
+//!
+//! The visualizer decides when a lifecycle event is lost (see
+//! `visualizer::strict` and `visualizer::durable`); the session reacts to the first such failure by
+//! aborting every running task with [`TurnAbortReason::TelemetryFailure`]
+//! and sending an error event, which `codex exec` turns into a non-zero
+//! exit status. Later losses are not reported again.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::codex::Session;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::TurnAbortReason;
+
+/// How long a lifecycle event may take to reach the relay.
+pub(crate) const STRICT_TELEMETRY_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Session {
+    /// Handle the first strict telemetry failure, if strict delivery or the
+    /// durable queue is on.
+    /// Holds the session weakly, so the watcher ends with it.
+    pub(crate) fn watch_telemetry_failures(self: &Arc<Self>) {
+        let Some(mut failures) = self.visualizer.telemetry_failures() else {
+            return;
+        };
+        let session = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let cause = match failures.wait_for(Option::is_some).await {
+                Ok(failure) => failure.clone().unwrap_or_default(),
+                Err(_) => return,
+            };
+            if let Some(session) = session.upgrade() {
+                session.on_telemetry_failure(cause).await;
+            }
+        });
+    }
+
+    async fn on_telemetry_failure(self: &Arc<Self>, cause: String) {
+        let sub_id = self.state.lock().await.last_task_sub_id.clone();
+        self.abort_all_tasks(TurnAbortReason::TelemetryFailure)
+            .await;
+        self.abort_background_tasks(TurnAbortReason::TelemetryFailure)
+            .await;
+        self.emit_with_state("telemetry_failure", json!({ "cause": cause }))
+            .await;
+        self.send_event(Event {
+            id: sub_id.unwrap_or_default(),
+            msg: EventMsg::Error(ErrorEvent {
+                message: format!(
+                    "strict telemetry: {cause}; the audit stream is incomplete, so running tasks were aborted"
+                ),
+            }),
+        })
+        .await;
+    }
+}
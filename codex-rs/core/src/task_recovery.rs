@@ -0,0 +1,289 @@
+//! Recovery of tasks cut short by a crash.
+//!
+//! While a task runs, [`TaskCheckpoint`]s are written to the rollout in the
+//! same batch as the event that produced them: one when it starts, one per
+//! successfully applied patch and per completed exec, and one when it
+//! completes or is aborted. Resuming a rollout whose last started task has
+//! no `Finished` checkpoint reports that task, together with what it had
+//! done and the tool calls still waiting for a result, and keeps it until
+//! the client settles it with `Op::ResolveRecoveredTask`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use codex_protocol::models::ResponseItem;
+use serde_json::json;
+
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::InputItem;
+use crate::protocol::RecoveredCommand;
+use crate::protocol::RecoveredTaskAction;
+use crate::protocol::RecoveredToolCall;
+use crate::protocol::RolloutItem;
+use crate::protocol::TaskCheckpoint;
+use crate::protocol::TaskCheckpointItem;
+use crate::protocol::TaskRecoveredIncompleteEvent;
+use crate::tasks::RegularTask;
+
+/// What the begin events of in-flight calls said, for their checkpoints.
+#[derive(Debug, Default)]
+pub(crate) struct InFlightCalls {
+    patches: HashMap<String, Vec<PathBuf>>,
+    execs: HashMap<String, Vec<String>>,
+}
+
+impl InFlightCalls {
+    /// The checkpoint `event` closes, if any.
+    fn checkpoint(&mut self, event: &Event) -> Option<TaskCheckpoint> {
+        match &event.msg {
+            EventMsg::TaskStarted(_) => Some(TaskCheckpoint::Started),
+            EventMsg::TaskComplete(_) | EventMsg::TurnAborted(_) => Some(TaskCheckpoint::Finished),
+            EventMsg::PatchApplyBegin(begin) => {
+                let mut paths: Vec<PathBuf> = begin.changes.keys().cloned().collect();
+                paths.sort();
+                self.patches.insert(begin.call_id.clone(), paths);
+                None
+            }
+            EventMsg::PatchApplyEnd(end) => {
+                let paths = self.patches.remove(&end.call_id)?;
+                end.success.then(|| TaskCheckpoint::PatchApplied {
+                    call_id: end.call_id.clone(),
+                    paths,
+                })
+            }
+            EventMsg::ExecCommandBegin(begin) => {
+                self.execs
+                    .insert(begin.call_id.clone(), begin.command.clone());
+                None
+            }
+            EventMsg::ExecCommandEnd(end) => Some(TaskCheckpoint::ExecCompleted {
+                call_id: end.call_id.clone(),
+                command: self.execs.remove(&end.call_id)?,
+                exit_code: end.exit_code,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The last task started in `items` if it never finished.
+fn find_incomplete(items: &[RolloutItem]) -> Option<TaskRecoveredIncompleteEvent> {
+    let mut current: Option<TaskRecoveredIncompleteEvent> = None;
+    for item in items {
+        match item {
+            RolloutItem::TaskCheckpoint(TaskCheckpointItem { sub_id, checkpoint }) => {
+                if let TaskCheckpoint::Started = checkpoint {
+                    current = Some(TaskRecoveredIncompleteEvent {
+                        sub_id: sub_id.clone(),
+                        message: String::new(),
+                        patched_paths: Vec::new(),
+                        completed_commands: Vec::new(),
+                        pending_calls: Vec::new(),
+                    });
+                    continue;
+                }
+                let Some(task) = current.as_mut().filter(|task| &task.sub_id == sub_id) else {
+                    continue;
+                };
+                match checkpoint {
+                    TaskCheckpoint::PatchApplied { paths, .. } => {
+                        for path in paths {
+                            if !task.patched_paths.contains(path) {
+                                task.patched_paths.push(path.clone());
+                            }
+                        }
+                    }
+                    TaskCheckpoint::ExecCompleted {
+                        command, exit_code, ..
+                    } => task.completed_commands.push(RecoveredCommand {
+                        command: command.clone(),
+                        exit_code: *exit_code,
+                    }),
+                    TaskCheckpoint::Finished => current = None,
+                    TaskCheckpoint::Started => {}
+                }
+            }
+            RolloutItem::ResponseItem(item) => {
+                let Some(task) = current.as_mut() else {
+                    continue;
+                };
+                match item {
+                    ResponseItem::FunctionCall { name, call_id, .. }
+                    | ResponseItem::CustomToolCall { name, call_id, .. } => {
+                        task.pending_calls.push(RecoveredToolCall {
+                            call_id: call_id.clone(),
+                            name: name.clone(),
+                        });
+                    }
+                    ResponseItem::LocalShellCall {
+                        call_id: Some(call_id),
+                        ..
+                    } => task.pending_calls.push(RecoveredToolCall {
+                        call_id: call_id.clone(),
+                        name: "local_shell".to_string(),
+                    }),
+                    ResponseItem::FunctionCallOutput { call_id, .. }
+                    | ResponseItem::CustomToolCallOutput { call_id, .. } => {
+                        task.pending_calls.retain(|call| &call.call_id != call_id);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    current.map(|mut task| {
+        task.message = format!(
+            "The previous session ended before task {} finished ({} file(s) patched, {} command(s) run, {} tool call(s) without a result).",
+            task.sub_id,
+            task.patched_paths.len(),
+            task.completed_commands.len(),
+            task.pending_calls.len()
+        );
+        task
+    })
+}
+
+/// Input for the task continuing `task`.
+pub(crate) fn continuation_prompt(task: &TaskRecoveredIncompleteEvent) -> String {
+    let mut prompt = String::from(
+        "The previous session ended while a task was still in progress. Continue that task.",
+    );
+    if !task.patched_paths.is_empty() {
+        prompt.push_str("\n\nIt had already patched these files:");
+        for path in &task.patched_paths {
+            prompt.push_str(&format!("\n- {}", path.display()));
+        }
+    }
+    if !task.completed_commands.is_empty() {
+        prompt.push_str("\n\nIt had already run these commands:");
+        for command in &task.completed_commands {
+            let joined = shlex::try_join(command.command.iter().map(String::as_str))
+                .unwrap_or_else(|_| command.command.join(" "));
+            prompt.push_str(&format!("\n- `{joined}` (exit code {})", command.exit_code));
+        }
+    }
+    if !task.pending_calls.is_empty() {
+        prompt.push_str("\n\nThese tool calls never returned, so their effects are unknown:");
+        for call in &task.pending_calls {
+            prompt.push_str(&format!("\n- {} ({})", call.name, call.call_id));
+        }
+    }
+    prompt.push_str("\n\nCheck the current state of the workspace before making further changes.");
+    prompt
+}
+
+impl Session {
+    /// The checkpoint to persist along with `event`, if it makes one.
+    pub(crate) async fn task_checkpoint(&self, event: &Event) -> Option<RolloutItem> {
+        let checkpoint = self.state.lock().await.in_flight_calls.checkpoint(event)?;
+        Some(RolloutItem::TaskCheckpoint(TaskCheckpointItem {
+            sub_id: event.id.clone(),
+            checkpoint,
+        }))
+    }
+
+    /// Remember the unfinished task of a resumed rollout, if any.
+    pub(crate) async fn detect_incomplete_task(&self, items: &[RolloutItem]) {
+        if let Some(task) = find_incomplete(items) {
+            self.state.lock().await.recovered_task = Some(task);
+        }
+    }
+
+    /// Tell clients about the task found by
+    /// [`Session::detect_incomplete_task`], once the session is configured.
+    pub(crate) async fn report_recovered_task(&self, sub_id: &str) {
+        let Some(task) = self.state.lock().await.recovered_task.clone() else {
+            return;
+        };
+        self.emit_with_state("task_recovered_incomplete", json!(task))
+            .await;
+        self.send_event(Event {
+            id: sub_id.to_string(),
+            msg: EventMsg::TaskRecoveredIncomplete(task),
+        })
+        .await;
+    }
+
+    /// Handle `Op::ResolveRecoveredTask`.
+    pub(crate) async fn resolve_recovered_task(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        action: RecoveredTaskAction,
+    ) {
+        let Some(task) = self.state.lock().await.recovered_task.take() else {
+            self.send_event(Event {
+                id: sub_id,
+                msg: EventMsg::Error(ErrorEvent {
+                    message: "no recovered task to resolve".to_string(),
+                }),
+            })
+            .await;
+            return;
+        };
+        // Settled either way, so resuming again does not report it anew.
+        self.persist_rollout_items(&[RolloutItem::TaskCheckpoint(TaskCheckpointItem {
+            sub_id: task.sub_id.clone(),
+            checkpoint: TaskCheckpoint::Finished,
+        })])
+        .await;
+        self.emit_with_state(
+            "task_recovery_resolved",
+            json!({
+                "subId": task.sub_id,
+                "action": action,
+                "continuationSubId": (action == RecoveredTaskAction::Continue).then_some(&sub_id),
+            }),
+        )
+        .await;
+        if action == RecoveredTaskAction::Continue {
+            let input = vec![InputItem::Text {
+                text: continuation_prompt(&task),
+            }];
+            self.spawn_task(turn_context, sub_id, input, RegularTask::default())
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn checkpoint(sub_id: &str, checkpoint: TaskCheckpoint) -> RolloutItem {
+        RolloutItem::TaskCheckpoint(TaskCheckpointItem {
+            sub_id: sub_id.to_string(),
+            checkpoint,
+        })
+    }
+
+    #[test]
+    fn finished_tasks_are_not_recovered() {
+        let items = vec![
+            checkpoint("first", TaskCheckpoint::Started),
+            checkpoint(
+                "first",
+                TaskCheckpoint::PatchApplied {
+                    call_id: "patch-1".to_string(),
+                    paths: vec![PathBuf::from("/repo/a.rs")],
+                },
+            ),
+            checkpoint("first", TaskCheckpoint::Finished),
+        ];
+        assert_eq!(find_incomplete(&items), None);
+
+        // Only what happened after the unfinished task started counts.
+        let mut items = items;
+        items.push(checkpoint("second", TaskCheckpoint::Started));
+        let recovered = find_incomplete(&items).map(|task| (task.sub_id, task.patched_paths));
+        assert_eq!(recovered, Some(("second".to_string(), Vec::new())));
+    }
+}
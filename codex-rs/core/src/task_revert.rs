@@ -0,0 +1,501 @@
+//! Per-task record of the files a task changed through `apply_patch`, and
+//! reverting a finished task by restoring them.
+//!
+//! A record holds each file's contents from before the task's first change
+//! and after its last one. A revert restores the former, but only where the
+//! file still holds the latter and no later task touched it; anything else
+//! is reported as a conflict. The files are read and written without the
+//! session state locked.
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde_json::json;
+use tracing::warn;
+
+use crate::codex::Session;
+use crate::protocol::ErrorEvent;
+use crate::protocol::EventMsg;
+use crate::protocol::RevertConflict;
+use crate::protocol::RevertConflictReason;
+use crate::protocol::TaskRevertedEvent;
+use crate::turn_diff_tracker::TouchedFile;
+use tokio::fs;
+
+/// Oldest records are discarded beyond this many tasks.
+const MAX_RECORDED_TASKS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileWrite {
+    original_path: PathBuf,
+    /// `None` if the task created the file.
+    original: Option<Vec<u8>>,
+    final_path: PathBuf,
+    /// `None` if the task deleted the file.
+    written: Option<Vec<u8>>,
+}
+
+impl FileWrite {
+    fn moved(&self) -> bool {
+        self.original_path != self.final_path
+    }
+
+    /// The path whose pre-task state a revert brings back.
+    fn restored_path(&self) -> &Path {
+        if self.original.is_some() {
+            &self.original_path
+        } else {
+            &self.final_path
+        }
+    }
+
+    async fn conflict(&self, later: &[TaskWrites]) -> Option<RevertConflict> {
+        if let Some(task) = later.iter().find(|task| {
+            task.files.iter().any(|file| {
+                [&file.original_path, &file.final_path]
+                    .into_iter()
+                    .any(|path| *path == self.original_path || *path == self.final_path)
+            })
+        }) {
+            return Some(RevertConflict {
+                path: self.final_path.clone(),
+                reason: RevertConflictReason::ChangedByLaterTask {
+                    sub_id: task.sub_id.clone(),
+                },
+            });
+        }
+        if fs::read(&self.final_path).await.ok() != self.written {
+            return Some(RevertConflict {
+                path: self.final_path.clone(),
+                reason: RevertConflictReason::ModifiedSinceTask,
+            });
+        }
+        if self.moved()
+            && self.original.is_some()
+            && fs::try_exists(&self.original_path).await.unwrap_or(true)
+        {
+            return Some(RevertConflict {
+                path: self.original_path.clone(),
+                reason: RevertConflictReason::PathOccupied,
+            });
+        }
+        None
+    }
+
+    async fn restore(&self) -> io::Result<()> {
+        if self.written.is_some() && (self.original.is_none() || self.moved()) {
+            fs::remove_file(&self.final_path).await?;
+        }
+        if let Some(original) = &self.original {
+            if let Some(parent) = self.original_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&self.original_path, original).await?;
+        }
+        Ok(())
+    }
+
+    /// Put back what the task left, after a failed revert.
+    async fn reapply(&self) -> io::Result<()> {
+        if self.original.is_some() && (self.written.is_none() || self.moved()) {
+            fs::remove_file(&self.original_path).await?;
+        }
+        if let Some(written) = &self.written {
+            fs::write(&self.final_path, written).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The files one task changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaskWrites {
+    sub_id: String,
+    files: Vec<FileWrite>,
+}
+
+impl TaskWrites {
+    /// Snapshot the files the task's tracker saw, from
+    /// `TurnDiffTracker::touched_files`. Call once the task has finished so
+    /// the current disk contents are its final writes.
+    pub(crate) async fn capture(sub_id: &str, touched: Vec<TouchedFile>) -> Self {
+        let mut files = Vec::new();
+        for touched in touched {
+            let file = FileWrite {
+                written: fs::read(&touched.current_path).await.ok(),
+                original_path: touched.original_path,
+                original: touched.original_content,
+                final_path: touched.current_path,
+            };
+            if file.moved() || file.original != file.written {
+                files.push(file);
+            }
+        }
+        Self {
+            sub_id: sub_id.to_string(),
+            files,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub(crate) fn sub_id(&self) -> &str {
+        &self.sub_id
+    }
+
+    /// Where the task's files ended up.
+    pub(crate) fn changed_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.iter().map(|file| &file.final_path)
+    }
+
+    /// Restore this task's files. With `force`, conflicting files are left
+    /// alone and the rest restored; otherwise any conflict refuses the whole
+    /// revert. An I/O error part-way puts the already restored files back.
+    async fn revert(&self, later: &[TaskWrites], force: bool) -> io::Result<TaskRevertedEvent> {
+        let mut conflicts = Vec::new();
+        let mut restorable = Vec::new();
+        for file in &self.files {
+            match file.conflict(later).await {
+                Some(conflict) => conflicts.push(conflict),
+                None => restorable.push(file),
+            }
+        }
+        if !conflicts.is_empty() && !force {
+            return Ok(TaskRevertedEvent {
+                sub_id: self.sub_id.clone(),
+                reverted: false,
+                restored: Vec::new(),
+                conflicts,
+            });
+        }
+
+        let mut restored: Vec<&FileWrite> = Vec::new();
+        for file in restorable.into_iter().rev() {
+            if let Err(err) = file.restore().await {
+                for done in restored.iter().rev() {
+                    if let Err(undo_err) = done.reapply().await {
+                        warn!(
+                            "failed to roll back revert of {}: {undo_err}",
+                            done.restored_path().display()
+                        );
+                    }
+                }
+                return Err(err);
+            }
+            restored.push(file);
+        }
+        Ok(TaskRevertedEvent {
+            sub_id: self.sub_id.clone(),
+            reverted: !restored.is_empty(),
+            restored: restored
+                .into_iter()
+                .map(|file| file.restored_path().to_path_buf())
+                .collect(),
+            conflicts,
+        })
+    }
+
+    /// Forget the files a revert restored, keeping those it left alone.
+    fn forget_restored(&mut self, restored: &[PathBuf]) {
+        self.files
+            .retain(|file| !restored.iter().any(|path| path == file.restored_path()));
+    }
+}
+
+impl Session {
+    pub(crate) async fn record_task_writes(&self, writes: TaskWrites) {
+        if writes.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().await;
+        if state.task_writes.len() == MAX_RECORDED_TASKS {
+            state.task_writes.remove(0);
+        }
+        state.task_writes.push(writes);
+    }
+
+    /// Handle `Op::RevertTask`, returning the reply.
+    pub(crate) async fn revert_task(&self, sub_id: &str, force: bool) -> EventMsg {
+        let (task, later) = {
+            let state = self.state.lock().await;
+            let Some(index) = state
+                .task_writes
+                .iter()
+                .position(|task| task.sub_id == sub_id)
+            else {
+                return EventMsg::Error(ErrorEvent {
+                    message: format!("no recorded file changes for task {sub_id}"),
+                });
+            };
+            (
+                state.task_writes[index].clone(),
+                state.task_writes[index + 1..].to_vec(),
+            )
+        };
+        match task.revert(&later, force).await {
+            Ok(event) => {
+                if event.reverted {
+                    let mut state = self.state.lock().await;
+                    if let Some(index) = state
+                        .task_writes
+                        .iter()
+                        .position(|task| task.sub_id == sub_id)
+                    {
+                        state.task_writes[index].forget_restored(&event.restored);
+                        if state.task_writes[index].is_empty() {
+                            state.task_writes.remove(index);
+                        }
+                    }
+                }
+                self.emit_with_state(
+                    "task_reverted",
+                    serde_json::to_value(&event).unwrap_or_else(|_| json!({ "subId": sub_id })),
+                )
+                .await;
+                EventMsg::TaskReverted(event)
+            }
+            Err(err) => EventMsg::Error(ErrorEvent {
+                message: format!("failed to revert task {sub_id}: {err}"),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::FileChange;
+    use crate::turn_diff_tracker::TurnDiffTracker;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Run a scripted "task": announce the changes to a tracker the way
+    /// `apply_patch` does, then perform them on disk.
+    async fn patching_task(
+        sub_id: &str,
+        changes: Vec<(PathBuf, FileChange, Option<&str>)>,
+    ) -> TaskWrites {
+        let mut tracker = TurnDiffTracker::new();
+        let announced: HashMap<PathBuf, FileChange> = changes
+            .iter()
+            .map(|(path, change, _)| (path.clone(), change.clone()))
+            .collect();
+        tracker.on_patch_begin(&announced);
+        for (path, change, content) in changes {
+            let target = match &change {
+                FileChange::Update {
+                    move_path: Some(dest),
+                    ..
+                } => {
+                    fs::remove_file(&path).expect("remove moved source");
+                    dest.clone()
+                }
+                _ => path,
+            };
+            match content {
+                Some(content) => fs::write(&target, content).expect("write"),
+                None => fs::remove_file(&target).expect("delete"),
+            }
+        }
+        TaskWrites::capture(sub_id, tracker.touched_files()).await
+    }
+
+    fn update() -> FileChange {
+        FileChange::Update {
+            unified_diff: String::new(),
+            move_path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn revert_restores_files_byte_for_byte() {
+        let dir = TempDir::new().expect("tempdir");
+        let edited = dir.path().join("edited.txt");
+        let deleted = dir.path().join("deleted.bin");
+        let moved = dir.path().join("old_name.txt");
+        let moved_to = dir.path().join("new_name.txt");
+        let added = dir.path().join("added.txt");
+        fs::write(&edited, "line one\r\nline two\n").expect("seed");
+        fs::write(&deleted, [0u8, 159, 146, 150]).expect("seed");
+        fs::write(&moved, "keep me\n").expect("seed");
+
+        let task = patching_task(
+            "sub-1",
+            vec![
+                (edited.clone(), update(), Some("rewritten\n")),
+                (
+                    deleted.clone(),
+                    FileChange::Delete {
+                        content: String::new(),
+                    },
+                    None,
+                ),
+                (
+                    moved.clone(),
+                    FileChange::Update {
+                        unified_diff: String::new(),
+                        move_path: Some(moved_to.clone()),
+                    },
+                    Some("keep me, renamed\n"),
+                ),
+                (
+                    added.clone(),
+                    FileChange::Add {
+                        content: String::new(),
+                    },
+                    Some("brand new\n"),
+                ),
+            ],
+        )
+        .await;
+
+        let event = task.revert(&[], false).await.expect("revert");
+        assert_eq!(
+            event,
+            TaskRevertedEvent {
+                sub_id: "sub-1".to_string(),
+                reverted: true,
+                restored: vec![
+                    moved.clone(),
+                    edited.clone(),
+                    deleted.clone(),
+                    added.clone()
+                ],
+                conflicts: Vec::new(),
+            }
+        );
+        assert_eq!(
+            fs::read(&edited).expect("edited"),
+            b"line one\r\nline two\n"
+        );
+        assert_eq!(fs::read(&deleted).expect("deleted"), [0u8, 159, 146, 150]);
+        assert_eq!(fs::read(&moved).expect("moved"), b"keep me\n");
+        assert!(!moved_to.exists());
+        assert!(!added.exists());
+    }
+
+    #[tokio::test]
+    async fn conflicting_revert_is_refused_unless_forced() {
+        let dir = TempDir::new().expect("tempdir");
+        let mine = dir.path().join("mine.txt");
+        let shared = dir.path().join("shared.txt");
+        let later_only = dir.path().join("later.txt");
+        fs::write(&mine, "mine before\n").expect("seed");
+        fs::write(&shared, "shared before\n").expect("seed");
+
+        let task = patching_task(
+            "sub-1",
+            vec![
+                (mine.clone(), update(), Some("mine after\n")),
+                (shared.clone(), update(), Some("shared after\n")),
+            ],
+        )
+        .await;
+        let later = patching_task(
+            "sub-2",
+            vec![
+                (shared.clone(), update(), Some("shared later\n")),
+                (
+                    later_only.clone(),
+                    FileChange::Add {
+                        content: String::new(),
+                    },
+                    Some("later\n"),
+                ),
+            ],
+        )
+        .await;
+        // The user edits `mine.txt` by hand.
+        fs::write(&mine, "mine after, plus my edit\n").expect("user edit");
+
+        let refused = task
+            .revert(std::slice::from_ref(&later), false)
+            .await
+            .expect("revert");
+        let conflicts = vec![
+            RevertConflict {
+                path: mine.clone(),
+                reason: RevertConflictReason::ModifiedSinceTask,
+            },
+            RevertConflict {
+                path: shared.clone(),
+                reason: RevertConflictReason::ChangedByLaterTask {
+                    sub_id: "sub-2".to_string(),
+                },
+            },
+        ];
+        assert_eq!(
+            refused,
+            TaskRevertedEvent {
+                sub_id: "sub-1".to_string(),
+                reverted: false,
+                restored: Vec::new(),
+                conflicts: conflicts.clone(),
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(&mine).expect("mine"),
+            "mine after, plus my edit\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&shared).expect("shared"),
+            "shared later\n"
+        );
+
+        // Forcing restores nothing here, since every file conflicts, so the
+        // task is not reported as reverted.
+        let forced = task
+            .revert(std::slice::from_ref(&later), true)
+            .await
+            .expect("revert");
+        assert_eq!(
+            forced,
+            TaskRevertedEvent {
+                sub_id: "sub-1".to_string(),
+                reverted: false,
+                restored: Vec::new(),
+                conflicts,
+            }
+        );
+
+        // The later task itself reverts cleanly.
+        let event = later.revert(&[], false).await.expect("revert later");
+        assert_eq!(event.restored, vec![shared.clone(), later_only.clone()]);
+        assert_eq!(
+            fs::read_to_string(&shared).expect("shared"),
+            "shared after\n"
+        );
+        assert!(!later_only.exists());
+
+        // With the later task gone, a forced revert restores `shared.txt`
+        // and still leaves the hand-edited file alone.
+        let partial = task.revert(&[], true).await.expect("revert");
+        assert_eq!(
+            partial,
+            TaskRevertedEvent {
+                sub_id: "sub-1".to_string(),
+                reverted: true,
+                restored: vec![shared.clone()],
+                conflicts: vec![RevertConflict {
+                    path: mine.clone(),
+                    reason: RevertConflictReason::ModifiedSinceTask,
+                }],
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(&shared).expect("shared"),
+            "shared before
+"
+        );
+        assert_eq!(
+            fs::read_to_string(&mine).expect("mine"),
+            "mine after, plus my edit
+"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Retrying a task whose `run` failed (`Session::spawn_task_with_retry`).
+//!
+//! A `run` that returns [`TaskResult::Failed`] with a retryable
+//! [`TaskError`](crate::protocol::TaskError) is a failed attempt, which a
+//! task spawned with a [`RetryPolicy`] makes again: the session emits
+//! `task_retry_attempt` with the attempt and the delay before the next one,
+//! waits it out, and runs the task again through [`SessionTask::retry`], on
+//! the original input and a fresh copy of the turn context. Once the policy
+//! allows no further attempt the last result stands, and the task finishes
+//! as if it had run once. A task timeout covers all attempts together.
+
+use std::sync::Arc;
+
+use serde_json::json;
+
+use super::SessionTask;
+use super::SessionTaskContext;
+use super::TaskResult;
+use crate::codex::TurnContext;
+use crate::protocol::InputItem;
+use crate::retry::RetryPolicy;
+
+/// Run `task`, retrying on `retry` while it fails.
+pub(super) async fn run_attempts(
+    task: Arc<dyn SessionTask>,
+    session: Arc<SessionTaskContext>,
+    ctx: Arc<TurnContext>,
+    sub_id: String,
+    input: Vec<InputItem>,
+    retry: Option<RetryPolicy>,
+) -> TaskResult {
+    let Some(policy) = retry else {
+        return task.run(session, ctx, sub_id, input).await;
+    };
+    let mut attempt = 1;
+    loop {
+        let attempt_task = Arc::clone(&task);
+        let attempt_session = Arc::clone(&session);
+        // Each attempt gets its own copy, so nothing a failed attempt left
+        // on the context carries over.
+        let attempt_ctx = Arc::new(TurnContext::clone(&ctx));
+        let result = if attempt == 1 {
+            attempt_task
+                .run(attempt_session, attempt_ctx, sub_id.clone(), input.clone())
+                .await
+        } else {
+            attempt_task
+                .retry(attempt_session, attempt_ctx, sub_id.clone(), input.clone())
+                .await
+        };
+        let retryable = matches!(&result, TaskResult::Failed(error) if error.retryable);
+        if !retryable || !policy.allows(attempt + 1) {
+            return result;
+        }
+        let delay = policy.delay(attempt);
+        session
+            .clone_session()
+            .emit_with_state(
+                "task_retry_attempt",
+                json!({
+                    "subId": sub_id,
+                    "taskKind": format!("{:?}", task.kind()),
+                    "attempt": attempt,
+                    "maxAttempts": policy.max_attempts,
+                    "delayMs": delay.as_millis() as u64,
+                }),
+            )
+            .await;
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,198 @@
+//! Background lane for system work, such as writing the timeline report for
+//! `Op::WriteTimelineReport`, that must never count as the active turn.
+//!
+//! Background tasks run the same [`SessionTask`] trait as turn tasks but are
+//! registered outside [`ActiveTurn`](crate::state::ActiveTurn): spawning one
+//! does not replace the running turn, spawning a turn does not abort them,
+//! and status queries and digests do not report them. At most
+//! [`MAX_CONCURRENT_BACKGROUND_TASKS`] run at once; the rest wait for a slot.
+//! They emit no `TaskStarted` or `TaskComplete`, only `background_task_*`
+//! visualizer events shaped like their `task_*` counterparts, so clients
+//! never mistake one for a turn. A spawn whose sub id is still running in
+//! the lane is rejected. Shutdown aborts them along with the turn.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use indexmap::IndexMap;
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+use tracing::trace;
+
+use super::SessionTask;
+use super::SessionTaskContext;
+use super::SharedTaskTimings;
+use super::SpawnRejectReason;
+use super::TaskResult;
+use super::reproducibility;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::protocol::InputItem;
+use crate::protocol::TurnAbortReason;
+use crate::state::RunningTask;
+use crate::state::TaskStatus;
+use crate::visualizer::CwdSnapshot;
+
+const MAX_CONCURRENT_BACKGROUND_TASKS: usize = 2;
+
+pub(crate) struct BackgroundTasks {
+    tasks: Mutex<IndexMap<String, RunningTask>>,
+    slots: Arc<Semaphore>,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        Self {
+            tasks: Mutex::new(IndexMap::new()),
+            slots: Arc::new(Semaphore::new(MAX_CONCURRENT_BACKGROUND_TASKS)),
+        }
+    }
+}
+
+impl Session {
+    /// Start `task` in the background lane; see the module docs.
+    pub(crate) async fn spawn_background_task<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+    ) {
+        let task_kind = task.kind();
+        // Held until the task is registered, so a spawn with the same sub id
+        // cannot slip in and the task cannot deregister first.
+        let mut tasks = self.background_tasks.tasks.lock().await;
+        let rejected = if self.state.lock().await.shutting_down {
+            Some(SpawnRejectReason::ShuttingDown)
+        } else if tasks.contains_key(&sub_id) {
+            Some(SpawnRejectReason::DuplicateSubId)
+        } else {
+            None
+        };
+        if let Some(reason) = rejected {
+            drop(tasks);
+            self.emit_with_state(
+                "background_task_spawn_rejected",
+                json!({
+                    "subId": sub_id,
+                    "taskKind": format!("{task_kind:?}"),
+                    "reason": reason.to_json(),
+                }),
+            )
+            .await;
+            return;
+        }
+
+        // Emitted before the task can start, so its completion never
+        // precedes it in the stream.
+        let mut spawned = json!({
+            "subId": sub_id,
+            "taskKind": format!("{task_kind:?}"),
+            "inputItems": input.len(),
+            "isReviewMode": turn_context.is_review_mode,
+        });
+        CwdSnapshot::capture(&turn_context.cwd)
+            .await
+            .add_to(&mut spawned);
+        self.emit_with_state("background_task_spawned", spawned)
+            .await;
+
+        let task: Arc<dyn SessionTask> = Arc::new(task);
+        let run_returned = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let session_ctx = Arc::new(SessionTaskContext::new(Arc::clone(self)));
+            let slots = Arc::clone(&self.background_tasks.slots);
+            let task_for_run = Arc::clone(&task);
+            let sub_clone = sub_id.clone();
+            let run_returned = Arc::clone(&run_returned);
+            tokio::spawn(async move {
+                let Ok(_slot) = slots.acquire_owned().await else {
+                    return;
+                };
+                let result = task_for_run
+                    .run(
+                        Arc::clone(&session_ctx),
+                        turn_context,
+                        sub_clone.clone(),
+                        input,
+                    )
+                    .await;
+                run_returned.store(true, Ordering::Release);
+                session_ctx
+                    .clone_session()
+                    .on_background_task_finished(sub_clone, result)
+                    .await;
+            })
+            .abort_handle()
+        };
+        tasks.insert(
+            sub_id,
+            RunningTask {
+                handle,
+                kind: task_kind,
+                task,
+                timings: SharedTaskTimings::default(),
+                run_returned,
+                client_context: None,
+                seed: reproducibility::new_seed(),
+                template: None,
+                focus: None,
+                turn_template: None,
+                timeout: None,
+                custom_events: BTreeMap::new(),
+            },
+        );
+    }
+
+    async fn on_background_task_finished(&self, sub_id: String, result: TaskResult) {
+        let (last_agent_message, failure) = result.into_parts();
+        let timings = self
+            .background_tasks
+            .tasks
+            .lock()
+            .await
+            .swap_remove(&sub_id)
+            .map(|task| task.timings);
+        let latency_breakdown = timings
+            .as_ref()
+            .map(SharedTaskTimings::latency_breakdown)
+            .unwrap_or_default();
+        let mut completed = json!({
+            "subId": sub_id,
+            "lastAgentMessage": last_agent_message,
+            "latencyBreakdown": latency_breakdown,
+        });
+        if let Some(failure) = failure {
+            completed["error"] = json!(failure);
+        }
+        self.emit_with_state("background_task_completed", completed)
+            .await;
+    }
+
+    /// Abort every background task, e.g. while shutting down.
+    pub(crate) async fn abort_background_tasks(self: &Arc<Self>, reason: TurnAbortReason) {
+        let tasks = std::mem::take(&mut *self.background_tasks.tasks.lock().await);
+        for (sub_id, task) in tasks {
+            // Completing on its own; see `halt_task`.
+            if task.status() == TaskStatus::Finishing {
+                continue;
+            }
+            trace!(task_kind = ?task.kind, sub_id, "aborting background task");
+            task.handle.abort();
+            let session_ctx = Arc::new(SessionTaskContext::new(Arc::clone(self)));
+            task.task.abort(session_ctx, &sub_id).await;
+            self.emit_with_state(
+                "background_task_aborted",
+                json!({
+                    "subId": sub_id,
+                    "taskKind": format!("{:?}", task.kind),
+                    "reason": format!("{reason:?}"),
+                }),
+            )
+            .await;
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Cooperative cancellation for tasks that stream from the model.
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use serde_json::Value;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+/// Cancelled by [`SessionTask::abort`](super::SessionTask::abort) so the
+/// task's `run` stops at its next yield point instead of writing anything
+/// more, and counting the model output the task streamed until then.
+#[derive(Debug, Default)]
+pub(crate) struct TaskCancellation {
+    token: CancellationToken,
+    bytes_processed: AtomicU64,
+}
+
+impl TaskCancellation {
+    pub(crate) fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Resolves once [`Self::cancel`] was called.
+    pub(crate) async fn cancelled(&self) {
+        self.token.cancelled().await;
+    }
+
+    /// Account for `delta` of model output.
+    pub(crate) fn record(&self, delta: &str) {
+        self.bytes_processed
+            .fetch_add(delta.len() as u64, Ordering::Relaxed);
+    }
+
+    /// `{ reason: "abort", bytesProcessed }`, reported as `cancellation` on
+    /// the `task_aborted` of a cancelled task.
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "reason": "abort",
+            "bytesProcessed": self.bytes_processed.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_wakes_waiters_and_keeps_the_streamed_byte_count() {
+        let cancellation = TaskCancellation::default();
+        cancellation.record("abcd");
+        cancellation.record("ef");
+        let before = cancellation.is_cancelled();
+
+        cancellation.cancel();
+        cancellation.cancelled().await;
+
+        assert_eq!(
+            (before, cancellation.is_cancelled(), cancellation.to_json()),
+            (
+                false,
+                true,
+                json!({ "reason": "abort", "bytesProcessed": 6 }),
+            )
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! User turns whose input is too large to send to the model in one request.
+//!
+//! With `task_input_chunk_size` set, a turn with more input items than that
+//! runs as a [`ChunkedRegularTask`]: the items are split into batches of at
+//! most that many, and each batch goes through the model turn loop in turn,
+//! as if the user had sent it once the model was done with the previous
+//! one. The task is announced once, reports `task_progress` with
+//! `{batchIndex, batchCount}` as each batch starts, and completes once,
+//! after the last batch, with the agent messages of all batches joined.
+//! A failing batch fails the task and the remaining batches never run.
+//! A batch that fills the context window queues the usual blocking
+//! compaction, which reruns it together with the batches after it.
+//! An abort stops the batch running, like it stops a [`RegularTask`], and
+//! the batches after it never run.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use super::RegularTask;
+use super::SessionTask;
+use super::SessionTaskContext;
+use super::TaskCancellation;
+use super::TaskExecutionReport;
+use super::TaskResult;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::codex::announce_task;
+use crate::codex::run_task_input;
+use crate::protocol::ClientContext;
+use crate::protocol::InputItem;
+use crate::state::TaskKind;
+
+/// Separates the agent messages of consecutive batches in the merged one.
+const MESSAGE_SEPARATOR: &str = "\n\n";
+
+pub(crate) struct ChunkedRegularTask {
+    chunk_size: NonZeroUsize,
+    /// `(batchIndex, batchCount)` of the batch running.
+    batch: Mutex<Option<(usize, usize)>>,
+    /// Stops the batch running on abort.
+    cancellation: TaskCancellation,
+}
+
+impl ChunkedRegularTask {
+    pub(crate) fn new(chunk_size: NonZeroUsize) -> Self {
+        Self {
+            chunk_size,
+            batch: Mutex::new(None),
+            cancellation: TaskCancellation::default(),
+        }
+    }
+
+    fn batch_progress(&self) -> Option<Value> {
+        let batch = *self.batch.lock().ok()?;
+        batch.map(|(index, count)| json!({ "batchIndex": index, "batchCount": count }))
+    }
+}
+
+#[async_trait]
+impl SessionTask for ChunkedRegularTask {
+    fn kind(&self) -> TaskKind {
+        TaskKind::Regular
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        session: Arc<SessionTaskContext>,
+        ctx: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+    ) -> TaskResult {
+        if input.is_empty() {
+            return TaskResult::Completed(None);
+        }
+        let sess = session.clone_session();
+        announce_task(&sess, &ctx, &sub_id, &input).await;
+        let batches: Vec<Vec<InputItem>> = input
+            .chunks(self.chunk_size.get())
+            .map(<[InputItem]>::to_vec)
+            .collect();
+        let batch_count = batches.len();
+        let mut messages = Vec::new();
+        let mut batches = batches.into_iter().enumerate();
+        while let Some((index, batch)) = batches.next() {
+            if self.cancellation.is_cancelled() {
+                break;
+            }
+            if let Ok(mut current) = self.batch.lock() {
+                *current = Some((index, batch_count));
+            }
+            if let Some(progress) = self.batch_progress() {
+                sess.emit_with_state(
+                    "task_progress",
+                    json!({
+                        "subId": sub_id,
+                        "taskKind": format!("{:?}", self.kind()),
+                        "progress": progress,
+                    }),
+                )
+                .await;
+            }
+            let result = run_task_input(
+                Arc::clone(&sess),
+                Arc::clone(&ctx),
+                sub_id.clone(),
+                batch,
+                &self.cancellation,
+            )
+            .await;
+            match result {
+                TaskResult::Completed(message) => messages.extend(message),
+                failed @ TaskResult::Failed(_) => return failed,
+            }
+            // Only taken if a compaction was queued for this batch.
+            let rest = batches.by_ref().flat_map(|(_, batch)| batch);
+            if sess.extend_blocking_compaction(&sub_id, rest).await {
+                break;
+            }
+        }
+        TaskResult::Completed((!messages.is_empty()).then(|| messages.join(MESSAGE_SEPARATOR)))
+    }
+
+    async fn abort(&self, _session: Arc<SessionTaskContext>, _sub_id: &str) {
+        self.cancellation.cancel();
+    }
+
+    fn cancellation(&self) -> Option<Value> {
+        Some(self.cancellation.to_json())
+    }
+
+    fn runs_turn_loop(&self) -> bool {
+        true
+    }
+
+    async fn progress(&self) -> Option<Value> {
+        self.batch_progress()
+    }
+}
+
+impl Session {
+    /// [`Self::spawn_task_for_client`] for user input: a [`RegularTask`], or
+    /// a [`ChunkedRegularTask`] when `input` has more items than the
+    /// configured `task_input_chunk_size`. A regular task is retried on the
+    /// configured `task_retry` policy, if any.
+    pub(crate) async fn spawn_user_turn(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        client_context: Option<ClientContext>,
+        replay_of: Option<String>,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        match self.services.task_input_chunk_size {
+            Some(chunk_size) if input.len() > chunk_size.get() => {
+                let task = ChunkedRegularTask::new(chunk_size);
+                self.spawn_task_for_client(
+                    turn_context,
+                    sub_id,
+                    input,
+                    task,
+                    client_context,
+                    replay_of,
+                )
+                .await
+            }
+            _ => match self.services.task_retry {
+                Some(retry) => {
+                    self.spawn_task_with_retry(
+                        turn_context,
+                        sub_id,
+                        input,
+                        RegularTask::default(),
+                        client_context,
+                        replay_of,
+                        retry,
+                    )
+                    .await
+                }
+                None => {
+                    self.spawn_task_for_client(
+                        turn_context,
+                        sub_id,
+                        input,
+                        RegularTask::default(),
+                        client_context,
+                        replay_of,
+                    )
+                    .await
+                }
+            },
+        }
+    }
+}
@@ -1,17 +1,52 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use serde_json::Value;
 
 use crate::codex::TurnContext;
 use crate::codex::compact;
+use crate::codex::compact::CompactionProgress;
 use crate::protocol::InputItem;
 use crate::state::TaskKind;
 
+use super::FollowUpTask;
 use super::SessionTask;
 use super::SessionTaskContext;
+use super::TaskCancellation;
+use super::TaskResult;
 
-#[derive(Clone, Copy, Default)]
-pub(crate) struct CompactTask;
+/// Why a compaction was started.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum CompactionTrigger {
+    /// Requested compaction that no task is waiting on.
+    #[default]
+    Background,
+    /// The user task `sub_id` hit the context window limit. The compaction
+    /// runs on its behalf and the task is respawned with `input` afterwards.
+    BlockingUserTask {
+        sub_id: String,
+        input: Vec<InputItem>,
+    },
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct CompactTask {
+    trigger: CompactionTrigger,
+    /// Updated by the compaction as its summary streams in.
+    progress: Arc<CompactionProgress>,
+    /// Stops the compaction on abort.
+    cancellation: Arc<TaskCancellation>,
+}
+
+impl CompactTask {
+    pub(crate) fn blocking(sub_id: String, input: Vec<InputItem>) -> Self {
+        Self {
+            trigger: CompactionTrigger::BlockingUserTask { sub_id, input },
+            progress: Arc::default(),
+            cancellation: Arc::default(),
+        }
+    }
+}
 
 #[async_trait]
 impl SessionTask for CompactTask {
@@ -25,7 +60,58 @@ impl SessionTask for CompactTask {
         ctx: Arc<TurnContext>,
         sub_id: String,
         input: Vec<InputItem>,
-    ) -> Option<String> {
-        compact::run_compact_task(session.clone_session(), ctx, sub_id, input).await
+    ) -> TaskResult {
+        let sess = session.clone_session();
+        let result = compact::run_compact_task(
+            Arc::clone(&sess),
+            Arc::clone(&ctx),
+            sub_id.clone(),
+            input,
+            &self.progress,
+            &self.cancellation,
+        )
+        .await;
+        if let CompactionTrigger::BlockingUserTask {
+            sub_id: blocked_sub_id,
+            input,
+        } = &self.trigger
+        {
+            sess.queue_follow_up(FollowUpTask::Resume {
+                turn_context: ctx,
+                sub_id: blocked_sub_id.clone(),
+                input: input.clone(),
+                compaction_sub_id: sub_id,
+            })
+            .await;
+        }
+        result
+    }
+
+    /// Stops the summary request and drops what it recorded into the history
+    /// so far, unless the summary already replaced it.
+    async fn abort(&self, session: Arc<SessionTaskContext>, _sub_id: &str) {
+        self.cancellation.cancel();
+        compact::discard_partial_compaction(
+            &session.clone_session(),
+            &self.progress,
+            &self.cancellation,
+        )
+        .await;
+    }
+
+    fn cancellation(&self) -> Option<Value> {
+        Some(self.cancellation.to_json())
+    }
+
+    fn blocked_sub_id(&self) -> Option<&str> {
+        match &self.trigger {
+            CompactionTrigger::Background => None,
+            CompactionTrigger::BlockingUserTask { sub_id, .. } => Some(sub_id),
+        }
+    }
+
+    /// Summary tokens received so far; see [`CompactionProgress::to_json`].
+    async fn progress(&self) -> Option<Value> {
+        self.progress.to_json()
     }
 }
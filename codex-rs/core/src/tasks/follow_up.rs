@@ -0,0 +1,173 @@
+//! Work a task hands off to run after it finishes. A task cannot spawn its
+//! successor directly: `spawn_task` replaces, and therefore aborts, whatever
+//! is running, including the caller.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde_json::json;
+use tracing::debug;
+
+use super::CompactTask;
+use super::RegularTask;
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::codex::compact::SUMMARIZATION_PROMPT;
+use crate::protocol::InputItem;
+
+pub(crate) enum FollowUpTask {
+    /// Compact on behalf of the user task `sub_id`, which could not proceed
+    /// because the context window is full.
+    BlockingCompaction {
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+    },
+    /// Resume the blocked user task once `compaction_sub_id` completed,
+    /// recording `input`, which it never got to.
+    Resume {
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        compaction_sub_id: String,
+    },
+}
+
+impl Session {
+    /// Queue a compaction that the user task `sub_id` waits on, to be
+    /// followed by resuming it on the compacted history with `input`, which
+    /// it has not recorded. Returns `false` if that task is itself resuming
+    /// after a blocking compaction, so a turn that is still too large fails
+    /// instead of looping.
+    pub(crate) async fn queue_blocking_compaction(
+        &self,
+        turn_context: Arc<TurnContext>,
+        sub_id: &str,
+        input: Vec<InputItem>,
+    ) -> bool {
+        let mut state = self.state.lock().await;
+        if state.last_blocking_compaction_for.as_deref() == Some(sub_id) {
+            return false;
+        }
+        state.last_blocking_compaction_for = Some(sub_id.to_string());
+        state.follow_up = Some(FollowUpTask::BlockingCompaction {
+            turn_context,
+            sub_id: sub_id.to_string(),
+            input,
+        });
+        true
+    }
+
+    /// Forget that `sub_id` was blocked on a compaction, once it ended.
+    pub(crate) async fn end_blocking_compaction(&self, sub_id: &str) {
+        let mut state = self.state.lock().await;
+        if state.last_blocking_compaction_for.as_deref() == Some(sub_id) {
+            state.last_blocking_compaction_for = None;
+        }
+    }
+
+    /// Append `extra` to what the user task `sub_id` is rerun with after
+    /// its queued blocking compaction. Returns `false`, changing nothing, if
+    /// no compaction is queued for that task.
+    pub(crate) async fn extend_blocking_compaction(
+        &self,
+        sub_id: &str,
+        extra: impl IntoIterator<Item = InputItem>,
+    ) -> bool {
+        match &mut self.state.lock().await.follow_up {
+            Some(FollowUpTask::BlockingCompaction {
+                sub_id: blocked,
+                input,
+                ..
+            }) if blocked.as_str() == sub_id => {
+                input.extend(extra);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) async fn queue_follow_up(&self, follow_up: FollowUpTask) {
+        self.state.lock().await.follow_up = Some(follow_up);
+    }
+
+    /// Start the queued follow-up, if any. A task the user started in the
+    /// meantime takes precedence and the follow-up is dropped.
+    ///
+    /// Boxed because it is awaited by the task it spawns, which makes the
+    /// future recursive.
+    pub(crate) fn start_follow_up(
+        self: &Arc<Self>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            let Some(follow_up) = self.state.lock().await.follow_up.take() else {
+                return;
+            };
+            if self.active_turn.lock().await.is_some() {
+                debug!("dropping follow-up task; another task started first");
+                return;
+            }
+            match follow_up {
+                FollowUpTask::BlockingCompaction {
+                    turn_context,
+                    sub_id,
+                    input,
+                } => {
+                    let prompt = vec![InputItem::Text {
+                        text: SUMMARIZATION_PROMPT.to_string(),
+                    }];
+                    self.spawn_task(
+                        turn_context,
+                        self.next_internal_sub_id(),
+                        prompt,
+                        CompactTask::blocking(sub_id, input),
+                    )
+                    .await;
+                }
+                FollowUpTask::Resume {
+                    turn_context,
+                    sub_id,
+                    input,
+                    compaction_sub_id,
+                } => {
+                    // A task of its own: the blocked one already completed.
+                    let resumed_sub_id = self.next_internal_sub_id();
+                    self.state.lock().await.last_blocking_compaction_for =
+                        Some(resumed_sub_id.clone());
+                    self.emit_with_state(
+                        "blocked_task_resumed",
+                        json!({
+                            "subId": sub_id,
+                            "resumedSubId": resumed_sub_id,
+                            "compactionSubId": compaction_sub_id,
+                        }),
+                    )
+                    .await;
+                    // Resumed user input gets the same deadline, counted afresh.
+                    match self.services.task_timeout {
+                        Some(timeout) => {
+                            self.spawn_task_with_timeout(
+                                turn_context,
+                                resumed_sub_id,
+                                input,
+                                RegularTask::resumed(),
+                                timeout,
+                            )
+                            .await;
+                        }
+                        None => {
+                            self.spawn_task(
+                                turn_context,
+                                resumed_sub_id,
+                                input,
+                                RegularTask::resumed(),
+                            )
+                            .await;
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
@@ -1,28 +1,164 @@
+mod attempts;
+mod background;
+mod cancellation;
+mod chunked;
 mod compact;
+mod follow_up;
+mod progress;
 mod regular;
+mod report;
+mod reproducibility;
 mod review;
+mod templates;
+mod timing;
+mod validation;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
 
 use async_trait::async_trait;
 use tracing::trace;
+use tracing::warn;
 
 use crate::codex::Session;
 use crate::codex::TurnContext;
+use crate::digest::TaskOutcome;
+use crate::protocol::ClientContext;
+use crate::protocol::ErrorEvent;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
 use crate::protocol::InputItem;
+use crate::protocol::SandboxPolicy;
 use crate::protocol::TaskCompleteEvent;
+use crate::protocol::TaskError;
+use crate::protocol::TaskErrorKind;
 use crate::protocol::TurnAbortReason;
 use crate::protocol::TurnAbortedEvent;
+use crate::protocol::TurnTemplateUse;
+use crate::retry::RetryPolicy;
+use crate::review_slices::ReviewSlice;
+use crate::session_features::FinishedTask;
 use crate::state::ActiveTurn;
 use crate::state::RunningTask;
+use crate::state::RunningTaskStatus;
 use crate::state::TaskKind;
+use crate::state::TaskStatus;
+use crate::tools::router::ToolRouter;
+use crate::visualizer::CwdSnapshot;
+use serde_json::Value;
 use serde_json::json;
+use tokio::sync::MutexGuard;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
+pub(crate) use background::BackgroundTasks;
+pub(crate) use cancellation::TaskCancellation;
 pub(crate) use compact::CompactTask;
+pub(crate) use follow_up::FollowUpTask;
 pub(crate) use regular::RegularTask;
+pub(crate) use report::TimelineReportTask;
+pub(crate) use reproducibility::TurnReproducibility;
+pub(crate) use reproducibility::TurnSeeds;
 pub(crate) use review::ReviewTask;
+pub(crate) use templates::TaskTemplate;
+pub(crate) use templates::TaskTemplates;
+pub(crate) use timing::FirstResponseHistogram;
+pub(crate) use timing::ReasoningPhase;
+pub(crate) use timing::ReasoningPhaseTracker;
+pub(crate) use timing::SharedTaskTimings;
+pub(crate) use validation::SpawnRejectReason;
+
+/// A task taken off the active turn and halted, whose
+/// [`SessionTask::abort`] is still to run.
+struct HaltedTask {
+    sub_id: String,
+    reason: TurnAbortReason,
+    task: Arc<dyn SessionTask>,
+    /// `task_aborted` payload, but for the task's `cancellation`.
+    action: Value,
+    finished: FinishedTask,
+    in_flight: AbortInFlight,
+}
+
+/// A task taken off the active turn and stopped, whose abort is still to be
+/// reported by [`Session::report_transition`].
+struct AbortedTask {
+    sub_id: String,
+    reason: TurnAbortReason,
+    /// `task_aborted` payload.
+    action: Value,
+    finished: FinishedTask,
+    in_flight: AbortInFlight,
+}
+
+/// Counts an abort in `Session::aborts_in_flight` from the moment its task
+/// is halted until the abort is reported, or given up on.
+struct AbortInFlight(Arc<Session>);
+
+impl AbortInFlight {
+    fn start(session: &Arc<Session>) -> Self {
+        session.aborts_in_flight.fetch_add(1, Ordering::AcqRel);
+        Self(Arc::clone(session))
+    }
+}
+
+impl Drop for AbortInFlight {
+    fn drop(&mut self) {
+        self.0.aborts_in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.0.idle.notify_waiters();
+    }
+}
+
+/// How a task that ran to completion went, for embedders that await the
+/// handle [`Session::spawn_task`] returns instead of reading its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaskExecutionReport {
+    pub(crate) sub_id: String,
+    pub(crate) task_kind: TaskKind,
+    /// From the spawn until `run` returned, retries included.
+    pub(crate) duration: Duration,
+    pub(crate) input_item_count: usize,
+    pub(crate) last_agent_message: Option<String>,
+    /// Set when the task ended on an error instead of finishing its work.
+    pub(crate) error: Option<TaskError>,
+}
+
+/// How a task's `run` ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TaskResult {
+    /// The task finished its work, with its last agent message if it sent
+    /// one.
+    Completed(Option<String>),
+    /// The task ended on an error instead; reported on its `TaskComplete`.
+    Failed(TaskError),
+}
+
+impl TaskResult {
+    /// The last agent message and the error, as `TaskComplete` reports them.
+    pub(crate) fn into_parts(self) -> (Option<String>, Option<TaskError>) {
+        match self {
+            TaskResult::Completed(last_agent_message) => (last_agent_message, None),
+            TaskResult::Failed(error) => (None, Some(error)),
+        }
+    }
+}
+
+/// What a spawn records with the task besides its input.
+#[derive(Default)]
+struct SpawnOptions {
+    client_context: Option<ClientContext>,
+    replay_of: Option<String>,
+    /// Abort the task with [`TurnAbortReason::Timeout`] once it has run
+    /// this long.
+    timeout: Option<Duration>,
+    turn_template: Option<TurnTemplateUse>,
+    /// Run the task again, on this schedule, while it fails.
+    retry: Option<RetryPolicy>,
+}
 
 /// Thin wrapper that exposes the parts of [`Session`] task runners need.
 #[derive(Clone)]
@@ -50,164 +186,908 @@ pub(crate) trait SessionTask: Send + Sync + 'static {
         ctx: Arc<TurnContext>,
         sub_id: String,
         input: Vec<InputItem>,
-    ) -> Option<String>;
+    ) -> TaskResult;
+
+    /// Run the task again after an attempt failed; see
+    /// [`Session::spawn_task_with_retry`]. Tasks that record their input
+    /// before failing should not record it again. Runs [`Self::run`] on the
+    /// original input by default.
+    async fn retry(
+        self: Arc<Self>,
+        session: Arc<SessionTaskContext>,
+        ctx: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+    ) -> TaskResult {
+        self.run(session, ctx, sub_id, input).await
+    }
 
     async fn abort(&self, session: Arc<SessionTaskContext>, sub_id: &str) {
         let _ = (session, sub_id);
     }
+
+    /// What the task got through before [`Self::abort`], reported as
+    /// `cancellation` on its `task_aborted`.
+    fn cancellation(&self) -> Option<Value> {
+        None
+    }
+
+    /// The user task this one unblocks, if it was started on its behalf.
+    fn blocked_sub_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// The files of a larger diff this task reviews, for sliced reviews.
+    fn review_slice(&self) -> Option<&ReviewSlice> {
+        None
+    }
+
+    /// Whether `run` drives the model turn loop; see
+    /// [`crate::session_features::INSTRUMENTATION_CHECKLIST`].
+    fn runs_turn_loop(&self) -> bool {
+        false
+    }
+
+    /// Intermediate state to report while the task runs, polled every
+    /// `task_progress_interval_ms` and emitted as `task_progress`.
+    async fn progress(&self) -> Option<Value> {
+        None
+    }
+
+    /// How long one of the task's approval requests may go unanswered, if
+    /// shorter than the configured approval timeout. Once it has, the
+    /// request resolves as timed out and [`Self::approval_timed_out`] is
+    /// called.
+    fn approval_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called when an approval request of the task outlived
+    /// [`Self::approval_timeout`].
+    fn approval_timed_out(&self) {}
 }
 
 impl Session {
+    /// The running task `sub_id`, if any.
+    pub(crate) async fn running_task(&self, sub_id: &str) -> Option<Arc<dyn SessionTask>> {
+        let active = self.active_turn.lock().await;
+        active
+            .as_ref()
+            .and_then(|at| at.tasks.get(sub_id))
+            .map(|task| Arc::clone(&task.task))
+    }
+
     /// Visualization hook: every task represents a new agent phase (plan,
     /// edit, test, review, auto-compact). Emit an event here with the new
     /// `sub_id`, `task.kind()`, and size of the `input` vector so the UI can
     /// show task lifetimes and understand which payload kicked off the phase.
+    ///
+    /// The returned handle yields the task's [`TaskExecutionReport`] once it
+    /// completes or times out, with a [`TaskErrorKind::Timeout`] error if it
+    /// did, or a cancellation if it is aborted otherwise; a rejected spawn
+    /// has none.
     pub async fn spawn_task<T: SessionTask>(
         self: &Arc<Self>,
         turn_context: Arc<TurnContext>,
         sub_id: String,
         input: Vec<InputItem>,
         task: T,
-    ) {
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        self.spawn_with_options(turn_context, sub_id, input, task, SpawnOptions::default())
+            .await
+    }
+
+    /// [`Self::spawn_task`] for input submitted with a [`ClientContext`],
+    /// which is kept on the task and reported with its telemetry, and
+    /// optionally marked as a replay of the earlier turn `replay_of`. The
+    /// task gets the configured `task_timeout`.
+    pub(crate) async fn spawn_task_for_client<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+        client_context: Option<ClientContext>,
+        replay_of: Option<String>,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        let options = SpawnOptions {
+            client_context,
+            replay_of,
+            timeout: self.services.task_timeout,
+            turn_template: None,
+            retry: None,
+        };
+        self.spawn_with_options(turn_context, sub_id, input, task, options)
+            .await
+    }
+
+    /// [`Self::spawn_task`] for input rendered from `turn_template`, which
+    /// is reported with the task's `task_spawned` and digest turn. The task
+    /// gets the configured `task_timeout`.
+    pub(crate) async fn spawn_task_from_template<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+        turn_template: TurnTemplateUse,
+        client_context: Option<ClientContext>,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        let options = SpawnOptions {
+            client_context,
+            replay_of: None,
+            timeout: self.services.task_timeout,
+            turn_template: Some(turn_template),
+            retry: self.services.task_retry,
+        };
+        self.spawn_with_options(turn_context, sub_id, input, task, options)
+            .await
+    }
+
+    /// [`Self::spawn_task`], aborting the task with
+    /// [`TurnAbortReason::Timeout`] if it is still running after `timeout`.
+    /// A task that times out sends `TurnAborted` instead of `TaskComplete`,
+    /// and its `task_aborted` carries `timeoutMs`.
+    pub async fn spawn_task_with_timeout<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+        timeout: Duration,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        let options = SpawnOptions {
+            timeout: Some(timeout),
+            ..SpawnOptions::default()
+        };
+        self.spawn_with_options(turn_context, sub_id, input, task, options)
+            .await
+    }
+
+    /// [`Self::spawn_task_for_client`], running the task again on `retry`
+    /// each time it fails, that is its `run` returns a retryable
+    /// [`TaskResult::Failed`]. Every retry is announced with
+    /// `task_retry_attempt` and runs through [`SessionTask::retry`] on a
+    /// fresh copy of `turn_context`; the task completes, or fails, once,
+    /// after its last attempt, and the configured `task_timeout` covers
+    /// all attempts together.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn spawn_task_with_retry<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+        client_context: Option<ClientContext>,
+        replay_of: Option<String>,
+        retry: RetryPolicy,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        let options = SpawnOptions {
+            client_context,
+            replay_of,
+            timeout: self.services.task_timeout,
+            turn_template: None,
+            retry: Some(retry),
+        };
+        self.spawn_with_options(turn_context, sub_id, input, task, options)
+            .await
+    }
+
+    async fn spawn_with_options<T: SessionTask>(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        input: Vec<InputItem>,
+        task: T,
+        options: SpawnOptions,
+    ) -> Option<JoinHandle<TaskExecutionReport>> {
+        let SpawnOptions {
+            client_context,
+            replay_of,
+            timeout,
+            turn_template,
+            retry,
+        } = options;
+        let spawned_at = Instant::now();
+        // Held until the replacement is reported, so the aborts and spawn of
+        // one replacement never interleave with another's, and a shutdown
+        // that has drained the tasks sees no new one registered after. It is
+        // only let go while the replaced tasks run their abort hooks.
+        let mut _transition = self.begin_transition().await;
+        if self.state.lock().await.shutting_down {
+            self.reject_spawn(sub_id, task.kind(), SpawnRejectReason::ShuttingDown)
+                .await;
+            return None;
+        }
+        if let Err(reason) = Self::validate_spawn(&turn_context) {
+            if self.services.warn_on_invalid_cwd {
+                warn!("{reason}; starting task anyway");
+            } else {
+                self.reject_spawn(sub_id, task.kind(), reason).await;
+                return None;
+            }
+        }
+
         // Visualization hook: aborting older tasks maps to timeline branches
         // getting cancelled (interrupts, plan revisions). Emit telemetry that
         // lists each aborted task's `TaskKind` and the `TurnAbortReason` so
         // guardrail events can explain why lanes disappeared.
-        self.abort_all_tasks(TurnAbortReason::Replaced).await;
+        let halted = self.halt_running_tasks(TurnAbortReason::Replaced).await;
+        let mut aborted = Vec::new();
+        if !halted.is_empty() {
+            // Transitions wait out the halted tasks' aborts in
+            // `begin_transition`, so nothing is registered meanwhile.
+            drop(_transition);
+            aborted = self.finish_aborts(halted).await;
+            _transition = self.task_transitions.lock().await;
+            if self.state.lock().await.shutting_down {
+                self.report_transition(aborted, None).await;
+                self.reject_spawn(sub_id, task.kind(), SpawnRejectReason::ShuttingDown)
+                    .await;
+                return None;
+            }
+        }
 
         let task: Arc<dyn SessionTask> = Arc::new(task);
         let task_kind = task.kind();
+        let blocked_sub_id = task.blocked_sub_id().map(str::to_string);
+        let review_slice = task.review_slice().cloned();
         let input_len = input.len();
+        let (seed, history_hash, focus) = {
+            let mut state = self.state.lock().await;
+            let seed = replay_of
+                .as_ref()
+                .and_then(|replayed| state.turn_seeds.get(replayed))
+                .unwrap_or_else(reproducibility::new_seed);
+            state.turn_seeds.insert(sub_id.clone(), seed);
+            (seed, state.history_hash(), state.focus.clone())
+        };
+        let tools = ToolRouter::from_config(
+            &turn_context.tools_config,
+            Some(self.services.mcp_connection_manager.list_all_tools()),
+        )
+        .specs();
+        let template = self
+            .services
+            .task_templates
+            .load(task_kind, &turn_context.cwd);
+        let reproducibility = TurnReproducibility::capture(
+            &turn_context,
+            &history_hash,
+            &input,
+            &tools,
+            template.as_deref(),
+            seed,
+            replay_of,
+        );
+        let run_returned = Arc::new(AtomicBool::new(false));
 
-        let handle = {
+        self.services.instrumentation.spawning(&sub_id);
+        let (start_tx, start_rx) = oneshot::channel::<()>();
+        let (handle, join) = {
             let session_ctx = Arc::new(SessionTaskContext::new(Arc::clone(self)));
             let ctx = Arc::clone(&turn_context);
             let task_for_run = Arc::clone(&task);
             let sub_clone = sub_id.clone();
-            tokio::spawn(async move {
-                let last_agent_message = task_for_run
-                    .run(Arc::clone(&session_ctx), ctx, sub_clone.clone(), input)
-                    .await;
+            let run_returned = Arc::clone(&run_returned);
+            let report = {
+                let sub_id = sub_id.clone();
+                move |result: TaskResult| {
+                    let (last_agent_message, error) = result.into_parts();
+                    TaskExecutionReport {
+                        sub_id,
+                        task_kind,
+                        duration: spawned_at.elapsed(),
+                        input_item_count: input_len,
+                        last_agent_message,
+                        error,
+                    }
+                }
+            };
+            let join = tokio::spawn(async move {
+                // Nothing the task sends may precede the report of its
+                // spawn; it runs even if the spawn never got to report it.
+                let _ = start_rx.await;
+                let run = attempts::run_attempts(
+                    task_for_run,
+                    Arc::clone(&session_ctx),
+                    ctx,
+                    sub_clone.clone(),
+                    input,
+                    retry,
+                );
+                let result = match timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, run).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            // Reported from a task of its own, since the
+                            // abort would cancel this one before it could
+                            // return its report.
+                            let sess = session_ctx.clone_session();
+                            tokio::spawn(async move {
+                                sess.abort_timed_out_task(sub_clone, timeout).await;
+                            });
+                            return report(TaskResult::Failed(TaskError {
+                                kind: TaskErrorKind::Timeout,
+                                retryable: TaskErrorKind::Timeout.is_retryable(),
+                                message: format!("task ran past its {timeout:?} deadline"),
+                            }));
+                        }
+                    },
+                    None => run.await,
+                };
+                // Flag before awaiting anything so status queries stop
+                // reporting a task whose work is already done as running.
+                run_returned.store(true, Ordering::Release);
+                let report = report(result.clone());
                 // Emit completion uniformly from spawn site so all tasks share the same lifecycle.
                 let sess = session_ctx.clone_session();
-                sess.on_task_finished(sub_clone, last_agent_message).await;
-            })
-            .abort_handle()
+                sess.on_task_finished(sub_clone, result).await;
+                sess.start_follow_up().await;
+                sess.evict_activity_when_idle().await;
+                report
+            });
+            (join.abort_handle(), join)
         };
+        if let Some(interval) = self.services.task_progress_interval {
+            self.watch_task_progress(
+                sub_id.clone(),
+                Arc::clone(&task),
+                interval,
+                handle.clone(),
+                Arc::clone(&run_returned),
+            );
+        }
 
         let running_task = RunningTask {
             handle,
             kind: task_kind,
             task,
+            timings: SharedTaskTimings::spawned_at(spawned_at),
+            run_returned,
+            client_context: client_context.clone(),
+            seed,
+            template: template.clone(),
+            focus: focus.clone(),
+            turn_template: turn_template.clone(),
+            timeout,
+            custom_events: BTreeMap::new(),
         };
         // Visualization hook: track the moment a task becomes "active" by
         // logging the `sub_id`, `task.kind`, and optional approval/tool state so
         // the visualization can light up the corresponding lane.
         self.register_new_active_task(sub_id.clone(), running_task)
             .await;
+        {
+            let mut state = self.state.lock().await;
+            state.tasks_started += 1;
+            state.last_task_sub_id = Some(sub_id.clone());
+        }
+        let mut spawned = json!({
+            "subId": sub_id,
+            "taskKind": format!("{:?}", task_kind),
+            "inputItems": input_len,
+            "isReviewMode": turn_context.is_review_mode,
+        });
+        CwdSnapshot::capture(&turn_context.cwd)
+            .await
+            .add_to(&mut spawned);
+        if let Some(blocked_sub_id) = blocked_sub_id {
+            spawned["blockedSubId"] = json!(blocked_sub_id);
+        }
+        if let Some(client_context) = client_context {
+            spawned["clientContext"] = json!(client_context);
+        }
+        if let Some(slice) = review_slice {
+            spawned["reviewSlice"] = json!({
+                "first": slice.first,
+                "last": slice.last,
+                "total": slice.total,
+                "label": slice.label(),
+            });
+        }
+        if let Some(focus) = focus {
+            spawned["focus"] = json!({
+                "label": focus.label,
+                "paths": focus.paths,
+            });
+        }
+        if let Some(template) = template {
+            spawned["taskTemplate"] = json!(template.as_ref());
+        }
+        if let Some(turn_template) = turn_template {
+            spawned["turnTemplate"] = json!(turn_template);
+        }
+        if let Some(timeout) = timeout {
+            spawned["timeoutMs"] = json!(timeout.as_millis() as u64);
+        }
+        spawned["reproducibility"] = json!(reproducibility);
+        self.report_transition(aborted, Some((sub_id, spawned)))
+            .await;
+        let _ = start_tx.send(());
+        Some(join)
+    }
+
+    /// Sampling seed of the running task `sub_id`, for its model requests.
+    pub(crate) async fn task_seed(&self, sub_id: &str) -> Option<u64> {
+        let active = self.active_turn.lock().await;
+        active
+            .as_ref()
+            .and_then(|at| at.tasks.get(sub_id))
+            .map(|task| task.seed)
+    }
+
+    /// Instructions template applied to the running task `sub_id`.
+    pub(crate) async fn task_template(&self, sub_id: &str) -> Option<Arc<TaskTemplate>> {
+        let active = self.active_turn.lock().await;
+        active
+            .as_ref()
+            .and_then(|at| at.tasks.get(sub_id))
+            .and_then(|task| task.template.clone())
+    }
+
+    /// Catch an unusable working directory (e.g. deleted earlier in the
+    /// session) up front instead of letting it surface later as an opaque
+    /// exec failure. Write access is only required when the turn's sandbox
+    /// grants writes to the workspace.
+    fn validate_spawn(turn_context: &TurnContext) -> Result<(), SpawnRejectReason> {
+        let require_writable = !turn_context.is_review_mode
+            && matches!(
+                turn_context.sandbox_policy,
+                SandboxPolicy::WorkspaceWrite { .. }
+            );
+        validation::validate_cwd(&turn_context.cwd, require_writable)
+    }
+
+    async fn reject_spawn(&self, sub_id: String, task_kind: TaskKind, reason: SpawnRejectReason) {
+        let event = Event {
+            id: sub_id.clone(),
+            msg: EventMsg::Error(ErrorEvent {
+                message: reason.to_string(),
+            }),
+        };
+        self.send_event(event).await;
         self.emit_with_state(
-            "task_spawned",
+            "task_spawn_rejected",
             json!({
                 "subId": sub_id,
-                "taskKind": format!("{:?}", task_kind),
-                "inputItems": input_len,
-                "cwd": turn_context.cwd.display().to_string(),
-                "isReviewMode": turn_context.is_review_mode,
+                "taskKind": format!("{task_kind:?}"),
+                "reason": reason.to_json(),
             }),
         )
         .await;
     }
 
     pub async fn abort_all_tasks(self: &Arc<Self>, reason: TurnAbortReason) {
+        let halted = {
+            let _transition = self.begin_transition().await;
+            self.halt_running_tasks(reason).await
+        };
+        let aborted = self.finish_aborts(halted).await;
+        let _transition = self.task_transitions.lock().await;
+        self.report_transition(aborted, None).await;
+    }
+
+    /// Abort the running task `sub_id` for outliving `timeout`, unless it
+    /// was replaced or aborted meanwhile.
+    async fn abort_timed_out_task(self: &Arc<Self>, sub_id: String, timeout: Duration) {
+        let halted = {
+            let _transition = self.task_transitions.lock().await;
+            let Some(task) = self.take_running_task(&sub_id).await else {
+                return;
+            };
+            warn!("task {sub_id} ran past its {timeout:?} deadline; aborting it");
+            self.halt_task(sub_id, task, TurnAbortReason::Timeout).await
+        };
+        let aborted = self.finish_aborts(halted.into_iter().collect()).await;
+        let _transition = self.task_transitions.lock().await;
+        self.report_transition(aborted, None).await;
+    }
+
+    /// Take `task_transitions` once no abort is in flight. Halted tasks'
+    /// abort hooks run without the lock, and a transition starting before
+    /// their aborts are reported could otherwise report its own first.
+    async fn begin_transition(&self) -> MutexGuard<'_, ()> {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            let transition = self.task_transitions.lock().await;
+            if self.aborts_in_flight.load(Ordering::Acquire) == 0 {
+                return transition;
+            }
+            drop(transition);
+            idle.await;
+        }
+    }
+
+    /// Take every task off the active turn and halt it, returning what is
+    /// left of the aborts for [`Self::finish_aborts`]. Callers hold
+    /// `task_transitions`.
+    async fn halt_running_tasks(self: &Arc<Self>, reason: TurnAbortReason) -> Vec<HaltedTask> {
+        let mut halted = Vec::new();
         for (sub_id, task) in self.take_all_running_tasks().await {
-            self.handle_task_abort(sub_id, task, reason.clone()).await;
+            halted.extend(self.halt_task(sub_id, task, reason.clone()).await);
         }
+        halted
     }
 
-    pub async fn on_task_finished(
-        self: &Arc<Self>,
-        sub_id: String,
-        last_agent_message: Option<String>,
-    ) {
+    /// Run the abort hooks of halted tasks, returning the aborts to report.
+    /// Called without `task_transitions`, so that a slow hook does not hold
+    /// it from timeouts and [`Self::wait_for_idle`].
+    async fn finish_aborts(self: &Arc<Self>, halted: Vec<HaltedTask>) -> Vec<AbortedTask> {
+        let mut aborted = Vec::new();
+        for HaltedTask {
+            sub_id,
+            reason,
+            task,
+            mut action,
+            finished,
+            in_flight,
+        } in halted
+        {
+            let session_ctx = Arc::new(SessionTaskContext::new(Arc::clone(self)));
+            task.abort(session_ctx, &sub_id).await;
+            self.end_blocking_compaction(&sub_id).await;
+            if let Some(cancellation) = task.cancellation() {
+                action["cancellation"] = cancellation;
+            }
+            aborted.push(AbortedTask {
+                sub_id,
+                reason,
+                action,
+                finished,
+                in_flight,
+            });
+        }
+        aborted
+    }
+
+    /// Report the aborts of one transition and then the spawn that replaced
+    /// them, if any. All task aborts and spawns are reported here, with
+    /// `task_transitions` held, which is what guarantees consumers (see
+    /// `ordered_task_replacement` in the feature registry) that a replaced
+    /// task's `TurnAborted` and `task_aborted` come before its
+    /// replacement's `task_spawned` and anything the replacement sends.
+    async fn report_transition(&self, aborted: Vec<AbortedTask>, spawned: Option<(String, Value)>) {
+        for AbortedTask {
+            sub_id,
+            reason,
+            action,
+            finished,
+            in_flight,
+        } in aborted
+        {
+            self.send_event(Event {
+                id: sub_id.clone(),
+                msg: EventMsg::TurnAborted(TurnAbortedEvent { reason }),
+            })
+            .await;
+            self.emit_with_state("task_aborted", action).await;
+            self.check_instrumentation(&sub_id, finished).await;
+            drop(in_flight);
+        }
+        if let Some((sub_id, action)) = spawned {
+            self.emit_with_state("task_spawned", action).await;
+            if let Some(gap) = self.services.instrumentation.spawned(&sub_id) {
+                self.report_instrumentation_gap(&sub_id, gap).await;
+            }
+        }
+    }
+
+    pub async fn on_task_finished(self: &Arc<Self>, sub_id: String, result: TaskResult) {
+        let (last_agent_message, failure) = result.into_parts();
+        self.end_blocking_compaction(&sub_id).await;
         let mut active = self.active_turn.lock().await;
+        let finishing = active.as_ref().and_then(|at| at.tasks.get(&sub_id));
+        let runs_turn_loop = finishing.is_some_and(|task| task.task.runs_turn_loop());
+        let outcome = finishing.map(|task| TaskOutcome {
+            kind: task.kind,
+            duration: task.timings.elapsed(Instant::now()).unwrap_or_default(),
+            focus: task.focus.as_ref().map(|focus| focus.label.clone()),
+        });
+        let (timings, client_context) = finishing
+            .map(|task| (task.timings.clone(), task.client_context.clone()))
+            .unzip();
+        let turn_template = finishing.and_then(|task| task.turn_template.clone());
+        let custom_events = finishing
+            .map(|task| task.custom_events.clone())
+            .unwrap_or_default();
+        let mut turn_ended = false;
         if let Some(at) = active.as_mut()
             && at.remove_task(&sub_id)
         {
             *active = None;
+            turn_ended = true;
         }
         drop(active);
+        // A task that was never registered is still reported as a turn.
+        let outcome = outcome.unwrap_or(TaskOutcome {
+            kind: TaskKind::Regular,
+            duration: Duration::ZERO,
+            focus: None,
+        });
+        self.record_turn_completed(
+            &sub_id,
+            last_agent_message.clone(),
+            turn_template,
+            outcome,
+            failure.clone(),
+        )
+        .await;
+        let latency_breakdown = timings
+            .as_ref()
+            .map(SharedTaskTimings::latency_breakdown)
+            .unwrap_or_default();
+        let first_response = timings.as_ref().and_then(SharedTaskTimings::first_response);
+        if let Some(latency) = first_response {
+            self.state
+                .lock()
+                .await
+                .first_response_latency
+                .record(latency);
+        }
         // Visualization hook: TaskComplete closes the lane and carries the
         // assistant's final message for the phase. Emit the `sub_id` and
         // `last_agent_message` alongside completion timestamps so latency can
         // be derived relative to the spawn event.
         let completion_preview = last_agent_message.clone();
+        // A review rejected for want of an approval ends like an aborted
+        // turn, so clients do not take it for a finished review.
+        let msg = match failure.as_ref().map(|failure| failure.kind) {
+            Some(TaskErrorKind::ApprovalTimeout) => EventMsg::TurnAborted(TurnAbortedEvent {
+                reason: TurnAbortReason::ApprovalTimeout,
+            }),
+            _ => EventMsg::TaskComplete(TaskCompleteEvent {
+                last_agent_message,
+                error: failure.clone(),
+            }),
+        };
         let event = Event {
             id: sub_id.clone(),
-            msg: EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }),
+            msg,
         };
         self.send_event(event).await;
+        let mut completed = json!({
+            "subId": sub_id,
+            "lastAgentMessage": completion_preview,
+            "latencyBreakdown": latency_breakdown,
+            "firstResponseMs": first_response.map(|latency| latency.as_millis() as u64),
+        });
+        if let Some(client_context) = client_context.flatten() {
+            completed["clientContext"] = json!(client_context);
+        }
+        if let Some(failure) = failure {
+            completed["error"] = json!(failure);
+        }
+        if !custom_events.is_empty() {
+            completed["customEvents"] = json!(custom_events);
+        }
+        self.emit_with_state("task_completed", completed).await;
+        self.check_instrumentation(
+            &sub_id,
+            FinishedTask {
+                runs_turn_loop,
+                aborted: false,
+            },
+        )
+        .await;
+        // Only now, so a waiter sees the completion reported.
+        if turn_ended {
+            self.idle.notify_waiters();
+        }
+    }
+
+    /// Resolves once no task is running, every task having completed or
+    /// been aborted, and what ended the last one was reported. Returns at
+    /// once on an idle session.
+    pub(crate) async fn wait_for_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            {
+                // Aborts are reported with `task_transitions` held, so this
+                // waits out one being reported; those still running their
+                // abort hooks are counted in `aborts_in_flight`.
+                let _transition = self.task_transitions.lock().await;
+                if self.active_turn.lock().await.is_none()
+                    && self.aborts_in_flight.load(Ordering::Acquire) == 0
+                {
+                    return;
+                }
+            }
+            idle.await;
+        }
+    }
+
+    /// Record a finished reasoning phase against the running task and emit
+    /// it to the visualizer so the timeline can split "thinking" from
+    /// "answering".
+    pub(crate) async fn on_reasoning_phase_finished(&self, sub_id: &str, phase: ReasoningPhase) {
+        let timings = {
+            let active = self.active_turn.lock().await;
+            active
+                .as_ref()
+                .and_then(|at| at.tasks.get(sub_id))
+                .map(|task| task.timings.clone())
+        };
+        if let Some(timings) = timings {
+            timings.record_reasoning_phase(phase);
+        }
+        self.emit_with_state(
+            "reasoning_phase",
+            json!({
+                "subId": sub_id,
+                "durationMs": phase.duration.as_millis() as u64,
+                "reasoningTokenEstimate": phase.token_estimate,
+            }),
+        )
+        .await;
+    }
+
+    /// Record the first assistant output (text or a tool call) of the
+    /// running task `sub_id` and emit its latency from spawn as
+    /// `first_response_latency`. Later output of the task changes nothing.
+    pub(crate) async fn on_first_response(&self, sub_id: &str) {
+        let now = Instant::now();
+        let timings = {
+            let active = self.active_turn.lock().await;
+            active
+                .as_ref()
+                .and_then(|at| at.tasks.get(sub_id))
+                .map(|task| task.timings.clone())
+        };
+        let Some(latency) = timings.and_then(|timings| timings.record_first_response(now)) else {
+            return;
+        };
         self.emit_with_state(
-            "task_completed",
+            "first_response_latency",
             json!({
                 "subId": sub_id,
-                "lastAgentMessage": completion_preview,
+                "firstResponseMs": latency.as_millis() as u64,
             }),
         )
         .await;
     }
 
+    /// Tasks registered on the active turn, with `run`-completed tasks
+    /// reported as [`TaskStatus::Finishing`] until they are deregistered.
+    pub(crate) async fn running_tasks(&self) -> Vec<RunningTaskStatus> {
+        let active = self.active_turn.lock().await;
+        active
+            .as_ref()
+            .map(ActiveTurn::task_statuses)
+            .unwrap_or_default()
+    }
+
+    /// How many tasks are registered on the active turn; see
+    /// [`Codex::active_task_count`](crate::codex::Codex::active_task_count).
+    pub(crate) async fn active_task_count(&self) -> usize {
+        let active = self.active_turn.lock().await;
+        active.as_ref().map_or(0, |at| at.tasks.len())
+    }
+
+    /// The kinds of the tasks registered on the active turn, in the order
+    /// they were registered.
+    pub(crate) async fn task_kinds(&self) -> Vec<TaskKind> {
+        let active = self.active_turn.lock().await;
+        active
+            .as_ref()
+            .map(|at| at.tasks.values().map(|task| task.kind).collect())
+            .unwrap_or_default()
+    }
+
     async fn register_new_active_task(&self, sub_id: String, task: RunningTask) {
         let mut active = self.active_turn.lock().await;
+        // The spawn stopped every task first, so anything left is lost.
+        if let Some(previous) = active.as_ref()
+            && !previous.is_empty().await
+        {
+            warn!("task {sub_id} replaces a turn that still has tasks or pending operations");
+        }
         let mut turn = ActiveTurn::default();
         turn.add_task(sub_id, task);
         *active = Some(turn);
     }
 
-    async fn take_all_running_tasks(&self) -> Vec<(String, RunningTask)> {
-        let mut active = self.active_turn.lock().await;
-        match active.take() {
-            Some(mut at) => {
-                at.clear_pending().await;
-                let tasks = at.drain_tasks();
-                tasks.into_iter().collect()
+    async fn take_running_task(&self, sub_id: &str) -> Option<RunningTask> {
+        let (task, unanswered) = {
+            let mut active = self.active_turn.lock().await;
+            let at = active.as_mut()?;
+            let task = at.tasks.swap_remove(sub_id)?;
+            let mut unanswered = Vec::new();
+            if at.tasks.is_empty() {
+                unanswered = at.clear_pending().await;
+                *active = None;
+                self.idle.notify_waiters();
             }
-            None => Vec::new(),
+            (task, unanswered)
+        };
+        self.report_unanswered_approvals(unanswered).await;
+        Some(task)
+    }
+
+    async fn take_all_running_tasks(&self) -> Vec<(String, RunningTask)> {
+        let taken = self.active_turn.lock().await.take();
+        let Some(mut at) = taken else {
+            return Vec::new();
+        };
+        self.idle.notify_waiters();
+        let unanswered = at.clear_pending().await;
+        self.report_unanswered_approvals(unanswered).await;
+        at.drain_tasks().into_iter().collect()
+    }
+
+    /// Report the approval requests a cleared turn dropped unanswered.
+    async fn report_unanswered_approvals(&self, sub_ids: Vec<String>) {
+        if sub_ids.is_empty() {
+            return;
         }
+        self.emit_with_state("pending_approvals_cleared", json!({ "subIds": sub_ids }))
+            .await;
     }
 
-    async fn handle_task_abort(
+    /// Halt `task`, leaving its abort hook to [`Self::finish_aborts`].
+    async fn halt_task(
         self: &Arc<Self>,
         sub_id: String,
         task: RunningTask,
         reason: TurnAbortReason,
-    ) {
-        if task.handle.is_finished() {
-            return;
+    ) -> Option<HaltedTask> {
+        // A task whose `run` already returned is completing on its own;
+        // aborting it now would report an abort for work that finished.
+        if task.status() == TaskStatus::Finishing {
+            return None;
         }
 
+        let in_flight = AbortInFlight::start(self);
         let task_kind = task.kind;
-        trace!(task_kind = ?task.kind, sub_id, "aborting running task");
-        let session_task = task.task;
-        let handle = task.handle;
-        handle.abort();
-        let session_ctx = Arc::new(SessionTaskContext::new(Arc::clone(self)));
-        session_task.abort(session_ctx, &sub_id).await;
-
-        let reason_text = format!("{reason:?}");
-        let event = Event {
-            id: sub_id.clone(),
-            msg: EventMsg::TurnAborted(TurnAbortedEvent { reason }),
-        };
-        self.send_event(event).await;
-        self.emit_with_state(
-            "task_aborted",
-            json!({
-                "subId": sub_id,
-                "taskKind": format!("{:?}", task_kind),
-                "reason": reason_text,
-            }),
+        let timeout = task.timeout;
+        let runs_turn_loop = task.task.runs_turn_loop();
+        let first_response = task.timings.first_response();
+        self.record_task_aborted(
+            &sub_id,
+            TaskOutcome {
+                kind: task_kind,
+                duration: task.timings.elapsed(Instant::now()).unwrap_or_default(),
+                focus: task.focus.as_ref().map(|focus| focus.label.clone()),
+            },
         )
         .await;
+        {
+            let mut state = self.state.lock().await;
+            match first_response {
+                Some(latency) => state.first_response_latency.record(latency),
+                None => state
+                    .first_response_latency
+                    .record_aborted_before_response(),
+            }
+        }
+        trace!(task_kind = ?task.kind, sub_id, "aborting running task");
+        task.handle.abort();
+
+        let mut action = json!({
+            "subId": sub_id,
+            "taskKind": format!("{:?}", task_kind),
+            "reason": format!("{reason:?}"),
+            "firstResponseMs": first_response.map(|latency| latency.as_millis() as u64),
+            "abortedBeforeResponse": first_response.is_none(),
+        });
+        if let Some(timeout) = timeout {
+            action["timeoutMs"] = json!(timeout.as_millis() as u64);
+        }
+        Some(HaltedTask {
+            sub_id,
+            reason,
+            task: task.task,
+            action,
+            finished: FinishedTask {
+                runs_turn_loop,
+                aborted: true,
+            },
+            in_flight,
+        })
     }
 }
 
@@ -0,0 +1,66 @@
+//! Intermediate state of running tasks (`task_progress`).
+//!
+//! While a task runs, a poller of its own asks it for
+//! [`SessionTask::progress`] every `task_progress_interval_ms` and emits
+//! what it reports as a `task_progress` event with its `subId`. Tasks that
+//! report nothing emit nothing, so a poller costs a call per tick.
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+
+use super::SessionTask;
+use crate::codex::Session;
+
+impl Session {
+    /// Ask `task`, running as `sub_id`, for its progress and emit
+    /// `task_progress` if it reports some.
+    pub(crate) async fn poll_task_progress(&self, sub_id: &str, task: &dyn SessionTask) {
+        let Some(progress) = task.progress().await else {
+            return;
+        };
+        self.emit_with_state(
+            "task_progress",
+            json!({
+                "subId": sub_id,
+                "taskKind": format!("{:?}", task.kind()),
+                "progress": progress,
+            }),
+        )
+        .await;
+    }
+
+    /// Every `interval`, poll the progress of `task` until it has returned
+    /// or been aborted through `handle`. Holds the session weakly, so the
+    /// poller also ends with it.
+    pub(super) fn watch_task_progress(
+        self: &Arc<Self>,
+        sub_id: String,
+        task: Arc<dyn SessionTask>,
+        interval: Duration,
+        handle: AbortHandle,
+        run_returned: Arc<AtomicBool>,
+    ) {
+        let session = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + interval;
+            let mut ticks = tokio::time::interval_at(start, interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                if handle.is_finished() || run_returned.load(Ordering::Acquire) {
+                    return;
+                }
+                let Some(session) = session.upgrade() else {
+                    return;
+                };
+                session.poll_task_progress(&sub_id, task.as_ref()).await;
+            }
+        });
+    }
+}
@@ -1,17 +1,39 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use serde_json::Value;
 
 use crate::codex::TurnContext;
+use crate::codex::announce_task;
 use crate::codex::run_task;
+use crate::codex::run_task_input;
 use crate::protocol::InputItem;
 use crate::state::TaskKind;
 
 use super::SessionTask;
 use super::SessionTaskContext;
+use super::TaskCancellation;
+use super::TaskResult;
 
-#[derive(Clone, Copy, Default)]
-pub(crate) struct RegularTask;
+#[derive(Clone, Default)]
+pub(crate) struct RegularTask {
+    /// Stops the turn loop on abort.
+    cancellation: Arc<TaskCancellation>,
+    /// Continues a task blocked on a compaction, whose input is already in
+    /// the history; only the input it never got to is recorded.
+    resumed: bool,
+}
+
+impl RegularTask {
+    /// A task picking up where a blocked one left off; see
+    /// [`Session::start_follow_up`](crate::codex::Session::start_follow_up).
+    pub(crate) fn resumed() -> Self {
+        Self {
+            resumed: true,
+            ..Self::default()
+        }
+    }
+}
 
 #[async_trait]
 impl SessionTask for RegularTask {
@@ -25,8 +47,49 @@ impl SessionTask for RegularTask {
         ctx: Arc<TurnContext>,
         sub_id: String,
         input: Vec<InputItem>,
-    ) -> Option<String> {
+    ) -> TaskResult {
         let sess = session.clone_session();
-        run_task(sess, ctx, sub_id, input).await
+        if self.resumed {
+            announce_task(&sess, &ctx, &sub_id, &input).await;
+            return run_task_input(sess, ctx, sub_id, input, &self.cancellation).await;
+        }
+        run_task(sess, ctx, sub_id, input, &self.cancellation).await
+    }
+
+    /// Picks the turn loop up on the history the failed attempt left, which
+    /// already holds the input.
+    async fn retry(
+        self: Arc<Self>,
+        session: Arc<SessionTaskContext>,
+        ctx: Arc<TurnContext>,
+        sub_id: String,
+        _input: Vec<InputItem>,
+    ) -> TaskResult {
+        run_task_input(
+            session.clone_session(),
+            ctx,
+            sub_id,
+            Vec::new(),
+            &self.cancellation,
+        )
+        .await
+    }
+
+    /// Drops the turn in flight before it is recorded; see
+    /// [`crate::codex::run_task_input`].
+    async fn abort(&self, _session: Arc<SessionTaskContext>, _sub_id: &str) {
+        self.cancellation.cancel();
+    }
+
+    fn cancellation(&self) -> Option<Value> {
+        Some(self.cancellation.to_json())
+    }
+
+    fn runs_turn_loop(&self) -> bool {
+        true
+    }
+
+    async fn progress(&self) -> Option<Value> {
+        None
     }
 }
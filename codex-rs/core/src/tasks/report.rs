@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::codex::TurnContext;
+use crate::protocol::BackgroundEventEvent;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::InputItem;
+use crate::protocol::TaskError;
+use crate::protocol::TaskErrorKind;
+use crate::state::TaskKind;
+
+use super::SessionTask;
+use super::SessionTaskContext;
+use super::TaskResult;
+
+/// Writes the timeline report for `Op::WriteTimelineReport`. Runs in the
+/// background lane, so a long report neither holds up the submission loop
+/// nor replaces the running turn.
+pub(crate) struct TimelineReportTask {
+    path: PathBuf,
+}
+
+impl TimelineReportTask {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl SessionTask for TimelineReportTask {
+    fn kind(&self) -> TaskKind {
+        TaskKind::Report
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        session: Arc<SessionTaskContext>,
+        _ctx: Arc<TurnContext>,
+        sub_id: String,
+        _input: Vec<InputItem>,
+    ) -> TaskResult {
+        let sess = session.clone_session();
+        let (msg, result) = match sess.write_timeline_report(&self.path).await {
+            Ok(()) => (
+                EventMsg::BackgroundEvent(BackgroundEventEvent {
+                    message: format!("Wrote timeline report to {}", self.path.display()),
+                }),
+                TaskResult::Completed(None),
+            ),
+            Err(err) => {
+                let message = format!(
+                    "failed to write timeline report to {}: {err}",
+                    self.path.display()
+                );
+                (
+                    EventMsg::Error(ErrorEvent {
+                        message: message.clone(),
+                    }),
+                    TaskResult::Failed(TaskError {
+                        kind: TaskErrorKind::Internal,
+                        retryable: false,
+                        message,
+                    }),
+                )
+            }
+        };
+        sess.send_event(Event { id: sub_id, msg }).await;
+        result
+    }
+}
@@ -0,0 +1,138 @@
+//! The inputs that determine a turn's model output, captured at spawn time
+//! so a turn can be re-run and compared against the original.
+
+use std::collections::VecDeque;
+
+use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
+use codex_protocol::config_types::ReasoningSummary as ReasoningSummaryConfig;
+use rand::Rng;
+use serde::Serialize;
+use sha1::Digest;
+use sha1::Sha1;
+
+use crate::client_common::tools::ToolSpec;
+use crate::codex::TurnContext;
+use crate::protocol::InputItem;
+
+use super::TaskTemplate;
+
+/// Seeds of older turns are forgotten beyond this many; replaying one of
+/// them draws a fresh seed.
+const MAX_TURN_SEEDS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TurnReproducibility {
+    pub(crate) model: String,
+    pub(crate) effort: Option<ReasoningEffortConfig>,
+    pub(crate) summary: ReasoningSummaryConfig,
+    /// Hash of the instructions, task template, conversation history and new
+    /// input the turn starts from.
+    pub(crate) prompt_hash: String,
+    pub(crate) tools: Vec<ToolVersion>,
+    pub(crate) seed: u64,
+    /// Whether the provider receives the seed; without it the seed only
+    /// identifies the turn.
+    pub(crate) seed_forwarded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) replay_of: Option<String>,
+}
+
+/// Tools have no version of their own; the hash of the spec sent to the
+/// model changes whenever its description or schema does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ToolVersion {
+    pub(crate) name: String,
+    pub(crate) spec_hash: String,
+}
+
+impl TurnReproducibility {
+    pub(crate) fn capture(
+        turn_context: &TurnContext,
+        history_hash: &str,
+        input: &[InputItem],
+        tools: &[ToolSpec],
+        template: Option<&TaskTemplate>,
+        seed: u64,
+        replay_of: Option<String>,
+    ) -> Self {
+        let prompt = serde_json::json!({
+            "baseInstructions": turn_context.base_instructions,
+            "userInstructions": turn_context.user_instructions,
+            "taskTemplate": template.map(|template| &template.hash),
+            "history": history_hash,
+            "input": input,
+        });
+        Self {
+            model: turn_context.client.get_model(),
+            effort: turn_context.client.get_reasoning_effort(),
+            summary: turn_context.client.get_reasoning_summary(),
+            prompt_hash: sha1_hex(&prompt),
+            tools: tools
+                .iter()
+                .map(|spec| ToolVersion {
+                    name: spec.name().to_string(),
+                    spec_hash: sha1_hex(spec),
+                })
+                .collect(),
+            seed,
+            seed_forwarded: turn_context.client.get_provider().accepts_seed(),
+            replay_of,
+        }
+    }
+}
+
+/// Sampling seeds of the most recently spawned turns, by submission id.
+#[derive(Debug, Default)]
+pub(crate) struct TurnSeeds {
+    seeds: VecDeque<(String, u64)>,
+}
+
+impl TurnSeeds {
+    pub(crate) fn get(&self, sub_id: &str) -> Option<u64> {
+        self.seeds
+            .iter()
+            .find(|(id, _)| id == sub_id)
+            .map(|(_, seed)| *seed)
+    }
+
+    pub(crate) fn insert(&mut self, sub_id: String, seed: u64) {
+        if self.seeds.len() == MAX_TURN_SEEDS {
+            self.seeds.pop_front();
+        }
+        self.seeds.push_back((sub_id, seed));
+    }
+}
+
+/// A fresh seed. Kept within `u32` so it survives a round trip through
+/// clients that parse JSON numbers as doubles.
+pub(crate) fn new_seed() -> u64 {
+    u64::from(rand::rng().random::<u32>())
+}
+
+fn sha1_hex(value: &impl Serialize) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(serde_json::to_vec(value).unwrap_or_default());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn turn_seeds_forget_the_oldest_turns() {
+        let mut seeds = TurnSeeds::default();
+        for turn in 0..=MAX_TURN_SEEDS {
+            seeds.insert(format!("sub-{turn}"), turn as u64);
+        }
+        assert_eq!(seeds.get("sub-0"), None);
+        assert_eq!(seeds.get("sub-1"), Some(1));
+        assert_eq!(
+            seeds.get(&format!("sub-{MAX_TURN_SEEDS}")),
+            Some(MAX_TURN_SEEDS as u64)
+        );
+    }
+}
@@ -1,18 +1,67 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::sync::Notify;
 
 use crate::codex::TurnContext;
 use crate::codex::exit_review_mode;
 use crate::codex::run_task;
 use crate::protocol::InputItem;
+use crate::protocol::TaskError;
+use crate::protocol::TaskErrorKind;
+use crate::review_slices::ReviewSlice;
 use crate::state::TaskKind;
 
 use super::SessionTask;
 use super::SessionTaskContext;
+use super::TaskCancellation;
+use super::TaskResult;
 
-#[derive(Clone, Copy, Default)]
-pub(crate) struct ReviewTask;
+/// Seconds a review waits on one approval request before it is rejected;
+/// see [`ReviewTask::from_env`].
+const REVIEW_TIMEOUT_ENV: &str = "CODEX_REVIEW_TIMEOUT_SECS";
+
+#[derive(Clone, Default)]
+pub(crate) struct ReviewTask {
+    slice: Option<ReviewSlice>,
+    /// Set by [`ReviewTask::with_approval_timeout`].
+    approval_timeout: Option<Duration>,
+    /// Notified when an approval request outlived `approval_timeout`.
+    approval_expired: Arc<Notify>,
+}
+
+impl ReviewTask {
+    /// A review of only the files in `slice` of a larger diff.
+    pub(crate) fn sliced(slice: Option<ReviewSlice>) -> Self {
+        Self {
+            slice,
+            ..Self::default()
+        }
+    }
+
+    /// [`ReviewTask::sliced`], with the approval timeout set from
+    /// `CODEX_REVIEW_TIMEOUT_SECS` when that is a positive number of seconds.
+    pub(crate) fn from_env(slice: Option<ReviewSlice>) -> Self {
+        let approval_timeout = std::env::var(REVIEW_TIMEOUT_ENV)
+            .ok()
+            .and_then(|secs| secs.trim().parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+        Self::sliced(slice).with_approval_timeout(approval_timeout)
+    }
+
+    /// Reject the review once one of its approval requests has gone
+    /// unanswered for `timeout` of unpaused time, as in an automated run
+    /// nobody watches: the request is denied and `run` fails with a
+    /// non-retryable [`TaskErrorKind::ApprovalTimeout`] error, which ends
+    /// the turn with `TurnAbortReason::ApprovalTimeout`. `None` waits as
+    /// long as the session's approval limits allow.
+    pub(crate) fn with_approval_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.approval_timeout = timeout;
+        self
+    }
+}
 
 #[async_trait]
 impl SessionTask for ReviewTask {
@@ -26,12 +75,49 @@ impl SessionTask for ReviewTask {
         ctx: Arc<TurnContext>,
         sub_id: String,
         input: Vec<InputItem>,
-    ) -> Option<String> {
+    ) -> TaskResult {
         let sess = session.clone_session();
-        run_task(sess, ctx, sub_id, input).await
+        // Aborted by dropping it, which leaves review mode in `abort`.
+        let cancellation = TaskCancellation::default();
+        let review = run_task(Arc::clone(&sess), ctx, sub_id.clone(), input, &cancellation);
+        let Some(timeout) = self.approval_timeout else {
+            return review.await;
+        };
+        tokio::select! {
+            result = review => result,
+            () = self.approval_expired.notified() => {
+                // The review stopped mid-turn, so leave review mode the way
+                // an abort does.
+                exit_review_mode(sess, sub_id, None).await;
+                TaskResult::Failed(TaskError {
+                    kind: TaskErrorKind::ApprovalTimeout,
+                    retryable: TaskErrorKind::ApprovalTimeout.is_retryable(),
+                    message: format!(
+                        "review rejected: an approval request went unanswered for {}s",
+                        timeout.as_secs()
+                    ),
+                })
+            }
+        }
+    }
+
+    fn runs_turn_loop(&self) -> bool {
+        true
     }
 
     async fn abort(&self, session: Arc<SessionTaskContext>, sub_id: &str) {
         exit_review_mode(session.clone_session(), sub_id.to_string(), None).await;
     }
+
+    fn review_slice(&self) -> Option<&ReviewSlice> {
+        self.slice.as_ref()
+    }
+
+    fn approval_timeout(&self) -> Option<Duration> {
+        self.approval_timeout
+    }
+
+    fn approval_timed_out(&self) {
+        self.approval_expired.notify_one();
+    }
 }
@@ -0,0 +1,189 @@
+//! Instructions templates kept in the workspace, one per [`TaskKind`], that
+//! are appended to the instructions of every task of that kind.
+//!
+//! Templates are read when a task spawns and cached until the file's
+//! modification time changes. A missing file means the kind has no
+//! template; an unreadable one is logged once and otherwise treated the
+//! same way.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha1::Digest;
+use sha1::Sha1;
+use tracing::warn;
+
+use crate::config_types::TaskTemplatePaths;
+use crate::state::TaskKind;
+
+/// A template as applied to a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TaskTemplate {
+    pub(crate) path: PathBuf,
+    /// SHA-1 of the file contents.
+    pub(crate) hash: String,
+    #[serde(skip)]
+    pub(crate) text: String,
+}
+
+struct CachedTemplate {
+    modified: SystemTime,
+    template: Arc<TaskTemplate>,
+}
+
+#[derive(Default)]
+pub(crate) struct TaskTemplates {
+    paths: TaskTemplatePaths,
+    cache: Mutex<HashMap<PathBuf, CachedTemplate>>,
+    /// Unreadable templates already logged, until they can be read again.
+    warned: Mutex<HashSet<PathBuf>>,
+}
+
+impl TaskTemplates {
+    pub(crate) fn new(paths: TaskTemplatePaths) -> Self {
+        Self {
+            paths,
+            ..Default::default()
+        }
+    }
+
+    /// The template for tasks of `kind` running in `cwd`, if its file exists
+    /// and can be read.
+    pub(crate) fn load(&self, kind: TaskKind, cwd: &Path) -> Option<Arc<TaskTemplate>> {
+        let path = cwd.join(match kind {
+            TaskKind::Regular => &self.paths.regular,
+            TaskKind::Review => &self.paths.review,
+            TaskKind::Compact => &self.paths.compact,
+            // Reports send the model no prompts.
+            TaskKind::Report => return None,
+        });
+        match self.read_cached(&path) {
+            Ok(template) => {
+                if let Ok(mut warned) = self.warned.lock() {
+                    warned.remove(&path);
+                }
+                template
+            }
+            Err(err) => {
+                let first = self
+                    .warned
+                    .lock()
+                    .is_ok_and(|mut warned| warned.insert(path.clone()));
+                if first {
+                    warn!(
+                        "ignoring unreadable task template {}: {err}",
+                        path.display()
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    fn read_cached(&self, path: &Path) -> io::Result<Option<Arc<TaskTemplate>>> {
+        let modified = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if let Ok(mut cache) = self.cache.lock() {
+                    cache.remove(path);
+                }
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(modified) = modified
+            && let Ok(cache) = self.cache.lock()
+            && let Some(cached) = cache.get(path)
+            && cached.modified == modified
+        {
+            return Ok(Some(Arc::clone(&cached.template)));
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        let template = Arc::new(TaskTemplate {
+            path: path.to_path_buf(),
+            hash: format!("{:x}", Sha1::digest(text.as_bytes())),
+            text,
+        });
+        // Without a modification time there is nothing to invalidate on, so
+        // the file is read afresh every time.
+        if let Some(modified) = modified
+            && let Ok(mut cache) = self.cache.lock()
+        {
+            cache.insert(
+                path.to_path_buf(),
+                CachedTemplate {
+                    modified,
+                    template: Arc::clone(&template),
+                },
+            );
+        }
+        Ok(Some(template))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn text(templates: &TaskTemplates, kind: TaskKind, cwd: &Path) -> Option<String> {
+        templates
+            .load(kind, cwd)
+            .map(|template| template.text.clone())
+    }
+
+    #[test]
+    fn reloads_a_template_once_its_modification_time_changes() -> io::Result<()> {
+        let cwd = TempDir::new()?;
+        let templates = TaskTemplates::new(TaskTemplatePaths::default());
+        assert_eq!(text(&templates, TaskKind::Review, cwd.path()), None);
+
+        let path = cwd.path().join(TaskTemplatePaths::default().review);
+        std::fs::create_dir_all(path.parent().unwrap_or(cwd.path()))?;
+        std::fs::write(&path, "first")?;
+        let modified = std::fs::metadata(&path)?.modified()?;
+        assert_eq!(
+            text(&templates, TaskKind::Review, cwd.path()),
+            Some("first".to_string())
+        );
+
+        // Same modification time: the cached contents are kept.
+        std::fs::write(&path, "second")?;
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        assert_eq!(
+            text(&templates, TaskKind::Review, cwd.path()),
+            Some("first".to_string())
+        );
+
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified + Duration::from_secs(1))?;
+        assert_eq!(
+            text(&templates, TaskKind::Review, cwd.path()),
+            Some("second".to_string())
+        );
+        assert_eq!(text(&templates, TaskKind::Compact, cwd.path()), None);
+
+        std::fs::remove_file(&path)?;
+        assert_eq!(text(&templates, TaskKind::Review, cwd.path()), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+//! Per-task timing bookkeeping used to enrich lifecycle telemetry.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Reasoning output is not tokenized locally; approximate it the same way
+/// `truncate_middle` does (4 bytes/token).
+const APPROX_BYTES_PER_TOKEN: u64 = 4;
+
+/// A completed "thinking" phase: the time between the start of a model
+/// response stream and its first non-reasoning output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ReasoningPhase {
+    pub(crate) duration: Duration,
+    pub(crate) token_estimate: u64,
+}
+
+/// Tracks the reasoning phase of a single model response stream.
+#[derive(Debug)]
+pub(crate) struct ReasoningPhaseTracker {
+    stream_started_at: Instant,
+    reasoning_bytes: u64,
+    finished: bool,
+}
+
+impl ReasoningPhaseTracker {
+    pub(crate) fn new(stream_started_at: Instant) -> Self {
+        Self {
+            stream_started_at,
+            reasoning_bytes: 0,
+            finished: false,
+        }
+    }
+
+    /// Account for a reasoning delta (summary or raw content).
+    pub(crate) fn record_reasoning(&mut self, delta: &str) {
+        if !self.finished {
+            self.reasoning_bytes += delta.len() as u64;
+        }
+    }
+
+    /// Close the phase when the first non-reasoning output arrives. Returns
+    /// the phase exactly once, and only if any reasoning was observed, so
+    /// models without visible reasoning never produce one.
+    pub(crate) fn finish(&mut self, now: Instant) -> Option<ReasoningPhase> {
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        if self.reasoning_bytes == 0 {
+            return None;
+        }
+        Some(ReasoningPhase {
+            duration: now.saturating_duration_since(self.stream_started_at),
+            token_estimate: self.reasoning_bytes.div_ceil(APPROX_BYTES_PER_TOKEN),
+        })
+    }
+}
+
+/// Upper bounds, in milliseconds, of the first-response latency buckets;
+/// slower responses land in a final, unbounded bucket.
+const FIRST_RESPONSE_BUCKET_BOUNDS_MS: [u64; 6] = [250, 500, 1_000, 2_000, 5_000, 10_000];
+
+/// Timing data accumulated over the lifetime of a task.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct TaskTimings {
+    /// `None` for tasks that do not measure their first response.
+    spawned_at: Option<Instant>,
+    first_response: Option<Duration>,
+    reasoning_duration: Option<Duration>,
+    reasoning_token_estimate: Option<u64>,
+}
+
+impl TaskTimings {
+    /// Record the task's first assistant output (text or a tool call) at
+    /// `now`. Returns the latency from spawn exactly once.
+    pub(crate) fn record_first_response(&mut self, now: Instant) -> Option<Duration> {
+        let spawned_at = self.spawned_at?;
+        if self.first_response.is_some() {
+            return None;
+        }
+        let latency = now.saturating_duration_since(spawned_at);
+        self.first_response = Some(latency);
+        Some(latency)
+    }
+
+    pub(crate) fn first_response(&self) -> Option<Duration> {
+        self.first_response
+    }
+
+    /// Time since the task was spawned.
+    pub(crate) fn elapsed(&self, now: Instant) -> Option<Duration> {
+        self.spawned_at
+            .map(|spawned_at| now.saturating_duration_since(spawned_at))
+    }
+
+    pub(crate) fn record_reasoning_phase(&mut self, phase: ReasoningPhase) {
+        self.reasoning_duration =
+            Some(self.reasoning_duration.unwrap_or_default() + phase.duration);
+        self.reasoning_token_estimate =
+            Some(self.reasoning_token_estimate.unwrap_or_default() + phase.token_estimate);
+    }
+
+    /// Latency buckets reported alongside `task_completed`.
+    pub(crate) fn latency_breakdown(&self) -> LatencyBreakdown {
+        LatencyBreakdown {
+            reasoning_ms: self.reasoning_duration.map(|d| d.as_millis() as u64),
+            reasoning_token_estimate: self.reasoning_token_estimate,
+        }
+    }
+}
+
+/// Cheaply cloneable handle to a task's [`TaskTimings`], shared between the
+/// registered `RunningTask` and the code paths that observe the task.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SharedTaskTimings(Arc<Mutex<TaskTimings>>);
+
+impl SharedTaskTimings {
+    /// Timings of a task spawned at `spawned_at`, which measure its first
+    /// response.
+    pub(crate) fn spawned_at(spawned_at: Instant) -> Self {
+        Self(Arc::new(Mutex::new(TaskTimings {
+            spawned_at: Some(spawned_at),
+            ..TaskTimings::default()
+        })))
+    }
+
+    pub(crate) fn record_first_response(&self, now: Instant) -> Option<Duration> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|mut timings| timings.record_first_response(now))
+    }
+
+    pub(crate) fn first_response(&self) -> Option<Duration> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|timings| timings.first_response())
+    }
+
+    pub(crate) fn elapsed(&self, now: Instant) -> Option<Duration> {
+        self.0.lock().ok().and_then(|timings| timings.elapsed(now))
+    }
+
+    pub(crate) fn record_reasoning_phase(&self, phase: ReasoningPhase) {
+        if let Ok(mut timings) = self.0.lock() {
+            timings.record_reasoning_phase(phase);
+        }
+    }
+
+    pub(crate) fn latency_breakdown(&self) -> LatencyBreakdown {
+        match self.0.lock() {
+            Ok(timings) => timings.latency_breakdown(),
+            Err(_) => LatencyBreakdown::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LatencyBreakdown {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reasoning_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reasoning_token_estimate: Option<u64>,
+}
+
+/// Session-wide distribution of first-response latencies, reported with
+/// `conversation_ended`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FirstResponseHistogram {
+    buckets: Vec<LatencyBucket>,
+    /// Tasks aborted before any response, which have no latency.
+    aborted_before_response: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LatencyBucket {
+    /// Inclusive upper bound; `None` for the last bucket.
+    le_ms: Option<u64>,
+    count: u64,
+}
+
+impl Default for FirstResponseHistogram {
+    fn default() -> Self {
+        let bounds = FIRST_RESPONSE_BUCKET_BOUNDS_MS.iter().copied().map(Some);
+        Self {
+            buckets: bounds
+                .chain([None])
+                .map(|le_ms| LatencyBucket { le_ms, count: 0 })
+                .collect(),
+            aborted_before_response: 0,
+        }
+    }
+}
+
+impl FirstResponseHistogram {
+    /// Account for a finished task that first responded after `latency`.
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        if let Some(bucket) = self
+            .buckets
+            .iter_mut()
+            .find(|bucket| bucket.le_ms.is_none_or(|le_ms| latency_ms <= le_ms))
+        {
+            bucket.count += 1;
+        }
+    }
+
+    pub(crate) fn record_aborted_before_response(&mut self) {
+        self.aborted_before_response += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reasoning_then_text_produces_single_phase() {
+        let start = Instant::now();
+        let mut tracker = ReasoningPhaseTracker::new(start);
+        tracker.record_reasoning("thinking about");
+        tracker.record_reasoning(" the problem");
+
+        let phase = tracker.finish(start + Duration::from_millis(750));
+        assert_eq!(
+            phase,
+            Some(ReasoningPhase {
+                duration: Duration::from_millis(750),
+                token_estimate: 7,
+            })
+        );
+
+        // Later text deltas or reasoning must not reopen the phase.
+        tracker.record_reasoning("more");
+        assert_eq!(tracker.finish(start + Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn stream_without_reasoning_omits_phase() {
+        let start = Instant::now();
+        let mut tracker = ReasoningPhaseTracker::new(start);
+        assert_eq!(tracker.finish(start + Duration::from_millis(10)), None);
+
+        let timings = TaskTimings::default();
+        assert_eq!(
+            serde_json::to_value(timings.latency_breakdown()).expect("serialize"),
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn first_response_is_measured_once_from_spawn() {
+        let spawned_at = Instant::now();
+        let timings = SharedTaskTimings::spawned_at(spawned_at);
+        let first = timings.record_first_response(spawned_at + Duration::from_millis(420));
+        let second = timings.record_first_response(spawned_at + Duration::from_secs(3));
+        assert_eq!(
+            (first, second, timings.first_response()),
+            (
+                Some(Duration::from_millis(420)),
+                None,
+                Some(Duration::from_millis(420))
+            )
+        );
+
+        let unmeasured = SharedTaskTimings::default();
+        assert_eq!(unmeasured.record_first_response(spawned_at), None);
+    }
+
+    #[test]
+    fn histogram_buckets_latencies_and_counts_early_aborts() {
+        let mut histogram = FirstResponseHistogram::default();
+        histogram.record(Duration::from_millis(250));
+        histogram.record(Duration::from_millis(1_200));
+        histogram.record(Duration::from_secs(60));
+        histogram.record_aborted_before_response();
+        assert_eq!(
+            serde_json::to_value(&histogram).expect("serialize"),
+            serde_json::json!({
+                "buckets": [
+                    { "leMs": 250, "count": 1 },
+                    { "leMs": 500, "count": 0 },
+                    { "leMs": 1000, "count": 0 },
+                    { "leMs": 2000, "count": 1 },
+                    { "leMs": 5000, "count": 0 },
+                    { "leMs": 10000, "count": 0 },
+                    { "leMs": null, "count": 1 },
+                ],
+                "abortedBeforeResponse": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn task_timings_sum_phases_across_streams() {
+        let mut timings = TaskTimings::default();
+        timings.record_reasoning_phase(ReasoningPhase {
+            duration: Duration::from_millis(300),
+            token_estimate: 10,
+        });
+        timings.record_reasoning_phase(ReasoningPhase {
+            duration: Duration::from_millis(200),
+            token_estimate: 5,
+        });
+        assert_eq!(
+            timings.latency_breakdown(),
+            LatencyBreakdown {
+                reasoning_ms: Some(500),
+                reasoning_token_estimate: Some(15),
+            }
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Spawn-time checks that run before a task is handed to tokio.
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde_json::Value;
+use serde_json::json;
+use thiserror::Error;
+
+/// Why `spawn_task` refused to start a task.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub(crate) enum SpawnRejectReason {
+    #[error("working directory {} is unusable: {problem}", path.display())]
+    InvalidCwd { path: PathBuf, problem: CwdProblem },
+    #[error("the session is shutting down")]
+    ShuttingDown,
+    #[error("a background task with this id is already running")]
+    DuplicateSubId,
+}
+
+impl SpawnRejectReason {
+    /// Payload attached to the `task_spawn_rejected` visualizer event.
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            SpawnRejectReason::InvalidCwd { path, problem } => json!({
+                "type": "invalidCwd",
+                "path": path.display().to_string(),
+                "problem": problem.to_string(),
+            }),
+            SpawnRejectReason::ShuttingDown => json!({ "type": "shuttingDown" }),
+            SpawnRejectReason::DuplicateSubId => json!({ "type": "duplicateSubId" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub(crate) enum CwdProblem {
+    #[error("does not exist")]
+    Missing,
+    #[error("is not a directory")]
+    NotADirectory,
+    #[error("is not readable: {0}")]
+    Unreadable(String),
+    #[error("is not writable")]
+    NotWritable,
+}
+
+/// Check that `cwd` exists, is a directory, and can be listed. When
+/// `require_writable` is set the directory must also accept writes from the
+/// current user.
+pub(crate) fn validate_cwd(cwd: &Path, require_writable: bool) -> Result<(), SpawnRejectReason> {
+    check_cwd(cwd, require_writable).map_err(|problem| SpawnRejectReason::InvalidCwd {
+        path: cwd.to_path_buf(),
+        problem,
+    })
+}
+
+fn check_cwd(cwd: &Path, require_writable: bool) -> Result<(), CwdProblem> {
+    let metadata = match std::fs::metadata(cwd) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(CwdProblem::Missing),
+        Err(err) => return Err(CwdProblem::Unreadable(err.to_string())),
+    };
+    if !metadata.is_dir() {
+        return Err(CwdProblem::NotADirectory);
+    }
+    std::fs::read_dir(cwd).map_err(|err| CwdProblem::Unreadable(err.to_string()))?;
+    if require_writable && !is_writable(cwd) {
+        return Err(CwdProblem::NotWritable);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `c_path` is a valid NUL-terminated string for the duration of
+    // the call.
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_writable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|metadata| !metadata.permissions().readonly())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn accepts_existing_directory() {
+        let tmp = TempDir::new().expect("tempdir");
+        assert_eq!(validate_cwd(tmp.path(), true), Ok(()));
+    }
+
+    #[test]
+    fn rejects_missing_directory() {
+        let tmp = TempDir::new().expect("tempdir");
+        let missing = tmp.path().join("deleted");
+        assert_eq!(
+            validate_cwd(&missing, false),
+            Err(SpawnRejectReason::InvalidCwd {
+                path: missing.clone(),
+                problem: CwdProblem::Missing,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_file_as_cwd() {
+        let tmp = TempDir::new().expect("tempdir");
+        let file = tmp.path().join("file.txt");
+        std::fs::write(&file, "not a dir").expect("write file");
+        assert_eq!(
+            validate_cwd(&file, false),
+            Err(SpawnRejectReason::InvalidCwd {
+                path: file.clone(),
+                problem: CwdProblem::NotADirectory,
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_unreadable_and_unwritable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Root bypasses permission bits, so the check cannot fail there.
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let tmp = TempDir::new().expect("tempdir");
+        let locked = tmp.path().join("locked");
+        std::fs::create_dir(&locked).expect("create dir");
+        std::fs::write(locked.join("entry"), "x").expect("write entry");
+
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000))
+            .expect("chmod 000");
+        let unreadable = check_cwd(&locked, false);
+
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o500))
+            .expect("chmod 500");
+        let unwritable = check_cwd(&locked, true);
+        let read_only_ok = check_cwd(&locked, false);
+
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o700))
+            .expect("restore permissions");
+
+        assert!(matches!(unreadable, Err(CwdProblem::Unreadable(_))));
+        assert_eq!(unwritable, Err(CwdProblem::NotWritable));
+        assert_eq!(read_only_ok, Ok(()));
+    }
+
+    #[test]
+    fn reject_reason_serializes_for_visualizer() {
+        let reason = SpawnRejectReason::InvalidCwd {
+            path: PathBuf::from("/gone"),
+            problem: CwdProblem::Missing,
+        };
+        assert_eq!(
+            reason.to_json(),
+            json!({
+                "type": "invalidCwd",
+                "path": "/gone",
+                "problem": "does not exist",
+            })
+        );
+    }
+}
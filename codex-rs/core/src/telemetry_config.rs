@@ -0,0 +1,33 @@
+//! `Op::UpdateTelemetryConfig`: replace the visualizer's filtering,
+//! sampling, redaction and truncation rules mid-session. The rules
+//! themselves live in `visualizer::rules`.
+
+use serde_json::json;
+
+use crate::codex::Session;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::TelemetryConfigPatch;
+use crate::protocol::TelemetryConfigRejectedEvent;
+
+impl Session {
+    /// Handle `Op::UpdateTelemetryConfig` submitted as `op_id`.
+    pub(crate) async fn update_telemetry_config(&self, op_id: String, patch: TelemetryConfigPatch) {
+        match self.visualizer.update_rules(patch) {
+            Ok(changed_keys) => {
+                self.emit_with_state(
+                    "telemetry_config_updated",
+                    json!({ "changedKeys": changed_keys }),
+                )
+                .await;
+            }
+            Err(errors) => {
+                self.send_event(Event {
+                    id: op_id,
+                    msg: EventMsg::TelemetryConfigRejected(TelemetryConfigRejectedEvent { errors }),
+                })
+                .await;
+            }
+        }
+    }
+}
@@ -9,11 +9,14 @@ use crate::tools::context::ToolPayload;
 use crate::tools::registry::ToolHandler;
 use crate::tools::registry::ToolKind;
 use async_trait::async_trait;
+use codex_protocol::plan_tool::StepStatus;
 use codex_protocol::plan_tool::UpdatePlanArgs;
 use codex_protocol::protocol::Event;
 use codex_protocol::protocol::EventMsg;
+use serde_json::json;
 use std::collections::BTreeMap;
 use std::sync::LazyLock;
+use tracing::warn;
 
 pub struct PlanHandler;
 
@@ -69,8 +72,8 @@ impl ToolHandler for PlanHandler {
         let ToolInvocation {
             session,
             sub_id,
-            call_id,
             payload,
+            telemetry,
             ..
         } = invocation;
 
@@ -83,8 +86,23 @@ impl ToolHandler for PlanHandler {
             }
         };
 
-        let content =
-            handle_update_plan(session.as_ref(), arguments, sub_id.clone(), call_id).await?;
+        let args = parse_update_plan_arguments(&arguments)?;
+        let completed = args
+            .plan
+            .iter()
+            .filter(|item| matches!(item.status, StepStatus::Completed))
+            .count();
+        let total = args.plan.len();
+        let content = handle_update_plan(session.as_ref(), args, sub_id).await;
+        if let Err(err) = telemetry
+            .emit(
+                "plan.progress",
+                json!({ "completed": completed, "total": total }),
+            )
+            .await
+        {
+            warn!("failed to emit plan progress: {err}");
+        }
 
         Ok(ToolOutput::Function {
             content,
@@ -98,18 +116,16 @@ impl ToolHandler for PlanHandler {
 /// than forcing it to come up and document a plan (TBD how that affects performance).
 pub(crate) async fn handle_update_plan(
     session: &Session,
-    arguments: String,
+    args: UpdatePlanArgs,
     sub_id: String,
-    _call_id: String,
-) -> Result<String, FunctionCallError> {
-    let args = parse_update_plan_arguments(&arguments)?;
+) -> String {
     session
         .send_event(Event {
             id: sub_id.to_string(),
             msg: EventMsg::PlanUpdate(args),
         })
         .await;
-    Ok("Plan updated".to_string())
+    "Plan updated".to_string()
 }
 
 fn parse_update_plan_arguments(arguments: &str) -> Result<UpdatePlanArgs, FunctionCallError> {
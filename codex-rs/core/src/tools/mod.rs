@@ -4,6 +4,7 @@ pub mod parallel;
 pub mod registry;
 pub mod router;
 pub mod spec;
+pub mod telemetry;
 
 use crate::apply_patch;
 use crate::apply_patch::ApplyPatchExec;
@@ -12,6 +12,7 @@ use crate::tools::registry::ConfiguredToolSpec;
 use crate::tools::registry::ToolRegistry;
 use crate::tools::spec::ToolsConfig;
 use crate::tools::spec::build_specs;
+use crate::tools::telemetry::TelemetryScope;
 use codex_protocol::models::LocalShellAction;
 use codex_protocol::models::ResponseInputItem;
 use codex_protocol::models::ResponseItem;
@@ -145,6 +146,12 @@ impl ToolRouter {
         let payload_outputs_custom = matches!(payload, ToolPayload::Custom { .. });
         let failure_call_id = call_id.clone();
 
+        let telemetry = TelemetryScope::new(
+            Arc::clone(&session),
+            sub_id.clone(),
+            call_id.clone(),
+            tool_name.clone(),
+        );
         let invocation = ToolInvocation {
             session,
             turn,
@@ -153,6 +160,7 @@ impl ToolRouter {
             call_id,
             tool_name,
             payload,
+            telemetry,
         };
 
         match self.registry.dispatch(invocation).await {
@@ -0,0 +1,139 @@
+//! Custom visualizer events emitted by tool implementations.
+//!
+//! Each tool invocation gets a [`TelemetryScope`] through which it can add
+//! its own events to the timeline ("migration step 3/7 applied") without
+//! reaching into the session. An event emitted as `migration.step_applied`
+//! is sent as `custom.migration.step_applied`, with the owning task and tool
+//! call stamped on it, under the same redaction, truncation and filtering
+//! rules as every other visualizer event. The part before the first `.` is
+//! the event's namespace: the task's `task_completed` event counts the
+//! custom events it emitted per namespace, as `customEvents`. Payloads over
+//! [`MAX_PAYLOAD_BYTES`] of JSON are rejected rather than truncated.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::codex::Session;
+
+const CUSTOM_PREFIX: &str = "custom.";
+
+/// Largest serialized payload a custom event may carry.
+pub const MAX_PAYLOAD_BYTES: usize = 16 * 1024;
+
+/// Prefixes of the session's own action types, which custom events may not
+/// imitate.
+const RESERVED_PREFIXES: &[&str] = &[
+    "approval_",
+    "background_task_",
+    "custom.",
+    "instrumentation_",
+    "protocol_",
+    "task_",
+    "telemetry_",
+    "tool_call_",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CustomEventError {
+    #[error("custom event type is empty")]
+    Empty,
+    #[error("custom event type `{0}` may only contain ASCII letters, digits, `_`, `-` and `.`")]
+    InvalidCharacter(String),
+    #[error("custom event type `{action_type}` uses the reserved prefix `{prefix}`")]
+    Reserved {
+        action_type: String,
+        prefix: &'static str,
+    },
+    #[error(
+        "custom event `{action_type}` has a {bytes}-byte payload, over the {max_bytes}-byte limit"
+    )]
+    PayloadTooLarge {
+        action_type: String,
+        bytes: usize,
+        max_bytes: usize,
+    },
+}
+
+/// Emits custom visualizer events on behalf of one tool call.
+#[derive(Clone)]
+pub struct TelemetryScope {
+    session: Arc<Session>,
+    sub_id: String,
+    call_id: String,
+    tool_name: String,
+}
+
+impl TelemetryScope {
+    pub(crate) fn new(
+        session: Arc<Session>,
+        sub_id: String,
+        call_id: String,
+        tool_name: String,
+    ) -> Self {
+        Self {
+            session,
+            sub_id,
+            call_id,
+            tool_name,
+        }
+    }
+
+    /// Emit `payload` as a `custom.<action_type>` event of this tool call.
+    pub async fn emit(&self, action_type: &str, payload: Value) -> Result<(), CustomEventError> {
+        let namespace = namespace(action_type)?;
+        let bytes = payload.to_string().len();
+        if bytes > MAX_PAYLOAD_BYTES {
+            return Err(CustomEventError::PayloadTooLarge {
+                action_type: action_type.to_string(),
+                bytes,
+                max_bytes: MAX_PAYLOAD_BYTES,
+            });
+        }
+        if let Some(at) = self.session.active_turn.lock().await.as_mut()
+            && let Some(task) = at.tasks.get_mut(&self.sub_id)
+        {
+            *task.custom_events.entry(namespace.to_string()).or_default() += 1;
+        }
+        self.session
+            .emit_with_state(
+                &format!("{CUSTOM_PREFIX}{action_type}"),
+                json!({
+                    "subId": self.sub_id,
+                    "callId": self.call_id,
+                    "tool": self.tool_name,
+                    "payload": payload,
+                }),
+            )
+            .await;
+        Ok(())
+    }
+}
+
+/// The namespace of a custom `action_type`, once it is known to be valid.
+fn namespace(action_type: &str) -> Result<&str, CustomEventError> {
+    if action_type.is_empty() {
+        return Err(CustomEventError::Empty);
+    }
+    if !action_type
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(CustomEventError::InvalidCharacter(action_type.to_string()));
+    }
+    if let Some(prefix) = RESERVED_PREFIXES
+        .iter()
+        .copied()
+        .find(|prefix| action_type.starts_with(prefix))
+    {
+        return Err(CustomEventError::Reserved {
+            action_type: action_type.to_string(),
+            prefix,
+        });
+    }
+    Ok(action_type
+        .split_once('.')
+        .map_or(action_type, |(namespace, _)| namespace))
+}
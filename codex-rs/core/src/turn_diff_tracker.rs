@@ -22,6 +22,16 @@ struct BaselineFileInfo {
     oid: String,
 }
 
+/// A file changed during the turn, with what it held before the first change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchedFile {
+    pub original_path: PathBuf,
+    /// `None` if the file did not exist before the turn.
+    pub original_content: Option<Vec<u8>>,
+    /// Where the file lives now, after any moves.
+    pub current_path: PathBuf,
+}
+
 /// Tracks sets of changes to files and exposes the overall unified diff.
 /// Internally, the way this works is now:
 /// 1. Maintain an in-memory baseline snapshot of files when they are first seen.
@@ -127,6 +137,25 @@ impl TurnDiffTracker {
         }
     }
 
+    /// Every file seen by [`Self::on_patch_begin`], ordered by original path.
+    pub fn touched_files(&self) -> Vec<TouchedFile> {
+        let mut touched: Vec<TouchedFile> = self
+            .baseline_file_info
+            .iter()
+            .map(|(internal, info)| TouchedFile {
+                original_path: info.path.clone(),
+                original_content: (info.oid != ZERO_OID).then(|| info.content.clone()),
+                current_path: self
+                    .temp_name_to_current_path
+                    .get(internal)
+                    .cloned()
+                    .unwrap_or_else(|| info.path.clone()),
+            })
+            .collect();
+        touched.sort_by(|a, b| a.original_path.cmp(&b.original_path));
+        touched
+    }
+
     fn get_path_for_internal(&self, internal: &str) -> Option<PathBuf> {
         self.temp_name_to_current_path
             .get(internal)
@@ -0,0 +1,637 @@
+//! Reusable prompts with `{param}` placeholders, kept in the workspace and
+//! spawned as tasks with `Op::SpawnTemplate`.
+//!
+//! Each `<name>.toml` or `<name>.md` in the templates directory
+//! (`turn_templates_dir`, relative to the session's cwd) defines the
+//! template `<name>`:
+//!
+//! ```toml
+//! prompt = "Add a changelog entry for {change} under {section}."
+//!
+//! [params.change]
+//!
+//! [params.section]
+//! default = "Unreleased"
+//! ```
+//!
+//! A markdown template is its prompt, after an optional TOML front matter
+//! block between `+++` lines that declares the params:
+//!
+//! ```markdown
+//! +++
+//! [params.change]
+//! +++
+//! Add a changelog entry for {change}.
+//! ```
+//!
+//! A param with a `default` is optional; the others are required. Every
+//! placeholder must name a declared param, and `{{` and `}}` stand for
+//! literal braces. Templates are read each time one is spawned, so an edit
+//! applies to the next spawn.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::codex::Session;
+use crate::codex::TurnContext;
+use crate::protocol::ClientContext;
+use crate::protocol::ErrorEvent;
+use crate::protocol::Event;
+use crate::protocol::EventMsg;
+use crate::protocol::InputItem;
+use crate::protocol::TurnTemplateUse;
+use crate::tasks::RegularTask;
+
+pub(crate) const DEFAULT_TURN_TEMPLATES_DIR: &str = ".codex/turn-templates";
+
+/// How a template file is written, by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateFormat {
+    Toml,
+    Markdown,
+}
+
+impl TemplateFormat {
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("toml") {
+            Some(Self::Toml)
+        } else if extension.eq_ignore_ascii_case("md") {
+            Some(Self::Markdown)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub(crate) enum TemplateError {
+    #[error("no turn template named `{name}`; available: {}", list(available))]
+    Unknown {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error("turn template `{name}` is invalid: {problem}")]
+    Invalid { name: String, problem: String },
+    #[error(
+        "turn template `{name}` is missing {}; it requires {}",
+        list(missing),
+        list(required)
+    )]
+    MissingParams {
+        name: String,
+        missing: Vec<String>,
+        required: Vec<String>,
+    },
+    #[error(
+        "turn template `{name}` has no params {}; it accepts {}",
+        list(unknown),
+        list(accepted)
+    )]
+    UnknownParams {
+        name: String,
+        unknown: Vec<String>,
+        accepted: Vec<String>,
+    },
+}
+
+impl TemplateError {
+    fn invalid(name: &str, problem: String) -> Self {
+        TemplateError::Invalid {
+            name: name.to_string(),
+            problem,
+        }
+    }
+
+    /// Payload attached to the `template_rejected` visualizer event.
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            TemplateError::Unknown { available, .. } => json!({
+                "type": "unknown",
+                "available": available,
+            }),
+            TemplateError::Invalid { problem, .. } => json!({
+                "type": "invalid",
+                "problem": problem,
+            }),
+            TemplateError::MissingParams {
+                missing, required, ..
+            } => json!({
+                "type": "missingParams",
+                "missing": missing,
+                "required": required,
+            }),
+            TemplateError::UnknownParams {
+                unknown, accepted, ..
+            } => json!({
+                "type": "unknownParams",
+                "unknown": unknown,
+                "accepted": accepted,
+            }),
+        }
+    }
+}
+
+fn list(names: &[String]) -> String {
+    if names.is_empty() {
+        return "none".to_string();
+    }
+    names
+        .iter()
+        .map(|name| format!("`{name}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    prompt: String,
+    #[serde(default)]
+    params: BTreeMap<String, ParamFile>,
+}
+
+/// The front matter of a markdown template.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    #[serde(default)]
+    params: BTreeMap<String, ParamFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParamFile {
+    default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Param(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TurnTemplate {
+    name: String,
+    segments: Vec<Segment>,
+    /// Declared params and their defaults; `None` is required.
+    params: BTreeMap<String, Option<String>>,
+}
+
+impl TurnTemplate {
+    fn parse(name: &str, contents: &str) -> Result<Self, TemplateError> {
+        let file: TemplateFile = toml::from_str(contents)
+            .map_err(|err| TemplateError::invalid(name, err.to_string()))?;
+        Self::new(name, &file.prompt, file.params)
+    }
+
+    fn parse_markdown(name: &str, contents: &str) -> Result<Self, TemplateError> {
+        let mut lines = contents.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some("+++") {
+            return Self::new(name, contents.trim(), BTreeMap::new());
+        }
+        let mut front_matter = String::new();
+        loop {
+            match lines.next() {
+                Some(line) if line.trim_end() == "+++" => break,
+                Some(line) => front_matter.push_str(line),
+                None => {
+                    return Err(TemplateError::invalid(
+                        name,
+                        "front matter has no closing `+++`".to_string(),
+                    ));
+                }
+            }
+        }
+        let front_matter: FrontMatter = toml::from_str(&front_matter)
+            .map_err(|err| TemplateError::invalid(name, err.to_string()))?;
+        let prompt: String = lines.collect();
+        Self::new(name, prompt.trim(), front_matter.params)
+    }
+
+    fn new(
+        name: &str,
+        prompt: &str,
+        params: BTreeMap<String, ParamFile>,
+    ) -> Result<Self, TemplateError> {
+        let params: BTreeMap<_, _> = params
+            .into_iter()
+            .map(|(param, spec)| (param, spec.default))
+            .collect();
+        let segments = parse_prompt(prompt, &params)
+            .map_err(|problem| TemplateError::invalid(name, problem))?;
+        Ok(Self {
+            name: name.to_string(),
+            segments,
+            params,
+        })
+    }
+
+    /// The prompt with `params` substituted, and every param it was
+    /// rendered with, defaults included.
+    pub(crate) fn render(
+        &self,
+        params: &BTreeMap<String, String>,
+    ) -> Result<(String, TurnTemplateUse), TemplateError> {
+        let unknown: Vec<_> = params
+            .keys()
+            .filter(|param| !self.params.contains_key(*param))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(TemplateError::UnknownParams {
+                name: self.name.clone(),
+                unknown,
+                accepted: self.params.keys().cloned().collect(),
+            });
+        }
+        let required = self
+            .params
+            .iter()
+            .filter(|(_, default)| default.is_none())
+            .map(|(param, _)| param.clone());
+        let missing: Vec<_> = required
+            .clone()
+            .filter(|param| !params.contains_key(param))
+            .collect();
+        if !missing.is_empty() {
+            return Err(TemplateError::MissingParams {
+                name: self.name.clone(),
+                missing,
+                required: required.collect(),
+            });
+        }
+
+        let resolved: BTreeMap<_, _> = self
+            .params
+            .iter()
+            .filter_map(|(param, default)| {
+                let value = params.get(param).or(default.as_ref())?;
+                Some((param.clone(), value.clone()))
+            })
+            .collect();
+        let text = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Param(param) => resolved.get(param).map_or("", String::as_str),
+            })
+            .collect();
+        Ok((
+            text,
+            TurnTemplateUse {
+                name: self.name.clone(),
+                params: resolved,
+            },
+        ))
+    }
+}
+
+/// Split `prompt` into text and placeholders, each of which must name one
+/// of `params`.
+fn parse_prompt(
+    prompt: &str,
+    params: &BTreeMap<String, Option<String>>,
+) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = prompt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+            '{' => {
+                let mut param = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => param.push(c),
+                        None => return Err("prompt has an unclosed `{`".to_string()),
+                    }
+                }
+                if !params.contains_key(&param) {
+                    return Err(format!(
+                        "prompt placeholder `{{{param}}}` is not a declared param"
+                    ));
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Param(param));
+            }
+            '}' => {
+                return Err(
+                    "prompt has an unmatched `}`; write `}}` for a literal brace".to_string(),
+                );
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+/// The template files in `dir` by template name. A missing or unreadable
+/// directory has none.
+async fn template_files(dir: &Path) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut files: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return files;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !entry
+            .file_type()
+            .await
+            .is_ok_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let path = entry.path();
+        if TemplateFormat::of(&path).is_none() {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            files.entry(name.to_string()).or_default().push(path);
+        }
+    }
+    files
+}
+
+/// Names of the templates in `dir`, sorted. A missing or unreadable
+/// directory has none.
+pub(crate) async fn discover_templates(dir: &Path) -> Vec<String> {
+    template_files(dir).await.into_keys().collect()
+}
+
+/// Read the template `name` from `dir`. Only discovered names are looked
+/// up, so `name` cannot reach outside the directory.
+pub(crate) async fn load_template(dir: &Path, name: &str) -> Result<TurnTemplate, TemplateError> {
+    let mut files = template_files(dir).await;
+    let Some(mut paths) = files.remove(name) else {
+        return Err(TemplateError::Unknown {
+            name: name.to_string(),
+            available: files.into_keys().collect(),
+        });
+    };
+    paths.sort();
+    let path = match <[PathBuf; 1]>::try_from(paths) {
+        Ok([path]) => path,
+        Err(paths) => {
+            let names: Vec<_> = paths
+                .iter()
+                .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                .collect();
+            return Err(TemplateError::invalid(
+                name,
+                format!("it is defined by more than one file: {}", list(&names)),
+            ));
+        }
+    };
+    let contents = tokio::fs::read_to_string(&path).await.map_err(|err| {
+        TemplateError::invalid(name, format!("failed to read {}: {err}", path.display()))
+    })?;
+    match TemplateFormat::of(&path) {
+        Some(TemplateFormat::Markdown) => TurnTemplate::parse_markdown(name, &contents),
+        Some(TemplateFormat::Toml) | None => TurnTemplate::parse(name, &contents),
+    }
+}
+
+impl Session {
+    /// Handle `Op::SpawnTemplate` submitted as `sub_id`.
+    pub(crate) async fn spawn_template(
+        self: &Arc<Self>,
+        turn_context: Arc<TurnContext>,
+        sub_id: String,
+        name: String,
+        params: BTreeMap<String, String>,
+        client_context: Option<ClientContext>,
+    ) {
+        let dir = turn_context.cwd.join(&self.services.turn_templates_dir);
+        let rendered = load_template(&dir, &name)
+            .await
+            .and_then(|template| template.render(&params));
+        let (text, template) = match rendered {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                self.send_event(Event {
+                    id: sub_id.clone(),
+                    msg: EventMsg::Error(ErrorEvent {
+                        message: err.to_string(),
+                    }),
+                })
+                .await;
+                self.emit_with_state(
+                    "template_rejected",
+                    json!({
+                        "subId": sub_id,
+                        "template": name,
+                        "reason": err.to_json(),
+                    }),
+                )
+                .await;
+                return;
+            }
+        };
+        self.spawn_task_from_template(
+            turn_context,
+            sub_id,
+            vec![InputItem::Text { text }],
+            RegularTask::default(),
+            template,
+            client_context,
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const CHANGELOG: &str = r#"
+prompt = "Add a changelog entry for {change} under {section}; keep {{braces}}."
+
+[params.change]
+
+[params.section]
+default = "Unreleased"
+"#;
+
+    fn params(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(param, value)| (param.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn changelog() -> TurnTemplate {
+        match TurnTemplate::parse("changelog", CHANGELOG) {
+            Ok(template) => template,
+            Err(err) => panic!("changelog template is valid: {err}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn templates_are_discovered_by_file_stem() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::write(dir.path().join("tests.toml"), CHANGELOG)?;
+        std::fs::write(dir.path().join("changelog.TOML"), CHANGELOG)?;
+        std::fs::write(dir.path().join("notes.txt"), "not a template")?;
+        std::fs::create_dir(dir.path().join("nested.toml"))?;
+
+        assert_eq!(
+            discover_templates(dir.path()).await,
+            vec!["changelog".to_string(), "tests".to_string()]
+        );
+        assert_eq!(
+            discover_templates(&dir.path().join("missing")).await,
+            Vec::<String>::new()
+        );
+        assert!(load_template(dir.path(), "changelog").await.is_ok());
+        assert_eq!(
+            load_template(dir.path(), "../tests").await,
+            Err(TemplateError::Unknown {
+                name: "../tests".to_string(),
+                available: vec!["changelog".to_string(), "tests".to_string()],
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn markdown_templates_take_params_from_their_front_matter() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::write(
+            dir.path().join("fix.md"),
+            "+++\n[params.bug]\n+++\n\nFix {bug}.\n",
+        )?;
+        std::fs::write(dir.path().join("plain.md"), "Tidy the imports.\n")?;
+
+        let render = |template: Result<TurnTemplate, TemplateError>, values: &[(&str, &str)]| {
+            template.and_then(|template| template.render(&params(values)))
+        };
+        assert_eq!(
+            render(load_template(dir.path(), "fix").await, &[("bug", "#12")]),
+            Ok((
+                "Fix #12.".to_string(),
+                TurnTemplateUse {
+                    name: "fix".to_string(),
+                    params: params(&[("bug", "#12")]),
+                },
+            ))
+        );
+        assert_eq!(
+            render(load_template(dir.path(), "plain").await, &[]),
+            Ok((
+                "Tidy the imports.".to_string(),
+                TurnTemplateUse {
+                    name: "plain".to_string(),
+                    params: BTreeMap::new(),
+                },
+            ))
+        );
+        assert!(matches!(
+            TurnTemplate::parse_markdown("open", "+++\n[params.bug]\nFix {bug}.\n"),
+            Err(TemplateError::Invalid { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn a_name_defined_twice_is_invalid() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::write(dir.path().join("fix.toml"), "prompt = \"Fix it.\"")?;
+        std::fs::write(dir.path().join("fix.md"), "Fix it.")?;
+
+        assert_eq!(
+            load_template(dir.path(), "fix").await,
+            Err(TemplateError::Invalid {
+                name: "fix".to_string(),
+                problem: "it is defined by more than one file: `fix.md`, `fix.toml`".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn optional_params_fall_back_to_their_default() {
+        let template = changelog();
+        assert_eq!(
+            template.render(&params(&[("change", "the retry fix")])),
+            Ok((
+                "Add a changelog entry for the retry fix under Unreleased; keep {braces}."
+                    .to_string(),
+                TurnTemplateUse {
+                    name: "changelog".to_string(),
+                    params: params(&[("change", "the retry fix"), ("section", "Unreleased")]),
+                },
+            ))
+        );
+        assert_eq!(
+            template.render(&params(&[("change", "{section}"), ("section", "1.2.0")])),
+            Ok((
+                "Add a changelog entry for {section} under 1.2.0; keep {braces}.".to_string(),
+                TurnTemplateUse {
+                    name: "changelog".to_string(),
+                    params: params(&[("change", "{section}"), ("section", "1.2.0")]),
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_and_undeclared_params_are_rejected() {
+        let template = changelog();
+        assert_eq!(
+            template.render(&params(&[("section", "1.2.0")])),
+            Err(TemplateError::MissingParams {
+                name: "changelog".to_string(),
+                missing: vec!["change".to_string()],
+                required: vec!["change".to_string()],
+            })
+        );
+        assert_eq!(
+            template.render(&params(&[("change", "x"), ("sectoin", "1.2.0")])),
+            Err(TemplateError::UnknownParams {
+                name: "changelog".to_string(),
+                unknown: vec!["sectoin".to_string()],
+                accepted: vec!["change".to_string(), "section".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_prompts_are_invalid() {
+        let invalid = |contents: &str| match TurnTemplate::parse("broken", contents) {
+            Err(TemplateError::Invalid { problem, .. }) => problem,
+            other => panic!("expected an invalid template, got {other:?}"),
+        };
+        assert_eq!(
+            [
+                invalid(r#"prompt = "Fix {bug}""#),
+                invalid(r#"prompt = "Fix {bug""#),
+                invalid(r#"prompt = "Fix }""#),
+            ],
+            [
+                "prompt placeholder `{bug}` is not a declared param".to_string(),
+                "prompt has an unclosed `{`".to_string(),
+                "prompt has an unmatched `}`; write `}}` for a literal brace".to_string(),
+            ]
+        );
+        assert!(invalid("prompt = \"x\"\n[params.bug]\ndefualt = \"y\"").contains("defualt"));
+    }
+}
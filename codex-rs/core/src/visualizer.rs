@@ -1,179 +1,1211 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::time::Duration;
+use std::time::Instant;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use codex_protocol::ConversationId;
-use futures::SinkExt;
-use serde::Serialize;
+use codex_protocol::protocol::EventQuery;
+use codex_protocol::protocol::TelemetryConfigError;
+use codex_protocol::protocol::TelemetryConfigPatch;
 use serde_json::Value;
-use tokio::sync::mpsc;
-use tokio_tungstenite::connect_async;
-use tokio_tungstenite::tungstenite::Message;
+use serde_json::json;
+use tokio::sync::watch;
 use tracing::debug;
 use tracing::error;
+use tracing::warn;
 use url::Url;
 use url::form_urlencoded;
 
+use crate::config_types::VisualizerConnect;
+use crate::config_types::VisualizerDropPolicy;
+use crate::config_types::VisualizerTokenPlacement;
+use crate::retry::RetryPolicy;
+
+mod auth;
+pub(crate) use self::auth::RelayToken;
+use self::auth::relay_token_from_env;
+
+mod batch;
+pub(crate) use self::batch::EventBatching;
+use self::batch::Flushing;
+
+mod buffer;
+use self::buffer::BufferConfig;
+use self::buffer::BufferDiagnostics;
+use self::buffer::EventQueue;
+use self::buffer::QueueSender;
+
+mod chunks;
+pub use self::chunks::ChunkReassembler;
+pub(crate) use self::chunks::EventChunking;
+
+mod coarse;
+use self::coarse::SinkTransform;
+pub(crate) use self::coarse::TelemetryFidelity;
+
+mod compression;
+
+mod connection;
+use self::connection::Connector;
+use self::connection::WebSocketConnector;
+
+mod cwd;
+pub(crate) use self::cwd::CwdSnapshot;
+
+mod durable;
+use self::durable::DurableSink;
+
+#[cfg(any(test, feature = "failpoints"))]
+pub mod failpoints;
+
+mod forwarder;
+use self::forwarder::Forwarder;
+use self::forwarder::ForwarderHealth;
+use self::forwarder::Heartbeat;
+use self::forwarder::LazyForwarder;
+
+mod gap_detector;
+pub(crate) use self::gap_detector::GapCallback;
+pub(crate) use self::gap_detector::GapDetector;
+
+mod gaps;
+pub(crate) use self::gaps::Gap;
+use self::gaps::GapLedger;
+
+mod hello;
+use self::hello::Capabilities;
+
+mod http;
+use self::http::HttpConnector;
+
+mod ndjson;
+use self::ndjson::FileConnector;
+
+mod pins;
+pub(crate) use self::pins::PinMismatch;
+pub(crate) use self::pins::PinStore;
+
+mod query;
+
+mod replay;
+pub(crate) use self::replay::EventReplay;
+use self::replay::ReplayBuffer;
+
+mod report;
+pub(crate) use self::report::render_html;
+
+mod retention;
+pub(crate) use self::retention::RetentionRules;
+
+mod scope;
+pub(crate) use self::scope::ContentField;
+pub(crate) use self::scope::TrustedRoots;
+
+mod rules;
+use self::rules::ActionTypeFilter;
+use self::rules::ActionTypeSampling;
+use self::rules::LiveRules;
+
+mod self_test;
+pub use self::self_test::RoundTrip;
+pub use self::self_test::SelfTestReport;
+pub use self::self_test::self_test;
+
+mod size_cap;
+
+mod stdio;
+use self::stdio::StdioConnector;
+
+mod strict;
+use self::strict::Pending;
+use self::strict::StrictDelivery;
+
+mod timeline;
+
+mod tls;
+pub(crate) use self::tls::TlsFiles;
+
+mod unix;
+use self::unix::UnixConnector;
+
+mod wire;
+pub(crate) use self::wire::TimestampEncoding;
+use self::wire::WireEvent;
+use self::wire::split_sequence;
+
+/// Serialization errors are logged at most this often per action type.
+const SERIALIZATION_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Most recent events kept in memory for on-demand timeline reports.
+const RECENT_EVENTS_LIMIT: usize = 4096;
+
+/// How long the relay has to answer a heartbeat enabled with
+/// `CODEX_VISUALIZER_HEARTBEAT_MS`.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failed connects in a row after which the relay is given up on, unless
+/// configured otherwise with `visualizer_max_connect_failures`.
+pub(crate) const DEFAULT_MAX_CONNECT_FAILURES: u32 = 50;
+
+/// Serialized size above which an event not sent in chunks is truncated,
+/// unless configured otherwise with `visualizer_max_event_bytes`; see the
+/// `size_cap` module.
+pub(crate) const DEFAULT_MAX_EVENT_BYTES: usize = 256 * 1024;
+
 #[derive(Clone)]
 pub(crate) struct AgentVisualizer {
-    sender: Option<mpsc::Sender<VisualizerEvent>>,
+    sink: Option<Arc<Sink>>,
+    /// Further relays each event is also sent to, set by
+    /// [`AgentVisualizer::with_endpoints`]. Each has its own queue and
+    /// forwarder, so a slow or failing one only loses its own events. The
+    /// builders configure them like `sink`, but strict delivery, the
+    /// durable queue, and status only concern `sink`.
+    mirrors: Vec<Arc<Sink>>,
     sequence: Arc<AtomicU64>,
+    clock: EventClock,
+    /// Next `conversation_sequence` of each conversation emitting through
+    /// this visualizer.
+    conversation_sequences: Arc<Mutex<HashMap<ConversationId, u64>>>,
+    /// Events the primary relay lost, shared with its queue and forwarder.
+    gaps: Arc<GapLedger>,
+    recent: Arc<Mutex<VecDeque<VisualizerEvent>>>,
+    /// Applied to recent events returned to clients, which may be remote.
+    query_transform: SinkTransform,
+    retention: RetentionRules,
+    /// Set by [`AgentVisualizer::with_strict_delivery`].
+    strict: Option<StrictDelivery>,
+    rules: LiveRules,
+    /// Set by [`AgentVisualizer::with_durable_queue`].
+    durable: Option<Arc<DurableSink>>,
+    /// Set by [`AgentVisualizer::with_fork_suffix`].
+    fork_suffix: Option<Arc<str>>,
+    /// Set by [`AgentVisualizer::with_default_ttl`].
+    default_ttl: Option<Duration>,
+}
+
+/// Producer side of a configured websocket sink. Dropping it closes the
+/// queue, which lets a running forwarder drain and exit.
+struct Sink {
+    sender: QueueSender,
+    forwarder: LazyForwarder,
+    delivered: Arc<watch::Sender<u64>>,
+    /// One past the newest sequence handed to this sink's queue, so
+    /// `delivered` is compared with what this forwarder was given and not
+    /// with events that went to the durable queue.
+    queued_through: AtomicU64,
+    /// What a durable queue needs to deliver to the same relay.
+    connector: Arc<dyn Connector>,
+    connect_url: String,
+    transform: SinkTransform,
+    timestamps: TimestampEncoding,
+    /// Read by both forwarders at each reconnect.
+    reconnect: Arc<Mutex<RetryPolicy>>,
+    /// Set by [`AgentVisualizer::with_event_chunking`].
+    chunking: Arc<Mutex<Option<EventChunking>>>,
+    /// Set by [`AgentVisualizer::with_max_event_bytes`].
+    max_event_bytes: Arc<Mutex<Option<usize>>>,
+    /// Set by [`AgentVisualizer::with_event_batching`].
+    batching: Arc<Mutex<Option<EventBatching>>>,
+    /// Raised by [`AgentVisualizer::flush`] while it waits, so the
+    /// forwarder sends its batch at once.
+    flushes: Arc<watch::Sender<usize>>,
+    /// Set by [`AgentVisualizer::with_heartbeat`].
+    heartbeat: Arc<Mutex<Option<Heartbeat>>>,
+    /// Set by [`AgentVisualizer::with_max_connect_failures`].
+    max_connect_failures: Arc<Mutex<Option<u32>>>,
+    /// Set by [`AgentVisualizer::with_gap_detector`].
+    gap_detector: Arc<Mutex<Option<GapDetector>>>,
+    /// Limited by [`AgentVisualizer::with_replay`].
+    replay: Arc<Mutex<ReplayBuffer>>,
+    /// Set by the forwarder once it gave up on the relay.
+    abandoned: Arc<watch::Sender<Option<u32>>>,
+    /// Drops already reported by [`AgentVisualizer::drop_summary`].
+    reported_drops: AtomicU64,
+    /// Stale events already reported by [`AgentVisualizer::drop_summary`].
+    reported_stale: AtomicU64,
+}
+
+impl Sink {
+    /// Queue and forwarder for the relay at `url`, started lazily, which
+    /// record the events they lose in `gaps`.
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        url: String,
+        fidelity: TelemetryFidelity,
+        idle_shutdown: Option<Duration>,
+        timestamps: TimestampEncoding,
+        trusted_roots: TrustedRoots,
+        connector: Arc<dyn Connector>,
+        sequence: &Arc<AtomicU64>,
+        clock: EventClock,
+        gaps: &Arc<GapLedger>,
+    ) -> Arc<Self> {
+        let connect_url = match ensure_producer_role(&url) {
+            // Not a URL, so there is nowhere to put the role.
+            _ if stdio::is_stdio_sink(&url) => url,
+            Ok(prepared) => prepared,
+            Err(err) => {
+                error!("failed to prepare visualizer websocket url: {err:?}");
+                url
+            }
+        };
+        let diagnostics = Arc::new(DiagnosticsState {
+            gaps: Arc::clone(gaps),
+            ..Default::default()
+        });
+        let replay = Arc::new(Mutex::new(ReplayBuffer::new(None)));
+        let queue = EventQueue::new(BufferConfig::default(), Arc::clone(&diagnostics));
+        let queue = Arc::new(if connector.greets() {
+            queue.with_replay(Arc::clone(&replay))
+        } else {
+            queue
+        });
+        let delivered = Arc::new(watch::Sender::new(0));
+        let transform = SinkTransform::for_sink(&connect_url, fidelity, trusted_roots);
+        let reconnect = Arc::new(Mutex::new(RetryPolicy::VISUALIZER_RECONNECT));
+        let chunking = Arc::new(Mutex::new(None));
+        let max_event_bytes = Arc::new(Mutex::new(Some(DEFAULT_MAX_EVENT_BYTES)));
+        let batching = Arc::new(Mutex::new(None));
+        let flushes = Arc::new(watch::Sender::new(0));
+        let heartbeat = Arc::new(Mutex::new(None));
+        let max_connect_failures = Arc::new(Mutex::new(Some(DEFAULT_MAX_CONNECT_FAILURES)));
+        let abandoned = Arc::new(watch::Sender::new(None));
+        let gap_detector = Arc::new(Mutex::new(None));
+        let forwarder = Forwarder {
+            queue: Arc::clone(&queue),
+            connector: Arc::clone(&connector),
+            transform: transform.clone(),
+            connect_url: connect_url.clone(),
+            failures: SerializationFailures::new(
+                diagnostics,
+                Arc::clone(sequence),
+                clock,
+                timestamps,
+            ),
+            delivered: Arc::clone(&delivered),
+            idle_shutdown,
+            reconnect: Arc::clone(&reconnect),
+            chunking: Arc::clone(&chunking),
+            max_event_bytes: Arc::clone(&max_event_bytes),
+            batching: Arc::clone(&batching),
+            flushes: Arc::clone(&flushes),
+            connect_mode: Arc::new(Mutex::new(VisualizerConnect::Lazy)),
+            heartbeat: Arc::clone(&heartbeat),
+            max_connect_failures: Arc::clone(&max_connect_failures),
+            abandoned: Arc::clone(&abandoned),
+            stopped: Arc::new(watch::Sender::new(false)),
+            gap_detector: Arc::clone(&gap_detector),
+            replay: Arc::clone(&replay),
+            accepted: Capabilities::default(),
+        };
+        Arc::new(Sink {
+            sender: QueueSender::new(queue),
+            forwarder: LazyForwarder::new(forwarder),
+            delivered,
+            queued_through: AtomicU64::new(0),
+            connector,
+            connect_url,
+            transform,
+            timestamps,
+            reconnect,
+            chunking,
+            max_event_bytes,
+            batching,
+            flushes,
+            heartbeat,
+            max_connect_failures,
+            abandoned,
+            gap_detector,
+            replay,
+            reported_drops: AtomicU64::new(0),
+            reported_stale: AtomicU64::new(0),
+        })
+    }
+}
+
+/// Health counters maintained by the forwarder task.
+#[derive(Default)]
+struct DiagnosticsState {
+    serialization_failures: Mutex<BTreeMap<String, u64>>,
+    buffer: Mutex<BufferDiagnostics>,
+    forwarder: Mutex<ForwarderHealth>,
+    gaps: Arc<GapLedger>,
 }
 
 #[derive(Clone)]
 pub(crate) struct SessionVisualizer {
     inner: AgentVisualizer,
     conversation_id: ConversationId,
+    /// Set by [`SessionVisualizer::close`]; later events are discarded.
+    closed: Arc<AtomicBool>,
+}
+
+/// Whether the sink's forwarder is still delivering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VisualizerStatus {
+    /// No relay is configured.
+    Disabled,
+    /// Delivering, after recovering from these forwarder panics. The
+    /// latency is that of the latest successful connect, retries included;
+    /// `None` before the first.
+    Running {
+        recovered_panics: Vec<String>,
+        connect_latency: Option<Duration>,
+    },
+    /// The forwarder panicked more often than it is restarted; events are
+    /// still recorded but no longer delivered.
+    Failed { panics: Vec<String> },
+    /// The relay presented a key other than the one pinned for it; nothing
+    /// is delivered after.
+    PinRejected { mismatch: PinMismatch },
+    /// The relay refused the token with this HTTP status; nothing is
+    /// delivered after.
+    AuthRejected { status: u16 },
+    /// The relay could not be reached this many times in a row and was
+    /// given up on; events are still recorded but no longer queued.
+    Abandoned { connect_failures: u32 },
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
+/// Serialized as described in the `wire` module.
+#[derive(Debug, Clone)]
 pub(crate) struct VisualizerEvent {
+    /// Session counter; never resets, unlike the emitted `sequence`.
     pub(crate) sequence: u64,
     pub(crate) timestamp_ms: u128,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Milliseconds since the visualizer's [`EventClock`] origin; unlike
+    /// `timestamp_ms`, never smaller than that of an earlier event.
+    pub(crate) monotonic_ms: u64,
     pub(crate) conversation_id: Option<ConversationId>,
+    /// Position among the events of `conversation_id`, counting from 0 with
+    /// no gaps, while `sequence` counts every conversation sharing the
+    /// session's visualizer. `None` exactly when `conversation_id` is.
+    pub(crate) conversation_sequence: Option<u64>,
     pub(crate) action_type: String,
     pub(crate) action: Value,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) state: Option<Value>,
+    /// Task the event belongs to: its `subId`, or the submission id of a
+    /// protocol event. Indexes `query_recent`; never serialized.
+    pub(crate) sub_id: Option<String>,
+    /// Appended to `conversation_id` on the wire; see
+    /// [`AgentVisualizer::with_fork_suffix`].
+    pub(crate) fork_suffix: Option<Arc<str>>,
+    /// Fields of `action` holding file contents; never serialized.
+    pub(crate) content: Vec<ContentField>,
+    /// How long after `timestamp_ms` the event is still worth sending; a
+    /// forwarder drops it instead once that has passed, as after a long
+    /// reconnect. `None` never expires. Never serialized.
+    pub(crate) ttl: Option<Duration>,
+    /// Set on the copy sent again to a relay that connected after the
+    /// event was done with; see the `replay` module.
+    pub(crate) replayed: bool,
+}
+
+impl VisualizerEvent {
+    /// Whether the event outlived its `ttl` by `now_ms`.
+    pub(crate) fn is_stale(&self, now_ms: u128) -> bool {
+        self.ttl
+            .is_some_and(|ttl| self.timestamp_ms + ttl.as_millis() < now_ms)
+    }
+
+    /// An event with `sequence` and `action_type`, an empty action and
+    /// nothing else set, for tests to adjust with struct update syntax.
+    #[cfg(test)]
+    pub(crate) fn for_test(sequence: u64, action_type: &str) -> Self {
+        Self {
+            sequence,
+            timestamp_ms: 0,
+            monotonic_ms: 0,
+            conversation_id: None,
+            conversation_sequence: None,
+            action_type: action_type.to_string(),
+            action: json!({}),
+            state: None,
+            sub_id: None,
+            fork_suffix: None,
+            content: Vec::new(),
+            ttl: None,
+            replayed: false,
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_millis()
+}
+
+/// Where events get their times from: the wall clock for `timestamp_ms`,
+/// and the time elapsed since `origin` for `monotonic_ms`, which never goes
+/// back when the wall clock does. Clones of a visualizer copy its clock, so
+/// every session in the process measures from the same origin.
+#[derive(Clone, Copy)]
+struct EventClock {
+    origin: Instant,
+    wall_ms: fn() -> u128,
+}
+
+impl EventClock {
+    fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            wall_ms: now_ms,
+        }
+    }
+
+    fn monotonic_ms(&self) -> u64 {
+        u64::try_from(self.origin.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+fn build_event(
+    sequence: &AtomicU64,
+    clock: &EventClock,
+    conversation_id: Option<ConversationId>,
+    action_type: String,
+    action: Value,
+    state: Option<Value>,
+) -> VisualizerEvent {
+    let sub_id = match action_type.as_str() {
+        "protocol_event" => action.pointer("/event/id"),
+        _ => action.get("subId"),
+    }
+    .and_then(Value::as_str)
+    .map(str::to_string);
+    VisualizerEvent {
+        sequence: sequence.fetch_add(1, Ordering::SeqCst),
+        timestamp_ms: (clock.wall_ms)(),
+        monotonic_ms: clock.monotonic_ms(),
+        conversation_id,
+        conversation_sequence: None,
+        action_type,
+        action,
+        state,
+        sub_id,
+        fork_suffix: None,
+        content: Vec::new(),
+        ttl: None,
+        replayed: false,
+    }
+}
+
+/// An event ready for the queue, with the lifecycle event strict delivery
+/// waits for if it is one. `durable` events go to the durable queue
+/// instead.
+struct Queued {
+    event: VisualizerEvent,
+    pending: Option<Pending>,
+    durable: bool,
+}
+
+/// Report a lifecycle event the durable queue could not persist. It is not
+/// sent best-effort instead, so this failure is what the session hears.
+fn report_unpersisted(
+    strict: Option<&StrictDelivery>,
+    action_type: &str,
+    sequence: u64,
+    err: &io::Error,
+) {
+    let cause = format!(
+        "lifecycle event `{action_type}` (sequence {sequence}) could not be persisted: {err}"
+    );
+    error!("durable visualizer queue: {cause}");
+    if let Some(strict) = strict {
+        strict.fail(cause);
+    }
+}
+
+/// Outcome of recording one serialization failure.
+#[derive(Debug, PartialEq, Eq)]
+struct SerializationFailure {
+    count: u64,
+    first: bool,
+    should_log: bool,
+}
+
+/// Forwarder-side handling of events that fail to serialize. Failures are
+/// counted per action type, logged with a per-action rate limit, and the
+/// event is still shipped in a lossy form so the stream keeps its shape.
+#[derive(Clone)]
+struct SerializationFailures {
+    diagnostics: Arc<DiagnosticsState>,
+    sequence: Arc<AtomicU64>,
+    clock: EventClock,
+    timestamps: TimestampEncoding,
+    last_logged: HashMap<String, Instant>,
+}
+
+impl SerializationFailures {
+    fn new(
+        diagnostics: Arc<DiagnosticsState>,
+        sequence: Arc<AtomicU64>,
+        clock: EventClock,
+        timestamps: TimestampEncoding,
+    ) -> Self {
+        Self {
+            diagnostics,
+            sequence,
+            clock,
+            timestamps,
+            last_logged: HashMap::new(),
+        }
+    }
+
+    fn encode(&mut self, event: &VisualizerEvent, queue: &EventQueue) -> String {
+        let err = match serde_json::to_string(&WireEvent::new(event, self.timestamps)) {
+            Ok(payload) => return payload,
+            Err(err) => err,
+        };
+        let failure = self.record(&event.action_type, Instant::now());
+        if failure.should_log {
+            error!(
+                "failed to serialize visualizer event `{}` ({} failures so far): {err:?}",
+                event.action_type, failure.count
+            );
+        }
+        if failure.first {
+            let notice = build_event(
+                &self.sequence,
+                &self.clock,
+                None,
+                "serialization_degraded".to_string(),
+                json!({
+                    "actionType": event.action_type,
+                    "error": err.to_string(),
+                }),
+                None,
+            );
+            if !queue.push(notice) {
+                debug!("visualizer queue unavailable; dropping serialization_degraded notice");
+            }
+        }
+        lossy_payload(event, self.timestamps)
+    }
+
+    fn record(&mut self, action_type: &str, now: Instant) -> SerializationFailure {
+        let count = match self.diagnostics.serialization_failures.lock() {
+            Ok(mut failures) => {
+                let count = failures.entry(action_type.to_string()).or_default();
+                *count += 1;
+                *count
+            }
+            Err(_) => 0,
+        };
+        let should_log = match self.last_logged.get(action_type) {
+            Some(last) => now.saturating_duration_since(*last) >= SERIALIZATION_ERROR_LOG_INTERVAL,
+            None => true,
+        };
+        if should_log {
+            self.last_logged.insert(action_type.to_string(), now);
+        }
+        SerializationFailure {
+            count,
+            first: count == 1,
+            should_log,
+        }
+    }
+}
+
+/// Re-encode an event whose payload failed to serialize, replacing only
+/// the parts of its action and state that fail on their own with a
+/// sentinel. Note that `serde_json::Value` already coerces non-finite floats
+/// to `null`, so this only triggers for payloads that are invalid for other
+/// reasons.
+fn lossy_payload(event: &VisualizerEvent, timestamps: TimestampEncoding) -> String {
+    lossy_payload_with(event, timestamps, &|value| {
+        serde_json::to_string(value).map(drop)
+    })
+}
+
+/// [`lossy_payload`], with `encodes` deciding whether a value serializes.
+fn lossy_payload_with(
+    event: &VisualizerEvent,
+    timestamps: TimestampEncoding,
+    encodes: &dyn Fn(&Value) -> serde_json::Result<()>,
+) -> String {
+    let mut lossy = event.clone();
+    degrade_failing(&mut lossy.action, encodes);
+    if let Some(state) = lossy.state.as_mut() {
+        degrade_failing(state, encodes);
+    }
+    serde_json::to_string(&WireEvent::new(&lossy, timestamps)).unwrap_or_else(|_| {
+        let (sequence_epoch, sequence) = split_sequence(event.sequence);
+        json!({
+            "sequenceEpoch": sequence_epoch,
+            "sequence": sequence,
+            "monotonicMs": event.monotonic_ms,
+            "actionType": event.action_type,
+            "action": { "lossy": true },
+        })
+        .to_string()
+    })
+}
+
+/// Replace `value` with a sentinel if it fails to serialize, descending
+/// first into its members so that only the failing ones are replaced.
+fn degrade_failing(value: &mut Value, encodes: &dyn Fn(&Value) -> serde_json::Result<()>) {
+    if encodes(value).is_ok() {
+        return;
+    }
+    match value {
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|member| degrade_failing(member, encodes)),
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| degrade_failing(item, encodes)),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {}
+    }
+    if let Err(err) = encodes(value) {
+        *value = json!({
+            "lossy": true,
+            "serializationError": err.to_string(),
+        });
+    }
+}
+
+/// The transport for `url`: `file://` URLs name an NDJSON file to append
+/// to instead of a relay, see the `ndjson` module, `unix://` URLs a
+/// socket to write NDJSON to, see the `unix` module, and `http(s)://` URLs
+/// or ones asking for `transport=http` a relay to POST batches to, see the
+/// `http` module. `stdout` and `stderr` are not URLs but name a standard
+/// stream to write to, see the `stdio` module.
+fn connector_for(url: &str) -> Arc<dyn Connector> {
+    if let Some(connector) = StdioConnector::for_sink(url) {
+        Arc::new(connector)
+    } else if ndjson::is_file_url(url) {
+        Arc::new(FileConnector)
+    } else if unix::is_unix_url(url) {
+        Arc::new(UnixConnector)
+    } else if http::is_http_url(url) {
+        Arc::new(HttpConnector::default())
+    } else {
+        Arc::new(WebSocketConnector::default())
+    }
+}
+
+/// Every sink to deliver to, the primary first: `CODEX_VISUALIZER_WS`'s
+/// comma-separated relays, the first of which is the primary,
+/// `CODEX_VISUALIZER_WS_EXTRA`'s, which get every event too, and the log
+/// file, which is the primary without a relay.
+fn endpoint_urls(
+    relays: Option<String>,
+    extra: Option<String>,
+    log_file: Option<String>,
+) -> Vec<String> {
+    [relays, extra]
+        .into_iter()
+        .flatten()
+        .flat_map(|urls| {
+            urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .chain(log_file)
+        .collect()
 }
 
 fn ensure_producer_role(raw_url: &str) -> Result<String, url::ParseError> {
+    with_role(raw_url, "producer")
+}
+
+fn with_role(raw_url: &str, role: &str) -> Result<String, url::ParseError> {
+    with_query_pair(raw_url, "role", role)
+}
+
+/// `raw_url` with its query parameter `name` set to `value`, replacing any
+/// it already had.
+fn with_query_pair(raw_url: &str, name: &str, value: &str) -> Result<String, url::ParseError> {
     let mut parsed = Url::parse(raw_url)?;
     let mut serializer = form_urlencoded::Serializer::new(String::new());
-    for (key, value) in parsed.query_pairs().filter(|(key, _)| key != "role") {
+    for (key, value) in parsed.query_pairs().filter(|(key, _)| key != name) {
         serializer.append_pair(&key, &value);
     }
-    serializer.append_pair("role", "producer");
+    serializer.append_pair(name, value);
     let query = serializer.finish();
     parsed.set_query(Some(&query));
     Ok(parsed.into())
 }
 
 impl AgentVisualizer {
-    pub(crate) fn from_env() -> Self {
-        let url = std::env::var("CODEX_VISUALIZER_WS").ok();
-        Self::new(url)
-    }
-
-    pub(crate) fn new(url: Option<String>) -> Self {
-        if let Some(url) = url {
-            let connect_url = match ensure_producer_role(&url) {
-                Ok(prepared) => prepared,
+    /// Sinks from `CODEX_VISUALIZER_WS`, `CODEX_VISUALIZER_WS_EXTRA` and
+    /// `CODEX_VISUALIZER_LOG_FILE`, authenticated with
+    /// `CODEX_VISUALIZER_TOKEN` sent as `token_placement` says, emitting
+    /// only the action types `CODEX_VISUALIZER_INCLUDE` and
+    /// `CODEX_VISUALIZER_EXCLUDE` let through, sampled as
+    /// `CODEX_VISUALIZER_SAMPLE` says. Events expire after
+    /// `CODEX_VISUALIZER_DEFAULT_TTL_MS`, if set. Standard stream sinks the
+    /// host needs go to a file instead: both while the TUI
+    /// `owns_terminal`, and otherwise standard output unless
+    /// `CODEX_VISUALIZER_ALLOW_STDOUT=1`; see the `stdio` module.
+    pub(crate) fn from_env(
+        fidelity: TelemetryFidelity,
+        idle_shutdown: Option<Duration>,
+        retention: RetentionRules,
+        timestamps: TimestampEncoding,
+        trusted_roots: TrustedRoots,
+        token_placement: VisualizerTokenPlacement,
+        owns_terminal: bool,
+    ) -> Self {
+        let log_file = std::env::var_os("CODEX_VISUALIZER_LOG_FILE")
+            .filter(|path| !path.is_empty())
+            .and_then(|path| match ndjson::file_url(Path::new(&path)) {
+                Ok(url) => Some(url),
                 Err(err) => {
-                    error!("failed to prepare visualizer websocket url: {err:?}");
-                    url
-                }
-            };
-            let (tx, mut rx) = mpsc::channel(256);
-            tokio::spawn(async move {
-                let mut pending: Option<VisualizerEvent> = None;
-                let mut stream: Option<_> = None;
-                let retry_delay = Duration::from_secs(1);
-
-                'outer: loop {
-                    if pending.is_none() {
-                        match rx.recv().await {
-                            Some(event) => pending = Some(event),
-                            None => break,
-                        }
-                    }
-
-                    let Some(event) = pending.take() else {
-                        continue;
-                    };
-
-                    if stream.is_none() {
-                        match connect_async(&connect_url).await {
-                            Ok((ws, _)) => stream = Some(ws),
-                            Err(err) => {
-                                error!("failed to connect to visualizer websocket: {err:?}");
-                                pending = Some(event);
-                                tokio::time::sleep(retry_delay).await;
-                                continue;
-                            }
-                        }
-                    }
-
-                    let serialized = match serde_json::to_string(&event) {
-                        Ok(payload) => payload,
-                        Err(err) => {
-                            error!("failed to serialize visualizer event: {err:?}");
-                            continue;
-                        }
-                    };
-
-                    let send_result = match stream.as_mut() {
-                        Some(ws) => ws.send(Message::Text(serialized)).await,
-                        None => {
-                            error!("visualizer websocket stream missing before send");
-                            pending = Some(event);
-                            tokio::time::sleep(retry_delay).await;
-                            continue;
-                        }
-                    };
-
-                    match send_result {
-                        Ok(()) => loop {
-                            match rx.try_recv() {
-                                Ok(next) => {
-                                    let serialized = match serde_json::to_string(&next) {
-                                        Ok(payload) => payload,
-                                        Err(err) => {
-                                            error!("failed to serialize visualizer event: {err:?}");
-                                            continue;
-                                        }
-                                    };
-
-                                    let backlog_send = match stream.as_mut() {
-                                        Some(ws) => ws.send(Message::Text(serialized)).await,
-                                        None => {
-                                            error!(
-                                                "visualizer websocket stream missing before backlog send"
-                                            );
-                                            pending = Some(next);
-                                            tokio::time::sleep(retry_delay).await;
-                                            continue 'outer;
-                                        }
-                                    };
-
-                                    if let Err(err) = backlog_send {
-                                        error!("failed to send visualizer event: {err:?}");
-                                        pending = Some(next);
-                                        stream = None;
-                                        tokio::time::sleep(retry_delay).await;
-                                        continue 'outer;
-                                    }
-                                }
-                                Err(mpsc::error::TryRecvError::Empty) => continue 'outer,
-                                Err(mpsc::error::TryRecvError::Disconnected) => break 'outer,
-                            }
-                        },
-                        Err(err) => {
-                            error!("failed to send visualizer event: {err:?}");
-                            pending = Some(event);
-                            stream = None;
-                            tokio::time::sleep(retry_delay).await;
-                        }
-                    }
+                    error!("ignoring CODEX_VISUALIZER_LOG_FILE: {err}");
+                    None
                 }
-                debug!("visualizer channel closed; stopping websocket forwarder");
             });
+        let urls = endpoint_urls(
+            std::env::var("CODEX_VISUALIZER_WS").ok(),
+            std::env::var("CODEX_VISUALIZER_WS_EXTRA").ok(),
+            log_file,
+        );
+        let stdout_allowed = stdio::stdout_allowed_by_env();
+        let urls = urls
+            .into_iter()
+            .filter_map(|url| stdio::route(url, owns_terminal, stdout_allowed))
+            .collect();
+        let visualizer = Self::with_endpoints(
+            urls,
+            fidelity,
+            idle_shutdown,
+            retention,
+            timestamps,
+            trusted_roots,
+        );
+        let heartbeat_ms = std::env::var("CODEX_VISUALIZER_HEARTBEAT_MS")
+            .ok()
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .filter(|ms| *ms > 0);
+        let visualizer = match heartbeat_ms {
+            Some(ms) => visualizer.with_heartbeat(Duration::from_millis(ms), HEARTBEAT_TIMEOUT),
+            None => visualizer,
+        };
+        let default_ttl = std::env::var("CODEX_VISUALIZER_DEFAULT_TTL_MS")
+            .ok()
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis);
+        let filter = ActionTypeFilter::new(
+            std::env::var("CODEX_VISUALIZER_INCLUDE").ok().as_deref(),
+            std::env::var("CODEX_VISUALIZER_EXCLUDE").ok().as_deref(),
+        );
+        let sample_seed = std::env::var("CODEX_VISUALIZER_SAMPLE_SEED")
+            .ok()
+            .and_then(|seed| seed.parse::<u64>().ok())
+            .unwrap_or_else(rand::random);
+        let sampling = ActionTypeSampling::new(
+            std::env::var("CODEX_VISUALIZER_SAMPLE").ok().as_deref(),
+            sample_seed,
+        );
+        let visualizer = visualizer
+            .with_default_ttl(default_ttl)
+            .with_action_type_filter(filter)
+            .with_action_type_sampling(sampling);
+        match relay_token_from_env() {
+            Some(token) => visualizer.with_relay_token(RelayToken::new(token, token_placement)),
+            None => visualizer,
+        }
+    }
+
+    pub(crate) fn new(
+        url: Option<String>,
+        fidelity: TelemetryFidelity,
+        idle_shutdown: Option<Duration>,
+        retention: RetentionRules,
+        timestamps: TimestampEncoding,
+        trusted_roots: TrustedRoots,
+    ) -> Self {
+        let connector = connector_for(url.as_deref().unwrap_or_default());
+        Self::with_connector(
+            url,
+            fidelity,
+            idle_shutdown,
+            retention,
+            timestamps,
+            trusted_roots,
+            connector,
+        )
+    }
+
+    /// Like [`AgentVisualizer::new`], sending every event to each of `urls`.
+    /// The first is the primary relay; see `mirrors`.
+    pub(crate) fn with_endpoints(
+        urls: Vec<String>,
+        fidelity: TelemetryFidelity,
+        idle_shutdown: Option<Duration>,
+        retention: RetentionRules,
+        timestamps: TimestampEncoding,
+        trusted_roots: TrustedRoots,
+    ) -> Self {
+        let mut urls = urls.into_iter();
+        let mut visualizer = Self::new(
+            urls.next(),
+            fidelity,
+            idle_shutdown,
+            retention,
+            timestamps,
+            trusted_roots.clone(),
+        );
+        visualizer.mirrors = urls
+            .map(|url| {
+                Sink::start(
+                    url.clone(),
+                    fidelity,
+                    idle_shutdown,
+                    timestamps,
+                    trusted_roots.clone(),
+                    connector_for(&url),
+                    &visualizer.sequence,
+                    visualizer.clock,
+                    // What a mirror loses is not reported.
+                    &Arc::default(),
+                )
+            })
+            .collect();
+        visualizer
+    }
+
+    fn with_connector(
+        url: Option<String>,
+        fidelity: TelemetryFidelity,
+        idle_shutdown: Option<Duration>,
+        retention: RetentionRules,
+        timestamps: TimestampEncoding,
+        trusted_roots: TrustedRoots,
+        connector: Arc<dyn Connector>,
+    ) -> Self {
+        let sequence = Arc::new(AtomicU64::new(0));
+        let clock = EventClock::new();
+        let gaps = Arc::new(GapLedger::default());
+        let query_transform = SinkTransform::for_clients(fidelity, trusted_roots.clone());
+        let sink = url.map(|url| {
+            Sink::start(
+                url,
+                fidelity,
+                idle_shutdown,
+                timestamps,
+                trusted_roots,
+                connector,
+                &sequence,
+                clock,
+                &gaps,
+            )
+        });
+        Self {
+            sink,
+            mirrors: Vec::new(),
+            sequence,
+            clock,
+            conversation_sequences: Arc::default(),
+            gaps,
+            recent: Arc::default(),
+            query_transform,
+            retention,
+            strict: None,
+            rules: LiveRules::default(),
+            durable: None,
+            fork_suffix: None,
+            default_ttl: None,
+        }
+    }
+
+    /// Make lifecycle events wait up to `timeout` for delivery; see the
+    /// `strict` module. Without a sink there is nothing to deliver to, so
+    /// this changes nothing.
+    pub(crate) fn with_strict_delivery(mut self, timeout: Duration) -> Self {
+        if let Some(sink) = &self.sink {
+            sink.sender.reserve_for_lifecycle(strict::LIFECYCLE_RESERVE);
+            self.strict = Some(StrictDelivery::new(timeout));
+        }
+        self
+    }
 
-            Self {
-                sender: Some(tx),
-                sequence: Arc::new(AtomicU64::new(0)),
+    /// Persist lifecycle events in `dir` before sending them and deliver
+    /// them at least once, across restarts; see the `durable` module. Fails
+    /// if `dir` cannot be written, and a lifecycle event that cannot be
+    /// persisted later is a failure reported through
+    /// [`AgentVisualizer::telemetry_failures`]. Without a sink this changes
+    /// nothing. Must be called within a tokio runtime.
+    pub(crate) fn with_durable_queue(mut self, dir: &Path) -> io::Result<Self> {
+        if let Some(sink) = &self.sink {
+            let durable = DurableSink::start(
+                dir,
+                Arc::clone(&sink.connector),
+                sink.connect_url.clone(),
+                sink.transform.clone(),
+                sink.timestamps,
+                Arc::clone(&sink.reconnect),
+            )?;
+            self.durable = Some(Arc::new(durable));
+            // Only the failure channel is used unless strict delivery is on
+            // as well: lifecycle events go to the durable queue, and once
+            // one could not be persisted nothing waits any more.
+            self.strict
+                .get_or_insert_with(|| StrictDelivery::new(Duration::ZERO));
+        }
+        Ok(self)
+    }
+
+    /// Reconnect to the relay on `policy` instead of
+    /// [`RetryPolicy::VISUALIZER_RECONNECT`]. Without a sink this changes
+    /// nothing.
+    pub(crate) fn with_reconnect_policy(self, policy: RetryPolicy) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut reconnect) = sink.reconnect.lock() {
+                *reconnect = policy;
             }
-        } else {
-            Self {
-                sender: None,
-                sequence: Arc::new(AtomicU64::new(0)),
+        }
+        self
+    }
+
+    /// Ping the relay whenever the connection has been idle for `interval`
+    /// and drop the connection if the pong takes longer than `timeout`, in
+    /// every connect mode. Without a sink this changes nothing.
+    pub(crate) fn with_heartbeat(self, interval: Duration, timeout: Duration) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.heartbeat.lock() {
+                *slot = Some(Heartbeat {
+                    interval,
+                    timeout: Some(timeout),
+                });
+            }
+        }
+        self
+    }
+
+    /// Give up on the relay after `max_failures` failed connects in a row
+    /// instead of [`DEFAULT_MAX_CONNECT_FAILURES`]; `None` never gives up.
+    /// Without a sink this changes nothing.
+    pub(crate) fn with_max_connect_failures(self, max_failures: Option<u32>) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.max_connect_failures.lock() {
+                *slot = max_failures;
             }
         }
+        self
+    }
+
+    /// Handle events emitted while the queue is full as `policy` says
+    /// instead of dropping them; see the `buffer` module. Without a sink
+    /// this changes nothing.
+    pub(crate) fn with_drop_policy(self, policy: VisualizerDropPolicy) -> Self {
+        for sink in self.sinks() {
+            sink.sender.set_drop_policy(policy);
+        }
+        self
+    }
+
+    /// Queue at most `capacity` events instead of adapting the queue to
+    /// bursts; see the `buffer` module. `None`, or no sink, changes
+    /// nothing.
+    pub(crate) fn with_queue_capacity(self, capacity: Option<usize>) -> Self {
+        if let Some(capacity) = capacity {
+            for sink in self.sinks() {
+                sink.sender.set_capacity(capacity);
+            }
+        }
+        self
+    }
+
+    /// Send events that serialize to more than `chunking.frame_bytes` as
+    /// `event_chunk` frames; see the `chunks` module. Without a sink this
+    /// changes nothing.
+    pub(crate) fn with_event_chunking(self, chunking: EventChunking) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.chunking.lock() {
+                *slot = Some(chunking);
+            }
+        }
+        self
+    }
+
+    /// Truncate events not sent in chunks that serialize to more than
+    /// `max_bytes` instead of [`DEFAULT_MAX_EVENT_BYTES`]; `None` sends them
+    /// whole. See the `size_cap` module. Without a sink this changes
+    /// nothing.
+    pub(crate) fn with_max_event_bytes(self, max_bytes: Option<usize>) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.max_event_bytes.lock() {
+                *slot = max_bytes;
+            }
+        }
+        self
+    }
+
+    /// Send events in batches as `batching` says instead of one frame
+    /// each; see the `batch` module. Without a sink this changes nothing.
+    pub(crate) fn with_event_batching(self, batching: EventBatching) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.batching.lock() {
+                *slot = Some(batching);
+            }
+        }
+        self
+    }
+
+    /// Call `callback` whenever a relay is sent a sequence past the one
+    /// after the last it was sent; see the `gap_detector` module. Without
+    /// a sink this changes nothing.
+    pub(crate) fn with_gap_detector(self, callback: GapCallback) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut slot) = sink.gap_detector.lock() {
+                *slot = Some(GapDetector::new(Arc::clone(&callback)));
+            }
+        }
+        self
+    }
+
+    /// Replay the events dropped while no relay could be reached, within
+    /// `replay`, to the next relay that connects, or none with `None`, the
+    /// default; see the `replay` module. Without a sink this changes
+    /// nothing.
+    pub(crate) fn with_replay(self, replay: Option<EventReplay>) -> Self {
+        for sink in self.sinks() {
+            if let Ok(mut buffer) = sink.replay.lock() {
+                buffer.set_limits(replay);
+            }
+        }
+        self
+    }
+
+    /// Gzip large frames and send them as binary frames; see the
+    /// `compression` module. Without a sink this changes nothing.
+    pub(crate) fn with_compression(self) -> Self {
+        for sink in self.sinks() {
+            sink.connector.compress_frames();
+        }
+        self
+    }
+
+    /// Authenticate to every websocket relay with `token`; see the `auth`
+    /// module. Without a sink this changes nothing.
+    pub(crate) fn with_relay_token(self, token: RelayToken) -> Self {
+        for sink in self.sinks() {
+            sink.connector.authenticate(token.clone());
+        }
+        self
+    }
+
+    /// Pin the key of each `wss` relay on first use in `pins`, and refuse
+    /// relays presenting any other key after; see the `pins` module.
+    /// Without a sink this changes nothing.
+    pub(crate) fn with_pinned_tls(self, pins: PinStore) -> Self {
+        for sink in self.sinks() {
+            sink.connector.pin_tls(pins.clone());
+        }
+        self
+    }
+
+    /// Verify `wss` relays against the extra roots in `files` and present
+    /// its client certificate; see the `tls` module. Fails if a file cannot
+    /// be read or holds no usable certificate or key. Without a sink, or
+    /// files, this changes nothing.
+    pub(crate) fn with_tls(self, files: &TlsFiles) -> io::Result<Self> {
+        if self.sink.is_none() || files.is_empty() {
+            return Ok(self);
+        }
+        let tls = files.load()?;
+        for sink in self.sinks() {
+            sink.connector.use_tls(tls.clone());
+        }
+        Ok(self)
+    }
+
+    /// Connect to the relay as `mode` says; the eager modes start the
+    /// forwarder now, so call this after the builders that configure its
+    /// connection.
+    pub(crate) fn with_connect_mode(self, mode: VisualizerConnect) -> Self {
+        for sink in self.sinks() {
+            sink.forwarder.set_connect_mode(mode);
+        }
+        self
+    }
+
+    /// Report the conversation of every event as `<id><suffix>`, for a
+    /// session running alongside another one on the same conversation; see
+    /// `conversation_lease`.
+    pub(crate) fn with_fork_suffix(mut self, suffix: String) -> Self {
+        self.fork_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Only emit events whose action type `filter` admits; see the `rules`
+    /// module.
+    pub(crate) fn with_action_type_filter(self, filter: ActionTypeFilter) -> Self {
+        self.rules.set_action_type_filter(filter);
+        self
+    }
+
+    /// Sample events by action type as `sampling` says; see the `rules`
+    /// module.
+    pub(crate) fn with_action_type_sampling(self, sampling: ActionTypeSampling) -> Self {
+        self.rules.set_action_type_sampling(sampling);
+        self
+    }
+
+    /// Expire events emitted without a TTL of their own after `ttl`; `None`
+    /// keeps them until delivered. See [`VisualizerEvent::ttl`].
+    pub(crate) fn with_default_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Read `timestamp_ms` from `wall_ms` instead of the system clock.
+    #[cfg(test)]
+    fn with_wall_clock(mut self, wall_ms: fn() -> u128) -> Self {
+        self.clock.wall_ms = wall_ms;
+        self
+    }
+
+    /// Yields the cause of the first strict delivery failure, or of the
+    /// first lifecycle event the durable queue could not persist; `None`
+    /// unless strict delivery or the durable queue is on.
+    pub(crate) fn telemetry_failures(&self) -> Option<watch::Receiver<Option<String>>> {
+        self.strict.as_ref().map(StrictDelivery::failures)
+    }
+
+    /// What the primary relay accepted on the latest connection; see the
+    /// `hello` module. `None` without a sink.
+    fn accepted_capabilities(&self) -> Option<Capabilities> {
+        self.sink
+            .as_ref()
+            .map(|sink| sink.forwarder.health().accepted)
+    }
+
+    /// The primary sink, if any, and the mirrors.
+    fn sinks(&self) -> impl Iterator<Item = &Arc<Sink>> {
+        self.sink.iter().chain(&self.mirrors)
+    }
+
+    /// Yields the number of failed connects once the relay is given up on;
+    /// `None` without a sink.
+    pub(crate) fn abandonment(&self) -> Option<watch::Receiver<Option<u32>>> {
+        self.sink.as_ref().map(|sink| sink.abandoned.subscribe())
+    }
+
+    /// Replace the rules applied to events from now on; see the `rules`
+    /// module. Returns the keys whose value changed.
+    pub(crate) fn update_rules(
+        &self,
+        patch: TelemetryConfigPatch,
+    ) -> Result<Vec<&'static str>, Vec<TelemetryConfigError>> {
+        self.rules.update(patch)
     }
 
     pub(crate) async fn emit(
@@ -183,30 +1215,455 @@ impl AgentVisualizer {
         action: Value,
         state: Option<Value>,
     ) {
-        if let Some(tx) = &self.sender {
-            let timestamp_ms = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_else(|_| Duration::from_secs(0))
-                .as_millis();
-            let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
-            let event = VisualizerEvent {
-                sequence,
-                timestamp_ms,
-                conversation_id,
-                action_type: action_type.into(),
+        let queued = self.prepare(
+            conversation_id,
+            action_type.into(),
+            action,
+            Vec::new(),
+            state,
+            None,
+        );
+        self.send(queued).await;
+    }
+
+    /// Like [`AgentVisualizer::emit`] for an event that is dropped rather
+    /// than sent once it is older than `ttl`, such as a progress update a
+    /// later one supersedes.
+    pub(crate) async fn emit_with_ttl(
+        &self,
+        conversation_id: Option<ConversationId>,
+        action_type: impl Into<String>,
+        action: Value,
+        state: Option<Value>,
+        ttl: Duration,
+    ) {
+        let queued = self.prepare(
+            conversation_id,
+            action_type.into(),
+            action,
+            Vec::new(),
+            state,
+            Some(ttl),
+        );
+        self.send(queued).await;
+    }
+
+    /// Like [`AgentVisualizer::emit`] for actions carrying file contents,
+    /// which remote sinks only get for files inside the trusted roots.
+    pub(crate) async fn emit_scoped(
+        &self,
+        conversation_id: Option<ConversationId>,
+        action_type: impl Into<String>,
+        action: Value,
+        content: Vec<ContentField>,
+        state: Option<Value>,
+    ) {
+        let queued = self.prepare(
+            conversation_id,
+            action_type.into(),
+            action,
+            content,
+            state,
+            None,
+        );
+        self.send(queued).await;
+    }
+
+    /// Queue `queued`, waiting for room if the drop policy says so, and
+    /// then for its delivery under strict delivery.
+    async fn send(&self, queued: Option<Queued>) {
+        let (Some(queued), Some(sink)) = (queued, self.sink.as_ref()) else {
+            return;
+        };
+        if queued.durable {
+            if let Some(durable) = &self.durable
+                && let Err(err) = durable.append(&queued.event).await
+            {
+                report_unpersisted(
+                    self.strict.as_ref(),
+                    &queued.event.action_type,
+                    queued.event.sequence,
+                    &err,
+                );
+            }
+            return;
+        }
+        let accepted = sink.sender.send(queued.event).await;
+        let pending = self.queued(sink, queued.pending, accepted);
+        self.await_delivery(pending).await;
+    }
+
+    async fn await_delivery(&self, pending: Option<Pending>) {
+        let (Some(pending), Some(sink), Some(strict)) =
+            (pending, self.sink.as_ref(), self.strict.as_ref())
+        else {
+            return;
+        };
+        let mut delivered = sink.delivered.subscribe();
+        let _flushing = Flushing::new(&sink.flushes);
+        let waited = tokio::time::timeout(
+            strict.timeout,
+            delivered.wait_for(|done| *done > pending.sequence),
+        )
+        .await;
+        if !waited.is_ok_and(|waited| waited.is_ok()) {
+            strict.fail(format!(
+                "lifecycle event `{}` (sequence {}) was not delivered within {:?}",
+                pending.action_type, pending.sequence, strict.timeout
+            ));
+        }
+    }
+
+    /// Wait up to `timeout` for every event enqueued so far to be written to
+    /// the websocket. Returns `false` if one was dropped or is still
+    /// undelivered when the timeout expires, and `None` without a sink.
+    pub(crate) async fn flush(&self, timeout: Duration) -> Option<bool> {
+        let sink = self.sink.as_ref()?;
+        let mut delivered = sink.delivered.subscribe();
+        let through = sink.queued_through.load(Ordering::SeqCst);
+        let _flushing = Flushing::new(&sink.flushes);
+        let flushed = async {
+            let queued = delivered.wait_for(|done| *done >= through).await.is_ok();
+            if let Some(durable) = &self.durable {
+                durable.flushed().await;
+            }
+            queued
+        };
+        Some(
+            tokio::time::timeout(timeout, flushed)
+                .await
+                .unwrap_or(false),
+        )
+    }
+
+    /// Events the queue dropped or evicted so far; `0` without a sink.
+    #[cfg(any(test, feature = "failpoints"))]
+    pub(crate) fn dropped_events(&self) -> u64 {
+        self.sink.as_ref().map_or(0, |sink| sink.sender.dropped())
+    }
+
+    /// Payload of a `visualizer_events_dropped` summary, if events were
+    /// dropped since the last one: how many the queue dropped and how many
+    /// the forwarder dropped because their TTL ran out before they could be
+    /// sent, each also in total, and the sequence number the next event
+    /// will get, so a consumer can place the gap its sequence numbers
+    /// already show.
+    pub(crate) fn drop_summary(&self) -> Option<Value> {
+        let sink = self.sink.as_ref()?;
+        let total = sink.sender.dropped();
+        let reported = sink.reported_drops.swap(total, Ordering::Relaxed);
+        let stale = sink.forwarder.health().dropped_stale;
+        let reported_stale = sink.reported_stale.swap(stale, Ordering::Relaxed);
+        (total > reported || stale > reported_stale).then(|| {
+            json!({
+                "dropped": total - reported,
+                "droppedTotal": total,
+                "droppedStale": stale - reported_stale,
+                "droppedStaleTotal": stale,
+                "nextSequence": self.next_sequence(),
+            })
+        })
+    }
+
+    /// Payload of a `visualizer_events_sampled` summary, if events were
+    /// sampled away by action type since the last one: how many of each
+    /// type, and how many in all.
+    pub(crate) fn sampling_summary(&self) -> Option<Value> {
+        let sampled = self.rules.take_sampled_away();
+        (!sampled.is_empty()).then(|| {
+            json!({
+                "total": sampled.values().sum::<u64>(),
+                "sampled": sampled,
+            })
+        })
+    }
+
+    /// Events lost since the last gap taken, reported as `visualizer_gap`;
+    /// see the `gaps` module.
+    pub(crate) fn take_gap(&self) -> Option<Gap> {
+        self.gaps.take()
+    }
+
+    /// Changes each time the relay is reconnected to after losing events,
+    /// which is when a gap should be reported.
+    pub(crate) fn gap_recoveries(&self) -> watch::Receiver<u64> {
+        self.gaps.recoveries()
+    }
+
+    /// Sequence number the next emitted event will get, i.e. how many
+    /// events have been emitted so far.
+    pub(crate) fn next_sequence(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn status(&self) -> VisualizerStatus {
+        let Some(sink) = &self.sink else {
+            return VisualizerStatus::Disabled;
+        };
+        let health = sink.forwarder.health();
+        let abandoned = *sink.abandoned.borrow();
+        if let Some(mismatch) = health.pin_mismatch {
+            VisualizerStatus::PinRejected { mismatch }
+        } else if let Some(status) = health.auth_rejected {
+            VisualizerStatus::AuthRejected { status }
+        } else if let Some(connect_failures) = abandoned {
+            VisualizerStatus::Abandoned { connect_failures }
+        } else if health.failed {
+            VisualizerStatus::Failed {
+                panics: health.panics,
+            }
+        } else {
+            VisualizerStatus::Running {
+                recovered_panics: health.panics,
+                connect_latency: health.connect_latency,
+            }
+        }
+    }
+
+    /// Close the sink's queue and wait up to `timeout` for the forwarder to
+    /// deliver what is already queued and close its websocket; anything
+    /// emitted afterwards is dropped. Mirrors are closed alongside and get
+    /// the same time. Returns how many events the primary sink left
+    /// undelivered, and `None` without a sink. Dropping the last handle
+    /// closes the queues the same way without waiting, and the forwarders
+    /// still deliver what was queued and close their connections, for as
+    /// long as the runtime keeps running.
+    async fn shutdown(&self, timeout: Duration) -> Option<u64> {
+        let sink = self.sink.as_ref()?;
+        for sink in self.sinks() {
+            sink.sender.close();
+        }
+        let stopped = tokio::time::timeout(
+            timeout,
+            futures::future::join_all(self.sinks().map(|sink| sink.forwarder.stopped())),
+        )
+        .await
+        .is_ok();
+        let undelivered = sink
+            .queued_through
+            .load(Ordering::SeqCst)
+            .saturating_sub(*sink.delivered.borrow())
+            + self
+                .durable
+                .as_ref()
+                .map_or(0, |durable| durable.undelivered());
+        if undelivered > 0 {
+            warn!("visualizer shut down with {undelivered} events undelivered after {timeout:?}");
+        } else if !stopped {
+            debug!("visualizer websocket not closed within {timeout:?}");
+        }
+        Some(undelivered)
+    }
+
+    /// Apply the current rules, then record the event and hand it to the
+    /// sink without awaiting anything, so it can also be used from `Drop`.
+    /// Returns the lifecycle event to wait for under strict delivery.
+    fn enqueue(
+        &self,
+        conversation_id: Option<ConversationId>,
+        action_type: String,
+        action: Value,
+        content: Vec<ContentField>,
+        state: Option<Value>,
+    ) -> Option<Pending> {
+        let queued = self.prepare(conversation_id, action_type, action, content, state, None)?;
+        let sink = self.sink.as_ref()?;
+        if queued.durable {
+            let durable = self.durable.as_ref()?;
+            let strict = self.strict.clone();
+            let event = queued.event;
+            let (action_type, sequence) = (event.action_type.clone(), event.sequence);
+            durable.append_detached(&event, move |err| {
+                report_unpersisted(strict.as_ref(), &action_type, sequence, &err);
+            });
+            return None;
+        }
+        let accepted = sink.sender.push(queued.event);
+        self.queued(sink, queued.pending, accepted)
+    }
+
+    /// Apply the current rules and record the event, returning it if it
+    /// still has to be queued for the sink. `ttl` overrides the default.
+    fn prepare(
+        &self,
+        conversation_id: Option<ConversationId>,
+        action_type: String,
+        mut action: Value,
+        content: Vec<ContentField>,
+        mut state: Option<Value>,
+        ttl: Option<Duration>,
+    ) -> Option<Queued> {
+        let rules = self.rules.current();
+        if !rules.admits(&action_type, &action) {
+            return None;
+        }
+        rules.scrub(&mut action);
+        if let Some(state) = state.as_mut() {
+            rules.scrub(state);
+        }
+        let mut event = match conversation_id {
+            Some(id) => {
+                // Held while the global number is drawn too, so both orders
+                // agree.
+                let mut counters = self
+                    .conversation_sequences
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let mut event = build_event(
+                    &self.sequence,
+                    &self.clock,
+                    conversation_id,
+                    action_type,
+                    action,
+                    state,
+                );
+                let next = counters.entry(id).or_default();
+                event.conversation_sequence = Some(*next);
+                *next += 1;
+                event
+            }
+            None => build_event(
+                &self.sequence,
+                &self.clock,
+                None,
+                action_type,
                 action,
                 state,
-            };
-            if tx.send(event).await.is_err() {
-                debug!("visualizer channel dropped; disabling event stream");
+            ),
+        };
+        event.content = content;
+        event.ttl = ttl.or(self.default_ttl);
+        if event.conversation_id.is_some() {
+            event.fork_suffix = self.fork_suffix.clone();
+        }
+        self.record_recent(&event);
+        let sink = self.sink.as_ref()?;
+        // Mirrors never wait, so none of them can hold up the others.
+        for mirror in &self.mirrors {
+            if mirror.sender.push(event.clone()) {
+                mirror.forwarder.ensure_running();
             }
         }
+        let lifecycle = strict::is_lifecycle(&event);
+        if lifecycle && self.durable.is_some() {
+            // Once on disk the event survives a crash, which is all strict
+            // delivery asks of it.
+            return Some(Queued {
+                event,
+                pending: None,
+                durable: true,
+            });
+        }
+        sink.queued_through
+            .fetch_max(event.sequence + 1, Ordering::SeqCst);
+        let strict = self
+            .strict
+            .as_ref()
+            .filter(|strict| !strict.has_failed() && lifecycle);
+        let pending = strict.map(|_| Pending {
+            sequence: event.sequence,
+            action_type: event.action_type.clone(),
+        });
+        // A relay given up on gets nothing more queued for it.
+        if sink.abandoned.borrow().is_some() {
+            return None;
+        }
+        Some(Queued {
+            event,
+            pending,
+            durable: false,
+        })
+    }
+
+    /// Account for an event the queue `accepted` or dropped. Returns the
+    /// lifecycle event to wait for under strict delivery.
+    fn queued(&self, sink: &Sink, pending: Option<Pending>, accepted: bool) -> Option<Pending> {
+        if accepted {
+            sink.forwarder.ensure_running();
+            return pending;
+        }
+        if let (Some(strict), Some(pending)) = (&self.strict, pending) {
+            strict.fail(format!(
+                "lifecycle event `{}` (sequence {}) was dropped",
+                pending.action_type, pending.sequence
+            ));
+        }
+        None
+    }
+
+    fn record_recent(&self, event: &VisualizerEvent) {
+        let Ok(mut recent) = self.recent.lock() else {
+            return;
+        };
+        self.retention
+            .record(&mut recent, event, RECENT_EVENTS_LIMIT);
+    }
+
+    /// Snapshot of the last [`RECENT_EVENTS_LIMIT`] emitted events that the
+    /// retention rules still allow, oldest first, whether or not a websocket
+    /// sink is configured.
+    pub(crate) fn recent_events(&self) -> Vec<VisualizerEvent> {
+        let now_ms = now_ms();
+        self.recent
+            .lock()
+            .map(|recent| {
+                recent
+                    .iter()
+                    .filter(|event| !self.retention.expired(event, now_ms))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The recent events matching `query`, oldest first; only those are
+    /// cloned out of the ring.
+    #[cfg(test)]
+    pub(crate) fn query_recent(&self, query: &EventQuery) -> Vec<VisualizerEvent> {
+        self.query_recent_where(query, |_| true)
+    }
+
+    /// Matching events are transformed like for a remote sink, since the
+    /// client asking may not be on this host.
+    fn query_recent_where(
+        &self,
+        query: &EventQuery,
+        keep: impl Fn(&VisualizerEvent) -> bool,
+    ) -> Vec<VisualizerEvent> {
+        let now_ms = now_ms();
+        let limit = match query.limit {
+            0 => usize::MAX,
+            limit => limit,
+        };
+        let Ok(recent) = self.recent.lock() else {
+            return Vec::new();
+        };
+        let mut events: Vec<VisualizerEvent> = recent
+            .iter()
+            .rev()
+            .filter(|event| {
+                query::matches(query, event)
+                    && keep(event)
+                    && !self.retention.expired(event, now_ms)
+            })
+            .take(limit)
+            .map(|event| self.query_transform.apply(event).into_owned())
+            .collect();
+        events.reverse();
+        events
     }
 }
 
 impl Default for AgentVisualizer {
     fn default() -> Self {
-        Self::new(None)
+        Self::new(
+            None,
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
     }
 }
 
@@ -215,6 +1672,7 @@ impl SessionVisualizer {
         Self {
             inner,
             conversation_id,
+            closed: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -224,8 +1682,375 @@ impl SessionVisualizer {
         action: Value,
         state: Option<Value>,
     ) {
+        if self.is_closed() {
+            return;
+        }
         self.inner
             .emit(Some(self.conversation_id), action_type, action, state)
             .await;
     }
+
+    /// See [`AgentVisualizer::emit_with_ttl`].
+    pub(crate) async fn emit_with_ttl(
+        &self,
+        action_type: impl Into<String>,
+        action: Value,
+        state: Option<Value>,
+        ttl: Duration,
+    ) {
+        if self.is_closed() {
+            return;
+        }
+        self.inner
+            .emit_with_ttl(Some(self.conversation_id), action_type, action, state, ttl)
+            .await;
+    }
+
+    /// See [`AgentVisualizer::emit_scoped`].
+    pub(crate) async fn emit_scoped(
+        &self,
+        action_type: impl Into<String>,
+        action: Value,
+        content: Vec<ContentField>,
+        state: Option<Value>,
+    ) {
+        if self.is_closed() {
+            return;
+        }
+        self.inner
+            .emit_scoped(
+                Some(self.conversation_id),
+                action_type,
+                action,
+                content,
+                state,
+            )
+            .await;
+    }
+
+    pub(crate) fn enqueue(&self, action_type: impl Into<String>, action: Value) {
+        if self.is_closed() {
+            return;
+        }
+        // Cannot wait here; a drop still counts as a strict failure.
+        self.inner.enqueue(
+            Some(self.conversation_id),
+            action_type.into(),
+            action,
+            Vec::new(),
+            None,
+        );
+    }
+
+    /// See [`AgentVisualizer::flush`].
+    pub(crate) async fn flush(&self, timeout: Duration) -> Option<bool> {
+        self.inner.flush(timeout).await
+    }
+
+    /// See [`AgentVisualizer::telemetry_failures`].
+    pub(crate) fn telemetry_failures(&self) -> Option<watch::Receiver<Option<String>>> {
+        self.inner.telemetry_failures()
+    }
+
+    /// See [`AgentVisualizer::update_rules`].
+    pub(crate) fn update_rules(
+        &self,
+        patch: TelemetryConfigPatch,
+    ) -> Result<Vec<&'static str>, Vec<TelemetryConfigError>> {
+        self.inner.update_rules(patch)
+    }
+
+    /// See [`AgentVisualizer::next_sequence`].
+    pub(crate) fn next_sequence(&self) -> u64 {
+        self.inner.next_sequence()
+    }
+
+    /// See [`AgentVisualizer::status`].
+    pub(crate) fn status(&self) -> VisualizerStatus {
+        self.inner.status()
+    }
+
+    /// See [`AgentVisualizer::abandonment`].
+    pub(crate) fn abandonment(&self) -> Option<watch::Receiver<Option<u32>>> {
+        self.inner.abandonment()
+    }
+
+    /// See [`AgentVisualizer::drop_summary`].
+    pub(crate) fn drop_summary(&self) -> Option<Value> {
+        self.inner.drop_summary()
+    }
+
+    /// See [`AgentVisualizer::sampling_summary`].
+    pub(crate) fn sampling_summary(&self) -> Option<Value> {
+        self.inner.sampling_summary()
+    }
+
+    /// See [`AgentVisualizer::take_gap`].
+    pub(crate) fn take_gap(&self) -> Option<Gap> {
+        self.inner.take_gap()
+    }
+
+    /// See [`AgentVisualizer::gap_recoveries`].
+    pub(crate) fn gap_recoveries(&self) -> watch::Receiver<u64> {
+        self.inner.gap_recoveries()
+    }
+
+    /// Stop emitting for this session and wait up to `timeout` for the
+    /// forwarder to drain and exit; see [`AgentVisualizer::shutdown`].
+    /// Events emitted afterwards are not even kept in the recent-event ring,
+    /// so the last event before closing stays the session's last event.
+    pub(crate) async fn shutdown(&self, timeout: Duration) -> Option<u64> {
+        self.closed.store(true, Ordering::SeqCst);
+        self.inner.shutdown(timeout).await
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Recent events belonging to this session.
+    pub(crate) fn recent_events(&self) -> Vec<VisualizerEvent> {
+        let mut events = self.inner.recent_events();
+        events.retain(|event| event.conversation_id == Some(self.conversation_id));
+        events
+    }
+
+    /// Recent events of this session matching `query`.
+    pub(crate) fn query_recent(&self, query: &EventQuery) -> Vec<VisualizerEvent> {
+        self.inner.query_recent_where(query, |event| {
+            event.conversation_id == Some(self.conversation_id)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn failures() -> SerializationFailures {
+        SerializationFailures::new(
+            Arc::new(DiagnosticsState::default()),
+            Arc::new(AtomicU64::new(0)),
+            EventClock::new(),
+            TimestampEncoding::Number,
+        )
+    }
+
+    #[test]
+    fn log_file_is_the_primary_sink_only_without_a_relay() {
+        let log_file = || Some("file:///tmp/events.ndjson".to_string());
+        assert_eq!(
+            (
+                endpoint_urls(None, None, log_file()),
+                endpoint_urls(
+                    Some("ws://relay, stdout".to_string()),
+                    Some(" ws://mirror ,".to_string()),
+                    log_file(),
+                ),
+            ),
+            (
+                vec!["file:///tmp/events.ndjson".to_string()],
+                vec![
+                    "ws://relay".to_string(),
+                    "stdout".to_string(),
+                    "ws://mirror".to_string(),
+                    "file:///tmp/events.ndjson".to_string(),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn serialization_failures_are_rate_limited_per_action_type() {
+        let mut failures = failures();
+        let start = Instant::now();
+
+        assert_eq!(
+            failures.record("exec_output", start),
+            SerializationFailure {
+                count: 1,
+                first: true,
+                should_log: true,
+            }
+        );
+        assert_eq!(
+            failures.record("exec_output", start + Duration::from_secs(30)),
+            SerializationFailure {
+                count: 2,
+                first: false,
+                should_log: false,
+            }
+        );
+        // A different action type has its own budget.
+        assert_eq!(
+            failures.record("task_spawned", start + Duration::from_secs(31)),
+            SerializationFailure {
+                count: 1,
+                first: true,
+                should_log: true,
+            }
+        );
+        assert_eq!(
+            failures.record("exec_output", start + SERIALIZATION_ERROR_LOG_INTERVAL),
+            SerializationFailure {
+                count: 3,
+                first: false,
+                should_log: true,
+            }
+        );
+
+        let counts = failures
+            .diagnostics
+            .serialization_failures
+            .lock()
+            .expect("lock")
+            .clone();
+        assert_eq!(
+            counts,
+            BTreeMap::from([
+                ("exec_output".to_string(), 3),
+                ("task_spawned".to_string(), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn lossy_payload_replaces_only_the_failing_fields() {
+        let event = build_event(
+            &AtomicU64::new(3),
+            &EventClock::new(),
+            None,
+            "custom".to_string(),
+            json!({
+                "subId": "sub-1",
+                "output": { "stdout": "poison", "exitCode": 0 },
+                "lines": ["fine", "poison"],
+            }),
+            Some(json!({ "activeTasks": [], "last": "poison" })),
+        );
+        // Anything holding "poison" fails, as its parents do.
+        let rejects_poison = |value: &Value| -> serde_json::Result<()> {
+            if value.to_string().contains("poison") {
+                Err(serde::ser::Error::custom("unserializable"))
+            } else {
+                Ok(())
+            }
+        };
+
+        let parsed: Value = serde_json::from_str(&lossy_payload_with(
+            &event,
+            TimestampEncoding::Number,
+            &rejects_poison,
+        ))
+        .expect("valid json");
+        let sentinel = json!({ "lossy": true, "serializationError": "unserializable" });
+        assert_eq!(parsed["sequence"], json!(3));
+        assert_eq!(
+            parsed["action"],
+            json!({
+                "subId": "sub-1",
+                "output": { "stdout": sentinel, "exitCode": 0 },
+                "lines": ["fine", sentinel],
+            })
+        );
+        assert_eq!(
+            parsed["state"],
+            json!({ "activeTasks": [], "last": sentinel })
+        );
+    }
+
+    #[test]
+    fn lossy_payload_keeps_an_event_that_serializes() {
+        let event = build_event(
+            &AtomicU64::new(3),
+            &EventClock::new(),
+            None,
+            "custom".to_string(),
+            json!({ "big": "payload" }),
+            Some(json!({ "activeTasks": [] })),
+        );
+
+        assert_eq!(
+            lossy_payload(&event, TimestampEncoding::Number),
+            serde_json::to_string(&WireEvent::new(&event, TimestampEncoding::Number))
+                .expect("serialize")
+        );
+    }
+
+    #[tokio::test]
+    async fn each_conversation_numbers_its_own_events_from_zero() {
+        let agent = AgentVisualizer::default();
+        let first = SessionVisualizer::new(agent.clone(), ConversationId::new());
+        let second = SessionVisualizer::new(agent.clone(), ConversationId::new());
+
+        first.emit("a", json!({}), None).await;
+        second.emit("b", json!({}), None).await;
+        agent.emit(None, "agent", json!({}), None).await;
+        first.emit("a", json!({}), None).await;
+        second.emit("b", json!({}), None).await;
+        second.emit("b", json!({}), None).await;
+
+        let numbered: Vec<(String, u64, Option<u64>)> = agent
+            .recent_events()
+            .into_iter()
+            .map(|event| {
+                (
+                    event.action_type,
+                    event.sequence,
+                    event.conversation_sequence,
+                )
+            })
+            .collect();
+        assert_eq!(
+            numbered,
+            vec![
+                ("a".to_string(), 0, Some(0)),
+                ("b".to_string(), 1, Some(0)),
+                ("agent".to_string(), 2, None),
+                ("a".to_string(), 3, Some(1)),
+                ("b".to_string(), 4, Some(1)),
+                ("b".to_string(), 5, Some(2)),
+            ]
+        );
+        let wire = serde_json::to_value(&agent.recent_events()[4]).expect("serialize");
+        assert_eq!(wire["conversationSequence"], json!(1));
+    }
+
+    #[tokio::test]
+    async fn monotonic_time_never_goes_back_with_the_wall_clock() {
+        static WALL_READS: AtomicU64 = AtomicU64::new(0);
+        // Set back a second on every read.
+        fn receding_wall_clock() -> u128 {
+            let reads = WALL_READS.fetch_add(1, Ordering::SeqCst);
+            1_700_000_000_000 - u128::from(reads) * 1_000
+        }
+        let agent = AgentVisualizer::default().with_wall_clock(receding_wall_clock);
+        let session = SessionVisualizer::new(agent.clone(), ConversationId::new());
+
+        for batch in 0..5 {
+            for _ in 0..10 {
+                session.emit("tick", json!({}), None).await;
+                agent.emit(None, "tick", json!({}), None).await;
+            }
+            if batch < 4 {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        let events = agent.recent_events();
+        assert_eq!(events.len(), 100);
+        for pair in events.windows(2) {
+            assert!(pair[0].timestamp_ms > pair[1].timestamp_ms);
+            assert!(
+                pair[0].monotonic_ms <= pair[1].monotonic_ms,
+                "{} then {}",
+                pair[0].monotonic_ms,
+                pair[1].monotonic_ms
+            );
+        }
+        assert!(events[99].monotonic_ms >= events[0].monotonic_ms + 20);
+        let wire = serde_json::to_value(&events[99]).expect("serialize");
+        assert_eq!(wire["monotonicMs"], json!(events[99].monotonic_ms));
+    }
 }
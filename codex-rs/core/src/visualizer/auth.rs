@@ -0,0 +1,348 @@
+//! Bearer-token authentication to the visualizer relay.
+//!
+//! When `CODEX_VISUALIZER_TOKEN` is set, every websocket handshake, and
+//! every POST of the `http` transport, carries it, in an `Authorization: Bearer <token>` header or, with
+//! `visualizer_token_placement = "query"`, as a `token` query parameter.
+//! The token is only added to the request, so it never shows up
+//! in the URLs the forwarders log, and [`RelayToken`] debug-prints as
+//! `[REDACTED]`. Every reconnect sends it again. A relay answering the
+//! handshake with 401 or 403 refused the token, which no retry can fix, so
+//! the forwarders stop for good, as for a pin mismatch. `file://` sinks
+//! ignore the token.
+
+use std::fmt;
+use std::io;
+
+use tokio_tungstenite::tungstenite::Error;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+
+use super::with_query_pair;
+use crate::config_types::VisualizerTokenPlacement;
+
+/// The token the relay expects, if any.
+pub(super) fn relay_token_from_env() -> Option<String> {
+    std::env::var("CODEX_VISUALIZER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// A token and where the handshake carries it.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct RelayToken {
+    token: String,
+    placement: VisualizerTokenPlacement,
+}
+
+impl RelayToken {
+    pub(crate) fn new(token: String, placement: VisualizerTokenPlacement) -> Self {
+        Self { token, placement }
+    }
+}
+
+impl fmt::Debug for RelayToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RelayToken")
+            .field("token", &"[REDACTED]")
+            .field("placement", &self.placement)
+            .finish()
+    }
+}
+
+/// The handshake request for `url`, carrying `token` if there is one.
+pub(super) fn handshake_request(
+    url: &str,
+    token: Option<&RelayToken>,
+) -> Result<Request, Box<Error>> {
+    let Some(token) = token else {
+        return Ok(url.into_client_request()?);
+    };
+    match token.placement {
+        VisualizerTokenPlacement::Header => {
+            let mut request = url.into_client_request()?;
+            let value = HeaderValue::try_from(format!("Bearer {}", token.token))
+                .map_err(|err| Error::HttpFormat(err.into()))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+            Ok(request)
+        }
+        VisualizerTokenPlacement::Query => Ok(with_query_pair(url, "token", &token.token)
+            .map_err(|err| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, err)))?
+            .into_client_request()?),
+    }
+}
+
+/// A POST of events to `url`, carrying `token` if there is one.
+pub(super) fn post_request(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&RelayToken>,
+) -> Result<reqwest::RequestBuilder, url::ParseError> {
+    let Some(token) = token else {
+        return Ok(client.post(url));
+    };
+    Ok(match token.placement {
+        VisualizerTokenPlacement::Header => client.post(url).bearer_auth(&token.token),
+        VisualizerTokenPlacement::Query => {
+            client.post(with_query_pair(url, "token", &token.token)?)
+        }
+    })
+}
+
+/// The status of a handshake the relay refused for its credentials.
+pub(super) fn auth_rejected(err: &Error) -> Option<u16> {
+    match err {
+        Error::Http(response)
+            if matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) =>
+        {
+            Some(response.status().as_u16())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::tungstenite::handshake::server::ErrorResponse;
+    use tokio_tungstenite::tungstenite::handshake::server::Request as ServerRequest;
+    use tokio_tungstenite::tungstenite::handshake::server::Response;
+    use tokio_tungstenite::tungstenite::http;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::VisualizerStatus;
+    use crate::visualizer::hello;
+
+    /// The `Authorization` header and query a handshake arrived with.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Handshake {
+        authorization: Option<String>,
+        query: Option<String>,
+    }
+
+    impl Handshake {
+        fn of(request: &ServerRequest) -> Self {
+            Self {
+                authorization: request
+                    .headers()
+                    .get(AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                query: request.uri().query().map(str::to_string),
+            }
+        }
+    }
+
+    /// What the relay saw, in order.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Seen {
+        Handshake(Handshake),
+        /// The sequence of an event frame.
+        Frame(u64),
+    }
+
+    /// Which handshakes the relay accepts.
+    #[derive(Clone, Copy)]
+    enum Gate {
+        Open,
+        /// Refuse every handshake with this status.
+        Reject(u16),
+        /// Refuse handshakes without this bearer token with 401, and close
+        /// every connection after one frame, so each event needs a new
+        /// handshake.
+        Require(&'static str),
+    }
+
+    impl Gate {
+        fn answer(
+            self,
+            handshake: &Handshake,
+            response: Response,
+        ) -> Result<Response, ErrorResponse> {
+            let status = match self {
+                Gate::Open => None,
+                Gate::Reject(status) => Some(status),
+                Gate::Require(token) => {
+                    let expected = format!("Bearer {token}");
+                    (handshake.authorization.as_deref() != Some(expected.as_str())).then_some(401)
+                }
+            };
+            match status {
+                Some(status) => Err(http::Response::builder()
+                    .status(status)
+                    .body(None)
+                    .expect("response")),
+                None => Ok(response),
+            }
+        }
+    }
+
+    async fn relay(gate: Gate) -> (String, mpsc::UnboundedReceiver<Seen>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let callback = |request: &ServerRequest, response: Response| {
+                        let handshake = Handshake::of(request);
+                        let answer = gate.answer(&handshake, response);
+                        let _ = tx.send(Seen::Handshake(handshake));
+                        answer
+                    };
+                    let Ok(mut ws) = accept_hdr_async(socket, callback).await else {
+                        return;
+                    };
+                    while let Some(Ok(message)) = ws.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let frame: Value = serde_json::from_str(&text).expect("json");
+                        if hello::is_hello(&frame) {
+                            continue;
+                        }
+                        let _ = tx.send(Seen::Frame(frame["sequence"].as_u64().expect("sequence")));
+                        if let Gate::Require(_) = gate {
+                            let _ = ws.close(None).await;
+                        }
+                    }
+                });
+            }
+        });
+        (format!("ws://{addr}"), rx)
+    }
+
+    fn visualizer(url: String) -> AgentVisualizer {
+        AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+    }
+
+    async fn next_seen(seen: &mut mpsc::UnboundedReceiver<Seen>) -> Seen {
+        tokio::time::timeout(Duration::from_secs(5), seen.recv())
+            .await
+            .expect("relay saw something before timeout")
+            .expect("relay running")
+    }
+
+    #[tokio::test]
+    async fn token_is_sent_where_configured() {
+        for (placement, expected) in [
+            (
+                VisualizerTokenPlacement::Header,
+                Handshake {
+                    authorization: Some("Bearer s3cret".to_string()),
+                    query: Some("role=producer".to_string()),
+                },
+            ),
+            (
+                VisualizerTokenPlacement::Query,
+                Handshake {
+                    authorization: None,
+                    query: Some("role=producer&token=s3cret".to_string()),
+                },
+            ),
+        ] {
+            let (url, mut seen) = relay(Gate::Open).await;
+            let visualizer =
+                visualizer(url).with_relay_token(RelayToken::new("s3cret".to_string(), placement));
+
+            visualizer.emit(None, "tick", json!({}), None).await;
+            assert_eq!(next_seen(&mut seen).await, Seen::Handshake(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_token_stops_delivery_without_retrying() {
+        let (url, mut seen) = relay(Gate::Reject(401)).await;
+        let visualizer = visualizer(url).with_relay_token(RelayToken::new(
+            "expired".to_string(),
+            VisualizerTokenPlacement::Header,
+        ));
+
+        visualizer.emit(None, "tick", json!({}), None).await;
+        next_seen(&mut seen).await;
+        let status = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match visualizer.status() {
+                    VisualizerStatus::Running { .. } => {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    status => return status,
+                }
+            }
+        })
+        .await
+        .expect("forwarder stops");
+        assert_eq!(status, VisualizerStatus::AuthRejected { status: 401 });
+
+        visualizer.emit(None, "tick", json!({}), None).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), seen.recv())
+                .await
+                .is_err(),
+            "no further handshake after the rejection"
+        );
+    }
+
+    #[tokio::test]
+    async fn every_reconnect_is_authenticated() {
+        let (url, mut seen) = relay(Gate::Require("s3cret")).await;
+        let visualizer = visualizer(url).with_relay_token(RelayToken::new(
+            "s3cret".to_string(),
+            VisualizerTokenPlacement::Header,
+        ));
+        let authorized = || {
+            Seen::Handshake(Handshake {
+                authorization: Some("Bearer s3cret".to_string()),
+                query: Some("role=producer".to_string()),
+            })
+        };
+
+        visualizer.emit(None, "tick", json!({}), None).await;
+        let mut observed = vec![next_seen(&mut seen).await, next_seen(&mut seen).await];
+        // The relay closed the connection; the forwarder opens the next one.
+        observed.push(next_seen(&mut seen).await);
+        visualizer.emit(None, "tick", json!({}), None).await;
+        observed.push(next_seen(&mut seen).await);
+        assert_eq!(
+            observed,
+            vec![authorized(), Seen::Frame(0), authorized(), Seen::Frame(1)]
+        );
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+    }
+
+    #[test]
+    fn debug_output_redacts_the_token() {
+        let token = RelayToken::new("s3cret".to_string(), VisualizerTokenPlacement::Header);
+        assert_eq!(
+            format!("{token:?}"),
+            "RelayToken { token: \"[REDACTED]\", placement: Header }"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Batching of events into one frame (`visualizer_batch`).
+//!
+//! With batching on, the forwarder no longer writes each event as soon as
+//! it is queued. It takes the first event, keeps taking events for up to
+//! [`EventBatching::interval`] or until it holds
+//! [`EventBatching::max_events`], and writes them as a single text frame
+//! holding a JSON array of the events, each exactly as it would have been
+//! written on its own. A zero interval waits for nothing and takes only
+//! what is already queued, so a burst that queued up behind a send goes out
+//! as one frame while a lone event is written at once. A closed queue, and
+//! so a graceful shutdown, sends the batch at once, as do
+//! `AgentVisualizer::flush` and a lifecycle event waiting on strict
+//! delivery.
+//!
+//! A batch whose array would exceed [`EventBatching::max_bytes`] is split
+//! into several array frames, and an event on its own in a frame is
+//! written as the plain object it would be without batching, so consumers
+//! tell the two apart by the frame being an array. Events keep their queue
+//! order within and across frames, so sequence numbers only ever increase.
+//! A batch holding an event sent as `event_chunk` frames (see the `chunks`
+//! module) is written frame by frame instead, since a run of chunks has to
+//! stay contiguous.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use super::VisualizerEvent;
+
+/// When the forwarder writes a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EventBatching {
+    /// How long after its first event a batch is sent.
+    pub(crate) interval: Duration,
+    /// Events after which a batch is sent before `interval` is up.
+    pub(crate) max_events: usize,
+    /// Largest array frame a batch is written as; a batch that would be
+    /// larger is split.
+    pub(crate) max_bytes: usize,
+}
+
+/// Held while waiting for delivery, and until dropped makes the forwarder
+/// send its batch without waiting out the interval.
+pub(super) struct Flushing<'a>(&'a watch::Sender<usize>);
+
+impl<'a> Flushing<'a> {
+    pub(super) fn new(flushes: &'a watch::Sender<usize>) -> Self {
+        flushes.send_modify(|waiting| *waiting += 1);
+        Self(flushes)
+    }
+}
+
+impl Drop for Flushing<'_> {
+    fn drop(&mut self) {
+        self.0
+            .send_modify(|waiting| *waiting = waiting.saturating_sub(1));
+    }
+}
+
+/// The frames a batch is written as, given the frames of each of its
+/// events in order, with no array frame over `max_bytes`.
+pub(super) fn batch_frames(events: Vec<Vec<String>>, max_bytes: usize) -> Vec<String> {
+    if events.len() < 2 || events.iter().any(|frames| frames.len() != 1) {
+        return events.into_iter().flatten().collect();
+    }
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut group_bytes = 0;
+    for event in events.into_iter().flatten() {
+        // Brackets, and a comma per event after the first.
+        let added = event.len() + 1;
+        match groups.last_mut() {
+            Some(group) if group_bytes + added < max_bytes => {
+                group_bytes += added;
+                group.push(event);
+            }
+            _ => {
+                group_bytes = added;
+                groups.push(vec![event]);
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .map(|group| match <[String; 1]>::try_from(group) {
+            Ok([event]) => event,
+            Err(group) => format!("[{}]", group.join(",")),
+        })
+        .collect()
+}
+
+/// How log lines name the events of `batch`.
+pub(super) fn describe(batch: &[VisualizerEvent]) -> String {
+    match batch {
+        [] => "no visualizer events".to_string(),
+        [event] => format!("visualizer event {}", event.sequence),
+        [first, .., last] => format!(
+            "{} visualizer events {} to {}",
+            batch.len(),
+            first.sequence,
+            last.sequence
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn frames(frames: &[&str]) -> Vec<String> {
+        frames.iter().map(|frame| frame.to_string()).collect()
+    }
+
+    #[test]
+    fn single_frame_events_share_one_array_frame() {
+        let batched = batch_frames(
+            vec![
+                frames(&[r#"{"sequence":0}"#]),
+                frames(&[r#"{"sequence":1}"#]),
+            ],
+            usize::MAX,
+        );
+        assert_eq!(batched, frames(&[r#"[{"sequence":0},{"sequence":1}]"#]));
+        let events: Vec<serde_json::Value> =
+            serde_json::from_str(&batched[0]).expect("batch is a json array");
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn lone_and_chunked_events_are_written_as_is() {
+        assert_eq!(
+            batch_frames(vec![frames(&["a"])], usize::MAX),
+            frames(&["a"])
+        );
+        assert_eq!(
+            batch_frames(vec![frames(&["a"]), frames(&["b1", "b2"])], usize::MAX),
+            frames(&["a", "b1", "b2"])
+        );
+    }
+
+    #[test]
+    fn batches_over_max_bytes_are_split_in_order() {
+        let events = ["aa", "bb", "cc", "dddddddd", "ee"]
+            .into_iter()
+            .map(|event| frames(&[event]))
+            .collect();
+        // `[aa,bb,cc]` is 10 bytes; `dddddddd` fits no array with another.
+        assert_eq!(
+            batch_frames(events, 10),
+            frames(&["[aa,bb,cc]", "dddddddd", "ee"])
+        );
+    }
+}
@@ -0,0 +1,780 @@
+//! Adaptive queue between visualizer producers and the websocket forwarder.
+//!
+//! The queue starts at a base capacity, temporarily doubles (up to a hard
+//! maximum and a byte budget) when a burst would otherwise overflow it, and
+//! shrinks back once it has been drained and idle for a while; a configured
+//! capacity replaces both sizes and so turns growth off. What happens
+//! to an event that still does not fit is up to the
+//! [`VisualizerDropPolicy`]: by default it is dropped, `drop-oldest` evicts
+//! the oldest queued events that are not task lifecycle events to make room
+//! for it, and `block` makes an emit that can wait do so until the
+//! forwarder has made room. Every event dropped or evicted is counted.
+//! Under strict delivery, the last slots of the queue are reserved for
+//! lifecycle events, so a burst of other events cannot crowd them out.
+//! For a relay that replays (see the `replay` module), every event dropped
+//! or evicted is also kept to replay on the next connect.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde_json::Value;
+use tokio::sync::Notify;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::debug;
+
+use super::DiagnosticsState;
+use super::VisualizerEvent;
+use super::replay::ReplayBuffer;
+use super::strict;
+use crate::config_types::VisualizerDropPolicy;
+
+/// Rough per-event overhead for envelope fields (sequence, timestamp, ids).
+const EVENT_ENVELOPE_BYTES: usize = 96;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BufferConfig {
+    pub(super) base_capacity: usize,
+    pub(super) max_capacity: usize,
+    /// Upper bound on the estimated JSON size of all queued events.
+    pub(super) byte_budget: usize,
+    /// Growth is only allowed this long after the first overflow of a burst;
+    /// overflow that persists past it is a sustained rate, not a burst.
+    pub(super) burst_window: Duration,
+    /// How long the queue must sit drained before it returns to
+    /// `base_capacity`.
+    pub(super) idle_shrink_after: Duration,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            base_capacity: 256,
+            max_capacity: 4096,
+            byte_budget: 16 * 1024 * 1024,
+            burst_window: Duration::from_secs(5),
+            idle_shrink_after: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Buffer counters surfaced through [`DiagnosticsState`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct BufferDiagnostics {
+    pub(super) resizes: u64,
+    pub(super) dropped: u64,
+}
+
+struct QueueState {
+    events: VecDeque<(VisualizerEvent, usize)>,
+    bytes: usize,
+    capacity: usize,
+    /// `BufferConfig::base_capacity` and `max_capacity`, unless a fixed
+    /// capacity was set.
+    base_capacity: usize,
+    max_capacity: usize,
+    /// First overflow of the current burst, which ends once the queue
+    /// drains.
+    burst_started: Option<Instant>,
+    drained_at: Option<Instant>,
+    closed: bool,
+    drop_policy: VisualizerDropPolicy,
+    /// Slots only lifecycle events may take; at most half the capacity.
+    lifecycle_reserve: usize,
+}
+
+/// Outcome of offering an event to the queue.
+enum Offer {
+    Queued,
+    Dropped,
+    /// No room under [`VisualizerDropPolicy::Block`]; the event is handed
+    /// back to wait with.
+    Full(Box<VisualizerEvent>),
+}
+
+pub(super) struct EventQueue {
+    config: BufferConfig,
+    state: Mutex<QueueState>,
+    notify: Notify,
+    /// Woken whenever an event leaves the queue or it closes, for emits
+    /// blocked on a full queue.
+    room: Notify,
+    diagnostics: Arc<DiagnosticsState>,
+    /// Where dropped events are kept for the next relay to connect.
+    replay: Option<Arc<Mutex<ReplayBuffer>>>,
+}
+
+impl EventQueue {
+    pub(super) fn new(config: BufferConfig, diagnostics: Arc<DiagnosticsState>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(QueueState {
+                events: VecDeque::with_capacity(config.base_capacity),
+                bytes: 0,
+                capacity: config.base_capacity,
+                base_capacity: config.base_capacity,
+                max_capacity: config.max_capacity,
+                burst_started: None,
+                drained_at: None,
+                closed: false,
+                drop_policy: VisualizerDropPolicy::default(),
+                lifecycle_reserve: 0,
+            }),
+            notify: Notify::new(),
+            room: Notify::new(),
+            diagnostics,
+            replay: None,
+        }
+    }
+
+    /// Keep every event dropped or evicted in `replay`.
+    pub(super) fn with_replay(self, replay: Arc<Mutex<ReplayBuffer>>) -> Self {
+        Self {
+            replay: Some(replay),
+            ..self
+        }
+    }
+
+    pub(super) fn set_drop_policy(&self, policy: VisualizerDropPolicy) {
+        if let Ok(mut state) = self.state.lock() {
+            state.drop_policy = policy;
+        }
+    }
+
+    /// Keep `slots` of the queue free for lifecycle events; other events
+    /// are refused, or evict and wait, as if the queue were that much
+    /// smaller.
+    pub(super) fn reserve_for_lifecycle(&self, slots: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.lifecycle_reserve = slots;
+        }
+    }
+
+    /// Hold exactly `capacity` events from now on, without growing for
+    /// bursts.
+    pub(super) fn set_capacity(&self, capacity: usize) {
+        let capacity = capacity.max(1);
+        if let Ok(mut state) = self.state.lock() {
+            state.capacity = capacity;
+            state.base_capacity = capacity;
+            state.max_capacity = capacity;
+        }
+    }
+
+    /// Enqueue an event without waiting. Returns `false` if the event was
+    /// dropped because the queue is closed or out of room; under
+    /// [`VisualizerDropPolicy::Block`] it is dropped like the newest event.
+    pub(super) fn push(&self, event: VisualizerEvent) -> bool {
+        self.push_at(event, Instant::now())
+    }
+
+    fn push_at(&self, event: VisualizerEvent, now: Instant) -> bool {
+        matches!(self.offer(event, now, false), Offer::Queued)
+    }
+
+    /// Enqueue an event, waiting for room under
+    /// [`VisualizerDropPolicy::Block`]. Returns `false` if the event was
+    /// dropped.
+    pub(super) async fn send(&self, mut event: VisualizerEvent) -> bool {
+        loop {
+            let room = self.room.notified();
+            match self.offer(event, Instant::now(), true) {
+                Offer::Queued => return true,
+                Offer::Dropped => return false,
+                Offer::Full(returned) => {
+                    event = *returned;
+                    room.await;
+                }
+            }
+        }
+    }
+
+    fn offer(&self, event: VisualizerEvent, now: Instant, can_wait: bool) -> Offer {
+        let size = approx_event_size(&event);
+        let lifecycle = strict::is_lifecycle(&event);
+        let Ok(mut state) = self.state.lock() else {
+            return Offer::Dropped;
+        };
+        if state.closed {
+            return Offer::Dropped;
+        }
+        self.maybe_shrink(&mut state, now);
+
+        let mut evicted = Vec::new();
+        let refused = loop {
+            let Err(why) = self.make_room(&mut state, size, lifecycle, now) else {
+                break None;
+            };
+            match state.drop_policy {
+                VisualizerDropPolicy::DropOldest if size <= self.config.byte_budget => {
+                    let Some(index) = state
+                        .events
+                        .iter()
+                        .position(|(queued, _)| !strict::is_lifecycle(queued))
+                    else {
+                        break Some(why);
+                    };
+                    if let Some((oldest, oldest_size)) = state.events.remove(index) {
+                        state.bytes -= oldest_size;
+                        evicted.push((index, oldest, oldest_size));
+                    }
+                }
+                // Waiting on an empty queue would never end.
+                VisualizerDropPolicy::Block if can_wait && !state.events.is_empty() => {
+                    return Offer::Full(Box::new(event));
+                }
+                VisualizerDropPolicy::DropNewest
+                | VisualizerDropPolicy::DropOldest
+                | VisualizerDropPolicy::Block => break Some(why),
+            }
+        };
+        if let Some(why) = refused {
+            // Evicting did not make enough room, so the evicted events go
+            // back where they were and only the new one is dropped.
+            for (index, oldest, oldest_size) in evicted.into_iter().rev() {
+                state.bytes += oldest_size;
+                state.events.insert(index, (oldest, oldest_size));
+            }
+            drop(state);
+            self.record_drop(&event, why);
+            return Offer::Dropped;
+        }
+
+        state.drained_at = None;
+        state.bytes += size;
+        state.events.push_back((event, size));
+        drop(state);
+        for (_, oldest, _) in &evicted {
+            self.record_drop(oldest, "evicted by a newer event");
+        }
+        self.notify.notify_one();
+        Offer::Queued
+    }
+
+    /// Grow the queue if an event of `size` bytes does not fit and the
+    /// burst allows it, or say why it does not fit.
+    fn make_room(
+        &self,
+        state: &mut QueueState,
+        size: usize,
+        lifecycle: bool,
+        now: Instant,
+    ) -> Result<(), &'static str> {
+        if state.bytes + size > self.config.byte_budget {
+            return Err("byte budget exhausted");
+        }
+        let reserved = if lifecycle {
+            0
+        } else {
+            state.lifecycle_reserve.min(state.capacity / 2)
+        };
+        if state.events.len() + reserved >= state.capacity {
+            let burst_started = *state.burst_started.get_or_insert(now);
+            let in_burst = now.saturating_duration_since(burst_started) <= self.config.burst_window;
+            if !in_burst || state.capacity >= state.max_capacity {
+                return Err("queue full");
+            }
+            let to = (state.capacity * 2).min(state.max_capacity);
+            self.record_resize(state.capacity, to);
+            state.capacity = to;
+        }
+        Ok(())
+    }
+
+    /// Events dropped or evicted so far.
+    pub(super) fn dropped(&self) -> u64 {
+        self.diagnostics
+            .buffer
+            .lock()
+            .map(|buffer| buffer.dropped)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn try_recv(&self) -> Result<VisualizerEvent, TryRecvError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(TryRecvError::Disconnected);
+        };
+        match state.events.pop_front() {
+            Some((event, size)) => {
+                state.bytes -= size;
+                if state.events.is_empty() {
+                    state.drained_at = Some(Instant::now());
+                    state.burst_started = None;
+                }
+                drop(state);
+                self.room.notify_waiters();
+                Ok(event)
+            }
+            None if state.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Wait for the next event. Returns `None` once the queue is closed and
+    /// fully drained.
+    pub(super) async fn recv(&self) -> Option<VisualizerEvent> {
+        loop {
+            let notified = self.notify.notified();
+            match self.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => notified.await,
+            }
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.state
+            .lock()
+            .map(|state| state.events.is_empty())
+            .unwrap_or(true)
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.notify.notify_one();
+        self.room.notify_waiters();
+    }
+
+    /// Close the queue and drop everything in it, counted as dropped, for a
+    /// forwarder that will never drain it.
+    pub(super) fn abandon(&self) {
+        let dropped = match self.state.lock() {
+            Ok(mut state) => {
+                state.closed = true;
+                state.bytes = 0;
+                state.burst_started = None;
+                std::mem::take(&mut state.events)
+            }
+            Err(_) => VecDeque::new(),
+        };
+        for (event, _) in &dropped {
+            self.diagnostics.gaps.record(event);
+        }
+        if let Ok(mut buffer) = self.diagnostics.buffer.lock() {
+            buffer.dropped += dropped.len() as u64;
+        }
+        self.notify.notify_one();
+        self.room.notify_waiters();
+    }
+
+    fn maybe_shrink(&self, state: &mut QueueState, now: Instant) {
+        let Some(drained_at) = state.drained_at else {
+            return;
+        };
+        if now.saturating_duration_since(drained_at) < self.config.idle_shrink_after {
+            return;
+        }
+        state.burst_started = None;
+        if state.capacity > state.base_capacity && state.events.len() <= state.base_capacity {
+            self.record_resize(state.capacity, state.base_capacity);
+            state.capacity = state.base_capacity;
+        }
+    }
+
+    fn record_resize(&self, from: usize, to: usize) {
+        if let Ok(mut buffer) = self.diagnostics.buffer.lock() {
+            buffer.resizes += 1;
+            debug!(
+                "resized visualizer buffer from {from} to {to} events ({} resizes so far)",
+                buffer.resizes
+            );
+        }
+    }
+
+    fn record_drop(&self, event: &VisualizerEvent, why: &str) {
+        self.diagnostics.gaps.record(event);
+        if let Some(replay) = &self.replay
+            && let Ok(mut buffer) = replay.lock()
+        {
+            buffer.record(event);
+        }
+        if let Ok(mut buffer) = self.diagnostics.buffer.lock() {
+            buffer.dropped += 1;
+            debug!(
+                "dropped visualizer event `{}`: {why} ({} dropped so far)",
+                event.action_type, buffer.dropped
+            );
+        }
+    }
+
+    #[cfg(test)]
+    fn capacity(&self) -> usize {
+        self.state.lock().map(|state| state.capacity).unwrap_or(0)
+    }
+}
+
+/// Producer handle. The queue closes when the last clone of the owning
+/// `Arc<QueueSender>` is dropped, mirroring `mpsc::Sender` semantics.
+pub(super) struct QueueSender(Arc<EventQueue>);
+
+impl QueueSender {
+    pub(super) fn new(queue: Arc<EventQueue>) -> Self {
+        Self(queue)
+    }
+
+    pub(super) fn push(&self, event: VisualizerEvent) -> bool {
+        self.0.push(event)
+    }
+
+    /// See [`EventQueue::send`].
+    pub(super) async fn send(&self, event: VisualizerEvent) -> bool {
+        self.0.send(event).await
+    }
+
+    pub(super) fn set_drop_policy(&self, policy: VisualizerDropPolicy) {
+        self.0.set_drop_policy(policy);
+    }
+
+    pub(super) fn set_capacity(&self, capacity: usize) {
+        self.0.set_capacity(capacity);
+    }
+
+    /// See [`EventQueue::reserve_for_lifecycle`].
+    pub(super) fn reserve_for_lifecycle(&self, slots: usize) {
+        self.0.reserve_for_lifecycle(slots);
+    }
+
+    pub(super) fn dropped(&self) -> u64 {
+        self.0.dropped()
+    }
+
+    /// Close the queue before the last sender is dropped.
+    pub(super) fn close(&self) {
+        self.0.close();
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+fn approx_event_size(event: &VisualizerEvent) -> usize {
+    EVENT_ENVELOPE_BYTES
+        + event.action_type.len()
+        + approx_json_len(&event.action)
+        + event.state.as_ref().map_or(0, approx_json_len)
+}
+
+/// Cheap upper-bound-ish estimate of a value's serialized length, so sizing
+/// decisions do not require encoding every event twice.
+fn approx_json_len(value: &Value) -> usize {
+    match value {
+        Value::Null => 4,
+        Value::Bool(_) => 5,
+        Value::Number(_) => 20,
+        Value::String(s) => s.len() + 2,
+        Value::Array(items) => {
+            2 + items
+                .iter()
+                .map(|item| approx_json_len(item) + 1)
+                .sum::<usize>()
+        }
+        Value::Object(map) => {
+            2 + map
+                .iter()
+                .map(|(key, value)| key.len() + 4 + approx_json_len(value))
+                .sum::<usize>()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn event(sequence: u64) -> VisualizerEvent {
+        VisualizerEvent {
+            action: json!({ "chunk": "tool output line" }),
+            ..VisualizerEvent::for_test(sequence, "exec_output")
+        }
+    }
+
+    fn new_queue(config: BufferConfig) -> (EventQueue, Arc<DiagnosticsState>) {
+        let diagnostics = Arc::new(DiagnosticsState::default());
+        (
+            EventQueue::new(config, Arc::clone(&diagnostics)),
+            diagnostics,
+        )
+    }
+
+    fn buffer_diagnostics(diagnostics: &DiagnosticsState) -> BufferDiagnostics {
+        diagnostics.buffer.lock().expect("lock").clone()
+    }
+
+    #[test]
+    fn burst_grows_without_drops_and_shrinks_after_idle() {
+        let config = BufferConfig::default();
+        let (queue, diagnostics) = new_queue(config);
+        let start = Instant::now();
+
+        for sequence in 0..1000 {
+            assert!(queue.push_at(event(sequence), start));
+        }
+        assert_eq!(queue.capacity(), 1024);
+        assert_eq!(
+            buffer_diagnostics(&diagnostics),
+            BufferDiagnostics {
+                resizes: 2,
+                dropped: 0,
+            }
+        );
+
+        let received: Vec<u64> = std::iter::from_fn(|| queue.try_recv().ok())
+            .map(|event| event.sequence)
+            .collect();
+        assert_eq!(received, (0..1000).collect::<Vec<_>>());
+
+        assert!(queue.push_at(event(1000), start + config.idle_shrink_after * 2));
+        assert_eq!(queue.capacity(), config.base_capacity);
+        assert_eq!(
+            buffer_diagnostics(&diagnostics),
+            BufferDiagnostics {
+                resizes: 3,
+                dropped: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn growth_is_bounded_by_max_capacity_and_burst_window() {
+        let config = BufferConfig {
+            base_capacity: 2,
+            max_capacity: 4,
+            ..BufferConfig::default()
+        };
+        let (queue, diagnostics) = new_queue(config);
+        let start = Instant::now();
+
+        let accepted = (0..6)
+            .filter(|sequence| queue.push_at(event(*sequence), start))
+            .count();
+        assert_eq!(accepted, 4);
+
+        // Overflow that outlasts the burst window is dropped even below the
+        // hard maximum.
+        let config = BufferConfig {
+            base_capacity: 2,
+            max_capacity: 8,
+            ..BufferConfig::default()
+        };
+        let (late, _) = new_queue(config);
+        assert!(late.push_at(event(0), start));
+        assert!(late.push_at(event(1), start));
+        assert!(late.push_at(event(2), start));
+        assert!(late.push_at(event(3), start));
+        assert!(!late.push_at(event(4), start + config.burst_window * 2));
+
+        assert_eq!(buffer_diagnostics(&diagnostics).dropped, 2);
+    }
+
+    #[test]
+    fn a_drained_queue_starts_the_next_burst_afresh() {
+        let config = BufferConfig {
+            base_capacity: 2,
+            max_capacity: 8,
+            ..BufferConfig::default()
+        };
+        let (queue, diagnostics) = new_queue(config);
+        let start = Instant::now();
+        for sequence in 0..3 {
+            assert!(queue.push_at(event(sequence), start));
+        }
+        assert_eq!(drain(&queue), vec![0, 1, 2]);
+
+        // Long after the first burst, but well before the queue shrinks.
+        let later = start + config.burst_window * 2;
+        let accepted = (3..8)
+            .filter(|sequence| queue.push_at(event(*sequence), later))
+            .count();
+        assert_eq!(
+            (
+                accepted,
+                queue.capacity(),
+                buffer_diagnostics(&diagnostics).dropped
+            ),
+            (5, 8, 0)
+        );
+    }
+
+    #[test]
+    fn byte_budget_caps_growth() {
+        let one_event = approx_event_size(&event(0));
+        let config = BufferConfig {
+            base_capacity: 2,
+            max_capacity: 64,
+            byte_budget: one_event * 3,
+            ..BufferConfig::default()
+        };
+        let (queue, diagnostics) = new_queue(config);
+        let start = Instant::now();
+
+        let accepted = (0..10)
+            .filter(|sequence| queue.push_at(event(*sequence), start))
+            .count();
+        assert_eq!(accepted, 3);
+        assert_eq!(buffer_diagnostics(&diagnostics).dropped, 7);
+    }
+
+    /// A queue with room for two events that never grows, under `policy`.
+    fn full_queue(policy: VisualizerDropPolicy) -> (EventQueue, Arc<DiagnosticsState>) {
+        let (queue, diagnostics) = new_queue(BufferConfig {
+            base_capacity: 2,
+            max_capacity: 2,
+            ..BufferConfig::default()
+        });
+        queue.set_drop_policy(policy);
+        (queue, diagnostics)
+    }
+
+    fn drain(queue: &EventQueue) -> Vec<u64> {
+        std::iter::from_fn(|| queue.try_recv().ok())
+            .map(|event| event.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn drop_newest_keeps_what_is_queued() {
+        let (queue, _) = full_queue(VisualizerDropPolicy::DropNewest);
+        let accepted: Vec<bool> = (0..4).map(|sequence| queue.push(event(sequence))).collect();
+
+        assert_eq!(accepted, vec![true, true, false, false]);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&queue), vec![0, 1]);
+    }
+
+    #[test]
+    fn drop_oldest_evicts_all_but_lifecycle_events() {
+        let (queue, _) = full_queue(VisualizerDropPolicy::DropOldest);
+        let mut spawned = event(0);
+        spawned.action_type = "task_spawned".to_string();
+        assert!(queue.push(spawned));
+        for sequence in 1..4 {
+            assert!(queue.push(event(sequence)));
+        }
+
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&queue), vec![0, 3]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_what_it_cannot_make_room_with() {
+        let (queue, _) = full_queue(VisualizerDropPolicy::DropOldest);
+        queue.reserve_for_lifecycle(1);
+        assert!(queue.push(event(0)));
+        let mut spawned = event(1);
+        spawned.action_type = "task_spawned".to_string();
+        assert!(queue.push(spawned));
+
+        // With one slot reserved and the other taken by a lifecycle event,
+        // evicting event 0 would still leave no room for event 2.
+        assert!(!queue.push(event(2)));
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain(&queue), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_the_forwarder_to_make_room() {
+        let (queue, _) = full_queue(VisualizerDropPolicy::Block);
+        let queue = Arc::new(queue);
+        assert!(queue.send(event(0)).await);
+        assert!(queue.send(event(1)).await);
+
+        let blocked = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.send(event(2)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!blocked.is_finished());
+        // Emits that cannot wait still drop the newest event.
+        assert!(!queue.push(event(3)));
+
+        assert_eq!(queue.try_recv().map(|event| event.sequence), Ok(0));
+        assert!(blocked.await.expect("send task"));
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain(&queue), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn block_gives_up_when_the_queue_is_abandoned() {
+        let (queue, _) = full_queue(VisualizerDropPolicy::Block);
+        let queue = Arc::new(queue);
+        assert!(queue.send(event(0)).await);
+        assert!(queue.send(event(1)).await);
+
+        let blocked = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.send(event(2)).await }
+        });
+        tokio::task::yield_now().await;
+        // The two queued events are what abandoning drops; the blocked one
+        // is refused by the closed queue.
+        queue.abandon();
+
+        assert!(!blocked.await.expect("send task"));
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn fixed_capacity_never_grows() {
+        let (queue, diagnostics) = new_queue(BufferConfig::default());
+        queue.set_capacity(3);
+        let start = Instant::now();
+
+        let accepted = (0..5)
+            .filter(|sequence| queue.push_at(event(*sequence), start))
+            .count();
+        assert_eq!(
+            (accepted, queue.capacity(), buffer_diagnostics(&diagnostics)),
+            (
+                3,
+                3,
+                BufferDiagnostics {
+                    resizes: 0,
+                    dropped: 2,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn reserved_slots_are_left_to_lifecycle_events() {
+        let (queue, _) = new_queue(BufferConfig::default());
+        queue.set_capacity(4);
+        queue.reserve_for_lifecycle(2);
+        let start = Instant::now();
+
+        let accepted = (0..4)
+            .filter(|sequence| queue.push_at(event(*sequence), start))
+            .count();
+        assert_eq!(accepted, 2);
+        for sequence in 4..6 {
+            let mut lifecycle = event(sequence);
+            lifecycle.action_type = "task_completed".to_string();
+            assert!(queue.push_at(lifecycle, start));
+        }
+        let mut overflow = event(6);
+        overflow.action_type = "task_completed".to_string();
+        assert!(!queue.push_at(overflow, start));
+    }
+
+    #[tokio::test]
+    async fn closing_sender_drains_then_disconnects() {
+        let (queue, _) = new_queue(BufferConfig::default());
+        let queue = Arc::new(queue);
+        let sender = QueueSender::new(Arc::clone(&queue));
+        assert!(sender.push(event(0)));
+        drop(sender);
+
+        assert_eq!(queue.recv().await.map(|event| event.sequence), Some(0));
+        assert!(queue.recv().await.is_none());
+    }
+}
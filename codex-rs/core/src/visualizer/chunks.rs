@@ -0,0 +1,452 @@
+//! Oversized events sent in parts rather than cut down.
+//!
+//! With chunking on, an event that serializes to more than the frame limit
+//! is sent as a run of `event_chunk` frames instead. Each carries the
+//! original event's `sequenceEpoch`, `sequence`, `timestampMs`,
+//! `monotonicMs` and conversation, plus an action of
+//! `{ index, total, bytes }`, where `bytes` is the base64 of its slice of
+//! the serialized event. A run is always
+//! contiguous: the forwarder writes no other frame between the first chunk
+//! and the last, and a failed send resends the whole run from index 0. So a
+//! consumer can reassemble with one buffer, dropping it whenever anything
+//! else arrives first; [`ChunkReassembler`] does exactly that.
+//!
+//! Events larger than the cap on chunked size are not chunked; their
+//! strings are truncated until the event fits one frame. Sessions with
+//! chunking on advertise the `event_chunks` feature, and offer chunks to
+//! every relay they greet (see the `hello` module); a relay that does not
+//! accept them gets oversized events cut down to the size cap instead, as
+//! if chunking were off. An event a chunk or its truncation fails to
+//! serialize for is logged and not sent, rather than sent as an empty
+//! frame. Lifecycle events kept in the durable queue are never chunked.
+
+use base64::Engine;
+use serde_json::Value;
+use serde_json::json;
+use tracing::debug;
+use tracing::error;
+use tracing::warn;
+
+use super::VisualizerEvent;
+use super::wire::TimestampEncoding;
+use super::wire::WireEvent;
+
+pub(crate) const CHUNK_ACTION_TYPE: &str = "event_chunk";
+
+/// Limits on the frames the forwarder writes; see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EventChunking {
+    /// Events serializing to more bytes than this are chunked, into frames
+    /// of at most this many bytes.
+    pub(crate) frame_bytes: usize,
+    /// Events serializing to more bytes than this are truncated to one
+    /// frame instead.
+    pub(crate) max_event_bytes: usize,
+}
+
+/// The frames `serialized`, the encoding of `event`, is sent as; none if
+/// they could not be serialized.
+pub(super) fn frames(
+    serialized: String,
+    event: &VisualizerEvent,
+    chunking: Option<EventChunking>,
+    timestamps: TimestampEncoding,
+) -> Vec<String> {
+    let Some(chunking) = chunking.filter(|chunking| serialized.len() > chunking.frame_bytes) else {
+        return vec![serialized];
+    };
+    if serialized.len() > chunking.max_event_bytes {
+        warn!(
+            "visualizer event `{}` (sequence {}) is {} bytes, over the {}-byte cap on chunked events; truncating it",
+            event.action_type,
+            event.sequence,
+            serialized.len(),
+            chunking.max_event_bytes
+        );
+        return match truncated(event, chunking.frame_bytes, timestamps) {
+            Ok(frame) => vec![frame],
+            Err(err) => {
+                log_unsent(event, &err);
+                Vec::new()
+            }
+        };
+    }
+    // Sized for the largest index and total the event could need.
+    let overhead = match chunk_frame(event, serialized.len(), serialized.len(), &[], timestamps) {
+        Ok(frame) => frame.len(),
+        Err(err) => {
+            log_unsent(event, &err);
+            return Vec::new();
+        }
+    };
+    let piece = (chunking.frame_bytes.saturating_sub(overhead) / 4 * 3).max(3);
+    let pieces: Vec<&[u8]> = serialized.as_bytes().chunks(piece).collect();
+    // A run missing a chunk could never be reassembled, so one failure
+    // drops them all.
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(index, bytes)| chunk_frame(event, index, pieces.len(), bytes, timestamps))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|err| {
+            log_unsent(event, &err);
+            Vec::new()
+        })
+}
+
+fn log_unsent(event: &VisualizerEvent, err: &serde_json::Error) {
+    error!(
+        "failed to serialize visualizer event `{}` (sequence {}) for sending; not sending it: {err}",
+        event.action_type, event.sequence
+    );
+}
+
+fn chunk_frame(
+    event: &VisualizerEvent,
+    index: usize,
+    total: usize,
+    bytes: &[u8],
+    timestamps: TimestampEncoding,
+) -> Result<String, serde_json::Error> {
+    let chunk = VisualizerEvent {
+        sequence: event.sequence,
+        timestamp_ms: event.timestamp_ms,
+        monotonic_ms: event.monotonic_ms,
+        conversation_id: event.conversation_id,
+        conversation_sequence: event.conversation_sequence,
+        action_type: CHUNK_ACTION_TYPE.to_string(),
+        action: json!({
+            "index": index,
+            "total": total,
+            "bytes": base64::engine::general_purpose::STANDARD.encode(bytes),
+        }),
+        state: None,
+        sub_id: event.sub_id.clone(),
+        fork_suffix: event.fork_suffix.clone(),
+        content: Vec::new(),
+        ttl: event.ttl,
+        replayed: event.replayed,
+    };
+    serde_json::to_string(&WireEvent::new(&chunk, timestamps))
+}
+
+/// `event` with its strings cut short, each time to half the length, until
+/// it fits in `frame_bytes`; failing that, a sentinel action.
+fn truncated(
+    event: &VisualizerEvent,
+    frame_bytes: usize,
+    timestamps: TimestampEncoding,
+) -> Result<String, serde_json::Error> {
+    let mut max_chars =
+        longest_string(&event.action).max(event.state.as_ref().map_or(0, longest_string));
+    let mut attempt = event.clone();
+    while max_chars > 0 {
+        max_chars /= 2;
+        attempt.action = event.action.clone();
+        truncate_strings(&mut attempt.action, max_chars);
+        attempt.state = event.state.clone();
+        if let Some(state) = attempt.state.as_mut() {
+            truncate_strings(state, max_chars);
+        }
+        if let Ok(frame) = serde_json::to_string(&WireEvent::new(&attempt, timestamps))
+            && frame.len() <= frame_bytes
+        {
+            return Ok(frame);
+        }
+    }
+    attempt.action = json!({ "truncated": true });
+    attempt.state = None;
+    serde_json::to_string(&WireEvent::new(&attempt, timestamps))
+}
+
+fn longest_string(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.chars().count(),
+        Value::Array(items) => items.iter().map(longest_string).max().unwrap_or(0),
+        Value::Object(fields) => fields.values().map(longest_string).max().unwrap_or(0),
+        Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+    }
+}
+
+fn truncate_strings(value: &mut Value, max_chars: usize) {
+    match value {
+        Value::String(text) => {
+            if let Some((cut, _)) = text.char_indices().nth(max_chars) {
+                text.truncate(cut);
+                text.push('…');
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                truncate_strings(item, max_chars);
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                truncate_strings(field, max_chars);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Turns the events read from a relay back into the events the producer
+/// emitted, reassembling runs of `event_chunk` frames.
+#[derive(Debug, Default)]
+pub struct ChunkReassembler {
+    partial: Option<PartialEvent>,
+}
+
+#[derive(Debug)]
+struct PartialEvent {
+    sequence: (u64, u64),
+    total: u64,
+    next: u64,
+    bytes: Vec<u8>,
+}
+
+impl ChunkReassembler {
+    /// Feed the next event off the relay, in order. Returns the event to
+    /// hand on: `event` itself unless it is a chunk, or the reassembled
+    /// event once its last chunk arrives. A run interrupted by any other
+    /// frame is dropped.
+    pub fn push(&mut self, event: Value) -> Option<Value> {
+        if event["actionType"] != CHUNK_ACTION_TYPE {
+            if self.partial.take().is_some() {
+                debug!("dropping an interrupted run of visualizer event chunks");
+            }
+            return Some(event);
+        }
+        let bytes = self.push_chunk(&event)?;
+        serde_json::from_slice(&bytes)
+            .inspect_err(|err| debug!("dropping unparseable reassembled visualizer event: {err}"))
+            .ok()
+    }
+
+    /// The serialized event, once `chunk` completes it.
+    fn push_chunk(&mut self, chunk: &Value) -> Option<Vec<u8>> {
+        let sequence = (
+            chunk["sequenceEpoch"].as_u64().unwrap_or(0),
+            chunk["sequence"].as_u64()?,
+        );
+        let index = chunk["action"]["index"].as_u64()?;
+        let total = chunk["action"]["total"].as_u64()?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(chunk["action"]["bytes"].as_str()?)
+            .ok()?;
+        let mut partial = match self.partial.take() {
+            _ if index == 0 => PartialEvent {
+                sequence,
+                total,
+                next: 0,
+                bytes: Vec::new(),
+            },
+            Some(partial)
+                if partial.sequence == sequence
+                    && partial.total == total
+                    && partial.next == index =>
+            {
+                partial
+            }
+            _ => {
+                debug!(
+                    "dropping visualizer event chunk {index} of {total} without its predecessors"
+                );
+                return None;
+            }
+        };
+        partial.bytes.extend_from_slice(&bytes);
+        partial.next += 1;
+        if partial.next < partial.total {
+            self.partial = Some(partial);
+            return None;
+        }
+        Some(partial.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::visualizer::wire::split_sequence;
+
+    const CHUNKING: EventChunking = EventChunking {
+        frame_bytes: 64 * 1024,
+        max_event_bytes: 4 * 1024 * 1024,
+    };
+
+    /// A patch body of about `bytes` bytes, with multi-byte characters so
+    /// that chunk boundaries fall inside them.
+    fn patch_event(bytes: usize) -> VisualizerEvent {
+        let line = "+ let crab = \"🦀 ünïcödé\";\n";
+        VisualizerEvent {
+            timestamp_ms: 1_700_000_000_000,
+            action: json!({ "path": "src/lib.rs", "patch": line.repeat(bytes / line.len()) }),
+            state: Some(json!({ "turn": 3 })),
+            sub_id: Some("1".to_string()),
+            ..VisualizerEvent::for_test((1 << 53) + 7, "patch_body")
+        }
+    }
+
+    fn serialize(event: &VisualizerEvent) -> String {
+        serde_json::to_string(&WireEvent::new(event, TimestampEncoding::Number)).expect("serialize")
+    }
+
+    #[test]
+    fn two_megabyte_event_round_trips_through_chunks() {
+        let event = patch_event(2 * 1024 * 1024);
+        let serialized = serialize(&event);
+        let frames = frames(
+            serialized.clone(),
+            &event,
+            Some(CHUNKING),
+            TimestampEncoding::Number,
+        );
+        assert!(frames.len() > 1);
+        assert!(
+            frames
+                .iter()
+                .all(|frame| frame.len() <= CHUNKING.frame_bytes),
+            "every frame fits the limit"
+        );
+
+        let chunks: Vec<Value> = frames
+            .iter()
+            .map(|frame| serde_json::from_str(frame).expect("json"))
+            .collect();
+        let (epoch, sequence) = split_sequence(event.sequence);
+        let headers: Vec<(Value, Value, Value, Value, Value)> = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk["actionType"].clone(),
+                    chunk["sequenceEpoch"].clone(),
+                    chunk["sequence"].clone(),
+                    chunk["action"]["index"].clone(),
+                    chunk["action"]["total"].clone(),
+                )
+            })
+            .collect();
+        let total = chunks.len();
+        assert_eq!(
+            headers,
+            (0..total)
+                .map(|index| (
+                    json!(CHUNK_ACTION_TYPE),
+                    json!(epoch),
+                    json!(sequence),
+                    json!(index),
+                    json!(total),
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        let mut reassembler = ChunkReassembler::default();
+        let (last, rest) = chunks.split_last().expect("chunks");
+        for chunk in rest {
+            assert_eq!(reassembler.push_chunk(chunk), None);
+        }
+        assert_eq!(
+            reassembler.push_chunk(last),
+            Some(serialized.clone().into_bytes()),
+            "reassembled bytes equal the original frame"
+        );
+
+        let mut reassembler = ChunkReassembler::default();
+        let reassembled: Vec<Value> = chunks
+            .into_iter()
+            .filter_map(|chunk| reassembler.push(chunk))
+            .collect();
+        assert_eq!(
+            reassembled,
+            vec![serde_json::from_str::<Value>(&serialized).expect("json")]
+        );
+    }
+
+    #[test]
+    fn events_over_the_cap_are_truncated_to_one_frame() {
+        let event = patch_event(2 * 1024 * 1024);
+        let chunking = EventChunking {
+            max_event_bytes: 1024 * 1024,
+            ..CHUNKING
+        };
+        let frames = frames(
+            serialize(&event),
+            &event,
+            Some(chunking),
+            TimestampEncoding::Number,
+        );
+
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].len() <= chunking.frame_bytes);
+        let frame: Value = serde_json::from_str(&frames[0]).expect("json");
+        assert_eq!(
+            (frame["actionType"].clone(), frame["action"]["path"].clone()),
+            (json!("patch_body"), json!("src/lib.rs"))
+        );
+        let patch = frame["action"]["patch"].as_str().expect("patch");
+        assert!(patch.ends_with('…'));
+        assert!(
+            event.action["patch"]
+                .as_str()
+                .expect("patch")
+                .starts_with(patch.trim_end_matches('…'))
+        );
+    }
+
+    #[test]
+    fn small_events_and_disabled_chunking_send_one_frame() {
+        let event = patch_event(1024 * 1024);
+        let serialized = serialize(&event);
+        assert_eq!(
+            frames(serialized.clone(), &event, None, TimestampEncoding::Number),
+            vec![serialized.clone()]
+        );
+        let small = patch_event(1024);
+        assert_eq!(
+            frames(
+                serialize(&small),
+                &small,
+                Some(CHUNKING),
+                TimestampEncoding::Number
+            ),
+            vec![serialize(&small)]
+        );
+    }
+
+    #[test]
+    fn interrupted_runs_are_dropped() {
+        let event = patch_event(256 * 1024);
+        let chunks: Vec<Value> = frames(
+            serialize(&event),
+            &event,
+            Some(CHUNKING),
+            TimestampEncoding::Number,
+        )
+        .iter()
+        .map(|frame| serde_json::from_str(frame).expect("json"))
+        .collect();
+        let other = json!({ "sequence": 9, "actionType": "tick", "action": {} });
+
+        let mut reassembler = ChunkReassembler::default();
+        let mut seen = Vec::new();
+        seen.extend(reassembler.push(chunks[0].clone()));
+        seen.extend(reassembler.push(other.clone()));
+        for chunk in &chunks[1..] {
+            seen.extend(reassembler.push(chunk.clone()));
+        }
+        // A resent run starts over at index 0 and completes.
+        for chunk in &chunks {
+            seen.extend(reassembler.push(chunk.clone()));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                other,
+                serde_json::from_str::<Value>(&serialize(&event)).expect("json"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Per-sink transform that strips precise timing and identifying strings
+//! from events before they leave the host.
+//!
+//! In coarse mode, events bound for a non-loopback sink have timestamps
+//! rounded to the nearest second, `*Ms` durations floored into buckets and
+//! file paths reduced to their extension and depth. Every other string is
+//! replaced by a SHA-256 digest salted per sink, whatever key it is under,
+//! as is every object key that is not an identifier; only identifiers and
+//! enumerations listed in `KEPT_KEYS`, numbers, booleans and the shape of
+//! the payload pass through. Loopback sinks keep full fidelity.
+//!
+//! Independently of fidelity, remote sinks never get file contents from
+//! outside the trusted roots; see the `scope` module.
+
+use std::borrow::Cow;
+use std::path::Component;
+use std::path::Path;
+
+use serde_json::Map;
+use serde_json::Value;
+use serde_json::json;
+use sha2::Digest;
+use sha2::Sha256;
+use url::Host;
+use url::Url;
+
+use super::VisualizerEvent;
+use super::scope::OMITTED_CONTENT;
+use super::scope::TrustedRoots;
+use super::stdio;
+
+/// How much detail visualizer events keep when shipped to a remote sink.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum TelemetryFidelity {
+    #[default]
+    Full,
+    Coarse,
+}
+
+/// Keys whose string values are identifiers or enumerations, kept as is.
+const KEPT_KEYS: &[&str] = &[
+    "callId",
+    "conversationId",
+    "kind",
+    "model",
+    "role",
+    "status",
+    "subId",
+    "taskKind",
+    "type",
+];
+
+/// Keys whose string (or string array) values are file system paths.
+const PATH_KEYS: &[&str] = &[
+    "cwd",
+    "cwdCanonical",
+    "cwdRelative",
+    "path",
+    "paths",
+    "projectDocPaths",
+    "workdir",
+    "workspaceRoot",
+];
+
+/// Lower bounds, in milliseconds, that coarse durations are floored to.
+const DURATION_BUCKETS_MS: &[u64] = &[
+    0, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000, 300_000,
+];
+
+/// Transform stage for a single sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SinkTransform {
+    coarse: bool,
+    /// Set for remote sinks.
+    scope: Option<TrustedRoots>,
+    /// Prefixed to every hashed string, so a digest cannot be matched
+    /// against digests of guessed values or those another sink receives.
+    salt: [u8; 16],
+}
+
+impl SinkTransform {
+    pub(super) fn for_sink(url: &str, fidelity: TelemetryFidelity, roots: TrustedRoots) -> Self {
+        let remote = !is_loopback_sink(url);
+        Self {
+            coarse: fidelity == TelemetryFidelity::Coarse && remote,
+            scope: remote.then_some(roots),
+            salt: rand::random(),
+        }
+    }
+
+    /// Transform for events handed to clients, which may be anywhere, so
+    /// they are treated like a remote sink.
+    pub(super) fn for_clients(fidelity: TelemetryFidelity, roots: TrustedRoots) -> Self {
+        Self {
+            coarse: fidelity == TelemetryFidelity::Coarse,
+            scope: Some(roots),
+            salt: rand::random(),
+        }
+    }
+
+    pub(super) fn apply<'a>(&self, event: &'a VisualizerEvent) -> Cow<'a, VisualizerEvent> {
+        let scope = self.scope.as_ref().filter(|roots| roots.omits_any(event));
+        if !self.coarse && scope.is_none() {
+            return Cow::Borrowed(event);
+        }
+        let mut transformed = event.clone();
+        if let Some(roots) = scope {
+            roots.omit_outside(&mut transformed);
+        }
+        if self.coarse {
+            self.coarsen_event(&mut transformed);
+        }
+        Cow::Owned(transformed)
+    }
+
+    fn coarsen_event(&self, coarse: &mut VisualizerEvent) {
+        coarse.timestamp_ms = round_to_second(coarse.timestamp_ms);
+        coarse.monotonic_ms =
+            u64::try_from(round_to_second(u128::from(coarse.monotonic_ms))).unwrap_or(u64::MAX);
+        self.coarsen_value(&mut coarse.action);
+        if let Some(state) = coarse.state.as_mut() {
+            self.coarsen_value(state);
+        }
+    }
+
+    fn coarsen_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => self.coarsen_object(map),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.coarsen_value(item)),
+            // Left readable so clients can tell omitted content apart.
+            Value::String(text) if text == OMITTED_CONTENT => {}
+            Value::String(text) => *text = self.digest(text),
+            Value::Null | Value::Bool(_) | Value::Number(_) => {}
+        }
+    }
+
+    fn coarsen_object(&self, map: &mut Map<String, Value>) {
+        let entries = std::mem::take(map);
+        for (key, mut value) in entries {
+            let timestamp = key == "timestampMs" || key == "timestamp_ms";
+            let duration = key.ends_with("Ms") || key.ends_with("_ms");
+            match value.as_u64() {
+                Some(ms) if timestamp => value = json!(ms.saturating_add(500) / 1000 * 1000),
+                Some(ms) if duration => value = json!(bucket_duration(ms)),
+                _ if PATH_KEYS.contains(&key.as_str()) => self.coarsen_paths(&mut value),
+                _ if KEPT_KEYS.contains(&key.as_str()) && value.is_string() => {}
+                _ => self.coarsen_value(&mut value),
+            }
+            // Keys that are not identifiers are data, e.g. the paths of a
+            // patch's changes.
+            let key = if is_identifier(&key) {
+                key
+            } else {
+                self.digest(&key)
+            };
+            map.insert(key, value);
+        }
+    }
+
+    fn coarsen_paths(&self, value: &mut Value) {
+        match value {
+            Value::String(path) => *value = coarse_path(Path::new(path.as_str())),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.coarsen_paths(item)),
+            _ => self.coarsen_value(value),
+        }
+    }
+
+    fn digest(&self, text: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(text.as_bytes());
+        format!("sha256:{:x}", hasher.finalize())
+    }
+}
+
+/// Unparseable URLs are treated as remote so coarse mode fails closed.
+fn is_loopback_sink(url: &str) -> bool {
+    if stdio::is_stdio_sink(url) {
+        return true;
+    }
+    let Ok(parsed) = Url::parse(url) else {
+        return false;
+    };
+    // Neither a file nor a Unix socket sink leaves the machine.
+    if matches!(parsed.scheme(), "file" | "unix") {
+        return true;
+    }
+    match parsed.host() {
+        Some(Host::Domain(domain)) => domain == "localhost" || domain.ends_with(".localhost"),
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+fn round_to_second(timestamp_ms: u128) -> u128 {
+    (timestamp_ms + 500) / 1000 * 1000
+}
+
+fn is_identifier(key: &str) -> bool {
+    key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn coarse_path(path: &Path) -> Value {
+    let depth = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count();
+    json!({
+        "extension": path.extension().map(|ext| ext.to_string_lossy().into_owned()),
+        "depth": depth,
+    })
+}
+
+fn bucket_duration(ms: u64) -> u64 {
+    DURATION_BUCKETS_MS
+        .iter()
+        .rev()
+        .copied()
+        .find(|bucket| *bucket <= ms)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualizer::scope::ContentField;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn sample_event() -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms: 1_700_000_000_678,
+            action: json!({
+                "subId": "sub-1",
+                "command": ["bash", "-lc", "cat secrets.txt"],
+                "cwd": "/home/dev/repo",
+                "durationMs": 1_234,
+                "changes": [{ "path": "/home/dev/repo/src/main.rs" }],
+                "output": "token=abc123",
+                "env": { "API.KEY": "hunter2" },
+            }),
+            state: Some(json!({
+                "tool": { "arguments": "{\"query\":\"internal\"}" },
+                "latencyBreakdown": { "reasoningMs": 70 },
+            })),
+            ..VisualizerEvent::for_test(4, "protocol_event")
+        }
+    }
+
+    fn encode(transform: &SinkTransform, event: &VisualizerEvent) -> Value {
+        serde_json::to_value(transform.apply(event).as_ref()).expect("serialize")
+    }
+
+    #[test]
+    fn remote_sink_gets_coarse_event_and_localhost_keeps_detail() {
+        let event = sample_event();
+        let local = SinkTransform::for_sink(
+            "ws://localhost:4100",
+            TelemetryFidelity::Coarse,
+            TrustedRoots::default(),
+        );
+        let remote = SinkTransform::for_sink(
+            "wss://relay.example.com",
+            TelemetryFidelity::Coarse,
+            TrustedRoots::default(),
+        );
+
+        assert_eq!(
+            encode(&local, &event),
+            serde_json::to_value(&event).expect("serialize")
+        );
+        assert_eq!(
+            encode(&remote, &event),
+            json!({
+                "schemaVersion": 3,
+                "sequenceEpoch": 0,
+                "sequence": 4,
+                "timestampMs": 1_700_000_001_000u64,
+                "monotonicMs": 0,
+                "actionType": "protocol_event",
+                "action": {
+                    "subId": "sub-1",
+                    "command": [
+                        remote.digest("bash"),
+                        remote.digest("-lc"),
+                        remote.digest("cat secrets.txt"),
+                    ],
+                    "cwd": { "extension": null, "depth": 3 },
+                    "durationMs": 1_000,
+                    "changes": [{ "path": { "extension": "rs", "depth": 5 } }],
+                    "output": remote.digest("token=abc123"),
+                    "env": { remote.digest("API.KEY"): remote.digest("hunter2") },
+                },
+                "state": {
+                    "tool": { "arguments": remote.digest("{\"query\":\"internal\"}") },
+                    "latencyBreakdown": { "reasoningMs": 0 },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn digests_are_salted_per_sink() {
+        let sink =
+            |url| SinkTransform::for_sink(url, TelemetryFidelity::Coarse, TrustedRoots::default());
+        let first = sink("wss://relay.example.com");
+        let second = sink("wss://relay.example.com");
+
+        assert_eq!(
+            first.digest("cat secrets.txt"),
+            first.digest("cat secrets.txt")
+        );
+        assert_ne!(
+            first.digest("cat secrets.txt"),
+            second.digest("cat secrets.txt")
+        );
+    }
+
+    #[test]
+    fn full_fidelity_never_transforms() {
+        let event = sample_event();
+        let remote = SinkTransform::for_sink(
+            "wss://relay.example.com",
+            TelemetryFidelity::Full,
+            TrustedRoots::default(),
+        );
+        assert!(matches!(remote.apply(&event), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn only_remote_sinks_omit_content_outside_the_trusted_roots() {
+        let mut event = sample_event();
+        event.action = json!({ "path": "/etc/hosts", "output": "127.0.0.1 localhost" });
+        event.content = vec![ContentField::new(["output"], Path::new("/etc/hosts"))];
+        let roots = TrustedRoots::new([PathBuf::from("/home/dev/repo")]);
+        let local = SinkTransform::for_sink(
+            "ws://127.0.0.1:4100",
+            TelemetryFidelity::Full,
+            roots.clone(),
+        );
+        let remote =
+            SinkTransform::for_sink("wss://relay.example.com", TelemetryFidelity::Full, roots);
+
+        assert_eq!(
+            (
+                local.apply(&event).action.clone(),
+                remote.apply(&event).action.clone(),
+            ),
+            (
+                json!({ "path": "/etc/hosts", "output": "127.0.0.1 localhost" }),
+                json!({ "path": "/etc/hosts", "output": OMITTED_CONTENT }),
+            )
+        );
+    }
+
+    #[test]
+    fn loopback_detection() {
+        assert!(is_loopback_sink("ws://127.0.0.1:4100"));
+        assert!(is_loopback_sink("ws://[::1]:4100"));
+        assert!(is_loopback_sink("ws://viz.localhost"));
+        assert!(!is_loopback_sink("ws://10.0.0.5:4100"));
+        assert!(!is_loopback_sink("not a url"));
+        assert!(is_loopback_sink("file:///tmp/events.ndjson"));
+        assert!(is_loopback_sink("unix:///tmp/codex-viz.sock"));
+        assert!(is_loopback_sink("stdout?pretty"));
+    }
+}
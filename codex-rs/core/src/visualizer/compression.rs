@@ -0,0 +1,148 @@
+//! Gzip compression of large frames (`visualizer_compression`).
+//!
+//! The websocket crate this links negotiates no permessage-deflate, so
+//! compression happens a layer up instead: with it on, every frame of at
+//! least [`MIN_COMPRESSED_BYTES`] is gzipped and written as a binary frame,
+//! and smaller ones stay text frames, since the gzip header would eat most
+//! of the saving. The relay protocol sends no other binary frames, and the
+//! gzip magic number (`1f 8b`) opens every compressed one. Compression is
+//! offered to the relay as the `gzip` capability when greeting it (see the
+//! `hello` module), and a connection whose relay did not accept it keeps
+//! every frame text. Off by default, which offers nothing.
+
+use std::io;
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// Frames shorter than this are sent as text even with compression on.
+pub(super) const MIN_COMPRESSED_BYTES: usize = 1024;
+
+/// `text` gzipped, as a compressed frame carries it.
+pub(super) fn compress(text: &str) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::time::Duration;
+
+    use flate2::read::GzDecoder;
+    use futures::SinkExt;
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::hello;
+
+    fn gunzip(compressed: &[u8]) -> String {
+        let mut text = String::new();
+        GzDecoder::new(compressed)
+            .read_to_string(&mut text)
+            .expect("decompress");
+        text
+    }
+
+    #[test]
+    fn compressed_frames_start_with_the_gzip_magic_and_round_trip() {
+        let text = r#"{"state":"snapshot"}"#.repeat(100);
+        let compressed = compress(&text).expect("compress");
+        assert_eq!(
+            (
+                &compressed[..2],
+                compressed.len() < text.len(),
+                gunzip(&compressed)
+            ),
+            (&[0x1f, 0x8b][..], true, text)
+        );
+    }
+
+    /// A large state snapshot arrives gzipped in a binary frame and
+    /// decompresses to what was emitted, while a small event stays text.
+    #[tokio::test]
+    async fn large_state_round_trips_through_a_local_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let (tx, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let Ok((socket, _)) = listener.accept().await else {
+                return;
+            };
+            let Ok(mut ws) = accept_async(socket).await else {
+                return;
+            };
+            while let Some(Ok(message)) = ws.next().await {
+                if let Message::Text(text) = &message
+                    && let Ok(frame) = serde_json::from_str::<Value>(text)
+                    && hello::is_hello(&frame)
+                {
+                    let _ = ws.send(Message::Text(hello::welcome(&frame))).await;
+                }
+                let _ = tx.send(message);
+            }
+        });
+        let visualizer = AgentVisualizer::new(
+            Some(format!("ws://{addr}")),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_compression();
+
+        let state = json!({
+            "activeTasks": (0..200)
+                .map(|n| json!({ "subId": n.to_string(), "kind": "Regular" }))
+                .collect::<Vec<_>>(),
+        });
+        visualizer
+            .emit(None, "task_started", json!({}), Some(state.clone()))
+            .await;
+        visualizer.emit(None, "tick", json!({}), None).await;
+
+        let mut frames = Vec::new();
+        while frames.len() < 2 {
+            let message = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .expect("frame before timeout")
+                .expect("relay running");
+            let (compressed, text) = match message {
+                Message::Binary(data) => (true, gunzip(&data)),
+                Message::Text(text) => (false, text),
+                _ => continue,
+            };
+            let frame: Value = serde_json::from_str(&text).expect("json");
+            if hello::is_hello(&frame) {
+                continue;
+            }
+            frames.push((
+                compressed,
+                frame["actionType"].clone(),
+                frame["state"].clone(),
+            ));
+        }
+        assert_eq!(
+            frames,
+            vec![
+                (true, json!("task_started"), state),
+                (false, json!("tick"), Value::Null),
+            ]
+        );
+    }
+}
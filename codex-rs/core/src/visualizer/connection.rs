@@ -0,0 +1,330 @@
+//! Transport between the forwarder and the visualizer relay. The forwarder
+//! only sees these traits, so tests can substitute the websocket.
+//!
+//! A websocket connection is split in two. Writes go out on the caller's
+//! task, while a read loop of its own keeps reading whatever the relay
+//! sends, so its control frames never pile up unread while the forwarder
+//! is busy writing: pings are answered, a close frame is acknowledged and
+//! fails later sends with [`Error::ConnectionClosed`], which the forwarder
+//! takes as a cue to reconnect rather than as an error, and binary frames
+//! are dropped. Text frames and pongs are handed to [`Connection::recv`]
+//! and [`Connection::pong`]. With compression on, and accepted by the
+//! relay, large frames are written gzipped as binary frames instead (see
+//! the `compression` module). The
+//! handshake carries the relay token, if any (see the `auth` module), and
+//! is followed by a `hello` frame (see the `hello` module).
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use futures::SinkExt;
+use futures::StreamExt;
+use futures::stream::SplitSink;
+use futures::stream::SplitStream;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tokio_tungstenite::Connector as TlsConnector;
+use tokio_tungstenite::MaybeTlsStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::connect_async_tls_with_config;
+use tokio_tungstenite::tungstenite::Error;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::debug;
+use url::Url;
+
+use super::auth::RelayToken;
+use super::auth::handshake_request;
+use super::compression;
+use super::hello::Capabilities;
+use super::pins::PinStore;
+use super::pins::connect_pinned;
+use super::tls::ClientTls;
+
+#[async_trait]
+pub(super) trait Connector: Send + Sync {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error>;
+
+    /// Authenticate `wss` relays by the keys pinned in `pins` from now on;
+    /// see the `pins` module. Connectors without TLS ignore this.
+    fn pin_tls(&self, _pins: PinStore) {}
+
+    /// Offer to gzip large frames on connections made from now on; see the
+    /// `compression` module. Connectors without binary frames ignore this.
+    fn compress_frames(&self) {}
+
+    /// Send `token` with connections made from now on; see the `auth`
+    /// module. Connectors without a handshake ignore this.
+    fn authenticate(&self, _token: RelayToken) {}
+
+    /// Verify `wss` relays against `tls`'s roots and present its client
+    /// certificate from now on; see the `tls` module. Connectors without
+    /// TLS ignore this.
+    fn use_tls(&self, _tls: ClientTls) {}
+
+    /// Whether a relay that could restart sits at the other end, to be
+    /// greeted and replayed the recent events after each connect; see the
+    /// `hello` and `replay` modules.
+    fn greets(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+pub(super) trait Connection: Send {
+    /// Write one serialized event as a text frame.
+    async fn send(&mut self, text: String) -> Result<(), Error>;
+
+    /// Next text frame from the relay, or `None` once it closed the
+    /// connection. The durable forwarder reads acknowledgements; the live
+    /// one reads while idle only to notice the relay closing. Transports
+    /// the relay never writes to leave it pending.
+    async fn recv(&mut self) -> Result<Option<String>, Error> {
+        std::future::pending().await
+    }
+
+    /// Keep an idle connection from being timed out by the relay or a
+    /// proxy in between. Transports without a keepalive frame do nothing.
+    async fn heartbeat(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Wait for the relay to answer the last heartbeat. Frames read
+    /// meanwhile are kept for [`Self::recv`]. Transports without a
+    /// keepalive frame answer at once.
+    async fn pong(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once the queue has been drained, so transports that buffer
+    /// writes persist them once per batch. Others have nothing to do.
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Close the connection cleanly; the connection is dropped either way.
+    async fn close(&mut self) -> Result<(), Error>;
+
+    /// The optional frame formats of its own this connection could use,
+    /// to offer the relay; see the `hello` module.
+    fn offers(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Use only those of [`Self::offers`] the relay accepted.
+    fn accept(&mut self, _accepted: Capabilities) {}
+}
+
+#[derive(Default)]
+pub(super) struct WebSocketConnector {
+    /// Set by [`Connector::pin_tls`]; `None` verifies `wss` relays against
+    /// the platform's trusted roots.
+    pins: Mutex<Option<PinStore>>,
+    /// Set by [`Connector::compress_frames`].
+    compress: AtomicBool,
+    /// Set by [`Connector::authenticate`].
+    token: Mutex<Option<RelayToken>>,
+    /// Set by [`Connector::use_tls`].
+    tls: Mutex<Option<ClientTls>>,
+}
+
+#[async_trait]
+impl Connector for WebSocketConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        let pins = self.pins.lock().ok().and_then(|pins| pins.clone());
+        let token = self.token.lock().ok().and_then(|token| token.clone());
+        if let Some(token) = &token {
+            debug!("authenticating to visualizer relay {url} with {token:?}");
+        }
+        let request = handshake_request(url, token.as_ref()).map_err(|err| *err)?;
+        let tls = self.tls.lock().ok().and_then(|tls| tls.clone());
+        let is_wss = Url::parse(url).is_ok_and(|parsed| parsed.scheme() == "wss");
+        let ws = match (pins, tls) {
+            (Some(pins), tls) if is_wss => {
+                connect_pinned(url, request, &pins, tls.as_ref()).await?
+            }
+            (None, Some(tls)) if is_wss => {
+                let connector = TlsConnector::Rustls(tls.verified());
+                connect_async_tls_with_config(request, None, false, Some(connector))
+                    .await?
+                    .0
+            }
+            _ => connect_async(request).await?.0,
+        };
+        let compress = self.compress.load(Ordering::Relaxed);
+        Ok(Box::new(WebSocketConnection::new(ws, compress)))
+    }
+
+    fn pin_tls(&self, pins: PinStore) {
+        if let Ok(mut slot) = self.pins.lock() {
+            *slot = Some(pins);
+        }
+    }
+
+    fn compress_frames(&self) {
+        self.compress.store(true, Ordering::Relaxed);
+    }
+
+    fn authenticate(&self, token: RelayToken) {
+        if let Ok(mut slot) = self.token.lock() {
+            *slot = Some(token);
+        }
+    }
+
+    fn use_tls(&self, tls: ClientTls) {
+        if let Ok(mut slot) = self.tls.lock() {
+            *slot = Some(tls);
+        }
+    }
+
+    fn greets(&self) -> bool {
+        true
+    }
+}
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// What the read loop of a [`WebSocketConnection`] hands on.
+enum Incoming {
+    Text(String),
+    Pong,
+    /// The connection ended, cleanly unless reading it failed.
+    Ended(Option<Error>),
+}
+
+struct WebSocketConnection {
+    sink: SplitSink<WebSocket, Message>,
+    incoming: mpsc::UnboundedReceiver<Incoming>,
+    /// Text frames [`Connection::pong`] read past, for `recv`.
+    unread: VecDeque<String>,
+    /// Set by the read loop once the relay sent a close frame.
+    closed: Arc<AtomicBool>,
+    reader: AbortHandle,
+    /// Set by [`Connector::compress_frames`], to offer the relay.
+    offer_gzip: bool,
+    /// Gzip frames of at least [`compression::MIN_COMPRESSED_BYTES`]; set
+    /// once the relay accepted it.
+    compress: bool,
+}
+
+impl WebSocketConnection {
+    fn new(ws: WebSocket, offer_gzip: bool) -> Self {
+        let (sink, stream) = ws.split();
+        let (tx, incoming) = mpsc::unbounded_channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader = tokio::spawn(read_loop(stream, tx, Arc::clone(&closed))).abort_handle();
+        Self {
+            sink,
+            incoming,
+            unread: VecDeque::new(),
+            closed,
+            reader,
+            offer_gzip,
+            compress: false,
+        }
+    }
+}
+
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Read `stream` until it ends. Tungstenite queues the reply to a ping or
+/// close frame as it reads one and writes it on the next read, so reading
+/// on is all it takes to answer them.
+async fn read_loop(
+    mut stream: SplitStream<WebSocket>,
+    incoming: mpsc::UnboundedSender<Incoming>,
+    closed: Arc<AtomicBool>,
+) {
+    let failure = loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(text))) => {
+                let _ = incoming.send(Incoming::Text(text));
+            }
+            Some(Ok(Message::Pong(_))) => {
+                let _ = incoming.send(Incoming::Pong);
+            }
+            Some(Ok(Message::Close(frame))) => {
+                debug!("visualizer relay closed the connection: {frame:?}");
+                closed.store(true, Ordering::Release);
+            }
+            Some(Ok(Message::Binary(data))) => {
+                debug!(
+                    "ignoring a {}-byte binary frame from the visualizer relay",
+                    data.len()
+                );
+            }
+            Some(Ok(Message::Ping(_) | Message::Frame(_))) => {}
+            Some(Err(Error::ConnectionClosed)) | None => break None,
+            Some(Err(err)) => break Some(err),
+        }
+    };
+    let _ = incoming.send(Incoming::Ended(failure));
+}
+
+#[async_trait]
+impl Connection for WebSocketConnection {
+    async fn send(&mut self, text: String) -> Result<(), Error> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(Error::ConnectionClosed);
+        }
+        let message = if self.compress && text.len() >= compression::MIN_COMPRESSED_BYTES {
+            Message::Binary(compression::compress(&text)?)
+        } else {
+            Message::Text(text)
+        };
+        self.sink.send(message).await
+    }
+
+    async fn recv(&mut self) -> Result<Option<String>, Error> {
+        if let Some(text) = self.unread.pop_front() {
+            return Ok(Some(text));
+        }
+        loop {
+            match self.incoming.recv().await {
+                Some(Incoming::Text(text)) => return Ok(Some(text)),
+                Some(Incoming::Pong) => {}
+                Some(Incoming::Ended(Some(err))) => return Err(err),
+                Some(Incoming::Ended(None)) | None => return Ok(None),
+            }
+        }
+    }
+
+    async fn heartbeat(&mut self) -> Result<(), Error> {
+        self.sink.send(Message::Ping(Vec::new())).await
+    }
+
+    async fn pong(&mut self) -> Result<(), Error> {
+        loop {
+            match self.incoming.recv().await {
+                Some(Incoming::Pong) => return Ok(()),
+                Some(Incoming::Text(text)) => self.unread.push_back(text),
+                Some(Incoming::Ended(Some(err))) => return Err(err),
+                Some(Incoming::Ended(None)) | None => return Err(Error::ConnectionClosed),
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.sink.close().await
+    }
+
+    fn offers(&self) -> Capabilities {
+        Capabilities {
+            gzip: self.offer_gzip,
+            ..Capabilities::default()
+        }
+    }
+
+    fn accept(&mut self, accepted: Capabilities) {
+        self.compress = accepted.gzip;
+    }
+}
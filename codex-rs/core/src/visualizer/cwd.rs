@@ -0,0 +1,115 @@
+//! Working-directory fields for visualizer payloads.
+//!
+//! The configured cwd can be a path the user never sees, e.g. the
+//! symlinked `/var/folders/...` form macOS hands out under seatbelt, so
+//! payloads carry the resolved path and the cwd relative to the workspace
+//! root as well, which stay stable for filtering by project.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::git_info::get_git_repo_root;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CwdSnapshot {
+    /// The cwd as configured.
+    pub(crate) cwd: String,
+    /// `cwd` with symlinks resolved; `None` when it cannot be resolved.
+    pub(crate) cwd_canonical: Option<String>,
+    /// Enclosing git repository of the resolved cwd, when there is one.
+    pub(crate) workspace_root: Option<String>,
+    /// The resolved cwd relative to `workspace_root`; empty at the root.
+    pub(crate) cwd_relative: Option<String>,
+}
+
+impl CwdSnapshot {
+    /// Never fails: whatever cannot be resolved is left out, and an
+    /// unresolvable cwd is looked up as given.
+    pub(crate) async fn capture(cwd: &Path) -> Self {
+        let canonical = tokio::fs::canonicalize(cwd)
+            .await
+            .ok()
+            .map(|path| dunce::simplified(&path).to_path_buf());
+        let resolved = canonical.as_deref().unwrap_or(cwd);
+        let workspace_root = get_git_repo_root(resolved);
+        let cwd_relative = workspace_root
+            .as_deref()
+            .and_then(|root| resolved.strip_prefix(root).ok())
+            .map(|relative| relative.display().to_string());
+        Self {
+            cwd: cwd.display().to_string(),
+            cwd_canonical: canonical.map(|path| path.display().to_string()),
+            workspace_root: workspace_root.map(|root| root.display().to_string()),
+            cwd_relative,
+        }
+    }
+
+    /// Set the snapshot's fields on a payload object, leaving out unknown
+    /// ones.
+    pub(crate) fn add_to(&self, payload: &mut Value) {
+        let Some(payload) = payload.as_object_mut() else {
+            return;
+        };
+        payload.insert("cwd".to_string(), Value::String(self.cwd.clone()));
+        let optional = [
+            ("cwdCanonical", &self.cwd_canonical),
+            ("workspaceRoot", &self.workspace_root),
+            ("cwdRelative", &self.cwd_relative),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                payload.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinked_cwd_reports_resolved_path_and_workspace_relative_path() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).expect("create repo");
+        std::fs::create_dir_all(repo.join("crates/app")).expect("create subdir");
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&repo, &link).expect("symlink");
+        let repo = dunce::canonicalize(&repo).expect("canonical repo");
+
+        let snapshot = CwdSnapshot::capture(&link.join("crates/app")).await;
+
+        assert_eq!(
+            snapshot,
+            CwdSnapshot {
+                cwd: link.join("crates/app").display().to_string(),
+                cwd_canonical: Some(repo.join("crates/app").display().to_string()),
+                workspace_root: Some(repo.display().to_string()),
+                cwd_relative: Some("crates/app".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn unresolvable_cwd_keeps_only_the_raw_path() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let missing = dir.path().join("missing");
+
+        let mut payload = json!({ "subId": "sub-1" });
+        CwdSnapshot::capture(&missing).await.add_to(&mut payload);
+
+        assert_eq!(
+            payload,
+            json!({
+                "subId": "sub-1",
+                "cwd": missing.display().to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,749 @@
+//! Durable, at-least-once delivery of lifecycle events
+//! (`visualizer_durable_queue_dir`).
+//!
+//! Every lifecycle event (see `strict`) is appended to a segment file in the
+//! queue directory and fsync'd before it may be sent; other events bypass
+//! the queue and keep the in-memory, best-effort path. A record is the
+//! event's serialized frame behind a little-endian `u32` length. Segments
+//! are named after the offset of their first record, and a new one is
+//! started once the current one reaches its size limit.
+//!
+//! A dedicated forwarder sends one record at a time, with its
+//! `durableOffset` added to the frame, and waits for the relay to answer
+//! with a `{"type": "ack", "durableOffset": n}` text frame, which
+//! acknowledges every record up to `n`. Only then is the offset persisted
+//! (in the `acked` file) and segments holding nothing but acknowledged
+//! records deleted. Opening the directory again, e.g. after a crash,
+//! resumes from the first unacknowledged record, so a record whose ack was
+//! lost is sent again: relays deduplicate by `durableOffset`.
+//!
+//! Unlike the in-memory forwarder, this one never gives up on an
+//! unreachable relay: its records are on disk, and whatever it cannot
+//! deliver is left for a later session. It keeps its own delivery
+//! watermark, so the in-memory forwarder's progress never vouches for a
+//! record it has not had acknowledged, nor the other way around.
+//!
+//! Appends and acknowledgements write and fsync files, so they run on
+//! blocking threads; the locks the forwarder takes on the runtime only
+//! guard memory. An event that cannot be persisted is not sent best-effort
+//! instead: the visualizer reports it as a telemetry failure.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde_json::Value;
+use serde_json::json;
+use tokio::sync::Notify;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tracing::debug;
+use tracing::error;
+
+use super::VisualizerEvent;
+use super::coarse::SinkTransform;
+use super::connection::Connection;
+use super::connection::Connector;
+use super::forwarder::Backoff;
+use super::forwarder::ConnectFailure;
+use super::forwarder::connect_with_retry;
+use super::forwarder::mark_delivered;
+use super::forwarder::reconnect_policy;
+use super::lossy_payload;
+use super::wire::TimestampEncoding;
+use super::wire::WireEvent;
+use crate::retry::RetryPolicy;
+
+const SEGMENT_MAX_BYTES: u64 = 1 << 20;
+const SEGMENT_EXTENSION: &str = "seg";
+const ACKED_FILE: &str = "acked";
+const LENGTH_PREFIX_BYTES: usize = 4;
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(super) struct DurableQueue {
+    dir: PathBuf,
+    segment_max_bytes: u64,
+    /// Records not acknowledged yet, oldest first. Only held briefly, so
+    /// the forwarder may take it on the runtime.
+    unacked: Mutex<VecDeque<Record>>,
+    /// Held across file system calls, so only taken on blocking threads.
+    files: Mutex<Segments>,
+    appended: Notify,
+}
+
+struct Segments {
+    next_offset: u64,
+    /// First offset of every segment on disk, oldest first. The last one is
+    /// `active`.
+    first_offsets: Vec<u64>,
+    active: File,
+    active_len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    offset: u64,
+    frame: String,
+    /// Sequence of the event for records appended by this process; `None`
+    /// for records recovered from an earlier one.
+    sequence: Option<u64>,
+}
+
+impl DurableQueue {
+    /// Open or create the queue in `dir`, recovering unacknowledged records
+    /// and dropping a record torn by a crash mid-append. Fails if the
+    /// directory cannot be written.
+    pub(super) fn open(dir: &Path, segment_max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let acked = match fs::read_to_string(dir.join(ACKED_FILE)) {
+            Ok(text) => text
+                .trim()
+                .parse::<u64>()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION)
+                && let Some(first) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok())
+            {
+                segments.push(first);
+            }
+        }
+        segments.sort_unstable();
+
+        let mut unacked = VecDeque::new();
+        let mut next_offset = acked;
+        let mut active_len = 0;
+        for (index, &first) in segments.iter().enumerate() {
+            let path = segment_path(dir, first);
+            let bytes = fs::read(&path)?;
+            let (frames, valid_len) = parse_segment(&bytes)?;
+            if valid_len < bytes.len() {
+                if index + 1 < segments.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("truncated record in {}", path.display()),
+                    ));
+                }
+                debug!("dropping a torn record at the end of {}", path.display());
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(valid_len as u64)?;
+            }
+            for (offset, frame) in (first..).zip(frames) {
+                if offset >= acked {
+                    unacked.push_back(Record {
+                        offset,
+                        frame,
+                        sequence: None,
+                    });
+                }
+                next_offset = offset + 1;
+            }
+            active_len = valid_len as u64;
+        }
+
+        let first = match segments.last() {
+            Some(&first) => first,
+            None => {
+                segments.push(next_offset);
+                next_offset
+            }
+        };
+        let active = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(dir, first))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segment_max_bytes,
+            unacked: Mutex::new(unacked),
+            files: Mutex::new(Segments {
+                next_offset: next_offset.max(acked),
+                first_offsets: segments,
+                active,
+                active_len,
+            }),
+            appended: Notify::new(),
+        })
+    }
+
+    /// Append and fsync `frame`, the serialized event `sequence`. Returns
+    /// its offset. Blocks on the file system.
+    fn append(&self, frame: String, sequence: u64) -> io::Result<u64> {
+        let mut files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+        if files.active_len >= self.segment_max_bytes {
+            let first = files.next_offset;
+            files.active = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(segment_path(&self.dir, first))?;
+            files.first_offsets.push(first);
+            files.active_len = 0;
+        }
+
+        let len = u32::try_from(frame.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut record = Vec::with_capacity(LENGTH_PREFIX_BYTES + frame.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(frame.as_bytes());
+        let written = files
+            .active
+            .write_all(&record)
+            .and_then(|()| files.active.sync_data());
+        if let Err(err) = written {
+            // Leave no partial record for recovery to stumble over.
+            let active_len = files.active_len;
+            if let Err(truncate_err) = files.active.set_len(active_len) {
+                debug!("failed to truncate durable visualizer segment: {truncate_err}");
+            }
+            return Err(err);
+        }
+        files.active_len += record.len() as u64;
+
+        let offset = files.next_offset;
+        files.next_offset += 1;
+        // Still holding `files`, so records are queued in offset order.
+        self.unacked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(Record {
+                offset,
+                frame,
+                sequence: Some(sequence),
+            });
+        drop(files);
+        self.appended.notify_one();
+        Ok(offset)
+    }
+
+    fn front(&self) -> Option<Record> {
+        let unacked = self.unacked.lock().unwrap_or_else(PoisonError::into_inner);
+        unacked.front().cloned()
+    }
+
+    /// Records appended by this process that are not acknowledged yet.
+    fn undelivered(&self) -> u64 {
+        let unacked = self.unacked.lock().unwrap_or_else(PoisonError::into_inner);
+        unacked
+            .iter()
+            .filter(|record| record.sequence.is_some())
+            .count() as u64
+    }
+
+    /// Settle every record up to `offset`, the relay having acknowledged
+    /// them. Returns the sequences of those appended by this process.
+    fn settle(&self, offset: u64) -> Vec<u64> {
+        let mut unacked = self.unacked.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut sequences = Vec::new();
+        while let Some(record) = unacked.front()
+            && record.offset <= offset
+        {
+            sequences.extend(record.sequence);
+            unacked.pop_front();
+        }
+        sequences
+    }
+
+    /// Persist that every record up to `offset` is acknowledged, and delete
+    /// the segments that covers. Blocks on the file system.
+    fn persist_ack(&self, offset: u64) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+        let acked = offset + 1;
+        let tmp = self.dir.join(format!("{ACKED_FILE}.tmp"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(acked.to_string().as_bytes())?;
+        file.sync_data()?;
+        fs::rename(&tmp, self.dir.join(ACKED_FILE))?;
+
+        // A segment is fully acknowledged once the next one starts at or
+        // before `acked`; the active segment is always kept.
+        let covered = files
+            .first_offsets
+            .windows(2)
+            .take_while(|pair| pair[1] <= acked)
+            .count();
+        for first in files.first_offsets.drain(..covered) {
+            fs::remove_file(segment_path(&self.dir, first))?;
+        }
+        Ok(())
+    }
+}
+
+fn segment_path(dir: &Path, first: u64) -> PathBuf {
+    dir.join(format!("{first:020}.{SEGMENT_EXTENSION}"))
+}
+
+/// The complete records of a segment, and how many bytes they span.
+fn parse_segment(bytes: &[u8]) -> io::Result<(Vec<String>, usize)> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while let Some(prefix) = bytes.get(pos..pos + LENGTH_PREFIX_BYTES) {
+        let mut len = [0; LENGTH_PREFIX_BYTES];
+        len.copy_from_slice(prefix);
+        let start = pos + LENGTH_PREFIX_BYTES;
+        let Some(frame) = bytes.get(start..start + u32::from_le_bytes(len) as usize) else {
+            break;
+        };
+        let frame = String::from_utf8(frame.to_vec())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        pos = start + frame.len();
+        frames.push(frame);
+    }
+    Ok((frames, pos))
+}
+
+/// `frame` with `durableOffset` added.
+fn with_offset(frame: &str, offset: u64) -> String {
+    match serde_json::from_str::<Value>(frame) {
+        Ok(Value::Object(mut fields)) => {
+            fields.insert("durableOffset".to_string(), json!(offset));
+            Value::Object(fields).to_string()
+        }
+        _ => json!({ "durableOffset": offset, "frame": frame }).to_string(),
+    }
+}
+
+/// The offset acknowledged by a relay frame, if it is an ack.
+fn acked_offset(text: &str) -> Option<u64> {
+    let frame: Value = serde_json::from_str(text).ok()?;
+    if frame.get("type").and_then(Value::as_str) != Some("ack") {
+        return None;
+    }
+    frame.get("durableOffset").and_then(Value::as_u64)
+}
+
+/// The durable queue of a visualizer and the forwarder draining it, which
+/// stops when this is dropped; what it has not delivered stays on disk.
+pub(super) struct DurableSink {
+    queue: Arc<DurableQueue>,
+    transform: SinkTransform,
+    timestamps: TimestampEncoding,
+    /// This forwarder's own watermark: one past the newest sequence the
+    /// relay acknowledged.
+    delivered: Arc<watch::Sender<u64>>,
+    /// One past the newest sequence handed to [`DurableSink::append`].
+    appended_through: AtomicU64,
+    forwarder: AbortHandle,
+}
+
+impl DurableSink {
+    /// Open the queue in `dir` and start delivering it. Must be called
+    /// within a tokio runtime.
+    pub(super) fn start(
+        dir: &Path,
+        connector: Arc<dyn Connector>,
+        connect_url: String,
+        transform: SinkTransform,
+        timestamps: TimestampEncoding,
+        reconnect: Arc<Mutex<RetryPolicy>>,
+    ) -> io::Result<Self> {
+        let queue = Arc::new(DurableQueue::open(dir, SEGMENT_MAX_BYTES)?);
+        let delivered = Arc::new(watch::Sender::new(0));
+        let forwarder = DurableForwarder {
+            queue: Arc::clone(&queue),
+            connector,
+            connect_url,
+            delivered: Arc::clone(&delivered),
+            reconnect,
+        };
+        Ok(Self {
+            queue,
+            transform,
+            timestamps,
+            delivered,
+            appended_through: AtomicU64::new(0),
+            forwarder: tokio::spawn(forwarder.run()).abort_handle(),
+        })
+    }
+
+    /// Persist `event` on a blocking thread; once this returns `Ok` it
+    /// survives a crash.
+    pub(super) async fn append(&self, event: &VisualizerEvent) -> io::Result<u64> {
+        let (frame, sequence) = self.frame(event);
+        let queue = Arc::clone(&self.queue);
+        tokio::task::spawn_blocking(move || queue.append(frame, sequence))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Like [`DurableSink::append`], without waiting: `failed` gets the
+    /// error, if any. Outside a runtime, e.g. in `Drop`, the event is
+    /// persisted on the calling thread.
+    pub(super) fn append_detached(
+        &self,
+        event: &VisualizerEvent,
+        failed: impl FnOnce(io::Error) + Send + 'static,
+    ) {
+        let (frame, sequence) = self.frame(event);
+        let queue = Arc::clone(&self.queue);
+        let append = move || {
+            if let Err(err) = queue.append(frame, sequence) {
+                failed(err);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(append)),
+            Err(_) => append(),
+        }
+    }
+
+    fn frame(&self, event: &VisualizerEvent) -> (String, u64) {
+        self.appended_through
+            .fetch_max(event.sequence + 1, Ordering::SeqCst);
+        let event = self.transform.apply(event);
+        let frame = match serde_json::to_string(&WireEvent::new(&event, self.timestamps)) {
+            Ok(frame) => frame,
+            Err(err) => lossy_payload(&event, &err, self.timestamps),
+        };
+        (frame, event.sequence)
+    }
+
+    /// Wait until the relay acknowledged every event appended so far. One
+    /// that could not be persisted never is, so this then waits for a later
+    /// one.
+    pub(super) async fn flushed(&self) {
+        let through = self.appended_through.load(Ordering::SeqCst);
+        let mut delivered = self.delivered.subscribe();
+        // `self` holds the sender, so the watch cannot close.
+        let _ = delivered.wait_for(|done| *done >= through).await;
+    }
+
+    /// Events appended by this process the relay has not acknowledged.
+    pub(super) fn undelivered(&self) -> u64 {
+        self.queue.undelivered()
+    }
+}
+
+impl Drop for DurableSink {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
+
+struct DurableForwarder {
+    queue: Arc<DurableQueue>,
+    connector: Arc<dyn Connector>,
+    connect_url: String,
+    delivered: Arc<watch::Sender<u64>>,
+    reconnect: Arc<Mutex<RetryPolicy>>,
+}
+
+impl DurableForwarder {
+    async fn run(self) {
+        let mut stream: Option<Box<dyn Connection>> = None;
+        let mut backoff = Backoff::default();
+        loop {
+            let Some(record) = self.queue.front() else {
+                self.queue.appended.notified().await;
+                continue;
+            };
+            if stream.is_none() {
+                let policy = reconnect_policy(&self.reconnect);
+                match connect_with_retry(
+                    self.connector.as_ref(),
+                    &self.connect_url,
+                    false,
+                    policy,
+                    None,
+                    &mut backoff,
+                )
+                .await
+                {
+                    Ok((connection, _)) => {
+                        backoff.connected();
+                        stream = Some(connection);
+                    }
+                    Err(ConnectFailure::PinMismatch(mismatch)) => {
+                        // The records stay on disk for a later session,
+                        // once the pin is cleared.
+                        error!("{mismatch}; stopping durable visualizer delivery");
+                        return;
+                    }
+                    Err(ConnectFailure::AuthRejected(status)) => {
+                        // Likewise, until the token is fixed.
+                        error!(
+                            "visualizer relay rejected authentication (HTTP {status}); stopping durable visualizer delivery"
+                        );
+                        return;
+                    }
+                    Err(ConnectFailure::Exhausted(attempts) | ConnectFailure::GaveUp(attempts)) => {
+                        // The record stays queued; try again once another
+                        // one is appended.
+                        error!(
+                            "durable visualizer forwarder gave up after {attempts} failed connection attempts"
+                        );
+                        self.queue.appended.notified().await;
+                        continue;
+                    }
+                }
+            }
+            let Some(connection) = stream.as_mut() else {
+                continue;
+            };
+            if let Err(err) = deliver(connection.as_mut(), &record).await {
+                let delay = backoff.next_delay(&reconnect_policy(&self.reconnect));
+                error!(
+                    "durable visualizer event {} not acknowledged: {err}; reconnecting in {delay:?}",
+                    record.offset
+                );
+                stream = None;
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            backoff.reset();
+            for sequence in self.queue.settle(record.offset) {
+                mark_delivered(&self.delivered, sequence);
+            }
+            let queue = Arc::clone(&self.queue);
+            let persisted = tokio::task::spawn_blocking(move || queue.persist_ack(record.offset))
+                .await
+                .unwrap_or_else(|err| Err(io::Error::other(err)));
+            if let Err(err) = persisted {
+                error!("failed to persist durable visualizer offset: {err}");
+            }
+        }
+    }
+}
+
+/// Send `record` and wait for the relay to acknowledge it.
+async fn deliver(connection: &mut dyn Connection, record: &Record) -> Result<(), String> {
+    connection
+        .send(with_offset(&record.frame, record.offset))
+        .await
+        .map_err(|err| format!("send failed: {err}"))?;
+    let acked = tokio::time::timeout(ACK_TIMEOUT, async {
+        loop {
+            match connection.recv().await {
+                Ok(Some(text)) => {
+                    if acked_offset(&text).is_some_and(|acked| acked >= record.offset) {
+                        return Ok(());
+                    }
+                }
+                Ok(None) => return Err("relay closed the connection".to_string()),
+                Err(err) => return Err(format!("receive failed: {err}")),
+            }
+        }
+    })
+    .await;
+    acked.unwrap_or_else(|_| Err(format!("no ack within {ACK_TIMEOUT:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use async_trait::async_trait;
+    use pretty_assertions::assert_eq;
+    use tokio_tungstenite::tungstenite::Error;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TrustedRoots;
+
+    fn frames(queue: &DurableQueue) -> Vec<(u64, String)> {
+        let unacked = queue.unacked.lock().unwrap_or_else(PoisonError::into_inner);
+        unacked
+            .iter()
+            .map(|record| (record.offset, record.frame.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn recovery_resumes_after_the_last_ack_and_drops_a_torn_record() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let queue = DurableQueue::open(dir.path(), 16).expect("open");
+        for n in 0..4 {
+            queue.append(format!("frame-{n}-long"), n).expect("append");
+        }
+        assert_eq!(queue.settle(1), vec![0, 1]);
+        queue.persist_ack(1).expect("persist ack");
+        // Every record fills a segment; the acknowledged ones are gone.
+        let mut segments: Vec<String> = fs::read_dir(dir.path())
+            .expect("read dir")
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.ends_with(".seg"))
+            .collect();
+        segments.sort();
+        assert_eq!(
+            segments,
+            vec![
+                "00000000000000000002.seg".to_string(),
+                "00000000000000000003.seg".to_string(),
+            ]
+        );
+        drop(queue);
+
+        let mut last = OpenOptions::new()
+            .append(true)
+            .open(segment_path(dir.path(), 3))
+            .expect("open segment");
+        last.write_all(&[200, 0, 0, 0, b'x']).expect("tear");
+
+        let queue = DurableQueue::open(dir.path(), 16).expect("reopen");
+        assert_eq!(
+            frames(&queue),
+            vec![
+                (2, "frame-2-long".to_string()),
+                (3, "frame-3-long".to_string()),
+            ]
+        );
+        assert_eq!(queue.append("frame-4".to_string(), 4).ok(), Some(4));
+    }
+
+    #[test]
+    fn unwritable_directory_is_an_error() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let not_a_dir = dir.path().join("file");
+        fs::write(&not_a_dir, "").expect("write");
+        assert!(DurableQueue::open(&not_a_dir.join("queue"), SEGMENT_MAX_BYTES).is_err());
+    }
+
+    /// A relay that acknowledges the first `ack_limit` frames it gets and
+    /// fails every later send, recording the `durableOffset` of each frame
+    /// it acknowledged with how often it got it.
+    #[derive(Clone)]
+    struct AckingRelay {
+        ack_limit: usize,
+        received: Arc<Mutex<BTreeMap<u64, usize>>>,
+    }
+
+    struct AckingConnection {
+        relay: AckingRelay,
+        acks: VecDeque<String>,
+    }
+
+    #[async_trait]
+    impl Connector for AckingRelay {
+        async fn connect(&self, _url: &str) -> Result<Box<dyn Connection>, Error> {
+            Ok(Box::new(AckingConnection {
+                relay: self.clone(),
+                acks: VecDeque::new(),
+            }))
+        }
+    }
+
+    #[async_trait]
+    impl Connection for AckingConnection {
+        async fn send(&mut self, text: String) -> Result<(), Error> {
+            let frame: Value = serde_json::from_str(&text).map_err(|_| Error::ConnectionClosed)?;
+            let Some(offset) = frame["durableOffset"].as_u64() else {
+                return Ok(());
+            };
+            let mut received = self
+                .relay
+                .received
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if received.values().sum::<usize>() >= self.relay.ack_limit {
+                return Err(Error::ConnectionClosed);
+            }
+            *received.entry(offset).or_default() += 1;
+            self.acks
+                .push_back(json!({ "type": "ack", "durableOffset": offset }).to_string());
+            Ok(())
+        }
+
+        async fn recv(&mut self) -> Result<Option<String>, Error> {
+            Ok(self.acks.pop_front())
+        }
+
+        async fn close(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn durable_visualizer(dir: &Path, relay: &AckingRelay) -> AgentVisualizer {
+        AgentVisualizer::with_connector(
+            Some("ws://relay.example/visualizer".to_string()),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+            Arc::new(relay.clone()),
+        )
+        .with_durable_queue(dir)
+        .expect("durable queue")
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_persisted_before_a_crash_are_delivered_once_after_restart() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let first = AckingRelay {
+            ack_limit: 2,
+            received: Arc::default(),
+        };
+        let visualizer = durable_visualizer(dir.path(), &first);
+        for n in 0..5 {
+            visualizer
+                .emit(None, "task_completed", json!({ "n": n }), None)
+                .await;
+        }
+        // Not lifecycle, so never written to the queue.
+        visualizer
+            .emit(None, "reasoning_phase", json!({}), None)
+            .await;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        // The crash: the forwarder stops with three records unacknowledged.
+        drop(visualizer);
+
+        let second = AckingRelay {
+            ack_limit: usize::MAX,
+            received: Arc::default(),
+        };
+        let _visualizer = durable_visualizer(dir.path(), &second);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+
+        let delivered = |relay: &AckingRelay| {
+            relay
+                .received
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        };
+        assert_eq!(delivered(&first), BTreeMap::from([(0, 1), (1, 1)]));
+        assert_eq!(delivered(&second), BTreeMap::from([(2, 1), (3, 1), (4, 1)]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flush_waits_for_the_durable_relay_even_when_the_queue_is_delivered() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        // Fails every durable frame but takes the others.
+        let relay = AckingRelay {
+            ack_limit: 0,
+            received: Arc::default(),
+        };
+        let visualizer = durable_visualizer(dir.path(), &relay);
+        visualizer
+            .emit(None, "task_completed", json!({}), None)
+            .await;
+        visualizer
+            .emit(None, "reasoning_phase", json!({}), None)
+            .await;
+
+        assert_eq!(visualizer.flush(Duration::from_secs(5)).await, Some(false));
+    }
+}
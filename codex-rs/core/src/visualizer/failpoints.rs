@@ -0,0 +1,1374 @@
+//! Failure injection for the forwarder's connection.
+//!
+//! Each [`Failpoint`] names an operation of the forwarder's connection. Arming
+//! one queues [`Injection`]s that the next calls consume in order; once the
+//! queue is empty the operation behaves normally again. [`run_scenario`]
+//! drives a visualizer whose relay is an in-memory sink through a list of
+//! [`Step`]s and reports what the sink saw, so resilience behavior can be
+//! specified without standing up a websocket server per case.
+//!
+//! The forwarder never reads from the relay, so there is no `recv`
+//! failpoint.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use serde_json::json;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Error;
+
+use super::AgentVisualizer;
+use super::RetentionRules;
+use super::TelemetryFidelity;
+use super::TimestampEncoding;
+use super::TrustedRoots;
+use super::connection::Connection;
+use super::connection::Connector;
+
+/// Loopback, so events reach the sink at full fidelity.
+const SCENARIO_URL: &str = "ws://127.0.0.1:0/scenario";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Failpoint {
+    Connect,
+    Send,
+    Heartbeat,
+    Pong,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Injection {
+    /// Let the call through unchanged.
+    Pass,
+    /// Fail the call with an I/O error; a failed send writes nothing.
+    Fail,
+    /// Stall the call for this long, then let it through.
+    Delay(Duration),
+    /// Panic inside the call, taking the forwarder task down with it.
+    Panic,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Failpoints {
+    armed: Arc<Mutex<HashMap<Failpoint, VecDeque<Injection>>>>,
+    /// When each failpoint was reached, armed or not.
+    calls: Arc<Mutex<HashMap<Failpoint, Vec<Instant>>>>,
+}
+
+impl Failpoints {
+    pub(crate) fn arm(&self, point: Failpoint, injections: impl IntoIterator<Item = Injection>) {
+        if let Ok(mut armed) = self.armed.lock() {
+            armed.entry(point).or_default().extend(injections);
+        }
+    }
+
+    /// When `point` was reached, oldest first, on the tokio clock.
+    #[cfg(test)]
+    pub(crate) fn calls(&self, point: Failpoint) -> Vec<Instant> {
+        self.calls
+            .lock()
+            .ok()
+            .and_then(|calls| calls.get(&point).cloned())
+            .unwrap_or_default()
+    }
+
+    async fn trigger(&self, point: Failpoint) -> Result<(), Error> {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.entry(point).or_default().push(Instant::now());
+        }
+        let next = self
+            .armed
+            .lock()
+            .ok()
+            .and_then(|mut armed| armed.get_mut(&point).and_then(VecDeque::pop_front));
+        match next {
+            None | Some(Injection::Pass) => Ok(()),
+            Some(Injection::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                Ok(())
+            }
+            Some(Injection::Fail) => Err(Error::Io(std::io::Error::other(format!(
+                "failpoint {point:?}"
+            )))),
+            Some(Injection::Panic) => panic!("failpoint {point:?}"),
+        }
+    }
+}
+
+/// Wraps a connector so every connection it opens honors the failpoints.
+struct FailpointConnector {
+    inner: Arc<dyn Connector>,
+    failpoints: Failpoints,
+}
+
+#[async_trait]
+impl Connector for FailpointConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        self.failpoints.trigger(Failpoint::Connect).await?;
+        let inner = self.inner.connect(url).await?;
+        Ok(Box::new(FailpointConnection {
+            inner,
+            failpoints: self.failpoints.clone(),
+        }))
+    }
+}
+
+struct FailpointConnection {
+    inner: Box<dyn Connection>,
+    failpoints: Failpoints,
+}
+
+#[async_trait]
+impl Connection for FailpointConnection {
+    async fn send(&mut self, text: String) -> Result<(), Error> {
+        self.failpoints.trigger(Failpoint::Send).await?;
+        self.inner.send(text).await
+    }
+
+    async fn recv(&mut self) -> Result<Option<String>, Error> {
+        self.inner.recv().await
+    }
+
+    async fn heartbeat(&mut self) -> Result<(), Error> {
+        self.failpoints.trigger(Failpoint::Heartbeat).await?;
+        self.inner.heartbeat().await
+    }
+
+    async fn pong(&mut self) -> Result<(), Error> {
+        self.failpoints.trigger(Failpoint::Pong).await?;
+        self.inner.pong().await
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.failpoints.trigger(Failpoint::Close).await?;
+        self.inner.close().await
+    }
+}
+
+/// What the in-memory relay observed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SinkLog {
+    /// Sequence numbers of the events written, in arrival order.
+    pub delivered: Vec<u64>,
+    /// Connections opened.
+    pub connections: usize,
+    /// Connections closed cleanly.
+    pub closes: usize,
+}
+
+#[derive(Clone, Default)]
+struct RecordingConnector {
+    log: Arc<Mutex<SinkLog>>,
+}
+
+#[async_trait]
+impl Connector for RecordingConnector {
+    async fn connect(&self, _url: &str) -> Result<Box<dyn Connection>, Error> {
+        if let Ok(mut log) = self.log.lock() {
+            log.connections += 1;
+        }
+        Ok(Box::new(self.clone()))
+    }
+}
+
+#[async_trait]
+impl Connection for RecordingConnector {
+    async fn send(&mut self, text: String) -> Result<(), Error> {
+        // A batch frame holds an array of events.
+        let events = match serde_json::from_str::<Value>(&text) {
+            Ok(Value::Array(events)) => events,
+            Ok(event) => vec![event],
+            Err(_) => Vec::new(),
+        };
+        if let Ok(mut log) = self.log.lock() {
+            log.delivered
+                .extend(events.iter().filter_map(|event| event["sequence"].as_u64()));
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        if let Ok(mut log) = self.log.lock() {
+            log.closes += 1;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Queue injections on a failpoint, after any already queued.
+    Arm(Failpoint, Vec<Injection>),
+    /// Emit this many events.
+    Emit(usize),
+    /// Flush the visualizer with this timeout; the result is recorded
+    /// in [`ScenarioOutcome::flushes`].
+    Flush(Duration),
+    /// Let time pass without emitting.
+    Wait(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioOutcome {
+    pub sink: SinkLog,
+    pub flushes: Vec<Option<bool>>,
+}
+
+/// Run `steps` against a fresh visualizer that parks its forwarder after
+/// `idle_shutdown`, and report what reached the sink. Meant for a paused
+/// tokio clock, so retry delays and injected latency cost no real time.
+pub async fn run_scenario(idle_shutdown: Option<Duration>, steps: Vec<Step>) -> ScenarioOutcome {
+    let failpoints = Failpoints::default();
+    let sink = RecordingConnector::default();
+    let visualizer = scenario_visualizer(idle_shutdown, &failpoints, sink.clone());
+
+    let mut flushes = Vec::new();
+    for step in steps {
+        match step {
+            Step::Arm(point, injections) => failpoints.arm(point, injections),
+            Step::Emit(count) => {
+                for n in 0..count {
+                    visualizer
+                        .emit(None, "scenario_tick", json!({ "n": n }), None)
+                        .await;
+                }
+            }
+            Step::Flush(timeout) => flushes.push(visualizer.flush(timeout).await),
+            Step::Wait(duration) => tokio::time::sleep(duration).await,
+        }
+    }
+
+    let sink = sink.log.lock().map(|log| log.clone()).unwrap_or_default();
+    ScenarioOutcome { sink, flushes }
+}
+
+/// A visualizer whose relay is an in-memory sink behind `failpoints`, so
+/// tests elsewhere in the crate can break delivery mid-session.
+#[cfg(test)]
+pub(crate) fn visualizer_with_failpoints(failpoints: &Failpoints) -> AgentVisualizer {
+    scenario_visualizer(None, failpoints, RecordingConnector::default())
+}
+
+/// Like [`visualizer_with_failpoints`], also returning the log of what
+/// reached the sink.
+#[cfg(test)]
+pub(crate) fn recorded_visualizer_with_failpoints(
+    failpoints: &Failpoints,
+) -> (AgentVisualizer, Arc<Mutex<SinkLog>>) {
+    let sink = RecordingConnector::default();
+    let log = Arc::clone(&sink.log);
+    (scenario_visualizer(None, failpoints, sink), log)
+}
+
+fn scenario_visualizer(
+    idle_shutdown: Option<Duration>,
+    failpoints: &Failpoints,
+    sink: RecordingConnector,
+) -> AgentVisualizer {
+    AgentVisualizer::with_connector(
+        Some(SCENARIO_URL.to_string()),
+        TelemetryFidelity::Full,
+        idle_shutdown,
+        RetentionRules::default(),
+        TimestampEncoding::Number,
+        TrustedRoots::default(),
+        Arc::new(FailpointConnector {
+            inner: Arc::new(sink),
+            failpoints: failpoints.clone(),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::config_types::VisualizerConnect;
+    use crate::config_types::VisualizerDropPolicy;
+    use crate::retry::Jitter;
+    use crate::retry::RetryPolicy;
+    use crate::visualizer::DEFAULT_MAX_CONNECT_FAILURES;
+    use crate::visualizer::EventBatching;
+    use crate::visualizer::Gap;
+    use crate::visualizer::Sink;
+    use crate::visualizer::VisualizerStatus;
+    use crate::visualizer::forwarder::MAX_RESTARTS;
+
+    fn failures(count: usize) -> Vec<Injection> {
+        vec![Injection::Fail; count]
+    }
+
+    /// Offsets of each connection attempt from the first.
+    fn connect_schedule(failpoints: &Failpoints) -> Vec<Duration> {
+        let calls = failpoints.calls(Failpoint::Connect);
+        calls
+            .iter()
+            .map(|call| call.duration_since(calls[0]))
+            .collect()
+    }
+
+    /// The default reconnect schedule without its jitter, so delays can be
+    /// asserted exactly.
+    fn unjittered_visualizer(failpoints: &Failpoints) -> AgentVisualizer {
+        visualizer_with_failpoints(failpoints).with_reconnect_policy(RetryPolicy {
+            jitter: Jitter::None,
+            ..RetryPolicy::VISUALIZER_RECONNECT
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_connects_are_retried_with_doubling_delays() {
+        let failpoints = Failpoints::default();
+        let visualizer = unjittered_visualizer(&failpoints);
+        failpoints.arm(Failpoint::Connect, failures(11));
+
+        visualizer
+            .emit(None, "scenario_tick", json!({}), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(300)).await, Some(true));
+        // Doubling from 100ms until the 30s cap, which then holds.
+        assert_eq!(
+            connect_schedule(&failpoints),
+            [
+                0, 100, 300, 700, 1_500, 3_100, 6_300, 12_700, 25_500, 51_100, 81_100, 111_100
+            ]
+            .map(Duration::from_millis)
+            .to_vec()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_send_reconnects_after_the_initial_delay() {
+        let failpoints = Failpoints::default();
+        let visualizer = unjittered_visualizer(&failpoints);
+        failpoints.arm(Failpoint::Send, vec![Injection::Pass, Injection::Fail]);
+
+        for n in 0..2 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(30)).await, Some(true));
+        assert_eq!(
+            connect_schedule(&failpoints),
+            [0, 100].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    /// Connections that accept and then drop the first send keep backing
+    /// off; a delivered event resets the schedule.
+    #[tokio::test(start_paused = true)]
+    async fn delivered_event_resets_the_backoff() {
+        let failpoints = Failpoints::default();
+        let visualizer = unjittered_visualizer(&failpoints);
+        failpoints.arm(
+            Failpoint::Send,
+            vec![
+                Injection::Pass,
+                Injection::Fail,
+                Injection::Fail,
+                Injection::Pass,
+                Injection::Fail,
+            ],
+        );
+
+        for n in 0..3 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(30)).await, Some(true));
+        assert_eq!(
+            connect_schedule(&failpoints),
+            [0, 100, 300, 400].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn event_stays_queued_once_reconnect_attempts_run_out() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_reconnect_policy(RetryPolicy {
+                initial_delay: Duration::from_millis(100),
+                multiplier: 2.0,
+                max_delay: Duration::from_secs(1),
+                jitter: Jitter::None,
+                max_attempts: Some(3),
+            });
+        failpoints.arm(Failpoint::Connect, failures(3));
+
+        for n in 0..2 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(30)).await, Some(true));
+        // The third failure runs out of attempts; the retry after the next
+        // delay on the schedule connects.
+        assert_eq!(
+            connect_schedule(&failpoints),
+            [0, 100, 300, 700].map(Duration::from_millis).to_vec()
+        );
+        let delivered = sink.log.lock().map(|log| log.delivered.clone());
+        assert_eq!(delivered.ok(), Some(vec![0, 1]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnect_storm_delivers_each_event_once_in_order() {
+        let outcome = run_scenario(
+            None,
+            vec![
+                Step::Arm(Failpoint::Connect, failures(5)),
+                Step::Emit(3),
+                Step::Flush(Duration::from_secs(30)),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 1, 2],
+                    connections: 1,
+                    closes: 0,
+                },
+                flushes: vec![Some(true)],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_send_is_replayed_on_a_new_connection() {
+        let outcome = run_scenario(
+            None,
+            vec![
+                Step::Arm(
+                    Failpoint::Send,
+                    vec![
+                        Injection::Pass,
+                        Injection::Fail,
+                        Injection::Pass,
+                        Injection::Fail,
+                    ],
+                ),
+                Step::Emit(4),
+                Step::Flush(Duration::from_secs(30)),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 1, 2, 3],
+                    connections: 3,
+                    closes: 0,
+                },
+                flushes: vec![Some(true)],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_relay_reports_undelivered_until_it_catches_up() {
+        let outcome = run_scenario(
+            None,
+            vec![
+                Step::Arm(
+                    Failpoint::Send,
+                    vec![Injection::Delay(Duration::from_secs(3))],
+                ),
+                Step::Emit(2),
+                Step::Flush(Duration::from_secs(1)),
+                Step::Flush(Duration::from_secs(5)),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 1],
+                    connections: 1,
+                    closes: 0,
+                },
+                flushes: vec![Some(false), Some(true)],
+            }
+        );
+    }
+
+    /// Short of the failure limit, an unreachable relay is not given up
+    /// on: the forwarder keeps retrying and delivers the backlog once the
+    /// relay comes back.
+    #[tokio::test(start_paused = true)]
+    async fn unreachable_relay_is_retried_up_to_the_failure_limit() {
+        let outcome = run_scenario(
+            None,
+            vec![
+                Step::Arm(
+                    Failpoint::Connect,
+                    failures(DEFAULT_MAX_CONNECT_FAILURES as usize - 1),
+                ),
+                Step::Emit(2),
+                Step::Flush(Duration::from_secs(10)),
+                Step::Flush(Duration::from_secs(60 * 60)),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 1],
+                    connections: 1,
+                    closes: 0,
+                },
+                flushes: vec![Some(false), Some(true)],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn relay_is_given_up_on_after_the_failure_limit() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer =
+            scenario_visualizer(None, &failpoints, sink.clone()).with_max_connect_failures(Some(3));
+        failpoints.arm(Failpoint::Connect, failures(100));
+
+        for n in 0..2 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(60)).await, Some(false));
+        assert_eq!(
+            visualizer.status(),
+            VisualizerStatus::Abandoned {
+                connect_failures: 3
+            }
+        );
+
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": "late" }), None)
+            .await;
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        assert_eq!(failpoints.calls(Failpoint::Connect).len(), 3);
+        let delivered = sink.log.lock().map(|log| log.delivered.clone());
+        assert_eq!(delivered.ok(), Some(Vec::new()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_close_while_parking_loses_nothing() {
+        let idle = Duration::from_millis(50);
+        let outcome = run_scenario(
+            Some(idle),
+            vec![
+                Step::Arm(Failpoint::Close, failures(1)),
+                Step::Emit(2),
+                Step::Flush(Duration::from_secs(5)),
+                Step::Wait(idle * 4),
+                Step::Emit(2),
+                Step::Flush(Duration::from_secs(5)),
+                Step::Wait(idle * 4),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 1, 2, 3],
+                    connections: 2,
+                    closes: 1,
+                },
+                flushes: vec![Some(true), Some(true)],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicking_forwarder_is_replaced_and_delivery_continues() {
+        let outcome = run_scenario(
+            None,
+            vec![
+                Step::Arm(Failpoint::Send, vec![Injection::Pass, Injection::Panic]),
+                Step::Emit(4),
+                Step::Flush(Duration::from_secs(30)),
+            ],
+        )
+        .await;
+
+        // Event 1 was being sent when the forwarder panicked; the
+        // replacement opens its own connection for the rest.
+        assert_eq!(
+            outcome,
+            ScenarioOutcome {
+                sink: SinkLog {
+                    delivered: vec![0, 2, 3],
+                    connections: 2,
+                    closes: 0,
+                },
+                flushes: vec![Some(true)],
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn forwarder_that_keeps_panicking_is_reported_failed() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone());
+        failpoints.arm(Failpoint::Send, vec![Injection::Panic; MAX_RESTARTS]);
+
+        for n in 0..MAX_RESTARTS + 1 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(30)).await, Some(true));
+        let panics = vec!["failpoint Send".to_string(); MAX_RESTARTS];
+        assert_eq!(
+            visualizer.status(),
+            VisualizerStatus::Running {
+                recovered_panics: panics.clone(),
+                connect_latency: Some(Duration::ZERO),
+            }
+        );
+
+        failpoints.arm(Failpoint::Send, vec![Injection::Panic]);
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": "last" }), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(5)).await, Some(false));
+        let mut all_panics = panics;
+        all_panics.push("failpoint Send".to_string());
+        assert_eq!(
+            visualizer.status(),
+            VisualizerStatus::Failed { panics: all_panics }
+        );
+        let delivered = sink.log.lock().map(|log| log.delivered.clone());
+        assert_eq!(delivered.ok(), Some(vec![MAX_RESTARTS as u64]));
+    }
+
+    /// When the first event is written, relative to its emit, with the
+    /// relay taking `handshake` to accept a connection.
+    async fn first_send_delay(mode: VisualizerConnect, handshake: Duration) -> Duration {
+        let failpoints = Failpoints::default();
+        failpoints.arm(Failpoint::Connect, vec![Injection::Delay(handshake)]);
+        let visualizer = visualizer_with_failpoints(&failpoints).with_connect_mode(mode);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+
+        let emitted = Instant::now();
+        visualizer
+            .emit(None, "scenario_tick", json!({}), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(30)).await, Some(true));
+        assert_eq!(
+            visualizer.status(),
+            VisualizerStatus::Running {
+                recovered_panics: Vec::new(),
+                connect_latency: Some(handshake),
+            }
+        );
+        let sends = failpoints.calls(Failpoint::Send);
+        assert_eq!(sends.len(), 1);
+        sends[0].duration_since(emitted)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eager_connect_writes_the_first_event_without_waiting_for_the_handshake() {
+        let handshake = Duration::from_secs(2);
+        assert_eq!(
+            first_send_delay(VisualizerConnect::Eager, handshake).await,
+            Duration::ZERO
+        );
+        assert_eq!(
+            first_send_delay(VisualizerConnect::Lazy, handshake).await,
+            handshake
+        );
+    }
+
+    /// Heartbeat offsets from the start, whether the forwarder is still
+    /// running, and what the sink saw, after idling for 95 seconds with a
+    /// 45 second idle shutdown.
+    async fn idle_standby(mode: VisualizerConnect) -> (Vec<Duration>, bool, SinkLog) {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer =
+            scenario_visualizer(Some(Duration::from_secs(45)), &failpoints, sink.clone())
+                .with_connect_mode(mode);
+        tokio::time::sleep(Duration::from_secs(95)).await;
+
+        let heartbeats = failpoints
+            .calls(Failpoint::Heartbeat)
+            .iter()
+            .map(|call| call.duration_since(started))
+            .collect();
+        let running = visualizer
+            .sink
+            .as_ref()
+            .is_some_and(|sink| sink.forwarder.is_running());
+        let sink = sink.log.lock().map(|log| log.clone()).unwrap_or_default();
+        (heartbeats, running, sink)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eager_connection_is_heartbeated_while_idle() {
+        // `eager` ignores the idle shutdown.
+        assert_eq!(
+            idle_standby(VisualizerConnect::Eager).await,
+            (
+                [30, 60, 90].map(Duration::from_secs).to_vec(),
+                true,
+                SinkLog {
+                    delivered: Vec::new(),
+                    connections: 1,
+                    closes: 0,
+                },
+            )
+        );
+        assert_eq!(
+            idle_standby(VisualizerConnect::EagerIdleShutdown).await,
+            (
+                vec![Duration::from_secs(30)],
+                false,
+                SinkLog {
+                    delivered: Vec::new(),
+                    connections: 1,
+                    closes: 1,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connection_is_replaced_when_a_heartbeat_goes_unanswered() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_heartbeat(Duration::from_secs(1), Duration::from_millis(500))
+            .with_reconnect_policy(RetryPolicy {
+                jitter: Jitter::None,
+                ..RetryPolicy::VISUALIZER_RECONNECT
+            });
+        // The first pong is answered; the second arrives after the timeout.
+        failpoints.arm(
+            Failpoint::Pong,
+            vec![Injection::Pass, Injection::Delay(Duration::from_secs(5))],
+        );
+
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 0 }), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(1)).await, Some(true));
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 1 }), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(1)).await, Some(true));
+
+        let offsets = |point| {
+            failpoints
+                .calls(point)
+                .iter()
+                .map(|call| call.duration_since(started))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            (
+                offsets(Failpoint::Heartbeat),
+                offsets(Failpoint::Connect),
+                sink.log.lock().map(|log| log.clone()).unwrap_or_default()
+            ),
+            (
+                [1_000, 2_000, 3_600].map(Duration::from_millis).to_vec(),
+                // The pong is given up on at 2.5s, and the replacement is
+                // connected 100ms later, before the next event.
+                [0, 2_600].map(Duration::from_millis).to_vec(),
+                SinkLog {
+                    delivered: vec![0, 1],
+                    connections: 2,
+                    closes: 0,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_are_sent_while_a_pong_is_awaited() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_heartbeat(Duration::from_secs(1), Duration::from_secs(3));
+        // The first pong takes 2s, well within the timeout.
+        failpoints.arm(
+            Failpoint::Pong,
+            vec![Injection::Delay(Duration::from_secs(2))],
+        );
+
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 0 }), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(1)).await, Some(true));
+        tokio::time::sleep_until(started + Duration::from_millis(1_500)).await;
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 1 }), None)
+            .await;
+        assert_eq!(
+            visualizer.flush(Duration::from_millis(100)).await,
+            Some(true)
+        );
+
+        let offsets = |point| {
+            failpoints
+                .calls(point)
+                .iter()
+                .map(|call| call.duration_since(started))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            (
+                offsets(Failpoint::Heartbeat),
+                offsets(Failpoint::Send),
+                sink.log.lock().map(|log| log.clone()).unwrap_or_default()
+            ),
+            (
+                vec![Duration::from_secs(1)],
+                // Sent as it arrived, not once the pong was in.
+                [0, 1_500].map(Duration::from_millis).to_vec(),
+                SinkLog {
+                    delivered: vec![0, 1],
+                    connections: 1,
+                    closes: 0,
+                },
+            )
+        );
+    }
+
+    /// Emit five events behind a relay that takes `per_send` to accept each,
+    /// shut down with `timeout`, and report what was left undelivered, the
+    /// offsets of the sends and closes, and what reached the sink.
+    async fn shut_down_slow_relay(
+        per_send: Duration,
+        timeout: Duration,
+    ) -> (Option<u64>, Vec<Duration>, Vec<Duration>, SinkLog) {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone());
+        failpoints.arm(Failpoint::Send, vec![Injection::Delay(per_send); 5]);
+
+        for n in 0..5 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        let undelivered = visualizer.shutdown(timeout).await;
+
+        let offsets = |point| {
+            failpoints
+                .calls(point)
+                .iter()
+                .map(|call| call.duration_since(started))
+                .collect()
+        };
+        let sink = sink.log.lock().map(|log| log.clone()).unwrap_or_default();
+        (
+            undelivered,
+            offsets(Failpoint::Send),
+            offsets(Failpoint::Close),
+            sink,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_delivers_the_queue_before_closing() {
+        assert_eq!(
+            shut_down_slow_relay(Duration::from_millis(100), Duration::from_secs(3)).await,
+            (
+                Some(0),
+                [0, 100, 200, 300, 400].map(Duration::from_millis).to_vec(),
+                vec![Duration::from_millis(500)],
+                SinkLog {
+                    delivered: vec![0, 1, 2, 3, 4],
+                    connections: 1,
+                    closes: 1,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_the_last_handle_delivers_the_queue_before_closing() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone());
+        failpoints.arm(
+            Failpoint::Send,
+            vec![Injection::Delay(Duration::from_millis(100)); 5],
+        );
+
+        for n in 0..5 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        drop(visualizer);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(
+            sink.log.lock().map(|log| log.clone()).unwrap_or_default(),
+            SinkLog {
+                delivered: vec![0, 1, 2, 3, 4],
+                connections: 1,
+                closes: 1,
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_counts_what_it_could_not_deliver_in_time() {
+        assert_eq!(
+            shut_down_slow_relay(Duration::from_secs(1), Duration::from_millis(2_500)).await,
+            (
+                Some(3),
+                [0, 1, 2].map(Duration::from_secs).to_vec(),
+                Vec::new(),
+                SinkLog {
+                    delivered: vec![0, 1],
+                    connections: 1,
+                    closes: 0,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_oldest_keeps_the_newest_events_behind_a_slow_relay() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_drop_policy(VisualizerDropPolicy::DropOldest);
+        failpoints.arm(
+            Failpoint::Send,
+            vec![Injection::Delay(Duration::from_secs(1))],
+        );
+
+        // Four more than the queue holds at its largest.
+        let emitted = 4100;
+        for n in 0..emitted {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        let dropped = visualizer.dropped_events();
+        visualizer.shutdown(Duration::from_secs(60)).await;
+
+        let delivered = sink
+            .log
+            .lock()
+            .map(|log| log.delivered.clone())
+            .unwrap_or_default();
+        assert_eq!(
+            (dropped, delivered),
+            (4, (4..emitted).collect::<Vec<u64>>())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn emits_never_wait_on_a_saturated_queue_under_drop_oldest() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_queue_capacity(Some(2))
+            .with_drop_policy(VisualizerDropPolicy::DropOldest);
+        failpoints.arm(
+            Failpoint::Send,
+            vec![Injection::Delay(Duration::from_secs(3600))],
+        );
+
+        // Any wait on the stalled relay would advance the paused clock.
+        let started = Instant::now();
+        for n in 0..100 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(
+            (
+                started.elapsed(),
+                visualizer.drop_summary(),
+                visualizer.drop_summary()
+            ),
+            (
+                Duration::ZERO,
+                Some(json!({
+                    "dropped": 98,
+                    "droppedTotal": 98,
+                    "droppedStale": 0,
+                    "droppedStaleTotal": 0,
+                    "nextSequence": 100,
+                })),
+                None,
+            )
+        );
+    }
+
+    /// Offsets of each frame written from `started`, and what reached the
+    /// sink, once `visualizer` has shut down.
+    async fn frame_offsets(
+        visualizer: &AgentVisualizer,
+        failpoints: &Failpoints,
+        sink: &RecordingConnector,
+        started: Instant,
+    ) -> (Vec<Duration>, SinkLog) {
+        visualizer.shutdown(Duration::from_secs(5)).await;
+        let offsets = failpoints
+            .calls(Failpoint::Send)
+            .iter()
+            .map(|call| call.duration_since(started))
+            .collect();
+        let sink = sink.log.lock().map(|log| log.clone()).unwrap_or_default();
+        (offsets, sink)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_are_sent_once_their_interval_is_up_or_on_shutdown() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone()).with_event_batching(
+            EventBatching {
+                interval: Duration::from_millis(50),
+                max_events: 100,
+                max_bytes: usize::MAX,
+            },
+        );
+
+        for n in 0..3 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for n in 3..5 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+
+        // The second batch goes out as the queue closes, not 50ms later.
+        assert_eq!(
+            frame_offsets(&visualizer, &failpoints, &sink, started).await,
+            (
+                [50, 200].map(Duration::from_millis).to_vec(),
+                SinkLog {
+                    delivered: vec![0, 1, 2, 3, 4],
+                    connections: 1,
+                    closes: 1,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn full_batches_and_flushes_do_not_wait_for_the_interval() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone()).with_event_batching(
+            EventBatching {
+                interval: Duration::from_secs(3600),
+                max_events: 2,
+                max_bytes: usize::MAX,
+            },
+        );
+
+        for n in 0..3 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(visualizer.flush(Duration::from_secs(5)).await, Some(true));
+        assert_eq!(
+            frame_offsets(&visualizer, &failpoints, &sink, started).await,
+            (
+                vec![Duration::ZERO; 2],
+                SinkLog {
+                    delivered: vec![0, 1, 2],
+                    connections: 1,
+                    closes: 1,
+                },
+            )
+        );
+    }
+
+    /// Ten events, nine of which queue up behind a stalled send, take four
+    /// frames rather than ten with a zero interval.
+    #[tokio::test(start_paused = true)]
+    async fn a_backlog_is_coalesced_into_frames_of_at_most_max_events() {
+        let failpoints = Failpoints::default();
+        failpoints.arm(
+            Failpoint::Send,
+            [Injection::Delay(Duration::from_millis(100))],
+        );
+        let sink = RecordingConnector::default();
+        let started = Instant::now();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone()).with_event_batching(
+            EventBatching {
+                interval: Duration::ZERO,
+                max_events: 4,
+                max_bytes: usize::MAX,
+            },
+        );
+
+        for n in 0..10 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+            if n == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        assert_eq!(
+            frame_offsets(&visualizer, &failpoints, &sink, started).await,
+            (
+                [0, 100, 100, 100].map(Duration::from_millis).to_vec(),
+                SinkLog {
+                    delivered: (0..10).collect(),
+                    connections: 1,
+                    closes: 1,
+                },
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queue_drops_are_reported_as_one_gap_by_action_type() {
+        let failpoints = Failpoints::default();
+        let visualizer = visualizer_with_failpoints(&failpoints).with_queue_capacity(Some(2));
+
+        // The forwarder takes nothing before the emits are done, so all but
+        // the first two are dropped.
+        for n in 0..10 {
+            let action_type = if n % 2 == 0 {
+                "scenario_tick"
+            } else {
+                "scenario_tock"
+            };
+            visualizer
+                .emit(None, action_type, json!({ "n": n }), None)
+                .await;
+        }
+        assert_eq!(
+            (visualizer.take_gap(), visualizer.take_gap()),
+            (
+                Some(Gap {
+                    dropped: BTreeMap::from([
+                        ("scenario_tick".to_string(), 4),
+                        ("scenario_tock".to_string(), 4),
+                    ]),
+                    first_sequence: 2,
+                    last_sequence: 9,
+                }),
+                None,
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_gap_detector_sees_the_hole_dropped_events_leave() {
+        let failpoints = Failpoints::default();
+        let holes = Arc::new(Mutex::new(Vec::new()));
+        let visualizer = visualizer_with_failpoints(&failpoints)
+            .with_queue_capacity(Some(2))
+            .with_gap_detector({
+                let holes = Arc::clone(&holes);
+                Arc::new(move |expected, received| {
+                    holes.lock().expect("holes").push((expected, received));
+                })
+            });
+
+        // As above, only the first two of these are queued.
+        for n in 0..5 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 5 }), None)
+            .await;
+        assert_eq!(visualizer.flush(Duration::from_secs(5)).await, Some(true));
+
+        assert_eq!(*holes.lock().expect("holes"), vec![(2, 5)]);
+    }
+
+    // TTLs run on the wall clock, which paused time does not advance.
+    #[tokio::test]
+    async fn events_whose_ttl_ran_out_during_a_slow_connect_are_dropped() {
+        let failpoints = Failpoints::default();
+        let sink = RecordingConnector::default();
+        let visualizer = scenario_visualizer(None, &failpoints, sink.clone())
+            .with_default_ttl(Some(Duration::from_millis(50)));
+        failpoints.arm(
+            Failpoint::Connect,
+            vec![Injection::Delay(Duration::from_millis(300))],
+        );
+
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 0 }), None)
+            .await;
+        visualizer
+            .emit_with_ttl(
+                None,
+                "scenario_tick",
+                json!({ "n": 1 }),
+                None,
+                Duration::from_secs(60),
+            )
+            .await;
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 2 }), None)
+            .await;
+        visualizer.shutdown(Duration::from_secs(5)).await;
+
+        assert_eq!(
+            (
+                sink.log.lock().map(|log| log.clone()).unwrap_or_default(),
+                visualizer.drop_summary(),
+                visualizer.take_gap(),
+            ),
+            (
+                SinkLog {
+                    delivered: vec![1],
+                    connections: 1,
+                    closes: 1,
+                },
+                Some(json!({
+                    "dropped": 0,
+                    "droppedTotal": 0,
+                    "droppedStale": 2,
+                    "droppedStaleTotal": 2,
+                    "nextSequence": 3,
+                })),
+                Some(Gap {
+                    dropped: BTreeMap::from([("scenario_tick".to_string(), 2)]),
+                    first_sequence: 0,
+                    last_sequence: 2,
+                }),
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnecting_after_dropped_events_is_a_recovery() {
+        let failpoints = Failpoints::default();
+        let visualizer = visualizer_with_failpoints(&failpoints)
+            .with_reconnect_policy(RetryPolicy {
+                max_attempts: Some(1),
+                ..RetryPolicy::VISUALIZER_RECONNECT
+            })
+            .with_queue_capacity(Some(1));
+        let recoveries = visualizer.gap_recoveries();
+        failpoints.arm(Failpoint::Connect, failures(1));
+
+        // The forwarder holds the first event through the failed connect,
+        // the second waits in the queue and the third does not fit.
+        visualizer
+            .emit(None, "scenario_tick", json!({ "n": 0 }), None)
+            .await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for n in 1..3 {
+            visualizer
+                .emit(None, "scenario_tick", json!({ "n": n }), None)
+                .await;
+        }
+        let before = recoveries.has_changed().ok();
+        assert_eq!(visualizer.flush(Duration::from_secs(5)).await, Some(true));
+
+        assert_eq!(
+            (before, recoveries.has_changed().ok(), visualizer.take_gap()),
+            (
+                Some(false),
+                Some(true),
+                Some(Gap {
+                    dropped: BTreeMap::from([("scenario_tick".to_string(), 1)]),
+                    first_sequence: 2,
+                    last_sequence: 2,
+                }),
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_mirror_dying_mid_stream_does_not_hold_up_the_primary() {
+        let primary = RecordingConnector::default();
+        let mirror = RecordingConnector::default();
+        let mirror_failpoints = Failpoints::default();
+        let mut visualizer = scenario_visualizer(None, &Failpoints::default(), primary.clone());
+        visualizer.mirrors.push(Sink::start(
+            SCENARIO_URL.to_string(),
+            TelemetryFidelity::Full,
+            None,
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+            Arc::new(FailpointConnector {
+                inner: Arc::new(mirror.clone()),
+                failpoints: mirror_failpoints.clone(),
+            }),
+            &visualizer.sequence,
+            visualizer.clock,
+            &Arc::default(),
+        ));
+        let visualizer = visualizer.with_max_connect_failures(Some(1));
+
+        let emit = |range: std::ops::Range<u64>| {
+            let visualizer = visualizer.clone();
+            async move {
+                for n in range {
+                    visualizer
+                        .emit(None, "scenario_tick", json!({ "n": n }), None)
+                        .await;
+                }
+            }
+        };
+        emit(0..3).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // The mirror's connection drops and it never comes back.
+        mirror_failpoints.arm(Failpoint::Send, failures(1));
+        mirror_failpoints.arm(Failpoint::Connect, failures(100));
+        emit(3..6).await;
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        let delivered = |sink: &RecordingConnector| {
+            sink.log
+                .lock()
+                .map(|log| log.delivered.clone())
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            (delivered(&primary), delivered(&mirror)),
+            (vec![0, 1, 2, 3, 4, 5], vec![0, 1, 2])
+        );
+    }
+}
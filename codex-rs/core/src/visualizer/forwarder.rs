@@ -0,0 +1,1665 @@
+//! Websocket forwarder that drains the event queue.
+//!
+//! The forwarder task is started by the first `emit` rather than when the
+//! visualizer is built, so sessions that never emit (for example ones created
+//! only to read config) cost no task. It also parks itself after the queue
+//! has been idle for the configured period, and the next emit starts it again.
+//! The queue, sequence counter, and recent-event ring are not owned by the
+//! task, so a restart neither loses nor repeats events.
+//!
+//! In the eager [`VisualizerConnect`] modes the visualizer starts the task
+//! as soon as it is built, and the task connects before any event arrives,
+//! so the first one is written without waiting for the handshake. While
+//! idle it heartbeats that connection every [`HEARTBEAT_INTERVAL`]; only
+//! `eager-idle-shutdown` parks it, after which the next emit reconnects as
+//! in lazy mode.
+//!
+//! A configured [`Heartbeat`] applies in every mode and also waits for the
+//! relay's pong, so a connection that died without a close, which accepts
+//! sends until the kernel gives up on it, is noticed while idle. An event
+//! arriving meanwhile is sent at once rather than after the pong. While it
+//! waits for the next event the forwarder also reads the connection, so a
+//! relay closing it is noticed at once. Either way the dead connection is
+//! replaced right away, after the same backoff as a failed send, rather
+//! than by the next event. Pings are written by the forwarder between
+//! events, on the same connection, so they never reorder them. A relay
+//! closing the connection while events are being sent, for instance to
+//! rotate it, fails the next send with [`Error::ConnectionClosed`]; that
+//! event stays pending and is resent on the new connection, and the close
+//! is logged as routine rather than as an error.
+//!
+//! The task runs under a supervisor. If it panics, the panic is recorded in
+//! the diagnostics and a fresh forwarder, with a new connection, takes over
+//! the same queue; only the event being sent at the time is lost, and emits
+//! in between keep buffering. After [`MAX_RESTARTS`] restarts the next
+//! panic stops delivery for good, which `AgentVisualizer::status` reports.
+//!
+//! Connections are retried on the configured [`RetryPolicy`], by default
+//! backing off exponentially with jitter. Failed connects and failed sends
+//! both move the schedule along, and only a successful send resets it (see
+//! [`Backoff`]), so a relay that accepts connections and then drops them
+//! is backed off from like one that is down. An event whose reconnect runs
+//! out of attempts stays queued, and is retried with as many again after
+//! the next delay; meanwhile the queue fills and drops what does not fit,
+//! like it would behind a slow relay. After
+//! the configured number of failed connects in a row, however they were
+//! spread over events, the relay is given up on: the forwarder logs it
+//! once, drops everything queued and stops for good, and later emits queue
+//! nothing. A relay refused for presenting the wrong pinned key is not
+//! retried either: the forwarder records the mismatch and stops for good.
+//!
+//! Every connection a relay accepts is greeted with a `hello` frame before
+//! the first event (see the `hello` module), so a reconnect greets again.
+//! Right after the greeting the relay is replayed the events the queue
+//! dropped, for instance while no relay could be reached, if replay is on (see the `replay` module); a
+//! replay that fails part way is retried on a new connection, and counts
+//! as a failed connect.
+//!
+//! An event sent as several `event_chunk` frames (see the `chunks` module)
+//! is written in one go, and a failure part way through resends all of
+//! them, so no other frame ever lands inside a run.
+//!
+//! An event with a TTL that ran out while it waited, for instance through
+//! a long reconnect, is dropped instead of sent, counted in
+//! [`ForwarderHealth::dropped_stale`], which the drop summary reports, and
+//! as a gap.
+//!
+//! With [`EventBatching`] on, the event taken from the queue opens a batch
+//! that is filled before anything is written (see the `batch` module). The
+//! batch is sent, retried, and dropped as a whole like a single event
+//! would be, and the queue is not drained in between batches.
+
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Error;
+use tracing::debug;
+use tracing::error;
+use tracing::warn;
+
+use super::DEFAULT_MAX_CONNECT_FAILURES;
+use super::DEFAULT_MAX_EVENT_BYTES;
+use super::SerializationFailures;
+use super::VisualizerEvent;
+use super::auth::auth_rejected;
+use super::batch;
+use super::batch::EventBatching;
+use super::buffer::EventQueue;
+use super::chunks;
+use super::chunks::EventChunking;
+use super::coarse::SinkTransform;
+use super::connection::Connection;
+use super::connection::Connector;
+use super::gap_detector::GapDetector;
+use super::hello;
+use super::hello::Capabilities;
+use super::now_ms;
+use super::pins::PinMismatch;
+use super::pins::pin_mismatch;
+use super::replay::ReplayBuffer;
+use super::size_cap;
+use crate::config_types::VisualizerConnect;
+use crate::retry::RetryPolicy;
+
+/// Panics the supervisor recovers from before giving up.
+pub(super) const MAX_RESTARTS: usize = 3;
+
+/// How often an eagerly opened connection is heartbeated while idle.
+pub(super) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub(super) struct Forwarder {
+    pub(super) queue: Arc<EventQueue>,
+    pub(super) connector: Arc<dyn Connector>,
+    pub(super) connect_url: String,
+    pub(super) transform: SinkTransform,
+    pub(super) failures: SerializationFailures,
+    /// One past the highest sequence written to the socket.
+    pub(super) delivered: Arc<watch::Sender<u64>>,
+    /// Park after this long without an event; `None` keeps the task alive
+    /// until the queue closes.
+    pub(super) idle_shutdown: Option<Duration>,
+    /// Shared with the visualizer, which may replace it before the first
+    /// emit.
+    pub(super) reconnect: Arc<Mutex<RetryPolicy>>,
+    /// Shared with the visualizer like `reconnect`; `None` sends every
+    /// event as one frame.
+    pub(super) chunking: Arc<Mutex<Option<EventChunking>>>,
+    /// Shared with the visualizer like `reconnect`; caps the size of
+    /// events not sent in chunks, with `None` leaving them whole.
+    pub(super) max_event_bytes: Arc<Mutex<Option<usize>>>,
+    /// Shared with the visualizer like `reconnect`; `None` writes each
+    /// event on its own as soon as it is taken.
+    pub(super) batching: Arc<Mutex<Option<EventBatching>>>,
+    /// How many `AgentVisualizer::flush` calls are waiting; while any are,
+    /// a batch is sent without waiting out its interval.
+    pub(super) flushes: Arc<watch::Sender<usize>>,
+    /// Set through [`LazyForwarder::set_connect_mode`] before the task
+    /// first starts.
+    pub(super) connect_mode: Arc<Mutex<VisualizerConnect>>,
+    /// Shared with the visualizer like `reconnect`; `None` heartbeats only
+    /// in the eager modes, without waiting for the pong.
+    pub(super) heartbeat: Arc<Mutex<Option<Heartbeat>>>,
+    /// Failed connects in a row after which the relay is given up on;
+    /// `None` never gives up. Shared with the visualizer like `reconnect`.
+    pub(super) max_connect_failures: Arc<Mutex<Option<u32>>>,
+    /// Set to the number of failed connects once the relay is given up on.
+    pub(super) abandoned: Arc<watch::Sender<Option<u32>>>,
+    /// Set once the forwarder exited for good: the queue closed and its
+    /// connection was closed, or delivery stopped.
+    pub(super) stopped: Arc<watch::Sender<bool>>,
+    /// Shared with the visualizer like `reconnect`; sees every sequence
+    /// written.
+    pub(super) gap_detector: Arc<Mutex<Option<GapDetector>>>,
+    /// Shared with the visualizer like `reconnect`, and so kept across
+    /// restarts; holds the events the queue dropped, to replay on connect.
+    pub(super) replay: Arc<Mutex<ReplayBuffer>>,
+    /// What the relay of the current connection accepted; see the `hello`
+    /// module.
+    pub(super) accepted: Capabilities,
+}
+
+enum Slot {
+    Parked(Box<Forwarder>),
+    Running,
+}
+
+/// How an idle connection is heartbeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Heartbeat {
+    /// Idle time before each ping.
+    pub(super) interval: Duration,
+    /// How long the relay has to answer a ping; `None` does not wait.
+    pub(super) timeout: Option<Duration>,
+}
+
+/// Panics of the forwarder task, kept in the visualizer's diagnostics.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct ForwarderHealth {
+    /// Panic messages, oldest first.
+    pub(super) panics: Vec<String>,
+    /// Set by the panic after the last restart; nothing is delivered after.
+    pub(super) failed: bool,
+    /// Why the relay was refused, which also stopped delivery.
+    pub(super) pin_mismatch: Option<PinMismatch>,
+    /// HTTP status the relay refused the token with, which also stopped
+    /// delivery.
+    pub(super) auth_rejected: Option<u16>,
+    /// How long the latest successful connect took, retries included.
+    pub(super) connect_latency: Option<Duration>,
+    /// What the relay of the latest successful connect accepted.
+    pub(super) accepted: Capabilities,
+    /// Events dropped because their TTL ran out before they were sent.
+    pub(super) dropped_stale: u64,
+}
+
+/// What [`Forwarder::wait_for_event`] returned for.
+enum Wake {
+    /// The next event, or `None` once the queue closed.
+    Event(Option<Box<VisualizerEvent>>),
+    /// The queue stayed empty for the idle shutdown.
+    Idle,
+    /// The connection died and was dropped.
+    Dropped,
+}
+
+/// Why [`connect_with_retry`] gave up.
+pub(super) enum ConnectFailure {
+    /// Every attempt the policy allows failed; this many were made.
+    Exhausted(u32),
+    /// This many connects failed in a row, reaching the limit after which
+    /// the relay is given up on.
+    GaveUp(u32),
+    /// The relay's key is not the pinned one, which no retry changes.
+    PinMismatch(PinMismatch),
+    /// The relay refused the token with this HTTP status, which no retry
+    /// changes either.
+    AuthRejected(u16),
+}
+
+/// Where a forwarder is on its reconnect schedule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Backoff {
+    /// Failed connects and sends since the last successful send.
+    failures: u32,
+    /// Failed connects since the last successful one.
+    connect_failures: u32,
+}
+
+impl Backoff {
+    /// Count a failure and return how long to wait before trying again.
+    pub(super) fn next_delay(&mut self, policy: &RetryPolicy) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        policy.delay(self.failures)
+    }
+
+    /// Back to the policy's first delay, after a successful send.
+    pub(super) fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// A connect succeeded, and whatever the caller had to do on the new
+    /// connection before using it, too.
+    pub(super) fn connected(&mut self) {
+        self.connect_failures = 0;
+    }
+
+    /// Count a failed connect. Returns how many failed in a row, once that
+    /// reaches `max_failures`.
+    fn connect_failed(&mut self, max_failures: Option<u32>) -> Option<u32> {
+        self.connect_failures = self.connect_failures.saturating_add(1);
+        max_failures
+            .filter(|max| self.connect_failures >= *max)
+            .map(|_| self.connect_failures)
+    }
+}
+
+/// Starts the forwarder on demand. Whoever holds the slot lock decides
+/// between parking and starting, which is what keeps exactly one task
+/// draining the queue.
+pub(super) struct LazyForwarder {
+    slot: Arc<Mutex<Slot>>,
+    /// Copied to replace a forwarder that panicked.
+    template: Arc<Forwarder>,
+}
+
+impl LazyForwarder {
+    pub(super) fn new(forwarder: Forwarder) -> Self {
+        Self {
+            template: Arc::new(forwarder.clone()),
+            slot: Arc::new(Mutex::new(Slot::Parked(Box::new(forwarder)))),
+        }
+    }
+
+    pub(super) fn health(&self) -> ForwarderHealth {
+        self.template
+            .failures
+            .diagnostics
+            .forwarder
+            .lock()
+            .map(|health| health.clone())
+            .unwrap_or_default()
+    }
+
+    /// Start the forwarder unless it is already running. Callers push their
+    /// event first: a forwarder that is about to park re-checks the queue
+    /// under the same lock and keeps going if it is not empty.
+    pub(super) fn ensure_running(&self) {
+        let Ok(mut slot) = self.slot.lock() else {
+            return;
+        };
+        if let Slot::Parked(forwarder) = std::mem::replace(&mut *slot, Slot::Running) {
+            debug!("starting visualizer websocket forwarder");
+            tokio::spawn(supervise(
+                forwarder,
+                Arc::clone(&self.slot),
+                Arc::clone(&self.template),
+            ));
+        }
+    }
+
+    /// Switch to `mode`, starting the forwarder right away unless it is
+    /// lazy. Outside a runtime there is nothing to start it on, and the
+    /// first emit starts it as usual.
+    pub(super) fn set_connect_mode(&self, mode: VisualizerConnect) {
+        if let Ok(mut connect_mode) = self.template.connect_mode.lock() {
+            *connect_mode = mode;
+        }
+        if mode != VisualizerConnect::Lazy && tokio::runtime::Handle::try_current().is_ok() {
+            self.ensure_running();
+        }
+    }
+
+    /// Wait until the forwarder has exited for good. Only meaningful once
+    /// the queue is closed; a parked forwarder holds no connection and has
+    /// nothing left to deliver, so there is nothing to wait for.
+    pub(super) async fn stopped(&self) {
+        let mut stopped = self.template.stopped.subscribe();
+        let parked = self
+            .slot
+            .lock()
+            .is_ok_and(|slot| matches!(*slot, Slot::Parked(_)));
+        if !parked {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn is_running(&self) -> bool {
+        self.slot
+            .lock()
+            .is_ok_and(|slot| matches!(*slot, Slot::Running))
+    }
+}
+
+/// Run `forwarder`, replacing it with a copy of `template` each time it
+/// panics, until it parks, the queue closes, or it panics once too often.
+/// The slot stays `Running` throughout, so no emit starts a second task.
+async fn supervise(
+    mut forwarder: Box<Forwarder>,
+    slot: Arc<Mutex<Slot>>,
+    template: Arc<Forwarder>,
+) {
+    loop {
+        let err = match tokio::spawn(forwarder.run(Arc::clone(&slot))).await {
+            Ok(()) => return,
+            Err(err) if err.is_panic() => err,
+            Err(_) => return,
+        };
+        let message = panic_message(err.into_panic());
+        let Ok(mut health) = template.failures.diagnostics.forwarder.lock() else {
+            return;
+        };
+        health.panics.push(message.clone());
+        if health.panics.len() > MAX_RESTARTS {
+            health.failed = true;
+            error!(
+                "visualizer forwarder panicked ({message}); giving up after {MAX_RESTARTS} restarts"
+            );
+            // Nothing will drain the queue any more; closing it also
+            // releases emits blocked on it.
+            template.queue.abandon();
+            template.stopped.send_replace(true);
+            return;
+        }
+        error!("visualizer forwarder panicked ({message}); restarting it");
+        drop(health);
+        forwarder = Box::new(template.as_ref().clone());
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .copied()
+            .unwrap_or("non-string panic payload")
+            .to_string(),
+    }
+}
+
+impl Forwarder {
+    async fn run(mut self: Box<Self>, slot: Arc<Mutex<Slot>>) {
+        let mut pending: Vec<VisualizerEvent> = Vec::new();
+        let mut stream: Option<Box<dyn Connection>> = None;
+        let mut backoff = Backoff::default();
+
+        let mode = self
+            .connect_mode
+            .lock()
+            .map(|mode| *mode)
+            .unwrap_or_default();
+        let idle_shutdown = match mode {
+            VisualizerConnect::Eager => None,
+            VisualizerConnect::Lazy | VisualizerConnect::EagerIdleShutdown => self.idle_shutdown,
+        };
+        let configured = self.heartbeat.lock().ok().and_then(|heartbeat| *heartbeat);
+        let heartbeat = configured.or((mode != VisualizerConnect::Lazy).then_some(Heartbeat {
+            interval: HEARTBEAT_INTERVAL,
+            timeout: None,
+        }));
+        let batching = self.batching.lock().ok().and_then(|batching| *batching);
+        if mode != VisualizerConnect::Lazy {
+            match self.connect_ahead(&mut backoff).await {
+                Some(connection) => stream = connection,
+                None => return,
+            }
+        }
+
+        'outer: loop {
+            if pending.is_empty() {
+                let next = match self
+                    .wait_for_event(idle_shutdown, heartbeat, &mut stream)
+                    .await
+                {
+                    Wake::Event(next) => next,
+                    Wake::Dropped => {
+                        tokio::time::sleep(self.retry_delay(&mut backoff)).await;
+                        match self.connect_ahead(&mut backoff).await {
+                            Some(connection) => stream = connection,
+                            None => return,
+                        }
+                        continue;
+                    }
+                    Wake::Idle => {
+                        if let Ok(mut slot) = slot.lock()
+                            && self.queue.is_empty()
+                        {
+                            debug!("visualizer forwarder idle; parking until the next event");
+                            *slot = Slot::Parked(self);
+                        } else {
+                            continue;
+                        }
+                        // Closed outside the slot lock; a forwarder started
+                        // meanwhile opens its own connection.
+                        close(stream).await;
+                        return;
+                    }
+                };
+                match next {
+                    Some(event) => pending.push(*event),
+                    None => break,
+                }
+                if let Some(batching) = batching {
+                    self.fill_batch(&mut pending, batching).await;
+                }
+            }
+
+            if stream.is_none() {
+                match self.connect(&mut backoff).await {
+                    Ok(connection) => stream = Some(connection),
+                    Err(ConnectFailure::Exhausted(attempts)) => {
+                        error!(
+                            "keeping {} queued after {attempts} failed connection attempts",
+                            batch::describe(&pending)
+                        );
+                        tokio::time::sleep(self.retry_delay(&mut backoff)).await;
+                        continue;
+                    }
+                    Err(ConnectFailure::PinMismatch(mismatch)) => {
+                        self.reject(mismatch);
+                        return;
+                    }
+                    Err(ConnectFailure::AuthRejected(status)) => {
+                        self.reject_auth(status);
+                        return;
+                    }
+                    Err(ConnectFailure::GaveUp(failures)) => {
+                        self.abandon(failures);
+                        return;
+                    }
+                }
+            }
+
+            pending.retain(|event| !self.drop_if_stale(event));
+            if pending.is_empty() {
+                continue;
+            }
+
+            let max_bytes = batching.map_or(usize::MAX, |batching| batching.max_bytes);
+            let frames = batch::batch_frames(
+                pending.iter().map(|event| self.frames(event)).collect(),
+                max_bytes,
+            );
+
+            let send_result = match stream.as_mut() {
+                Some(connection) => send_frames(connection.as_mut(), frames).await,
+                None => {
+                    error!("visualizer websocket stream missing before send");
+                    tokio::time::sleep(self.retry_delay(&mut backoff)).await;
+                    continue;
+                }
+            };
+
+            match send_result {
+                Ok(()) => {
+                    backoff.reset();
+                    self.mark_sent(&pending);
+                    pending.clear();
+                    if batching.is_some() {
+                        if !self.flush(&mut stream, &mut backoff).await {
+                            return;
+                        }
+                        continue;
+                    }
+                    loop {
+                        match self.queue.try_recv() {
+                            Ok(next) if self.drop_if_stale(&next) => {}
+                            Ok(next) => {
+                                let frames = self.frames(&next);
+
+                                let backlog_send = match stream.as_mut() {
+                                    Some(connection) => {
+                                        send_frames(connection.as_mut(), frames).await
+                                    }
+                                    None => {
+                                        error!(
+                                            "visualizer websocket stream missing before backlog send"
+                                        );
+                                        pending.push(next);
+                                        tokio::time::sleep(self.retry_delay(&mut backoff)).await;
+                                        continue 'outer;
+                                    }
+                                };
+
+                                if let Err(err) = backlog_send {
+                                    let delay = self.retry_delay(&mut backoff);
+                                    log_send_failure(&err, delay);
+                                    pending.push(next);
+                                    stream = None;
+                                    tokio::time::sleep(delay).await;
+                                    continue 'outer;
+                                }
+                                backoff.reset();
+                                self.mark_sent(std::slice::from_ref(&next));
+                            }
+                            Err(TryRecvError::Empty) => {
+                                if !self.flush(&mut stream, &mut backoff).await {
+                                    return;
+                                }
+                                continue 'outer;
+                            }
+                            Err(TryRecvError::Disconnected) => break 'outer,
+                        }
+                    }
+                }
+                Err(err) => {
+                    let delay = self.retry_delay(&mut backoff);
+                    log_send_failure(&err, delay);
+                    stream = None;
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+        debug!("visualizer queue closed; stopping websocket forwarder");
+        close(stream).await;
+        self.stopped.send_replace(true);
+    }
+
+    /// Wait for the next event while the queue is empty, for at most
+    /// `idle_shutdown`. Meanwhile `stream` is heartbeated on `heartbeat` and
+    /// read, and dropped if a heartbeat fails or goes unanswered or the
+    /// relay closes it.
+    async fn wait_for_event(
+        &self,
+        idle_shutdown: Option<Duration>,
+        heartbeat: Option<Heartbeat>,
+        stream: &mut Option<Box<dyn Connection>>,
+    ) -> Wake {
+        let park_at = idle_shutdown.map(|idle| Instant::now() + idle);
+        // Set while the last heartbeat awaits its pong. An event arriving
+        // meanwhile is sent without waiting for it.
+        let mut pong_due: Option<(Instant, Duration)> = None;
+        loop {
+            let beat_at = heartbeat
+                .filter(|_| stream.is_some() && pong_due.is_none())
+                .map(|heartbeat| Instant::now() + heartbeat.interval);
+            let wake_at = match (park_at, beat_at) {
+                (Some(park_at), Some(beat_at)) => Some(park_at.min(beat_at)),
+                (park_at, beat_at) => park_at.or(beat_at),
+            };
+            let watched = tokio::select! {
+                next = self.queue.recv() => return Wake::Event(next.map(Box::new)),
+                watched = watch(stream, pong_due) => Some(watched),
+                () = sleep_until(wake_at) => None,
+            };
+            match watched {
+                Some(Ok(())) => {
+                    pong_due = None;
+                    continue;
+                }
+                Some(Err(reason)) => {
+                    debug!("visualizer connection lost while idle ({reason}); reconnecting");
+                    *stream = None;
+                    return Wake::Dropped;
+                }
+                None => {}
+            }
+            if park_at.is_some_and(|park_at| Instant::now() >= park_at) {
+                return Wake::Idle;
+            }
+            if let (Some(connection), Some(heartbeat)) = (stream.as_mut(), heartbeat) {
+                if let Err(err) = connection.heartbeat().await {
+                    debug!("visualizer heartbeat failed ({err:?}); reconnecting");
+                    *stream = None;
+                    return Wake::Dropped;
+                }
+                pong_due = heartbeat
+                    .timeout
+                    .map(|timeout| (Instant::now() + timeout, timeout));
+            }
+        }
+    }
+
+    /// Connect before the next event arrives. Returns `None` once delivery
+    /// stopped for good, and `Some(None)` if the next event should try
+    /// again.
+    async fn connect_ahead(
+        &mut self,
+        backoff: &mut Backoff,
+    ) -> Option<Option<Box<dyn Connection>>> {
+        match self.connect(backoff).await {
+            Ok(connection) => Some(Some(connection)),
+            Err(ConnectFailure::Exhausted(attempts)) => {
+                debug!(
+                    "visualizer standby connection failed after {attempts} attempts; connecting on the next event"
+                );
+                Some(None)
+            }
+            Err(ConnectFailure::PinMismatch(mismatch)) => {
+                self.reject(mismatch);
+                None
+            }
+            Err(ConnectFailure::AuthRejected(status)) => {
+                self.reject_auth(status);
+                None
+            }
+            Err(ConnectFailure::GaveUp(failures)) => {
+                self.abandon(failures);
+                None
+            }
+        }
+    }
+
+    /// Flush what `stream` buffered. If that fails, the connection is
+    /// replaced after the backoff of a failed send, without waiting for the
+    /// next event, so a transport that keeps what it failed to flush (see
+    /// the `http` module) retries it. Returns `false` once delivery stopped
+    /// for good.
+    async fn flush(
+        &mut self,
+        stream: &mut Option<Box<dyn Connection>>,
+        backoff: &mut Backoff,
+    ) -> bool {
+        let Some(connection) = stream.as_mut() else {
+            return true;
+        };
+        let Err(err) = connection.flush().await else {
+            return true;
+        };
+        let delay = self.retry_delay(backoff);
+        error!("failed to flush visualizer events: {err:?}; reconnecting in {delay:?}");
+        *stream = None;
+        tokio::time::sleep(delay).await;
+        match self.connect_ahead(backoff).await {
+            Some(connection) => {
+                *stream = connection;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add queued events to `batch` until it holds `batching.max_events`,
+    /// `batching.interval` has passed since it was opened, a flush is
+    /// waiting, or the queue closed.
+    async fn fill_batch(&self, batch: &mut Vec<VisualizerEvent>, batching: EventBatching) {
+        let send_at = Instant::now() + batching.interval;
+        let mut flushes = self.flushes.subscribe();
+        while batch.len() < batching.max_events {
+            tokio::select! {
+                next = tokio::time::timeout_at(send_at, self.queue.recv()) => match next {
+                    Ok(Some(event)) => batch.push(event),
+                    Ok(None) | Err(_) => return,
+                },
+                _ = flushes.wait_for(|waiting| *waiting > 0) => return,
+            }
+        }
+    }
+
+    /// Stop delivery for good over a relay presenting the wrong key,
+    /// dropping what is queued. The slot stays `Running`, so no emit
+    /// restarts the forwarder.
+    fn reject(&self, mismatch: PinMismatch) {
+        error!("{mismatch}; stopping visualizer delivery");
+        if let Ok(mut health) = self.failures.diagnostics.forwarder.lock() {
+            health.pin_mismatch = Some(mismatch);
+        }
+        self.queue.abandon();
+        self.stopped.send_replace(true);
+    }
+
+    /// Stop delivery for good over a relay refusing the token with HTTP
+    /// `status`, dropping what is queued. Like [`Self::reject`], this leaves
+    /// the slot `Running`.
+    fn reject_auth(&self, status: u16) {
+        error!(
+            "visualizer relay rejected authentication (HTTP {status}); stopping visualizer delivery"
+        );
+        if let Ok(mut health) = self.failures.diagnostics.forwarder.lock() {
+            health.auth_rejected = Some(status);
+        }
+        self.queue.abandon();
+        self.stopped.send_replace(true);
+    }
+
+    /// Stop delivery for good after `failures` failed connects in a row,
+    /// dropping what is queued. Like [`Self::reject`], this leaves the slot
+    /// `Running`.
+    fn abandon(&self, failures: u32) {
+        warn!(
+            "visualizer relay {} unreachable after {failures} connection attempts in a row; disabling the visualizer",
+            self.connect_url
+        );
+        self.queue.abandon();
+        self.abandoned.send_replace(Some(failures));
+        self.stopped.send_replace(true);
+    }
+
+    /// Whether `event` outlived its TTL, in which case it is counted as
+    /// dropped stale and as lost, and not to be sent.
+    fn drop_if_stale(&self, event: &VisualizerEvent) -> bool {
+        if !event.is_stale(now_ms()) {
+            return false;
+        }
+        if let Ok(mut health) = self.failures.diagnostics.forwarder.lock() {
+            health.dropped_stale += 1;
+        }
+        self.failures.diagnostics.gaps.record(event);
+        true
+    }
+
+    /// Record that `events`, ordered by sequence, were written.
+    fn mark_sent(&self, events: &[VisualizerEvent]) {
+        if let Ok(mut detector) = self.gap_detector.lock()
+            && let Some(detector) = detector.as_mut()
+        {
+            for event in events {
+                detector.observe(event.sequence);
+            }
+        }
+        if let Some(last) = events.last() {
+            mark_delivered(&self.delivered, last.sequence);
+        }
+    }
+
+    /// Serialize `event` into the frames it is written as; none if that
+    /// failed, which was logged.
+    fn frames(&mut self, event: &VisualizerEvent) -> Vec<String> {
+        let event = self.transform.apply(event);
+        let serialized = self.failures.encode(&event, &self.queue);
+        let chunking = self
+            .chunking
+            .lock()
+            .ok()
+            .and_then(|chunking| *chunking)
+            .filter(|_| self.accepted.chunks);
+        let serialized = match chunking {
+            Some(_) => serialized,
+            None => {
+                let capped = size_cap::capped(
+                    serialized,
+                    &event,
+                    self.max_event_bytes
+                        .lock()
+                        .map_or(Some(DEFAULT_MAX_EVENT_BYTES), |max_bytes| *max_bytes),
+                    self.failures.timestamps,
+                );
+                let Some(capped) = capped else {
+                    return Vec::new();
+                };
+                capped
+            }
+        };
+        chunks::frames(serialized, &event, chunking, self.failures.timestamps)
+    }
+
+    /// Connect on the reconnect policy, recording how long it took, and
+    /// replay the missed events on the new connection. A failed replay
+    /// counts as a failed connect, towards both the policy's attempts and
+    /// giving up on the relay.
+    async fn connect(
+        &mut self,
+        backoff: &mut Backoff,
+    ) -> Result<Box<dyn Connection>, ConnectFailure> {
+        let started = Instant::now();
+        let mut replay_attempts = 0;
+        loop {
+            let policy = reconnect_policy(&self.reconnect);
+            let max_failures = max_connect_failures(&self.max_connect_failures);
+            let chunks = self
+                .chunking
+                .lock()
+                .is_ok_and(|chunking| chunking.is_some());
+            let (mut connection, accepted) = connect_with_retry(
+                self.connector.as_ref(),
+                &self.connect_url,
+                chunks,
+                policy,
+                max_failures,
+                backoff,
+            )
+            .await?;
+            self.accepted = accepted;
+            if let Ok(mut health) = self.failures.diagnostics.forwarder.lock() {
+                health.connect_latency = Some(started.elapsed());
+                health.accepted = accepted;
+            }
+            self.failures.diagnostics.gaps.connected();
+            let Err(err) = self.replay(connection.as_mut()).await else {
+                backoff.connected();
+                return Ok(connection);
+            };
+            replay_attempts += 1;
+            if let Some(failures) = backoff.connect_failed(max_failures) {
+                error!("failed to replay to visualizer relay: {err:?}; giving up on the relay");
+                return Err(ConnectFailure::GaveUp(failures));
+            }
+            if !policy.allows(replay_attempts + 1) {
+                error!("failed to replay to visualizer relay: {err:?}; giving up");
+                return Err(ConnectFailure::Exhausted(replay_attempts));
+            }
+            let delay = self.retry_delay(backoff);
+            log_send_failure(&err, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Write the events kept for replay on `connection`, oldest first, if
+    /// it has a relay at the other end. Those a failure leaves unwritten
+    /// are kept for the next connect.
+    async fn replay(&mut self, connection: &mut dyn Connection) -> Result<(), Error> {
+        if !self.connector.greets() {
+            return Ok(());
+        }
+        let now = now_ms();
+        let events = self
+            .replay
+            .lock()
+            .map(|mut buffer| buffer.take(now))
+            .unwrap_or_default();
+        let mut events: Vec<VisualizerEvent> = events
+            .into_iter()
+            .filter(|event| !event.is_stale(now))
+            .collect();
+        if events.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "replaying {} missed events to the visualizer relay",
+            events.len()
+        );
+        for index in 0..events.len() {
+            let frames = self.frames(&events[index]);
+            if let Err(err) = send_frames(connection, frames).await {
+                if let Ok(mut buffer) = self.replay.lock() {
+                    buffer.restore(events.split_off(index));
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// How long a failed send waits before the reconnect, counted on
+    /// `backoff` like a failed connection attempt.
+    fn retry_delay(&self, backoff: &mut Backoff) -> Duration {
+        backoff.next_delay(&reconnect_policy(&self.reconnect))
+    }
+}
+
+pub(super) fn reconnect_policy(policy: &Mutex<RetryPolicy>) -> RetryPolicy {
+    policy
+        .lock()
+        .map(|policy| *policy)
+        .unwrap_or(RetryPolicy::VISUALIZER_RECONNECT)
+}
+
+pub(super) fn max_connect_failures(max_failures: &Mutex<Option<u32>>) -> Option<u32> {
+    max_failures
+        .lock()
+        .map(|max_failures| *max_failures)
+        .unwrap_or(Some(DEFAULT_MAX_CONNECT_FAILURES))
+}
+
+/// Open a connection to `url` and greet the relay on `policy`, offering
+/// `chunks` along with what the connection offers itself, retrying every
+/// failure but a pin mismatch or a refused token until the policy runs out
+/// of attempts or `max_failures` connects in a row failed, counting those
+/// of earlier calls. The delays continue from wherever `backoff` is, and it
+/// is left where they got to. Returns the connection and what it may use.
+pub(super) async fn connect_with_retry(
+    connector: &dyn Connector,
+    url: &str,
+    chunks: bool,
+    policy: RetryPolicy,
+    max_failures: Option<u32>,
+    backoff: &mut Backoff,
+) -> Result<(Box<dyn Connection>, Capabilities), ConnectFailure> {
+    // Stopping at the connect that reaches `max_failures` is what gives up
+    // on the relay, so the policy is not allowed past it.
+    let policy = match max_failures {
+        Some(max) => {
+            let remaining = max.saturating_sub(backoff.connect_failures).max(1);
+            RetryPolicy {
+                max_attempts: Some(
+                    policy
+                        .max_attempts
+                        .map_or(remaining, |max_attempts| max_attempts.max(1).min(remaining)),
+                ),
+                ..policy
+            }
+        }
+        None => policy,
+    };
+    let mut attempts = 0;
+    let result = policy
+        .resume_with_retry(
+            backoff.failures,
+            |attempt| {
+                attempts = attempt;
+                connect_and_greet(connector, url, chunks)
+            },
+            |err| pin_mismatch(err).is_none() && auth_rejected(err).is_none(),
+            |err, delay| {
+                error!("failed to connect to visualizer websocket: {err:?}; retrying in {delay:?}");
+            },
+        )
+        .await;
+    let retries = attempts.saturating_sub(1);
+    backoff.failures = backoff.failures.saturating_add(retries);
+    backoff.connect_failures = backoff.connect_failures.saturating_add(retries);
+    let err = match result {
+        Ok(connected) => return Ok(connected),
+        Err(err) => err,
+    };
+    if let Some(mismatch) = pin_mismatch(&err) {
+        return Err(ConnectFailure::PinMismatch(mismatch.clone()));
+    }
+    if let Some(status) = auth_rejected(&err) {
+        return Err(ConnectFailure::AuthRejected(status));
+    }
+    if let Some(failures) = backoff.connect_failed(max_failures) {
+        error!("failed to connect to visualizer websocket: {err:?}; giving up on the relay");
+        return Err(ConnectFailure::GaveUp(failures));
+    }
+    error!("failed to connect to visualizer websocket: {err:?}; giving up");
+    Err(ConnectFailure::Exhausted(attempts))
+}
+
+/// Open a connection to `url` and, if the connector greets, write the
+/// `hello` frame on it before anything else, offering `chunks` and what
+/// the connection offers itself. Returns the connection and what it may
+/// use: without a relay to greet, that is whatever is configured.
+async fn connect_and_greet(
+    connector: &dyn Connector,
+    url: &str,
+    chunks: bool,
+) -> Result<(Box<dyn Connection>, Capabilities), Error> {
+    let mut connection = connector.connect(url).await?;
+    let offered = Capabilities {
+        chunks,
+        ..connection.offers()
+    };
+    if !connector.greets() {
+        return Ok((connection, offered));
+    }
+    let accepted = hello::greet(connection.as_mut(), offered).await?;
+    connection.accept(accepted);
+    Ok((connection, accepted))
+}
+
+/// Read and discard what the relay sends on `stream` until it closes the
+/// connection, and say why it ended. Never returns without a connection.
+async fn read_until_closed(stream: &mut Option<Box<dyn Connection>>) -> String {
+    let Some(connection) = stream.as_mut() else {
+        return std::future::pending().await;
+    };
+    loop {
+        match connection.recv().await {
+            Ok(Some(text)) => debug!(
+                "ignoring a {}-byte text frame from the visualizer relay",
+                text.len()
+            ),
+            Ok(None) => return "closed by the relay".to_string(),
+            Err(err) => return format!("{err:?}"),
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Read `stream` until the relay closes it or, with `pong_due` set, until
+/// it answers the last heartbeat. `Err` says why the connection is lost,
+/// including a pong not in by its deadline. Never returns without a
+/// connection.
+async fn watch(
+    stream: &mut Option<Box<dyn Connection>>,
+    pong_due: Option<(Instant, Duration)>,
+) -> Result<(), String> {
+    let Some((deadline, timeout)) = pong_due else {
+        return Err(read_until_closed(stream).await);
+    };
+    let Some(connection) = stream.as_mut() else {
+        return std::future::pending().await;
+    };
+    match tokio::time::timeout_at(deadline, connection.pong()).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(format!("{err:?}")),
+        Err(_) => Err(format!("no pong within {timeout:?}")),
+    }
+}
+
+/// Log the failed send before reconnecting in `delay`. The relay closing
+/// the connection, for instance to rotate it, is expected rather than an
+/// error; the event is resent on the new connection either way.
+fn log_send_failure(err: &Error, delay: Duration) {
+    if matches!(err, Error::ConnectionClosed) {
+        debug!("visualizer relay closed the connection; resending on a new one in {delay:?}");
+    } else {
+        error!("failed to send visualizer event: {err:?}; reconnecting in {delay:?}");
+    }
+}
+
+async fn send_frames(connection: &mut dyn Connection, frames: Vec<String>) -> Result<(), Error> {
+    for frame in frames {
+        connection.send(frame).await?;
+    }
+    Ok(())
+}
+
+/// Record that every event through `sequence` has been delivered.
+pub(super) fn mark_delivered(delivered: &watch::Sender<u64>, sequence: u64) {
+    delivered.send_if_modified(|through| {
+        let advanced = *through <= sequence;
+        if advanced {
+            *through = sequence + 1;
+        }
+        advanced
+    });
+}
+
+async fn close(stream: Option<Box<dyn Connection>>) {
+    if let Some(mut connection) = stream
+        && let Err(err) = connection.close().await
+    {
+        debug!("failed to close visualizer websocket: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::Jitter;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::ChunkReassembler;
+    use crate::visualizer::EventClock;
+    use crate::visualizer::EventReplay;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::VisualizerStatus;
+    use crate::visualizer::build_event;
+    use crate::visualizer::connection::WebSocketConnector;
+    use crate::visualizer::wire::SCHEMA_VERSION;
+    use async_trait::async_trait;
+    use futures::SinkExt;
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Accepts any number of connections and forwards every text frame it
+    /// receives, `hello` included, plus a `None` marker for each new
+    /// connection.
+    async fn capture_server() -> (String, mpsc::UnboundedReceiver<Option<String>>) {
+        serve(Relay::Capture).await
+    }
+
+    /// Like [`capture_server`], except that its first connection stops
+    /// reading after one frame and so never answers a ping, like a relay
+    /// whose machine went to sleep.
+    async fn stalling_server() -> (String, mpsc::UnboundedReceiver<Option<String>>) {
+        serve(Relay::StallFirst).await
+    }
+
+    /// Like [`capture_server`], except that it closes every connection once
+    /// it received `frames` frames on it, like a relay rotating them.
+    async fn rotating_server(frames: usize) -> (String, mpsc::UnboundedReceiver<Option<String>>) {
+        serve(Relay::CloseAfter(frames)).await
+    }
+
+    /// Like [`capture_server`], except that it never answers a greeting,
+    /// like a relay built before capabilities.
+    async fn unwelcoming_server() -> (String, mpsc::UnboundedReceiver<Option<String>>) {
+        serve(Relay::Unwelcoming).await
+    }
+
+    #[derive(Clone, Copy)]
+    enum Relay {
+        Capture,
+        StallFirst,
+        CloseAfter(usize),
+        Unwelcoming,
+    }
+
+    async fn serve(relay: Relay) -> (String, mpsc::UnboundedReceiver<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut stall = matches!(relay, Relay::StallFirst);
+            while let Ok((socket, _)) = listener.accept().await {
+                let tx = tx.clone();
+                let stalls = std::mem::take(&mut stall);
+                tokio::spawn(async move {
+                    let Ok(mut ws) = accept_async(socket).await else {
+                        return;
+                    };
+                    let _ = tx.send(None);
+                    let mut received = 0;
+                    while let Some(Ok(message)) = ws.next().await {
+                        if let Message::Text(text) = message {
+                            let greeting = serde_json::from_str(&text)
+                                .ok()
+                                .filter(|frame: &Value| hello::is_hello(frame));
+                            let _ = tx.send(Some(text));
+                            // Only events count towards stalling or closing.
+                            if let Some(greeting) = greeting {
+                                if !matches!(relay, Relay::Unwelcoming) {
+                                    let welcome = hello::welcome(&greeting);
+                                    let _ = ws.send(Message::Text(welcome)).await;
+                                }
+                                continue;
+                            }
+                            received += 1;
+                            if stalls {
+                                std::future::pending::<()>().await;
+                            }
+                            if let Relay::CloseAfter(frames) = relay
+                                && received == frames
+                            {
+                                let _ = ws.close(None).await;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (format!("ws://{addr}"), rx)
+    }
+
+    fn is_running(visualizer: &AgentVisualizer) -> bool {
+        visualizer
+            .sink
+            .as_ref()
+            .is_some_and(|sink| sink.forwarder.is_running())
+    }
+
+    async fn emit(visualizer: &AgentVisualizer, n: u64) {
+        visualizer.emit(None, "tick", json!({ "n": n }), None).await;
+    }
+
+    /// Collect `count` events' sequence numbers and the number of connections
+    /// opened while receiving them, skipping `hello` frames.
+    async fn receive(
+        captured: &mut mpsc::UnboundedReceiver<Option<String>>,
+        count: usize,
+    ) -> (Vec<u64>, usize) {
+        let mut sequences = Vec::new();
+        let mut connections = 0;
+        while sequences.len() < count {
+            let next = tokio::time::timeout(Duration::from_secs(5), captured.recv())
+                .await
+                .expect("frame before timeout")
+                .expect("capture server running");
+            match next {
+                None => connections += 1,
+                Some(text) => {
+                    let frame: Value = serde_json::from_str(&text).expect("json");
+                    if !hello::is_hello(&frame) {
+                        sequences.push(frame["sequence"].as_u64().expect("sequence"));
+                    }
+                }
+            }
+        }
+        (sequences, connections)
+    }
+
+    #[test]
+    fn unused_visualizer_spawns_no_forwarder() {
+        // Outside a runtime: spawning here would panic.
+        let visualizer = AgentVisualizer::new(
+            Some("ws://127.0.0.1:9".to_string()),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+        assert!(!is_running(&visualizer));
+    }
+
+    #[tokio::test]
+    async fn first_emit_starts_forwarder() {
+        let (url, mut captured) = capture_server().await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+        assert!(!is_running(&visualizer));
+
+        emit(&visualizer, 0).await;
+        assert!(is_running(&visualizer));
+        assert_eq!(receive(&mut captured, 1).await, (vec![0], 1));
+    }
+
+    #[tokio::test]
+    async fn idle_forwarder_parks_and_restarts_without_loss_or_duplicates() {
+        let (url, mut captured) = capture_server().await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            Some(Duration::from_millis(50)),
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+
+        for n in 0..3 {
+            emit(&visualizer, n).await;
+        }
+        assert_eq!(receive(&mut captured, 3).await, (vec![0, 1, 2], 1));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while is_running(&visualizer) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("forwarder parks after idling");
+
+        for n in 3..5 {
+            emit(&visualizer, n).await;
+        }
+        assert!(is_running(&visualizer));
+        assert_eq!(receive(&mut captured, 2).await, (vec![3, 4], 1));
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), captured.recv())
+                .await
+                .is_err(),
+            "no event is delivered twice"
+        );
+        assert_eq!(visualizer.recent_events().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn unanswered_ping_replaces_the_connection_before_the_next_event() {
+        let (url, mut captured) = stalling_server().await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_heartbeat(Duration::from_millis(50), Duration::from_millis(100));
+
+        emit(&visualizer, 0).await;
+        assert_eq!(receive(&mut captured, 1).await, (vec![0], 1));
+        let reconnected = tokio::time::timeout(Duration::from_secs(5), captured.recv())
+            .await
+            .expect("reconnect without an event");
+        assert_eq!(reconnected, Some(None));
+
+        emit(&visualizer, 1).await;
+        assert_eq!(receive(&mut captured, 1).await, (vec![1], 0));
+    }
+
+    #[tokio::test]
+    async fn frames_read_while_awaiting_a_pong_are_kept() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let url = format!("ws://{}", listener.local_addr().expect("addr"));
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.expect("accept");
+            let mut ws = accept_async(socket).await.expect("handshake");
+            // Written ahead of the pong, which reading the ping queues.
+            let _ = ws.send(Message::Text("ack".to_string())).await;
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let mut connection = WebSocketConnector::default()
+            .connect(&url)
+            .await
+            .expect("connect");
+        connection.heartbeat().await.expect("ping");
+        let pong = tokio::time::timeout(Duration::from_secs(5), connection.pong()).await;
+        let next = tokio::time::timeout(Duration::from_secs(5), connection.recv()).await;
+        assert_eq!(
+            (
+                pong.map(|pong| pong.is_ok()),
+                next.map(|next| next.ok().flatten())
+            ),
+            (Ok(true), Ok(Some("ack".to_string())))
+        );
+    }
+
+    #[tokio::test]
+    async fn relay_closing_the_connection_gets_the_next_event_on_a_new_one() {
+        let (url, mut captured) = rotating_server(2).await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+
+        emit(&visualizer, 0).await;
+        emit(&visualizer, 1).await;
+        assert_eq!(receive(&mut captured, 2).await, (vec![0, 1], 1));
+        let reconnected = tokio::time::timeout(Duration::from_secs(5), captured.recv())
+            .await
+            .expect("reconnect after the relay closed");
+        assert_eq!(reconnected, Some(None));
+
+        emit(&visualizer, 2).await;
+        assert_eq!(receive(&mut captured, 1).await, (vec![2], 0));
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+    }
+
+    /// The next frame from `captured`, `None` marking a new connection.
+    async fn next_frame(captured: &mut mpsc::UnboundedReceiver<Option<String>>) -> Option<Value> {
+        let next = tokio::time::timeout(Duration::from_secs(5), captured.recv())
+            .await
+            .expect("frame before timeout")
+            .expect("capture server running");
+        next.map(|text| serde_json::from_str(&text).expect("json"))
+    }
+
+    #[tokio::test]
+    async fn every_connection_is_greeted_before_the_first_event() {
+        let (url, mut captured) = rotating_server(1).await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+        let summary = |frame: Option<Value>| {
+            frame.map(|frame| {
+                (
+                    frame["type"].clone(),
+                    frame["schemaVersion"].clone(),
+                    frame["sequence"].clone(),
+                )
+            })
+        };
+        let hello = Some((json!("hello"), json!(SCHEMA_VERSION), Value::Null));
+        let event = |sequence: u64| Some((Value::Null, json!(SCHEMA_VERSION), json!(sequence)));
+
+        emit(&visualizer, 0).await;
+        let mut frames = Vec::new();
+        // The relay closes the connection after each event, so the
+        // forwarder reconnects and greets it again before the next one.
+        for _ in 0..5 {
+            frames.push(summary(next_frame(&mut captured).await));
+        }
+        emit(&visualizer, 1).await;
+        frames.push(summary(next_frame(&mut captured).await));
+
+        assert_eq!(
+            frames,
+            vec![None, hello.clone(), event(0), None, hello, event(1)]
+        );
+    }
+
+    /// A websocket relay that refuses every connect until `up` is set.
+    struct LateRelay {
+        up: Arc<AtomicBool>,
+        inner: WebSocketConnector,
+    }
+
+    #[async_trait]
+    impl Connector for LateRelay {
+        async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+            if !self.up.load(Ordering::SeqCst) {
+                return Err(Error::Io(std::io::Error::other("relay not up yet")));
+            }
+            self.inner.connect(url).await
+        }
+
+        fn greets(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn a_relay_connecting_late_is_replayed_what_it_missed_before_live_events() {
+        let (url, mut captured) = capture_server().await;
+        let up = Arc::new(AtomicBool::new(false));
+        let visualizer = AgentVisualizer::with_connector(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+            Arc::new(LateRelay {
+                up: Arc::clone(&up),
+                inner: WebSocketConnector::default(),
+            }),
+        )
+        .with_reconnect_policy(RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            multiplier: 1.0,
+            max_delay: Duration::from_millis(1),
+            jitter: Jitter::None,
+            max_attempts: Some(1),
+        })
+        .with_queue_capacity(Some(1))
+        .with_replay(Some(EventReplay::default()));
+        let kept_for_replay = || {
+            visualizer.sink.as_ref().map_or(0, |sink| {
+                sink.replay.lock().map_or(0, |buffer| buffer.len())
+            })
+        };
+
+        // The forwarder holds on to the first event while its connects
+        // fail, the second waits in the queue, and the rest do not fit.
+        emit(&visualizer, 0).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for n in 1..10 {
+            emit(&visualizer, n).await;
+        }
+        assert_eq!(kept_for_replay(), 8);
+
+        up.store(true, Ordering::SeqCst);
+        let mut frames = Vec::new();
+        for _ in 0..12 {
+            frames.push(next_frame(&mut captured).await.map(|frame| {
+                (
+                    frame["type"].clone(),
+                    frame["sequence"].clone(),
+                    frame["replayed"].clone(),
+                )
+            }));
+        }
+
+        let mut expected = vec![None, Some((json!("hello"), Value::Null, Value::Null))];
+        expected.extend((2..10).map(|sequence| Some((Value::Null, json!(sequence), json!(true)))));
+        expected.extend((0..2).map(|sequence| Some((Value::Null, json!(sequence), Value::Null))));
+        assert_eq!(frames, expected);
+        assert_eq!(kept_for_replay(), 0);
+    }
+
+    /// Takes every connect and the `hello`, but fails every replayed frame.
+    struct ReplayRefusingRelay;
+
+    struct ReplayRefusingConnection;
+
+    #[async_trait]
+    impl Connection for ReplayRefusingConnection {
+        async fn send(&mut self, text: String) -> Result<(), Error> {
+            if text.contains("\"replayed\":true") {
+                return Err(Error::Io(std::io::Error::other("replay refused")));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Connector for ReplayRefusingRelay {
+        async fn connect(&self, _url: &str) -> Result<Box<dyn Connection>, Error> {
+            Ok(Box::new(ReplayRefusingConnection))
+        }
+
+        fn greets(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_replays_count_towards_giving_up_on_the_relay() {
+        let visualizer = AgentVisualizer::with_connector(
+            Some("ws://127.0.0.1:9".to_string()),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+            Arc::new(ReplayRefusingRelay),
+        )
+        .with_reconnect_policy(RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            multiplier: 1.0,
+            max_delay: Duration::from_millis(1),
+            jitter: Jitter::None,
+            max_attempts: None,
+        })
+        .with_max_connect_failures(Some(3))
+        .with_replay(Some(EventReplay::default()));
+        let missed = build_event(
+            &AtomicU64::new(0),
+            &EventClock::new(),
+            None,
+            "tick".to_string(),
+            json!({ "n": "missed" }),
+            None,
+        );
+        if let Some(sink) = &visualizer.sink
+            && let Ok(mut buffer) = sink.replay.lock()
+        {
+            buffer.record(&missed);
+        }
+
+        emit(&visualizer, 1).await;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !matches!(visualizer.status(), VisualizerStatus::Abandoned { .. }) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("relay given up on");
+        assert_eq!(
+            visualizer.status(),
+            VisualizerStatus::Abandoned {
+                connect_failures: 3
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_event_is_sent_as_a_contiguous_run_of_chunks() {
+        let (url, mut captured) = capture_server().await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_event_chunking(EventChunking {
+            frame_bytes: 16 * 1024,
+            max_event_bytes: 1024 * 1024,
+        });
+        let patch = "+ fn main() {}\n".repeat(20_000);
+        emit(&visualizer, 0).await;
+        visualizer
+            .emit(None, "patch_body", json!({ "patch": patch }), None)
+            .await;
+        emit(&visualizer, 2).await;
+
+        let mut reassembler = ChunkReassembler::default();
+        let mut frames = 0;
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), captured.recv())
+                .await
+                .expect("frame before timeout")
+                .expect("capture server running");
+            let Some(text) = next else {
+                continue;
+            };
+            assert!(text.len() <= 16 * 1024);
+            let frame: Value = serde_json::from_str(&text).expect("json");
+            if hello::is_hello(&frame) {
+                continue;
+            }
+            frames += 1;
+            received.extend(reassembler.push(frame));
+        }
+        assert!(frames > 3, "the patch was chunked");
+        let received: Vec<(Value, Value)> = received
+            .into_iter()
+            .map(|event| (event["sequence"].clone(), event["actionType"].clone()))
+            .collect();
+        assert_eq!(
+            received,
+            vec![
+                (json!(0), json!("tick")),
+                (json!(1), json!("patch_body")),
+                (json!(2), json!("tick")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_relay_that_does_not_welcome_chunks_gets_oversized_events_truncated() {
+        let (url, mut captured) = unwelcoming_server().await;
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_event_chunking(EventChunking {
+            frame_bytes: 16 * 1024,
+            max_event_bytes: 1024 * 1024,
+        });
+        let patch = "+ fn main() {}\n".repeat(20_000);
+        visualizer
+            .emit(None, "patch_body", json!({ "patch": patch }), None)
+            .await;
+
+        let mut frames = Vec::new();
+        while frames.len() < 2 {
+            let next = tokio::time::timeout(Duration::from_secs(10), captured.recv())
+                .await
+                .expect("frame before timeout")
+                .expect("capture server running");
+            if let Some(text) = next {
+                frames.push(serde_json::from_str::<Value>(&text).expect("json"));
+            }
+        }
+        assert_eq!(
+            (
+                frames[0]["capabilities"].clone(),
+                frames[1]["actionType"].clone(),
+                frames[1]["action"]["patch"]["truncated"].clone(),
+            ),
+            (json!(["event_chunk"]), json!("patch_body"), json!(true))
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Notices holes in the sequence numbers a relay is sent, the way a
+//! consumer would in what it receives.
+//!
+//! Sequence numbers are assigned at emit and never skipped, so each one
+//! written to the relay should be one past the last. When it is further
+//! on, the events in between were lost before they could be sent, and
+//! the [`GapCallback`] installed with
+//! [`AgentVisualizer::with_gap_detector`](super::AgentVisualizer::with_gap_detector)
+//! gets the sequence that was expected and the one that came instead.
+//! Resent or older sequences are ignored. Unlike the `gaps` module, which
+//! accounts for the lost events themselves, this only sees the hole, but
+//! per relay, mirrors included.
+
+use std::sync::Arc;
+
+/// Called with the expected and the received sequence.
+pub(crate) type GapCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+pub(crate) struct GapDetector {
+    last_seen_sequence: Option<u64>,
+    callback: GapCallback,
+}
+
+impl GapDetector {
+    pub(crate) fn new(callback: GapCallback) -> Self {
+        Self {
+            last_seen_sequence: None,
+            callback,
+        }
+    }
+
+    /// Count `sequence` as seen, reporting the hole before it if any. The
+    /// first sequence seen only sets where counting starts.
+    pub(crate) fn observe(&mut self, sequence: u64) {
+        match self.last_seen_sequence {
+            Some(last) if sequence <= last => return,
+            Some(last) if sequence > last + 1 => (self.callback)(last + 1, sequence),
+            _ => {}
+        }
+        self.last_seen_sequence = Some(sequence);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn reports_each_hole_with_the_expected_and_received_sequence() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut detector = GapDetector::new({
+            let reported = Arc::clone(&reported);
+            Arc::new(move |expected, received| {
+                reported
+                    .lock()
+                    .expect("reported")
+                    .push((expected, received));
+            })
+        });
+
+        for sequence in [3, 4, 7, 7, 5, 8, 12] {
+            detector.observe(sequence);
+        }
+
+        assert_eq!(*reported.lock().expect("reported"), vec![(5, 7), (9, 12)]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Accounting of events that never reached the relay, reported to
+//! consumers as `visualizer_gap` events so they can mark the missing range
+//! instead of drawing an incomplete timeline.
+//!
+//! Every event the queue drops or evicts, drops when its forwarder gives
+//! up, or the forwarder drops after a reconnect ran out of attempts is
+//! counted by action type, along with the lowest and highest sequence
+//! number lost. The session reports the gap once the forwarder connects to
+//! the relay again, and otherwise on its drop summary interval; the counts
+//! then start over for the next gap. Only the primary relay is accounted
+//! for, since mirrors are best effort.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde_json::Value;
+use serde_json::json;
+use tokio::sync::watch;
+
+use super::VisualizerEvent;
+
+/// Events lost since the last reported gap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Gap {
+    /// How many were lost, by action type.
+    pub(crate) dropped: BTreeMap<String, u64>,
+    /// Lowest sequence number lost.
+    pub(crate) first_sequence: u64,
+    /// Highest sequence number lost. Events between the two bounds may
+    /// still have been delivered if drops were interleaved with sends.
+    pub(crate) last_sequence: u64,
+}
+
+impl Gap {
+    /// Payload of the `visualizer_gap` event reporting this gap.
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "dropped": self.dropped,
+            "total": self.dropped.values().sum::<u64>(),
+            "firstSequence": self.first_sequence,
+            "lastSequence": self.last_sequence,
+        })
+    }
+
+    fn record(&mut self, event: &VisualizerEvent) {
+        *self.dropped.entry(event.action_type.clone()).or_default() += 1;
+        self.first_sequence = self.first_sequence.min(event.sequence);
+        self.last_sequence = self.last_sequence.max(event.sequence);
+    }
+}
+
+/// Drop counters of one relay, shared by its queue and forwarder and by
+/// every clone of the visualizer.
+pub(crate) struct GapLedger {
+    open: Mutex<Option<Gap>>,
+    /// Bumped each time the forwarder connects while a gap is open.
+    recoveries: watch::Sender<u64>,
+}
+
+impl Default for GapLedger {
+    fn default() -> Self {
+        Self {
+            open: Mutex::new(None),
+            recoveries: watch::Sender::new(0),
+        }
+    }
+}
+
+impl GapLedger {
+    pub(super) fn record(&self, event: &VisualizerEvent) {
+        if let Ok(mut open) = self.open.lock() {
+            open.get_or_insert_with(|| Gap {
+                dropped: BTreeMap::new(),
+                first_sequence: event.sequence,
+                last_sequence: event.sequence,
+            })
+            .record(event);
+        }
+    }
+
+    /// Note that the forwarder connected to the relay, which ends an open
+    /// gap.
+    pub(super) fn connected(&self) {
+        let open = self.open.lock().is_ok_and(|open| open.is_some());
+        if open {
+            self.recoveries.send_modify(|recoveries| *recoveries += 1);
+        }
+    }
+
+    /// The gap since the last one taken, if any event was lost since.
+    pub(crate) fn take(&self) -> Option<Gap> {
+        self.open.lock().ok().and_then(|mut open| open.take())
+    }
+
+    /// Changes each time the relay is reconnected to after losing events.
+    pub(crate) fn recoveries(&self) -> watch::Receiver<u64> {
+        self.recoveries.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn gaps_count_by_action_type_and_start_over_once_taken() {
+        let ledger = GapLedger::default();
+        for (sequence, action_type) in [(7, "exec_output"), (3, "exec_output"), (9, "task_started")]
+        {
+            ledger.record(&VisualizerEvent::for_test(sequence, action_type));
+        }
+        let gap = ledger.take();
+        ledger.record(&VisualizerEvent::for_test(12, "exec_output"));
+
+        assert_eq!(
+            (gap, ledger.take(), ledger.take()),
+            (
+                Some(Gap {
+                    dropped: BTreeMap::from([
+                        ("exec_output".to_string(), 2),
+                        ("task_started".to_string(), 1),
+                    ]),
+                    first_sequence: 3,
+                    last_sequence: 9,
+                }),
+                Some(Gap {
+                    dropped: BTreeMap::from([("exec_output".to_string(), 1)]),
+                    first_sequence: 12,
+                    last_sequence: 12,
+                }),
+                None,
+            )
+        );
+    }
+
+    #[test]
+    fn only_connects_after_a_drop_are_recoveries() {
+        let ledger = GapLedger::default();
+        let recoveries = ledger.recoveries();
+        ledger.connected();
+        assert_eq!(*recoveries.borrow(), 0);
+
+        ledger.record(&VisualizerEvent::for_test(0, "exec_output"));
+        ledger.connected();
+        assert_eq!(*recoveries.borrow(), 1);
+        assert_eq!(
+            ledger.take().map(|gap| gap.to_json()),
+            Some(json!({
+                "dropped": { "exec_output": 1 },
+                "total": 1,
+                "firstSequence": 0,
+                "lastSequence": 0,
+            }))
+        );
+    }
+}
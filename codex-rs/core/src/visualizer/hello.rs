@@ -0,0 +1,247 @@
+//! The `hello` frame a relay is greeted with on connect.
+//!
+//! Right after each successful connect, before any event, the forwarder
+//! writes `{"type": "hello", "schemaVersion": n, "producerVersion": v,
+//! "actionTypes": [...]}`: the [`SCHEMA_VERSION`] every event that follows
+//! carries, the version of this crate, and every action type it can emit.
+//! A relay built for another version can then tell from the first frame
+//! which events it will not understand, rather than failing on them one by
+//! one. Every reconnect greets again, since the relay may have restarted in
+//! between. A greeting the relay does not take fails the connect like a
+//! refused handshake would, and is retried with it.
+//!
+//! The greeting also lists, as `capabilities`, the optional frame formats
+//! this connection would use: `event_chunk` runs with chunking on (see the
+//! `chunks` module) and gzipped binary frames with compression on (see the
+//! `compression` module). A relay answers with `{"type": "welcome",
+//! "capabilities": [...]}`, naming those it takes, and only those are used
+//! on the connection. A relay that has not answered within
+//! [`WELCOME_TIMEOUT`], like one built before capabilities, gets none: its
+//! oversized events are truncated and every frame is text. Nothing is
+//! offered, and no answer awaited, when neither format is configured.
+//!
+//! Only transports with a relay at the other end greet (see
+//! [`Connector::greets`]): files, standard streams and HTTP posts get the
+//! events alone, each still carrying its `schemaVersion`.
+//!
+//! [`ACTION_TYPES`] has to list every action type emitted outside tests;
+//! add to it along with the first emit of a new one.
+//!
+//! [`Connector::greets`]: super::connection::Connector::greets
+
+use std::time::Duration;
+
+use serde_json::Value;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Error;
+use tracing::debug;
+
+use super::chunks::CHUNK_ACTION_TYPE;
+use super::connection::Connection;
+use super::wire::SCHEMA_VERSION;
+
+/// How long a relay has to answer the greeting before it is taken to
+/// accept no optional frame formats.
+pub(super) const WELCOME_TIMEOUT: Duration = Duration::from_secs(2);
+
+const GZIP: &str = "gzip";
+
+/// Every action type this crate emits, sorted.
+pub(super) const ACTION_TYPES: &[&str] = &[
+    "annotation",
+    "approval_expired",
+    "background_task_aborted",
+    "background_task_completed",
+    "background_task_spawn_rejected",
+    "background_task_spawned",
+    "blocked_task_resumed",
+    "conversation_ended",
+    "conversation_lease_conflict",
+    "conversation_lease_reclaimed",
+    CHUNK_ACTION_TYPE,
+    "findings_restored",
+    "first_response_latency",
+    "focus_changed",
+    "focus_drift",
+    "git_commit_created",
+    "input_degraded",
+    "instrumentation_gap",
+    "llm_prompt_prepared",
+    "llm_response_complete",
+    "llm_retry_scheduled",
+    "llm_stream_started",
+    "memory_bootstrap",
+    "pending_approvals_cleared",
+    "protocol_event",
+    "reasoning_phase",
+    "self_test",
+    "serialization_degraded",
+    "session_loop_started",
+    "shutdown_report",
+    "task_aborted",
+    "task_completed",
+    "task_progress",
+    "task_recovered_incomplete",
+    "task_recovery_resolved",
+    "task_retry_attempt",
+    "task_reverted",
+    "task_spawn_rejected",
+    "task_spawned",
+    "task_started",
+    "telemetry_config_updated",
+    "telemetry_failure",
+    "telemetry_inconsistency",
+    "template_rejected",
+    "tool_call_finished",
+    "tool_call_progress",
+    "tool_call_started",
+    "tool_catalog_snapshot",
+    "turn_context_persisted",
+    "visualizer_events_dropped",
+    "visualizer_events_sampled",
+    "visualizer_gap",
+];
+
+/// Optional frame formats, offered in the greeting and used once the
+/// relay accepted them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Capabilities {
+    /// Oversized events as runs of `event_chunk` frames.
+    pub(super) chunks: bool,
+    /// Large frames gzipped into binary frames.
+    pub(super) gzip: bool,
+}
+
+impl Capabilities {
+    fn names(self) -> Vec<&'static str> {
+        [(self.chunks, CHUNK_ACTION_TYPE), (self.gzip, GZIP)]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect()
+    }
+
+    /// Those of `self` that the relay's `reply` accepts; none unless it is
+    /// a `welcome`.
+    fn accepted_by(self, reply: &str) -> Self {
+        let reply: Value = serde_json::from_str(reply).unwrap_or_default();
+        if reply["type"] != "welcome" {
+            debug!(
+                "visualizer relay answered the greeting with something else; using no optional frame formats"
+            );
+            return Self::default();
+        }
+        let names = reply["capabilities"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let accepts = |name: &str| names.iter().any(|accepted| accepted == name);
+        Self {
+            chunks: self.chunks && accepts(CHUNK_ACTION_TYPE),
+            gzip: self.gzip && accepts(GZIP),
+        }
+    }
+}
+
+/// The serialized `hello` frame, offering `offered`.
+pub(super) fn frame(offered: Capabilities) -> String {
+    json!({
+        "type": "hello",
+        "schemaVersion": SCHEMA_VERSION,
+        "producerVersion": env!("CARGO_PKG_VERSION"),
+        "actionTypes": ACTION_TYPES,
+        "capabilities": offered.names(),
+    })
+    .to_string()
+}
+
+/// Greet the relay on `connection`, offering `offered`, and return what
+/// it accepted.
+pub(super) async fn greet(
+    connection: &mut dyn Connection,
+    offered: Capabilities,
+) -> Result<Capabilities, Error> {
+    connection.send(frame(offered)).await?;
+    if offered == Capabilities::default() {
+        return Ok(offered);
+    }
+    match tokio::time::timeout(WELCOME_TIMEOUT, connection.recv()).await {
+        Ok(Ok(Some(reply))) => Ok(offered.accepted_by(&reply)),
+        Ok(Ok(None)) => Err(Error::ConnectionClosed),
+        Ok(Err(err)) => Err(err),
+        Err(_) => {
+            debug!(
+                "visualizer relay did not answer the greeting within {WELCOME_TIMEOUT:?}; using no optional frame formats"
+            );
+            Ok(Capabilities::default())
+        }
+    }
+}
+
+/// The `welcome` a relay that takes everything offered answers `hello`
+/// with.
+#[cfg(test)]
+pub(super) fn welcome(hello: &Value) -> String {
+    json!({ "type": "welcome", "capabilities": hello["capabilities"] }).to_string()
+}
+
+/// Whether `frame` is a `hello` rather than an event.
+#[cfg(test)]
+pub(super) fn is_hello(frame: &serde_json::Value) -> bool {
+    frame["type"] == "hello"
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn hello_names_the_schema_the_crate_and_every_action_type_once() {
+        let hello: Value = serde_json::from_str(&frame(Capabilities::default())).expect("json");
+        let mut sorted = ACTION_TYPES.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        assert_eq!(
+            (
+                is_hello(&hello),
+                &hello["schemaVersion"],
+                &hello["producerVersion"],
+                sorted,
+            ),
+            (
+                true,
+                &json!(SCHEMA_VERSION),
+                &json!(env!("CARGO_PKG_VERSION")),
+                ACTION_TYPES.to_vec(),
+            )
+        );
+        assert_eq!(hello["actionTypes"], json!(ACTION_TYPES));
+    }
+
+    #[test]
+    fn only_offered_capabilities_the_relay_welcomes_are_used() {
+        let offered = Capabilities {
+            chunks: true,
+            gzip: false,
+        };
+        let hello: Value = serde_json::from_str(&frame(offered)).expect("json");
+
+        assert_eq!(
+            (
+                hello["capabilities"].clone(),
+                offered.accepted_by(r#"{"type":"welcome","capabilities":["event_chunk","gzip"]}"#),
+                offered.accepted_by(r#"{"type":"welcome","capabilities":["gzip"]}"#),
+                offered.accepted_by(r#"{"sequence":0}"#),
+            ),
+            (
+                json!(["event_chunk"]),
+                offered,
+                Capabilities::default(),
+                Capabilities::default(),
+            )
+        );
+    }
+}
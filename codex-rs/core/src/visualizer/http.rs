@@ -0,0 +1,390 @@
+//! HTTP transport for networks whose proxies refuse websocket upgrades.
+//!
+//! Relay URLs with an `http` or `https` scheme, and any relay URL with a
+//! `transport=http` query parameter (`ws` then standing for `http` and
+//! `wss` for `https`), are delivered to by POSTing JSON arrays of events
+//! instead of writing frames. The frames the forwarder writes are
+//! collected, batch frames unpacked into their events, and POSTed once
+//! [`DEFAULT_BATCH_EVENTS`] are waiting and whenever the forwarder flushes:
+//! when its queue drains or, with `visualizer_batch`, after each batch.
+//! Requests go through the proxy in `HTTPS_PROXY`, if any, like the
+//! client's other requests.
+//!
+//! The relay's status decides what becomes of a POST. 2xx acknowledges
+//! it. 413 halves the batch size for the rest of the session and sends
+//! the same events again in halves; an event refused on its own is
+//! dropped. 401 and 403 refuse the token, as they would a handshake (see
+//! the `auth` module). Anything else, and a request that failed outright,
+//! fails the flush: the batch stays outstanding, and the forwarder
+//! reconnects after the backoff of a failed send. Connecting POSTs what is
+//! outstanding, so repeated failures are retried and backed off from on
+//! the reconnect policy like a relay that is down, and newer events are
+//! only ever posted after the batch ahead of them was acknowledged.
+//!
+//! There is no heartbeat and nothing is read back, so the durable queue,
+//! which waits for acknowledgement frames, cannot deliver over it.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Error;
+use tokio_tungstenite::tungstenite::http::Response;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tracing::debug;
+use tracing::error;
+use url::Url;
+
+use super::auth::RelayToken;
+use super::auth::post_request;
+use super::connection::Connection;
+use super::connection::Connector;
+
+/// Events waiting before a POST is sent without waiting for a flush, and
+/// the most one POST carries until the relay refuses a batch as too large.
+pub(super) const DEFAULT_BATCH_EVENTS: usize = 100;
+
+/// Whether events for `url` are POSTed rather than sent over a websocket.
+pub(super) fn is_http_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|parsed| {
+        matches!(parsed.scheme(), "http" | "https")
+            || parsed
+                .query_pairs()
+                .any(|(key, value)| key == "transport" && value == "http")
+    })
+}
+
+/// The URL events for `url` are POSTed to: `url` over `http` or `https`,
+/// without its `transport` parameter.
+fn endpoint(url: &str) -> Result<String, url::ParseError> {
+    let mut parsed = Url::parse(url)?;
+    let scheme = match parsed.scheme() {
+        "ws" => Some("http"),
+        "wss" => Some("https"),
+        _ => None,
+    };
+    if let Some(scheme) = scheme {
+        // Both are special schemes, which the URL may switch between.
+        let _ = parsed.set_scheme(scheme);
+    }
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| key != "transport")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    Ok(parsed.into())
+}
+
+/// Serialized events not yet acknowledged, oldest first. Shared by every
+/// connection of a connector, so a batch a dropped connection failed to
+/// post is the first thing the next one posts.
+struct Outbox {
+    events: VecDeque<String>,
+    /// Most events one POST carries.
+    batch_size: usize,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            events: VecDeque::new(),
+            batch_size: DEFAULT_BATCH_EVENTS,
+        }
+    }
+}
+
+impl Outbox {
+    /// Add the events of `frame`, unpacking a batch frame, and say whether
+    /// a full batch is waiting.
+    fn push(&mut self, frame: String) -> bool {
+        match serde_json::from_str::<Value>(&frame) {
+            Ok(Value::Array(events)) => self.events.extend(events.iter().map(Value::to_string)),
+            _ => self.events.push_back(frame),
+        }
+        self.events.len() >= self.batch_size
+    }
+
+    /// The oldest events, as many as one POST carries.
+    fn next_batch(&self) -> Vec<String> {
+        self.events.iter().take(self.batch_size).cloned().collect()
+    }
+}
+
+#[derive(Default)]
+pub(super) struct HttpConnector {
+    outbox: Arc<Mutex<Outbox>>,
+    /// Set by [`Connector::authenticate`].
+    token: Mutex<Option<RelayToken>>,
+}
+
+#[async_trait]
+impl Connector for HttpConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        let endpoint = endpoint(url)
+            .map_err(|err| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, err)))?;
+        let token = self.token.lock().ok().and_then(|token| token.clone());
+        if let Some(token) = &token {
+            debug!("authenticating to visualizer relay {endpoint} with {token:?}");
+        }
+        let mut connection = HttpConnection {
+            client: crate::default_client::create_client(),
+            endpoint,
+            token,
+            outbox: Arc::clone(&self.outbox),
+            failed: false,
+        };
+        connection.post_outstanding().await?;
+        Ok(Box::new(connection))
+    }
+
+    fn authenticate(&self, token: RelayToken) {
+        if let Ok(mut slot) = self.token.lock() {
+            *slot = Some(token);
+        }
+    }
+}
+
+struct HttpConnection {
+    client: reqwest::Client,
+    endpoint: String,
+    token: Option<RelayToken>,
+    outbox: Arc<Mutex<Outbox>>,
+    /// Set once a POST failed; nothing more is posted before the flush,
+    /// which fails and so gets the connection replaced.
+    failed: bool,
+}
+
+impl HttpConnection {
+    /// POST the outbox batch by batch until it is empty, the relay refuses
+    /// a batch, or a request fails.
+    async fn post_outstanding(&mut self) -> Result<(), Error> {
+        loop {
+            let batch = self
+                .outbox
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .next_batch();
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let status = self.post(&batch).await?;
+            let mut outbox = self.outbox.lock().unwrap_or_else(PoisonError::into_inner);
+            if status.is_success() {
+                outbox.events.drain(..batch.len());
+            } else if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE && batch.len() > 1 {
+                outbox.batch_size = batch.len() / 2;
+                debug!(
+                    "visualizer relay refused {} events as too large; posting {} at a time",
+                    batch.len(),
+                    outbox.batch_size
+                );
+            } else if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+                error!("visualizer relay refused an event as too large; dropping it");
+                outbox.events.pop_front();
+            } else {
+                return Err(status_error(status));
+            }
+        }
+    }
+
+    async fn post(&self, batch: &[String]) -> Result<reqwest::StatusCode, Error> {
+        let request = post_request(&self.client, &self.endpoint, self.token.as_ref())
+            .map_err(|err| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, err)))?;
+        let response = request
+            .header(CONTENT_TYPE, "application/json")
+            .body(format!("[{}]", batch.join(",")))
+            .send()
+            .await
+            .map_err(|err| Error::Io(io::Error::other(err)))?;
+        Ok(response.status())
+    }
+}
+
+/// A refused POST as the error a handshake refused with `status` is.
+fn status_error(status: reqwest::StatusCode) -> Error {
+    let mut response = Response::new(None);
+    *response.status_mut() =
+        StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    Error::Http(response)
+}
+
+#[async_trait]
+impl Connection for HttpConnection {
+    async fn send(&mut self, text: String) -> Result<(), Error> {
+        let full = self
+            .outbox
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(text);
+        if full
+            && !self.failed
+            && let Err(err) = self.post_outstanding().await
+        {
+            debug!("failed to post visualizer events: {err:?}; retrying at the next flush");
+            self.failed = true;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.post_outstanding().await?;
+        self.failed = false;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.post_outstanding().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::Request;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    use super::*;
+    use crate::retry::Jitter;
+    use crate::retry::RetryPolicy;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::EventBatching;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+
+    fn sequences(request: &Request) -> Vec<u64> {
+        let events: Vec<Value> = serde_json::from_slice(&request.body).expect("json array");
+        events
+            .iter()
+            .map(|event| event["sequence"].as_u64().expect("sequence"))
+            .collect()
+    }
+
+    /// A visualizer POSTing to `server` in batches of up to `max_events`.
+    fn visualizer(server: &MockServer, max_events: usize) -> AgentVisualizer {
+        AgentVisualizer::new(
+            Some(format!("{}/ingest", server.uri())),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_event_batching(EventBatching {
+            interval: Duration::from_secs(5),
+            max_events,
+            max_bytes: usize::MAX,
+        })
+        .with_reconnect_policy(RetryPolicy {
+            initial_delay: Duration::from_millis(10),
+            multiplier: 1.0,
+            max_delay: Duration::from_millis(10),
+            jitter: Jitter::None,
+            max_attempts: None,
+        })
+    }
+
+    async fn posted(server: &MockServer) -> Vec<Vec<u64>> {
+        server
+            .received_requests()
+            .await
+            .expect("recording")
+            .iter()
+            .map(sequences)
+            .collect()
+    }
+
+    #[test]
+    fn transport_param_posts_to_the_same_relay_over_http() {
+        let url = "wss://relay.example.com/viz?transport=http&role=producer";
+        assert_eq!(
+            (
+                is_http_url(url),
+                is_http_url("https://relay.example.com/ingest"),
+                is_http_url("wss://relay.example.com/viz"),
+                endpoint(url),
+            ),
+            (
+                true,
+                true,
+                false,
+                Ok("https://relay.example.com/viz?role=producer".to_string()),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn a_batch_refused_as_too_large_is_posted_in_halves() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ingest"))
+            .respond_with(|request: &Request| {
+                let status = if sequences(request).len() > 1 {
+                    413
+                } else {
+                    200
+                };
+                ResponseTemplate::new(status)
+            })
+            .mount(&server)
+            .await;
+        let visualizer = visualizer(&server, 3);
+
+        for _ in 0..3 {
+            visualizer.emit(None, "tick", json!({}), None).await;
+        }
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        assert_eq!(
+            posted(&server).await,
+            vec![vec![0, 1, 2], vec![0], vec![1], vec![2]]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_batch_is_retried_before_newer_events() {
+        let server = MockServer::start().await;
+        let requests = AtomicUsize::new(0);
+        Mock::given(method("POST"))
+            .respond_with(move |_: &Request| {
+                if requests.fetch_add(1, Ordering::SeqCst) < 2 {
+                    ResponseTemplate::new(503)
+                } else {
+                    ResponseTemplate::new(200)
+                }
+            })
+            .mount(&server)
+            .await;
+        let visualizer = visualizer(&server, 2);
+
+        for _ in 0..3 {
+            visualizer.emit(None, "tick", json!({}), None).await;
+        }
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        assert_eq!(
+            posted(&server).await,
+            vec![vec![0, 1], vec![0, 1], vec![0, 1], vec![2]]
+        );
+    }
+}
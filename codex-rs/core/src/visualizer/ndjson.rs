@@ -0,0 +1,238 @@
+//! File transport for relay URLs like `file:///tmp/events.ndjson`, for
+//! machines that cannot reach a relay. Each frame the forwarder would send
+//! over the websocket is appended to the file as one line, so the file
+//! holds exactly the websocket payloads. Parent directories are created and
+//! an existing file is appended to. Lines are buffered and synced to disk
+//! once the forwarder has drained the queue, not once per event.
+//!
+//! `CODEX_VISUALIZER_LOG_FILE` names such a file by path. It is the only
+//! sink when `CODEX_VISUALIZER_WS` is unset, and a mirror of the relay
+//! otherwise, numbered by the same sequence.
+
+use std::io;
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufWriter;
+use tokio_tungstenite::tungstenite::Error;
+use url::Url;
+
+use super::connection::Connection;
+use super::connection::Connector;
+
+/// Whether `url` names a file rather than a relay.
+pub(super) fn is_file_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|parsed| parsed.scheme() == "file")
+}
+
+/// The `file://` URL of `path`, relative to the working directory unless
+/// absolute.
+pub(super) fn file_url(path: &Path) -> io::Result<String> {
+    let path = std::path::absolute(path)?;
+    Url::from_file_path(&path).map(String::from).map_err(|()| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` is not a file path", path.display()),
+        )
+    })
+}
+
+pub(super) struct FileConnector;
+
+#[async_trait]
+impl Connector for FileConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        let path = Url::parse(url)
+            .ok()
+            .and_then(|parsed| parsed.to_file_path().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("visualizer url `{url}` is not a file path"),
+                )
+            })?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        Ok(Box::new(FileConnection {
+            file: BufWriter::new(file),
+        }))
+    }
+}
+
+struct FileConnection {
+    file: BufWriter<tokio::fs::File>,
+}
+
+#[async_trait]
+impl Connection for FileConnection {
+    async fn send(&mut self, mut text: String) -> Result<(), Error> {
+        text.push('\n');
+        self.file.write_all(text.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.file.flush().await?;
+        self.file.get_ref().sync_data().await?;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+
+    fn file_visualizer(url: String) -> AgentVisualizer {
+        AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+    }
+
+    fn action_types(path: &std::path::Path) -> Vec<Value> {
+        lines(path)
+            .into_iter()
+            .map(|event| event["actionType"].clone())
+            .collect()
+    }
+
+    fn lines(path: &std::path::Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .expect("read events")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("one json event per line"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn events_are_appended_as_lines_under_new_directories() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("ci/run-1/events.ndjson");
+        let url = url::Url::from_file_path(&path)
+            .expect("file url")
+            .to_string();
+
+        for run in 0..2 {
+            let visualizer = file_visualizer(url.clone());
+            visualizer
+                .emit(None, "task_spawned", json!({ "run": run }), None)
+                .await;
+            visualizer
+                .emit(None, "task_completed", json!({ "run": run }), None)
+                .await;
+            assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+        }
+
+        let events: Vec<(Value, Value, Value)> = lines(&path)
+            .into_iter()
+            .map(|event| {
+                (
+                    event["sequence"].clone(),
+                    event["actionType"].clone(),
+                    event["action"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (json!(0), json!("task_spawned"), json!({ "run": 0 })),
+                (json!(1), json!("task_completed"), json!({ "run": 0 })),
+                (json!(0), json!("task_spawned"), json!({ "run": 1 })),
+                (json!(1), json!("task_completed"), json!({ "run": 1 })),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn log_file_path_gets_every_event_until_shutdown() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("session.ndjson");
+        let visualizer = file_visualizer(super::file_url(&path).expect("file url"));
+
+        for n in 0..3 {
+            visualizer.emit(None, "tick", json!({ "n": n }), None).await;
+        }
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        let events: Vec<(Value, Value)> = lines(&path)
+            .into_iter()
+            .map(|event| (event["sequence"].clone(), event["action"].clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (json!(0), json!({ "n": 0 })),
+                (json!(1), json!({ "n": 1 })),
+                (json!(2), json!({ "n": 2 })),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn every_endpoint_gets_every_event_despite_a_failing_one() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let primary = dir.path().join("primary.ndjson");
+        let mirror = dir.path().join("mirror.ndjson");
+        let file_url = |path: &std::path::Path| {
+            url::Url::from_file_path(path)
+                .expect("file url")
+                .to_string()
+        };
+        let visualizer = AgentVisualizer::with_endpoints(
+            vec![
+                file_url(&primary),
+                // Nothing listens on port 1, so this relay is given up on.
+                "ws://127.0.0.1:1".to_string(),
+                file_url(&mirror),
+            ],
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_max_connect_failures(Some(1));
+
+        for action_type in ["task_spawned", "exec_output", "task_completed"] {
+            visualizer.emit(None, action_type, json!({}), None).await;
+        }
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        let expected = vec![
+            json!("task_spawned"),
+            json!("exec_output"),
+            json!("task_completed"),
+        ];
+        assert_eq!(
+            (action_types(&primary), action_types(&mirror)),
+            (expected.clone(), expected)
+        );
+    }
+}
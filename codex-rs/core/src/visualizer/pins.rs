@@ -0,0 +1,540 @@
+//! Trust-on-first-use pinning of `wss` relay certificates.
+//!
+//! The first time a relay at a given `host:port` completes a connection,
+//! the SHA-256 fingerprint of its certificate's public key (its SPKI, as in
+//! HPKP's `sha256/<base64>`) is recorded in [`PINS_FILE`] under
+//! `CODEX_HOME`. Later connections to that `host:port` only complete if the
+//! relay presents the same key; any other is refused with a [`PinMismatch`],
+//! which no retry can fix, so the forwarders stop for good. The pinned key
+//! stands in for the chain of trust, so neither the certificate's issuer
+//! nor its names and validity period are checked. `Op::ClearVisualizerPin`
+//! forgets a pin, letting the next connection pin whatever it sees.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use base64::Engine;
+use rustls::ClientConfig;
+use rustls::DigitallySignedStruct;
+use rustls::SignatureScheme;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::client::danger::ServerCertVerified;
+use rustls::client::danger::ServerCertVerifier;
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::ServerName;
+use rustls::pki_types::UnixTime;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::net::TcpStream;
+use tokio_tungstenite::Connector as TlsConnector;
+use tokio_tungstenite::MaybeTlsStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::connect_async_tls_with_config;
+use tokio_tungstenite::tungstenite::Error;
+use tokio_tungstenite::tungstenite::error::UrlError;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tracing::info;
+use url::Url;
+
+use super::tls::ClientTls;
+
+/// Pins by `host:port`, as a JSON object, under `CODEX_HOME`.
+pub(crate) const PINS_FILE: &str = "visualizer_pins.json";
+
+/// Locked for each read-modify-write cycle on [`PINS_FILE`], so sessions in
+/// other processes cannot lose each other's pins; the rename in
+/// [`PinStore::store`] keeps readers from seeing a torn file.
+const PINS_LOCK_FILE: &str = "visualizer_pins.json.lock";
+
+/// A relay presented a different key than the one pinned for it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "visualizer relay {host_port} presented certificate key {observed}, but {pinned} is pinned for it"
+)]
+pub(crate) struct PinMismatch {
+    pub(crate) host_port: String,
+    pub(crate) pinned: String,
+    pub(crate) observed: String,
+}
+
+/// The pins file of one `CODEX_HOME`.
+#[derive(Debug, Clone)]
+pub(crate) struct PinStore {
+    path: PathBuf,
+}
+
+impl PinStore {
+    pub(crate) fn new(codex_home: &Path) -> Self {
+        Self {
+            path: codex_home.join(PINS_FILE),
+        }
+    }
+
+    /// The fingerprint pinned for `host_port`, if any.
+    pub(crate) async fn get(&self, host_port: &str) -> io::Result<Option<String>> {
+        let host_port = host_port.to_string();
+        self.blocking(move |store| Ok(store.load()?.remove(&host_port)))
+            .await
+    }
+
+    /// Pin `fingerprint` for `host_port`, unless another connection pinned
+    /// one first; returns the pin that holds.
+    async fn pin(&self, host_port: &str, fingerprint: &str) -> io::Result<String> {
+        let host_port = host_port.to_string();
+        let fingerprint = fingerprint.to_string();
+        self.blocking(move |store| {
+            let _lock = store.lock()?;
+            let mut pins = store.load()?;
+            if let Some(pinned) = pins.get(&host_port) {
+                return Ok(pinned.clone());
+            }
+            pins.insert(host_port, fingerprint.clone());
+            store.store(&pins)?;
+            Ok(fingerprint)
+        })
+        .await
+    }
+
+    /// Forget the pin for `host_port`; returns whether there was one.
+    pub(crate) async fn clear(&self, host_port: &str) -> io::Result<bool> {
+        let host_port = host_port.to_string();
+        self.blocking(move |store| {
+            let _lock = store.lock()?;
+            let mut pins = store.load()?;
+            if pins.remove(&host_port).is_none() {
+                return Ok(false);
+            }
+            store.store(&pins)?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Run `f` on the store on a blocking thread, off the connect path.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&PinStore) -> io::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || f(&store))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)))
+    }
+
+    /// Take the lock on the pins file, waiting for other processes to
+    /// release it; it is held until the returned file is dropped.
+    fn lock(&self) -> io::Result<fs::File> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(PINS_LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    fn load(&self) -> io::Result<BTreeMap<String, String>> {
+        match fs::read(&self.path) {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replace the file as a whole, so it never holds a partial write.
+    fn store(&self, pins: &BTreeMap<String, String>) -> io::Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        tmp.write_all(&serde_json::to_vec_pretty(pins)?)?;
+        tmp.as_file().sync_data()?;
+        tmp.persist(&self.path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+/// The `host:port` a relay is pinned under; the port defaults to the
+/// scheme's.
+pub(super) fn host_port(url: &str) -> Result<String, UrlError> {
+    let parsed = Url::parse(url).map_err(|err| UrlError::UnableToConnect(err.to_string()))?;
+    let host = parsed.host_str().ok_or(UrlError::NoHostName)?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or(UrlError::UnsupportedUrlScheme)?;
+    Ok(format!("{host}:{port}"))
+}
+
+/// The [`PinMismatch`] that refused a connection, if that is why it failed.
+pub(super) fn pin_mismatch(err: &Error) -> Option<&PinMismatch> {
+    match err {
+        Error::Io(err) => err.get_ref()?.downcast_ref(),
+        _ => None,
+    }
+}
+
+/// Connect to the `wss` relay at `url` with the handshake `request`,
+/// pinning its key on first use and requiring the pinned one after, and
+/// presenting the client certificate of `tls`, if any.
+pub(super) async fn connect_pinned(
+    url: &str,
+    request: Request,
+    pins: &PinStore,
+    tls: Option<&ClientTls>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Error> {
+    let host_port = host_port(url).map_err(Error::Url)?;
+    let pinned = pins.get(&host_port).await?;
+    let provider = rustls::crypto::ring::default_provider();
+    let verifier = Arc::new(PinVerifier {
+        pinned: pinned.clone(),
+        observed: Mutex::new(None),
+        algorithms: provider.signature_verification_algorithms,
+    });
+    let builder = ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| Error::Tls(err.into()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::clone(&verifier) as Arc<dyn ServerCertVerifier>);
+    let config = match tls {
+        Some(tls) => tls.finish(builder).map_err(|err| Error::Tls(err.into()))?,
+        None => builder.with_no_client_auth(),
+    };
+    let connected = connect_async_tls_with_config(
+        request,
+        None,
+        false,
+        Some(TlsConnector::Rustls(Arc::new(config))),
+    )
+    .await;
+    let observed = verifier
+        .observed
+        .lock()
+        .ok()
+        .and_then(|observed| observed.clone());
+    let (ws, _) = match connected {
+        Ok(connected) => connected,
+        Err(err) => {
+            return Err(match (pinned, observed) {
+                (Some(pinned), Some(observed)) if pinned != observed => refused(PinMismatch {
+                    host_port,
+                    pinned,
+                    observed,
+                }),
+                _ => err,
+            });
+        }
+    };
+    if pinned.is_none()
+        && let Some(observed) = observed
+    {
+        let pinned = pins.pin(&host_port, &observed).await?;
+        if pinned != observed {
+            // Another session pinned a different key while this one
+            // connected.
+            return Err(refused(PinMismatch {
+                host_port,
+                pinned,
+                observed,
+            }));
+        }
+        info!("pinned visualizer relay {host_port} to certificate key {observed}");
+    }
+    Ok(ws)
+}
+
+/// The error a connection refused for `mismatch` fails with; see
+/// [`pin_mismatch`].
+fn refused(mismatch: PinMismatch) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, mismatch))
+}
+
+/// Accepts exactly the pinned key, or any key when there is no pin yet,
+/// and records the key it saw. Handshake signatures are still checked, so
+/// the relay must hold the private key.
+#[derive(Debug)]
+struct PinVerifier {
+    pinned: Option<String>,
+    observed: Mutex<Option<String>>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let observed = spki_fingerprint(end_entity).ok_or(rustls::Error::InvalidCertificate(
+            rustls::CertificateError::BadEncoding,
+        ))?;
+        if let Ok(mut slot) = self.observed.lock() {
+            *slot = Some(observed.clone());
+        }
+        match &self.pinned {
+            Some(pinned) if *pinned != observed => Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// `sha256/<base64>` of the DER `SubjectPublicKeyInfo` of the X.509
+/// certificate `cert`, or `None` if it does not parse.
+fn spki_fingerprint(cert: &CertificateDer<'_>) -> Option<String> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    let digest = Sha256::digest(cert.subject_public_key_info());
+    Some(format!(
+        "sha256/{}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use rustls::ServerConfig;
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+    use tokio_tungstenite::accept_async;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::VisualizerStatus;
+    use crate::visualizer::connection::Connector;
+    use crate::visualizer::connection::WebSocketConnector;
+
+    /// Two self-signed certificates for `127.0.0.1`, each with its own key.
+    const FIRST: (&[u8], &[u8]) = (
+        include_bytes!("testdata/relay-first.crt.der"),
+        include_bytes!("testdata/relay-first.key.der"),
+    );
+    const SECOND: (&[u8], &[u8]) = (
+        include_bytes!("testdata/relay-second.crt.der"),
+        include_bytes!("testdata/relay-second.key.der"),
+    );
+    /// As computed by `openssl x509 -pubkey | openssl pkey -pubin -outform
+    /// DER | openssl dgst -sha256 -binary | base64`.
+    const FIRST_PIN: &str = "sha256/FeNgU0imaptuz4B+fqkwnB0AtN5UpBEAkDv9+ayHPeE=";
+    const SECOND_PIN: &str = "sha256/iPZqSl5e4Eazh0DkzdhnM7OqvLnXZxWkNwX84sM1qM0=";
+
+    fn server_config((cert, key): (&[u8], &[u8])) -> Arc<ServerConfig> {
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("protocol versions")
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![CertificateDer::from(cert.to_vec())],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.to_vec())),
+                )
+                .expect("server certificate");
+        Arc::new(config)
+    }
+
+    /// A `wss` relay on one port that presents whichever certificate
+    /// `serving` holds when a connection comes in.
+    async fn tls_relay(serving: Arc<Mutex<Arc<ServerConfig>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let config = Arc::clone(&serving.lock().expect("serving"));
+                tokio::spawn(async move {
+                    let Ok(tls) = TlsAcceptor::from(config).accept(socket).await else {
+                        return;
+                    };
+                    let Ok(mut ws) = accept_async(tls).await else {
+                        return;
+                    };
+                    while let Some(Ok(_)) = ws.next().await {}
+                });
+            }
+        });
+        format!("wss://{addr}/")
+    }
+
+    async fn connect(connector: &WebSocketConnector, url: &str) -> Result<(), Error> {
+        connector.connect(url).await.map(drop)
+    }
+
+    #[test]
+    fn fingerprints_the_subject_public_key_info() {
+        assert_eq!(
+            (
+                spki_fingerprint(&CertificateDer::from(FIRST.0)),
+                spki_fingerprint(&CertificateDer::from(SECOND.0))
+            ),
+            (Some(FIRST_PIN.to_string()), Some(SECOND_PIN.to_string()))
+        );
+        assert_eq!(
+            spki_fingerprint(&CertificateDer::from(&FIRST.0[..100])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn pins_are_kept_per_host_and_port() {
+        let home = TempDir::new().expect("tempdir");
+        let store = PinStore::new(home.path());
+        store.pin("relay:443", FIRST_PIN).await.expect("pin");
+        store.pin("relay:8443", SECOND_PIN).await.expect("pin");
+        assert_eq!(
+            store.pin("relay:443", SECOND_PIN).await.expect("pin"),
+            FIRST_PIN.to_string(),
+            "an existing pin holds"
+        );
+
+        assert!(store.clear("relay:443").await.expect("clear"));
+        assert!(!store.clear("relay:443").await.expect("clear"));
+        let contents = fs::read_to_string(home.path().join(PINS_FILE)).expect("pins file");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&contents).expect("json"),
+            json!({ "relay:8443": SECOND_PIN })
+        );
+        let mut files: Vec<_> = fs::read_dir(home.path())
+            .expect("read dir")
+            .map(|entry| entry.expect("entry").file_name())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![PINS_FILE, PINS_LOCK_FILE],
+            "no temporary file is left"
+        );
+    }
+
+    #[tokio::test]
+    async fn first_key_is_pinned_then_required() {
+        let home = TempDir::new().expect("tempdir");
+        let store = PinStore::new(home.path());
+        let serving = Arc::new(Mutex::new(server_config(FIRST)));
+        let url = tls_relay(Arc::clone(&serving)).await;
+        let host_port = host_port(&url).expect("host and port");
+        let connector = WebSocketConnector::default();
+        connector.pin_tls(store.clone());
+
+        connect(&connector, &url).await.expect("first connection");
+        assert_eq!(
+            store.get(&host_port).await.expect("pins"),
+            Some(FIRST_PIN.to_string())
+        );
+        connect(&connector, &url)
+            .await
+            .expect("pinned key is accepted");
+
+        *serving.lock().expect("serving") = server_config(SECOND);
+        let err = connect(&connector, &url)
+            .await
+            .expect_err("other key is refused");
+        assert_eq!(
+            pin_mismatch(&err),
+            Some(&PinMismatch {
+                host_port: host_port.clone(),
+                pinned: FIRST_PIN.to_string(),
+                observed: SECOND_PIN.to_string(),
+            })
+        );
+        assert_eq!(
+            store.get(&host_port).await.expect("pins"),
+            Some(FIRST_PIN.to_string()),
+            "a refused key does not replace the pin"
+        );
+
+        assert!(store.clear(&host_port).await.expect("clear"));
+        connect(&connector, &url)
+            .await
+            .expect("cleared pin lets the new key in");
+        assert_eq!(
+            store.get(&host_port).await.expect("pins"),
+            Some(SECOND_PIN.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn mismatch_stops_the_forwarder_for_good() {
+        let home = TempDir::new().expect("tempdir");
+        let store = PinStore::new(home.path());
+        let url = tls_relay(Arc::new(Mutex::new(server_config(SECOND)))).await;
+        let host_port = host_port(&url).expect("host and port");
+        store.pin(&host_port, FIRST_PIN).await.expect("pin");
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_pinned_tls(store);
+
+        visualizer.emit(None, "tick", json!({}), None).await;
+        let status = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match visualizer.status() {
+                    VisualizerStatus::Running { .. } => {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    status => return status,
+                }
+            }
+        })
+        .await
+        .expect("forwarder stops");
+        assert_eq!(
+            status,
+            VisualizerStatus::PinRejected {
+                mismatch: PinMismatch {
+                    host_port,
+                    pinned: FIRST_PIN.to_string(),
+                    observed: SECOND_PIN.to_string(),
+                },
+            }
+        );
+    }
+}
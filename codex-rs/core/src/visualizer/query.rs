@@ -0,0 +1,189 @@
+//! Filtering of the recent-event ring for `Op::QueryRecentEvents` and the
+//! TUI activity pane.
+
+use codex_protocol::protocol::EventQuery;
+use codex_protocol::protocol::RecentEvent;
+
+use super::VisualizerEvent;
+
+/// Whether `event` matches every key set in `query`; `limit` is applied by
+/// the caller.
+pub(super) fn matches(query: &EventQuery, event: &VisualizerEvent) -> bool {
+    query
+        .action_types
+        .as_ref()
+        .is_none_or(|action_types| action_types.contains(&event.action_type))
+        && query
+            .sub_id
+            .as_ref()
+            .is_none_or(|sub_id| event.sub_id.as_ref() == Some(sub_id))
+        && query
+            .since_sequence
+            .is_none_or(|since| event.sequence > since)
+}
+
+impl From<VisualizerEvent> for RecentEvent {
+    fn from(event: VisualizerEvent) -> Self {
+        Self {
+            sequence: event.sequence,
+            timestamp_ms: event.timestamp_ms,
+            action_type: event.action_type,
+            sub_id: event.sub_id,
+            action: event.action,
+            state: event.state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::ContentField;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::scope::OMITTED_CONTENT;
+
+    /// Sequence numbers of the events `query` returns from a ring holding,
+    /// in order: for each of `sub-1` and `sub-2`, a `task_started`, two
+    /// `exec_output`s and an `exec_approval_request` protocol event, then a
+    /// `memory_bootstrap` belonging to no task.
+    async fn query(query: EventQuery) -> Vec<u64> {
+        let visualizer = AgentVisualizer::default();
+        for sub_id in ["sub-1", "sub-2"] {
+            for action_type in ["task_started", "exec_output", "exec_output"] {
+                visualizer
+                    .emit(None, action_type, json!({ "subId": sub_id }), None)
+                    .await;
+            }
+            let event =
+                json!({ "event": { "id": sub_id, "msg": { "type": "exec_approval_request" } } });
+            visualizer.emit(None, "protocol_event", event, None).await;
+        }
+        visualizer
+            .emit(None, "memory_bootstrap", json!({}), None)
+            .await;
+        visualizer
+            .query_recent(&query)
+            .iter()
+            .map(|event| event.sequence)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn each_filter_narrows_the_ring_on_its_own() {
+        assert_eq!(
+            query(EventQuery::default()).await,
+            (0..9).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            query(EventQuery {
+                action_types: Some(vec![
+                    "task_started".to_string(),
+                    "memory_bootstrap".to_string()
+                ]),
+                ..Default::default()
+            })
+            .await,
+            vec![0, 4, 8]
+        );
+        assert_eq!(
+            query(EventQuery {
+                sub_id: Some("sub-2".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![4, 5, 6, 7]
+        );
+        assert_eq!(
+            query(EventQuery {
+                since_sequence: Some(5),
+                ..Default::default()
+            })
+            .await,
+            vec![6, 7, 8]
+        );
+        assert_eq!(
+            query(EventQuery {
+                limit: 2,
+                ..Default::default()
+            })
+            .await,
+            vec![7, 8]
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_combine() {
+        let sub_1_without_output = EventQuery {
+            action_types: Some(vec![
+                "task_started".to_string(),
+                "protocol_event".to_string(),
+            ]),
+            sub_id: Some("sub-1".to_string()),
+            since_sequence: None,
+            limit: 20,
+        };
+        assert_eq!(query(sub_1_without_output).await, vec![0, 3]);
+
+        let newest_output = EventQuery {
+            action_types: Some(vec!["exec_output".to_string()]),
+            sub_id: Some("sub-2".to_string()),
+            since_sequence: Some(4),
+            limit: 1,
+        };
+        assert_eq!(query(newest_output).await, vec![6]);
+    }
+
+    #[tokio::test]
+    async fn queried_events_are_redacted_like_for_a_remote_sink() {
+        let visualizer = AgentVisualizer::new(
+            None,
+            TelemetryFidelity::Coarse,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::new([PathBuf::from("/repo")]),
+        );
+        let content = ContentField::tag_all(vec![
+            (vec!["inside".to_string()], PathBuf::from("/repo/a.rs")),
+            (vec!["outside".to_string()], PathBuf::from("/etc/passwd")),
+        ])
+        .await;
+        visualizer
+            .emit_scoped(
+                None,
+                "protocol_event",
+                json!({ "inside": "kept", "outside": "secret", "durationMs": 1_234 }),
+                content,
+                None,
+            )
+            .await;
+
+        let mut events = visualizer.query_recent(&EventQuery::default());
+        assert_eq!(events.len(), 1);
+        let mut action = events.remove(0).action;
+        let inside = action["inside"].take();
+        assert!(
+            inside
+                .as_str()
+                .is_some_and(|inside| inside.starts_with("sha256:")),
+            "{inside}"
+        );
+        assert_eq!(
+            action,
+            json!({
+                "inside": null,
+                "outside": OMITTED_CONTENT,
+                "durationMs": 1_000,
+            })
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Events a relay missed, replayed once it connects (`visualizer_replay`,
+//! off unless configured).
+//!
+//! A relay started after the session would otherwise begin its timeline
+//! mid-flight, without the `task_spawned` that explains the lane it is
+//! looking at. Events wait in the queue while no relay can be reached, so
+//! the ones a long outage loses are those the queue drops for want of room.
+//! The queue keeps those in a [`ReplayBuffer`], and right after the next
+//! successful connect, once the relay was greeted, the forwarder writes
+//! them before anything live. Replayed events are sent exactly as they were, plus
+//! `"replayed": true`. An event written once, live or replayed, leaves the
+//! buffer for good, so no relay is sent it twice; one that a failed replay
+//! did not get to stays for the next connect. Events waiting to be sent are
+//! not in the buffer, so they only ever go out live.
+//!
+//! The buffer holds at most [`EventReplay::max_events`] events, none older
+//! than [`EventReplay::max_age`], whether or not a relay ever connects.
+//! When it is full, the oldest event that is not a `task_*` lifecycle event
+//! makes room, so the few events that explain a timeline outlast the many
+//! that fill it. Only transports with a relay at the other end replay (see
+//! [`Connector::greets`]); a file or a stream would just get duplicates.
+//!
+//! [`Connector::greets`]: super::connection::Connector::greets
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::VisualizerEvent;
+
+/// How many recent events are replayed to a relay that connects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EventReplay {
+    /// Events kept; `0` replays nothing.
+    pub(crate) max_events: usize,
+    /// Events older than this are not replayed.
+    pub(crate) max_age: Duration,
+}
+
+impl Default for EventReplay {
+    fn default() -> Self {
+        Self {
+            max_events: 500,
+            max_age: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// The events a forwarder is done with, oldest first, within its
+/// [`EventReplay`] limits.
+#[derive(Debug)]
+pub(super) struct ReplayBuffer {
+    events: VecDeque<VisualizerEvent>,
+    /// `None` keeps nothing.
+    limits: Option<EventReplay>,
+}
+
+impl ReplayBuffer {
+    pub(super) fn new(limits: Option<EventReplay>) -> Self {
+        Self {
+            events: VecDeque::new(),
+            limits,
+        }
+    }
+
+    /// Replace the limits, dropping what they no longer allow.
+    pub(super) fn set_limits(&mut self, limits: Option<EventReplay>) {
+        self.limits = limits;
+        self.evict();
+    }
+
+    /// Keep `event` for the next connect, evicting as the limits say.
+    pub(super) fn record(&mut self, event: &VisualizerEvent) {
+        if self.limits.is_none_or(|limits| limits.max_events == 0) {
+            return;
+        }
+        self.events.push_back(event.clone());
+        self.evict();
+    }
+
+    /// Take the events to replay at `now_ms`, oldest first, each flagged
+    /// as replayed.
+    pub(super) fn take(&mut self, now_ms: u128) -> Vec<VisualizerEvent> {
+        let Some(limits) = self.limits else {
+            return Vec::new();
+        };
+        std::mem::take(&mut self.events)
+            .into_iter()
+            .filter(|event| !expired(event, limits, now_ms))
+            .map(|event| VisualizerEvent {
+                replayed: true,
+                ..event
+            })
+            .collect()
+    }
+
+    /// Put back `events`, taken but not written, ahead of anything kept
+    /// since.
+    pub(super) fn restore(&mut self, events: Vec<VisualizerEvent>) {
+        for event in events.into_iter().rev() {
+            self.events.push_front(event);
+        }
+        self.evict();
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    fn evict(&mut self) {
+        let Some(limits) = self.limits else {
+            self.events.clear();
+            return;
+        };
+        if let Some(newest) = self.events.back().map(|event| event.timestamp_ms) {
+            self.events.retain(|event| !expired(event, limits, newest));
+        }
+        while self.events.len() > limits.max_events {
+            let oldest_routine = self
+                .events
+                .iter()
+                .position(|event| !is_lifecycle(event))
+                .unwrap_or(0);
+            self.events.remove(oldest_routine);
+        }
+    }
+}
+
+fn expired(event: &VisualizerEvent, limits: EventReplay, now_ms: u128) -> bool {
+    now_ms.saturating_sub(event.timestamp_ms) > limits.max_age.as_millis()
+}
+
+/// Whether `event` is one of the `task_*` events a timeline is laid out
+/// by.
+fn is_lifecycle(event: &VisualizerEvent) -> bool {
+    event.action_type.starts_with("task_")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn event(sequence: u64, action_type: &str, timestamp_ms: u128) -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms,
+            ..VisualizerEvent::for_test(sequence, action_type)
+        }
+    }
+
+    fn taken(buffer: &mut ReplayBuffer, now_ms: u128) -> Vec<(u64, String, bool)> {
+        buffer
+            .take(now_ms)
+            .into_iter()
+            .map(|event| (event.sequence, event.action_type, event.replayed))
+            .collect()
+    }
+
+    #[test]
+    fn full_buffer_evicts_routine_events_before_lifecycle_ones() {
+        let mut buffer = ReplayBuffer::new(Some(EventReplay {
+            max_events: 3,
+            max_age: Duration::from_secs(60),
+        }));
+        buffer.record(&event(0, "task_spawned", 1_000));
+        buffer.record(&event(1, "exec_output", 1_000));
+        buffer.record(&event(2, "task_started", 1_000));
+        buffer.record(&event(3, "exec_output", 1_000));
+        buffer.record(&event(4, "exec_output", 1_000));
+
+        assert_eq!(
+            taken(&mut buffer, 1_000),
+            vec![
+                (0, "task_spawned".to_string(), true),
+                (2, "task_started".to_string(), true),
+                (4, "exec_output".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn taken_events_are_replayed_once_unless_restored() {
+        let mut buffer = ReplayBuffer::new(Some(EventReplay::default()));
+        buffer.record(&event(0, "task_spawned", 1_000));
+        buffer.record(&event(1, "exec_output", 1_000));
+        let mut first = buffer.take(1_000);
+        buffer.record(&event(2, "exec_output", 1_000));
+
+        // The replay wrote the first event, then failed.
+        buffer.restore(first.split_off(1));
+        assert_eq!(
+            (taken(&mut buffer, 1_000), taken(&mut buffer, 1_000)),
+            (
+                vec![
+                    (1, "exec_output".to_string(), true),
+                    (2, "exec_output".to_string(), true),
+                ],
+                Vec::new(),
+            )
+        );
+    }
+
+    #[test]
+    fn events_past_the_age_limit_are_neither_kept_nor_replayed() {
+        let mut buffer = ReplayBuffer::new(Some(EventReplay {
+            max_events: 10,
+            max_age: Duration::from_secs(1),
+        }));
+        buffer.record(&event(0, "task_spawned", 1_000));
+        buffer.record(&event(1, "tick", 1_500));
+        // Evicts the first event; the second expires before the replay.
+        buffer.record(&event(2, "tick", 2_200));
+
+        assert_eq!(buffer.events.len(), 2);
+        assert_eq!(
+            taken(&mut buffer, 2_600),
+            vec![(2, "tick".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn disabled_buffer_keeps_nothing() {
+        let mut buffer = ReplayBuffer::new(Some(EventReplay::default()));
+        buffer.record(&event(0, "task_spawned", 1_000));
+        buffer.set_limits(None);
+        buffer.record(&event(1, "task_spawned", 1_000));
+
+        assert_eq!(taken(&mut buffer, 1_000), Vec::new());
+    }
+}
@@ -0,0 +1,287 @@
+//! Self-contained HTML export of a session's task timeline, for reviewing
+//! unattended runs without the live UI.
+
+use std::fmt::Write as _;
+
+use serde_json::json;
+
+use super::VisualizerEvent;
+use super::timeline::Lane;
+use super::timeline::LaneOutcome;
+use super::timeline::TimelineAnnotation;
+use super::timeline::TimelineState;
+
+const STYLE: &str = r#"
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2rem; color: #1f2328; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #d0d7de; padding: 0.4rem 0.6rem; text-align: left; vertical-align: top; }
+.summary { color: #57606a; }
+.gaps { color: #9a6700; }
+.outcome-completed { color: #1a7f37; }
+.outcome-aborted, .outcome-rejected { color: #cf222e; }
+.outcome-running { color: #57606a; }
+.track { position: relative; height: 0.8rem; min-width: 16rem; background: #f6f8fa; }
+.bar { position: absolute; top: 0; bottom: 0; background: #0969da; min-width: 2px; }
+ul { margin: 0; padding-left: 1.1rem; }
+"#;
+
+/// Lay each lane's bar out against the overall session span.
+const SCRIPT: &str = r#"
+const data = JSON.parse(document.getElementById("timeline-data").textContent);
+const starts = data.lanes.map((l) => l.startedMs).filter((v) => v != null);
+const ends = data.lanes.map((l) => l.endedMs ?? l.startedMs).filter((v) => v != null);
+const min = Math.min(...starts), max = Math.max(...ends);
+const span = Math.max(max - min, 1);
+for (const lane of data.lanes) {
+  const bar = document.querySelector(`.bar[data-sub-id="${CSS.escape(lane.subId)}"]`);
+  if (!bar || lane.startedMs == null) continue;
+  const end = lane.endedMs ?? max;
+  bar.style.left = `${((lane.startedMs - min) / span) * 100}%`;
+  bar.style.width = `${((end - lane.startedMs) / span) * 100}%`;
+}
+"#;
+
+/// Render `events` as a standalone HTML page: a table of task lanes with
+/// durations, tool calls, and outcomes, a table of reviewer annotations,
+/// plus the folded timeline embedded as JSON. Sequence gaps and replayed events are reported, not fatal.
+pub(crate) fn render_html(events: &[VisualizerEvent]) -> String {
+    let timeline = TimelineState::from_events(events);
+    let gaps = timeline.gaps();
+    let lanes: Vec<&Lane> = timeline.lanes().collect();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Codex task timeline</title>\n");
+    let _ = writeln!(html, "<style>{STYLE}</style>\n</head>\n<body>");
+    html.push_str("<h1>Codex task timeline</h1>\n");
+    let _ = writeln!(
+        html,
+        "<p class=\"summary\">{} lanes · {} events · {} gaps · {} replayed</p>",
+        timeline.lane_count(),
+        events.len(),
+        gaps.len(),
+        timeline.replayed()
+    );
+    if !gaps.is_empty() {
+        let ranges = gaps
+            .iter()
+            .map(|gap| {
+                if gap.from == gap.to {
+                    gap.from.to_string()
+                } else {
+                    format!("{}–{}", gap.from, gap.to)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            html,
+            "<p class=\"gaps\">Missing event sequences: {ranges}</p>"
+        );
+    }
+
+    html.push_str("<table>\n<thead><tr><th>Task</th><th>Kind</th><th>Outcome</th><th>Duration</th><th>Tool calls</th><th>Timeline</th></tr></thead>\n<tbody>\n");
+    for lane in &lanes {
+        render_lane(&mut html, lane);
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    let annotations = timeline.annotations();
+    if !annotations.is_empty() {
+        html.push_str("<h2>Annotations</h2>\n<table>\n<thead><tr><th>Time</th><th>Task</th><th>Note</th><th>Tags</th></tr></thead>\n<tbody>\n");
+        for annotation in annotations {
+            render_annotation(&mut html, annotation);
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    let data = json!({
+        "lanes": lanes,
+        "annotations": annotations,
+        "gaps": gaps,
+        "eventCount": events.len(),
+        "replayed": timeline.replayed(),
+    });
+    // `<` never appears outside string literals in JSON, so escaping it
+    // keeps `</script>` inside payloads from closing the element.
+    let data = data.to_string().replace('<', "\\u003c");
+    let _ = writeln!(
+        html,
+        "<script type=\"application/json\" id=\"timeline-data\">{data}</script>"
+    );
+    let _ = writeln!(html, "<script>{SCRIPT}</script>\n</body>\n</html>");
+    html
+}
+
+fn render_lane(html: &mut String, lane: &Lane) {
+    let (outcome_class, outcome_label) = match &lane.outcome {
+        LaneOutcome::Running => ("running", "Running".to_string()),
+        LaneOutcome::Completed => ("completed", "Completed".to_string()),
+        LaneOutcome::Aborted { reason } => ("aborted", format!("Aborted: {reason}")),
+        LaneOutcome::Rejected { reason } => (
+            "rejected",
+            format!(
+                "Rejected: {}",
+                reason["problem"].as_str().unwrap_or("spawn rejected")
+            ),
+        ),
+    };
+    let duration = lane
+        .duration_ms()
+        .map(format_duration)
+        .unwrap_or_else(|| "—".to_string());
+    let tool_calls = if lane.tool_calls.is_empty() {
+        String::new()
+    } else {
+        let items: String = lane
+            .tool_calls
+            .iter()
+            .map(|call| format!("<li>{}: {}</li>", escape(&call.kind), escape(&call.label)))
+            .collect();
+        format!("<ul>{items}</ul>")
+    };
+    let sub_id = escape(&lane.sub_id);
+    let _ = writeln!(
+        html,
+        "<tr class=\"lane\"><td>{sub_id}</td><td>{}</td><td class=\"outcome-{outcome_class}\">{}</td><td>{duration}</td><td>{tool_calls}</td><td><div class=\"track\"><div class=\"bar\" data-sub-id=\"{sub_id}\"></div></div></td></tr>",
+        escape(lane.task_kind.as_deref().unwrap_or("?")),
+        escape(&outcome_label),
+    );
+}
+
+fn render_annotation(html: &mut String, annotation: &TimelineAnnotation) {
+    let _ = writeln!(
+        html,
+        "<tr class=\"annotation\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+        annotation.at_ms,
+        escape(annotation.sub_id.as_deref().unwrap_or("—")),
+        escape(&annotation.text),
+        escape(&annotation.tags.join(", ")),
+    );
+}
+
+fn format_duration(ms: u128) -> String {
+    if ms < 1_000 {
+        format!("{ms} ms")
+    } else if ms < 60_000 {
+        format!("{:.1} s", ms as f64 / 1_000.0)
+    } else {
+        let secs = ms / 1_000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualizer::timeline::SequenceGap;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    fn event(
+        sequence: u64,
+        timestamp_ms: u128,
+        action_type: &str,
+        action: Value,
+    ) -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms,
+            action,
+            ..VisualizerEvent::for_test(sequence, action_type)
+        }
+    }
+
+    fn fixture() -> Vec<VisualizerEvent> {
+        vec![
+            event(
+                0,
+                1_000,
+                "task_spawned",
+                json!({ "subId": "1", "taskKind": "Regular" }),
+            ),
+            event(
+                1,
+                1_200,
+                "protocol_event",
+                json!({ "event": { "id": "1", "msg": {
+                    "type": "exec_command_begin",
+                    "call_id": "c1",
+                    "command": ["cargo", "test"],
+                    "cwd": "/repo",
+                    "parsed_cmd": [],
+                } } }),
+            ),
+            event(2, 2_500, "task_completed", json!({ "subId": "1" })),
+            event(
+                3,
+                3_000,
+                "task_spawned",
+                json!({ "subId": "2", "taskKind": "Review" }),
+            ),
+            // Sequences 4 and 5 were lost.
+            event(
+                6,
+                3_450,
+                "task_aborted",
+                json!({ "subId": "2", "reason": "Interrupted" }),
+            ),
+            // Replay of an event that was already applied.
+            event(
+                2,
+                2_500,
+                "task_completed",
+                json!({ "subId": "1", "replay": true }),
+            ),
+            event(
+                7,
+                4_000,
+                "protocol_event",
+                json!({ "event": { "id": "3", "msg": {
+                    "type": "mcp_tool_call_begin",
+                    "call_id": "c2",
+                    "invocation": { "server": "docs", "tool": "<search>" },
+                } } }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn timeline_tolerates_gaps_and_replays() {
+        let timeline = TimelineState::from_events(&fixture());
+        assert_eq!(timeline.lane_count(), 3);
+        assert_eq!(timeline.replayed(), 1);
+        assert_eq!(timeline.gaps(), vec![SequenceGap { from: 4, to: 5 }]);
+        let durations: Vec<Option<u128>> = timeline.lanes().map(Lane::duration_ms).collect();
+        assert_eq!(durations, vec![Some(1_500), Some(450), None]);
+    }
+
+    #[test]
+    fn html_contains_lane_markers() {
+        let html = render_html(&fixture());
+        assert!(html.contains("3 lanes · 7 events · 1 gaps · 1 replayed"));
+        assert!(html.contains("Missing event sequences: 4–5"));
+        assert!(html.contains("1.5 s"));
+        assert!(html.contains("450 ms"));
+        assert!(html.contains("Aborted: Interrupted"));
+        assert!(html.contains("<li>exec: cargo test</li>"));
+        assert!(html.contains("<li>mcp: docs/&lt;search&gt;</li>"));
+        assert!(!html.contains("<search>"));
+        assert!(!html.contains("src=\"http"));
+        assert_eq!(html.matches("<tr class=\"lane\">").count(), 3);
+    }
+}
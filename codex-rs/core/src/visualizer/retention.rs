@@ -0,0 +1,195 @@
+//! Per-action-type retention of the in-memory recent-event ring.
+//!
+//! Rules only ever drop events of their own action type, so noisy types can
+//! be thinned out without evicting lifecycle events that share the ring.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use super::VisualizerEvent;
+use crate::config_types::VisualizerRetention;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RetentionRules {
+    rules: Arc<HashMap<String, VisualizerRetention>>,
+}
+
+impl RetentionRules {
+    pub(crate) fn new(rules: HashMap<String, VisualizerRetention>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+        }
+    }
+
+    /// Append `event` to `recent`, which holds at most `limit` events, then
+    /// drop the events of its action type that its rule no longer allows.
+    pub(super) fn record(
+        &self,
+        recent: &mut VecDeque<VisualizerEvent>,
+        event: &VisualizerEvent,
+        limit: usize,
+    ) {
+        let rule = self.rule(&event.action_type);
+        if rule.max_count == Some(0) {
+            return;
+        }
+        if recent.len() == limit {
+            recent.pop_front();
+        }
+        recent.push_back(event.clone());
+
+        let action_type = event.action_type.as_str();
+        if rule.max_age.is_some() {
+            recent.retain(|kept| {
+                kept.action_type != action_type || !self.expired(kept, event.timestamp_ms)
+            });
+        }
+        if let Some(max_count) = rule.max_count {
+            let kept = recent
+                .iter()
+                .filter(|kept| kept.action_type == action_type)
+                .count();
+            let mut excess = kept.saturating_sub(max_count);
+            recent.retain(|kept| {
+                if excess > 0 && kept.action_type == action_type {
+                    excess -= 1;
+                    return false;
+                }
+                true
+            });
+        }
+    }
+
+    /// Whether `event` is past its rule's age limit at `now_ms`. Events are
+    /// only aged out on writes of their own type, so readers filter too.
+    pub(super) fn expired(&self, event: &VisualizerEvent, now_ms: u128) -> bool {
+        self.rule(&event.action_type)
+            .max_age
+            .is_some_and(|max_age| now_ms.saturating_sub(event.timestamp_ms) > max_age.as_millis())
+    }
+
+    fn rule(&self, action_type: &str) -> VisualizerRetention {
+        self.rules.get(action_type).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn event(sequence: u64, action_type: &str, timestamp_ms: u128) -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms,
+            ..VisualizerEvent::for_test(sequence, action_type)
+        }
+    }
+
+    fn survivors(recent: &VecDeque<VisualizerEvent>) -> Vec<(u64, String)> {
+        recent
+            .iter()
+            .map(|event| (event.sequence, event.action_type.clone()))
+            .collect()
+    }
+
+    fn rules() -> RetentionRules {
+        RetentionRules::new(HashMap::from([
+            (
+                "exec_output".to_string(),
+                VisualizerRetention {
+                    max_count: Some(2),
+                    max_age: None,
+                },
+            ),
+            (
+                "assistant_preview".to_string(),
+                VisualizerRetention {
+                    max_count: Some(0),
+                    max_age: None,
+                },
+            ),
+            (
+                "reasoning_phase".to_string(),
+                VisualizerRetention {
+                    max_count: None,
+                    max_age: Some(Duration::from_secs(10)),
+                },
+            ),
+        ]))
+    }
+
+    #[test]
+    fn excess_events_are_dropped_per_action_type() {
+        let rules = rules();
+        let mut recent = VecDeque::new();
+        let types = [
+            "task_spawned",
+            "exec_output",
+            "assistant_preview",
+            "exec_output",
+            "exec_output",
+            "assistant_preview",
+            "exec_output",
+            "task_completed",
+        ];
+        for (sequence, action_type) in types.into_iter().enumerate() {
+            rules.record(&mut recent, &event(sequence as u64, action_type, 0), 16);
+        }
+
+        assert_eq!(
+            survivors(&recent),
+            vec![
+                (0, "task_spawned".to_string()),
+                (4, "exec_output".to_string()),
+                (6, "exec_output".to_string()),
+                (7, "task_completed".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn old_events_age_out_on_write_and_on_read() {
+        let rules = rules();
+        let mut recent = VecDeque::new();
+        rules.record(&mut recent, &event(0, "reasoning_phase", 0), 16);
+        rules.record(&mut recent, &event(1, "task_spawned", 0), 16);
+        rules.record(&mut recent, &event(2, "reasoning_phase", 5_000), 16);
+        rules.record(&mut recent, &event(3, "reasoning_phase", 12_000), 16);
+
+        assert_eq!(
+            survivors(&recent),
+            vec![
+                (1, "task_spawned".to_string()),
+                (2, "reasoning_phase".to_string()),
+                (3, "reasoning_phase".to_string()),
+            ]
+        );
+        let expired: Vec<bool> = recent
+            .iter()
+            .map(|event| rules.expired(event, 20_000))
+            .collect();
+        assert_eq!(expired, vec![false, true, false]);
+    }
+
+    #[test]
+    fn ring_limit_still_applies_to_unruled_types() {
+        let rules = RetentionRules::default();
+        let mut recent = VecDeque::new();
+        for sequence in 0..4 {
+            rules.record(&mut recent, &event(sequence, "task_spawned", 0), 3);
+        }
+
+        assert_eq!(
+            survivors(&recent),
+            vec![
+                (1, "task_spawned".to_string()),
+                (2, "task_spawned".to_string()),
+                (3, "task_spawned".to_string()),
+            ]
+        );
+    }
+}
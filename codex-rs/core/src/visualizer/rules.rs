@@ -0,0 +1,651 @@
+//! Filtering, sampling, redaction and truncation of visualizer events,
+//! replaceable at runtime with `Op::UpdateTelemetryConfig`.
+//!
+//! The rules are read once per emitted event, before it gets its sequence
+//! number, so events they filter out leave no gap in the sequence, and each
+//! event is handled entirely under the rules current when it was emitted.
+//! Swapping them touches neither the relay connection nor the queue.
+//! Lifecycle events (see `strict`) and the visualizer's own `telemetry_*`
+//! diagnostics are never filtered out or sampled.
+//!
+//! Besides the replaceable rules, `CODEX_VISUALIZER_INCLUDE` and
+//! `CODEX_VISUALIZER_EXCLUDE` fix an [`ActionTypeFilter`] for the whole
+//! session: comma-separated globs, where `*` stands for any run of
+//! characters, that action types must match one of and must not match any
+//! of, respectively. An action type matching both is excluded.
+//!
+//! `CODEX_VISUALIZER_SAMPLE` likewise fixes [`ActionTypeSampling`]: a
+//! comma-separated list of `glob:rate` pairs, such as
+//! `agent_delta:0.05,exec_output:0.2`, that keep each event of a matching
+//! action type with the first matching pair's probability. Whether an event
+//! is kept depends only on `CODEX_VISUALIZER_SAMPLE_SEED`, its action type
+//! and how many events of that type came before it, so a fixed seed samples
+//! the same events on every run. `task_*` events are never sampled this
+//! way, whatever the globs match.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use codex_protocol::protocol::TelemetryConfigError;
+use codex_protocol::protocol::TelemetryConfigPatch;
+use regex_lite::Regex;
+use serde_json::Value;
+use tracing::warn;
+
+use super::strict;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// The current [`TelemetryRules`], shared by every clone of a visualizer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LiveRules {
+    current: Arc<RwLock<Arc<TelemetryRules>>>,
+}
+
+impl LiveRules {
+    pub(super) fn current(&self) -> Arc<TelemetryRules> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Apply `patch` as a whole or not at all. Returns the keys whose value
+    /// changed.
+    pub(super) fn update(
+        &self,
+        patch: TelemetryConfigPatch,
+    ) -> Result<Vec<&'static str>, Vec<TelemetryConfigError>> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let (rules, changed) = current.patched(patch)?;
+        *current = Arc::new(rules);
+        Ok(changed)
+    }
+
+    /// Filter action types with `filter` from now on, whatever later
+    /// updates change.
+    pub(super) fn set_action_type_filter(&self, filter: ActionTypeFilter) {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut rules = TelemetryRules::clone(&current);
+        rules.action_type_filter = filter;
+        *current = Arc::new(rules);
+    }
+
+    /// Sample action types with `sampling` from now on, whatever later
+    /// updates change.
+    pub(super) fn set_action_type_sampling(&self, sampling: ActionTypeSampling) {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut rules = TelemetryRules::clone(&current);
+        rules.action_type_sampling = sampling;
+        *current = Arc::new(rules);
+    }
+
+    /// Events sampled away by action type since the last call; see
+    /// [`ActionTypeSampling::take_sampled_away`].
+    pub(super) fn take_sampled_away(&self) -> BTreeMap<String, u64> {
+        self.current().action_type_sampling.take_sampled_away()
+    }
+}
+
+/// Action types to emit, from `CODEX_VISUALIZER_INCLUDE` and
+/// `CODEX_VISUALIZER_EXCLUDE`; see the module docs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ActionTypeFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ActionTypeFilter {
+    /// The filter for the comma-separated globs `include` and `exclude`;
+    /// without any to include, everything not excluded is.
+    pub(crate) fn new(include: Option<&str>, exclude: Option<&str>) -> Self {
+        let globs = |list: Option<&str>| {
+            list.unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|glob| !glob.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        Self {
+            include: globs(include),
+            exclude: globs(exclude),
+        }
+    }
+
+    fn admits(&self, action_type: &str) -> bool {
+        let matches = |glob: &String| glob_matches(glob, action_type);
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Probabilistic sampling by action type, from `CODEX_VISUALIZER_SAMPLE`
+/// and `CODEX_VISUALIZER_SAMPLE_SEED`; see the module docs.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActionTypeSampling {
+    /// `(glob, rate)` pairs, in the order given.
+    rates: Vec<(String, f64)>,
+    seed: u64,
+    /// Shared by every copy of these rules, so updates keep the counts.
+    counts: Arc<Mutex<SamplingCounts>>,
+}
+
+#[derive(Debug, Default)]
+struct SamplingCounts {
+    /// Events of each sampled action type seen so far.
+    seen: HashMap<String, u64>,
+    /// Events sampled away since the last summary, by action type.
+    sampled_away: BTreeMap<String, u64>,
+}
+
+impl ActionTypeSampling {
+    /// Sampling for the comma-separated `glob:rate` pairs in `rates`. Pairs
+    /// that do not parse, or whose rate is not in `[0, 1]`, are skipped with
+    /// a warning.
+    pub(crate) fn new(rates: Option<&str>, seed: u64) -> Self {
+        let rates = rates
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| {
+                let parsed = pair
+                    .rsplit_once(':')
+                    .and_then(|(glob, rate)| Some((glob.trim(), rate.trim().parse::<f64>().ok()?)))
+                    .filter(|(glob, rate)| !glob.is_empty() && (0.0..=1.0).contains(rate));
+                if parsed.is_none() {
+                    warn!("ignoring CODEX_VISUALIZER_SAMPLE entry {pair:?}");
+                }
+                parsed.map(|(glob, rate)| (glob.to_string(), rate))
+            })
+            .collect();
+        Self {
+            rates,
+            seed,
+            counts: Arc::default(),
+        }
+    }
+
+    fn rate(&self, action_type: &str) -> Option<f64> {
+        // Nor is the summary of what was sampled away.
+        if action_type.starts_with("task_") || action_type == "visualizer_events_sampled" {
+            return None;
+        }
+        self.rates
+            .iter()
+            .find(|(glob, _)| glob_matches(glob, action_type))
+            .map(|(_, rate)| *rate)
+    }
+
+    /// Whether to keep this event of `action_type`, counting it as sampled
+    /// away if not.
+    fn admit(&self, action_type: &str) -> bool {
+        let Some(rate) = self.rate(action_type) else {
+            return true;
+        };
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = counts.seen.entry(action_type.to_string()).or_default();
+        let index = *seen;
+        *seen += 1;
+        let keep = sample_point(self.seed, action_type, index) < rate;
+        if !keep {
+            *counts
+                .sampled_away
+                .entry(action_type.to_string())
+                .or_default() += 1;
+        }
+        keep
+    }
+
+    /// Events sampled away by action type since the last call.
+    pub(crate) fn take_sampled_away(&self) -> BTreeMap<String, u64> {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut counts.sampled_away)
+    }
+}
+
+/// A point in `[0, 1)` fixed by `seed`, `action_type` and `index`, spread
+/// evenly enough that comparing it with a rate keeps that share of events.
+fn sample_point(seed: u64, action_type: &str, index: u64) -> f64 {
+    // FNV-1a over the action type, then splitmix64's finalizer.
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in action_type.bytes() {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    let mut mixed = seed ^ hash ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    mixed ^= mixed >> 31;
+    // The top 53 bits, as many as an `f64` holds exactly.
+    (mixed >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Whether `text` as a whole matches `glob`, in which `*` matches any run
+/// of characters, including none, and everything else only itself.
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.next_back() else {
+        // No `*` at all.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TelemetryRules {
+    action_type_filter: ActionTypeFilter,
+    action_type_sampling: ActionTypeSampling,
+    exclude_action_types: BTreeSet<String>,
+    /// Shared with the rules that replace these unless the rates change,
+    /// so an update of another key does not restart sampling.
+    samplers: HashMap<String, Arc<Sampler>>,
+    redact_patterns: Vec<Regex>,
+    max_string_chars: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Sampler {
+    rate: f64,
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    /// Keeps the first event, then one each time `rate` of the events seen
+    /// adds up to another whole event, so a rate of `0.25` keeps events 1,
+    /// 5, 9 and so on.
+    fn admit(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * self.rate).ceil() > (seen * self.rate).ceil()
+    }
+}
+
+impl TelemetryRules {
+    /// Whether an event of `action_type` is emitted at all.
+    pub(super) fn admits(&self, action_type: &str, action: &Value) -> bool {
+        if action_type.starts_with("telemetry_") || strict::is_lifecycle_action(action_type, action)
+        {
+            return true;
+        }
+        if !self.action_type_filter.admits(action_type)
+            || self.exclude_action_types.contains(action_type)
+        {
+            return false;
+        }
+        self.samplers
+            .get(action_type)
+            .is_none_or(|sampler| sampler.admit())
+            && self.action_type_sampling.admit(action_type)
+    }
+
+    /// Redact, then truncate, every string in `value`, so truncation cannot
+    /// cut a secret short of its pattern.
+    pub(super) fn scrub(&self, value: &mut Value) {
+        if self.redact_patterns.is_empty() && self.max_string_chars.is_none() {
+            return;
+        }
+        match value {
+            Value::String(text) => self.scrub_string(text),
+            Value::Array(items) => {
+                for item in items {
+                    self.scrub(item);
+                }
+            }
+            Value::Object(fields) => {
+                for field in fields.values_mut() {
+                    self.scrub(field);
+                }
+            }
+            Value::Null | Value::Bool(_) | Value::Number(_) => {}
+        }
+    }
+
+    fn scrub_string(&self, text: &mut String) {
+        for pattern in &self.redact_patterns {
+            if let Cow::Owned(redacted) = pattern.replace_all(text, REDACTED) {
+                *text = redacted;
+            }
+        }
+        if let Some(max_chars) = self.max_string_chars
+            && let Some((cut, _)) = text.char_indices().nth(max_chars)
+        {
+            text.truncate(cut);
+            text.push('…');
+        }
+    }
+
+    /// These rules with `patch` applied, and the keys whose value changed.
+    fn patched(
+        &self,
+        patch: TelemetryConfigPatch,
+    ) -> Result<(Self, Vec<&'static str>), Vec<TelemetryConfigError>> {
+        let mut errors = Vec::new();
+        let mut changed = Vec::new();
+
+        let exclude_action_types = match patch.exclude_action_types {
+            Some(action_types) => {
+                for (index, action_type) in action_types.iter().enumerate() {
+                    if action_type.trim().is_empty() {
+                        errors.push(error(
+                            format!("exclude_action_types[{index}]"),
+                            "action type is empty".to_string(),
+                        ));
+                    }
+                }
+                let action_types: BTreeSet<String> = action_types.into_iter().collect();
+                if action_types != self.exclude_action_types {
+                    changed.push("exclude_action_types");
+                }
+                action_types
+            }
+            None => self.exclude_action_types.clone(),
+        };
+
+        let samplers = match patch.sample_rates {
+            Some(rates) => {
+                let mut action_types: Vec<&String> = rates.keys().collect();
+                action_types.sort();
+                for action_type in action_types {
+                    let rate = rates[action_type];
+                    if rate.is_nan() || rate <= 0.0 || rate > 1.0 {
+                        errors.push(error(
+                            format!("sample_rates.{action_type}"),
+                            format!("sampling rate {rate} is not in (0, 1]"),
+                        ));
+                    }
+                }
+                let unchanged = rates.len() == self.samplers.len()
+                    && rates.iter().all(|(action_type, rate)| {
+                        self.samplers
+                            .get(action_type)
+                            .is_some_and(|sampler| sampler.rate == *rate)
+                    });
+                if unchanged {
+                    self.samplers.clone()
+                } else {
+                    changed.push("sample_rates");
+                    rates
+                        .into_iter()
+                        .map(|(action_type, rate)| {
+                            let sampler = Sampler {
+                                rate,
+                                seen: AtomicU64::new(0),
+                            };
+                            (action_type, Arc::new(sampler))
+                        })
+                        .collect()
+                }
+            }
+            None => self.samplers.clone(),
+        };
+
+        let redact_patterns = match patch.redact_patterns {
+            Some(patterns) => {
+                let mut compiled = Vec::new();
+                for (index, pattern) in patterns.iter().enumerate() {
+                    let key = format!("redact_patterns[{index}]");
+                    match Regex::new(pattern) {
+                        Ok(regex) if regex.is_match("") => {
+                            errors.push(error(key, "pattern matches the empty string".to_string()))
+                        }
+                        Ok(regex) => compiled.push(regex),
+                        Err(err) => errors.push(error(key, err.to_string())),
+                    }
+                }
+                let current: Vec<&str> = self.redact_patterns.iter().map(Regex::as_str).collect();
+                if patterns != current {
+                    changed.push("redact_patterns");
+                }
+                compiled
+            }
+            None => self.redact_patterns.clone(),
+        };
+
+        let max_string_chars = match patch.max_string_chars {
+            Some(0) => None,
+            Some(max_chars) => Some(max_chars),
+            None => self.max_string_chars,
+        };
+        if max_string_chars != self.max_string_chars {
+            changed.push("max_string_chars");
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let rules = Self {
+            action_type_filter: self.action_type_filter.clone(),
+            action_type_sampling: self.action_type_sampling.clone(),
+            exclude_action_types,
+            samplers,
+            redact_patterns,
+            max_string_chars,
+        };
+        Ok((rules, changed))
+    }
+}
+
+fn error(key: String, message: String) -> TelemetryConfigError {
+    TelemetryConfigError { key, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn invalid_patches_change_nothing() {
+        let rules = LiveRules::default();
+        let rejected = rules.update(TelemetryConfigPatch {
+            exclude_action_types: Some(vec!["reasoning_phase".to_string()]),
+            sample_rates: Some(HashMap::from([("exec_output".to_string(), 1.5)])),
+            redact_patterns: Some(vec!["sk-[a-z]+".to_string(), "(unclosed".to_string()]),
+            max_string_chars: None,
+        });
+        let keys: Option<Vec<String>> = rejected
+            .err()
+            .map(|errors| errors.into_iter().map(|error| error.key).collect());
+        assert_eq!(
+            keys,
+            Some(vec![
+                "sample_rates.exec_output".to_string(),
+                "redact_patterns[1]".to_string(),
+            ])
+        );
+        assert!(rules.current().admits("reasoning_phase", &json!({})));
+
+        let empty_match = rules.update(TelemetryConfigPatch {
+            redact_patterns: Some(vec!["x*".to_string()]),
+            ..Default::default()
+        });
+        assert_eq!(
+            empty_match,
+            Err(vec![error(
+                "redact_patterns[0]".to_string(),
+                "pattern matches the empty string".to_string(),
+            )])
+        );
+    }
+
+    #[test]
+    fn updates_report_only_the_keys_that_changed() {
+        let rules = LiveRules::default();
+        let patch = TelemetryConfigPatch {
+            exclude_action_types: Some(vec!["reasoning_phase".to_string()]),
+            max_string_chars: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            rules.update(patch.clone()),
+            Ok(vec!["exclude_action_types"])
+        );
+        assert_eq!(rules.update(patch), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn rules_filter_sample_redact_and_truncate() {
+        let rules = LiveRules::default();
+        let update = rules.update(TelemetryConfigPatch {
+            exclude_action_types: Some(vec!["reasoning_phase".to_string()]),
+            sample_rates: Some(HashMap::from([("exec_output".to_string(), 0.5)])),
+            redact_patterns: Some(vec!["sk-[a-z0-9]+".to_string()]),
+            max_string_chars: Some(16),
+        });
+        assert!(update.is_ok());
+        let rules = rules.current();
+
+        let admitted: Vec<bool> = [
+            ("reasoning_phase", json!({})),
+            ("exec_output", json!({})),
+            ("exec_output", json!({})),
+            ("exec_output", json!({})),
+            ("task_completed", json!({})),
+            ("telemetry_config_updated", json!({})),
+        ]
+        .iter()
+        .map(|(action_type, action)| rules.admits(action_type, action))
+        .collect();
+        assert_eq!(admitted, vec![false, true, false, true, true, true]);
+
+        let mut action = json!({
+            "command": ["curl", "-H", "key: sk-abc123"],
+            "output": "a very long line of output",
+            "exitCode": 0,
+        });
+        rules.scrub(&mut action);
+        assert_eq!(
+            action,
+            json!({
+                "command": ["curl", "-H", "key: [REDACTED]"],
+                "output": "a very long line…",
+                "exitCode": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn action_types_are_filtered_by_glob_with_exclude_winning() {
+        let action_types = [
+            "task_progress",
+            "task_retry_attempt",
+            "exec_output",
+            "exec_command_begin",
+            "reasoning_phase",
+            // A lifecycle event, which is never filtered out.
+            "task_completed",
+        ];
+        let admitted = |include: Option<&str>, exclude: Option<&str>| {
+            let rules = LiveRules::default();
+            rules.set_action_type_filter(ActionTypeFilter::new(include, exclude));
+            let rules = rules.current();
+            action_types
+                .iter()
+                .map(|action_type| rules.admits(action_type, &json!({})))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            (
+                admitted(Some("task_*, exec_*"), None),
+                admitted(None, Some("exec_*_begin,reasoning_phase")),
+                admitted(
+                    Some("task_*,exec_*"),
+                    Some("*_output,task_retry_*,task_completed")
+                ),
+            ),
+            (
+                vec![true, true, true, true, false, true],
+                vec![true, true, true, false, false, true],
+                vec![true, false, false, true, false, true],
+            )
+        );
+    }
+
+    #[test]
+    fn the_action_type_filter_survives_updates() {
+        let rules = LiveRules::default();
+        rules.set_action_type_filter(ActionTypeFilter::new(Some("task_*"), None));
+        let update = rules.update(TelemetryConfigPatch {
+            max_string_chars: Some(16),
+            ..Default::default()
+        });
+        assert_eq!(update, Ok(vec!["max_string_chars"]));
+        assert!(!rules.current().admits("exec_output", &json!({})));
+    }
+
+    #[test]
+    fn env_sampling_keeps_about_the_configured_share_of_events() {
+        let rules = LiveRules::default();
+        rules.set_action_type_sampling(ActionTypeSampling::new(
+            Some("agent_delta:0.05, exec_*:0.2, *:0"),
+            42,
+        ));
+        let current = rules.current();
+        let kept = |action_type: &str| {
+            (0..4000)
+                .filter(|_| current.admits(action_type, &json!({})))
+                .count()
+        };
+        let (agent_delta, exec_output, task_progress, task_completed) = (
+            kept("agent_delta"),
+            kept("exec_output"),
+            kept("task_progress"),
+            kept("task_completed"),
+        );
+        assert!((140..=260).contains(&agent_delta), "kept {agent_delta}");
+        assert!((700..=900).contains(&exec_output), "kept {exec_output}");
+        assert_eq!((task_progress, task_completed), (4000, 4000));
+        assert_eq!(kept("reasoning_phase"), 0);
+
+        let sampled_away = rules.take_sampled_away();
+        assert_eq!(
+            (sampled_away, rules.take_sampled_away()),
+            (
+                BTreeMap::from([
+                    ("agent_delta".to_string(), (4000 - agent_delta) as u64),
+                    ("exec_output".to_string(), (4000 - exec_output) as u64),
+                    ("reasoning_phase".to_string(), 4000),
+                ]),
+                BTreeMap::new(),
+            )
+        );
+    }
+
+    #[test]
+    fn env_sampling_is_reproducible_for_a_seed() {
+        let decisions = |seed: u64| {
+            let rules = LiveRules::default();
+            rules.set_action_type_sampling(ActionTypeSampling::new(Some("exec_output:0.5"), seed));
+            let current = rules.current();
+            (0..256)
+                .map(|_| current.admits("exec_output", &json!({})))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(decisions(7), decisions(7));
+        assert_ne!(decisions(7), decisions(8));
+    }
+
+    #[test]
+    fn malformed_sample_entries_are_skipped() {
+        let sampling =
+            ActionTypeSampling::new(Some("exec_output:0.2,agent_delta,:0.5,x:2,y:nan"), 0);
+        assert_eq!(sampling.rates, vec![("exec_output".to_string(), 0.2)]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Keeps file contents from outside the trusted roots off remote sinks.
+//!
+//! Emit sites tag the payload fields that hold file contents with a
+//! [`ContentField`]: a JSON pointer into the action and the file the content
+//! belongs to. Before an event leaves for a non-loopback sink, every tagged
+//! field whose file lies outside all trusted roots is replaced by
+//! [`OMITTED_CONTENT`]. Paths themselves are kept, so the timeline still
+//! shows which file was touched. Untagged fields are not inspected.
+
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use super::VisualizerEvent;
+
+pub(crate) const OMITTED_CONTENT: &str = "[content outside workspace omitted]";
+
+/// A field of an event's action that holds the contents of `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ContentField {
+    /// JSON pointer into the action, e.g. `/event/msg/stdout`.
+    pub(crate) pointer: String,
+    /// The file, normalized with [`normalize_path`].
+    pub(crate) path: PathBuf,
+}
+
+impl ContentField {
+    /// `segments` are the unescaped reference tokens of the pointer.
+    #[cfg(test)]
+    pub(crate) fn new<'a>(segments: impl IntoIterator<Item = &'a str>, path: &Path) -> Self {
+        Self {
+            pointer: pointer(segments),
+            path: normalize_path(path),
+        }
+    }
+
+    /// Tag `fields`, given as the pointer's unescaped reference tokens and
+    /// the file. Paths are normalized on a blocking thread, since that
+    /// resolves symlinks on disk. Should that thread fail, the fields are
+    /// tagged with an empty path, which no root contains, so their content
+    /// is omitted rather than sent.
+    pub(crate) async fn tag_all(fields: Vec<(Vec<String>, PathBuf)>) -> Vec<Self> {
+        if fields.is_empty() {
+            return Vec::new();
+        }
+        let (pointers, paths): (Vec<String>, Vec<PathBuf>) = fields
+            .into_iter()
+            .map(|(segments, path)| (pointer(segments.iter().map(String::as_str)), path))
+            .unzip();
+        let count = paths.len();
+        let paths = tokio::task::spawn_blocking(move || {
+            paths
+                .iter()
+                .map(|path| normalize_path(path))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_else(|_| vec![PathBuf::new(); count]);
+        pointers
+            .into_iter()
+            .zip(paths)
+            .map(|(pointer, path)| Self { pointer, path })
+            .collect()
+    }
+}
+
+fn pointer<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+    segments
+        .into_iter()
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Directories whose file contents may leave the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TrustedRoots {
+    roots: Arc<Vec<PathBuf>>,
+}
+
+impl TrustedRoots {
+    pub(crate) fn new(roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            roots: Arc::new(
+                roots
+                    .into_iter()
+                    .map(|root| normalize_path(&root))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Whether any tagged field of `event` would be omitted.
+    pub(super) fn omits_any(&self, event: &VisualizerEvent) -> bool {
+        event
+            .content
+            .iter()
+            .any(|field| !self.contains(&field.path))
+    }
+
+    /// Replace the tagged fields of `event` whose file is outside the roots.
+    pub(super) fn omit_outside(&self, event: &mut VisualizerEvent) {
+        for field in &event.content {
+            if self.contains(&field.path) {
+                continue;
+            }
+            if let Some(value) = event.action.pointer_mut(&field.pointer) {
+                *value = Value::String(OMITTED_CONTENT.to_string());
+            }
+        }
+    }
+}
+
+/// Resolve `.` and `..` lexically, then symlinks through the longest
+/// existing ancestor, so files that do not exist yet (or any more) compare
+/// like their directory does. Falls back to the lexical form.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut lexical = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            other => lexical.push(other),
+        }
+    }
+    for ancestor in lexical.ancestors() {
+        if let Ok(canonical) = dunce::canonicalize(ancestor) {
+            return match lexical.strip_prefix(ancestor) {
+                Ok(rest) if !rest.as_os_str().is_empty() => canonical.join(rest),
+                _ => canonical,
+            };
+        }
+    }
+    lexical
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pointer_segments_are_escaped() {
+        let field = ContentField::new(["event", "msg", "changes", "/repo/a~b.rs"], Path::new("/"));
+        assert_eq!(field.pointer, "/event/msg/changes/~1repo~1a~0b.rs");
+    }
+
+    #[test]
+    fn parent_components_cannot_escape_a_root() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let roots = TrustedRoots::new([dir.path().join("repo")]);
+
+        assert_eq!(
+            (
+                roots.contains(&normalize_path(&dir.path().join("repo/src/new.rs"))),
+                roots.contains(&normalize_path(&dir.path().join("repo/../secrets"))),
+            ),
+            (true, false)
+        );
+    }
+
+    #[test]
+    fn only_tagged_fields_outside_the_roots_are_omitted() {
+        let roots = TrustedRoots::new([PathBuf::from("/repo")]);
+        let mut event = VisualizerEvent {
+            action: json!({ "inside": "kept", "outside": "secret", "untagged": "kept" }),
+            content: vec![
+                ContentField::new(["inside"], Path::new("/repo/a.rs")),
+                ContentField::new(["outside"], Path::new("/etc/passwd")),
+            ],
+            ..VisualizerEvent::for_test(0, "protocol_event")
+        };
+
+        assert!(roots.omits_any(&event));
+        roots.omit_outside(&mut event);
+        assert_eq!(
+            event.action,
+            json!({ "inside": "kept", "outside": OMITTED_CONTENT, "untagged": "kept" })
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! One-shot end-to-end check of a visualizer endpoint, for users wiring up
+//! `CODEX_VISUALIZER_WS` without running a full session.
+
+use std::time::Duration;
+
+use futures::SinkExt;
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use serde_json::json;
+use tokio::time::Instant;
+use tokio::time::timeout_at;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use super::AgentVisualizer;
+use super::ChunkReassembler;
+use super::RelayToken;
+use super::RetentionRules;
+use super::TelemetryFidelity;
+use super::TimestampEncoding;
+use super::TrustedRoots;
+use super::VisualizerStatus;
+use super::auth::relay_token_from_env;
+use super::ensure_producer_role;
+use super::with_role;
+use crate::config_types::VisualizerTokenPlacement;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    pub url: String,
+    pub connected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_latency_ms: Option<u64>,
+    /// `gzip` when the relay accepted gzipped frames for large events,
+    /// `json` otherwise.
+    pub encoding: String,
+    /// The visualizer confirmed that the `self_test` event was written, as
+    /// `AgentVisualizer::flush` does at the end of a session.
+    pub event_written: bool,
+    pub round_trip: RoundTrip,
+    pub errors: Vec<String>,
+}
+
+impl SelfTestReport {
+    pub fn ok(&self) -> bool {
+        self.connected && self.event_written && self.errors.is_empty()
+    }
+}
+
+/// Whether the event was observed coming back through a viewer connection.
+/// Only relays that fan producer events out to viewers can confirm this.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum RoundTrip {
+    /// No viewer connection could be opened alongside the producer.
+    Unsupported,
+    /// A viewer connected but did not see the event before the deadline.
+    NotObserved,
+    #[serde(rename_all = "camelCase")]
+    Confirmed { latency_ms: u64 },
+}
+
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Emit a single `self_test` event through an `AgentVisualizer` for
+/// `url`, as a session would, and report what happened. Never fails;
+/// problems are collected in [`SelfTestReport::errors`]. `timeout` bounds
+/// the whole run.
+pub async fn self_test(url: &str, timeout: Duration) -> SelfTestReport {
+    let deadline = Instant::now() + timeout;
+    let mut report = SelfTestReport {
+        url: url.to_string(),
+        connected: false,
+        connect_latency_ms: None,
+        encoding: "json".to_string(),
+        event_written: false,
+        round_trip: RoundTrip::Unsupported,
+        errors: Vec::new(),
+    };
+
+    if let Err(err) = ensure_producer_role(url) {
+        report.errors.push(format!("invalid visualizer url: {err}"));
+        return report;
+    }
+
+    // Open the viewer first so a relay cannot broadcast before we listen.
+    let mut viewer = match with_role(url, "viewer") {
+        Ok(viewer_url) => connect(&viewer_url, deadline).await.ok(),
+        Err(_) => None,
+    };
+
+    let visualizer = AgentVisualizer::new(
+        Some(url.to_string()),
+        TelemetryFidelity::Full,
+        None,
+        RetentionRules::default(),
+        TimestampEncoding::Number,
+        TrustedRoots::default(),
+    )
+    // A session keeps retrying; one refused connect is enough to report.
+    .with_max_connect_failures(Some(1))
+    // Offered so the report shows whether the relay takes it.
+    .with_compression();
+    let visualizer = match relay_token_from_env() {
+        Some(token) => {
+            visualizer.with_relay_token(RelayToken::new(token, VisualizerTokenPlacement::default()))
+        }
+        None => visualizer,
+    };
+
+    let nonce = Uuid::new_v4().to_string();
+    let sent_at = Instant::now();
+    visualizer
+        .emit(None, "self_test", json!({ "nonce": nonce }), None)
+        .await;
+    let flushed = visualizer.flush(deadline.saturating_duration_since(Instant::now()));
+    let abandoned = async {
+        match visualizer.abandonment() {
+            Some(mut abandonment) => {
+                let _ = abandonment.wait_for(Option::is_some).await;
+            }
+            None => std::future::pending().await,
+        }
+    };
+    report.event_written = tokio::select! {
+        flushed = flushed => flushed == Some(true),
+        () = abandoned => false,
+    };
+
+    match visualizer.status() {
+        VisualizerStatus::Running {
+            connect_latency: Some(latency),
+            ..
+        } => {
+            report.connected = true;
+            report.connect_latency_ms = Some(latency.as_millis() as u64);
+            if visualizer
+                .accepted_capabilities()
+                .is_some_and(|accepted| accepted.gzip)
+            {
+                report.encoding = "gzip".to_string();
+            }
+            if !report.event_written {
+                report.errors.push("timed out writing event".to_string());
+            }
+        }
+        VisualizerStatus::Running {
+            connect_latency: None,
+            ..
+        } => report.errors.push(format!("timed out connecting to {url}")),
+        VisualizerStatus::Abandoned { .. } => {
+            report.errors.push(format!("failed to connect to {url}"));
+        }
+        VisualizerStatus::AuthRejected { status } => report
+            .errors
+            .push(format!("{url} refused the relay token with HTTP {status}")),
+        VisualizerStatus::PinRejected { mismatch } => report.errors.push(mismatch.to_string()),
+        VisualizerStatus::Failed { panics } => report.errors.push(format!(
+            "visualizer forwarder failed: {}",
+            panics.join("; ")
+        )),
+        VisualizerStatus::Disabled => {}
+    }
+    visualizer
+        .shutdown(deadline.saturating_duration_since(Instant::now()))
+        .await;
+
+    if let Some(viewer) = viewer.as_mut() {
+        report.round_trip = if report.event_written
+            && timeout_at(deadline, wait_for_echo(viewer, &nonce))
+                .await
+                .unwrap_or(false)
+        {
+            RoundTrip::Confirmed {
+                latency_ms: sent_at.elapsed().as_millis() as u64,
+            }
+        } else {
+            RoundTrip::NotObserved
+        };
+        let _ = timeout_at(deadline, viewer.close(None)).await;
+    }
+
+    report
+}
+
+async fn connect(url: &str, deadline: Instant) -> Result<WsStream, String> {
+    match timeout_at(deadline, connect_async(url)).await {
+        Ok(Ok((ws, _))) => Ok(ws),
+        Ok(Err(err)) => Err(format!("failed to connect to {url}: {err}")),
+        Err(_) => Err(format!("timed out connecting to {url}")),
+    }
+}
+
+/// Read viewer frames until the relay echoes our event back. Accepts both a
+/// bare event and the relay's `{"type":"event","event":...}` envelope, and
+/// reassembles other sessions' chunked events along the way.
+async fn wait_for_echo(viewer: &mut WsStream, nonce: &str) -> bool {
+    let mut reassembler = ChunkReassembler::default();
+    while let Some(message) = viewer.next().await {
+        let Ok(Message::Text(text)) = message else {
+            continue;
+        };
+        let Ok(mut value) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        let event = match value.get_mut("event").map(Value::take) {
+            Some(event) => event,
+            None => value,
+        };
+        let Some(event) = reassembler.push(event) else {
+            continue;
+        };
+        if event["actionType"] == "self_test" && event["action"]["nonce"] == nonce {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::accept_async;
+
+    use crate::visualizer::hello::is_hello;
+    use crate::visualizer::hello::welcome;
+
+    /// Accepts any number of connections, welcomes every greeting and
+    /// forwards every other text frame it receives; never echoes.
+    async fn capture_server() -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let Ok(mut ws) = accept_async(socket).await else {
+                        return;
+                    };
+                    while let Some(Ok(message)) = ws.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let frame: Value = serde_json::from_str(&text).unwrap_or_default();
+                        if is_hello(&frame) {
+                            let _ = ws.send(Message::Text(welcome(&frame))).await;
+                        } else {
+                            let _ = tx.send(text);
+                        }
+                    }
+                });
+            }
+        });
+        (format!("ws://{addr}"), rx)
+    }
+
+    #[tokio::test]
+    async fn reports_success_against_capture_server() {
+        let (url, mut captured) = capture_server().await;
+
+        let mut report = self_test(&url, Duration::from_millis(300)).await;
+        assert!(report.connect_latency_ms.is_some());
+        report.connect_latency_ms = None;
+        assert_eq!(
+            report,
+            SelfTestReport {
+                url,
+                connected: true,
+                connect_latency_ms: None,
+                encoding: "gzip".to_string(),
+                event_written: true,
+                round_trip: RoundTrip::NotObserved,
+                errors: Vec::new(),
+            }
+        );
+        assert!(report.ok());
+
+        let frame: Value =
+            serde_json::from_str(&captured.recv().await.expect("frame")).expect("json");
+        assert_eq!(frame["actionType"], json!("self_test"));
+    }
+
+    #[tokio::test]
+    async fn reports_failure_against_dead_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let url = format!("ws://{}", listener.local_addr().expect("addr"));
+        drop(listener);
+
+        let report = self_test(&url, Duration::from_millis(300)).await;
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            report,
+            SelfTestReport {
+                url,
+                connected: false,
+                connect_latency_ms: None,
+                encoding: "json".to_string(),
+                event_written: false,
+                round_trip: RoundTrip::Unsupported,
+                errors: report.errors.clone(),
+            }
+        );
+        assert!(!report.ok());
+
+        let serialized = serde_json::to_value(&report).expect("serialize");
+        assert_eq!(serialized["roundTrip"], json!({ "status": "unsupported" }));
+        assert_eq!(serialized.get("connectLatencyMs"), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Cap on the serialized size of an event sent in one frame.
+//!
+//! A state snapshot can carry the whole conversation history, making
+//! frames of several megabytes that stall the socket or exceed the relay's
+//! frame limit. Unless the sink sends oversized events in chunks (see the
+//! `chunks` module, whose own cap applies then), the forwarder cuts an event
+//! that serializes to more than the cap down until it fits: first its
+//! `state`, then the longest strings in its `action`, halving the length
+//! that counts as long each round, and as a last resort the whole action.
+//! Each value cut is replaced by `{"truncated": true, "originalBytes": N}`,
+//! with `N` the serialized size of the value, so consumers can tell data
+//! that was cut from data that was never there. The result is always a
+//! complete event, never JSON cut off mid-value; an event whose cut-down
+//! form fails to serialize is logged and not sent at all.
+
+use serde_json::Value;
+use serde_json::json;
+use tracing::error;
+use tracing::warn;
+
+use super::VisualizerEvent;
+use super::wire::TimestampEncoding;
+use super::wire::WireEvent;
+
+/// Strings no longer than this are never replaced, since the marker would
+/// hardly be shorter.
+const MIN_TRUNCATED_BYTES: usize = 64;
+
+/// `serialized`, the encoding of `event`, or if it is longer than
+/// `max_bytes`, the encoding of `event` cut down to fit; see the module
+/// docs. `None` if that fails to serialize.
+pub(super) fn capped(
+    serialized: String,
+    event: &VisualizerEvent,
+    max_bytes: Option<usize>,
+    timestamps: TimestampEncoding,
+) -> Option<String> {
+    let Some(max_bytes) = max_bytes.filter(|max_bytes| serialized.len() > *max_bytes) else {
+        return Some(serialized);
+    };
+    warn!(
+        "visualizer event `{}` (sequence {}) is {} bytes, over the {max_bytes}-byte cap; truncating it",
+        event.action_type,
+        event.sequence,
+        serialized.len(),
+    );
+    let mut attempt = event.clone();
+    if let Some(state) = attempt.state.as_mut() {
+        *state = marker(state);
+        if let Some(frame) = encode_within(&attempt, max_bytes, timestamps) {
+            return Some(frame);
+        }
+    }
+    let mut long_bytes = longest_string(&attempt.action);
+    while long_bytes > MIN_TRUNCATED_BYTES {
+        long_bytes /= 2;
+        truncate_strings(&mut attempt.action, long_bytes.max(MIN_TRUNCATED_BYTES));
+        if let Some(frame) = encode_within(&attempt, max_bytes, timestamps) {
+            return Some(frame);
+        }
+    }
+    attempt.action = marker(&event.action);
+    serde_json::to_string(&WireEvent::new(&attempt, timestamps))
+        .inspect_err(|err| {
+            error!(
+                "failed to serialize truncated visualizer event `{}` (sequence {}); not sending it: {err}",
+                event.action_type, event.sequence
+            );
+        })
+        .ok()
+}
+
+fn encode_within(
+    event: &VisualizerEvent,
+    max_bytes: usize,
+    timestamps: TimestampEncoding,
+) -> Option<String> {
+    serde_json::to_string(&WireEvent::new(event, timestamps))
+        .ok()
+        .filter(|frame| frame.len() <= max_bytes)
+}
+
+fn marker(value: &Value) -> Value {
+    let original_bytes = serde_json::to_string(value).map_or(0, |encoded| encoded.len());
+    json!({ "truncated": true, "originalBytes": original_bytes })
+}
+
+fn longest_string(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.len(),
+        Value::Array(items) => items.iter().map(longest_string).max().unwrap_or(0),
+        Value::Object(fields) => fields.values().map(longest_string).max().unwrap_or(0),
+        Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+    }
+}
+
+/// Replace every string in `value` longer than `max_bytes` with a marker.
+fn truncate_strings(value: &mut Value, max_bytes: usize) {
+    match value {
+        Value::String(text) if text.len() > max_bytes => *value = marker(value),
+        Value::Array(items) => {
+            for item in items {
+                truncate_strings(item, max_bytes);
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                truncate_strings(field, max_bytes);
+            }
+        }
+        Value::String(_) | Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const CAP: usize = 16 * 1024;
+
+    fn event(action: Value, state: Option<Value>) -> VisualizerEvent {
+        VisualizerEvent {
+            action,
+            state,
+            ..VisualizerEvent::for_test(3, "memory_snapshot")
+        }
+    }
+
+    fn cap(event: &VisualizerEvent) -> Value {
+        let serialized = serde_json::to_string(&WireEvent::new(event, TimestampEncoding::Number))
+            .expect("serialize");
+        let frame =
+            capped(serialized, event, Some(CAP), TimestampEncoding::Number).expect("serialize");
+        assert!(frame.len() <= CAP, "{} bytes", frame.len());
+        serde_json::from_str(&frame).expect("valid json")
+    }
+
+    #[test]
+    fn small_events_are_left_alone() {
+        let event = event(json!({ "note": "short" }), Some(json!({ "turns": 1 })));
+        let capped = cap(&event);
+        assert_eq!(
+            (&capped["action"], &capped["state"]),
+            (&json!({ "note": "short" }), &json!({ "turns": 1 }))
+        );
+    }
+
+    #[test]
+    fn huge_nested_payloads_lose_their_state_then_their_longest_strings() {
+        let history: Vec<Value> = (0..200)
+            .map(|turn| json!({ "turn": turn, "text": "history ".repeat(500) }))
+            .collect();
+        let state = json!({ "history": history });
+        let diff = "+ fn main() {}\n".repeat(2_000);
+        let action = json!({
+            "files": [
+                { "path": "src/main.rs", "diff": diff },
+                { "path": "README.md", "diff": "+ docs\n" },
+            ],
+            "summary": { "title": "edit", "body": "b".repeat(4_000) },
+        });
+        let state_bytes = serde_json::to_string(&state).expect("serialize").len();
+        let diff_bytes = serde_json::to_string(&diff).expect("serialize").len();
+
+        let capped = cap(&event(action, Some(state)));
+        assert_eq!(
+            (
+                &capped["state"],
+                &capped["action"]["files"][0]["diff"],
+                &capped["action"]["files"][1],
+                &capped["action"]["summary"]["title"],
+            ),
+            (
+                &json!({ "truncated": true, "originalBytes": state_bytes }),
+                &json!({ "truncated": true, "originalBytes": diff_bytes }),
+                &json!({ "path": "README.md", "diff": "+ docs\n" }),
+                &json!("edit"),
+            )
+        );
+        // Short enough to survive the round that cut the diff.
+        assert_eq!(
+            capped["action"]["summary"]["body"],
+            json!("b".repeat(4_000))
+        );
+    }
+
+    #[test]
+    fn actions_of_many_short_values_are_replaced_whole() {
+        let lines: Vec<String> = (0..5_000).map(|line| format!("line {line}")).collect();
+        let action = json!({ "lines": lines });
+        let action_bytes = serde_json::to_string(&action).expect("serialize").len();
+
+        let capped = cap(&event(action, None));
+        assert_eq!(
+            (&capped["action"], &capped["actionType"]),
+            (
+                &json!({ "truncated": true, "originalBytes": action_bytes }),
+                &json!("memory_snapshot"),
+            )
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Standard output and standard error sinks, for seeing what a consumer
+//! would get without running a relay.
+//!
+//! `CODEX_VISUALIZER_WS=stdout` (or `stderr`) writes each frame the
+//! forwarder would send as one line, so the stream is NDJSON exactly as a
+//! relay would receive it; `stdout?pretty` (or `stderr?pretty`) indents
+//! each frame over several lines instead, for reading rather than parsing.
+//! Lines are buffered and written out whenever the forwarder flushes, at
+//! the latest once its queue drains, so a burst is one write and a lone
+//! event shows up at once.
+//!
+//! The host may need the streams itself (see [`route`]). The TUI draws on
+//! the terminal, so under it neither stream is written to. `codex exec
+//! --json`, the MCP server and the app server write their own output to
+//! standard output, so `stdout` is only written to when
+//! `CODEX_VISUALIZER_ALLOW_STDOUT=1` says nothing else is. A sink that may
+//! not be written to is redirected to an NDJSON file in the temp directory,
+//! whose path is logged.
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufWriter;
+use tokio_tungstenite::tungstenite::Error;
+use tracing::error;
+use tracing::warn;
+
+use super::connection::Connection;
+use super::connection::Connector;
+use super::ndjson;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub(super) struct StdioConnector {
+    stream: Stream,
+    pretty: bool,
+}
+
+impl StdioConnector {
+    /// The connector for `sink`, if it names a standard stream.
+    pub(super) fn for_sink(sink: &str) -> Option<Self> {
+        let (name, flags) = sink.split_once('?').unwrap_or((sink, ""));
+        let stream = match name {
+            "stdout" => Stream::Stdout,
+            "stderr" => Stream::Stderr,
+            _ => return None,
+        };
+        Some(Self {
+            stream,
+            pretty: flags == "pretty",
+        })
+    }
+}
+
+/// Whether `sink` names a standard stream rather than a relay.
+pub(super) fn is_stdio_sink(sink: &str) -> bool {
+    StdioConnector::for_sink(sink).is_some()
+}
+
+/// Whether `CODEX_VISUALIZER_ALLOW_STDOUT` lets a `stdout` sink write to
+/// standard output.
+pub(super) fn stdout_allowed_by_env() -> bool {
+    std::env::var("CODEX_VISUALIZER_ALLOW_STDOUT").is_ok_and(|value| value == "1")
+}
+
+/// Where events for `sink` go: `sink` itself, unless it names a standard
+/// stream the host needs. While the TUI `owns_terminal` that is either
+/// stream; otherwise it is standard output, unless `stdout_allowed`.
+pub(super) fn route(sink: String, owns_terminal: bool, stdout_allowed: bool) -> Option<String> {
+    let Some(connector) = StdioConnector::for_sink(&sink) else {
+        return Some(sink);
+    };
+    let reason = match connector.stream {
+        _ if owns_terminal => "the TUI owns the terminal",
+        Stream::Stdout if !stdout_allowed => {
+            "standard output is the host's (set CODEX_VISUALIZER_ALLOW_STDOUT=1 if it is free)"
+        }
+        Stream::Stdout | Stream::Stderr => return Some(sink),
+    };
+    redirect(&sink, reason)
+}
+
+/// Where events for the standard stream `sink` go instead: a `file://` URL
+/// in the temp directory, or `None` if there is none to be had, in which
+/// case they are not delivered at all.
+fn redirect(sink: &str, reason: &str) -> Option<String> {
+    let path = std::env::temp_dir().join(format!("codex-visualizer-{}.ndjson", std::process::id()));
+    match ndjson::file_url(&path) {
+        Ok(url) => {
+            warn!(
+                "{reason}; writing visualizer events meant for {sink} to {}",
+                path.display()
+            );
+            Some(url)
+        }
+        Err(err) => {
+            error!(
+                "{reason} and {} is unusable ({err}); not writing visualizer events to {sink}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for StdioConnector {
+    async fn connect(&self, _url: &str) -> Result<Box<dyn Connection>, Error> {
+        let writer: Box<dyn AsyncWrite + Send + Unpin> = match self.stream {
+            Stream::Stdout => Box::new(tokio::io::stdout()),
+            Stream::Stderr => Box::new(tokio::io::stderr()),
+        };
+        Ok(Box::new(StdioConnection::new(writer, self.pretty)))
+    }
+}
+
+struct StdioConnection<W> {
+    writer: BufWriter<W>,
+    pretty: bool,
+}
+
+impl<W: AsyncWrite + Send + Unpin> StdioConnection<W> {
+    fn new(writer: W, pretty: bool) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            pretty,
+        }
+    }
+}
+
+/// `frame` indented for reading, or as it is if it is not JSON.
+fn pretty(frame: String) -> String {
+    serde_json::from_str::<Value>(&frame)
+        .ok()
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or(frame)
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Send + Unpin> Connection for StdioConnection<W> {
+    async fn send(&mut self, text: String) -> Result<(), Error> {
+        let mut line = if self.pretty { pretty(text) } else { text };
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Only flushes; the stream stays open for the rest of the process.
+    async fn close(&mut self) -> Result<(), Error> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::visualizer::VisualizerEvent;
+
+    fn event(sequence: u64, action_type: &str, action: Value) -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms: 1_700_000_000_000,
+            action,
+            ..VisualizerEvent::for_test(sequence, action_type)
+        }
+    }
+
+    /// What a connection to `sink` writes for a task starting and
+    /// finishing, as the records read back and the number of lines.
+    async fn written(sink: &str) -> (Vec<Value>, usize) {
+        let connector = StdioConnector::for_sink(sink).expect("standard stream");
+        let mut connection = StdioConnection::new(Vec::new(), connector.pretty);
+        for event in [
+            event(0, "task_started", json!({ "subId": "1" })),
+            event(1, "task_completed", json!({ "subId": "1" })),
+        ] {
+            let frame = serde_json::to_string(&event).expect("serialize");
+            connection.send(frame).await.expect("send");
+        }
+        connection.flush().await.expect("flush");
+        let output = String::from_utf8(connection.writer.into_inner()).expect("utf-8");
+        assert!(output.ends_with("}\n"));
+        let records = serde_json::Deserializer::from_str(&output)
+            .into_iter::<Value>()
+            .collect::<Result<_, _>>()
+            .expect("json records");
+        (records, output.lines().count())
+    }
+
+    #[tokio::test]
+    async fn frames_are_written_one_per_line_or_indented() {
+        let expected = vec![
+            json!({
+                "schemaVersion": 3,
+                "sequenceEpoch": 0,
+                "sequence": 0,
+                "timestampMs": 1_700_000_000_000u64,
+                "monotonicMs": 0,
+                "actionType": "task_started",
+                "action": { "subId": "1" },
+            }),
+            json!({
+                "schemaVersion": 3,
+                "sequenceEpoch": 0,
+                "sequence": 1,
+                "timestampMs": 1_700_000_000_000u64,
+                "monotonicMs": 0,
+                "actionType": "task_completed",
+                "action": { "subId": "1" },
+            }),
+        ];
+        assert_eq!(written("stdout").await, (expected.clone(), 2));
+        // Eleven lines each: the braces, one per key, and `action`'s own key
+        // and closing brace.
+        assert_eq!(written("stderr?pretty").await, (expected, 22));
+    }
+
+    #[test]
+    fn standard_streams_the_host_needs_are_redirected() {
+        let redirected = |sink: &str, owns_terminal: bool, stdout_allowed: bool| {
+            route(sink.to_string(), owns_terminal, stdout_allowed)
+                .is_none_or(|url| url.starts_with("file://"))
+        };
+
+        assert_eq!(
+            [
+                redirected("stdout", true, true),
+                redirected("stderr?pretty", true, false),
+                redirected("stdout?pretty", false, false),
+                redirected("stdout", false, true),
+                redirected("stderr", false, false),
+                redirected("ws://127.0.0.1:9", true, false),
+            ],
+            [true, true, true, false, false, false]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Strict telemetry: the audit part of the stream is delivered or the
+//! session is told it is incomplete.
+//!
+//! With strict delivery, emitting a lifecycle event (task lifecycle,
+//! approvals and patches, see [`is_lifecycle`]) waits until the forwarder
+//! has written it to the relay. An event the queue drops, or one still
+//! undelivered after the timeout, is a telemetry failure: it is published
+//! once through [`StrictDelivery::failures`], and from then on lifecycle
+//! events no longer wait, so the session can wind down without stalling on
+//! a dead relay. Other events keep the usual best-effort semantics, but may
+//! not take the last [`LIFECYCLE_RESERVE`] slots of the queue, so a full
+//! queue does not by itself fail the stream.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::sync::watch;
+use tracing::error;
+
+use super::VisualizerEvent;
+
+/// Queue slots kept free for lifecycle events.
+pub(super) const LIFECYCLE_RESERVE: usize = 32;
+
+/// Action types that make up the task lifecycle.
+const LIFECYCLE_ACTIONS: &[&str] = &[
+    "task_spawned",
+    "task_started",
+    "task_completed",
+    "task_aborted",
+    "task_reverted",
+    "task_recovered_incomplete",
+    "task_recovery_resolved",
+    "approval_expired",
+];
+
+/// `protocol_event`s whose message is part of the audit stream.
+const LIFECYCLE_PROTOCOL_EVENTS: &[&str] = &[
+    "task_started",
+    "task_complete",
+    "turn_aborted",
+    "exec_approval_request",
+    "apply_patch_approval_request",
+    "patch_apply_begin",
+    "patch_apply_end",
+];
+
+/// A lifecycle event whose emitter has to wait for its delivery.
+pub(super) struct Pending {
+    pub(super) sequence: u64,
+    pub(super) action_type: String,
+}
+
+#[derive(Clone)]
+pub(super) struct StrictDelivery {
+    pub(super) timeout: Duration,
+    /// Cause of the first failure; `None` while the stream is complete.
+    failure: Arc<watch::Sender<Option<String>>>,
+}
+
+impl StrictDelivery {
+    pub(super) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            failure: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    /// Changes once, to the cause of the first failure.
+    pub(super) fn failures(&self) -> watch::Receiver<Option<String>> {
+        self.failure.subscribe()
+    }
+
+    pub(super) fn has_failed(&self) -> bool {
+        self.failure.borrow().is_some()
+    }
+
+    /// Record a failure; only the first one is published.
+    pub(super) fn fail(&self, cause: String) {
+        self.failure.send_if_modified(|failure| {
+            if failure.is_some() {
+                return false;
+            }
+            error!("strict telemetry failure: {cause}");
+            *failure = Some(cause);
+            true
+        });
+    }
+}
+
+pub(super) fn is_lifecycle(event: &VisualizerEvent) -> bool {
+    is_lifecycle_action(&event.action_type, &event.action)
+}
+
+pub(super) fn is_lifecycle_action(action_type: &str, action: &Value) -> bool {
+    if LIFECYCLE_ACTIONS.contains(&action_type) {
+        return true;
+    }
+    action_type == "protocol_event"
+        && action
+            .pointer("/event/msg/type")
+            .and_then(Value::as_str)
+            .is_some_and(|kind| LIFECYCLE_PROTOCOL_EVENTS.contains(&kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn event(action_type: &str, action: Value) -> VisualizerEvent {
+        VisualizerEvent {
+            action,
+            ..VisualizerEvent::for_test(0, action_type)
+        }
+    }
+
+    fn protocol_event(kind: &str) -> VisualizerEvent {
+        event(
+            "protocol_event",
+            json!({ "event": { "id": "sub-1", "msg": { "type": kind } } }),
+        )
+    }
+
+    #[test]
+    fn lifecycle_covers_tasks_approvals_and_patches_only() {
+        let classified: Vec<bool> = [
+            event("task_completed", json!({ "subId": "sub-1" })),
+            protocol_event("exec_approval_request"),
+            protocol_event("patch_apply_end"),
+            protocol_event("agent_message_delta"),
+            event("reasoning_phase", json!({ "phase": "thinking" })),
+        ]
+        .iter()
+        .map(is_lifecycle)
+        .collect();
+        assert_eq!(classified, vec![true, true, true, false, false]);
+    }
+
+    #[test]
+    fn only_the_first_failure_is_published() {
+        let strict = StrictDelivery::new(Duration::from_secs(1));
+        let failures = strict.failures();
+        strict.fail("first".to_string());
+        strict.fail("second".to_string());
+        assert_eq!(failures.borrow().clone(), Some("first".to_string()));
+    }
+}
@@ -0,0 +1,241 @@
+//! Folds a stream of visualizer events into per-task lanes, the same shape
+//! the web UI renders live.
+
+use std::collections::BTreeSet;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::VisualizerEvent;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Lane {
+    pub(crate) sub_id: String,
+    pub(crate) task_kind: Option<String>,
+    pub(crate) started_ms: Option<u128>,
+    pub(crate) ended_ms: Option<u128>,
+    pub(crate) outcome: LaneOutcome,
+    pub(crate) tool_calls: Vec<ToolCall>,
+}
+
+impl Lane {
+    fn new(sub_id: &str) -> Self {
+        Self {
+            sub_id: sub_id.to_string(),
+            task_kind: None,
+            started_ms: None,
+            ended_ms: None,
+            outcome: LaneOutcome::Running,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// `None` when either end of the lane fell into a gap.
+    pub(crate) fn duration_ms(&self) -> Option<u128> {
+        Some(self.ended_ms?.saturating_sub(self.started_ms?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub(crate) enum LaneOutcome {
+    Running,
+    Completed,
+    Aborted { reason: String },
+    Rejected { reason: Value },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ToolCall {
+    pub(crate) kind: String,
+    pub(crate) label: String,
+    pub(crate) at_ms: u128,
+}
+
+/// A reviewer's note, attached to the lane of `sub_id` if set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TimelineAnnotation {
+    pub(crate) sub_id: Option<String>,
+    pub(crate) text: String,
+    pub(crate) tags: Vec<String>,
+    pub(crate) at_ms: u128,
+}
+
+/// Inclusive range of sequence numbers that never arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct SequenceGap {
+    pub(crate) from: u64,
+    pub(crate) to: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TimelineState {
+    lanes: IndexMap<String, Lane>,
+    annotations: Vec<TimelineAnnotation>,
+    seen: BTreeSet<u64>,
+    replayed: u64,
+}
+
+impl TimelineState {
+    pub(crate) fn from_events(events: &[VisualizerEvent]) -> Self {
+        let mut timeline = Self::default();
+        for event in events {
+            timeline.apply(event);
+        }
+        timeline
+    }
+
+    /// Apply one event. Events whose sequence was already applied are
+    /// counted as replays and otherwise ignored; replay-flagged events that
+    /// are not duplicates are counted and applied.
+    pub(crate) fn apply(&mut self, event: &VisualizerEvent) {
+        let action = &event.action;
+        let flagged = action["replay"].as_bool().unwrap_or(false);
+        if !self.seen.insert(event.sequence) {
+            self.replayed += 1;
+            return;
+        }
+        if flagged {
+            self.replayed += 1;
+        }
+        let at = event.timestamp_ms;
+        match event.action_type.as_str() {
+            "task_spawned" => {
+                if let Some(lane) = self.lane(action) {
+                    lane.task_kind = action["taskKind"].as_str().map(str::to_string);
+                    lane.started_ms = Some(at);
+                }
+            }
+            "task_completed" => {
+                if let Some(lane) = self.lane(action) {
+                    lane.ended_ms = Some(at);
+                    lane.outcome = LaneOutcome::Completed;
+                }
+            }
+            "task_aborted" => {
+                if let Some(lane) = self.lane(action) {
+                    lane.ended_ms = Some(at);
+                    lane.outcome = LaneOutcome::Aborted {
+                        reason: action["reason"].as_str().unwrap_or("unknown").to_string(),
+                    };
+                }
+            }
+            "task_spawn_rejected" => {
+                if let Some(lane) = self.lane(action) {
+                    lane.task_kind = action["taskKind"].as_str().map(str::to_string);
+                    lane.started_ms = Some(at);
+                    lane.ended_ms = Some(at);
+                    lane.outcome = LaneOutcome::Rejected {
+                        reason: action["reason"].clone(),
+                    };
+                }
+            }
+            "annotation" => self.annotations.push(TimelineAnnotation {
+                sub_id: action["subId"].as_str().map(str::to_string),
+                text: action["text"].as_str().unwrap_or_default().to_string(),
+                tags: action["tags"]
+                    .as_array()
+                    .map(|tags| {
+                        tags.iter()
+                            .filter_map(Value::as_str)
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                at_ms: at,
+            }),
+            "protocol_event" => {
+                let protocol_event = &action["event"];
+                if let Some((kind, label)) = tool_call(&protocol_event["msg"])
+                    && let Some(sub_id) = protocol_event["id"].as_str()
+                {
+                    self.lane_for(sub_id).tool_calls.push(ToolCall {
+                        kind: kind.to_string(),
+                        label,
+                        at_ms: at,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn lanes(&self) -> impl Iterator<Item = &Lane> {
+        self.lanes.values()
+    }
+
+    pub(crate) fn annotations(&self) -> &[TimelineAnnotation] {
+        &self.annotations
+    }
+
+    pub(crate) fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub(crate) fn replayed(&self) -> u64 {
+        self.replayed
+    }
+
+    pub(crate) fn gaps(&self) -> Vec<SequenceGap> {
+        let mut gaps = Vec::new();
+        let mut previous: Option<u64> = None;
+        for &sequence in &self.seen {
+            if let Some(previous) = previous
+                && sequence > previous + 1
+            {
+                gaps.push(SequenceGap {
+                    from: previous + 1,
+                    to: sequence - 1,
+                });
+            }
+            previous = Some(sequence);
+        }
+        gaps
+    }
+
+    fn lane(&mut self, action: &Value) -> Option<&mut Lane> {
+        let sub_id = action["subId"].as_str()?;
+        Some(self.lane_for(sub_id))
+    }
+
+    fn lane_for(&mut self, sub_id: &str) -> &mut Lane {
+        self.lanes
+            .entry(sub_id.to_string())
+            .or_insert_with(|| Lane::new(sub_id))
+    }
+}
+
+fn tool_call(msg: &Value) -> Option<(&'static str, String)> {
+    match msg["type"].as_str()? {
+        "exec_command_begin" => {
+            let command = msg["command"]
+                .as_array()
+                .map(|argv| {
+                    argv.iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .unwrap_or_default();
+            Some(("exec", command))
+        }
+        "mcp_tool_call_begin" => {
+            let invocation = &msg["invocation"];
+            Some((
+                "mcp",
+                format!(
+                    "{}/{}",
+                    invocation["server"].as_str().unwrap_or("?"),
+                    invocation["tool"].as_str().unwrap_or("?")
+                ),
+            ))
+        }
+        "patch_apply_begin" => Some(("patch", "apply_patch".to_string())),
+        "web_search_begin" => Some(("web_search", "web_search".to_string())),
+        _ => None,
+    }
+}
@@ -0,0 +1,327 @@
+//! Private roots and client certificates for `wss` relays.
+//!
+//! `visualizer_tls_ca_file` names a PEM bundle of root CAs trusted on top
+//! of the platform's, for a relay behind a TLS terminator signed by a
+//! private CA. `visualizer_tls_client_cert` and `visualizer_tls_client_key`
+//! name a PEM certificate chain and private key presented to relays that
+//! require mutual TLS. The files are read when the visualizer is built, so
+//! a malformed one fails session startup naming the file, instead of
+//! every handshake failing later. `ws://` relays are unaffected. With
+//! `visualizer_tls = "trust-on-first-use"`, the pinned key stands in for
+//! the roots, but the client certificate is still presented.
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rustls::ClientConfig;
+use rustls::ConfigBuilder;
+use rustls::RootCertStore;
+use rustls::client::WantsClientCert;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::PrivateKeyDer;
+use rustls::pki_types::pem::PemObject;
+use tracing::debug;
+
+/// Where the TLS material for `wss` relays is read from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TlsFiles {
+    pub(crate) ca_file: Option<PathBuf>,
+    pub(crate) client_cert: Option<PathBuf>,
+    pub(crate) client_key: Option<PathBuf>,
+}
+
+/// The client certificate chain and its key.
+struct ClientIdentity {
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+/// TLS settings loaded from [`TlsFiles`], shared by a sink's connections.
+#[derive(Clone)]
+pub(super) struct ClientTls {
+    /// Verifies relays against the platform's roots and the extra ones.
+    verified: Arc<ClientConfig>,
+    identity: Option<Arc<ClientIdentity>>,
+}
+
+impl TlsFiles {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Read and check every configured file.
+    pub(super) fn load(&self) -> io::Result<ClientTls> {
+        let mut roots = RootCertStore::empty();
+        let native = rustls_native_certs::load_native_certs();
+        for err in &native.errors {
+            debug!("skipping a platform root certificate: {err}");
+        }
+        roots.add_parsable_certificates(native.certs);
+        if let Some(path) = &self.ca_file {
+            for cert in read_certificates(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|err| invalid(path, format!("unusable root certificate: {err}")))?;
+            }
+        }
+        let identity = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some((
+                key,
+                Arc::new(ClientIdentity {
+                    chain: read_certificates(cert)?,
+                    key: PrivateKeyDer::from_pem_file(key).map_err(|err| invalid(key, err))?,
+                }),
+            )),
+            (None, None) => None,
+            (Some(path), None) | (None, Some(path)) => {
+                return Err(invalid(
+                    path,
+                    "visualizer_tls_client_cert and visualizer_tls_client_key must be set together",
+                ));
+            }
+        };
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(io::Error::other)?
+                .with_root_certificates(roots);
+        let verified = match &identity {
+            Some((key, identity)) => {
+                with_identity(builder, Some(identity)).map_err(|err| invalid(key, err))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(ClientTls {
+            verified: Arc::new(verified),
+            identity: identity.map(|(_, identity)| identity),
+        })
+    }
+}
+
+impl ClientTls {
+    /// The client config for relays verified against the roots.
+    pub(super) fn verified(&self) -> Arc<ClientConfig> {
+        Arc::clone(&self.verified)
+    }
+
+    /// Finish `builder` with the client certificate, if there is one.
+    pub(super) fn finish(
+        &self,
+        builder: ConfigBuilder<ClientConfig, WantsClientCert>,
+    ) -> Result<ClientConfig, rustls::Error> {
+        with_identity(builder, self.identity.as_deref())
+    }
+}
+
+fn with_identity(
+    builder: ConfigBuilder<ClientConfig, WantsClientCert>,
+    identity: Option<&ClientIdentity>,
+) -> Result<ClientConfig, rustls::Error> {
+    match identity {
+        Some(identity) => {
+            builder.with_client_auth_cert(identity.chain.clone(), identity.key.clone_key())
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Every certificate in the PEM file at `path`, of which there must be one.
+fn read_certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .map_err(|err| invalid(path, err))?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no PEM certificates found"));
+    }
+    Ok(certs)
+}
+
+fn invalid(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {err}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use rustls::RootCertStore;
+    use rustls::ServerConfig;
+    use rustls::server::WebPkiClientVerifier;
+    use serde_json::Value;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_rustls::TlsAcceptor;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::*;
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::connection::Connector;
+    use crate::visualizer::connection::WebSocketConnector;
+    use crate::visualizer::hello;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/visualizer/testdata")
+            .join(name)
+    }
+
+    /// A relay presenting a certificate for `127.0.0.1` signed by the test
+    /// CA, requiring client certificates from that CA if `mutual`.
+    /// Forwards the sequence of every frame it receives.
+    async fn private_relay(mutual: bool) -> (String, mpsc::UnboundedReceiver<u64>) {
+        let builder =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("protocol versions");
+        let builder = if mutual {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certificates(&testdata("visualizer-ca.pem")).expect("ca") {
+                roots.add(cert).expect("root");
+            }
+            builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder(Arc::new(roots))
+                    .build()
+                    .expect("client verifier"),
+            )
+        } else {
+            builder.with_no_client_auth()
+        };
+        let config = builder
+            .with_single_cert(
+                read_certificates(&testdata("relay-signed.crt.pem")).expect("relay cert"),
+                PrivateKeyDer::from_pem_file(testdata("relay-signed.key.pem")).expect("relay key"),
+            )
+            .expect("server certificate");
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let Ok(tls) = acceptor.accept(socket).await else {
+                        return;
+                    };
+                    let Ok(mut ws) = accept_async(tls).await else {
+                        return;
+                    };
+                    while let Some(Ok(message)) = ws.next().await {
+                        if let Message::Text(text) = message {
+                            let frame: Value = serde_json::from_str(&text).expect("json");
+                            if !hello::is_hello(&frame) {
+                                let _ = tx.send(frame["sequence"].as_u64().expect("sequence"));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (format!("wss://{addr}/"), rx)
+    }
+
+    fn with_ca() -> TlsFiles {
+        TlsFiles {
+            ca_file: Some(testdata("visualizer-ca.pem")),
+            ..Default::default()
+        }
+    }
+
+    async fn connects(tls: Option<TlsFiles>, url: &str) -> bool {
+        let connector = WebSocketConnector::default();
+        if let Some(tls) = tls {
+            connector.use_tls(tls.load().expect("load"));
+        }
+        connector.connect(url).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn private_root_authenticates_the_relay() {
+        let (url, mut received) = private_relay(false).await;
+        assert_eq!(
+            (
+                connects(None, &url).await,
+                connects(Some(with_ca()), &url).await
+            ),
+            (false, true)
+        );
+
+        let visualizer = AgentVisualizer::new(
+            Some(url),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        )
+        .with_tls(&with_ca())
+        .expect("tls files");
+        visualizer.emit(None, "tick", json!({}), None).await;
+        let sequence = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("frame before timeout");
+        assert_eq!(sequence, Some(0));
+    }
+
+    #[tokio::test]
+    async fn client_certificate_is_presented_for_mutual_tls() {
+        let (url, _received) = private_relay(true).await;
+        let with_identity = TlsFiles {
+            client_cert: Some(testdata("client.crt.pem")),
+            client_key: Some(testdata("client.key.pem")),
+            ..with_ca()
+        };
+        assert_eq!(
+            (
+                connects(Some(with_ca()), &url).await,
+                connects(Some(with_identity), &url).await,
+            ),
+            (false, true)
+        );
+    }
+
+    #[test]
+    fn malformed_files_fail_to_load_naming_the_file() {
+        let dir = TempDir::new().expect("tempdir");
+        let garbage = dir.path().join("garbage.pem");
+        std::fs::write(&garbage, "not a certificate\n").expect("write");
+        let errors: Vec<Option<String>> = [
+            TlsFiles {
+                ca_file: Some(garbage.clone()),
+                ..Default::default()
+            },
+            TlsFiles {
+                client_cert: Some(testdata("client.crt.pem")),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        .map(|files| files.load().err().map(|err| err.to_string()))
+        .collect();
+        assert_eq!(
+            errors,
+            vec![
+                Some(format!("{}: no PEM certificates found", garbage.display())),
+                Some(format!(
+                    "{}: visualizer_tls_client_cert and visualizer_tls_client_key must be set together",
+                    testdata("client.crt.pem").display()
+                )),
+            ]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Unix domain socket transport for relay URLs like
+//! `unix:///tmp/codex-viz.sock`, for a visualizer on the same machine
+//! that should not need a TCP port. Each frame the forwarder would send
+//! over a websocket is written to the socket as one line, with no
+//! websocket framing, so the lines are exactly the websocket payloads.
+//! It is one more [`Connector`], so the queue, batching and reconnects
+//! work as for a relay: a failed write or the listener closing the socket
+//! reconnects, and lines the listener writes back are read like text
+//! frames. Each connect greets the listener with a `hello` line first (see
+//! the `hello` module). There is no keepalive. Other platforms fail every
+//! connect, saying so.
+
+use std::io;
+
+use async_trait::async_trait;
+#[cfg(unix)]
+use tokio::io::AsyncBufReadExt;
+#[cfg(unix)]
+use tokio::io::AsyncWriteExt;
+#[cfg(unix)]
+use tokio::io::BufReader;
+#[cfg(unix)]
+use tokio::io::BufWriter;
+#[cfg(unix)]
+use tokio::io::Lines;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+#[cfg(unix)]
+use tokio::net::unix::OwnedReadHalf;
+#[cfg(unix)]
+use tokio::net::unix::OwnedWriteHalf;
+use tokio_tungstenite::tungstenite::Error;
+use url::Url;
+
+use super::connection::Connection;
+use super::connection::Connector;
+
+/// Whether `url` names a Unix domain socket rather than a relay.
+pub(super) fn is_unix_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|parsed| parsed.scheme() == "unix")
+}
+
+pub(super) struct UnixConnector;
+
+#[cfg(unix)]
+#[async_trait]
+impl Connector for UnixConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        let path = Url::parse(url)
+            .ok()
+            .map(|parsed| parsed.path().to_string())
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("visualizer url `{url}` names no socket path"),
+                )
+            })?;
+        let (reader, writer) = UnixStream::connect(&path).await?.into_split();
+        Ok(Box::new(UnixConnection {
+            lines: BufReader::new(reader).lines(),
+            writer: BufWriter::new(writer),
+        }))
+    }
+
+    fn greets(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(not(unix))]
+#[async_trait]
+impl Connector for UnixConnector {
+    async fn connect(&self, url: &str) -> Result<Box<dyn Connection>, Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("visualizer url `{url}` names a Unix domain socket, which this platform does not support"),
+        )
+        .into())
+    }
+}
+
+#[cfg(unix)]
+struct UnixConnection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: BufWriter<OwnedWriteHalf>,
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Connection for UnixConnection {
+    async fn send(&mut self, mut text: String) -> Result<(), Error> {
+        text.push('\n');
+        self.writer.write_all(text.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.lines.next_line().await?)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use serde_json::json;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::io::BufReader;
+    use tokio::net::UnixListener;
+    use tokio::sync::mpsc;
+
+    use crate::visualizer::AgentVisualizer;
+    use crate::visualizer::RetentionRules;
+    use crate::visualizer::TelemetryFidelity;
+    use crate::visualizer::TimestampEncoding;
+    use crate::visualizer::TrustedRoots;
+    use crate::visualizer::hello;
+
+    #[tokio::test]
+    async fn a_session_is_written_to_the_socket_as_lines() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("codex-viz.sock");
+        let listener = UnixListener::bind(&path).expect("bind");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut lines = BufReader::new(socket).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let _ = tx.send(line);
+                    }
+                });
+            }
+        });
+        let visualizer = AgentVisualizer::new(
+            Some(format!("unix://{}", path.display())),
+            TelemetryFidelity::Full,
+            None,
+            RetentionRules::default(),
+            TimestampEncoding::Number,
+            TrustedRoots::default(),
+        );
+
+        for (action_type, action) in [
+            (
+                "task_spawned",
+                json!({ "subId": "1", "taskKind": "Regular" }),
+            ),
+            ("exec_output", json!({ "subId": "1", "chunk": "ok\n" })),
+            ("task_completed", json!({ "subId": "1" })),
+        ] {
+            visualizer.emit(None, action_type, action, None).await;
+        }
+        assert_eq!(visualizer.shutdown(Duration::from_secs(5)).await, Some(0));
+
+        let mut lines = Vec::new();
+        while lines.len() < 3 {
+            let line = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("line before timeout")
+                .expect("listener running");
+            let line = serde_json::from_str::<Value>(&line).expect("one json frame per line");
+            if !hello::is_hello(&line) {
+                lines.push(line);
+            }
+        }
+        let emitted: Vec<Value> = visualizer
+            .recent_events()
+            .iter()
+            .map(|event| serde_json::to_value(event).expect("serialize"))
+            .collect();
+        assert_eq!(lines, emitted);
+    }
+}
@@ -0,0 +1,233 @@
+//! JSON shape of visualizer events, kept safe for consumers that parse
+//! numbers as IEEE doubles (JavaScript loses precision above 2^53).
+//!
+//! Guarantees, as of [`SCHEMA_VERSION`] 3:
+//!
+//! - `sequence` is always below 2^53. The counter behind it is a u64 that
+//!   never resets within a visualizer's lifetime; every 2^53 events the
+//!   emitted `sequence` starts again at 0 and `sequenceEpoch` goes up by
+//!   one, so `(sequenceEpoch, sequence)` orders events and is never reused.
+//!   Only emitted events are numbered: an event filtered out by action
+//!   type, sampled away, or dropped by other rules gets no `sequence`, so a
+//!   skipped number always means an event was lost, never that it was
+//!   filtered.
+//! - `conversationSequence`, present exactly when `conversationId` is,
+//!   numbers the events of that conversation alone: 0 for its first, then
+//!   one more for each, never skipping. Several conversations can share a
+//!   session's visualizer (and its `sequence`), so this is the counter to
+//!   use to detect losses or order events within one of them. It never
+//!   wraps and counts only emitted events, like `sequence`.
+//! - `timestampMs` is milliseconds since the Unix epoch as a number no
+//!   larger than 2^53 - 1; later times saturate to that value. With the
+//!   [`TimestampEncoding::String`] compat setting it is instead the exact
+//!   value as a decimal string. It follows the system clock, which can be
+//!   set back; use it for display, not to measure durations.
+//! - `monotonicMs` is milliseconds since the visualizer started, on a clock
+//!   that never goes back: it never decreases from one event to the next,
+//!   whatever the system clock does, so durations between events are its
+//!   differences. Every session of a process shares its origin, so the
+//!   field compares across conversations but not across processes.
+//! - `replayed: true` marks an event sent again to a relay that connected
+//!   after it was sent or dropped (see the `replay` module); it is left out
+//!   on events sent live. A replayed event is otherwise identical, so one
+//!   the relay already has can be recognized by `(sequenceEpoch,
+//!   sequence)`.
+//!
+//! Compatibility, newest first; bump [`SCHEMA_VERSION`] and add a line here
+//! whenever what a relay is sent changes shape:
+//!
+//! - 3: relays are greeted with a `hello` frame after each connect (see the
+//!   `hello` module). It has a `type` and no `actionType`, so a consumer
+//!   that stores every frame as an event should skip frames with a `type`.
+//!   Events are unchanged, and later gain the optional `replayed`.
+//! - 2: adds `schemaVersion` and `sequenceEpoch`, and later
+//!   `conversationSequence` and `monotonicMs`. Migrating from version 1
+//!   (which had no `schemaVersion`): treat a missing `sequenceEpoch` as 0,
+//!   and order by `(sequenceEpoch, sequence)` rather than by `sequence`
+//!   alone.
+
+use serde::Serialize;
+use serde::Serializer;
+use serde_json::Value;
+
+use super::VisualizerEvent;
+
+/// Bumped whenever the shape of an event changes.
+pub(crate) const SCHEMA_VERSION: u32 = 3;
+
+/// Largest integer every IEEE double represents exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// How `timestampMs` is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum TimestampEncoding {
+    /// A JSON number, saturated to 2^53 - 1.
+    #[default]
+    Number,
+    /// The exact value as a decimal string, for consumers that cannot be
+    /// trusted with large numbers at all.
+    String,
+}
+
+/// Split the session counter into the emitted `(sequenceEpoch, sequence)`.
+pub(super) fn split_sequence(sequence: u64) -> (u64, u64) {
+    (sequence >> 53, sequence & MAX_SAFE_INTEGER)
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum WireTimestamp {
+    Number(u64),
+    String(String),
+}
+
+impl WireTimestamp {
+    fn new(timestamp_ms: u128, encoding: TimestampEncoding) -> Self {
+        match encoding {
+            TimestampEncoding::Number => Self::Number(
+                u64::try_from(timestamp_ms)
+                    .unwrap_or(u64::MAX)
+                    .min(MAX_SAFE_INTEGER),
+            ),
+            TimestampEncoding::String => Self::String(timestamp_ms.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct WireEvent<'a> {
+    schema_version: u32,
+    sequence_epoch: u64,
+    sequence: u64,
+    timestamp_ms: WireTimestamp,
+    monotonic_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversation_sequence: Option<u64>,
+    action_type: &'a str,
+    action: &'a Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<&'a Value>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    replayed: bool,
+}
+
+impl<'a> WireEvent<'a> {
+    pub(super) fn new(event: &'a VisualizerEvent, timestamps: TimestampEncoding) -> Self {
+        let (sequence_epoch, sequence) = split_sequence(event.sequence);
+        Self {
+            schema_version: SCHEMA_VERSION,
+            sequence_epoch,
+            sequence,
+            timestamp_ms: WireTimestamp::new(event.timestamp_ms, timestamps),
+            monotonic_ms: event.monotonic_ms,
+            conversation_id: event.conversation_id.map(|id| match &event.fork_suffix {
+                Some(suffix) => format!("{id}{suffix}"),
+                None => id.to_string(),
+            }),
+            conversation_sequence: event.conversation_sequence,
+            action_type: &event.action_type,
+            action: &event.action,
+            state: event.state.as_ref(),
+            replayed: event.replayed,
+        }
+    }
+}
+
+impl Serialize for VisualizerEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WireEvent::new(self, TimestampEncoding::Number).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn event(sequence: u64, timestamp_ms: u128) -> VisualizerEvent {
+        VisualizerEvent {
+            timestamp_ms,
+            ..VisualizerEvent::for_test(sequence, "task_spawned")
+        }
+    }
+
+    fn wire(event: &VisualizerEvent, timestamps: TimestampEncoding) -> Value {
+        serde_json::to_value(WireEvent::new(event, timestamps)).expect("serialize")
+    }
+
+    #[test]
+    fn sequence_rolls_over_into_the_epoch_below_two_to_the_53() {
+        let boundaries = [0, MAX_SAFE_INTEGER, MAX_SAFE_INTEGER + 1, u64::MAX];
+        let emitted: Vec<(Value, Value)> = boundaries
+            .into_iter()
+            .map(|sequence| {
+                let wire = wire(&event(sequence, 0), TimestampEncoding::Number);
+                (wire["sequenceEpoch"].clone(), wire["sequence"].clone())
+            })
+            .collect();
+
+        assert_eq!(
+            emitted,
+            vec![
+                (json!(0), json!(0)),
+                (json!(0), json!(MAX_SAFE_INTEGER)),
+                (json!(1), json!(0)),
+                (json!(2047), json!(MAX_SAFE_INTEGER)),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_timestamps_saturate_at_the_safe_integer_limit() {
+        let boundaries = [
+            1_700_000_000_000,
+            u128::from(MAX_SAFE_INTEGER),
+            u128::from(MAX_SAFE_INTEGER) + 1,
+            u128::MAX,
+        ];
+        let emitted: Vec<Value> = boundaries
+            .into_iter()
+            .map(|timestamp_ms| {
+                wire(&event(0, timestamp_ms), TimestampEncoding::Number)["timestampMs"].clone()
+            })
+            .collect();
+
+        assert_eq!(
+            emitted,
+            vec![
+                json!(1_700_000_000_000u64),
+                json!(MAX_SAFE_INTEGER),
+                json!(MAX_SAFE_INTEGER),
+                json!(MAX_SAFE_INTEGER),
+            ]
+        );
+        // Every emitted number survives a trip through a double.
+        for timestamp in emitted {
+            let value = timestamp.as_u64().expect("number");
+            assert_eq!(value as f64 as u64, value);
+        }
+    }
+
+    #[test]
+    fn string_timestamps_keep_the_exact_value() {
+        let wire = wire(&event(3, u128::MAX), TimestampEncoding::String);
+
+        assert_eq!(
+            wire,
+            json!({
+                "schemaVersion": SCHEMA_VERSION,
+                "sequenceEpoch": 0,
+                "sequence": 3,
+                "timestampMs": u128::MAX.to_string(),
+                "monotonicMs": 0,
+                "actionType": "task_spawned",
+                "action": {},
+            })
+        );
+    }
+}
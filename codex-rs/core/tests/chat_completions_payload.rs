@@ -29,6 +29,12 @@ fn network_disabled() -> bool {
 }
 
 async fn run_request(input: Vec<ResponseItem>) -> Value {
+    let mut prompt = Prompt::default();
+    prompt.input = input;
+    run_prompt(prompt).await
+}
+
+async fn run_prompt(prompt: Prompt) -> Value {
     let server = MockServer::start().await;
 
     let template = ResponseTemplate::new(200)
@@ -94,9 +100,6 @@ async fn run_request(input: Vec<ResponseItem>) -> Value {
         conversation_id,
     );
 
-    let mut prompt = Prompt::default();
-    prompt.input = input;
-
     let mut stream = match client.stream(&prompt).await {
         Ok(s) => s,
         Err(e) => panic!("stream chat failed: {e}"),
@@ -203,6 +206,25 @@ async fn omits_reasoning_when_none_present() {
     assert!(assistant.get("reasoning").is_none());
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sends_seed_only_when_prompt_has_one() {
+    if network_disabled() {
+        println!(
+            "Skipping test because it cannot execute when network is disabled in a Codex sandbox."
+        );
+        return;
+    }
+
+    let mut prompt = Prompt::default();
+    prompt.input = vec![user_message("u1")];
+    prompt.seed = Some(42);
+    let body = run_prompt(prompt).await;
+    assert_eq!(body["seed"], Value::from(42));
+
+    let body = run_request(vec![user_message("u1")]).await;
+    assert!(body.get("seed").is_none());
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn attaches_reasoning_to_previous_assistant() {
     if network_disabled() {
@@ -41,11 +41,9 @@ async fn interrupt_long_running_tool_emits_turn_aborted() {
 
     // Kick off a turn that triggers the function call.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "start sleep".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "start sleep".into(),
+        }]))
         .await
         .unwrap();
 
@@ -67,3 +65,103 @@ async fn interrupt_long_running_tool_emits_turn_aborted() {
     )
     .await;
 }
+
+/// Integration test: the conversation reports the running task until an
+/// interrupt has ended it.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn running_tasks_are_reported_until_interrupted() {
+    let args = json!({
+        "command": ["bash", "-lc", "sleep 60"],
+        "timeout_ms": 60_000
+    })
+    .to_string();
+    let body = sse(vec![
+        ev_function_call("call_sleep", "shell", &args),
+        ev_completed("done"),
+    ]);
+
+    let server = start_mock_server().await;
+    mount_sse_once(&server, body).await;
+
+    let codex = test_codex().build(&server).await.unwrap().codex;
+
+    let wait_timeout = Duration::from_secs(5);
+
+    codex
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "start sleep".into(),
+        }]))
+        .await
+        .unwrap();
+    wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::ExecCommandBegin(_)),
+        wait_timeout,
+    )
+    .await;
+    assert_eq!(
+        (codex.active_task_count().await, codex.task_kinds().await),
+        (1, vec!["Regular".to_string()])
+    );
+
+    codex.submit(Op::Interrupt).await.unwrap();
+    wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::TurnAborted(_)),
+        wait_timeout,
+    )
+    .await;
+    assert_eq!(
+        (codex.active_task_count().await, codex.task_kinds().await),
+        (0, Vec::new())
+    );
+}
+
+/// Integration test: `wait_for_idle` resolves once an interrupt has ended
+/// the running task.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn wait_for_idle_resolves_after_an_interrupt() {
+    let args = json!({
+        "command": ["bash", "-lc", "sleep 60"],
+        "timeout_ms": 60_000
+    })
+    .to_string();
+    let body = sse(vec![
+        ev_function_call("call_sleep", "shell", &args),
+        ev_completed("done"),
+    ]);
+
+    let server = start_mock_server().await;
+    mount_sse_once(&server, body).await;
+
+    let codex = test_codex().build(&server).await.unwrap().codex;
+
+    let wait_timeout = Duration::from_secs(5);
+
+    codex
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "start sleep".into(),
+        }]))
+        .await
+        .unwrap();
+    wait_for_event_with_timeout(
+        &codex,
+        |ev| matches!(ev, EventMsg::ExecCommandBegin(_)),
+        wait_timeout,
+    )
+    .await;
+    let idle = codex.wait_for_idle();
+    tokio::pin!(idle);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), idle.as_mut())
+            .await
+            .is_err(),
+        "a running task keeps the session busy"
+    );
+
+    codex.submit(Op::Interrupt).await.unwrap();
+    tokio::time::timeout(wait_timeout, idle)
+        .await
+        .expect("session goes idle");
+    assert_eq!(codex.active_task_count().await, 0);
+}
@@ -262,11 +262,9 @@ async fn resume_includes_initial_messages_and_sends_prior_items() {
 
     // 2) Submit new input; the request body must include the prior item followed by the new user input.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -334,11 +332,9 @@ async fn includes_conversation_id_and_model_headers_in_request() {
         .expect("create new conversation");
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -389,11 +385,9 @@ async fn includes_base_instructions_override_in_request() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -449,11 +443,9 @@ async fn chatgpt_auth_sends_correct_request() {
         .expect("create new conversation");
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -539,11 +531,9 @@ async fn prefers_apikey_when_config_prefers_apikey_even_with_chatgpt_tokens() {
         .expect("create new conversation");
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -578,11 +568,9 @@ async fn includes_user_instructions_message_in_request() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -793,11 +781,9 @@ async fn token_count_includes_rate_limits_snapshot() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -944,11 +930,9 @@ async fn usage_limit_error_emits_rate_limit_event() -> anyhow::Result<()> {
     });
 
     let submission_id = codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .expect("submission should succeed while emitting usage limit error events");
 
@@ -1002,6 +986,26 @@ async fn context_window_error_sets_total_tokens_to_model_window() -> anyhow::Res
     )
     .await;
 
+    // The blocked turn is compacted on its behalf and rerun once; the rerun
+    // overflows again and that error is surfaced.
+    responses::mount_sse_once_match(
+        &server,
+        body_string_contains("You have exceeded the maximum number of tokens"),
+        sse_completed("resp_compact"),
+    )
+    .await;
+
+    responses::mount_sse_once_match(
+        &server,
+        body_string_contains("trigger context window"),
+        responses::sse_failed(
+            "resp_context_window_again",
+            "context_length_exceeded",
+            "Your input exceeds the context window of this model. Please adjust your input and try again.",
+        ),
+    )
+    .await;
+
     let TestCodex { codex, .. } = test_codex()
         .with_config(|config| {
             config.model = "gpt-5".to_string();
@@ -1012,21 +1016,17 @@ async fn context_window_error_sets_total_tokens_to_model_window() -> anyhow::Res
         .await?;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "seed turn".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "seed turn".into(),
+        }]))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "trigger context window".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "trigger context window".into(),
+        }]))
         .await?;
 
     use std::time::Duration;
@@ -1139,11 +1139,9 @@ async fn azure_overrides_assign_properties_used_for_responses_url() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -1216,11 +1214,9 @@ async fn env_var_overrides_loaded_auth() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -1294,27 +1290,21 @@ async fn history_dedupes_streamed_and_final_messages_across_turns() {
 
     // Turn 1: user sends U1; wait for completion.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text { text: "U1".into() }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text { text: "U1".into() }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     // Turn 2: user sends U2; wait for completion.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text { text: "U2".into() }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text { text: "U2".into() }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     // Turn 3: user sends U3; wait for completion.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text { text: "U3".into() }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text { text: "U3".into() }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -105,11 +105,9 @@ async fn summarize_context_three_requests_and_instructions() {
 
     // 1) Normal user input – should hit server once.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello world".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello world".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -120,11 +118,9 @@ async fn summarize_context_three_requests_and_instructions() {
 
     // 3) Next user input – third hit; history should include only the summary.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: THIRD_USER_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: THIRD_USER_MSG.into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -321,22 +317,18 @@ async fn auto_compact_runs_after_token_limit_hit() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: FIRST_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: FIRST_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: SECOND_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: SECOND_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
 
@@ -465,21 +457,17 @@ async fn auto_compact_persists_rollout_entries() {
     } = conversation_manager.new_conversation(config).await.unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: FIRST_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: FIRST_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: SECOND_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: SECOND_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -577,11 +565,9 @@ async fn auto_compact_stops_after_failed_attempt() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: FIRST_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: FIRST_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
 
@@ -671,11 +657,9 @@ async fn manual_compact_retries_after_context_window_error() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "first turn".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "first turn".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -799,11 +783,9 @@ async fn auto_compact_allows_multiple_attempts_when_interleaved_with_other_turn_
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: MULTI_AUTO_MSG.into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: MULTI_AUTO_MSG.into(),
+        }]))
         .await
         .unwrap();
 
@@ -860,3 +842,87 @@ async fn auto_compact_allows_multiple_attempts_when_interleaved_with_other_turn_
         "second auto compact request should include the summarization prompt"
     );
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn context_window_error_compacts_and_resumes_blocked_turn() {
+    skip_if_no_network!();
+
+    let server = start_mock_server().await;
+
+    let user_turn_failed = sse_failed(
+        "resp-fail",
+        "context_length_exceeded",
+        CONTEXT_LIMIT_MESSAGE,
+    );
+    let compact_succeeds = sse(vec![
+        ev_assistant_message("m1", SUMMARY_TEXT),
+        ev_completed("r1"),
+    ]);
+    let resumed_turn = sse(vec![
+        ev_assistant_message("m2", FINAL_REPLY),
+        ev_completed("r2"),
+    ]);
+
+    let request_log = mount_sse_sequence(
+        &server,
+        vec![user_turn_failed, compact_succeeds, resumed_turn],
+    )
+    .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = model_provider;
+    let codex = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"))
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: FIRST_AUTO_MSG.into(),
+        }]))
+        .await
+        .unwrap();
+
+    let final_message = wait_for_event(&codex, |ev| {
+        matches!(ev, EventMsg::AgentMessage(message) if message.message == FINAL_REPLY)
+            || matches!(ev, EventMsg::Error(_))
+    })
+    .await;
+    let EventMsg::AgentMessage(_) = final_message else {
+        panic!("blocked turn should resume after compaction, got {final_message:?}");
+    };
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let requests = request_log.requests();
+    assert_eq!(
+        requests.len(),
+        3,
+        "expected failed turn, compaction, resumed turn"
+    );
+    let bodies: Vec<String> = requests
+        .iter()
+        .map(|request| request.body_json().to_string())
+        .collect();
+    assert!(
+        bodies[1].contains("You have exceeded the maximum number of tokens"),
+        "second request should be the compaction"
+    );
+    assert!(
+        !bodies[2].contains("You have exceeded the maximum number of tokens")
+            && bodies[2].contains(SUMMARY_TEXT)
+            && bodies[2].contains(FIRST_AUTO_MSG),
+        "third request should rerun the blocked turn on the compacted history"
+    );
+    assert_eq!(
+        bodies[2].matches(FIRST_AUTO_MSG).count(),
+        1,
+        "the resumed turn should not record the blocked input a second time"
+    );
+}
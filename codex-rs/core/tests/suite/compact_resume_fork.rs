@@ -776,9 +776,7 @@ async fn start_test_conversation(
 
 async fn user_turn(conversation: &Arc<CodexConversation>, text: &str) {
     conversation
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text { text: text.into() }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text { text: text.into() }]))
         .await
         .expect("submit user turn");
     wait_for_event(conversation, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
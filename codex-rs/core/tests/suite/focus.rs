@@ -0,0 +1,156 @@
+#![cfg(not(target_os = "windows"))]
+
+use codex_core::protocol::AskForApproval;
+use codex_core::protocol::DigestFocus;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::SandboxPolicy;
+use codex_protocol::config_types::ReasoningSummary;
+use core_test_support::responses::ev_apply_patch_function_call;
+use core_test_support::responses::ev_assistant_message;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::ev_response_created;
+use core_test_support::responses::mount_sse_sequence;
+use core_test_support::responses::sse;
+use core_test_support::responses::start_mock_server;
+use core_test_support::skip_if_no_network;
+use core_test_support::test_codex::TestCodex;
+use core_test_support::test_codex::test_codex;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// A task spawned under a focus reports it in `task_spawned` and its digest
+/// turn, and its patch touching a file outside the focus emits
+/// `focus_drift` naming only that file.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn patches_outside_the_focus_drift_and_turns_are_attributed() -> anyhow::Result<()> {
+    skip_if_no_network!(Ok(()));
+
+    let server = start_mock_server().await;
+    let patch = r#"*** Begin Patch
+*** Add File: src/parser/ast.rs
++pub struct Ast;
+*** Add File: notes.txt
++parser notes
+*** End Patch"#;
+    mount_sse_sequence(
+        &server,
+        vec![
+            sse(vec![
+                ev_response_created("resp-1"),
+                ev_apply_patch_function_call("patch-call", patch),
+                ev_completed("resp-1"),
+            ]),
+            sse(vec![
+                ev_assistant_message("msg-1", "done"),
+                ev_completed("resp-2"),
+            ]),
+        ],
+    )
+    .await;
+
+    let mut builder = test_codex().with_config(|config| {
+        config.include_apply_patch_tool = true;
+    });
+    let TestCodex {
+        codex,
+        cwd,
+        session_configured,
+        ..
+    } = builder.build(&server).await?;
+
+    codex
+        .submit(Op::SetFocus {
+            label: "parser".to_string(),
+            paths: vec![PathBuf::from("src/parser")],
+        })
+        .await?;
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::BackgroundEvent(_))).await;
+
+    codex
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
+                text: "add the parser ast".into(),
+            }],
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_configured.model.clone(),
+            None,
+            ReasoningSummary::Auto,
+        ))
+        .await?;
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    codex
+        .submit(Op::QueryRecentEvents {
+            query: EventQuery {
+                action_types: Some(vec!["task_spawned".to_string(), "focus_drift".to_string()]),
+                ..Default::default()
+            },
+        })
+        .await?;
+    let EventMsg::RecentEvents(recent) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+    else {
+        unreachable!();
+    };
+    let focus_path = cwd.path().join("src/parser");
+    let [spawned, drift] = recent.events.as_slice() else {
+        panic!(
+            "expected task_spawned and focus_drift, got {:?}",
+            recent.events
+        );
+    };
+    assert_eq!(
+        spawned.action["focus"],
+        json!({ "label": "parser", "paths": [focus_path] })
+    );
+    let sub_id = spawned.action["subId"].clone();
+    assert_eq!(
+        (drift.action_type.as_str(), &drift.action),
+        (
+            "focus_drift",
+            &json!({
+                "subId": sub_id,
+                "callId": "patch-call",
+                "focus": "parser",
+                "focusPaths": [focus_path],
+                "outside": [cwd.path().join("notes.txt")],
+            })
+        )
+    );
+
+    codex
+        .submit(Op::GetDigest {
+            since_sequence: None,
+            since_time_ms: None,
+        })
+        .await?;
+    let EventMsg::Digest(digest) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::Digest(_))).await
+    else {
+        unreachable!();
+    };
+    let [turn] = digest.turns_completed.as_slice() else {
+        panic!("expected one turn, got {:?}", digest.turns_completed);
+    };
+    assert_eq!(turn.focus.as_deref(), Some("parser"));
+    assert_eq!(
+        digest.by_focus,
+        vec![DigestFocus {
+            focus: "parser".to_string(),
+            turns: 1,
+            files_changed: turn.files_changed.clone(),
+            tokens_spent: digest.tokens_spent.clone(),
+            errors: Vec::new(),
+            annotations: Vec::new(),
+        }]
+    );
+
+    Ok(())
+}
@@ -70,11 +70,9 @@ async fn fork_conversation_twice_drops_to_first_message() {
     // Send three user messages; wait for three completed turns.
     for text in ["first", "second", "third"] {
         codex
-            .submit(Op::UserInput {
-                items: vec![InputItem::Text {
-                    text: text.to_string(),
-                }],
-            })
+            .submit(Op::user_input(vec![InputItem::Text {
+                text: text.to_string(),
+            }]))
             .await
             .unwrap();
         let _ = wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -148,18 +148,17 @@ async fn submit_turn(test: &TestCodex, prompt: &str) -> Result<()> {
     let session_model = test.session_configured.model.clone();
 
     test.codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: prompt.into(),
             }],
-            final_output_json_schema: None,
-            cwd: test.cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            test.cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&test.codex, |event| {
@@ -0,0 +1,163 @@
+use base64::Engine;
+use codex_core::CodexAuth;
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::NewConversation;
+use codex_core::built_in_model_providers;
+use codex_core::config_types::ImageDegradation;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use core_test_support::load_default_config_for_test;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::mount_sse_sequence;
+use core_test_support::responses::sse;
+use core_test_support::responses::start_mock_server;
+use core_test_support::skip_if_no_network;
+use core_test_support::wait_for_event;
+use image::RgbImage;
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+use serde_json::json;
+use tempfile::TempDir;
+
+const PROMPT: &str = "compare these screenshots";
+
+/// Data URLs of the `input_image` parts of the last user message in `input`.
+fn user_images(input: &[Value]) -> Vec<String> {
+    input
+        .iter()
+        .rev()
+        .find(|item| item["type"] == "message" && item["role"] == "user")
+        .and_then(|message| message["content"].as_array())
+        .into_iter()
+        .flatten()
+        .filter(|part| part["type"] == "input_image")
+        .filter_map(|part| part["image_url"].as_str().map(str::to_string))
+        .collect()
+}
+
+fn dimensions(data_url: &str) -> (u32, u32) {
+    let (_, data) = data_url.split_once(";base64,").unwrap();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .unwrap();
+    let image = image::load_from_memory(&bytes).unwrap();
+    (image.width(), image.height())
+}
+
+/// Three 600x400 screenshots overflow a 50-token budget: all of them are
+/// downscaled, the two oldest are dropped, and the rollout still has all
+/// three as submitted.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn large_images_are_downscaled_then_dropped_but_persisted_unmodified() {
+    skip_if_no_network!();
+
+    let server = start_mock_server().await;
+    mount_sse_sequence(&server, vec![sse(vec![ev_completed("r1")])]).await;
+
+    let fixtures = TempDir::new().unwrap();
+    let mut input = vec![InputItem::Text {
+        text: PROMPT.to_string(),
+    }];
+    let mut originals = Vec::new();
+    for name in ["first.png", "second.png", "third.png"] {
+        let path = fixtures.path().join(name);
+        RgbImage::new(600, 400).save(&path).unwrap();
+        let encoded =
+            base64::engine::general_purpose::STANDARD.encode(std::fs::read(&path).unwrap());
+        originals.push(format!("data:image/png;base64,{encoded}"));
+        input.push(InputItem::LocalImage { path });
+    }
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    config.model_auto_compact_token_limit = Some(50);
+    config.image_degradation = ImageDegradation {
+        max_pixels: 200 * 100,
+        keep_recent: 1,
+    };
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let NewConversation {
+        conversation: codex,
+        session_configured,
+        ..
+    } = conversation_manager.new_conversation(config).await.unwrap();
+
+    codex.submit(Op::user_input(input)).await.unwrap();
+    let EventMsg::BackgroundEvent(notice) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::BackgroundEvent(_))).await
+    else {
+        unreachable!();
+    };
+    assert_eq!(
+        notice.message,
+        "Reduced image input to fit the context budget (about 34 of 50 tokens, down from 967): \
+         downscaled item 2 from 600x400 to 173x115; downscaled item 3 from 600x400 to 173x115; \
+         downscaled item 4 from 600x400 to 173x115; dropped item(s) 2, 3, keeping the 1 most \
+         recent image(s). The original images are still saved in the session."
+    );
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    // The model only sees the most recent image, downscaled.
+    let requests = server.received_requests().await.unwrap();
+    let body = requests[0].body_json::<Value>().unwrap();
+    let sent = user_images(body["input"].as_array().unwrap());
+    assert_eq!(
+        sent.iter().map(|url| dimensions(url)).collect::<Vec<_>>(),
+        vec![(173, 115)]
+    );
+
+    codex
+        .submit(Op::QueryRecentEvents {
+            query: EventQuery {
+                action_types: Some(vec!["input_degraded".to_string()]),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+    let EventMsg::RecentEvents(recent) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+    else {
+        unreachable!();
+    };
+    let mut actions: Vec<Value> = recent
+        .events
+        .into_iter()
+        .map(|event| event.action)
+        .collect();
+    for action in &mut actions {
+        action.as_object_mut().unwrap().remove("subId");
+    }
+    let downscaled = |index: usize| json!({ "index": index, "from": [600, 400], "to": [173, 115] });
+    assert_eq!(
+        actions,
+        vec![json!({
+            "budgetTokens": 50,
+            "estimatedTokensBefore": 967,
+            "estimatedTokensAfter": 34,
+            "stages": [
+                { "stage": "downscale", "images": [downscaled(1), downscaled(2), downscaled(3)] },
+                { "stage": "drop", "keepRecent": 1, "dropped": [1, 2] },
+            ],
+        })]
+    );
+
+    codex.submit(Op::Shutdown).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::ShutdownComplete)).await;
+
+    let rollout = std::fs::read_to_string(&session_configured.rollout_path).unwrap();
+    let persisted: Vec<Value> = rollout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|line| line["type"] == "response_item")
+        .map(|line| line["payload"].clone())
+        .collect();
+    assert_eq!(user_images(&persisted), originals);
+}
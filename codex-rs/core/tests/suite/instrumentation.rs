@@ -0,0 +1,107 @@
+use codex_core::CodexAuth;
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::ReviewRequest;
+use core_test_support::load_default_config_for_test;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::mount_sse_sequence;
+use core_test_support::responses::sse;
+use core_test_support::responses::start_mock_server;
+use core_test_support::skip_if_no_network;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// A regular turn, a review, and a compaction each emit every checklist
+/// action, so none of them reports an `instrumentation_gap`, and their
+/// lifecycle snapshots never report a `telemetry_inconsistency`.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn regular_review_and_compact_turns_report_no_instrumentation_gap() {
+    skip_if_no_network!();
+
+    let server = start_mock_server().await;
+    mount_sse_sequence(
+        &server,
+        vec![
+            sse(vec![ev_completed("r1")]),
+            sse(vec![ev_completed("r2")]),
+            sse(vec![ev_completed("r3")]),
+        ],
+    )
+    .await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".to_string(),
+        }]))
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex
+        .submit(Op::Review {
+            review_request: ReviewRequest {
+                prompt: "review the change".to_string(),
+                user_facing_hint: "review the change".to_string(),
+            },
+            target: None,
+            continue_from_cursor: false,
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex.submit(Op::Compact).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    // `task_completed` is emitted after `TaskComplete`, so poll for the last
+    // one.
+    let mut action_types = Vec::new();
+    for _ in 0..50 {
+        codex
+            .submit(Op::QueryRecentEvents {
+                query: EventQuery {
+                    action_types: Some(vec![
+                        "task_completed".to_string(),
+                        "instrumentation_gap".to_string(),
+                        "telemetry_inconsistency".to_string(),
+                    ]),
+                    ..Default::default()
+                },
+            })
+            .await
+            .unwrap();
+        let EventMsg::RecentEvents(recent) =
+            wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+        else {
+            unreachable!();
+        };
+        action_types = recent
+            .events
+            .into_iter()
+            .map(|event| event.action_type)
+            .collect();
+        if action_types.len() >= 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(action_types, vec!["task_completed".to_string(); 3]);
+}
@@ -84,6 +84,8 @@ async fn codex_returns_json_result(model: String) -> anyhow::Result<()> {
             model,
             effort: None,
             summary: ReasoningSummary::Auto,
+            client_context: None,
+            replay_of: None,
         })
         .await?;
 
@@ -65,18 +65,17 @@ async fn list_dir_tool_returns_entries() -> anyhow::Result<()> {
     let session_model = session_configured.model.clone();
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "list directory contents".into(),
             }],
-            final_output_json_schema: None,
-            cwd: cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -170,18 +169,17 @@ async fn list_dir_tool_depth_one_omits_children() -> anyhow::Result<()> {
     let session_model = session_configured.model.clone();
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "list directory contents depth one".into(),
             }],
-            final_output_json_schema: None,
-            cwd: cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -282,18 +280,17 @@ async fn list_dir_tool_depth_two_includes_children_only() -> anyhow::Result<()>
     let session_model = session_configured.model.clone();
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "list directory contents depth two".into(),
             }],
-            final_output_json_schema: None,
-            cwd: cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -397,18 +394,17 @@ async fn list_dir_tool_depth_three_includes_grandchildren() -> anyhow::Result<()
     let session_model = session_configured.model.clone();
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "list directory contents depth three".into(),
             }],
-            final_output_json_schema: None,
-            cwd: cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -8,8 +8,11 @@ mod compact;
 mod compact_resume_fork;
 mod exec;
 mod exec_stream_events;
+mod focus;
 mod fork_conversation;
 mod grep_files;
+mod image_degradation;
+mod instrumentation;
 mod json_result;
 mod list_dir;
 mod live_cli;
@@ -18,6 +21,7 @@ mod model_tools;
 mod otel;
 mod prompt_caching;
 mod read_file;
+mod reasoning_phase;
 mod review;
 mod rmcp_client;
 mod rollout_list_find;
@@ -25,9 +29,11 @@ mod seatbelt;
 mod shell_serialization;
 mod stream_error_allows_next_turn;
 mod stream_no_completed;
+mod task_templates;
 mod tool_harness;
 mod tool_parallelism;
 mod tools;
+mod turn_templates;
 mod unified_exec;
 mod user_notification;
 mod view_image;
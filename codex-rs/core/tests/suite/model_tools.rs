@@ -72,11 +72,9 @@ async fn collect_tool_identifiers_for_model(model: &str) -> Vec<String> {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello tools".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello tools".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -30,11 +30,9 @@ async fn responses_api_emits_api_request_event() {
     let TestCodex { codex, .. } = test_codex().build(&server).await.unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -76,11 +74,9 @@ async fn process_sse_emits_tracing_for_output_item() {
     let TestCodex { codex, .. } = test_codex().build(&server).await.unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -120,11 +116,9 @@ async fn process_sse_emits_failed_event_on_parse_error() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -165,11 +159,9 @@ async fn process_sse_records_failed_event_when_stream_closes_without_completed()
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -222,11 +214,9 @@ async fn process_sse_failed_event_records_response_error_message() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -277,11 +267,9 @@ async fn process_sse_failed_event_logs_parse_error() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -327,11 +315,9 @@ async fn process_sse_failed_event_logs_missing_error() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -377,11 +363,9 @@ async fn process_sse_failed_event_logs_response_completed_parse_error() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -432,11 +416,9 @@ async fn process_sse_emits_completed_telemetry() {
     let TestCodex { codex, .. } = test_codex().build(&server).await.unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -492,11 +474,9 @@ async fn handle_response_item_records_tool_result_for_custom_tool_call() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -556,11 +536,9 @@ async fn handle_response_item_records_tool_result_for_function_call() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -630,11 +608,9 @@ async fn handle_response_item_records_tool_result_for_local_shell_missing_ids()
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -688,11 +664,9 @@ async fn handle_response_item_records_tool_result_for_local_shell_call() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -786,11 +760,9 @@ async fn handle_container_exec_autoapprove_from_config_records_tool_decision() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -836,11 +808,9 @@ async fn handle_container_exec_user_approved_records_tool_decision() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "approved".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "approved".into(),
+        }]))
         .await
         .unwrap();
 
@@ -902,11 +872,9 @@ async fn handle_container_exec_user_approved_for_session_records_tool_decision()
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "persist".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "persist".into(),
+        }]))
         .await
         .unwrap();
 
@@ -968,11 +936,9 @@ async fn handle_sandbox_error_user_approves_retry_records_tool_decision() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "retry".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "retry".into(),
+        }]))
         .await
         .unwrap();
 
@@ -1030,11 +996,9 @@ async fn handle_container_exec_user_denies_records_tool_decision() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "deny".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "deny".into(),
+        }]))
         .await
         .unwrap();
 
@@ -1096,11 +1060,9 @@ async fn handle_sandbox_error_user_approves_for_session_records_tool_decision()
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "persist".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "persist".into(),
+        }]))
         .await
         .unwrap();
 
@@ -1158,11 +1120,9 @@ async fn handle_sandbox_error_user_denies_records_tool_decision() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "deny".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "deny".into(),
+        }]))
         .await
         .unwrap();
 
@@ -113,21 +113,17 @@ async fn codex_mini_latest_tools() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 1".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 1".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 2".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 2".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -197,21 +193,17 @@ async fn prompt_tools_are_consistent_across_requests() {
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 1".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 1".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 2".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 2".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -299,21 +291,17 @@ async fn prefixes_context_and_instructions_once_and_consistently_across_requests
         .conversation;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 1".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 1".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 2".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 2".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -419,11 +407,9 @@ async fn overrides_turn_context_but_keeps_cached_prefix_and_key_constant() {
 
     // First turn
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 1".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 1".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -448,11 +434,9 @@ async fn overrides_turn_context_but_keeps_cached_prefix_and_key_constant() {
 
     // Second turn after overrides
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 2".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 2".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -547,11 +531,9 @@ async fn per_turn_overrides_keep_cached_prefix_and_key_constant() {
 
     // First turn
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello 1".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello 1".into(),
+        }]))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -560,23 +542,22 @@ async fn per_turn_overrides_keep_cached_prefix_and_key_constant() {
     let new_cwd = TempDir::new().unwrap();
     let writable = TempDir::new().unwrap();
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "hello 2".into(),
             }],
-            cwd: new_cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::WorkspaceWrite {
+            new_cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::WorkspaceWrite {
                 writable_roots: vec![writable.path().to_path_buf()],
                 network_access: true,
                 exclude_tmpdir_env_var: true,
                 exclude_slash_tmp: true,
             },
-            model: "o3".to_string(),
-            effort: Some(ReasoningEffort::High),
-            summary: ReasoningSummary::Detailed,
-            final_output_json_schema: None,
-        })
+            "o3".to_string(),
+            Some(ReasoningEffort::High),
+            ReasoningSummary::Detailed,
+        ))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -676,35 +657,33 @@ async fn send_user_turn_with_no_changes_does_not_send_environment_context() {
         .conversation;
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "hello 1".into(),
             }],
-            cwd: default_cwd.clone(),
-            approval_policy: default_approval_policy,
-            sandbox_policy: default_sandbox_policy.clone(),
-            model: default_model.clone(),
-            effort: default_effort,
-            summary: default_summary,
-            final_output_json_schema: None,
-        })
+            default_cwd.clone(),
+            default_approval_policy,
+            default_sandbox_policy.clone(),
+            default_model.clone(),
+            default_effort,
+            default_summary,
+        ))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "hello 2".into(),
             }],
-            cwd: default_cwd.clone(),
-            approval_policy: default_approval_policy,
-            sandbox_policy: default_sandbox_policy.clone(),
-            model: default_model.clone(),
-            effort: default_effort,
-            summary: default_summary,
-            final_output_json_schema: None,
-        })
+            default_cwd.clone(),
+            default_approval_policy,
+            default_sandbox_policy.clone(),
+            default_model.clone(),
+            default_effort,
+            default_summary,
+        ))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -790,35 +769,33 @@ async fn send_user_turn_with_changes_sends_environment_context() {
         .conversation;
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "hello 1".into(),
             }],
-            cwd: default_cwd.clone(),
-            approval_policy: default_approval_policy,
-            sandbox_policy: default_sandbox_policy.clone(),
-            model: default_model,
-            effort: default_effort,
-            summary: default_summary,
-            final_output_json_schema: None,
-        })
+            default_cwd.clone(),
+            default_approval_policy,
+            default_sandbox_policy.clone(),
+            default_model,
+            default_effort,
+            default_summary,
+        ))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "hello 2".into(),
             }],
-            cwd: default_cwd.clone(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: "o3".to_string(),
-            effort: Some(ReasoningEffort::High),
-            summary: ReasoningSummary::Detailed,
-            final_output_json_schema: None,
-        })
+            default_cwd.clone(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            "o3".to_string(),
+            Some(ReasoningEffort::High),
+            ReasoningSummary::Detailed,
+        ))
         .await
         .unwrap();
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -63,18 +63,17 @@ async fn read_file_tool_returns_requested_lines() -> anyhow::Result<()> {
     let session_model = session_configured.model.clone();
 
     codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "please inspect sample.txt".into(),
             }],
-            final_output_json_schema: None,
-            cwd: cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -0,0 +1,170 @@
+use codex_core::CodexAuth;
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::RecentEvent;
+use core_test_support::load_default_config_for_test;
+use core_test_support::responses::ev_assistant_message;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::ev_response_created;
+use core_test_support::responses::sse;
+use core_test_support::skip_if_no_network;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncReadExt as _;
+use tokio::io::AsyncWriteExt as _;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+/// How long the scripted stream holds its answer back after the reasoning.
+const THINKING: Duration = Duration::from_millis(300);
+
+/// Answer a single responses request with `reasoning`, then, after
+/// [`THINKING`], with `answer`. Closing the connection ends the body, so the
+/// two halves reach the client as separate reads. Returns the base URL.
+async fn start_scripted_stream(reasoning: String, answer: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let Ok((mut socket, _)) = listener.accept().await else {
+            return;
+        };
+        read_request(&mut socket).await;
+        let head =
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n";
+        let _ = socket
+            .write_all(format!("{head}{reasoning}").as_bytes())
+            .await;
+        let _ = socket.flush().await;
+        tokio::time::sleep(THINKING).await;
+        let _ = socket.write_all(answer.as_bytes()).await;
+        let _ = socket.shutdown().await;
+    });
+    format!("http://{addr}/v1")
+}
+
+/// Read the request head and as much body as its `content-length` names.
+async fn read_request(socket: &mut TcpStream) {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = socket.read(&mut buf).await.unwrap_or(0);
+        if read == 0 {
+            return;
+        }
+        request.extend_from_slice(&buf[..read]);
+        let Some(head_end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&request[..head_end]).to_ascii_lowercase();
+        let content_length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|len| len.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        if request.len() >= head_end + 4 + content_length {
+            return;
+        }
+    }
+}
+
+/// Reasoning deltas followed, after a pause, by text: one `reasoning_phase`
+/// spans the pause and estimates the reasoning's tokens, and
+/// `task_completed` reports the same totals in its latency breakdown.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn scripted_stream_reports_the_reasoning_phase() {
+    skip_if_no_network!();
+
+    // 11 + 8 = 19 bytes of reasoning, about 5 tokens at 4 bytes each.
+    let reasoning = sse(vec![
+        ev_response_created("r1"),
+        json!({ "type": "response.reasoning_summary_text.delta", "delta": "thinking it" }),
+        json!({ "type": "response.reasoning_summary_text.delta", "delta": " through" }),
+    ]);
+    let answer = sse(vec![
+        json!({ "type": "response.output_text.delta", "delta": "done" }),
+        ev_assistant_message("m1", "done"),
+        ev_completed("r1"),
+    ]);
+    let base_url = start_scripted_stream(reasoning, answer).await;
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(base_url),
+        request_max_retries: Some(0),
+        stream_max_retries: Some(0),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".to_string(),
+        }]))
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    // `task_completed` is emitted after `TaskComplete`, so poll for it.
+    let mut events: Vec<RecentEvent> = Vec::new();
+    for _ in 0..50 {
+        codex
+            .submit(Op::QueryRecentEvents {
+                query: EventQuery {
+                    action_types: Some(vec![
+                        "reasoning_phase".to_string(),
+                        "task_completed".to_string(),
+                    ]),
+                    ..Default::default()
+                },
+            })
+            .await
+            .unwrap();
+        let EventMsg::RecentEvents(recent) =
+            wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+        else {
+            unreachable!();
+        };
+        events = recent.events;
+        if events
+            .iter()
+            .any(|event| event.action_type == "task_completed")
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let action_types: Vec<&str> = events
+        .iter()
+        .map(|event| event.action_type.as_str())
+        .collect();
+    assert_eq!(action_types, vec!["reasoning_phase", "task_completed"]);
+
+    let phase = &events[0].action;
+    let duration_ms = phase["durationMs"].as_u64().expect("durationMs");
+    // The phase starts once the response head arrives, a little after the
+    // pause has begun.
+    assert!(
+        duration_ms >= THINKING.as_millis() as u64 / 2,
+        "the phase spans the pause before the text, got {duration_ms}ms"
+    );
+    assert_eq!(phase["reasoningTokenEstimate"], json!(5));
+    assert_eq!(
+        events[1].action["latencyBreakdown"],
+        json!({ "reasoningMs": duration_ms, "reasoningTokenEstimate": 5 })
+    );
+}
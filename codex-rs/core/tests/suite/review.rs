@@ -10,6 +10,7 @@ use codex_core::config::Config;
 use codex_core::protocol::ConversationPathResponseEvent;
 use codex_core::protocol::ENVIRONMENT_CONTEXT_OPEN_TAG;
 use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
 use codex_core::protocol::ExitedReviewModeEvent;
 use codex_core::protocol::InputItem;
 use codex_core::protocol::Op;
@@ -18,6 +19,7 @@ use codex_core::protocol::ReviewFinding;
 use codex_core::protocol::ReviewLineRange;
 use codex_core::protocol::ReviewOutputEvent;
 use codex_core::protocol::ReviewRequest;
+use codex_core::protocol::ReviewTarget;
 use codex_core::protocol::RolloutItem;
 use codex_core::protocol::RolloutLine;
 use core_test_support::load_default_config_for_test;
@@ -85,6 +87,8 @@ async fn review_op_emits_lifecycle_and_review_output() {
                 prompt: "Please review my changes".to_string(),
                 user_facing_hint: "my changes".to_string(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -187,6 +191,8 @@ async fn review_op_with_plain_text_emits_review_fallback() {
                 prompt: "Plain text review".to_string(),
                 user_facing_hint: "plain text review".to_string(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -256,6 +262,8 @@ async fn review_does_not_emit_agent_message_on_structured_output() {
                 prompt: "check structured".to_string(),
                 user_facing_hint: "check structured".to_string(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -313,6 +321,8 @@ async fn review_uses_custom_review_model_from_config() {
                 prompt: "use custom model".to_string(),
                 user_facing_hint: "use custom model".to_string(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -429,6 +439,8 @@ async fn review_input_isolated_from_parent_history() {
                 prompt: review_prompt.clone(),
                 user_facing_hint: review_prompt.clone(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -547,6 +559,8 @@ async fn review_history_does_not_leak_into_parent_session() {
                 prompt: "Start a review".to_string(),
                 user_facing_hint: "Start a review".to_string(),
             },
+            target: None,
+            continue_from_cursor: false,
         })
         .await
         .unwrap();
@@ -565,11 +579,9 @@ async fn review_history_does_not_leak_into_parent_session() {
     // 2) Continue in the parent session; request input must not include any review items.
     let followup = "back to parent".to_string();
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: followup.clone(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: followup.clone(),
+        }]))
         .await
         .unwrap();
     let _complete = wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
@@ -607,6 +619,146 @@ async fn review_history_does_not_leak_into_parent_session() {
     server.verify().await;
 }
 
+/// Two consecutive sliced reviews cover disjoint files of the diff, and a
+/// continued review after the diff changed starts over with a warning.
+#[cfg_attr(windows, tokio::test(flavor = "multi_thread", worker_threads = 4))]
+#[cfg_attr(not(windows), tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn sliced_reviews_continue_from_the_cursor_until_the_diff_changes() {
+    skip_if_no_network!();
+
+    let sse_raw = r#"[
+        {"type":"response.output_item.done", "item":{
+            "type":"message", "role":"assistant",
+            "content":[{"type":"output_text","text":"no issues"}]
+        }},
+        {"type":"response.completed", "response": {"id": "__ID__"}}
+    ]"#;
+    let server = start_responses_server_with_sse(sse_raw, 3).await;
+
+    let repo = TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(repo.path())
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    };
+    git(&["init", "-q"]);
+    git(&["config", "user.email", "test@example.com"]);
+    git(&["config", "user.name", "Test"]);
+    let names = ["a.rs", "b.rs", "c.rs", "d.rs", "e.rs"];
+    for name in names {
+        std::fs::write(repo.path().join(name), "fn old() {}\n").unwrap();
+    }
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "fixture"]);
+    for name in names {
+        std::fs::write(repo.path().join(name), "fn new() {}\n").unwrap();
+    }
+
+    let codex_home = TempDir::new().unwrap();
+    let cwd = repo.path().to_path_buf();
+    let codex = new_conversation_for_server(&server, &codex_home, |cfg| {
+        cfg.cwd = cwd;
+        cfg.review_slice_files = 2;
+    })
+    .await;
+    let review = |continue_from_cursor: bool| Op::Review {
+        review_request: ReviewRequest {
+            prompt: "Review the current changes".to_string(),
+            user_facing_hint: "current changes".to_string(),
+        },
+        target: Some(ReviewTarget::UncommittedChanges),
+        continue_from_cursor,
+    };
+
+    codex.submit(review(false)).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex.submit(review(true)).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    std::fs::write(repo.path().join("f.rs"), "fn added() {}\n").unwrap();
+    codex.submit(review(true)).await.unwrap();
+    let warning = wait_for_event(&codex, |ev| matches!(ev, EventMsg::BackgroundEvent(_))).await;
+    let EventMsg::BackgroundEvent(warning) = warning else {
+        unreachable!();
+    };
+    assert_eq!(
+        warning.message,
+        "The diff changed since the last review slice (5 files then, 6 now), so the review cursor was reset; reviewing from the first file."
+    );
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    // Files listed in each review prompt.
+    let requests = server.received_requests().await.unwrap();
+    let covered: Vec<Vec<String>> = requests
+        .iter()
+        .map(|request| {
+            let body = request.body_json::<serde_json::Value>().unwrap();
+            let prompt = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .rev()
+                .find(|item| item["role"] == "user")
+                .and_then(|item| item["content"][0]["text"].as_str())
+                .unwrap_or_default()
+                .to_string();
+            prompt
+                .lines()
+                .filter_map(|line| line.strip_prefix("- "))
+                .map(str::to_string)
+                .collect()
+        })
+        .collect();
+    let files = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        covered,
+        vec![
+            files(&["a.rs", "b.rs"]),
+            files(&["c.rs", "d.rs"]),
+            files(&["a.rs", "b.rs"]),
+        ]
+    );
+
+    codex
+        .submit(Op::QueryRecentEvents {
+            query: EventQuery {
+                action_types: Some(vec!["task_spawned".to_string()]),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+    let EventMsg::RecentEvents(recent) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+    else {
+        unreachable!();
+    };
+    let labels: Vec<serde_json::Value> = recent
+        .events
+        .iter()
+        .map(|event| event.action["reviewSlice"]["label"].clone())
+        .collect();
+    assert_eq!(
+        labels,
+        vec![
+            serde_json::json!("files 1–2 of 5"),
+            serde_json::json!("files 3–4 of 5"),
+            serde_json::json!("files 1–2 of 6"),
+        ]
+    );
+
+    server.verify().await;
+}
+
 /// Start a mock Responses API server and mount the given SSE stream body.
 async fn start_responses_server_with_sse(sse_raw: &str, expected_requests: usize) -> MockServer {
     let server = MockServer::start().await;
@@ -98,18 +98,17 @@ async fn stdio_server_round_trip() -> anyhow::Result<()> {
 
     fixture
         .codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "call the rmcp echo tool".into(),
             }],
-            final_output_json_schema: None,
-            cwd: fixture.cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            fixture.cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     let begin_event = wait_for_event_with_timeout(
@@ -247,18 +246,17 @@ async fn streamable_http_tool_call_round_trip() -> anyhow::Result<()> {
 
     fixture
         .codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "call the rmcp streamable http echo tool".into(),
             }],
-            final_output_json_schema: None,
-            cwd: fixture.cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            fixture.cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     let begin_event = wait_for_event_with_timeout(
@@ -428,18 +426,17 @@ async fn streamable_http_with_oauth_round_trip() -> anyhow::Result<()> {
 
     fixture
         .codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: "call the rmcp streamable http oauth echo tool".into(),
             }],
-            final_output_json_schema: None,
-            cwd: fixture.cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
-            sandbox_policy: SandboxPolicy::DangerFullAccess,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            fixture.cwd.path().to_path_buf(),
+            AskForApproval::Never,
+            SandboxPolicy::DangerFullAccess,
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     let begin_event = wait_for_event_with_timeout(
@@ -27,18 +27,17 @@ async fn submit_turn(test: &TestCodex, prompt: &str, sandbox_policy: SandboxPoli
     let session_model = test.session_configured.model.clone();
 
     test.codex
-        .submit(Op::UserTurn {
-            items: vec![InputItem::Text {
+        .submit(Op::user_turn(
+            vec![InputItem::Text {
                 text: prompt.into(),
             }],
-            final_output_json_schema: None,
-            cwd: test.cwd.path().to_path_buf(),
-            approval_policy: AskForApproval::Never,
+            test.cwd.path().to_path_buf(),
+            AskForApproval::Never,
             sandbox_policy,
-            model: session_model,
-            effort: None,
-            summary: ReasoningSummary::Auto,
-        })
+            session_model,
+            None,
+            ReasoningSummary::Auto,
+        ))
         .await?;
 
     wait_for_event(&test.codex, |event| {
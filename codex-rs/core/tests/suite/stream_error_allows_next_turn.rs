@@ -86,11 +86,9 @@ async fn continue_after_stream_error() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "first message".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "first message".into(),
+        }]))
         .await
         .unwrap();
 
@@ -113,11 +111,9 @@ async fn continue_after_stream_error() {
     // mock server SSE stream. If the agent failed to clear the running task on
     // error above, this submission would be rejected/queued indefinitely.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "follow up".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "follow up".into(),
+        }]))
         .await
         .unwrap();
 
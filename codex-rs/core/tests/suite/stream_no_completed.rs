@@ -93,11 +93,9 @@ async fn retries_on_early_close() {
         .unwrap();
 
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello".into(),
+        }]))
         .await
         .unwrap();
 
@@ -0,0 +1,228 @@
+use codex_core::CodexAuth;
+use codex_core::ConversationManager;
+use codex_core::ModelProviderInfo;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::InputItem;
+use codex_core::protocol::Op;
+use codex_core::protocol::ReviewRequest;
+use core_test_support::load_default_config_for_test;
+use core_test_support::responses::ev_assistant_message;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::ev_completed_with_tokens;
+use core_test_support::responses::mount_sse_once_match;
+use core_test_support::responses::mount_sse_sequence;
+use core_test_support::responses::sse;
+use core_test_support::responses::start_mock_server;
+use core_test_support::skip_if_no_network;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+use serde_json::json;
+use sha1::Digest;
+use sha1::Sha1;
+use tempfile::TempDir;
+
+const REVIEW_TEMPLATE: &str = "REVIEW_TEMPLATE: flag missing tests.";
+const COMPACT_TEMPLATE: &str = "COMPACT_TEMPLATE: keep open questions.";
+const REGULAR_TEMPLATE: &str = "REGULAR_TEMPLATE: run the tests first.";
+
+/// A review and a compaction each get their own workspace template appended
+/// to their instructions, and `task_spawned` records which one was applied.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn review_and_compaction_apply_only_their_own_template() {
+    skip_if_no_network!();
+
+    let server = start_mock_server().await;
+    mount_sse_sequence(
+        &server,
+        vec![sse(vec![ev_completed("r1")]), sse(vec![ev_completed("r2")])],
+    )
+    .await;
+
+    let workspace = TempDir::new().unwrap();
+    let templates_dir = workspace.path().join(".codex/templates");
+    std::fs::create_dir_all(&templates_dir).unwrap();
+    std::fs::write(templates_dir.join("review.md"), REVIEW_TEMPLATE).unwrap();
+    std::fs::write(templates_dir.join("compact.md"), COMPACT_TEMPLATE).unwrap();
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    config.cwd = workspace.path().to_path_buf();
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    codex
+        .submit(Op::Review {
+            review_request: ReviewRequest {
+                prompt: "review the change".to_string(),
+                user_facing_hint: "review the change".to_string(),
+            },
+            target: None,
+            continue_from_cursor: false,
+        })
+        .await
+        .unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    codex.submit(Op::Compact).await.unwrap();
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let requests = server.received_requests().await.unwrap();
+    let instructions: Vec<(bool, bool)> = requests
+        .iter()
+        .map(|request| {
+            let body = request.body_json::<Value>().unwrap();
+            let instructions = body["instructions"].as_str().unwrap_or_default();
+            (
+                instructions.contains(REVIEW_TEMPLATE),
+                instructions.contains(COMPACT_TEMPLATE),
+            )
+        })
+        .collect();
+    assert_eq!(instructions, vec![(true, false), (false, true)]);
+
+    codex
+        .submit(Op::QueryRecentEvents {
+            query: EventQuery {
+                action_types: Some(vec!["task_spawned".to_string()]),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+    let EventMsg::RecentEvents(recent) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+    else {
+        unreachable!();
+    };
+    let applied: Vec<(Value, Value)> = recent
+        .events
+        .iter()
+        .map(|event| {
+            (
+                event.action["taskKind"].clone(),
+                event.action["taskTemplate"].clone(),
+            )
+        })
+        .collect();
+    let template = |name: &str, text: &str| {
+        json!({
+            "path": templates_dir.join(name),
+            "hash": format!("{:x}", Sha1::digest(text.as_bytes())),
+        })
+    };
+    assert_eq!(
+        applied,
+        vec![
+            (json!("Review"), template("review.md", REVIEW_TEMPLATE)),
+            (json!("Compact"), template("compact.md", COMPACT_TEMPLATE)),
+        ]
+    );
+}
+
+/// A compaction run inline, inside a regular turn that hit the token limit,
+/// gets the compaction template rather than that of the turn it runs in.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn inline_auto_compaction_applies_the_compact_template() {
+    skip_if_no_network!();
+
+    let server = start_mock_server().await;
+    mount_sse_once_match(
+        &server,
+        |req: &wiremock::Request| {
+            let body = std::str::from_utf8(&req.body).unwrap_or("");
+            body.contains("first turn") && !body.contains("second turn") && !is_compaction(body)
+        },
+        sse(vec![
+            ev_assistant_message("m1", "FIRST_REPLY"),
+            ev_completed_with_tokens("r1", 70_000),
+        ]),
+    )
+    .await;
+    mount_sse_once_match(
+        &server,
+        |req: &wiremock::Request| {
+            let body = std::str::from_utf8(&req.body).unwrap_or("");
+            body.contains("second turn") && !is_compaction(body)
+        },
+        sse(vec![
+            ev_assistant_message("m2", "SECOND_REPLY"),
+            ev_completed_with_tokens("r2", 330_000),
+        ]),
+    )
+    .await;
+    mount_sse_once_match(
+        &server,
+        |req: &wiremock::Request| is_compaction(std::str::from_utf8(&req.body).unwrap_or("")),
+        sse(vec![
+            ev_assistant_message("m3", "SUMMARY"),
+            ev_completed_with_tokens("r3", 200),
+        ]),
+    )
+    .await;
+
+    let workspace = TempDir::new().unwrap();
+    let templates_dir = workspace.path().join(".codex/templates");
+    std::fs::create_dir_all(&templates_dir).unwrap();
+    std::fs::write(templates_dir.join("regular.md"), REGULAR_TEMPLATE).unwrap();
+    std::fs::write(templates_dir.join("compact.md"), COMPACT_TEMPLATE).unwrap();
+
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home);
+    config.model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    config.model_auto_compact_token_limit = Some(200_000);
+    config.cwd = workspace.path().to_path_buf();
+    let conversation_manager = ConversationManager::with_auth(CodexAuth::from_api_key("dummy"));
+    let codex = conversation_manager
+        .new_conversation(config)
+        .await
+        .unwrap()
+        .conversation;
+
+    for text in ["first turn", "second turn"] {
+        codex
+            .submit(Op::user_input(vec![InputItem::Text { text: text.into() }]))
+            .await
+            .unwrap();
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+    }
+
+    let requests = server.received_requests().await.unwrap();
+    let instructions: Vec<(bool, bool, bool)> = requests
+        .iter()
+        .take(3)
+        .map(|request| {
+            let body = request.body_json::<Value>().unwrap();
+            let instructions = body["instructions"].as_str().unwrap_or_default();
+            (
+                is_compaction(std::str::from_utf8(&request.body).unwrap_or("")),
+                instructions.contains(REGULAR_TEMPLATE),
+                instructions.contains(COMPACT_TEMPLATE),
+            )
+        })
+        .collect();
+    assert_eq!(
+        instructions,
+        vec![
+            (false, true, false),
+            (false, true, false),
+            (true, false, true)
+        ]
+    );
+}
+
+fn is_compaction(body: &str) -> bool {
+    body.contains("You have exceeded the maximum number of tokens")
+}
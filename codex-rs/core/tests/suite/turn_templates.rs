@@ -0,0 +1,148 @@
+#![cfg(not(target_os = "windows"))]
+
+use std::collections::BTreeMap;
+
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::EventQuery;
+use codex_core::protocol::Op;
+use codex_core::protocol::TurnTemplateUse;
+use core_test_support::responses::ev_assistant_message;
+use core_test_support::responses::ev_completed;
+use core_test_support::responses::mount_sse_once;
+use core_test_support::responses::sse;
+use core_test_support::responses::start_mock_server;
+use core_test_support::skip_if_no_network;
+use core_test_support::test_codex::TestCodex;
+use core_test_support::test_codex::test_codex;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+const CHANGELOG: &str = r#"
+prompt = "Add a changelog entry for {change} under {section}."
+
+[params.change]
+
+[params.section]
+default = "Unreleased"
+"#;
+
+/// A spawned template sends the rendered prompt, and `task_spawned` and the
+/// digest turn report it with its defaulted params; a template that lacks
+/// a required param is refused without spawning anything.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn spawned_template_is_rendered_and_attributed() -> anyhow::Result<()> {
+    skip_if_no_network!(Ok(()));
+
+    let server = start_mock_server().await;
+    let response = mount_sse_once(
+        &server,
+        sse(vec![
+            ev_assistant_message("msg-1", "done"),
+            ev_completed("resp-1"),
+        ]),
+    )
+    .await;
+
+    let TestCodex { codex, cwd, .. } = test_codex().build(&server).await?;
+    let templates_dir = cwd.path().join(".codex/turn-templates");
+    std::fs::create_dir_all(&templates_dir)?;
+    std::fs::write(templates_dir.join("changelog.toml"), CHANGELOG)?;
+
+    codex
+        .submit(Op::SpawnTemplate {
+            name: "changelog".to_string(),
+            params: BTreeMap::new(),
+            client_context: None,
+        })
+        .await?;
+    let EventMsg::Error(error) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::Error(_))).await
+    else {
+        unreachable!();
+    };
+    assert_eq!(
+        error.message,
+        "turn template `changelog` is missing `change`; it requires `change`"
+    );
+
+    codex
+        .submit(Op::SpawnTemplate {
+            name: "changelog".to_string(),
+            params: BTreeMap::from([("change".to_string(), "the retry fix".to_string())]),
+            client_context: None,
+        })
+        .await?;
+    wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
+
+    let rendered = "Add a changelog entry for the retry fix under Unreleased.";
+    let sent_rendered = response.single_request().input().iter().any(|item| {
+        item["content"]
+            .as_array()
+            .is_some_and(|content| content.iter().any(|part| part["text"] == rendered))
+    });
+    assert!(sent_rendered, "rendered prompt was not sent");
+
+    let template = TurnTemplateUse {
+        name: "changelog".to_string(),
+        params: BTreeMap::from([
+            ("change".to_string(), "the retry fix".to_string()),
+            ("section".to_string(), "Unreleased".to_string()),
+        ]),
+    };
+    codex
+        .submit(Op::QueryRecentEvents {
+            query: EventQuery {
+                action_types: Some(vec![
+                    "template_rejected".to_string(),
+                    "task_spawned".to_string(),
+                ]),
+                ..Default::default()
+            },
+        })
+        .await?;
+    let EventMsg::RecentEvents(recent) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::RecentEvents(_))).await
+    else {
+        unreachable!();
+    };
+    let [rejected, spawned] = recent.events.as_slice() else {
+        panic!(
+            "expected template_rejected and task_spawned, got {:?}",
+            recent.events
+        );
+    };
+    assert_eq!(
+        (
+            rejected.action["template"].clone(),
+            rejected.action["reason"].clone()
+        ),
+        (
+            json!("changelog"),
+            json!({
+                "type": "missingParams",
+                "missing": ["change"],
+                "required": ["change"],
+            })
+        )
+    );
+    assert_eq!(spawned.action["turnTemplate"], json!(template));
+
+    codex
+        .submit(Op::GetDigest {
+            since_sequence: None,
+            since_time_ms: None,
+        })
+        .await?;
+    let EventMsg::Digest(digest) =
+        wait_for_event(&codex, |ev| matches!(ev, EventMsg::Digest(_))).await
+    else {
+        unreachable!();
+    };
+    let [turn] = digest.turns_completed.as_slice() else {
+        panic!("expected one turn, got {:?}", digest.turns_completed);
+    };
+    assert_eq!(turn.template, Some(template));
+
+    Ok(())
+}
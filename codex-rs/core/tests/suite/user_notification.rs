@@ -51,11 +51,9 @@ echo -n "${@: -1}" > $(dirname "${0}")/notify.txt"#,
 
     // 1) Normal user input – should hit server once.
     codex
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text {
-                text: "hello world".into(),
-            }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text {
+            text: "hello world".into(),
+        }]))
         .await?;
     wait_for_event(&codex, |ev| matches!(ev, EventMsg::TaskComplete(_))).await;
 
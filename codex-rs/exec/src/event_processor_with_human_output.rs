@@ -18,6 +18,8 @@ use codex_core::protocol::PatchApplyEndEvent;
 use codex_core::protocol::SessionConfiguredEvent;
 use codex_core::protocol::StreamErrorEvent;
 use codex_core::protocol::TaskCompleteEvent;
+use codex_core::protocol::TaskRecoveredIncompleteEvent;
+use codex_core::protocol::TaskRevertedEvent;
 use codex_core::protocol::TurnAbortReason;
 use codex_core::protocol::TurnDiffEvent;
 use codex_core::protocol::WebSearchBeginEvent;
@@ -167,10 +169,15 @@ impl EventProcessor for EventProcessorWithHumanOutput {
             EventMsg::StreamError(StreamErrorEvent { message }) => {
                 ts_msg!(self, "{}", message.style(self.dimmed));
             }
+            EventMsg::TaskRecoveredIncomplete(TaskRecoveredIncompleteEvent { message, .. }) => {
+                ts_msg!(self, "{}", message.style(self.dimmed));
+            }
             EventMsg::TaskStarted(_) => {
                 // Ignore.
             }
-            EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }) => {
+            EventMsg::TaskComplete(TaskCompleteEvent {
+                last_agent_message, ..
+            }) => {
                 let last_message = last_agent_message.as_deref();
                 if let Some(output_file) = self.last_message_path.as_deref() {
                     handle_last_message(last_message, output_file);
@@ -411,6 +418,31 @@ impl EventProcessor for EventProcessorWithHumanOutput {
                 );
                 eprintln!("{unified_diff}");
             }
+            EventMsg::TaskReverted(TaskRevertedEvent {
+                sub_id,
+                reverted,
+                restored,
+                conflicts,
+            }) => {
+                if reverted {
+                    ts_msg!(
+                        self,
+                        "{} task {sub_id}: restored {} file(s)",
+                        "reverted".style(self.magenta),
+                        restored.len()
+                    );
+                } else {
+                    ts_msg!(
+                        self,
+                        "{} task {sub_id}: {} file(s) changed since it ran",
+                        "revert refused".style(self.red),
+                        conflicts.len()
+                    );
+                }
+                for conflict in conflicts {
+                    ts_msg!(self, "  {} {:?}", conflict.path.display(), conflict.reason);
+                }
+            }
             EventMsg::ExecApprovalRequest(_) => {
                 // Should we exit?
             }
@@ -508,9 +540,29 @@ impl EventProcessor for EventProcessorWithHumanOutput {
                 TurnAbortReason::ReviewEnded => {
                     ts_msg!(self, "task aborted: review ended");
                 }
+                TurnAbortReason::TelemetryFailure => {
+                    ts_msg!(self, "task aborted: lifecycle telemetry was lost");
+                }
+                TurnAbortReason::Timeout => {
+                    ts_msg!(self, "task aborted: ran past its deadline");
+                }
+                TurnAbortReason::UserRequested => {
+                    ts_msg!(self, "task cancelled at the user's request");
+                }
+                TurnAbortReason::ApprovalTimeout => {
+                    ts_msg!(
+                        self,
+                        "task aborted: review rejected after an approval timed out"
+                    );
+                }
             },
+            EventMsg::ConversationEnded(_) => {}
             EventMsg::ShutdownComplete => return CodexStatus::Shutdown,
             EventMsg::ConversationPath(_) => {}
+            EventMsg::Digest(_) => {}
+            EventMsg::TurnExported(_) => {}
+            EventMsg::TelemetryConfigRejected(_) => {}
+            EventMsg::RecentEvents(_) => {}
             EventMsg::UserMessage(_) => {}
             EventMsg::EnteredReviewMode(_) => {}
             EventMsg::ExitedReviewMode(_) => {}
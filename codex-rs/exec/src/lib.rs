@@ -25,6 +25,8 @@ use codex_core::protocol::InputItem;
 use codex_core::protocol::Op;
 use codex_core::protocol::SessionSource;
 use codex_core::protocol::TaskCompleteEvent;
+use codex_core::protocol::TurnAbortReason;
+use codex_core::protocol::TurnAbortedEvent;
 use codex_ollama::DEFAULT_OSS_MODEL;
 use codex_protocol::config_types::SandboxMode;
 use event_processor_with_human_output::EventProcessorWithHumanOutput;
@@ -283,11 +285,11 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
                 tokio::select! {
                     _ = tokio::signal::ctrl_c() => {
                         tracing::debug!("Keyboard interrupt");
-                        // Immediately notify Codex to abort any in‑flight task.
-                        conversation.submit(Op::Interrupt).await.ok();
+                        // Immediately ask Codex to abort any in‑flight task.
+                        conversation.request_abort().await;
 
                         // Exit the inner loop and return to the main input prompt. The codex
-                        // will emit a `TurnInterrupted` (Error) event which is drained later.
+                        // will emit a `TurnAborted` event which is drained later.
                         break;
                     }
                     res = conversation.next_event() => match res {
@@ -320,7 +322,13 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
             .into_iter()
             .map(|path| InputItem::LocalImage { path })
             .collect();
-        let initial_images_event_id = conversation.submit(Op::UserInput { items }).await?;
+        let initial_images_event_id = conversation
+            .submit(Op::UserInput {
+                items,
+                client_context: None,
+                replay_of: None,
+            })
+            .await?;
         info!("Sent images with event ID: {initial_images_event_id}");
         while let Ok(event) = conversation.next_event().await {
             if event.id == initial_images_event_id
@@ -328,6 +336,7 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
                     event.msg,
                     EventMsg::TaskComplete(TaskCompleteEvent {
                         last_agent_message: _,
+                        ..
                     })
                 )
             {
@@ -348,6 +357,8 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
             effort: default_effort,
             summary: default_summary,
             final_output_json_schema: output_schema,
+            client_context: None,
+            replay_of: None,
         })
         .await?;
     info!("Sent prompt with event ID: {initial_prompt_task_id}");
@@ -360,7 +371,23 @@ pub async fn run_main(cli: Cli, codex_linux_sandbox_exe: Option<PathBuf>) -> any
         if matches!(event.msg, EventMsg::Error(_)) {
             error_seen = true;
         }
+        // Strict telemetry stops the run once the audit stream is incomplete,
+        // and a task past its deadline or rejected for want of an approval
+        // never completes.
+        let aborted_for_good = matches!(
+            &event.msg,
+            EventMsg::TurnAborted(TurnAbortedEvent {
+                reason: TurnAbortReason::TelemetryFailure
+                    | TurnAbortReason::Timeout
+                    | TurnAbortReason::ApprovalTimeout,
+            })
+        );
         let shutdown: CodexStatus = event_processor.process_event(event);
+        if aborted_for_good {
+            error_seen = true;
+            conversation.submit(Op::Shutdown).await?;
+            continue;
+        }
         match shutdown {
             CodexStatus::Running => continue,
             CodexStatus::InitiateShutdown => {
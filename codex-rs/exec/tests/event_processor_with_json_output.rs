@@ -203,6 +203,7 @@ fn plan_update_emits_todo_list_started_updated_and_completed() {
         "p3",
         EventMsg::TaskComplete(codex_core::protocol::TaskCompleteEvent {
             last_agent_message: None,
+            error: None,
         }),
     );
     let out_complete = ep.collect_thread_events(&complete);
@@ -356,6 +357,7 @@ fn plan_update_after_complete_starts_new_todo_list_with_new_id() {
         "t2",
         EventMsg::TaskComplete(codex_core::protocol::TaskCompleteEvent {
             last_agent_message: None,
+            error: None,
         }),
     );
     let _ = ep.collect_thread_events(&complete);
@@ -482,6 +484,7 @@ fn error_followed_by_task_complete_produces_turn_failed() {
         "e2",
         EventMsg::TaskComplete(codex_core::protocol::TaskCompleteEvent {
             last_agent_message: None,
+            error: None,
         }),
     );
     assert_eq!(
@@ -801,6 +804,7 @@ fn task_complete_produces_turn_completed_with_usage() {
         "e2",
         EventMsg::TaskComplete(codex_core::protocol::TaskCompleteEvent {
             last_agent_message: Some("done".to_string()),
+            error: None,
         }),
     );
     let out = ep.collect_thread_events(&complete_event);
@@ -6,6 +6,8 @@
 //!   2. Sending MCP requests and pairing them with their corresponding
 //!      responses.
 //!   3. Offering a convenience helper for the common `tools/list` request.
+//!   4. Routing `notifications/progress` to the caller of the request that
+//!      asked for them.
 //!
 //! The crate hides all JSON‐RPC framing details behind a typed API. Users
 //! interact with the [`ModelContextProtocolRequest`] trait from `mcp-types` to
@@ -36,9 +38,13 @@ use mcp_types::ListToolsRequestParams;
 use mcp_types::ListToolsResult;
 use mcp_types::ModelContextProtocolNotification;
 use mcp_types::ModelContextProtocolRequest;
+use mcp_types::ProgressNotification;
+use mcp_types::ProgressNotificationParams;
+use mcp_types::ProgressToken;
 use mcp_types::RequestId;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use serde_json::json;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
@@ -59,6 +65,11 @@ const CHANNEL_CAPACITY: usize = 128;
 /// Internal representation of a pending request sender.
 type PendingSender = oneshot::Sender<JSONRPCMessage>;
 
+/// Receives the progress notifications of one request.
+pub type ProgressSender = mpsc::UnboundedSender<ProgressNotificationParams>;
+
+type ProgressMap = Arc<Mutex<HashMap<ProgressToken, ProgressSender>>>;
+
 /// A running MCP client instance.
 pub struct McpClient {
     /// Retain this child process until the client is dropped. The Tokio runtime
@@ -74,6 +85,10 @@ pub struct McpClient {
     /// to the originating caller.
     pending: Arc<Mutex<HashMap<i64, PendingSender>>>,
 
+    /// Map of `progressToken -> sender` for in-flight requests that asked for
+    /// progress notifications.
+    progress: ProgressMap,
+
     /// Monotonically increasing counter used to generate request IDs.
     id_counter: AtomicI64,
 }
@@ -111,6 +126,7 @@ impl McpClient {
 
         let (outgoing_tx, mut outgoing_rx) = mpsc::channel::<JSONRPCMessage>(CHANNEL_CAPACITY);
         let pending: Arc<Mutex<HashMap<i64, PendingSender>>> = Arc::new(Mutex::new(HashMap::new()));
+        let progress: ProgressMap = Arc::new(Mutex::new(HashMap::new()));
 
         // Spawn writer task. It listens on the `outgoing_rx` channel and
         // writes messages to the child's STDIN.
@@ -141,6 +157,7 @@ impl McpClient {
         // STDOUT and dispatches responses to the pending map.
         let reader_handle = {
             let pending = pending.clone();
+            let progress = progress.clone();
             let mut lines = BufReader::new(stdout).lines();
 
             tokio::spawn(async move {
@@ -153,8 +170,13 @@ impl McpClient {
                         Ok(JSONRPCMessage::Error(err)) => {
                             Self::dispatch_error(err, &pending).await;
                         }
+                        Ok(JSONRPCMessage::Notification(notification))
+                            if notification.method == ProgressNotification::METHOD =>
+                        {
+                            Self::dispatch_progress(notification, &progress).await;
+                        }
                         Ok(JSONRPCMessage::Notification(JSONRPCNotification { .. })) => {
-                            // For now we only log server-initiated notifications.
+                            // Other server-initiated notifications are only logged.
                             info!("<- notification: {}", line);
                         }
                         Ok(other) => {
@@ -180,6 +202,7 @@ impl McpClient {
             child,
             outgoing_tx,
             pending,
+            progress,
             id_counter: AtomicI64::new(1),
         })
     }
@@ -194,6 +217,39 @@ impl McpClient {
         params: R::Params,
         timeout: Option<Duration>,
     ) -> Result<R::Result>
+    where
+        R: ModelContextProtocolRequest,
+        R::Params: Serialize,
+        R::Result: DeserializeOwned,
+    {
+        self.send_request_inner::<R>(params, timeout, None).await
+    }
+
+    /// Like [`send_request`](Self::send_request), but asks the server for
+    /// progress notifications and forwards them to `progress` until the
+    /// request completes. The sender is dropped once it does, so the
+    /// receiving end sees the channel close.
+    pub async fn send_request_with_progress<R>(
+        &self,
+        params: R::Params,
+        timeout: Option<Duration>,
+        progress: ProgressSender,
+    ) -> Result<R::Result>
+    where
+        R: ModelContextProtocolRequest,
+        R::Params: Serialize,
+        R::Result: DeserializeOwned,
+    {
+        self.send_request_inner::<R>(params, timeout, Some(progress))
+            .await
+    }
+
+    async fn send_request_inner<R>(
+        &self,
+        params: R::Params,
+        timeout: Option<Duration>,
+        progress: Option<ProgressSender>,
+    ) -> Result<R::Result>
     where
         R: ModelContextProtocolRequest,
         R::Params: Serialize,
@@ -205,13 +261,50 @@ impl McpClient {
 
         // Serialize params -> JSON. For many request types `Params` is
         // `Option<T>` and `None` should be encoded as *absence* of the field.
-        let params_json = serde_json::to_value(&params)?;
+        let mut params_json = serde_json::to_value(&params)?;
+        let progress_token = match progress {
+            Some(progress) => {
+                // The request id doubles as the progress token; it is unique
+                // among in-flight requests.
+                let token = ProgressToken::Integer(id);
+                let serde_json::Value::Object(object) = &mut params_json else {
+                    return Err(anyhow!(
+                        "progress notifications require object params for `{}`",
+                        R::METHOD
+                    ));
+                };
+                object.insert("_meta".to_string(), json!({ "progressToken": id }));
+                self.progress.lock().await.insert(token.clone(), progress);
+                Some(token)
+            }
+            None => None,
+        };
         let params_field = if params_json.is_null() {
             None
         } else {
             Some(params_json)
         };
+        let result = self
+            .exchange::<R>(id, request_id, params_field, timeout)
+            .await;
+        if let Some(token) = progress_token {
+            self.progress.lock().await.remove(&token);
+        }
+        result
+    }
 
+    /// Send one request and await its typed result.
+    async fn exchange<R>(
+        &self,
+        id: i64,
+        request_id: RequestId,
+        params_field: Option<serde_json::Value>,
+        timeout: Option<Duration>,
+    ) -> Result<R::Result>
+    where
+        R: ModelContextProtocolRequest,
+        R::Result: DeserializeOwned,
+    {
         let jsonrpc_request = JSONRPCRequest {
             id: request_id.clone(),
             jsonrpc: JSONRPC_VERSION.to_string(),
@@ -345,6 +438,20 @@ impl McpClient {
         self.send_request::<CallToolRequest>(params, timeout).await
     }
 
+    /// `tools/call` with progress notifications forwarded to `progress`.
+    pub async fn call_tool_with_progress(
+        &self,
+        name: String,
+        arguments: Option<serde_json::Value>,
+        timeout: Option<Duration>,
+        progress: ProgressSender,
+    ) -> Result<mcp_types::CallToolResult> {
+        let params = CallToolRequestParams { name, arguments };
+        debug!("MCP tool call with progress: {params:?}");
+        self.send_request_with_progress::<CallToolRequest>(params, timeout, progress)
+            .await
+    }
+
     /// Internal helper: route a JSON-RPC *response* object to the pending map.
     async fn dispatch_response(
         resp: JSONRPCResponse,
@@ -372,6 +479,36 @@ impl McpClient {
         }
     }
 
+    /// Internal helper: route a progress notification to the request that
+    /// asked for it.
+    async fn dispatch_progress(notification: JSONRPCNotification, progress: &ProgressMap) {
+        let params = match notification
+            .params
+            .map(serde_json::from_value::<ProgressNotificationParams>)
+        {
+            Some(Ok(params)) => params,
+            Some(Err(e)) => {
+                warn!("malformed progress notification: {e}");
+                return;
+            }
+            None => {
+                warn!("progress notification without params");
+                return;
+            }
+        };
+        let guard = progress.lock().await;
+        match guard.get(&params.progress_token) {
+            // Ignore send errors – the receiver might have been dropped.
+            Some(tx) => {
+                let _ = tx.send(params);
+            }
+            None => debug!(
+                "no in-flight request for progress token {:?}",
+                params.progress_token
+            ),
+        }
+    }
+
     /// Internal helper: route a JSON-RPC *error* object to the pending map.
     async fn dispatch_error(
         err: mcp_types::JSONRPCError,
@@ -461,6 +598,7 @@ fn create_env_for_mcp_server(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pretty_assertions::assert_eq;
 
     #[test]
     fn test_create_env_for_mcp_server() {
@@ -472,4 +610,56 @@ mod tests {
         assert!(mcp_server_env.contains_key("PATH"));
         assert_eq!(Some(&env_var_new_value), mcp_server_env.get(env_var));
     }
+
+    /// A server that answers the first request (id 1) after reporting
+    /// progress twice for it and once for an unknown token.
+    #[cfg(unix)]
+    const PROGRESS_SERVER: &str = r#"read -r request
+echo "$request" | grep -q '"_meta":{"progressToken":1}' || exit 1
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":1,"progress":1,"total":2}}'
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":7,"progress":1}}'
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":1,"progress":2,"total":2,"message":"done"}}'
+echo '{"jsonrpc":"2.0","id":1,"result":{"content":[]}}'
+read -r _"#;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn progress_notifications_reach_the_requesting_call() {
+        let client = McpClient::new_stdio_client(
+            "sh".into(),
+            vec!["-c".into(), PROGRESS_SERVER.into()],
+            None,
+        )
+        .await
+        .expect("spawn server");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let result = client
+            .call_tool_with_progress("build".to_string(), None, Some(Duration::from_secs(10)), tx)
+            .await
+            .expect("call tool");
+
+        assert_eq!(result.content, Vec::new());
+        let mut received = Vec::new();
+        while let Some(params) = rx.recv().await {
+            received.push(params);
+        }
+        assert_eq!(
+            received,
+            vec![
+                ProgressNotificationParams {
+                    message: None,
+                    progress: 1.0,
+                    progress_token: ProgressToken::Integer(1),
+                    total: Some(2.0),
+                },
+                ProgressNotificationParams {
+                    message: Some("done".to_string()),
+                    progress: 2.0,
+                    progress_token: ProgressToken::Integer(1),
+                    total: Some(2.0),
+                },
+            ]
+        );
+    }
 }
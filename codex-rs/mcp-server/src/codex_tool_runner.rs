@@ -90,11 +90,9 @@ pub async fn run_codex_tool_session(
         .insert(id.clone(), conversation_id);
     let submission = Submission {
         id: sub_id.clone(),
-        op: Op::UserInput {
-            items: vec![InputItem::Text {
-                text: initial_prompt.clone(),
-            }],
-        },
+        op: Op::user_input(vec![InputItem::Text {
+            text: initial_prompt.clone(),
+        }]),
     };
 
     if let Err(e) = conversation.submit_with_id(submission).await {
@@ -126,9 +124,7 @@ pub async fn run_codex_tool_session_reply(
         .await
         .insert(request_id.clone(), conversation_id);
     if let Err(e) = conversation
-        .submit(Op::UserInput {
-            items: vec![InputItem::Text { text: prompt }],
-        })
+        .submit(Op::user_input(vec![InputItem::Text { text: prompt }]))
         .await
     {
         tracing::error!("Failed to submit user input: {e}");
@@ -220,7 +216,9 @@ async fn run_codex_tool_session_inner(
                         .await;
                         continue;
                     }
-                    EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }) => {
+                    EventMsg::TaskComplete(TaskCompleteEvent {
+                        last_agent_message, ..
+                    }) => {
                         let text = match last_agent_message {
                             Some(msg) => msg,
                             None => "".to_string(),
@@ -272,6 +270,12 @@ async fn run_codex_tool_session_inner(
                     | EventMsg::PatchApplyBegin(_)
                     | EventMsg::PatchApplyEnd(_)
                     | EventMsg::TurnDiff(_)
+                    | EventMsg::TaskReverted(_)
+                    | EventMsg::Digest(_)
+                    | EventMsg::TurnExported(_)
+                    | EventMsg::TaskRecoveredIncomplete(_)
+                    | EventMsg::TelemetryConfigRejected(_)
+                    | EventMsg::RecentEvents(_)
                     | EventMsg::WebSearchBegin(_)
                     | EventMsg::WebSearchEnd(_)
                     | EventMsg::GetHistoryEntryResponse(_)
@@ -279,6 +283,7 @@ async fn run_codex_tool_session_inner(
                     | EventMsg::TurnAborted(_)
                     | EventMsg::ConversationPath(_)
                     | EventMsg::UserMessage(_)
+                    | EventMsg::ConversationEnded(_)
                     | EventMsg::ShutdownComplete
                     | EventMsg::ViewImageToolCall(_)
                     | EventMsg::EnteredReviewMode(_)
@@ -3,6 +3,7 @@
 //! Uses a SQ (Submission Queue) / EQ (Event Queue) pattern to asynchronously communicate
 //! between user and agent.
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt;
 use std::path::Path;
@@ -27,6 +28,7 @@ use serde::Serialize;
 use serde_json::Value;
 use serde_with::serde_as;
 use strum_macros::Display;
+use strum_macros::EnumDiscriminants;
 use ts_rs::TS;
 
 /// Open/close tags for special user-input blocks. Used across crates to avoid
@@ -60,6 +62,15 @@ pub enum Op {
     UserInput {
         /// User input items, see `InputItem`
         items: Vec<InputItem>,
+
+        /// The submitting client and its display constraints, for telemetry.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_context: Option<ClientContext>,
+
+        /// Submission id of an earlier turn this one deliberately replays.
+        /// The replay reuses that turn's sampling seed when it is known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replay_of: Option<String>,
     },
 
     /// Similar to [`Op::UserInput`], but contains additional context required
@@ -90,6 +101,15 @@ pub enum Op {
         summary: ReasoningSummaryConfig,
         // The JSON schema to use for the final assistant message
         final_output_json_schema: Option<Value>,
+
+        /// The submitting client and its display constraints, for telemetry.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_context: Option<ClientContext>,
+
+        /// Submission id of an earlier turn this one deliberately replays.
+        /// The replay reuses that turn's sampling seed when it is known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replay_of: Option<String>,
     },
 
     /// Override parts of the persistent turn context for subsequent turns.
@@ -129,7 +149,9 @@ pub enum Op {
 
     /// Approve a command execution
     ExecApproval {
-        /// The id of the submission we are approving
+        /// The `call_id` of the request we are answering, or the id of the
+        /// submission it was sent for, which answers that submission's
+        /// oldest outstanding request.
         id: String,
         /// The user's decision in response to the request.
         decision: ReviewDecision,
@@ -137,7 +159,9 @@ pub enum Op {
 
     /// Approve a code patch
     PatchApproval {
-        /// The id of the submission we are approving
+        /// The `call_id` of the request we are answering, or the id of the
+        /// submission it was sent for, which answers that submission's
+        /// oldest outstanding request.
         id: String,
         /// The user's decision in response to the request.
         decision: ReviewDecision,
@@ -172,12 +196,216 @@ pub enum Op {
     Compact,
 
     /// Request a code review from the agent.
-    Review { review_request: ReviewRequest },
+    Review {
+        review_request: ReviewRequest,
+        /// The diff the review covers. Only reviews that name one are
+        /// sliced, over the files that diff changes.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<ReviewTarget>,
+        /// Review the next slice of a diff too large for one review,
+        /// starting where the previous sliced review stopped.
+        #[serde(default, rename = "continue")]
+        continue_from_cursor: bool,
+    },
+
+    /// Mark the session as paused (for example, while the user is away) or
+    /// resumed. Approval request timeouts do not count down while paused.
+    SetPaused { paused: bool },
+
+    /// Write a standalone HTML report of this session's task timeline to
+    /// `path`, alongside any running task. Reply is delivered via
+    /// `EventMsg::BackgroundEvent`, or `EventMsg::Error` if the file could
+    /// not be written.
+    WriteTimelineReport { path: PathBuf },
+
+    /// Restore the files changed by the finished task `sub_id` to their
+    /// contents from before it ran. Refused as a whole if any of those files
+    /// changed afterwards, unless `force` is set, in which case only the
+    /// untouched files are restored. Reply is delivered via
+    /// `EventMsg::TaskReverted`.
+    RevertTask {
+        sub_id: String,
+        #[serde(default)]
+        force: bool,
+    },
+
+    /// Summarize what happened after a cursor, for a client that reconnects
+    /// and does not want a full replay: activity after visualizer event
+    /// `since_sequence` and after `since_time_ms` (milliseconds since the
+    /// Unix epoch). With neither set, covers the whole session. Reply is
+    /// delivered via `EventMsg::Digest`.
+    GetDigest {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since_sequence: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since_time_ms: Option<u64>,
+    },
+
+    /// Look up the digest turn of the completed task `sub_id`. Reply is
+    /// delivered via `EventMsg::TurnExported`, or `EventMsg::Error` if the
+    /// session has no such turn or only kept it in an hourly aggregate.
+    ExportTurn { sub_id: String },
+
+    /// Settle the task a resumed session found cut short, as reported by
+    /// `EventMsg::TaskRecoveredIncomplete`: drop it, or start a task that
+    /// continues it, seeded with what it had already done. Replies with
+    /// `EventMsg::Error` if there is no such task.
+    ResolveRecoveredTask { action: RecoveredTaskAction },
+
+    /// Mark the timeline with a note from a human reviewer, optionally
+    /// attached to task `sub_id`. Stored in the rollout and reported by
+    /// `Op::GetDigest` and `Op::WriteTimelineReport`. Replies with
+    /// `EventMsg::Error` if the text or a tag is empty or too long.
+    Annotate {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sub_id: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+
+    /// Replace the visualizer's filtering, sampling, redaction and
+    /// truncation rules with those in `patch`, keeping the relay connection
+    /// and sequence numbers. Keys missing from `patch` keep their value.
+    /// Replies with `EventMsg::TelemetryConfigRejected`, and changes
+    /// nothing, if any key is invalid.
+    UpdateTelemetryConfig { patch: TelemetryConfigPatch },
+
+    /// Look up events in the visualizer's in-memory ring of recent events,
+    /// redacted as for a remote relay: file contents from outside the
+    /// trusted roots are omitted, and coarse telemetry applies.
+    /// Reply is delivered via `EventMsg::RecentEvents`.
+    QueryRecentEvents { query: EventQuery },
+
+    /// Forget the visualizer's trust-on-first-use pin for the relay at
+    /// `host_port` (e.g. `relay.example.com:443`), so the next connection
+    /// to it pins whichever key it presents. A session whose visualizer
+    /// already stopped on a mismatch stays stopped. Reply is delivered via
+    /// `EventMsg::BackgroundEvent`, or `EventMsg::Error` if the pins file
+    /// could not be updated.
+    ClearVisualizerPin { host_port: String },
+
+    /// Declare which `paths` the work from now on concerns, under `label`,
+    /// replacing any earlier focus. Relative paths are resolved against the
+    /// session's working directory. Tasks spawned afterwards report the
+    /// label in `task_spawned` and in their digest turn, and their patches
+    /// touching files outside the paths emit a `focus_drift` visualizer
+    /// event; such patches are still applied. Reply is delivered via
+    /// `EventMsg::BackgroundEvent`, or `EventMsg::Error` if the label is
+    /// empty or no paths are given.
+    SetFocus { label: String, paths: Vec<PathBuf> },
+
+    /// Clear the focus set with `Op::SetFocus`. Reply is delivered via
+    /// `EventMsg::BackgroundEvent`.
+    ClearFocus,
+
+    /// Render the workspace turn template `name` with `params` and spawn
+    /// the result as a new task, replacing any running one. The template
+    /// and the params it was rendered with are reported in `task_spawned`
+    /// and in the task's digest turn. An unknown template, or params that
+    /// are missing or not declared by it, is answered with
+    /// `EventMsg::Error` and a `template_rejected` visualizer event.
+    SpawnTemplate {
+        name: String,
+        #[serde(default)]
+        params: BTreeMap<String, String>,
+
+        /// The submitting client and its display constraints, for telemetry.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_context: Option<ClientContext>,
+    },
 
     /// Request to shut down codex instance.
     Shutdown,
 }
 
+impl Op {
+    /// Returns an [`Op::UserInput`] without client context that replays no
+    /// earlier turn.
+    pub fn user_input(items: Vec<InputItem>) -> Self {
+        Op::UserInput {
+            items,
+            client_context: None,
+            replay_of: None,
+        }
+    }
+
+    /// Returns an [`Op::UserTurn`] without an output schema or client
+    /// context that replays no earlier turn.
+    pub fn user_turn(
+        items: Vec<InputItem>,
+        cwd: PathBuf,
+        approval_policy: AskForApproval,
+        sandbox_policy: SandboxPolicy,
+        model: String,
+        effort: Option<ReasoningEffortConfig>,
+        summary: ReasoningSummaryConfig,
+    ) -> Self {
+        Op::UserTurn {
+            items,
+            cwd,
+            approval_policy,
+            sandbox_policy,
+            model,
+            effort,
+            summary,
+            final_output_json_schema: None,
+            client_context: None,
+            replay_of: None,
+        }
+    }
+}
+
+/// Visualizer rules to replace with `Op::UpdateTelemetryConfig`; each
+/// present key replaces the current value as a whole. Task lifecycle,
+/// approval and patch events are never filtered out or sampled.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, TS)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetryConfigPatch {
+    /// Action types that are not emitted at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_action_types: Option<Vec<String>>,
+    /// Fraction of the events of an action type that are emitted, in
+    /// `(0, 1]`; types not listed are emitted in full.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rates: Option<HashMap<String, f64>>,
+    /// Regular expressions whose matches in event payloads are replaced
+    /// with `[REDACTED]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redact_patterns: Option<Vec<String>>,
+    /// Longest string kept in event payloads, in characters; `0` keeps
+    /// strings whole.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_string_chars: Option<usize>,
+}
+
+/// Filter for `Op::QueryRecentEvents`; an event must match every key that
+/// is set.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, TS)]
+#[serde(deny_unknown_fields)]
+pub struct EventQuery {
+    /// Action types to include; all of them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_types: Option<Vec<String>>,
+    /// Only events of this task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_id: Option<String>,
+    /// Only events after the one with this sequence number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(type = "number | null")]
+    pub since_sequence: Option<u64>,
+    /// At most this many of the newest matching events; `0` returns all.
+    #[serde(default)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveredTaskAction {
+    Abandon,
+    Continue,
+}
+
 /// Determines the conditions under which the user is consulted to approve
 /// running the command proposed by Codex.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Display, TS)]
@@ -407,6 +635,33 @@ pub enum InputItem {
     },
 }
 
+/// Identifies the client that submitted a turn and what it can display.
+/// Only used for telemetry; it never changes how the turn runs.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ClientContext {
+    /// e.g. `codex-tui`.
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal_cols: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal_rows: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_depth: Option<ColorDepth>,
+    /// Whether the client can render images inline.
+    #[serde(default)]
+    pub supports_images: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorDepth {
+    Monochrome,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
 /// Event Queue Entry - events from agent
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Event {
@@ -418,9 +673,12 @@ pub struct Event {
 
 /// Response event from the agent
 /// NOTE: Make sure none of these values have optional types, as it will mess up the extension code-gen.
-#[derive(Debug, Clone, Deserialize, Serialize, Display, TS)]
+///
+/// `EventDiscriminant` names each variant without its payload, for filtering.
+#[derive(Debug, Clone, Deserialize, Serialize, Display, TS, EnumDiscriminants)]
 #[serde(tag = "type", rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
+#[strum_discriminants(name(EventDiscriminant), derive(Hash))]
 pub enum EventMsg {
     /// Error while executing a submission
     Error(ErrorEvent),
@@ -499,6 +757,25 @@ pub enum EventMsg {
 
     TurnDiff(TurnDiffEvent),
 
+    /// Result of `Op::RevertTask`.
+    TaskReverted(TaskRevertedEvent),
+
+    /// Result of `Op::GetDigest`.
+    Digest(DigestEvent),
+
+    /// Result of `Op::ExportTurn`.
+    TurnExported(DigestTurn),
+
+    /// A resumed session found a task that never finished, e.g. because the
+    /// process crashed mid-turn. Settle it with `Op::ResolveRecoveredTask`.
+    TaskRecoveredIncomplete(TaskRecoveredIncompleteEvent),
+
+    /// `Op::UpdateTelemetryConfig` was rejected; the previous rules stay.
+    TelemetryConfigRejected(TelemetryConfigRejectedEvent),
+
+    /// Result of `Op::QueryRecentEvents`.
+    RecentEvents(RecentEventsEvent),
+
     /// Response to GetHistoryEntryRequest.
     GetHistoryEntryResponse(GetHistoryEntryResponseEvent),
 
@@ -512,6 +789,10 @@ pub enum EventMsg {
 
     TurnAborted(TurnAbortedEvent),
 
+    /// The conversation ended, sent before `ShutdownComplete` on an orderly
+    /// exit so clients can tell a clean exit from a crash.
+    ConversationEnded(ConversationEndedEvent),
+
     /// Notification that the agent is shutting down.
     ShutdownComplete,
 
@@ -539,6 +820,51 @@ pub struct ErrorEvent {
 #[derive(Debug, Clone, Deserialize, Serialize, TS)]
 pub struct TaskCompleteEvent {
     pub last_agent_message: Option<String>,
+    /// Set when the task ended on an error instead of finishing its work.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<TaskError>,
+}
+
+/// The error a task ended on, classified so a client can decide whether to
+/// offer a retry, a smaller context, or a credentials check.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, TS)]
+pub struct TaskError {
+    pub kind: TaskErrorKind,
+    /// Whether running the task again as is may succeed.
+    pub retryable: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskErrorKind {
+    /// Credentials are missing, invalid, or not entitled to the model.
+    ProviderAuth,
+    /// The provider is throttling requests or a usage limit was reached.
+    RateLimited,
+    /// The conversation no longer fits in the model's context window.
+    ContextOverflow,
+    /// The response stream broke off or the provider was briefly unavailable.
+    StreamInterrupted,
+    /// The sandbox refused or could not enforce a command.
+    SandboxDenied,
+    /// A tool process failed to start, timed out, or was killed.
+    ToolFailure,
+    /// An approval the task could not go on without went unanswered for
+    /// longer than the task would wait.
+    ApprovalTimeout,
+    /// The task ran past its deadline and was aborted.
+    Timeout,
+    /// Anything else, including errors not classified yet.
+    Internal,
+}
+
+impl TaskErrorKind {
+    /// Kinds where running the task again unchanged may succeed; only these
+    /// are retried automatically.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, Self::RateLimited | Self::StreamInterrupted)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, TS)]
@@ -546,7 +872,7 @@ pub struct TaskStartedEvent {
     pub model_context_window: Option<u64>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default, TS)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq, TS)]
 pub struct TokenUsage {
     #[ts(type = "number")]
     pub input_tokens: u64,
@@ -965,6 +1291,14 @@ pub enum SessionSource {
     Unknown,
 }
 
+/// Rollout format written by this build. Files from before the format was
+/// versioned have no `format_version` and read as `0`.
+///
+/// 1: adds [`RolloutItem::ReviewFindings`].
+/// 2: adds [`RolloutItem::TaskCheckpoint`].
+/// 3: adds [`RolloutItem::Annotation`].
+pub const ROLLOUT_FORMAT_VERSION: u32 = 3;
+
 #[derive(Serialize, Deserialize, Clone, Debug, TS)]
 pub struct SessionMeta {
     pub id: ConversationId,
@@ -975,6 +1309,17 @@ pub struct SessionMeta {
     pub instructions: Option<String>,
     #[serde(default)]
     pub source: SessionSource,
+    /// See [`ROLLOUT_FORMAT_VERSION`].
+    #[serde(default, skip_serializing_if = "is_unversioned")]
+    pub format_version: u32,
+}
+
+#[allow(
+    clippy::trivially_copy_pass_by_ref,
+    reason = "serde's skip_serializing_if passes the field by reference."
+)]
+fn is_unversioned(format_version: &u32) -> bool {
+    *format_version == 0
 }
 
 impl Default for SessionMeta {
@@ -987,6 +1332,7 @@ impl Default for SessionMeta {
             cli_version: String::new(),
             instructions: None,
             source: SessionSource::default(),
+            format_version: ROLLOUT_FORMAT_VERSION,
         }
     }
 }
@@ -1007,6 +1353,9 @@ pub enum RolloutItem {
     Compacted(CompactedItem),
     TurnContext(TurnContextItem),
     EventMsg(EventMsg),
+    ReviewFindings(ReviewFindingsItem),
+    TaskCheckpoint(TaskCheckpointItem),
+    Annotation(Annotation),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, TS)]
@@ -1014,6 +1363,49 @@ pub struct CompactedItem {
     pub message: String,
 }
 
+/// Findings of a completed review, restored into the session on resume.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+pub struct ReviewFindingsItem {
+    pub findings: Vec<ReviewFinding>,
+}
+
+/// A note added with `Op::Annotate`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+pub struct Annotation {
+    pub text: String,
+    pub sub_id: Option<String>,
+    pub tags: Vec<String>,
+    /// Milliseconds since the Unix epoch; not a `u128`, which rollout lines
+    /// could not be read back with.
+    #[ts(type = "number")]
+    pub at_ms: u64,
+}
+
+/// Progress of a task, written as it happens so that a session resumed
+/// after a crash can tell which task was cut short and how far it got.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+pub struct TaskCheckpointItem {
+    pub sub_id: String,
+    pub checkpoint: TaskCheckpoint,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskCheckpoint {
+    Started,
+    PatchApplied {
+        call_id: String,
+        paths: Vec<PathBuf>,
+    },
+    ExecCompleted {
+        call_id: String,
+        command: Vec<String>,
+        exit_code: i32,
+    },
+    /// Completed or aborted.
+    Finished,
+}
+
 impl From<CompactedItem> for ResponseItem {
     fn from(value: CompactedItem) -> Self {
         ResponseItem::Message {
@@ -1064,6 +1456,19 @@ pub struct ReviewRequest {
     pub user_facing_hint: String,
 }
 
+/// The diff a review covers, which sliced reviews split by file.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReviewTarget {
+    /// Staged, unstaged and untracked changes against `HEAD`.
+    UncommittedChanges,
+    /// What the current branch would merge into `branch`, since their merge
+    /// base.
+    BaseBranch { branch: String },
+    /// The changes introduced by one commit.
+    Commit { sha: String },
+}
+
 /// Structured review result produced by a child review session.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
 pub struct ReviewOutputEvent {
@@ -1230,6 +1635,225 @@ pub struct TurnDiffEvent {
     pub unified_diff: String,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct TaskRevertedEvent {
+    pub sub_id: String,
+    /// False when nothing was restored, e.g. because `conflicts` refused the
+    /// revert.
+    pub reverted: bool,
+    /// Paths written back or removed, in the order they were restored.
+    pub restored: Vec<PathBuf>,
+    pub conflicts: Vec<RevertConflict>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct RevertConflict {
+    pub path: PathBuf,
+    pub reason: RevertConflictReason,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RevertConflictReason {
+    /// A later task also changed this file.
+    ChangedByLaterTask { sub_id: String },
+    /// The file no longer holds what the task left there.
+    ModifiedSinceTask,
+    /// Restoring would overwrite a file that now exists at the original
+    /// location of a file the task moved or deleted.
+    PathOccupied,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct ConversationEndedEvent {
+    pub reason: ConversationEndReason,
+    /// The last task spawned in the conversation, if any.
+    pub last_sub_id: Option<String>,
+    #[ts(type = "number")]
+    pub tasks_started: u64,
+    #[ts(type = "number | null")]
+    pub total_tokens: Option<u64>,
+}
+
+/// Why the conversation ended.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, TS)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConversationEndReason {
+    /// The client sent `Op::Shutdown`.
+    UserExit,
+    /// The submission channel closed without an `Op::Shutdown`, e.g. the
+    /// host dropped the conversation.
+    Shutdown,
+    /// The session loop died. How an orderly teardown went is reported
+    /// separately.
+    Error { summary: String },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestEvent {
+    /// Pass back as `since_sequence` to cover only later activity. `None`
+    /// while the session has not emitted a visualizer event yet.
+    #[ts(type = "number | null")]
+    pub through_sequence: Option<u64>,
+    /// False when activity after the cursor was already discarded; the
+    /// digest then starts at the oldest activity still kept.
+    pub complete: bool,
+    pub turns_completed: Vec<DigestTurn>,
+    /// Files changed by `turns_completed`, sorted and deduplicated.
+    pub files_changed: Vec<PathBuf>,
+    pub tokens_spent: TokenUsage,
+    pub errors: Vec<DigestError>,
+    pub annotations: Vec<Annotation>,
+    pub running_task: Option<DigestTask>,
+    /// `turns_completed`, `errors` and `annotations` of tasks that ran
+    /// under an `Op::SetFocus` focus, grouped by its label in order of
+    /// first appearance.
+    #[serde(default)]
+    pub by_focus: Vec<DigestFocus>,
+    /// Counts for older tasks, by hour, whose full turns were no longer
+    /// kept. An hour that straddles the cursor is included whole; its turns
+    /// are not in `turns_completed`, `files_changed` or `by_focus`.
+    #[serde(default)]
+    pub aggregated_turns: Vec<DigestHourAggregate>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestTurn {
+    pub sub_id: String,
+    #[ts(type = "number")]
+    pub completed_at_ms: u128,
+    pub last_agent_message: Option<String>,
+    pub files_changed: Vec<PathBuf>,
+    /// Set when the turn ended on an error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<TaskError>,
+    /// Label of the focus the turn ran under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+    /// Set when the turn was spawned with `Op::SpawnTemplate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<TurnTemplateUse>,
+}
+
+/// A turn template as rendered for `Op::SpawnTemplate`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct TurnTemplateUse {
+    pub name: String,
+    /// Every param of the template, defaults included.
+    pub params: BTreeMap<String, String>,
+}
+
+/// Tasks of one hour folded together once their full turns were evicted.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestHourAggregate {
+    /// Start of the hour, in milliseconds since the Unix epoch.
+    #[ts(type = "number")]
+    pub hour_start_ms: u128,
+    /// Completed and aborted tasks by kind, e.g. `Regular`.
+    pub tasks_by_kind: BTreeMap<String, usize>,
+    pub aborted: usize,
+    /// Time from spawn to completion or abort, summed over the tasks.
+    pub total_duration_ms: u64,
+    /// Files changed, summed over the completed turns.
+    pub files_changed: usize,
+    /// Tasks that ran under an `Op::SetFocus` focus, by its label.
+    #[serde(default)]
+    pub tasks_by_focus: BTreeMap<String, usize>,
+    /// `files_changed` of those tasks, by focus label.
+    #[serde(default)]
+    pub files_changed_by_focus: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestFocus {
+    pub focus: String,
+    pub turns: usize,
+    /// Files changed by those turns, sorted and deduplicated.
+    pub files_changed: Vec<PathBuf>,
+    /// What the session spent from the previous turn's completion to each
+    /// of these turns', summed.
+    #[serde(default)]
+    pub tokens_spent: TokenUsage,
+    /// `errors` of tasks that ran under the focus.
+    #[serde(default)]
+    pub errors: Vec<DigestError>,
+    /// `annotations` on tasks that ran under the focus.
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestError {
+    pub sub_id: String,
+    #[ts(type = "number")]
+    pub at_ms: u128,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct DigestTask {
+    pub sub_id: String,
+    /// e.g. `Regular`, `Review`, `Compact`.
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct TaskRecoveredIncompleteEvent {
+    pub sub_id: String,
+    pub message: String,
+    /// Files the task had patched successfully, in order.
+    pub patched_paths: Vec<PathBuf>,
+    pub completed_commands: Vec<RecoveredCommand>,
+    /// Tool calls the model made that never got a result.
+    pub pending_calls: Vec<RecoveredToolCall>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct RecoveredCommand {
+    pub command: Vec<String>,
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct RecoveredToolCall {
+    pub call_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct TelemetryConfigRejectedEvent {
+    pub errors: Vec<TelemetryConfigError>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct TelemetryConfigError {
+    /// The offending key of the patch, e.g. `redact_patterns[1]`.
+    pub key: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct RecentEventsEvent {
+    /// Matching events, oldest first.
+    pub events: Vec<RecentEvent>,
+}
+
+/// A visualizer event as kept in memory, before any relay-specific
+/// encoding.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, TS)]
+pub struct RecentEvent {
+    #[ts(type = "number")]
+    pub sequence: u64,
+    #[ts(type = "number")]
+    pub timestamp_ms: u128,
+    pub action_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_id: Option<String>,
+    pub action: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<Value>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, TS)]
 pub struct GetHistoryEntryResponseEvent {
     pub offset: usize,
@@ -1357,6 +1981,16 @@ pub enum TurnAbortReason {
     Interrupted,
     Replaced,
     ReviewEnded,
+    /// Strict telemetry lost a lifecycle event.
+    TelemetryFailure,
+    /// The task outlived the deadline it was spawned with.
+    Timeout,
+    /// Cancelled on the user's behalf through `Session::request_abort`,
+    /// e.g. on Ctrl-C in an embedding REPL or CLI.
+    UserRequested,
+    /// A review gave up on an approval request nobody answered within its
+    /// approval timeout, and counts as rejected.
+    ApprovalTimeout,
 }
 
 #[cfg(test)]
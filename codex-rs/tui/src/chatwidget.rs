@@ -15,6 +15,8 @@ use codex_core::protocol::AgentReasoningRawContentDeltaEvent;
 use codex_core::protocol::AgentReasoningRawContentEvent;
 use codex_core::protocol::ApplyPatchApprovalRequestEvent;
 use codex_core::protocol::BackgroundEventEvent;
+use codex_core::protocol::ClientContext;
+use codex_core::protocol::ColorDepth;
 use codex_core::protocol::ErrorEvent;
 use codex_core::protocol::Event;
 use codex_core::protocol::EventMsg;
@@ -32,8 +34,11 @@ use codex_core::protocol::Op;
 use codex_core::protocol::PatchApplyBeginEvent;
 use codex_core::protocol::RateLimitSnapshot;
 use codex_core::protocol::ReviewRequest;
+use codex_core::protocol::ReviewTarget;
 use codex_core::protocol::StreamErrorEvent;
 use codex_core::protocol::TaskCompleteEvent;
+use codex_core::protocol::TaskRecoveredIncompleteEvent;
+use codex_core::protocol::TaskRevertedEvent;
 use codex_core::protocol::TokenUsage;
 use codex_core::protocol::TokenUsageInfo;
 use codex_core::protocol::TurnAbortReason;
@@ -276,6 +281,27 @@ impl From<String> for UserMessage {
     }
 }
 
+/// Describes this client and the terminal it is drawing into, attached to
+/// submissions so turn telemetry can explain rendering reports.
+fn client_context() -> ClientContext {
+    let (terminal_cols, terminal_rows) =
+        crossterm::terminal::size().map_or((None, None), |(cols, rows)| (Some(cols), Some(rows)));
+    let color_depth = match supports_color::on_cached(supports_color::Stream::Stdout) {
+        Some(level) if level.has_16m => ColorDepth::TrueColor,
+        Some(level) if level.has_256 => ColorDepth::Ansi256,
+        Some(_) => ColorDepth::Ansi16,
+        None => ColorDepth::Monochrome,
+    };
+    ClientContext {
+        name: "codex-tui".to_string(),
+        version: crate::version::CODEX_CLI_VERSION.to_string(),
+        terminal_cols,
+        terminal_rows,
+        color_depth: Some(color_depth),
+        supports_images: false,
+    }
+}
+
 fn create_initial_user_message(text: String, image_paths: Vec<PathBuf>) -> Option<UserMessage> {
     if text.is_empty() && image_paths.is_empty() {
         None
@@ -616,6 +642,28 @@ impl ChatWidget {
         debug!("TurnDiffEvent: {unified_diff}");
     }
 
+    fn on_task_reverted(&mut self, ev: TaskRevertedEvent) {
+        let cell = if ev.reverted {
+            history_cell::new_info_event(
+                format!("Reverted task {}", ev.sub_id),
+                Some(format!("restored {} file(s)", ev.restored.len())),
+            )
+        } else {
+            let paths = ev
+                .conflicts
+                .iter()
+                .map(|conflict| conflict.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            history_cell::new_error_event(format!(
+                "Not reverting task {}: changed since it ran: {paths}",
+                ev.sub_id
+            ))
+        };
+        self.add_to_history(cell);
+        self.request_redraw();
+    }
+
     fn on_background_event(&mut self, message: String) {
         debug!("BackgroundEvent: {message}");
     }
@@ -1264,7 +1312,11 @@ impl ChatWidget {
         }
 
         self.codex_op_tx
-            .send(Op::UserInput { items })
+            .send(Op::UserInput {
+                items,
+                client_context: Some(client_context()),
+                replay_of: None,
+            })
             .unwrap_or_else(|e| {
                 tracing::error!("failed to send message: {e}");
             });
@@ -1388,16 +1440,16 @@ impl ChatWidget {
             }
             EventMsg::AgentReasoningSectionBreak(_) => self.on_reasoning_section_break(),
             EventMsg::TaskStarted(_) => self.on_task_started(),
-            EventMsg::TaskComplete(TaskCompleteEvent { last_agent_message }) => {
-                self.on_task_complete(last_agent_message)
-            }
+            EventMsg::TaskComplete(TaskCompleteEvent {
+                last_agent_message, ..
+            }) => self.on_task_complete(last_agent_message),
             EventMsg::TokenCount(ev) => {
                 self.set_token_info(ev.info);
                 self.on_rate_limit_snapshot(ev.rate_limits);
             }
             EventMsg::Error(ErrorEvent { message }) => self.on_error(message),
             EventMsg::TurnAborted(ev) => match ev.reason {
-                TurnAbortReason::Interrupted => {
+                TurnAbortReason::Interrupted | TurnAbortReason::UserRequested => {
                     self.on_interrupted_turn(ev.reason);
                 }
                 TurnAbortReason::Replaced => {
@@ -1406,6 +1458,15 @@ impl ChatWidget {
                 TurnAbortReason::ReviewEnded => {
                     self.on_interrupted_turn(ev.reason);
                 }
+                TurnAbortReason::TelemetryFailure => {
+                    self.on_error("Turn aborted: lifecycle telemetry was lost".to_owned())
+                }
+                TurnAbortReason::Timeout => {
+                    self.on_error("Turn aborted: the task ran past its deadline".to_owned())
+                }
+                TurnAbortReason::ApprovalTimeout => {
+                    self.on_error("Review rejected: an approval request went unanswered".to_owned())
+                }
             },
             EventMsg::PlanUpdate(update) => self.on_plan_update(update),
             EventMsg::ExecApprovalRequest(ev) => {
@@ -1428,8 +1489,18 @@ impl ChatWidget {
             EventMsg::GetHistoryEntryResponse(ev) => self.on_get_history_entry_response(ev),
             EventMsg::McpListToolsResponse(ev) => self.on_list_mcp_tools(ev),
             EventMsg::ListCustomPromptsResponse(ev) => self.on_list_custom_prompts(ev),
+            EventMsg::ConversationEnded(_) => {}
             EventMsg::ShutdownComplete => self.on_shutdown_complete(),
             EventMsg::TurnDiff(TurnDiffEvent { unified_diff }) => self.on_turn_diff(unified_diff),
+            EventMsg::TaskReverted(ev) => self.on_task_reverted(ev),
+            EventMsg::Digest(_) => {}
+            EventMsg::TurnExported(_) => {}
+            EventMsg::TelemetryConfigRejected(_) => {}
+            EventMsg::RecentEvents(_) => {}
+            EventMsg::TaskRecoveredIncomplete(TaskRecoveredIncompleteEvent { message, .. }) => {
+                self.add_to_history(history_cell::new_warning_event(message));
+                self.request_redraw();
+            }
             EventMsg::BackgroundEvent(BackgroundEventEvent { message }) => {
                 self.on_background_event(message)
             }
@@ -1945,6 +2016,8 @@ impl ChatWidget {
                             prompt: "Review the current code changes (staged, unstaged, and untracked files) and provide prioritized findings.".to_string(),
                             user_facing_hint: "current changes".to_string(),
                         },
+                        target: Some(ReviewTarget::UncommittedChanges),
+                        continue_from_cursor: false,
                     }));
                 },
             )],
@@ -2001,6 +2074,10 @@ impl ChatWidget {
                             ),
                             user_facing_hint: format!("changes against '{branch}'"),
                         },
+                        target: Some(ReviewTarget::BaseBranch {
+                            branch: branch.clone(),
+                        }),
+                        continue_from_cursor: false,
                     }));
                 })],
                 dismiss_on_select: true,
@@ -2041,6 +2118,8 @@ impl ChatWidget {
                             prompt,
                             user_facing_hint: hint,
                         },
+                        target: Some(ReviewTarget::Commit { sha: sha.clone() }),
+                        continue_from_cursor: false,
                     }));
                 })],
                 dismiss_on_select: true,
@@ -2075,6 +2154,8 @@ impl ChatWidget {
                         prompt: trimmed.clone(),
                         user_facing_hint: trimmed,
                     },
+                    target: None,
+                    continue_from_cursor: false,
                 }));
             }),
         );
@@ -2265,6 +2346,8 @@ pub(crate) fn show_review_commit_picker_with_entries(
                         prompt,
                         user_facing_hint: hint,
                     },
+                    target: Some(ReviewTarget::Commit { sha: sha.clone() }),
+                    continue_from_cursor: false,
                 }));
             })],
             dismiss_on_select: true,
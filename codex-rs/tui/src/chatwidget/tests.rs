@@ -830,7 +830,7 @@ fn custom_prompt_submit_sends_review_op() {
     // Expect AppEvent::CodexOp(Op::Review { .. }) with trimmed prompt
     let evt = rx.try_recv().expect("expected one app event");
     match evt {
-        AppEvent::CodexOp(Op::Review { review_request }) => {
+        AppEvent::CodexOp(Op::Review { review_request, .. }) => {
             assert_eq!(
                 review_request.prompt,
                 "please audit dependencies".to_string()
@@ -2095,6 +2095,7 @@ fn multiple_agent_messages_in_single_turn_emit_multiple_headers() {
         id: "s1".into(),
         msg: EventMsg::TaskComplete(TaskCompleteEvent {
             last_agent_message: None,
+            error: None,
         }),
     });
 
@@ -2365,6 +2366,7 @@ printf 'fenced within fenced\n'
         id: "t1".into(),
         msg: EventMsg::TaskComplete(TaskCompleteEvent {
             last_agent_message: None,
+            error: None,
         }),
     });
     for lines in drain_insert_history(&mut rx) {
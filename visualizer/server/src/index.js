@@ -1,4 +1,5 @@
 import { createServer } from "node:http";
+import { gunzipSync } from "node:zlib";
 import { WebSocketServer } from "ws";
 
 const port = Number(process.env.CODEX_VISUALIZER_PORT ?? 4100);
@@ -10,6 +11,17 @@ const wss = new WebSocketServer({ server });
 const producerSockets = new Set();
 const viewerSockets = new Set();
 const backlog = [];
+// Sequences in the backlog, so events a producer replays on reconnect are
+// not stored twice.
+const backlogSequences = new Set();
+// Optional frame formats a producer may use once welcomed: runs of
+// `event_chunk` frames, which are forwarded as they are for viewers to
+// reassemble, and gzipped binary frames, decompressed here.
+const acceptedCapabilities = new Set(["event_chunk", "gzip"]);
+
+function sequenceKey(eventPayload) {
+  return `${eventPayload.sequenceEpoch ?? 0}:${eventPayload.sequence}`;
+}
 
 function broadcastEvent(eventPayload, except) {
   const message = JSON.stringify({ type: "event", event: eventPayload });
@@ -26,8 +38,27 @@ function broadcastEvent(eventPayload, except) {
 
 function pushBacklog(eventPayload) {
   backlog.push(eventPayload);
+  backlogSequences.add(sequenceKey(eventPayload));
   if (backlog.length > backlogLimit) {
-    backlog.splice(0, backlog.length - backlogLimit);
+    for (const evicted of backlog.splice(0, backlog.length - backlogLimit)) {
+      backlogSequences.delete(sequenceKey(evicted));
+    }
+  }
+}
+
+function decodeFrame(data, isBinary) {
+  if (!isBinary) {
+    return data.toString();
+  }
+  if (data[0] !== 0x1f || data[1] !== 0x8b) {
+    console.warn("ignoring a binary producer frame that is not gzipped");
+    return null;
+  }
+  try {
+    return gunzipSync(data).toString();
+  } catch (err) {
+    console.warn("failed to decompress producer frame", err);
+    return null;
   }
 }
 
@@ -58,13 +89,33 @@ wss.on("connection", (socket, request) => {
     }
   }
 
-  socket.on("message", (data) => {
-    const payload = typeof data === "string" ? data : data.toString();
+  socket.on("message", (data, isBinary) => {
     if (role === "producer") {
+      const payload = decodeFrame(data, isBinary);
+      if (payload === null) {
+        return;
+      }
       const parsed = safeParseEvent(payload);
       if (!parsed) {
         return;
       }
+      if (parsed.type === "hello") {
+        console.log(
+          `visualizer producer ${parsed.producerVersion} speaks schema version ${parsed.schemaVersion} (${clientDescription})`,
+        );
+        const capabilities = (parsed.capabilities ?? []).filter((capability) =>
+          acceptedCapabilities.has(capability),
+        );
+        try {
+          socket.send(JSON.stringify({ type: "welcome", capabilities }));
+        } catch (err) {
+          console.warn("failed to welcome producer", err);
+        }
+        return;
+      }
+      if (parsed.replayed && backlogSequences.has(sequenceKey(parsed))) {
+        return;
+      }
       pushBacklog(parsed);
       broadcastEvent(parsed, null);
     }
@@ -8,13 +8,92 @@ import {
   subscribeToVisualizerStore,
   useVisualizerSnapshot,
 } from "./visualizerStore";
-import { ConnectionStatus, VisualizerEvent, VisualizerSocketMessage } from "./visualizerTypes";
+import {
+  ConnectionStatus,
+  EventChunkAction,
+  VisualizerEvent,
+  VisualizerSocketMessage,
+  WireVisualizerEvent,
+} from "./visualizerTypes";
 
 const WEBSOCKET_URL = import.meta.env.VITE_VISUALIZER_WS ?? "ws://localhost:4100/?role=viewer";
 const RECONNECT_DELAY_MS = 1000;
 
 type ConnectionToken = symbol;
 
+const CHUNK_ACTION_TYPE = "event_chunk";
+
+type PartialEvent = {
+  sequenceEpoch: number;
+  sequence: number;
+  total: number;
+  next: number;
+  parts: Uint8Array[];
+};
+
+/**
+ * Reassembles runs of `event_chunk` frames into the event they were split
+ * from. A producer writes a run contiguously and resends it from index 0
+ * after a failure, so a run interrupted by any other event is dropped.
+ */
+class ChunkReassembler {
+  private partial: PartialEvent | null = null;
+
+  push(event: WireVisualizerEvent): WireVisualizerEvent | null {
+    if (event.actionType !== CHUNK_ACTION_TYPE) {
+      this.partial = null;
+      return event;
+    }
+    const { index, total, bytes } = event.action as EventChunkAction;
+    const sequenceEpoch = event.sequenceEpoch ?? 0;
+    const partial = this.partial;
+    if (index === 0) {
+      this.partial = { sequenceEpoch, sequence: event.sequence, total, next: 0, parts: [] };
+    } else if (
+      !partial ||
+      partial.sequenceEpoch !== sequenceEpoch ||
+      partial.sequence !== event.sequence ||
+      partial.total !== total ||
+      partial.next !== index
+    ) {
+      this.partial = null;
+      return null;
+    }
+    const current = this.partial as PartialEvent;
+    current.parts.push(Uint8Array.from(atob(bytes), (char) => char.charCodeAt(0)));
+    current.next += 1;
+    if (current.next < current.total) {
+      return null;
+    }
+    this.partial = null;
+    const joined = new Uint8Array(current.parts.reduce((length, part) => length + part.length, 0));
+    let offset = 0;
+    for (const part of current.parts) {
+      joined.set(part, offset);
+      offset += part.length;
+    }
+    try {
+      return JSON.parse(new TextDecoder().decode(joined)) as WireVisualizerEvent;
+    } catch (err) {
+      console.warn("failed to parse reassembled visualizer event", err);
+      return null;
+    }
+  }
+}
+
+function reassembleAll(events: WireVisualizerEvent[]): VisualizerEvent[] {
+  const reassembler = new ChunkReassembler();
+  return events.flatMap((event) => {
+    const whole = reassembler.push(event);
+    return whole ? [normalizeEvent(whole)] : [];
+  });
+}
+
+/** Turns a string `timestampMs` into a number, precise enough to display. */
+function normalizeEvent(event: WireVisualizerEvent): VisualizerEvent {
+  return { ...event, timestampMs: Number(event.timestampMs) };
+}
+
 let pendingEvents: VisualizerEvent[] = [];
 let flushHandle: number | null = null;
 
@@ -56,6 +135,7 @@ class VisualizerConnection {
   private reconnectTimer: number | null = null;
   private shouldReconnect = false;
   private activeTokens = new Set<ConnectionToken>();
+  private chunks = new ChunkReassembler();
 
   constructor(url: string) {
     this.url = url;
@@ -113,12 +193,16 @@ class VisualizerConnection {
 
       if (message.type === "backlog") {
         clearPendingEvents();
-        replaceEvents(message.events);
+        this.chunks = new ChunkReassembler();
+        replaceEvents(reassembleAll(message.events));
         return;
       }
 
       if (message.type === "event") {
-        enqueueEvent(message.event);
+        const event = this.chunks.push(message.event);
+        if (event) {
+          enqueueEvent(normalizeEvent(event));
+        }
         return;
       }
 
@@ -1,10 +1,41 @@
 export type VisualizerEvent = {
+  /** Absent before schema version 2. */
+  schemaVersion?: number;
+  /** Incremented each time `sequence` wraps below 2^53; absent means 0. */
+  sequenceEpoch?: number;
   sequence: number;
+  /** Wall-clock time; see `WireVisualizerEvent` for how it arrives. */
   timestampMs: number;
+  /** Milliseconds since the producer started; never decreases. */
+  monotonicMs?: number;
   conversationId?: string;
+  /** Position among the events of `conversationId`, from 0 without gaps. */
+  conversationSequence?: number;
   actionType: string;
   action: unknown;
   state?: unknown;
+  /** Set on an event resent to a relay that connected after it was sent. */
+  replayed?: boolean;
+};
+
+/**
+ * An event as the relay forwards it. With the producer's
+ * `visualizer_string_timestamps` setting `timestampMs` is an exact decimal
+ * string rather than a number saturated at 2^53 - 1.
+ */
+export type WireVisualizerEvent = Omit<VisualizerEvent, "timestampMs"> & {
+  timestampMs: number | string;
+};
+
+/**
+ * Action of an `event_chunk` frame: one slice of an event too large for a
+ * single frame. The frame carries the original event's sequence; `bytes` is
+ * the base64 of its slice of the serialized event.
+ */
+export type EventChunkAction = {
+  index: number;
+  total: number;
+  bytes: string;
 };
 
 export type ProtocolEventMessage = {
@@ -107,9 +138,9 @@ export type VisualizerState = {
 export type VisualizerSocketMessage =
   | {
       type: "backlog";
-      events: VisualizerEvent[];
+      events: WireVisualizerEvent[];
     }
   | {
       type: "event";
-      event: VisualizerEvent;
+      event: WireVisualizerEvent;
     };